@@ -0,0 +1,172 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+use termpdf_core::Session;
+use tracing::warn;
+
+/// Quiet time after moving around or changing marks before the state is
+/// saved.
+const SETTLE_AFTER: Duration = Duration::from_secs(2);
+/// Reading time changes all the time while reading, so it is only saved
+/// this often.
+const READING_TIME_EVERY: Duration = Duration::from_secs(60);
+
+/// Saves the session's state shortly after it changes rather than only on
+/// quit, so a crash or a killed terminal loses seconds of reading, not
+/// hours.
+#[derive(Debug)]
+pub struct Autosave {
+    saved: Fingerprint,
+    seen: Fingerprint,
+    changed_at: Instant,
+    saved_at: Instant,
+}
+
+/// Hashes of the parts of the state that change for different reasons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    /// Positions, marks, jumps and view settings.
+    edits: u64,
+    /// Total reading time of all documents, in milliseconds.
+    reading: u128,
+}
+
+impl Autosave {
+    /// Starts from the state as it was loaded.
+    pub fn new(session: &Session, now: Instant) -> Self {
+        let current = Fingerprint::of(session);
+        Self {
+            saved: current,
+            seen: current,
+            changed_at: now,
+            saved_at: now,
+        }
+    }
+
+    /// Saves the session if it changed and has settled, or if changes have
+    /// gone unsaved for long enough.
+    pub fn tick(&mut self, session: &Session) {
+        let now = session.clock().now();
+        let current = Fingerprint::of(session);
+        if !self.due(current, now) {
+            return;
+        }
+        match session.persist() {
+            Ok(()) => self.saved = current,
+            // Retried once things settle again rather than on every loop.
+            Err(err) => {
+                warn!(?err, "failed to autosave state");
+                self.changed_at = now;
+            }
+        }
+        self.saved_at = now;
+    }
+
+    fn due(&mut self, current: Fingerprint, now: Instant) -> bool {
+        if current.edits != self.seen.edits {
+            self.changed_at = now;
+        }
+        self.seen = current;
+        let unsaved_edits = current.edits != self.saved.edits;
+        let unsaved_reading = current.reading != self.saved.reading;
+        let settled = now.saturating_duration_since(self.changed_at) >= SETTLE_AFTER;
+        let overdue = now.saturating_duration_since(self.saved_at) >= READING_TIME_EVERY;
+        (unsaved_edits && settled) || ((unsaved_edits || unsaved_reading) && overdue)
+    }
+}
+
+impl Fingerprint {
+    fn of(session: &Session) -> Self {
+        let mut hasher = DefaultHasher::new();
+        let mut reading = 0;
+        for doc in session.documents() {
+            let state = &doc.state;
+            doc.info.id.hash(&mut hasher);
+            let edits = serde_json::to_vec(&(
+                state.current_page,
+                state.viewport,
+                state.scale,
+                state.dark_mode,
+                state.fit_mode,
+                &state.marks,
+                &state.named_marks,
+                &state.jumps,
+                state.first_page_number,
+                state.daily_goal,
+            ))
+            .unwrap_or_default();
+            edits.hash(&mut hasher);
+            reading += state.stats.total().as_millis();
+        }
+        Self {
+            edits: hasher.finish(),
+            reading,
+        }
+    }
+}
+
+/// Owns the session and saves its state if a panic unwinds past it.
+pub struct SaveOnPanic(pub Session);
+
+impl Deref for SaveOnPanic {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.0
+    }
+}
+
+impl DerefMut for SaveOnPanic {
+    fn deref_mut(&mut self) -> &mut Session {
+        &mut self.0
+    }
+}
+
+impl Drop for SaveOnPanic {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            if let Err(err) = self.0.persist() {
+                warn!(?err, "failed to save state after a panic");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_once_changes_settle() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let loaded = Fingerprint {
+            edits: 1,
+            reading: 0,
+        };
+        let mut autosave = Autosave {
+            saved: loaded,
+            seen: loaded,
+            changed_at: start,
+            saved_at: start,
+        };
+        let moved = |edits| Fingerprint { edits, reading: 0 };
+
+        assert!(!autosave.due(loaded, at(10)));
+        assert!(!autosave.due(moved(2), at(11)));
+        // Still paging through: wait until it stops.
+        assert!(!autosave.due(moved(3), at(12)));
+        assert!(autosave.due(moved(3), at(14)));
+        autosave.saved = moved(3);
+        autosave.saved_at = at(14);
+
+        let reading = Fingerprint {
+            edits: 3,
+            reading: 5_000,
+        };
+        assert!(!autosave.due(reading, at(20)));
+        assert!(autosave.due(reading, at(74)));
+    }
+}
@@ -0,0 +1,442 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use termpdf_core::DocumentInfo;
+
+/// One `@article{key, ...}` style entry of a BibTeX file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BibEntry {
+    pub key: String,
+    pub kind: String,
+    fields: Vec<(String, String)>,
+}
+
+impl BibEntry {
+    /// The value of a field, with braces removed and whitespace collapsed.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+
+    pub fn cite(&self) -> String {
+        format!("\\cite{{{}}}", self.key)
+    }
+
+    /// A one-line reference: `Knuth, Levy (1984). Title. Journal.`
+    pub fn reference(&self) -> String {
+        let mut parts = Vec::new();
+        let authors = self.field("author").or_else(|| self.field("editor"));
+        match (authors.map(short_authors), self.field("year")) {
+            (Some(authors), Some(year)) => parts.push(format!("{} ({})", authors, year)),
+            (Some(authors), None) => parts.push(authors),
+            (None, Some(year)) => parts.push(format!("({})", year)),
+            (None, None) => {}
+        }
+        let venue = ["journal", "booktitle", "publisher", "school", "institution"]
+            .iter()
+            .find_map(|name| self.field(name));
+        parts.extend(
+            self.field("title")
+                .into_iter()
+                .chain(venue)
+                .map(String::from),
+        );
+        parts
+            .into_iter()
+            .map(|part| format!("{}.", part.trim_end_matches('.')))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The files a Zotero export lists for the entry. Better BibTeX writes
+    /// plain paths separated by `;`, Zotero's own export `title:path:type`.
+    fn files(&self) -> Vec<PathBuf> {
+        let Some(value) = self.field("file") else {
+            return Vec::new();
+        };
+        value
+            .split(';')
+            .map(|file| {
+                let parts: Vec<&str> = file.split(':').collect();
+                // Keep a Windows drive letter attached to its path.
+                match parts.as_slice() {
+                    [_, path @ .., kind] if kind.contains('/') && !path.is_empty() => {
+                        path.join(":")
+                    }
+                    _ => file.to_string(),
+                }
+            })
+            .map(|path| PathBuf::from(path.trim().replace("\\:", ":")))
+            .filter(|path| !path.as_os_str().is_empty())
+            .collect()
+    }
+}
+
+/// The entries of every `--bibtex` file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bibliography {
+    entries: Vec<BibEntry>,
+    /// `(entry, canonical path)` for every `file` field, resolved once so
+    /// lookups do not touch the file system.
+    files: Vec<(usize, PathBuf)>,
+}
+
+impl Bibliography {
+    pub fn new(entries: Vec<BibEntry>) -> Self {
+        let files = entries
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, entry)| entry.files().into_iter().map(move |file| (idx, file)))
+            .map(|(idx, file)| (idx, fs::canonicalize(&file).unwrap_or(file)))
+            .collect();
+        Self { entries, files }
+    }
+
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let mut entries = Vec::new();
+        for path in paths {
+            let contents = fs::read_to_string(path)
+                .with_context(|| format!("failed to read bibliography {:?}", path))?;
+            entries.extend(parse(&contents));
+        }
+        Ok(Self::new(entries))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The entry for a document: the one with the same DOI, else the one
+    /// whose `file` field is the document, else the one with its title.
+    pub fn find(&self, info: &DocumentInfo, doi: Option<&str>) -> Option<&BibEntry> {
+        let by_doi = doi.map(normalize_doi).and_then(|doi| {
+            self.entries.iter().find(|entry| {
+                entry
+                    .field("doi")
+                    .is_some_and(|field| normalize_doi(field) == doi)
+            })
+        });
+        by_doi.or_else(|| self.find_file(&info.path)).or_else(|| {
+            let title = info.metadata.title.as_deref().map(normalize_title)?;
+            if title.is_empty() {
+                return None;
+            }
+            self.entries.iter().find(|entry| {
+                entry
+                    .field("title")
+                    .is_some_and(|field| normalize_title(field) == title)
+            })
+        })
+    }
+
+    /// Zotero's storage folder is often synced to another place, so a file
+    /// of the same name counts when no path matches exactly.
+    fn find_file(&self, path: &Path) -> Option<&BibEntry> {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let same_name =
+            |file: &PathBuf| file.file_name().is_some() && file.file_name() == path.file_name();
+        self.files
+            .iter()
+            .find(|(_, file)| *file == path)
+            .or_else(|| self.files.iter().find(|(_, file)| same_name(file)))
+            .map(|&(idx, _)| &self.entries[idx])
+    }
+}
+
+static BIBLIOGRAPHY: OnceLock<Bibliography> = OnceLock::new();
+
+/// Sets the bibliography; called once at startup from `--bibtex`.
+pub fn init(bibliography: Bibliography) {
+    let _ = BIBLIOGRAPHY.set(bibliography);
+}
+
+pub fn bibliography() -> Option<&'static Bibliography> {
+    BIBLIOGRAPHY
+        .get()
+        .filter(|bibliography| !bibliography.is_empty())
+}
+
+fn normalize_doi(doi: &str) -> String {
+    let doi = doi.trim().to_ascii_lowercase();
+    [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "doi:",
+    ]
+    .iter()
+    .find_map(|prefix| doi.strip_prefix(prefix))
+    .unwrap_or(&doi)
+    .trim()
+    .to_string()
+}
+
+fn normalize_title(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `Knuth, Donald E. and Levy, Silvio` as `Knuth, Levy`; four or more
+/// authors as `Knuth et al.`.
+fn short_authors(authors: &str) -> String {
+    let names: Vec<&str> = authors
+        .split(" and ")
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    let last_name = |name: &&str| match name.split_once(',') {
+        Some((last, _)) => last.trim().to_string(),
+        None => name.rsplit(' ').next().unwrap_or(name).to_string(),
+    };
+    match names.as_slice() {
+        [first, _, _, _, ..] => format!("{} et al", last_name(first)),
+        names => names.iter().map(last_name).collect::<Vec<_>>().join(", "),
+    }
+}
+
+/// Parses the entries of a BibTeX file. `@string`, `@preamble` and
+/// `@comment` blocks are skipped and macros are kept as their names, so a
+/// malformed entry only loses itself.
+pub fn parse(input: &str) -> Vec<BibEntry> {
+    let mut entries = Vec::new();
+    let mut rest = input;
+    while let Some(at) = rest.find('@') {
+        rest = &rest[at + 1..];
+        // Only `@<ident>{` starts a block; any other `@`, as in an email
+        // address, is text between entries.
+        let ident = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let open = ident + (rest[ident..].len() - rest[ident..].trim_start().len());
+        if ident == 0 || !rest[open..].starts_with(['{', '(']) {
+            continue;
+        }
+        let kind = rest[..ident].to_ascii_lowercase();
+        let Some(len) = block_len(&rest[open..]) else {
+            break;
+        };
+        let body = &rest[open + 1..open + len - 1];
+        rest = &rest[open + len..];
+        if matches!(kind.as_str(), "string" | "preamble" | "comment") {
+            continue;
+        }
+        if let Some(entry) = parse_entry(kind, body) {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+/// Length of the `{...}` or `(...)` block at the start of `text`,
+/// delimiters included.
+fn block_len(text: &str) -> Option<usize> {
+    let close = if text.starts_with('(') { ')' } else { '}' };
+    let mut depth = 0usize;
+    for (idx, c) in text.char_indices() {
+        match c {
+            '{' | '(' if c == '{' || close == ')' => depth += 1,
+            '}' | ')' if c == '}' || close == ')' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return (c == close).then_some(idx + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_entry(kind: String, body: &str) -> Option<BibEntry> {
+    let (key, mut rest) = body.split_once(',').unwrap_or((body, ""));
+    let key = key.trim();
+    if key.is_empty() {
+        return None;
+    }
+    let mut fields = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        let Some((name, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, after) = parse_value(after);
+        fields.push((name.trim().to_ascii_lowercase(), value));
+        rest = after;
+    }
+    Some(BibEntry {
+        key: key.to_string(),
+        kind,
+        fields,
+    })
+}
+
+/// Reads a field value up to the next top-level comma, joining the parts
+/// of `"a" # {b}` concatenations.
+fn parse_value(text: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut rest = text.trim_start();
+    loop {
+        let (part, after) = if let Some(braced) = rest.strip_prefix('{') {
+            match block_len(rest) {
+                Some(len) => (&braced[..len - 2], &rest[len..]),
+                None => (braced, ""),
+            }
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let mut depth = 0usize;
+            let end = quoted
+                .char_indices()
+                .find(|&(_, c)| {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth = depth.saturating_sub(1),
+                        _ => {}
+                    }
+                    c == '"' && depth == 0
+                })
+                .map_or(quoted.len(), |(idx, _)| idx);
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            let end = rest.find([',', '#']).unwrap_or(rest.len());
+            (rest[..end].trim(), &rest[end..])
+        };
+        value.push_str(part);
+        rest = after.trim_start();
+        match rest.strip_prefix('#') {
+            Some(after) => rest = after.trim_start(),
+            None => break,
+        }
+    }
+    (clean_value(&value), rest)
+}
+
+fn clean_value(value: &str) -> String {
+    value
+        .replace(['{', '}'], "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termpdf_core::{document_id_for_path, DocumentMetadata};
+
+    const LIBRARY: &str = r#"
+% Exported by Better BibTeX
+@string{ tug = "TUGboat" }
+
+@article{knuth1984,
+  author = {Knuth, Donald E. and Levy, Silvio},
+  title = {The {CWEB} System of
+           Structured Documentation},
+  journal = tug # { Journal},
+  year = 1984,
+  doi = {10.1000/CWEB.1984},
+}
+
+@inproceedings(lamport1994,
+  title = "LaTeX: A Document Preparation System",
+  author = "Leslie Lamport",
+  file = {Full Text PDF:/home/me/Zotero/storage/AB12/lamport.pdf:application/pdf}
+)
+
+@comment{ not an entry }
+@book{many, author = {A, A and B, B and C, C and D, D}, title = {Crowd}, year = {2000}}
+"#;
+
+    fn info(path: &str, title: Option<&str>) -> DocumentInfo {
+        DocumentInfo {
+            id: document_id_for_path(Path::new(path)),
+            path: PathBuf::from(path),
+            page_count: 1,
+            metadata: DocumentMetadata {
+                title: title.map(str::to_string),
+                ..DocumentMetadata::default()
+            },
+        }
+    }
+
+    #[test]
+    fn parses_entries_and_formats_references() {
+        let entries = parse(LIBRARY);
+        let keys: Vec<&str> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(keys, ["knuth1984", "lamport1994", "many"]);
+
+        let knuth = &entries[0];
+        assert_eq!(knuth.kind, "article");
+        assert_eq!(
+            knuth.field("title"),
+            Some("The CWEB System of Structured Documentation")
+        );
+        assert_eq!(knuth.cite(), "\\cite{knuth1984}");
+        assert_eq!(
+            knuth.reference(),
+            "Knuth, Levy (1984). The CWEB System of Structured Documentation. tug Journal."
+        );
+        assert_eq!(
+            entries[1].reference(),
+            "Lamport. LaTeX: A Document Preparation System."
+        );
+        assert_eq!(entries[2].reference(), "A et al (2000). Crowd.");
+    }
+
+    #[test]
+    fn stray_at_signs_do_not_swallow_entries() {
+        let entries = parse(
+            "% Maintained by me@example.org\n\
+             @misc{first, title = {One}}\n\
+             Ask @ the desk.\n\
+             @Book {second, title = {Two}}\n",
+        );
+        let keys: Vec<&str> = entries.iter().map(|entry| entry.key.as_str()).collect();
+        assert_eq!(keys, ["first", "second"]);
+        assert_eq!(entries[1].kind, "book");
+    }
+
+    #[test]
+    fn matches_documents_by_doi_file_and_title() {
+        let library = Bibliography::new(parse(LIBRARY));
+        let key = |info: &DocumentInfo, doi: Option<&str>| {
+            library.find(info, doi).map(|entry| entry.key.clone())
+        };
+        assert_eq!(
+            key(
+                &info("/tmp/x.pdf", None),
+                Some("https://doi.org/10.1000/cweb.1984")
+            ),
+            Some("knuth1984".into())
+        );
+        assert_eq!(
+            key(
+                &info("/home/me/Zotero/storage/AB12/lamport.pdf", None),
+                None
+            ),
+            Some("lamport1994".into())
+        );
+        assert_eq!(
+            key(&info("/elsewhere/lamport.pdf", None), None),
+            Some("lamport1994".into())
+        );
+        assert_eq!(
+            key(
+                &info(
+                    "/tmp/x.pdf",
+                    Some("The CWEB system of structured documentation")
+                ),
+                None
+            ),
+            Some("knuth1984".into())
+        );
+        assert_eq!(key(&info("/tmp/x.pdf", Some("Other")), None), None);
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use termpdf_tty::ListView;
+
+/// File extensions offered by the browser, compared case-insensitively.
+const SUPPORTED_EXTENSIONS: &[&str] = &["pdf"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrowserEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+impl BrowserEntry {
+    /// Name as shown in the list; directories end in a slash.
+    pub fn label(&self) -> String {
+        if self.is_dir {
+            format!("{}/", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            SUPPORTED_EXTENSIONS
+                .iter()
+                .any(|supported| ext.eq_ignore_ascii_case(supported))
+        })
+}
+
+/// Lists `dir`: its parent, then subdirectories, then supported documents,
+/// each sorted by name. Hidden entries are skipped.
+pub fn list_directory(dir: &Path) -> Result<Vec<BrowserEntry>> {
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {:?}", dir))? {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        // Follows symlinks, so linked folders and papers show up too.
+        if path.is_dir() {
+            dirs.push(BrowserEntry {
+                name,
+                path,
+                is_dir: true,
+            });
+        } else if is_supported(&path) {
+            files.push(BrowserEntry {
+                name,
+                path,
+                is_dir: false,
+            });
+        }
+    }
+    let by_name = |a: &BrowserEntry, b: &BrowserEntry| {
+        a.name
+            .to_lowercase()
+            .cmp(&b.name.to_lowercase())
+            .then_with(|| a.name.cmp(&b.name))
+    };
+    dirs.sort_by(by_name);
+    files.sort_by(by_name);
+
+    let mut entries = Vec::with_capacity(dirs.len() + files.len() + 1);
+    if let Some(parent) = dir.parent() {
+        entries.push(BrowserEntry {
+            name: "..".to_string(),
+            path: parent.to_path_buf(),
+            is_dir: true,
+        });
+    }
+    entries.extend(dirs);
+    entries.extend(files);
+    Ok(entries)
+}
+
+/// Scores `candidate` against `query` when the query's characters appear in
+/// it in order, ignoring case. Consecutive characters and characters at the
+/// start of a word score higher; `None` means no match.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0i64;
+    let mut position = 0usize;
+    let mut previous: Option<usize> = None;
+    for needle in query.chars().flat_map(char::to_lowercase) {
+        if needle.is_whitespace() {
+            continue;
+        }
+        let offset = candidate[position..].iter().position(|&ch| ch == needle)?;
+        let index = position + offset;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == index) {
+            score += 5;
+        }
+        if index == 0 || !candidate[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= offset as i64;
+        previous = Some(index);
+        position = index + 1;
+    }
+    Some(score)
+}
+
+/// Contents of the `:browse` overlay.
+pub struct FileBrowser {
+    pub dir: PathBuf,
+    pub entries: Vec<BrowserEntry>,
+    pub visible: Vec<usize>,
+    pub list: ListView,
+    pub filter: String,
+    pub filter_input: bool,
+    /// Document picked with Enter, waiting to be opened by the main loop.
+    chosen: Option<PathBuf>,
+}
+
+impl FileBrowser {
+    pub fn new(dir: &Path) -> Result<Self> {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let entries = list_directory(&dir)?;
+        let visible = (0..entries.len()).collect();
+        Ok(Self {
+            dir,
+            list: ListView::new(entries.len()),
+            entries,
+            visible,
+            filter: String::new(),
+            filter_input: false,
+            chosen: None,
+        })
+    }
+
+    /// Keeps the entries matching `query`, best matches first.
+    pub fn set_filter(&mut self, query: &str) {
+        self.filter = query.to_string();
+        let mut scored: Vec<(i64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entry)| Some((fuzzy_score(query, &entry.name)?, idx)))
+            .collect();
+        if !query.trim().is_empty() {
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        }
+        self.visible = scored.into_iter().map(|(_, idx)| idx).collect();
+        self.list = ListView::new(self.visible.len());
+    }
+
+    pub fn selected_entry(&self) -> Option<&BrowserEntry> {
+        self.visible
+            .get(self.list.selected())
+            .map(|&idx| &self.entries[idx])
+    }
+
+    /// Enters the selected directory or picks the selected document.
+    pub fn activate(&mut self) -> Result<()> {
+        let Some(entry) = self.selected_entry().cloned() else {
+            return Ok(());
+        };
+        if entry.is_dir {
+            let previous = self.dir.clone();
+            *self = Self::new(&entry.path)?;
+            // Coming back up keeps the folder we left selected.
+            if let Some(index) = self
+                .visible
+                .iter()
+                .position(|&idx| self.entries[idx].path == previous)
+            {
+                self.list.select(index);
+            }
+        } else {
+            self.chosen = Some(entry.path);
+        }
+        Ok(())
+    }
+
+    pub fn take_chosen(&mut self) -> Option<PathBuf> {
+        self.chosen.take()
+    }
+
+    pub fn title(&self) -> String {
+        let mut title = format!(
+            "{} ({}/{})",
+            self.dir.display(),
+            self.visible.len(),
+            self.entries.len()
+        );
+        if self.filter_input || !self.filter.is_empty() {
+            title.push_str(&format!(" /{}", self.filter));
+        }
+        title
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matches_prefer_words_and_runs() {
+        assert!(fuzzy_score("atn", "attention.pdf").is_some());
+        assert!(fuzzy_score("xyz", "attention.pdf").is_none());
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        let word = fuzzy_score("att", "attention.pdf").unwrap();
+        let scattered = fuzzy_score("att", "a-tiny-tale.pdf").unwrap();
+        assert!(word > scattered);
+        assert!(fuzzy_score("BERT", "bert-paper.PDF").is_some());
+    }
+
+    #[test]
+    fn browser_lists_folders_and_documents() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::create_dir(root.join("theses")).unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+        fs::write(root.join("Zeta.PDF"), b"%PDF").unwrap();
+        fs::write(root.join("attention.pdf"), b"%PDF").unwrap();
+        fs::write(root.join("notes.txt"), b"").unwrap();
+        fs::write(root.join("theses").join("mine.pdf"), b"%PDF").unwrap();
+
+        let mut browser = FileBrowser::new(&root).unwrap();
+        let labels: Vec<String> = browser.entries.iter().map(BrowserEntry::label).collect();
+        assert_eq!(labels, ["../", "theses/", "attention.pdf", "Zeta.PDF"]);
+
+        browser.set_filter("zt");
+        assert_eq!(browser.selected_entry().unwrap().name, "Zeta.PDF");
+        browser.activate().unwrap();
+        assert_eq!(browser.take_chosen(), Some(root.join("Zeta.PDF")));
+        assert_eq!(browser.take_chosen(), None);
+
+        browser.set_filter("thes");
+        browser.activate().unwrap();
+        assert_eq!(browser.dir, root.join("theses"));
+        assert_eq!(browser.entries.len(), 2);
+        browser.activate().unwrap();
+        assert_eq!(browser.dir, root);
+        assert_eq!(browser.selected_entry().unwrap().name, "theses");
+    }
+}
@@ -0,0 +1,126 @@
+//! The settings file, `config.toml` in termpdf's config directory (or the
+//! file given with `--config`). Keys are named after the command-line
+//! flags, which override them.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub highlight_width: Option<f32>,
+    pub highlight_opacity: Option<f32>,
+    pub prefetch: Option<usize>,
+    pub prefetch_direction: Option<String>,
+    pub pan_step: Option<f32>,
+    pub zoom_step: Option<f32>,
+    pub scrolloff: Option<usize>,
+    pub copy_reflow: Option<bool>,
+    pub links: LinksConfig,
+}
+
+/// The `[links]` table: what following an external link does.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LinksConfig {
+    /// Ask before starting a program; `--no-confirm-links` turns it off
+    pub confirm: Option<bool>,
+    /// Actions per URI scheme, as in `--link-handler`; the flags win
+    pub handlers: BTreeMap<String, String>,
+    /// As `--allow-links`, which replaces the list when given
+    pub allow: Vec<String>,
+    /// As `--deny-links`, which replaces the list when given
+    pub deny: Vec<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Like [`Config::load`], but a missing file means no settings.
+    pub fn load_or_default(path: &Path) -> Result<Self> {
+        match fs::metadata(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            _ => Self::load(path),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+}
+
+/// The flag's value if it was given, else the config file's, checked with
+/// the flag's parser so both accept the same values.
+pub fn setting<T, V: ToString>(
+    flag: Option<T>,
+    configured: Option<V>,
+    key: &str,
+    parse: impl Fn(&str) -> Result<T>,
+) -> Result<Option<T>> {
+    match (flag, configured) {
+        (Some(value), _) => Ok(Some(value)),
+        (None, Some(value)) => parse(&value.to_string())
+            .map(Some)
+            .with_context(|| format!("invalid `{}` in the config file", key)),
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn config_keys_are_read_and_flags_override_them() {
+        let config = Config::parse("highlight-width = 2.5\n").unwrap();
+        assert_eq!(config.highlight_width, Some(2.5));
+        assert_eq!(config.highlight_opacity, None);
+        assert!(Config::parse("highlight_width = 2.5\n").is_err());
+        let prefetch = Config::parse("prefetch = 0\nprefetch-direction = \"forward\"\n").unwrap();
+        assert_eq!(prefetch.prefetch, Some(0));
+        assert_eq!(prefetch.prefetch_direction.as_deref(), Some("forward"));
+        let copy = Config::parse("copy-reflow = true\n").unwrap();
+        assert_eq!(copy.copy_reflow, Some(true));
+        assert!(Config::parse("copy-reflow = \"yes\"\n").is_err());
+
+        let width = |value: &str| {
+            value
+                .parse::<f32>()
+                .ok()
+                .filter(|width| *width <= 20.0)
+                .ok_or_else(|| anyhow!("too wide"))
+        };
+        assert_eq!(
+            setting(None, config.highlight_width, "highlight-width", width).unwrap(),
+            Some(2.5)
+        );
+        assert_eq!(
+            setting(Some(4.0), config.highlight_width, "highlight-width", width).unwrap(),
+            Some(4.0)
+        );
+        assert!(setting(None, Some(40.0), "highlight-width", width).is_err());
+    }
+
+    #[test]
+    fn a_missing_config_file_has_no_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        assert!(Config::load_or_default(&path)
+            .unwrap()
+            .highlight_width
+            .is_none());
+        assert!(Config::load(&path).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{self, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+
+/// How long the screen stays inverted for a visual flash.
+const FLASH_DURATION: Duration = Duration::from_millis(80);
+
+/// What the terminal does when a motion cannot move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Feedback {
+    /// Ring the terminal bell
+    #[default]
+    Bell,
+    /// Briefly invert the screen
+    Flash,
+    /// Only show the status message
+    None,
+}
+
+static MODE: OnceLock<Feedback> = OnceLock::new();
+
+/// When the flash on screen ends.
+static FLASH_ENDS: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Selects the feedback; called once at startup from `--feedback`.
+pub fn init(mode: Feedback) {
+    let _ = MODE.set(mode);
+}
+
+/// Signals that the last key hit a boundary and did nothing.
+pub fn boundary_reached() {
+    let mode = MODE.get().copied().unwrap_or_default();
+    let mut stdout = io::stdout();
+    if let Err(err) = signal(&mut stdout, mode) {
+        tracing::trace!(?err, "failed to signal boundary");
+    }
+    if mode == Feedback::Flash {
+        *flash_ends() = Some(Instant::now() + FLASH_DURATION);
+    }
+}
+
+/// Turns a flash off once its time is up. Returns how much longer the
+/// flash on screen lasts, so the event loop wakes up in time to end it.
+pub fn end_flash() -> Option<Duration> {
+    let mut ends = flash_ends();
+    let left = ends.as_ref()?.saturating_duration_since(Instant::now());
+    if !left.is_zero() {
+        return Some(left);
+    }
+    *ends = None;
+    let mut stdout = io::stdout();
+    if let Err(err) = reverse_video(&mut stdout, false) {
+        tracing::trace!(?err, "failed to end flash");
+    }
+    None
+}
+
+/// Turns a flash off right away, as when the terminal is handed back.
+pub fn cancel_flash() {
+    if flash_ends().take().is_some() {
+        let _ = reverse_video(&mut io::stdout(), false);
+    }
+}
+
+fn flash_ends() -> std::sync::MutexGuard<'static, Option<Instant>> {
+    FLASH_ENDS.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Starts the signal; a flash is ended by [`end_flash`].
+fn signal(out: &mut impl Write, mode: Feedback) -> io::Result<()> {
+    match mode {
+        Feedback::Bell => out.write_all(b"\x07")?,
+        Feedback::Flash => return reverse_video(out, true),
+        Feedback::None => return Ok(()),
+    }
+    out.flush()
+}
+
+/// DECSCNM: the whole screen in reverse video.
+fn reverse_video(out: &mut impl Write, on: bool) -> io::Result<()> {
+    out.write_all(if on { b"\x1b[?5h" } else { b"\x1b[?5l" })?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feedback_writes_bell_or_flash() {
+        let mut out = Vec::new();
+        signal(&mut out, Feedback::Bell).unwrap();
+        assert_eq!(out, b"\x07");
+        out.clear();
+        signal(&mut out, Feedback::Flash).unwrap();
+        assert_eq!(out, b"\x1b[?5h");
+        reverse_video(&mut out, false).unwrap();
+        assert_eq!(out, b"\x1b[?5h\x1b[?5l");
+        out.clear();
+        signal(&mut out, Feedback::None).unwrap();
+        assert!(out.is_empty());
+    }
+}
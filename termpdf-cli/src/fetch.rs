@@ -0,0 +1,236 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use crossterm::terminal;
+use tokio::task;
+use tracing::warn;
+use url::Url;
+use uuid::Uuid;
+
+/// How long connecting to the server may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the server may go quiet in the middle of a download.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether a file argument names a remote document.
+pub fn is_url(value: &str) -> bool {
+    value.starts_with("http://") || value.starts_with("https://")
+}
+
+/// Where the document at `url` is cached: one directory per URL, keeping the
+/// file's own name so it shows up in the status line.
+pub fn cache_path(dir: &Path, url: &Url) -> PathBuf {
+    let name: String = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("download.pdf")
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || matches!(ch, '.' | '-' | '_') {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let key = Uuid::new_v5(&Uuid::NAMESPACE_URL, url.as_str().as_bytes());
+    dir.join(key.to_string()).join(name)
+}
+
+/// Downloads `url` into the cache under `dir` and returns the cached file.
+/// When the download fails and an earlier copy exists, that copy is used, so
+/// documents opened once keep working offline. Progress goes to standard
+/// error, and only until the viewer has taken over the terminal.
+pub async fn fetch(url: &str, dir: &Path) -> Result<PathBuf> {
+    let url = Url::parse(url).with_context(|| format!("invalid URL {:?}", url))?;
+    let target = cache_path(dir, &url);
+    let result = {
+        let url = url.clone();
+        let target = target.clone();
+        task::spawn_blocking(move || download(&url, &target)).await?
+    };
+    match result {
+        Ok(()) => Ok(target),
+        Err(err) if target.exists() => {
+            warn!(?err, %url, "download failed; using cached copy");
+            if console() {
+                eprintln!(
+                    "Could not download {} ({:#}); opening the cached copy",
+                    url, err
+                );
+            }
+            Ok(target)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Whether messages can go to standard error: it is a terminal, and the
+/// viewer has not put it in raw mode, where they would garble the screen.
+fn console() -> bool {
+    io::stderr().is_terminal() && !terminal::is_raw_mode_enabled().unwrap_or(true)
+}
+
+fn download(url: &Url, target: &Path) -> Result<()> {
+    let dir = target
+        .parent()
+        .ok_or_else(|| anyhow!("invalid cache path {:?}", target))?;
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {:?}", dir))?;
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build();
+    let response = agent
+        .get(url.as_str())
+        .call()
+        .with_context(|| format!("failed to download {}", url))?;
+
+    let mut partial = OsString::from(target.as_os_str());
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+    let file = File::create(&partial).with_context(|| format!("failed to create {:?}", partial))?;
+    let received = receive(url, response, file, target).and_then(|()| {
+        fs::rename(&partial, target).with_context(|| format!("failed to write {:?}", target))
+    });
+    if received.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    received
+}
+
+/// Writes the body of `response` to `file`, showing progress.
+fn receive(url: &Url, response: ureq::Response, mut file: File, target: &Path) -> Result<()> {
+    let total = response
+        .header("Content-Length")
+        .and_then(|value| value.parse::<u64>().ok());
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let show_progress = console();
+    let mut shown = String::new();
+    let mut reader = response.into_reader();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut received = 0u64;
+    let result = loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break Ok(()),
+            Ok(read) => read,
+            Err(err) => break Err(err).with_context(|| format!("failed to download {}", url)),
+        };
+        if let Err(err) = file.write_all(&buf[..read]) {
+            break Err(err).with_context(|| format!("failed to write {:?}", target));
+        }
+        received += read as u64;
+        let line = format_progress(&name, received, total);
+        if show_progress && line != shown {
+            eprint!("\r\x1b[2K{}", line);
+            shown = line;
+        }
+    };
+    if show_progress {
+        eprint!("\r\x1b[2K");
+    }
+    result?;
+    file.flush()?;
+    Ok(())
+}
+
+fn format_progress(name: &str, received: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) if total > 0 => format!(
+            "Downloading {}: {}% ({} / {})",
+            name,
+            received.min(total) * 100 / total,
+            format_size(received),
+            format_size(total)
+        ),
+        _ => format!("Downloading {}: {}", name, format_size(received)),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const MB: u64 = 1024 * 1024;
+    if bytes >= MB {
+        format!("{:.1} MB", bytes as f64 / MB as f64)
+    } else {
+        format!("{} KB", bytes.div_ceil(1024))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_cached_under_their_file_name() {
+        let dir = Path::new("/cache");
+        let url = Url::parse("https://arxiv.org/pdf/1706.03762").unwrap();
+        let path = cache_path(dir, &url);
+        assert_eq!(path.file_name().unwrap(), "1706.03762");
+        assert_eq!(path, cache_path(dir, &url));
+        let other = Url::parse("https://example.com/files/my%20paper.pdf?dl=1").unwrap();
+        let other_path = cache_path(dir, &other);
+        assert_eq!(other_path.file_name().unwrap(), "my_20paper.pdf");
+        assert_ne!(path.parent(), other_path.parent());
+        let root = Url::parse("https://example.com/").unwrap();
+        assert_eq!(cache_path(dir, &root).file_name().unwrap(), "download.pdf");
+
+        assert!(is_url("https://example.com/a.pdf"));
+        assert!(!is_url("notes/https.pdf"));
+    }
+
+    #[test]
+    fn progress_shows_percentage_when_size_is_known() {
+        assert_eq!(
+            format_progress("a.pdf", 512 * 1024, Some(2 * 1024 * 1024)),
+            "Downloading a.pdf: 25% (512 KB / 2.0 MB)"
+        );
+        assert_eq!(
+            format_progress("a.pdf", 3 * 1024 * 1024, None),
+            "Downloading a.pdf: 3.0 MB"
+        );
+    }
+
+    #[tokio::test]
+    async fn interrupted_downloads_leave_no_partial_file() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/paper.pdf", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request);
+            // Promises more than it sends, then hangs up.
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4096\r\n\r\n%PDF-1.7")
+                .unwrap();
+        });
+        let dir = tempfile::tempdir().unwrap();
+        assert!(fetch(&url, dir.path()).await.is_err());
+        server.join().unwrap();
+
+        let target = cache_path(dir.path(), &Url::parse(&url).unwrap());
+        let leftovers: Vec<_> = fs::read_dir(target.parent().unwrap())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert!(leftovers.is_empty(), "left behind {:?}", leftovers);
+    }
+
+    #[tokio::test]
+    async fn failed_download_falls_back_to_cached_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        // Nothing listens on port 9 (discard), so the request fails quickly.
+        let url = "http://127.0.0.1:9/paper.pdf";
+        assert!(fetch(url, dir.path()).await.is_err());
+        let cached = cache_path(dir.path(), &Url::parse(url).unwrap());
+        fs::create_dir_all(cached.parent().unwrap()).unwrap();
+        fs::write(&cached, b"%PDF-1.7").unwrap();
+        assert_eq!(fetch(url, dir.path()).await.unwrap(), cached);
+    }
+}
@@ -0,0 +1,108 @@
+use std::io::Write;
+
+use anyhow::Result;
+use clap::{CommandFactory, Subcommand};
+use clap_complete::Shell;
+use clap_mangen::roff::{bold, roman, Roff};
+use clap_mangen::Man;
+use termpdf_tty::KEY_BINDINGS;
+
+use crate::Args;
+
+/// Packaging helpers that print generated files instead of opening documents.
+#[derive(Debug, Subcommand)]
+pub enum Generate {
+    /// Print a completion script for a shell
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the man page (roff)
+    Manpage,
+}
+
+pub fn run(generate: &Generate, out: &mut dyn Write) -> Result<()> {
+    let mut command = Args::command();
+    match generate {
+        Generate::Completions { shell } => {
+            let name = command.get_name().to_string();
+            // clap_complete panics on write errors such as a closed pipe.
+            let mut script = Vec::new();
+            clap_complete::generate(*shell, &mut command, name, &mut script);
+            out.write_all(&script)?;
+        }
+        Generate::Manpage => manpage(command, out)?,
+    }
+    Ok(())
+}
+
+/// The clap-generated page plus a KEYS section listing the key bindings.
+fn manpage(command: clap::Command, out: &mut dyn Write) -> Result<()> {
+    let mut page = Vec::new();
+    Man::new(command).render(&mut page)?;
+    let page = String::from_utf8(page)?;
+
+    let mut keys = Roff::default();
+    keys.control("SH", ["KEYS"]);
+    let mut mode = "";
+    for binding in KEY_BINDINGS {
+        if binding.mode != mode {
+            mode = binding.mode;
+            keys.control("SS", [format!("{} mode", mode).as_str()]);
+        }
+        keys.control("TP", []);
+        keys.text([bold(binding.keys)]);
+        keys.text([roman(binding.action)]);
+    }
+    let keys = keys.render();
+    // Both renders start with the same preamble; the page already has it.
+    let preamble = &page[..page.find(".TH").unwrap_or(0)];
+    let keys = keys.strip_prefix(preamble).unwrap_or(&keys);
+
+    let at = page.find(".SH VERSION").unwrap_or(page.len());
+    out.write_all(&page.as_bytes()[..at])?;
+    out.write_all(keys.as_bytes())?;
+    out.write_all(&page.as_bytes()[at..])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn manpage_lists_options_and_keys() {
+        let mut out = Vec::new();
+        run(&Generate::Manpage, &mut out).unwrap();
+        let page = String::from_utf8(out).unwrap();
+        assert_eq!(page.matches(".TH termpdf").count(), 1);
+        assert_eq!(page.matches(".ds Aq").count(), 2);
+        assert!(page.contains("\\-\\-status\\-file"));
+        assert!(page.contains(".SH KEYS"));
+        assert!(page.contains(".SS \"visual mode\""));
+        assert!(page.contains("Ctrl\\-o"));
+    }
+
+    #[test]
+    fn files_are_not_taken_for_subcommands() {
+        let args = Args::try_parse_from(["termpdf", "paper.pdf", "notes.pdf"]).unwrap();
+        assert!(args.generate.is_none());
+        assert_eq!(args.files.len(), 2);
+        let args = Args::try_parse_from(["termpdf", "completions", "zsh"]).unwrap();
+        assert!(matches!(
+            args.generate,
+            Some(Generate::Completions { shell: Shell::Zsh })
+        ));
+    }
+
+    #[test]
+    fn completions_cover_flags() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut out = Vec::new();
+            run(&Generate::Completions { shell }, &mut out).unwrap();
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains("render-timeout"), "{shell}");
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use chrono::{DateTime, Local};
+use termpdf_core::{DocumentInstance, DocumentSearchContext};
+use termpdf_tty::ListView;
+
+use crate::outline_heading;
+
+/// One past reading session as `:history` shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryLine {
+    /// When the session ended, in local time.
+    pub ended: String,
+    pub page: usize,
+    /// Outline heading at or before the page.
+    pub heading: Option<String>,
+    /// The first line of text on the page.
+    pub preview: Option<String>,
+}
+
+impl HistoryLine {
+    pub fn text(&self) -> String {
+        let mut text = format!("{}  p{:<5}", self.ended, self.page + 1);
+        let details: Vec<&str> = [self.heading.as_deref(), self.preview.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        text.push_str(&details.join(" · "));
+        text.trim_end().to_string()
+    }
+}
+
+/// The `:history` overlay: where the active document was left at the end of
+/// each past session, newest first. Previews are read only for the rows on
+/// screen, as reading a page's text can take a while.
+#[derive(Clone)]
+pub struct HistoryWindow {
+    pub entries: Vec<HistoryLine>,
+    pub list: ListView,
+    document: DocumentSearchContext,
+    /// Previews read so far, by page.
+    previews: HashMap<usize, Option<String>>,
+}
+
+impl HistoryWindow {
+    /// `None` before a session with the document has ended.
+    pub fn from_document(doc: &DocumentInstance) -> Option<Self> {
+        if doc.state.history.is_empty() {
+            return None;
+        }
+        let entries = doc
+            .state
+            .history
+            .iter()
+            .rev()
+            .map(|entry| HistoryLine {
+                ended: format_ended_at(entry.ended_at),
+                page: entry.page,
+                heading: outline_heading(doc.outline(), entry.page),
+                preview: None,
+            })
+            .collect::<Vec<_>>();
+        Some(Self {
+            list: ListView::new(entries.len()),
+            entries,
+            document: doc.search_context(),
+            previews: HashMap::new(),
+        })
+    }
+
+    pub fn selected_entry(&self) -> Option<&HistoryLine> {
+        self.entries.get(self.list.selected())
+    }
+
+    /// Fills in the previews of the entries in `rows`.
+    pub fn load_previews(&mut self, rows: Range<usize>) {
+        let end = rows.end.min(self.entries.len());
+        for entry in &mut self.entries[rows.start.min(end)..end] {
+            if entry.preview.is_none() {
+                let document = &self.document;
+                entry.preview = self
+                    .previews
+                    .entry(entry.page)
+                    .or_insert_with(|| document.page_preview(entry.page))
+                    .clone();
+            }
+        }
+    }
+}
+
+fn format_ended_at(secs: u64) -> String {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| DateTime::from_timestamp(secs, 0))
+        .map(|ended| {
+            ended
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| "?".repeat(16))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_show_date_page_and_context() {
+        let line = HistoryLine {
+            ended: "2026-03-14 21:05".to_string(),
+            page: 41,
+            heading: Some("Methods".to_string()),
+            preview: None,
+        };
+        assert_eq!(line.text(), "2026-03-14 21:05  p42   Methods");
+        assert_eq!(format_ended_at(0).len(), 16);
+    }
+}
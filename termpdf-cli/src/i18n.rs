@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use termpdf_core::MotionBoundary;
+use tracing::warn;
+
+/// Looks up a UI string by key, filling `{name}` placeholders from the
+/// arguments: `t!("saved-path", path = path.display())`.
+macro_rules! t {
+    ($key:literal) => {
+        $crate::i18n::text($key, &[])
+    };
+    ($key:literal, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::i18n::text(
+            $key,
+            &[$((stringify!($name), &$value as &dyn ::std::fmt::Display)),+],
+        )
+    };
+}
+pub(crate) use t;
+
+/// Every UI string in English. Translations are JSON objects with the same
+/// keys; anything they leave out falls back to this table.
+const ENGLISH: &[(&str, &str)] = &[
+    ("no-active-document", "No active document"),
+    ("no-marks-saved", "No marks saved"),
+    ("no-jumps", "No jumps yet"),
+    ("no-history", "No reading history yet"),
+    ("no-links-found", "No links found"),
+    ("no-citation-link", "No citation link found"),
+    ("no-active-selection", "No active selection"),
+    (
+        "no-selection-on-page",
+        "No selection on this page (select with v first)",
+    ),
+    ("selection-empty", "Selection is empty"),
+    ("no-page-view", "No page view on screen"),
+    ("yanked-selection", "Yanked selection"),
+    ("yanked-quote", "Yanked selection as a quote"),
+    ("yanked-cite", "Yanked {cite}"),
+    ("no-bib-entry", "No BibTeX entry matches this document"),
+    ("no-doi-found", "No DOI found in this document"),
+    ("copied-doi", "Copied {doi}"),
+    ("saved", "Saved"),
+    ("saved-path", "Saved {path}"),
+    ("saved-session", "Saved session {name}"),
+    ("session-not-saved", "Session not saved: {error}"),
+    ("save-failed", "Save failed: {error}"),
+    ("saved-mark", "Saved mark '{name}' at page {page}"),
+    ("jumped-to-mark", "Jumped to mark '{name}' (page {page})"),
+    ("unknown-goto-target", "No mark or page label '{name}'"),
+    ("deleted-mark", "Deleted mark {name}"),
+    ("opened-link", "Opened {uri}"),
+    ("link-failed", "Failed to open link: {error}"),
+    ("copied-link", "Copied {uri}"),
+    ("ignored-link", "Ignored {uri}"),
+    (
+        "blocked-link",
+        "Not allowed to open {uri}; copied it instead",
+    ),
+    ("link-prompt-title", "Open this link?"),
+    ("link-prompt-handler", "with: {command}"),
+    ("link-prompt-keys", "Enter open · y copy URL · Esc cancel"),
+    ("citation-failed", "Failed to resolve citation: {error}"),
+    ("reload-failed", "Failed to reload {name}: {error}"),
+    (
+        "marked-document-failed",
+        "Failed to open marked document: {error}",
+    ),
+    ("field-not-editable", "This field type cannot be edited"),
+    ("field-update-failed", "Failed to update field: {error}"),
+    ("clipboard-error", "Clipboard error: {error}"),
+    ("extract-failed", "Extract failed: {error}"),
+    ("export-failed", "Export failed: {error}"),
+    ("wrote-pages", "Wrote {count} page(s) to {path}"),
+    ("undefined-command", "Undefined command: {command}"),
+    ("unknown-option", "Unknown option: {name}"),
+    ("usage-count", "Usage: :{command} [count]"),
+    ("usage-set", "Usage: :set <option>[=<value>]"),
+    ("usage-saveas", "Usage: :saveas <path>"),
+    ("usage-page", "Usage: :page <number>"),
+    ("usage-mark", "Usage: :mark <name>"),
+    ("usage-goto", "Usage: :goto <mark|page label>"),
+    ("usage-extract", "Usage: :extract <first>-<last> <file.pdf>"),
+    ("usage-export-view", "Usage: :export-view <file.png>"),
+    (
+        "usage-zoom",
+        "Usage: :zoom [+|-]<percent>% | <scale> ({error})",
+    ),
+    ("usage-doi", "Usage: :doi [copy|open]"),
+    ("usage-fit", "Usage: :fit page|width|toggle ({error})"),
+    (
+        "usage-zoom-rect",
+        "Usage: :zoom-rect <left> <top> <right> <bottom> (fractions of the page)",
+    ),
+    (
+        "no-graphics",
+        "This terminal cannot show images; pages are shown as text",
+    ),
+    (
+        "tmux-passthrough-off",
+        "Pages cannot be shown: run `tmux set -g allow-passthrough on`",
+    ),
+    ("already-first-page", "Already at first page"),
+    ("already-last-page", "Already at last page"),
+    ("no-more-matches", "No more matches"),
+    ("already-oldest-jump", "Already at oldest jump"),
+    ("already-newest-jump", "Already at newest jump"),
+    ("not-split", "The screen is not split"),
+    ("other-pane-closed", "The other pane's document is closed"),
+    ("command-mode", "Command mode"),
+    ("status-document", "{name} — page {page} — {zoom}"),
+    ("status-fit-width", "{zoom} (width)"),
+    ("status-rebuilding", "rebuilding"),
+    ("status-reflow", "{status} — reflow"),
+    ("status-no-matches", "no matches"),
+    ("status-match", "match {index}/{total} (p. {page})"),
+    ("status-link-mode", "link"),
+    ("status-no-links", "no links"),
+    ("status-form-mode", "form"),
+    ("status-no-fields", "no fields"),
+    ("no-text-on-page", "(no text on this page)"),
+    ("extract-text-failed", "Failed to extract text: {error}"),
+    ("copied-one-link", "copied 1 link"),
+    ("copied-links", "copied {count} links"),
+    ("toc-title", "Table of Contents"),
+    ("toc-empty", "No table of contents available"),
+    ("toc-position", "{index}/{count} entries"),
+    ("marks-title", "Marks"),
+    ("jumps-title", "Jumps"),
+    ("history-title", "History"),
+    ("links-title", "Links ({shown}/{total})"),
+    ("reference-title", "Reference (p{page})"),
+    ("reference-title-labelled", "Reference {label} (p{page})"),
+    ("cache-stats-title", "Cache"),
+    (
+        "cache-stats-rendered",
+        "  Rendered pages: {pages}/{capacity} ({size})",
+    ),
+    (
+        "cache-stats-text",
+        "  Page text: {pages} pages, {size} of {capacity}",
+    ),
+    ("stats-title", "Reading statistics"),
+    ("stats-total", "Reading time: {time}"),
+    ("stats-page", "On page {page}: {time}"),
+    ("stats-pages-read", "Pages read: {read}/{count}"),
+    ("stats-finish", "Time to finish: {time}"),
+    ("stats-unknown", "not known until a page is read"),
+    ("stats-today", "Read today: {read} pages"),
+    ("goal-progress", "{read}/{goal} pages today"),
+    (
+        "goal-reached",
+        "Daily goal reached: {read}/{goal} pages today",
+    ),
+    ("info-title-bar", "Document"),
+    ("info-title", "  Title: {title}"),
+    ("info-author", "  Author: {author}"),
+    ("info-keywords", "  Keywords: {keywords}"),
+    ("info-pages", "  Pages: {count}"),
+    ("info-doi", "  DOI: {doi}"),
+    ("info-cite-key", "  Citation key: {key}"),
+    ("info-reference", "  Reference: {reference}"),
+    (
+        "info-no-bibtex",
+        "No bibliography loaded (start termpdf with --bibtex <file>)",
+    ),
+];
+
+static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Loads the translation for `lang` (or the locale from the environment)
+/// from `dir`. Called once at startup; without a translation file the UI
+/// stays in English.
+pub fn init(lang: Option<&str>, dir: &Path) {
+    let Some(lang) = lang.map(str::to_string).or_else(env_locale) else {
+        return;
+    };
+    for candidate in candidates(&lang) {
+        let path = dir.join(format!("{}.json", candidate));
+        if !path.exists() {
+            continue;
+        }
+        match load(&path) {
+            Ok(catalog) => {
+                let _ = CATALOG.set(catalog);
+            }
+            Err(err) => warn!(?err, path = %path.display(), "failed to load translation"),
+        }
+        return;
+    }
+}
+
+/// The UI language from the usual POSIX variables, if it is not English.
+fn env_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .filter(|value| !matches!(value.as_str(), "C" | "POSIX") && !value.starts_with("en"))
+}
+
+/// File names to try for a locale such as `pt_BR.UTF-8`: `pt_BR`, then `pt`.
+fn candidates(locale: &str) -> Vec<String> {
+    let base = locale.split(['.', '@']).next().unwrap_or(locale);
+    let mut names = vec![base.to_string()];
+    if let Some((language, _)) = base.split_once(['_', '-']) {
+        names.push(language.to_string());
+    }
+    names
+}
+
+fn load(path: &Path) -> Result<HashMap<String, String>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+    serde_json::from_str(&contents).with_context(|| format!("invalid translation {:?}", path))
+}
+
+/// The UI string for `key` in the current language, with placeholders
+/// filled in. Prefer the [`t!`] macro.
+pub fn text(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let template = CATALOG
+        .get()
+        .and_then(|catalog| catalog.get(key))
+        .map(String::as_str)
+        .or_else(|| english(key))
+        .unwrap_or(key);
+    fill(template, args)
+}
+
+fn english(key: &str) -> Option<&'static str> {
+    ENGLISH
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, text)| *text)
+}
+
+fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| (value.to_string(), end))
+        });
+        match value {
+            Some((value, end)) => {
+                out.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Status message for a motion that hit `boundary`.
+pub fn boundary(boundary: MotionBoundary) -> String {
+    match boundary {
+        MotionBoundary::FirstPage => t!("already-first-page"),
+        MotionBoundary::LastPage => t!("already-last-page"),
+        MotionBoundary::NoMatches => t!("no-more-matches"),
+        MotionBoundary::OldestJump => t!("already-oldest-jump"),
+        MotionBoundary::NewestJump => t!("already-newest-jump"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_filled_by_name() {
+        assert_eq!(
+            t!("saved-mark", name = "intro", page = 3),
+            "Saved mark 'intro' at page 3"
+        );
+        assert_eq!(t!("no-active-document"), "No active document");
+        assert_eq!(fill("{a} {missing} {b", &[("a", &1)]), "1 {missing} {b");
+        assert_eq!(text("not-a-key", &[]), "not-a-key");
+    }
+
+    #[test]
+    fn english_keys_are_unique() {
+        let mut keys: Vec<&str> = ENGLISH.iter().map(|(key, _)| *key).collect();
+        keys.sort_unstable();
+        keys.dedup();
+        assert_eq!(keys.len(), ENGLISH.len());
+        for boundary_kind in [MotionBoundary::FirstPage, MotionBoundary::NewestJump] {
+            assert_eq!(boundary(boundary_kind), boundary_kind.message());
+        }
+    }
+
+    #[test]
+    fn every_key_in_use_has_english_text() {
+        let source = include_str!("main.rs");
+        for (at, _) in source.match_indices("t!(") {
+            let (before, rest) = source.split_at(at + 3);
+            // Skip `bail!(`, `format!(` and the like.
+            let macro_name = before[..at].chars().next_back();
+            if macro_name.is_some_and(|ch| ch.is_alphanumeric() || ch == '_') {
+                continue;
+            }
+            let Some(key) = rest.trim_start().strip_prefix('"') else {
+                continue;
+            };
+            let key = &key[..key.find('"').unwrap()];
+            assert!(english(key).is_some(), "missing English text for {key:?}");
+        }
+    }
+
+    #[test]
+    fn locale_falls_back_to_language() {
+        assert_eq!(candidates("pt_BR.UTF-8"), ["pt_BR", "pt"]);
+        assert_eq!(candidates("de"), ["de"]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fr.json");
+        fs::write(&path, r#"{"saved-path": "{path} enregistré"}"#).unwrap();
+        let catalog = load(&path).unwrap();
+        assert_eq!(
+            fill(&catalog["saved-path"], &[("path", &"a.pdf")]),
+            "a.pdf enregistré"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead};
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::thread;
+
+use anyhow::{Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::oneshot;
+use tracing::{trace, warn};
+
+/// A single command-language line received from an IPC client. The main loop
+/// executes it and answers through `reply`.
+pub struct IpcRequest {
+    pub line: String,
+    pub reply: oneshot::Sender<IpcReply>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpcReply {
+    Ok(Option<String>),
+    Error(String),
+}
+
+impl IpcReply {
+    fn render(&self) -> String {
+        match self {
+            IpcReply::Ok(None) => "ok\n".to_string(),
+            IpcReply::Ok(Some(message)) => format!("ok {}\n", single_line(message)),
+            IpcReply::Error(message) => format!("error {}\n", single_line(message)),
+        }
+    }
+}
+
+fn single_line(message: &str) -> String {
+    message.replace(['\n', '\r'], " ")
+}
+
+/// Unix socket server accepting newline-separated commands. The socket file is
+/// removed again when the server is dropped.
+pub struct IpcServer {
+    path: PathBuf,
+}
+
+impl IpcServer {
+    pub fn bind(path: PathBuf, sender: UnboundedSender<IpcRequest>) -> Result<Self> {
+        if path.exists() {
+            if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                anyhow::bail!("another termpdf instance is listening on {:?}", path);
+            }
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove stale socket {:?}", path))?;
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("failed to bind IPC socket at {:?}", path))?;
+
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let sender = sender.clone();
+                        tokio::spawn(async move {
+                            if let Err(err) = serve_client(stream, sender).await {
+                                trace!(?err, "IPC client disconnected with error");
+                            }
+                        });
+                    }
+                    Err(err) => {
+                        warn!(?err, "failed to accept IPC connection");
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Reads commands from a named pipe, one per line. The pipe is reopened after
+/// every writer disconnects so repeated `echo cmd > fifo` calls keep working.
+pub fn spawn_fifo_reader(path: PathBuf, sender: UnboundedSender<IpcRequest>) -> Result<()> {
+    let metadata =
+        fs::metadata(&path).with_context(|| format!("failed to access command FIFO {:?}", path))?;
+    if !metadata.file_type().is_fifo() {
+        anyhow::bail!("{:?} is not a named pipe (create one with mkfifo)", path);
+    }
+
+    thread::Builder::new()
+        .name("termpdf-fifo".into())
+        .spawn(move || loop {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(err) => {
+                    warn!(?err, path = %path.display(), "failed to open command FIFO");
+                    break;
+                }
+            };
+            for line in io::BufReader::new(file).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let line = line.trim().to_string();
+                if line.is_empty() {
+                    continue;
+                }
+                let (reply_tx, reply_rx) = oneshot::channel();
+                if sender
+                    .send(IpcRequest {
+                        line: line.clone(),
+                        reply: reply_tx,
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+                if let Ok(IpcReply::Error(message)) = reply_rx.blocking_recv() {
+                    warn!(%line, %message, "FIFO command failed");
+                }
+            }
+        })
+        .context("failed to spawn FIFO reader")?;
+    Ok(())
+}
+
+async fn serve_client(stream: UnixStream, sender: UnboundedSender<IpcRequest>) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        let (reply_tx, reply_rx) = oneshot::channel();
+        if sender
+            .send(IpcRequest {
+                line,
+                reply: reply_tx,
+            })
+            .is_err()
+        {
+            writer.write_all(b"error viewer is shutting down\n").await?;
+            break;
+        }
+        let reply = reply_rx
+            .await
+            .unwrap_or_else(|_| IpcReply::Error("command dropped".to_string()));
+        writer.write_all(reply.render().as_bytes()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn reply_renders_single_line() {
+        assert_eq!(IpcReply::Ok(None).render(), "ok\n");
+        assert_eq!(
+            IpcReply::Error("bad\nthing".to_string()).render(),
+            "error bad thing\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn server_forwards_lines_and_replies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("termpdf.sock");
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let server = IpcServer::bind(path.clone(), tx).unwrap();
+
+        tokio::spawn(async move {
+            while let Some(request) = rx.recv().await {
+                let reply = if request.line == "next" {
+                    IpcReply::Ok(None)
+                } else {
+                    IpcReply::Error(format!("Undefined command: {}", request.line))
+                };
+                let _ = request.reply.send(reply);
+            }
+        });
+
+        let stream = UnixStream::connect(&path).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"next\nbogus\n").await.unwrap();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), "ok");
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "error Undefined command: bogus"
+        );
+
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn fifo_reader_survives_writer_reconnects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("termpdf.fifo");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        spawn_fifo_reader(path.clone(), tx).unwrap();
+
+        for command in ["next", "prev 2"] {
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || fs::write(path, format!("{}\n", command)))
+                .await
+                .unwrap()
+                .unwrap();
+            let request = rx.recv().await.unwrap();
+            assert_eq!(request.line, command);
+            let _ = request.reply.send(IpcReply::Ok(None));
+        }
+    }
+
+    #[test]
+    fn fifo_reader_rejects_regular_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("commands.txt");
+        fs::write(&path, "next\n").unwrap();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        assert!(spawn_fifo_reader(path, tx).is_err());
+    }
+}
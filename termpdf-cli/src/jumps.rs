@@ -0,0 +1,93 @@
+use termpdf_core::DocumentInstance;
+use termpdf_tty::ListView;
+
+use crate::outline_heading;
+
+/// One position of the jump list as `:jumps` shows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JumpEntry {
+    /// Index into the document's jump list.
+    pub index: usize,
+    /// `Ctrl-o`/`Ctrl-i` presses it takes to get there.
+    pub distance: usize,
+    pub page: usize,
+    /// Outline heading at or before the page.
+    pub heading: Option<String>,
+    /// The first line of text on the page.
+    pub preview: Option<String>,
+}
+
+impl JumpEntry {
+    pub fn text(&self) -> String {
+        let mut text = format!("{:>3}  p{:<5}", self.distance, self.page + 1);
+        let details: Vec<&str> = [self.heading.as_deref(), self.preview.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect();
+        text.push_str(&details.join(" · "));
+        text.trim_end().to_string()
+    }
+}
+
+/// The `:jumps` overlay: the active document's jump list, oldest first.
+#[derive(Debug, Clone)]
+pub struct JumpsWindow {
+    pub entries: Vec<JumpEntry>,
+    pub list: ListView,
+    pub current: usize,
+}
+
+impl JumpsWindow {
+    /// `None` while there is nowhere to jump to.
+    pub fn from_document(doc: &DocumentInstance) -> Option<Self> {
+        let list = doc.jump_list();
+        if list.entries.len() < 2 {
+            return None;
+        }
+        let entries = list
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, position)| JumpEntry {
+                index,
+                distance: index.abs_diff(list.current),
+                page: position.page,
+                heading: outline_heading(doc.outline(), position.page),
+                preview: doc.page_preview(position.page),
+            })
+            .collect::<Vec<_>>();
+        Some(Self {
+            list: ListView::new(entries.len()).with_selected(list.current),
+            entries,
+            current: list.current,
+        })
+    }
+
+    pub fn selected_entry(&self) -> Option<&JumpEntry> {
+        self.entries.get(self.list.selected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_show_distance_page_and_context() {
+        let entry = JumpEntry {
+            index: 0,
+            distance: 2,
+            page: 11,
+            heading: Some("Results".to_string()),
+            preview: Some("Table 3 compares".to_string()),
+        };
+        assert_eq!(entry.text(), "  2  p12   Results · Table 3 compares");
+
+        let bare = JumpEntry {
+            heading: None,
+            preview: None,
+            ..entry
+        };
+        assert_eq!(bare.text(), "  2  p12");
+    }
+}
@@ -0,0 +1,327 @@
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, Context, Result};
+use termpdf_core::ExternalLink;
+use url::Url;
+
+/// What following an external link does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkAction {
+    /// Hand it to the system opener (`open` / `xdg-open`)
+    Open,
+    /// Do nothing
+    Ignore,
+    /// Copy the URL to the clipboard
+    Copy,
+    /// Run this program with the URL as its last argument
+    Run(Vec<String>),
+    /// Copy the URL because the allow and deny lists keep it from being
+    /// launched
+    Blocked,
+}
+
+impl LinkAction {
+    /// Whether the action starts another program, which is what the
+    /// confirmation prompt guards.
+    pub fn launches(&self) -> bool {
+        matches!(self, LinkAction::Open | LinkAction::Run(_))
+    }
+}
+
+/// An `--allow-links` / `--deny-links` entry: `mailto:` matches a scheme,
+/// `example.com` a domain and its subdomains, `https://example.com` both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPattern {
+    scheme: Option<String>,
+    domain: Option<String>,
+}
+
+impl LinkPattern {
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        let (scheme, domain) = match value.split_once("://") {
+            Some((scheme, domain)) => (Some(scheme), Some(domain)),
+            None => match value.strip_suffix(':') {
+                Some(scheme) => (Some(scheme), None),
+                None => (None, Some(value.as_str())),
+            },
+        };
+        let scheme = scheme.filter(|scheme| !scheme.is_empty());
+        let domain = domain
+            .map(|domain| {
+                domain
+                    .trim_start_matches("*.")
+                    .trim_end_matches('/')
+                    .trim_end_matches('.')
+            })
+            .filter(|domain| !domain.is_empty());
+        if scheme.is_none() && domain.is_none() {
+            return Err(anyhow!(
+                "expected a scheme (https:) or a domain, got '{}'",
+                value
+            ));
+        }
+        Ok(Self {
+            scheme: scheme.map(str::to_string),
+            domain: domain.map(str::to_string),
+        })
+    }
+
+    fn matches(&self, target: &ExternalLink) -> bool {
+        if let Some(scheme) = &self.scheme {
+            if *scheme != self::scheme(target) {
+                return false;
+            }
+        }
+        let Some(domain) = &self.domain else {
+            return true;
+        };
+        let Some(host) = host(target) else {
+            return false;
+        };
+        host == *domain
+            || host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    }
+}
+
+/// Per-scheme link actions, which links may start a program at all, and
+/// whether to ask before doing so.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPolicy {
+    pub confirm: bool,
+    handlers: Vec<(String, LinkAction)>,
+    allow: Vec<LinkPattern>,
+    deny: Vec<LinkPattern>,
+}
+
+impl Default for LinkPolicy {
+    fn default() -> Self {
+        Self {
+            confirm: true,
+            handlers: Vec::new(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+impl LinkPolicy {
+    pub fn new(confirm: bool, handlers: Vec<(String, LinkAction)>) -> Self {
+        Self {
+            confirm,
+            handlers,
+            ..Self::default()
+        }
+    }
+
+    /// Only links matching `allow` (when it is not empty) and none of
+    /// `deny` may start a program.
+    pub fn with_lists(mut self, allow: Vec<LinkPattern>, deny: Vec<LinkPattern>) -> Self {
+        self.allow = allow;
+        self.deny = deny;
+        self
+    }
+
+    /// The action for `target`'s scheme; later handlers win. Links the
+    /// lists keep from starting a program are only copied.
+    pub fn action_for(&self, target: &ExternalLink) -> LinkAction {
+        let scheme = scheme(target);
+        let action = self
+            .handlers
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == scheme)
+            .map(|(_, action)| action.clone())
+            .unwrap_or(LinkAction::Open);
+        if action.launches() && !self.permits(target) {
+            return LinkAction::Blocked;
+        }
+        action
+    }
+
+    fn permits(&self, target: &ExternalLink) -> bool {
+        if self.deny.iter().any(|pattern| pattern.matches(target)) {
+            return false;
+        }
+        // A web link without a host that can be read could be on any of
+        // the denied domains.
+        let web = matches!(scheme(target).as_str(), "http" | "https");
+        if web && host(target).is_none() && self.deny.iter().any(|pattern| pattern.domain.is_some())
+        {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|pattern| pattern.matches(target))
+    }
+}
+
+static POLICY: OnceLock<LinkPolicy> = OnceLock::new();
+
+/// Sets the policy; called once at startup from the config file's
+/// `[links]` table, `--link-handler`, `--allow-links`, `--deny-links` and
+/// `--no-confirm-links`.
+pub fn init(policy: LinkPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+pub fn policy() -> &'static LinkPolicy {
+    POLICY.get_or_init(LinkPolicy::default)
+}
+
+/// Parses a `--link-handler` value such as `http=firefox`,
+/// `mailto=ignore` or `https=copy`.
+pub fn parse_handler(value: &str) -> Result<(String, LinkAction)> {
+    let (scheme, action) = value
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected SCHEME=ACTION, e.g. http=firefox"))?;
+    let scheme = scheme.trim().to_ascii_lowercase();
+    if scheme.is_empty() {
+        return Err(anyhow!("missing scheme in '{}'", value));
+    }
+    let action = match action.trim() {
+        "" => return Err(anyhow!("missing action for '{}'", scheme)),
+        "open" => LinkAction::Open,
+        "ignore" => LinkAction::Ignore,
+        "copy" => LinkAction::Copy,
+        command => LinkAction::Run(command.split_whitespace().map(str::to_string).collect()),
+    };
+    Ok((scheme, action))
+}
+
+/// Lower-case URI scheme of `target`; file links are `file`.
+pub fn scheme(target: &ExternalLink) -> String {
+    match target {
+        ExternalLink::Url(uri) => uri
+            .split_once(':')
+            .map(|(scheme, _)| scheme.trim().to_ascii_lowercase())
+            .unwrap_or_default(),
+        ExternalLink::File(_) => "file".to_string(),
+    }
+}
+
+/// The link's host in lower case, without the trailing dot of a fully
+/// qualified name; `None` when it has none or does not parse.
+fn host(target: &ExternalLink) -> Option<String> {
+    let ExternalLink::Url(uri) = target else {
+        return None;
+    };
+    let url = Url::parse(uri).ok()?;
+    let host = url.host_str()?.trim_end_matches('.').to_ascii_lowercase();
+    (!host.is_empty()).then_some(host)
+}
+
+/// The link as shown to the user and passed to handlers.
+pub fn display(target: &ExternalLink) -> String {
+    match target {
+        ExternalLink::Url(uri) => uri.clone(),
+        ExternalLink::File(path) => path.display().to_string(),
+    }
+}
+
+/// Starts `command` on `target` without waiting for it, so a browser does
+/// not hold up the viewer.
+pub fn spawn(command: &[String], target: &ExternalLink) -> Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("empty link handler"))?;
+    Command::new(program)
+        .args(args)
+        .arg(display(target))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("failed to start '{}'", program))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handlers_are_chosen_by_scheme() {
+        let handlers = ["http=firefox --new-tab", "mailto=ignore", "HTTPS=copy"]
+            .iter()
+            .map(|value| parse_handler(value).unwrap())
+            .collect();
+        let policy = LinkPolicy::new(true, handlers);
+        let url = |uri: &str| ExternalLink::Url(uri.to_string());
+        assert_eq!(
+            policy.action_for(&url("http://example.com")),
+            LinkAction::Run(vec!["firefox".into(), "--new-tab".into()])
+        );
+        assert_eq!(
+            policy.action_for(&url("mailto:someone@example.com")),
+            LinkAction::Ignore
+        );
+        assert_eq!(
+            policy.action_for(&url("https://example.com")),
+            LinkAction::Copy
+        );
+        assert_eq!(policy.action_for(&url("doi:10.1000/1")), LinkAction::Open);
+        assert_eq!(
+            policy.action_for(&ExternalLink::File("/tmp/a.pdf".into())),
+            LinkAction::Open
+        );
+
+        assert!(parse_handler("firefox").is_err());
+        assert!(parse_handler("http=").is_err());
+        assert!(parse_handler("=copy").is_err());
+    }
+
+    #[test]
+    fn lists_limit_what_may_be_launched() {
+        let patterns = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| LinkPattern::parse(value).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let handlers = vec![parse_handler("mailto=ignore").unwrap()];
+        let policy = LinkPolicy::new(true, handlers).with_lists(
+            patterns(&["https:", "*.arxiv.org", "http://doi.org"]),
+            patterns(&["evil.arxiv.org"]),
+        );
+        let url = |uri: &str| policy.action_for(&ExternalLink::Url(uri.to_string()));
+        assert_eq!(url("https://example.com/paper"), LinkAction::Open);
+        assert_eq!(url("http://export.arxiv.org/abs/1"), LinkAction::Open);
+        assert_eq!(url("http://doi.org/10.1000/1"), LinkAction::Open);
+        assert_eq!(url("http://example.com"), LinkAction::Blocked);
+        assert_eq!(url("http://notarxiv.org"), LinkAction::Blocked);
+        assert_eq!(url("https://evil.arxiv.org/x"), LinkAction::Blocked);
+        // Only launching is limited.
+        assert_eq!(url("mailto:someone@example.com"), LinkAction::Ignore);
+        assert_eq!(
+            policy.action_for(&ExternalLink::File("/tmp/a.pdf".into())),
+            LinkAction::Blocked
+        );
+
+        assert!(LinkPattern::parse("").is_err());
+        assert!(LinkPattern::parse("://").is_err());
+    }
+
+    #[test]
+    fn denied_domains_cannot_be_dodged() {
+        let deny = vec![LinkPattern::parse("Tracker.Example.").unwrap()];
+        let policy = LinkPolicy::new(true, Vec::new()).with_lists(Vec::new(), deny);
+        let url = |uri: &str| policy.action_for(&ExternalLink::Url(uri.to_string()));
+        assert_eq!(url("https://tracker.example./x"), LinkAction::Blocked);
+        assert_eq!(url("https://ads.TRACKER.example/x"), LinkAction::Blocked);
+        assert_eq!(url("https://example.com/x"), LinkAction::Open);
+        // Web links whose host cannot be read might be on the domain.
+        assert_eq!(url("https://"), LinkAction::Blocked);
+        assert_eq!(url("http://exa mple.com/"), LinkAction::Blocked);
+        assert_eq!(url("mailto:someone@example.com"), LinkAction::Open);
+
+        // Without a denied domain they are left alone.
+        let schemes = vec![LinkPattern::parse("ftp:").unwrap()];
+        let policy = LinkPolicy::new(true, Vec::new()).with_lists(Vec::new(), schemes);
+        assert_eq!(
+            policy.action_for(&ExternalLink::Url("https://".into())),
+            LinkAction::Open
+        );
+    }
+}
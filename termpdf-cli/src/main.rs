@@ -1,28 +1,38 @@
 use std::ffi::OsStr;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Read, Write};
+use std::ops::{Range, RangeInclusive};
 use std::path::{Path, PathBuf};
 use std::process::Command as ProcessCommand;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
 use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{anyhow, Context, Result};
 use arboard::Clipboard;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use crossterm::cursor;
 use crossterm::event;
-use crossterm::style::{Attribute, Color, Print, SetAttribute, SetForegroundColor};
+use crossterm::style::Color;
 use crossterm::terminal::{self, Clear, ClearType};
 use directories::ProjectDirs;
+use termpdf_core::geometry::{rect_to_pixels, viewport_origin, PixelCrop, PixelRect};
 use termpdf_core::{
-    Command, DocumentId, DocumentInstance, ExternalLink, FileStateStore, Highlights,
-    NormalizedRect, OutlineItem, RenderImage, SearchMatch, SelectionMotion, Session, SessionEvent,
-    StateStore,
+    document_id_for_bytes, document_id_for_path, is_global_mark, system_clock, unix_seconds,
+    CancelToken, CitationPreview, Command, DocumentId, DocumentInstance, DocumentPosition,
+    DocumentProvider, ExternalLink, FileSessionStore, FileStateStore, FinishedRender, FitMode,
+    FormFieldKind, GlobalMarks, HighlightStyle, Highlights, LinkFilter, MemoryLimits,
+    MotionBoundary, NormalizedRect, OutlineItem, PageSize, PrefetchDirection, PrefetchPolicy,
+    ProviderRegistry, QuoteFormat, QuoteTemplates, RecentFiles, RenderCancelled, RenderImage,
+    RenderRequest, SearchMatch, SearchScope, SelectionMotion, Session, SessionEvent, SharedClock,
+    StateKey, StateStore, StepSizes, SupervisedProvider, UriLink, ViewGeometry,
 };
 use termpdf_render::PdfRenderFactory;
 use termpdf_tty::{
-    write_status_line, DrawParams, EventMapper, InputMode, KittyRenderer, UiEvent,
-    VisualMotion as TtyVisualMotion,
+    cluster_width, display_width, graphemes, truncate_with_ellipsis, Area, BorderedWindow, Buffer,
+    Capabilities, EventMapper, ImageEncoding, InputMode, KittyRenderer, Layer, ListRow, ListView,
+    Passthrough, Placement, Style, TransferMedium, UiEvent, VisualMotion as TtyVisualMotion,
 };
 use tokio::sync::mpsc::error::TryRecvError;
 use tokio::sync::mpsc::{self, UnboundedSender};
@@ -32,6 +42,43 @@ use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::{prelude::*, EnvFilter};
 use url::Url;
 
+mod autosave;
+mod bibtex;
+mod browse;
+mod config;
+mod feedback;
+mod fetch;
+mod generate;
+mod history;
+mod i18n;
+mod ipc;
+mod jumps;
+mod links;
+mod matching;
+mod notifications;
+mod panes;
+mod reading;
+mod status_file;
+mod tmux;
+mod watch;
+
+use autosave::{Autosave, SaveOnPanic};
+use bibtex::Bibliography;
+use browse::FileBrowser;
+use config::Config;
+use feedback::Feedback;
+use generate::Generate;
+use history::HistoryWindow;
+use i18n::t;
+use ipc::{IpcReply, IpcRequest, IpcServer};
+use jumps::{JumpEntry, JumpsWindow};
+use links::{LinkAction, LinkPattern, LinkPolicy};
+use notifications::NotifyEvent;
+use panes::{Orientation, PaneArea, Panes};
+use reading::ReadingTimer;
+use status_file::{StatusFile, StatusFormat};
+use watch::FileWatcher;
+
 #[derive(Debug, Parser)]
 #[command(
     name = "termpdf",
@@ -39,60 +86,328 @@ use url::Url;
     about = "kitty-native PDF viewer rewritten in Rust"
 )]
 struct Args {
-    /// Page to open each document on (0-based)
+    /// Page to open each document on (0-based); `file.pdf:N` overrides it per file
     #[arg(short = 'p', long = "page")]
     page: Option<usize>,
 
-    /// Paths to PDF files to open
-    #[arg(required = true)]
-    files: Vec<PathBuf>,
+    /// Settings file to read instead of `config.toml` in the config
+    /// directory; flags override its settings
+    #[arg(long = "config", value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Listen for commands on a Unix socket at this path
+    #[arg(long = "listen", value_name = "SOCKET")]
+    listen: Option<PathBuf>,
+
+    /// Seconds a page may take to render before it is replaced by a placeholder (0 waits forever)
+    #[arg(
+        long = "render-timeout",
+        value_name = "SECONDS",
+        default_value_t = 10.0
+    )]
+    render_timeout: f64,
+
+    /// Send desktop notifications for these events (comma separated)
+    #[arg(
+        long = "notify",
+        value_name = "EVENTS",
+        value_enum,
+        value_delimiter = ','
+    )]
+    notify: Vec<NotifyEvent>,
+
+    /// What to do when a motion hits the first or last page or runs out of
+    /// matches: bell, flash or none
+    #[arg(
+        long = "feedback",
+        value_name = "MODE",
+        value_enum,
+        default_value_t = Feedback::Bell
+    )]
+    feedback: Feedback,
+
+    /// What following an external link does, per URI scheme: open, ignore,
+    /// copy, or a command run with the URL (e.g. `http=firefox`,
+    /// `mailto=ignore`); may be repeated
+    #[arg(
+        long = "link-handler",
+        value_name = "SCHEME=ACTION",
+        value_parser = links::parse_handler
+    )]
+    link_handlers: Vec<(String, LinkAction)>,
+
+    /// Only these links may start a program (comma separated): schemes
+    /// such as `https:`, domains such as `arxiv.org` (with subdomains) or
+    /// both, as in `https://doi.org`. Other links are copied instead
+    #[arg(
+        long = "allow-links",
+        value_name = "PATTERNS",
+        value_delimiter = ',',
+        value_parser = LinkPattern::parse
+    )]
+    allow_links: Vec<LinkPattern>,
+
+    /// Links that may never start a program, in the form of
+    /// `--allow-links`; they are copied instead
+    #[arg(
+        long = "deny-links",
+        value_name = "PATTERNS",
+        value_delimiter = ',',
+        value_parser = LinkPattern::parse
+    )]
+    deny_links: Vec<LinkPattern>,
+
+    /// Open external links without asking first
+    #[arg(long = "no-confirm-links")]
+    no_confirm_links: bool,
+
+    /// BibTeX file (e.g. a Zotero Better BibTeX export) to look the open
+    /// document up in for `:info` and `:cite`; repeatable
+    #[arg(long = "bibtex", value_name = "FILE")]
+    bibtex: Vec<PathBuf>,
+
+    /// How page images reach kitty: direct, file or shm (defaults to shared
+    /// memory or a file when kitty could read one at startup, direct
+    /// transfer otherwise)
+    #[arg(long = "transfer", value_name = "MEDIUM", value_parser = parse_transfer)]
+    transfer: Option<TransferMedium>,
+
+    /// How graphics get past a terminal multiplexer: none or tmux (defaults
+    /// to tmux when running inside it)
+    #[arg(long = "passthrough", value_name = "MODE", value_parser = parse_passthrough)]
+    passthrough: Option<Passthrough>,
+
+    /// How pages are shown: auto, kitty or text (auto asks the terminal
+    /// whether it supports kitty's graphics and shows text otherwise)
+    #[arg(long = "graphics", value_name = "MODE", value_enum, default_value_t = GraphicsMode::Auto)]
+    graphics: GraphicsMode,
+
+    /// How page pixels are packed: png, raw or zlib (defaults to raw with
+    /// shared memory or files, zlib for direct transfer)
+    #[arg(long = "encoding", value_name = "FORMAT", value_parser = parse_encoding)]
+    encoding: Option<ImageEncoding>,
+
+    /// Width of highlight outlines in PDF points (0 disables them; defaults
+    /// to 1)
+    #[arg(
+        long = "highlight-width",
+        value_name = "POINTS",
+        value_parser = parse_highlight_width
+    )]
+    highlight_width: Option<f32>,
+
+    /// Multiplier for the fill opacity of highlights (defaults to 1)
+    #[arg(
+        long = "highlight-opacity",
+        value_name = "FACTOR",
+        value_parser = parse_highlight_opacity
+    )]
+    highlight_opacity: Option<f32>,
+
+    /// Distance a held pan key moves the view per repeat, as a fraction of
+    /// the page; a single press moves a quarter of it (defaults to 0.1)
+    #[arg(
+        long = "pan-step",
+        value_name = "FRACTION",
+        value_parser = parse_pan_step
+    )]
+    pan_step: Option<f32>,
+
+    /// Zoom change per `+`/`-` press, as a fraction of the current scale
+    /// (defaults to 0.1)
+    #[arg(
+        long = "zoom-step",
+        value_name = "FRACTION",
+        value_parser = parse_zoom_step
+    )]
+    zoom_step: Option<f32>,
+
+    /// Neighbouring pages to render ahead of time (0 disables prefetching;
+    /// defaults to 2, or 0 with --low-memory or when little memory is
+    /// available)
+    #[arg(long = "prefetch", value_name = "PAGES")]
+    prefetch: Option<usize>,
+
+    /// Which neighbours to prefetch: both (the default), forward or
+    /// backward
+    #[arg(
+        long = "prefetch-direction",
+        value_name = "DIRECTION",
+        value_parser = parse_prefetch_direction
+    )]
+    prefetch_direction: Option<PrefetchDirection>,
+
+    /// Keep memory use down for small containers and phones: no
+    /// prefetching, small caches and pages rendered at half resolution
+    #[arg(long = "low-memory")]
+    low_memory: bool,
+
+    /// Like `tail -f`: when a reload adds pages while you are on the last
+    /// page, go on to the new last page (same as `:set follow`)
+    #[arg(long = "follow")]
+    follow: bool,
+
+    /// After a reload, go to the first page whose text changed, e.g. where
+    /// a LaTeX edit landed (same as `:set jumptochange`)
+    #[arg(long = "jump-to-change")]
+    jump_to_change: bool,
+
+    /// Template for copying a selection as a Markdown quote (`gy` in visual
+    /// mode); see the README for its placeholders
+    #[arg(long = "quote-markdown", value_name = "TEMPLATE")]
+    quote_markdown: Option<String>,
+
+    /// Template for copying a selection as a LaTeX quote (`gY`)
+    #[arg(long = "quote-latex", value_name = "TEMPLATE")]
+    quote_latex: Option<String>,
+
+    /// Read commands line by line from an existing named pipe
+    #[arg(long = "commands-from", value_name = "FIFO")]
+    commands_from: Option<PathBuf>,
+
+    /// Keep reading positions in a synced folder (e.g. Dropbox or Syncthing),
+    /// matched by file content so copies on other machines share them
+    #[arg(long = "sync-dir", value_name = "DIR")]
+    sync_dir: Option<PathBuf>,
+
+    /// Keep saved state and recent files in this SQLite database instead of
+    /// one JSON file per document; existing JSON state is imported when the
+    /// database is created (requires building with `--features sqlite`)
+    #[arg(long = "state-db", value_name = "FILE", conflicts_with = "sync_dir")]
+    state_db: Option<PathBuf>,
+
+    /// Identify documents for saved state by path or by content (default:
+    /// path, or content with --sync-dir)
+    #[arg(long = "identity", value_name = "MODE", value_parser = parse_state_key)]
+    identity: Option<StateKey>,
+
+    /// Keep this file up to date with the document and page being read, for
+    /// status bars and scripts; it is removed on exit
+    #[arg(long = "status-file", value_name = "PATH")]
+    status_file: Option<PathBuf>,
+
+    /// Layout of the status file: json or plain
+    #[arg(
+        long = "status-format",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = StatusFormat::Json
+    )]
+    status_format: StatusFormat,
+
+    /// Language of the interface, e.g. `de` or `pt_BR` (defaults to the
+    /// locale); translations are read from `locales/<lang>.json` in the
+    /// config directory
+    #[arg(long = "lang", value_name = "LANG")]
+    lang: Option<String>,
+
+    /// Restore the named session (if saved before) and save it again on exit
+    #[arg(long = "session", value_name = "NAME")]
+    session: Option<String>,
+
+    /// Reopen the most recently viewed document
+    #[arg(long = "resume", conflicts_with = "files")]
+    resume: bool,
+
+    /// Paths to PDF files to open, optionally suffixed with `:N` to start on page N (1-based);
+    /// `-` reads a PDF from standard input and http(s) URLs are downloaded. Without any, a list
+    /// of recent documents is offered
+    #[arg(value_parser = parse_file_arg)]
+    files: Vec<FileArg>,
+
+    #[command(subcommand)]
+    generate: Option<Generate>,
 }
 
-const FILE_POLL_INTERVAL_MS: u64 = 300;
-const STATUS_MESSAGE_TTL: Duration = Duration::from_millis(1500);
-
-#[cfg(target_os = "macos")]
-const OPEN_COMMAND: &str = "open";
-#[cfg(all(unix, not(target_os = "macos")))]
-const OPEN_COMMAND: &str = "xdg-open";
-
-struct WatchedDocument {
-    id: DocumentId,
+/// A document path from the command line with its optional starting page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileArg {
     path: PathBuf,
-    last_modified: Option<SystemTime>,
-    last_checked: Instant,
+    page: Option<usize>,
 }
 
-impl WatchedDocument {
-    fn new(id: DocumentId, path: PathBuf) -> Self {
-        let last_modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
-        Self {
-            id,
-            path,
-            last_modified,
-            last_checked: Instant::now(),
-        }
+/// Splits `file.pdf:12` into the path and zero-based page 11. A path that
+/// exists as written is taken literally, so names containing colons still open.
+fn parse_file_arg(value: &str) -> Result<FileArg, String> {
+    let literal = FileArg {
+        path: PathBuf::from(value),
+        page: None,
+    };
+    if literal.path.exists() {
+        return Ok(literal);
     }
+    let Some((path, suffix)) = value.rsplit_once(':') else {
+        return Ok(literal);
+    };
+    if path.is_empty() || suffix.is_empty() || !suffix.bytes().all(|b| b.is_ascii_digit()) {
+        return Ok(literal);
+    }
+    let page = suffix
+        .parse::<usize>()
+        .ok()
+        .filter(|page| *page > 0)
+        .ok_or_else(|| format!("invalid page number in {value:?}"))?;
+    Ok(FileArg {
+        path: PathBuf::from(path),
+        page: Some(page - 1),
+    })
+}
 
-    fn should_check(&self, interval: Duration) -> bool {
-        self.last_checked.elapsed() >= interval
-    }
+/// File argument that reads the document from standard input.
+const STDIN_ARG: &str = "-";
+
+/// Saves a document piped in on stdin to `dir` so it can be opened like any
+/// other file.
+fn stash_stdin(dir: &Path) -> Result<PathBuf> {
+    let mut stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Err(anyhow!(
+            "`-` reads a PDF from standard input; pipe one in, e.g. `curl -sL <url> | termpdf -`"
+        ));
+    }
+    let mut bytes = Vec::new();
+    stdin
+        .read_to_end(&mut bytes)
+        .context("failed to read standard input")?;
+    stash_bytes(dir, &bytes)
+}
 
-    fn mark_checked(&mut self) {
-        self.last_checked = Instant::now();
+/// Writes `bytes` to a file named after their content, so piping the same
+/// document again reopens it with its saved state.
+fn stash_bytes(dir: &Path, bytes: &[u8]) -> Result<PathBuf> {
+    if bytes.is_empty() {
+        return Err(anyhow!("nothing was piped to standard input"));
     }
-
-    fn update_snapshot(&mut self, modified: Option<SystemTime>) {
-        self.last_modified = modified;
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {:?}", dir))?;
+    let path = dir.join(format!("stdin-{}.pdf", document_id_for_bytes(bytes)));
+    if !path.exists() {
+        fs::write(&path, bytes).with_context(|| format!("failed to write {:?}", path))?;
     }
+    Ok(path)
 }
 
+const STATUS_MESSAGE_TTL: Duration = Duration::from_millis(1500);
+
+/// The daily goal's progress is shown for longer at startup, while the
+/// first page is still being drawn.
+const GOAL_MESSAGE_TTL: Duration = Duration::from_secs(5);
+
+/// Where `:doi open` looks a DOI up.
+const DOI_RESOLVER: &str = "https://doi.org/";
+
+#[cfg(target_os = "macos")]
+const OPEN_COMMAND: &str = "open";
+#[cfg(all(unix, not(target_os = "macos")))]
+const OPEN_COMMAND: &str = "xdg-open";
+
 struct SearchResultMessage {
     token: u64,
     doc_id: DocumentId,
     start_page: usize,
     query: String,
+    scope: SearchScope,
     result: Result<Vec<SearchMatch>>,
+    elapsed: Duration,
 }
 
 struct ActiveSearch {
@@ -104,6 +419,8 @@ struct SearchManager {
     sender: UnboundedSender<SearchResultMessage>,
     active: Option<ActiveSearch>,
     next_token: u64,
+    /// Scope of the search being typed at the prompt.
+    prompt_scope: SearchScope,
 }
 
 impl SearchManager {
@@ -112,9 +429,14 @@ impl SearchManager {
             sender,
             active: None,
             next_token: 0,
+            prompt_scope: SearchScope::Document,
         }
     }
 
+    fn begin_prompt(&mut self, scope: SearchScope) {
+        self.prompt_scope = scope;
+    }
+
     fn start_search(&mut self, session: &Session, query: String) {
         let trimmed = query.trim().to_string();
         if trimmed.is_empty() {
@@ -130,19 +452,24 @@ impl SearchManager {
         let context = doc.search_context();
         let doc_id = doc.info.id;
         let start_page = doc.current_page();
+        let scope = self.prompt_scope;
+        let pages = doc.search_pages(scope);
         let token = self.next_token;
         self.next_token = self.next_token.wrapping_add(1);
         self.active = Some(ActiveSearch { token, doc_id });
         let tx = self.sender.clone();
 
         task::spawn_blocking(move || {
-            let matches = context.build_search_matches(&trimmed);
+            let started = Instant::now();
+            let matches = context.build_search_matches(&trimmed, pages);
             let _ = tx.send(SearchResultMessage {
                 token,
                 doc_id,
                 start_page,
                 query: trimmed,
+                scope,
                 result: matches,
+                elapsed: started.elapsed(),
             });
         });
     }
@@ -172,8 +499,10 @@ impl SearchManager {
                 session.apply_search_results(
                     message.doc_id,
                     message.query,
+                    message.scope,
                     matches,
                     message.start_page,
+                    message.elapsed,
                 )?;
                 Ok(true)
             }
@@ -185,73 +514,465 @@ impl SearchManager {
     }
 }
 
+/// Cell size in pixels as the terminal reported it at startup, for ttys
+/// that do not report their pixel size.
+static QUERIED_CELL_SIZE: OnceLock<Option<(u32, u32)>> = OnceLock::new();
+
+/// How long to wait for the terminal to say what it supports. Terminals
+/// answer at once, so this only runs out over slow remote connections,
+/// which it is long enough for.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The window's size in pixels as the tty reports it, or else from the
+/// cell size the terminal reported; zero when neither is known.
+fn window_pixels(window: &terminal::WindowSize) -> (u32, u32) {
+    if window.width > 0 && window.height > 0 {
+        return (u32::from(window.width), u32::from(window.height));
+    }
+    match QUERIED_CELL_SIZE.get().copied().flatten() {
+        Some((cell_width, cell_height)) => (
+            cell_width * u32::from(window.columns),
+            cell_height * u32::from(window.rows),
+        ),
+        None => (0, 0),
+    }
+}
+
+/// How graphics reach the terminal, for cleaning up without the renderer;
+/// `None` when the terminal cannot show them.
+static GRAPHICS: OnceLock<Option<Passthrough>> = OnceLock::new();
+
+/// How pages are shown, from `--graphics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+enum GraphicsMode {
+    /// Images when the terminal supports kitty's graphics, text otherwise
+    #[default]
+    Auto,
+    /// Images, whatever the terminal answers
+    Kitty,
+    /// Reflowed text only
+    Text,
+}
+
+/// How long the terminal has to stay the same size before tmux is asked
+/// where the pane moved; dragging a border resizes it many times.
+const PANE_ORIGIN_DELAY: Duration = Duration::from_millis(200);
+
+/// Tells the renderer where the tmux pane starts in the terminal, as
+/// placements are positioned in the terminal's cells rather than the
+/// pane's.
+fn update_pane_origin<W: Write>(renderer: &mut KittyRenderer<W>) {
+    if let Some((column, row)) = tmux::pane_origin() {
+        renderer.set_origin(column, row);
+    }
+}
+
 struct RawModeGuard;
 
 impl RawModeGuard {
     fn new() -> anyhow::Result<Self> {
-        terminal::enable_raw_mode()?;
+        restore_on_panic(restore_terminal);
+        take_over_terminal()?;
         Ok(Self)
     }
 }
 
+/// Runs `restore` before the panic message when the calling thread, which
+/// owns the terminal, panics. Panics on other threads are left alone: the
+/// render watchdog and the document workers catch them and carry on, so
+/// the terminal must stay as it is.
+fn restore_on_panic(restore: impl Fn() + Send + Sync + 'static) {
+    let owner = thread::current().id();
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if thread::current().id() == owner {
+            // Leave raw mode first so the panic message is readable.
+            restore();
+        }
+        default_hook(info);
+    }));
+}
+
+fn take_over_terminal() -> Result<()> {
+    terminal::enable_raw_mode()?;
+    // Save the terminal's window title so it can be restored on exit.
+    let _ = write!(io::stdout(), "\x1b[22;0t");
+    // Reading time only counts while the terminal has focus.
+    let _ = crossterm::execute!(io::stdout(), event::EnableFocusChange);
+    Ok(())
+}
+
 impl Drop for RawModeGuard {
     fn drop(&mut self) {
-        let _ = terminal::disable_raw_mode();
-        let mut stdout = io::stdout();
-        let _ = crossterm::execute!(stdout, cursor::Show);
+        restore_terminal();
+    }
+}
+
+/// Flags the main loop checks for the signals termpdf handles itself.
+struct Signals {
+    /// SIGTERM, SIGHUP or SIGINT: quit as on `q`, saving state and restoring
+    /// the terminal. A second signal exits right away.
+    terminate: Arc<AtomicBool>,
+    /// SIGTSTP sent from outside: suspend as on `Ctrl-z`.
+    stop: Arc<AtomicBool>,
+    /// SIGCONT: running again after being stopped some other way, such as
+    /// by SIGSTOP, so the terminal needs setting up and redrawing.
+    resumed: Arc<AtomicBool>,
+}
+
+impl Signals {
+    fn register() -> Result<Self> {
+        use signal_hook::consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP};
+        use signal_hook::flag;
+
+        let signals = Self {
+            terminate: Arc::new(AtomicBool::new(false)),
+            stop: Arc::new(AtomicBool::new(false)),
+            resumed: Arc::new(AtomicBool::new(false)),
+        };
+        for signal in [SIGTERM, SIGHUP, SIGINT] {
+            flag::register_conditional_shutdown(signal, 1, signals.terminate.clone())?;
+            flag::register(signal, signals.terminate.clone())?;
+        }
+        flag::register(SIGTSTP, signals.stop.clone())?;
+        flag::register(SIGCONT, signals.resumed.clone())?;
+        Ok(signals)
+    }
+}
+
+/// Hands the terminal back to the shell and stops until `fg`, then takes
+/// the terminal over again. The caller redraws everything.
+fn suspend(renderer: &mut KittyRenderer<io::Stdout>, signals: &Signals) -> Result<()> {
+    renderer.delete_all()?;
+    renderer.clear_all()?;
+    restore_terminal();
+    signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)?;
+    // Stopped here until the shell continues the process.
+    signals.resumed.store(false, Ordering::Relaxed);
+    take_over_terminal()?;
+    crossterm::execute!(renderer.writer(), cursor::Hide)?;
+    Ok(())
+}
+
+fn restore_terminal() {
+    let _ = terminal::disable_raw_mode();
+    feedback::cancel_flash();
+    let mut stdout = io::stdout();
+    if let Some(passthrough) = GRAPHICS.get().copied().unwrap_or(Some(Passthrough::None)) {
+        let _ = termpdf_tty::delete_all_images(&mut stdout, passthrough);
     }
+    let _ = write!(stdout, "\x1b[23;0t");
+    let _ = crossterm::execute!(stdout, event::DisableFocusChange, cursor::Show);
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    if args.files.is_empty() {
-        return Err(anyhow!("no input files provided"));
+    if let Some(generate) = &args.generate {
+        return generate::run(generate, &mut io::stdout());
     }
+    notifications::init(&args.notify)?;
+    feedback::init(args.feedback);
+    bibtex::init(Bibliography::load(&args.bibtex)?);
 
     let project_dirs = ProjectDirs::from("net", "termpdf", "termpdf")
         .ok_or_else(|| anyhow!("unable to resolve platform data directories"))?;
     let _log_guard = init_logging(&project_dirs)?;
+    let config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::load_or_default(&project_dirs.config_dir().join(config::CONFIG_FILE_NAME))?,
+    };
+    links::init(link_policy(&args, &config.links)?);
+    i18n::init(
+        args.lang.as_deref(),
+        &project_dirs.config_dir().join("locales"),
+    );
     let state_dir = project_dirs.data_local_dir().join("state");
-    let store: Arc<dyn StateStore> = Arc::new(FileStateStore::new(state_dir.clone())?);
-    let mut session = Session::new(store);
-    let mut watched_docs = Vec::new();
+    let store: Arc<dyn StateStore> = match (&args.state_db, &args.sync_dir) {
+        (Some(db), _) => open_state_db(db, &state_dir, args.identity.unwrap_or_default())?,
+        (None, Some(dir)) => Arc::new(FileStateStore::with_key(
+            dir.clone(),
+            args.identity.unwrap_or(StateKey::Content),
+        )?),
+        (None, None) => Arc::new(FileStateStore::with_key(
+            state_dir.clone(),
+            args.identity.unwrap_or_default(),
+        )?),
+    };
+    let stored_recent = store.load_recent().unwrap_or_else(|err| {
+        warn!(?err, "failed to load recent files");
+        Some(RecentFiles::default())
+    });
+    // `None` when the state store keeps the recent files itself.
+    let recent_path = stored_recent
+        .is_none()
+        .then(|| state_dir.join(RECENT_FILES_NAME));
+    let mut recent = match (stored_recent, &recent_path) {
+        (None, Some(path)) => RecentFiles::load(path).unwrap_or_else(|err| {
+            warn!(?err, "failed to load recent files");
+            RecentFiles::default()
+        }),
+        (stored, _) => stored.unwrap_or_default(),
+    };
+    let mut files = args.files.clone();
+    let stdin_args = files
+        .iter()
+        .filter(|file| file.path == Path::new(STDIN_ARG))
+        .count();
+    if stdin_args > 1 {
+        return Err(anyhow!("standard input (`-`) can only be opened once"));
+    }
+    for file in files.iter_mut() {
+        if file.path == Path::new(STDIN_ARG) {
+            file.path = stash_stdin(&project_dirs.cache_dir().join("stdin"))?;
+        } else if let Some(url) = file.path.to_str().filter(|path| fetch::is_url(path)) {
+            let downloads = project_dirs.data_local_dir().join("downloads");
+            file.path = fetch::fetch(url, &downloads).await?;
+        }
+    }
+    if files.is_empty() && args.session.is_none() {
+        let Some(path) = choose_recent(&recent, args.resume)? else {
+            return Ok(());
+        };
+        files.push(FileArg { path, page: None });
+    }
+    let mut session = SaveOnPanic(Session::new(store.clone()));
+    session.set_session_store(Arc::new(FileSessionStore::new(
+        project_dirs.data_local_dir().join("sessions"),
+    )?));
+    let steps = StepSizes::default();
+    session.set_step_sizes(StepSizes {
+        pan: config::setting(args.pan_step, config.pan_step, "pan-step", parse_pan_step)?
+            .unwrap_or(steps.pan),
+        zoom: config::setting(
+            args.zoom_step,
+            config.zoom_step,
+            "zoom-step",
+            parse_zoom_step,
+        )?
+        .unwrap_or(steps.zoom),
+    });
+    if let Some(scrolloff) = config.scrolloff {
+        session.set_scrolloff(scrolloff);
+    }
+    if let Some(copy_reflow) = config.copy_reflow {
+        session.set_copy_reflow(copy_reflow);
+    }
+    let highlight = HighlightStyle::default();
+    session.set_highlight_style(HighlightStyle {
+        stroke_width: config::setting(
+            args.highlight_width,
+            config.highlight_width,
+            "highlight-width",
+            parse_highlight_width,
+        )?
+        .unwrap_or(highlight.stroke_width),
+        fill_opacity: config::setting(
+            args.highlight_opacity,
+            config.highlight_opacity,
+            "highlight-opacity",
+            parse_highlight_opacity,
+        )?
+        .unwrap_or(highlight.fill_opacity),
+    });
+    let defaults = QuoteTemplates::default();
+    session.set_quote_templates(QuoteTemplates {
+        markdown: args.quote_markdown.clone().unwrap_or(defaults.markdown),
+        latex: args.quote_latex.clone().unwrap_or(defaults.latex),
+    });
+    if args.low_memory {
+        session.set_memory_limits(MemoryLimits::low());
+    }
+    if args.follow {
+        session.set_follow(true);
+    }
+    if args.jump_to_change {
+        session.set_jump_to_change(true);
+    }
+    if args.notify.contains(&NotifyEvent::Reload) {
+        session.set_count_changed_pages(true);
+    }
+    let prefetch = config::setting(args.prefetch, config.prefetch, "prefetch", |value| {
+        value
+            .parse::<usize>()
+            .map_err(|_| anyhow!("Invalid prefetch value: {}", value))
+    })?;
+    // Short of memory, prefetching starts off but keeps its radius, so that
+    // `:set prefetch` turns it back on.
+    let short_of_memory = prefetch.is_none()
+        && (args.low_memory
+            || match available_memory() {
+                Some(bytes) if bytes < LOW_MEMORY_BYTES => {
+                    tracing::info!(bytes, "little memory available; page prefetching disabled");
+                    true
+                }
+                _ => false,
+            });
+    session.set_prefetch(PrefetchPolicy {
+        enabled: !short_of_memory,
+        radius: prefetch.unwrap_or_else(|| PrefetchPolicy::default().radius),
+        direction: config::setting(
+            args.prefetch_direction,
+            config.prefetch_direction.as_deref(),
+            "prefetch-direction",
+            parse_prefetch_direction,
+        )?
+        .unwrap_or_default(),
+    });
+    let mut file_watcher = FileWatcher::new()?;
     let (search_tx, mut search_rx) = mpsc::unbounded_channel();
     let mut search_manager = SearchManager::new(search_tx);
+    let (render_tx, mut render_rx) = mpsc::unbounded_channel::<FinishedRender>();
 
-    let provider = PdfRenderFactory::new()?;
-    for path in &args.files {
+    let render_timeout = if args.render_timeout > 0.0 {
+        Duration::try_from_secs_f64(args.render_timeout).context("invalid --render-timeout")?
+    } else {
+        Duration::MAX
+    };
+    let registry = ProviderRegistry::new().register(PdfRenderFactory::new()?);
+    // A render holding up the main thread gives way to waiting input.
+    let provider =
+        SupervisedProvider::new(registry, render_timeout).with_interrupt(CancelToken::when(|| {
+            event::poll(Duration::ZERO).unwrap_or(false)
+        }));
+    if let Some(name) = &args.session {
+        session.set_session_name(name.clone())?;
         session
-            .open_with(&provider, path.clone())
+            .restore_session(&provider, name)
             .await
-            .with_context(|| format!("failed to open {:?}", path))?;
+            .with_context(|| format!("failed to restore session {:?}", name))?;
+        for doc in session.documents() {
+            file_watcher.watch(doc.info.id, &doc.info.path);
+        }
+    }
+    let mut browse_dir = None;
+    for file in &files {
+        let path = &file.path;
+        if path.is_dir() {
+            browse_dir.get_or_insert_with(|| path.clone());
+            continue;
+        }
+        open_document(&mut session, &provider, path).await?;
+        if let Some(page) = file.page.or(args.page) {
+            session.apply(Command::GotoPage { page })?;
+        }
 
         if let Some(doc) = session.active() {
-            if !watched_docs
-                .iter()
-                .any(|entry: &WatchedDocument| entry.id == doc.info.id)
-            {
-                watched_docs.push(WatchedDocument::new(doc.info.id, doc.info.path.clone()));
-            }
+            file_watcher.watch(doc.info.id, &doc.info.path);
         }
     }
 
-    if let Some(page) = args.page {
-        session.apply(Command::GotoPage { page })?;
+    if session.active().is_none() && browse_dir.is_none() {
+        return Err(anyhow!("no documents to open"));
     }
+    record_recent(
+        &session,
+        store.as_ref(),
+        &mut recent,
+        recent_path.as_deref(),
+    );
+    let mut status_file = args
+        .status_file
+        .clone()
+        .map(|path| StatusFile::new(path, args.status_format));
+
+    let (ipc_tx, mut ipc_rx) = mpsc::unbounded_channel();
+    if let Some(path) = &args.commands_from {
+        ipc::spawn_fifo_reader(path.clone(), ipc_tx.clone())?;
+    }
+    let _ipc_server = match &args.listen {
+        Some(path) => Some(IpcServer::bind(path.clone(), ipc_tx)?),
+        None => None,
+    };
 
     let _raw = RawModeGuard::new()?;
+    let passthrough = args.passthrough.unwrap_or_else(Passthrough::detect);
+    let capabilities = Capabilities::probe(passthrough, PROBE_TIMEOUT);
+    tracing::info!(?capabilities, "probed terminal");
+    let window = terminal::window_size()?;
+    if window.width == 0 || window.height == 0 {
+        let _ = QUERIED_CELL_SIZE.set(capabilities.cell_size);
+    }
+    let graphics = match args.graphics {
+        GraphicsMode::Auto => capabilities.kitty_graphics,
+        GraphicsMode::Kitty => true,
+        GraphicsMode::Text => false,
+    };
+    let _ = GRAPHICS.set(graphics.then_some(passthrough));
     let mut stdout = io::stdout();
     crossterm::execute!(stdout, cursor::Hide)?;
     let mut renderer = KittyRenderer::new(stdout);
+    renderer.set_graphics(graphics);
+    renderer.set_synchronized_output(capabilities.synchronized_output);
+    renderer.set_passthrough(passthrough);
+    if graphics && passthrough == Passthrough::Tmux {
+        update_pane_origin(&mut renderer);
+    }
+    let transfer = args
+        .transfer
+        .unwrap_or_else(|| TransferMedium::detect(&capabilities));
+    renderer.set_transfer(transfer);
+    renderer.set_encoding(
+        args.encoding
+            .unwrap_or_else(|| ImageEncoding::for_transfer(transfer)),
+    );
     let mut event_mapper = EventMapper::new();
-    let mut overlay = OverlayState::None;
-    let mut status_bar = StatusBar::default();
+    event_mapper.set_clock(session.clock());
+    let history_path = state_dir.join(COMMAND_HISTORY_NAME);
+    match load_command_history(&history_path) {
+        Ok(history) => event_mapper.set_command_history(history),
+        Err(err) => warn!(?err, "failed to load command history"),
+    }
+    let mut overlay = match &browse_dir {
+        Some(dir) => OverlayState::Browser(FileBrowser::new(dir)?),
+        None => OverlayState::None,
+    };
+    let mut status_bar = StatusBar::new(session.clock());
+    if let Some(progress) = reading::goal_progress(&session) {
+        status_bar.set_message(StatusMessage::new(
+            progress,
+            CommandStatusKind::Info,
+            Some(GOAL_MESSAGE_TTL),
+        ));
+    }
+    if !graphics {
+        session.apply(Command::SetReflow { enabled: true })?;
+        if args.graphics == GraphicsMode::Auto {
+            status_bar.set_message(StatusMessage::new(
+                t!("no-graphics"),
+                CommandStatusKind::Info,
+                Some(STATUS_MESSAGE_TTL),
+            ));
+        }
+    } else if passthrough == Passthrough::Tmux && tmux::allows_passthrough() == Some(false) {
+        status_bar.set_message(StatusMessage::new(
+            t!("tmux-passthrough-off"),
+            CommandStatusKind::Error,
+            None,
+        ));
+    }
+    let mut reading_timer = ReadingTimer::new(session.clock().now());
+    let mut autosave = Autosave::new(&session, session.clock().now());
     let mut dirty = true;
     let mut needs_initial_clear = true;
-    let file_poll_interval = Duration::from_millis(FILE_POLL_INTERVAL_MS);
+    let mut window_title = String::new();
+    // The last notice from a backend, which page errors do not replace.
+    let mut backend_notice: Option<String> = None;
+    let mut reflow_active = session.reflow();
+    let mut last_frame: Option<ScreenFrame> = None;
+    let mut panes = Panes::default();
+    let signals = Signals::register()?;
+    let mut suspend_requested = false;
+    // When the last resize arrived, while tmux has not been asked since.
+    let mut resized_at: Option<Instant> = None;
 
     loop {
+        if resized_at.is_some_and(|at| at.elapsed() >= PANE_ORIGIN_DELAY) {
+            resized_at = None;
+            update_pane_origin(&mut renderer);
+            dirty = true;
+        }
+        event_mapper.set_step_sizes(session.step_sizes());
         if overlay.requires_toc_mode() {
             if !matches!(event_mapper.mode(), InputMode::Toc | InputMode::TocSearch) {
                 event_mapper.set_mode(InputMode::Toc);
@@ -260,39 +981,11 @@ async fn main() -> Result<()> {
             event_mapper.set_mode(InputMode::Normal);
         }
 
-        let mut reload_queue = Vec::new();
-        for watched in watched_docs.iter_mut() {
-            if !watched.should_check(file_poll_interval) {
-                continue;
-            }
-            watched.mark_checked();
-            let modified = match fs::metadata(&watched.path)
-                .and_then(|meta| meta.modified())
-                .ok()
-            {
-                Some(ts) => ts,
-                None => continue,
-            };
-            if watched
-                .last_modified
-                .map(|prev| prev == modified)
-                .unwrap_or(false)
-            {
-                continue;
-            }
-            reload_queue.push((watched.id, modified));
-        }
-
-        for (doc_id, modified) in reload_queue {
+        let reload_queue = file_watcher.take_changed(session.clock().now());
+        for doc_id in reload_queue {
             match session.reload_document(&provider, doc_id).await {
                 Ok(true) => {
-                    {
-                        if let Some(entry) =
-                            watched_docs.iter_mut().find(|entry| entry.id == doc_id)
-                        {
-                            entry.update_snapshot(Some(modified));
-                        }
-                    }
+                    file_watcher.reloaded(doc_id);
                     if let Some(active) = session.active() {
                         if active.info.id == doc_id {
                             if let OverlayState::Toc(toc) = &mut overlay {
@@ -300,26 +993,41 @@ async fn main() -> Result<()> {
                                 toc.update_selection_for_page(active.state.current_page);
                                 toc.rebuild_search_matches();
                             } else if let OverlayState::Marks(window) = &mut overlay {
-                                if !window.rebuild(active) {
+                                if !window.rebuild(active, session.global_marks()) {
                                     overlay.deactivate();
                                 } else {
                                     window.update_current_page(active.state.current_page);
                                 }
                             }
-                            needs_initial_clear = true;
                             dirty = true;
                         }
                     }
                 }
                 Ok(false) => {
-                    watched_docs.retain(|entry| entry.id != doc_id);
+                    file_watcher.retain(|id| id != doc_id);
                 }
                 Err(err) => {
-                    trace!(
-                        ?err,
-                        doc = %doc_id,
-                        "failed to reload document after change"
-                    );
+                    if file_watcher.retry_later(doc_id, session.clock().now()) {
+                        trace!(
+                            ?err,
+                            doc = %doc_id,
+                            "failed to reload document after change; retrying"
+                        );
+                    } else {
+                        warn!(?err, doc = %doc_id, "giving up reloading document");
+                        session.cancel_rebuild(doc_id);
+                        let name = session
+                            .document(doc_id)
+                            .and_then(|doc| doc.info.path.file_name())
+                            .map(|name| name.to_string_lossy().into_owned())
+                            .unwrap_or_default();
+                        status_bar.set_message(StatusMessage::new(
+                            t!("reload-failed", name = name, error = err),
+                            CommandStatusKind::Error,
+                            Some(STATUS_MESSAGE_TTL),
+                        ));
+                    }
+                    dirty = true;
                 }
             }
         }
@@ -336,39 +1044,195 @@ async fn main() -> Result<()> {
             }
         }
 
+        while let Ok(finished) = render_rx.try_recv() {
+            if session
+                .document(finished.doc_id)
+                .is_some_and(|doc| doc.finish_background_render(finished))
+            {
+                dirty = true;
+            }
+        }
+
+        let mut quit_requested = false;
+        while let Ok(request) = ipc_rx.try_recv() {
+            match handle_ipc_request(
+                request,
+                &mut session,
+                &mut overlay,
+                &mut panes,
+                &mut event_mapper,
+                &mut status_bar,
+                last_frame.as_ref(),
+            ) {
+                LoopAction::ContinueRedraw => dirty = true,
+                LoopAction::Continue => {}
+                LoopAction::Quit => quit_requested = true,
+                LoopAction::Suspend => suspend_requested = true,
+            }
+        }
+        if quit_requested || signals.terminate.load(Ordering::Relaxed) {
+            break;
+        }
+        if signals.stop.swap(false, Ordering::Relaxed) {
+            suspend_requested = true;
+        }
+        if std::mem::take(&mut suspend_requested) {
+            if let Err(err) = session.persist() {
+                warn!(?err, "failed to save state before suspending");
+            }
+            suspend(&mut renderer, &signals)?;
+            needs_initial_clear = true;
+            dirty = true;
+            window_title.clear();
+        }
+        if signals.resumed.swap(false, Ordering::Relaxed) {
+            take_over_terminal()?;
+            crossterm::execute!(renderer.writer(), cursor::Hide)?;
+            renderer.delete_all()?;
+            needs_initial_clear = true;
+            dirty = true;
+        }
+
+        match session.open_pending_mark(&provider).await {
+            Ok(true) => {
+                if let Some(doc) = session.active() {
+                    file_watcher.watch(doc.info.id, &doc.info.path);
+                }
+                needs_initial_clear = true;
+                dirty = true;
+            }
+            Ok(false) => {}
+            Err(err) => {
+                status_bar.set_message(StatusMessage::new(
+                    t!("marked-document-failed", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                dirty = true;
+            }
+        }
+
         if process_session_events(&session) {
             dirty = true;
         }
 
         status_bar.prune_expired();
+        reading_timer.sample(&mut session);
+        autosave.tick(&session);
+
+        if let Some(file) = status_file.as_mut() {
+            if let Err(err) = file.update(&session) {
+                trace!(?err, "failed to write status file");
+            }
+        }
 
         if dirty {
             // Begin an atomic update.
             renderer.begin_sync_update()?;
 
             // Perform all drawing operations. The terminal won't show them yet.
+            if !graphics && !session.reflow() {
+                session.apply(Command::SetReflow { enabled: true })?;
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-graphics"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+            }
+            if session.reflow() != reflow_active {
+                reflow_active = session.reflow();
+                needs_initial_clear = true;
+            }
             if needs_initial_clear {
                 renderer.clear_all()?;
                 needs_initial_clear = false;
             }
 
             let pending = event_mapper.pending_input();
-            redraw(
+            let mut render_failure = None;
+            if let Some(view) = redraw(
                 &mut renderer,
                 &session,
                 pending.as_deref(),
                 &mut overlay,
+                &panes,
                 &status_bar,
-            )?;
+                &mut render_failure,
+                &mut last_frame,
+            )? {
+                session.set_view_geometry(view);
+            }
 
             // End the atomic update. The terminal renders everything at once.
             renderer.end_sync_update()?;
+            // Slow pages drawn as a preview are rendered in full meanwhile;
+            // the full frame then replaces the preview's placement.
+            for doc in session.documents() {
+                for render in doc.take_background_renders() {
+                    let tx = render_tx.clone();
+                    task::spawn_blocking(move || {
+                        let _ = tx.send(render.run());
+                    });
+                }
+            }
+            if let Some(frame) = last_frame.as_ref().filter(|frame| frame.plain) {
+                transmit_neighbors(&mut renderer, &session, frame.render_scale)?;
+            }
+
+            let title = session
+                .active()
+                .map(|doc| format!("termpdf: {}", document_title(doc)))
+                .unwrap_or_else(|| "termpdf".to_string());
+            if title != window_title {
+                crossterm::execute!(io::stdout(), terminal::SetTitle(&title))?;
+                window_title = title;
+            }
 
             dirty = false;
+            for doc in session.documents() {
+                if let Some(notice) = doc.take_backend_notice() {
+                    status_bar.set_message(StatusMessage::new(
+                        notice.clone(),
+                        CommandStatusKind::Error,
+                        Some(STATUS_MESSAGE_TTL),
+                    ));
+                    backend_notice = Some(notice);
+                    dirty = true;
+                }
+            }
+            match render_failure {
+                Some(RenderFailure::Error(message)) => {
+                    // A notice from the backend, such as lost form edits,
+                    // stays until it expires.
+                    let shown = status_bar.message().is_some_and(|current| {
+                        current.text == message
+                            || backend_notice.as_deref() == Some(current.text.as_str())
+                    });
+                    if !shown {
+                        status_bar.set_message(StatusMessage::new(
+                            message,
+                            CommandStatusKind::Error,
+                            Some(STATUS_MESSAGE_TTL),
+                        ));
+                        dirty = true;
+                    }
+                }
+                Some(RenderFailure::Interrupted) => dirty = true,
+                None => {}
+            }
         }
 
-        if event::poll(Duration::from_millis(100))? {
+        let wait = Duration::from_millis(100);
+        if event::poll(feedback::end_flash().map_or(wait, |left| left.min(wait)))? {
             let ev = event::read()?;
+            if graphics
+                && passthrough == Passthrough::Tmux
+                && matches!(ev, event::Event::Resize(..))
+            {
+                resized_at = Some(Instant::now());
+            }
+            reading_timer.observe(&ev, session.clock().now());
+            notifications::observe(&ev);
             let ui_event = event_mapper.map_event(ev);
             let pending = event_mapper.pending_input();
             status_bar.prune_expired();
@@ -380,33 +1244,124 @@ async fn main() -> Result<()> {
                 {
                     draw_status_line(&mut renderer, &status)?;
                 }
+                renderer.present()?;
             }
             let overlay_was_active = overlay.is_active();
-            let overlay_was_fullscreen =
-                matches!(overlay, OverlayState::Toc(_) | OverlayState::Marks(_));
+            let layout_before = panes.orientation();
+            let overlay_was_fullscreen = matches!(
+                overlay,
+                OverlayState::Toc(_)
+                    | OverlayState::Marks(_)
+                    | OverlayState::Jumps(_)
+                    | OverlayState::History(_)
+                    | OverlayState::Links(_)
+                    | OverlayState::Citation(_)
+                    | OverlayState::CacheStats(_)
+                    | OverlayState::Info(_)
+                    | OverlayState::Stats(_)
+                    | OverlayState::LinkPrompt(_)
+                    | OverlayState::Browser(_)
+            );
             match handle_event(
                 ui_event,
                 &mut session,
                 &mut overlay,
+                &mut panes,
                 &mut event_mapper,
                 &mut search_manager,
                 &mut status_bar,
-            )? {
-                LoopAction::ContinueRedraw => dirty = true,
-                LoopAction::Continue => {}
-                LoopAction::Quit => break,
+                last_frame.as_ref(),
+            ) {
+                Ok(LoopAction::ContinueRedraw) => dirty = true,
+                Ok(LoopAction::Continue) => {}
+                Ok(LoopAction::Quit) => break,
+                Ok(LoopAction::Suspend) => suspend_requested = true,
+                // A command that fails is reported rather than ending the
+                // session.
+                Err(err) => {
+                    warn!(?err, "command failed");
+                    status_bar.set_message(StatusMessage::new(
+                        format!("{:#}", err),
+                        CommandStatusKind::Error,
+                        Some(STATUS_MESSAGE_TTL),
+                    ));
+                    dirty = true;
+                }
+            }
+            let chosen = match &mut overlay {
+                OverlayState::Browser(browser) => browser.take_chosen(),
+                _ => None,
+            };
+            if let Some(path) = chosen {
+                match open_document(&mut session, &provider, &path).await {
+                    Ok(()) => {
+                        overlay.deactivate();
+                        event_mapper.set_mode(InputMode::Normal);
+                        if let Some(doc) = session.active() {
+                            file_watcher.watch(doc.info.id, &doc.info.path);
+                        }
+                    }
+                    Err(err) => status_bar.set_message(StatusMessage::new(
+                        format!("{:#}", err),
+                        CommandStatusKind::Error,
+                        Some(STATUS_MESSAGE_TTL),
+                    )),
+                }
+                dirty = true;
+            }
+            if let Some((path, orientation)) = panes.take_pending_open() {
+                let previous = session.active().map(|doc| doc.info.id);
+                match open_document(&mut session, &provider, &path).await {
+                    Ok(()) => {
+                        let active = session.active().map(|doc| doc.info.id);
+                        if let Some(doc) = session.active() {
+                            file_watcher.watch(doc.info.id, &doc.info.path);
+                        }
+                        if let Some(previous) = previous {
+                            if active == Some(previous) {
+                                // The file was already open and active.
+                                session.apply(Command::SplitView)?;
+                            }
+                            panes.split(orientation, previous);
+                        }
+                    }
+                    Err(err) => status_bar.set_message(StatusMessage::new(
+                        format!("{:#}", err),
+                        CommandStatusKind::Error,
+                        Some(STATUS_MESSAGE_TTL),
+                    )),
+                }
+                dirty = true;
+            }
+            if panes.retain_open(&session) {
+                dirty = true;
             }
-            watched_docs.retain(|entry| session.contains_document(entry.id));
-            let overlay_is_fullscreen =
-                matches!(overlay, OverlayState::Toc(_) | OverlayState::Marks(_));
+            file_watcher.retain(|id| session.contains_document(id));
+            let overlay_is_fullscreen = matches!(
+                overlay,
+                OverlayState::Toc(_)
+                    | OverlayState::Marks(_)
+                    | OverlayState::Jumps(_)
+                    | OverlayState::History(_)
+                    | OverlayState::Links(_)
+                    | OverlayState::Citation(_)
+                    | OverlayState::CacheStats(_)
+                    | OverlayState::Info(_)
+                    | OverlayState::Stats(_)
+                    | OverlayState::LinkPrompt(_)
+                    | OverlayState::Browser(_)
+            );
             if overlay.is_active() != overlay_was_active {
                 if overlay_is_fullscreen || overlay_was_fullscreen {
                     needs_initial_clear = true;
                 }
                 dirty = true;
             }
-            if overlay_is_fullscreen != overlay_was_fullscreen {
+            if overlay_is_fullscreen != overlay_was_fullscreen
+                || panes.orientation() != layout_before
+            {
                 needs_initial_clear = true;
+                dirty = true;
             }
         }
     }
@@ -416,47 +1371,286 @@ async fn main() -> Result<()> {
         crossterm::execute!(&mut writer, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
     }
 
+    session.record_history();
     session.persist()?;
+    record_recent(
+        &session,
+        store.as_ref(),
+        &mut recent,
+        recent_path.as_deref(),
+    );
+    if let Err(err) = save_command_history(&history_path, event_mapper.command_history()) {
+        warn!(?err, "failed to save command history");
+    }
+    if let Some(file) = &status_file {
+        if let Err(err) = file.remove() {
+            warn!(?err, "failed to remove status file");
+        }
+    }
+    if session.session_name().is_some() {
+        session.apply(Command::SaveSession { name: None })?;
+    }
     Ok(())
 }
 
-#[derive(Clone, Copy)]
-enum LoopAction {
-    Continue,
-    ContinueRedraw,
-    Quit,
+/// Opens `path`, or switches to it when it is already open.
+async fn open_document<P: DocumentProvider>(
+    session: &mut Session,
+    provider: &P,
+    path: &Path,
+) -> Result<()> {
+    let id = document_id_for_path(path);
+    if let Some(index) = session.documents().iter().position(|doc| doc.info.id == id) {
+        return session.apply(Command::SwitchDocument { index });
+    }
+    session
+        .open_with(provider, path.to_path_buf())
+        .await
+        .with_context(|| format!("failed to open {:?}", path))
 }
 
-enum OverlayState {
-    None,
-    Toc(TocWindow),
-    Command(CommandOverlay),
-    Marks(MarkWindow),
+/// File in the state directory listing recently opened documents.
+const RECENT_FILES_NAME: &str = "recent.json";
+/// File in the state directory with the `:` command history, one per line.
+const COMMAND_HISTORY_NAME: &str = "command_history";
+
+/// Reads the command history saved at `path`, oldest first; a missing file
+/// is an empty history.
+fn load_command_history(path: &Path) -> Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let buf = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read command history {:?}", path))?;
+    Ok(buf
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect())
 }
 
-impl OverlayState {
-    fn deactivate(&mut self) {
-        *self = OverlayState::None;
-    }
+fn save_command_history(path: &Path, history: &[String]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut payload = history.join("\n");
+    payload.push('\n');
+    std::fs::write(&tmp, payload)
+        .with_context(|| format!("failed to write command history {:?}", tmp))?;
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
 
-    fn is_active(&self) -> bool {
-        !matches!(self, OverlayState::None)
-    }
+/// Recent documents offered when termpdf starts without arguments.
+const RECENT_PICKER_LIMIT: usize = 20;
 
-    fn requires_toc_mode(&self) -> bool {
-        matches!(self, OverlayState::Toc(_) | OverlayState::Marks(_))
-    }
+/// The time for the recent documents picker, which runs before the session
+/// and its clock exist.
+fn unix_now() -> u64 {
+    unix_seconds(SystemTime::now())
+}
 
-    fn is_command(&self) -> bool {
-        matches!(self, OverlayState::Command(_))
-    }
+/// Opens `--state-db`, importing the JSON state next to it in `state_dir`
+/// unless the database already did.
+#[cfg(feature = "sqlite")]
+fn open_state_db(path: &Path, state_dir: &Path, key: StateKey) -> Result<Arc<dyn StateStore>> {
+    let store = termpdf_core::SqliteStateStore::open(path, key)?;
+    let imported = store
+        .import_json(state_dir, &state_dir.join(RECENT_FILES_NAME))
+        .context("failed to import saved state into the database")?;
+    if let Some(imported) = imported {
+        trace!(imported, ?path, "imported JSON state");
+    }
+    Ok(Arc::new(store))
+}
 
-    fn command_mut(&mut self) -> Option<&mut CommandOverlay> {
-        match self {
-            OverlayState::Command(cmd) => Some(cmd),
-            _ => None,
-        }
-    }
+#[cfg(not(feature = "sqlite"))]
+fn open_state_db(_path: &Path, _state_dir: &Path, _key: StateKey) -> Result<Arc<dyn StateStore>> {
+    Err(anyhow!(
+        "--state-db requires termpdf to be built with `--features sqlite`"
+    ))
+}
+
+/// Remembers every open document, the active one last so it ends up first.
+/// The list goes to `path`, or to the state store when that keeps it.
+fn record_recent(
+    session: &Session,
+    store: &dyn StateStore,
+    recent: &mut RecentFiles,
+    path: Option<&Path>,
+) {
+    let now = unix_seconds(session.clock().wall_time());
+    let active = session.active().map(|doc| doc.info.id);
+    let (active_docs, others): (Vec<_>, Vec<_>) = session
+        .documents()
+        .iter()
+        .partition(|doc| Some(doc.info.id) == active);
+    for doc in others.into_iter().chain(active_docs) {
+        let path = doc
+            .info
+            .path
+            .canonicalize()
+            .unwrap_or_else(|_| doc.info.path.clone());
+        recent.record(path, doc.state.current_page, now);
+    }
+    let saved = match path {
+        Some(path) => recent.save(path),
+        None => store.save_recent(recent),
+    };
+    if let Err(err) = saved {
+        warn!(?err, "failed to save recent files");
+    }
+}
+
+/// Picks a recent document to open when no files were given: the newest one
+/// with `--resume`, otherwise whichever the user chooses from a list.
+fn choose_recent(recent: &RecentFiles, resume: bool) -> Result<Option<PathBuf>> {
+    let existing = recent.existing();
+    if existing.is_empty() {
+        return Err(anyhow!("no input files provided and no recent documents"));
+    }
+    if resume {
+        return Ok(Some(existing[0].path.clone()));
+    }
+    if !io::stdin().is_terminal() {
+        return Err(anyhow!("no input files provided"));
+    }
+
+    let shown = &existing[..existing.len().min(RECENT_PICKER_LIMIT)];
+    let now = unix_now();
+    let mut stderr = io::stderr();
+    writeln!(stderr, "Recent documents:")?;
+    for (index, entry) in shown.iter().enumerate() {
+        let name = entry
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| entry.path.display().to_string());
+        writeln!(
+            stderr,
+            "{:>3}  {}  (page {}, {})  {}",
+            index + 1,
+            name,
+            entry.page + 1,
+            format_age(now.saturating_sub(entry.opened_at)),
+            entry.path.display()
+        )?;
+    }
+    write!(
+        stderr,
+        "Open which document? [1-{}, Enter for 1, q to quit] ",
+        shown.len()
+    )?;
+    stderr.flush()?;
+
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(parse_recent_choice(&line, shown.len())?.map(|index| shown[index].path.clone()))
+}
+
+/// Zero-based index of the chosen entry, or `None` to quit.
+fn parse_recent_choice(input: &str, count: usize) -> Result<Option<usize>> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(Some(0));
+    }
+    if input.eq_ignore_ascii_case("q") {
+        return Ok(None);
+    }
+    input
+        .parse::<usize>()
+        .ok()
+        .filter(|choice| (1..=count).contains(choice))
+        .map(|choice| Some(choice - 1))
+        .ok_or_else(|| anyhow!("expected a number between 1 and {}", count))
+}
+
+fn format_age(seconds: u64) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = 60 * MINUTE;
+    const DAY: u64 = 24 * HOUR;
+    let (amount, unit) = match seconds {
+        s if s < MINUTE => return "just now".to_string(),
+        s if s < HOUR => (s / MINUTE, "minute"),
+        s if s < DAY => (s / HOUR, "hour"),
+        s => (s / DAY, "day"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{} {}{} ago", amount, unit, plural)
+}
+
+#[derive(Clone, Copy)]
+enum LoopAction {
+    Continue,
+    ContinueRedraw,
+    /// Stop until the shell resumes termpdf.
+    Suspend,
+    Quit,
+}
+
+enum OverlayState {
+    None,
+    Toc(TocWindow),
+    Command(CommandOverlay),
+    Marks(MarkWindow),
+    Jumps(JumpsWindow),
+    History(HistoryWindow),
+    Links(LinksWindow),
+    Citation(CitationPopup),
+    CacheStats(TextPopup),
+    Info(TextPopup),
+    Stats(TextPopup),
+    LinkPrompt(LinkPrompt),
+    Browser(FileBrowser),
+}
+
+impl OverlayState {
+    fn deactivate(&mut self) {
+        *self = OverlayState::None;
+    }
+
+    fn is_active(&self) -> bool {
+        !matches!(self, OverlayState::None)
+    }
+
+    /// The list of entries the overlay lets the reader pick from.
+    fn list_mut(&mut self) -> Option<&mut ListView> {
+        match self {
+            OverlayState::Toc(toc) => Some(&mut toc.list),
+            OverlayState::Marks(window) => Some(&mut window.list),
+            OverlayState::Jumps(window) => Some(&mut window.list),
+            OverlayState::History(window) => Some(&mut window.list),
+            OverlayState::Links(window) => Some(&mut window.list),
+            OverlayState::Browser(browser) => Some(&mut browser.list),
+            _ => None,
+        }
+    }
+
+    fn requires_toc_mode(&self) -> bool {
+        matches!(
+            self,
+            OverlayState::Toc(_)
+                | OverlayState::Marks(_)
+                | OverlayState::Jumps(_)
+                | OverlayState::History(_)
+                | OverlayState::Links(_)
+                | OverlayState::Citation(_)
+                | OverlayState::CacheStats(_)
+                | OverlayState::Info(_)
+                | OverlayState::Stats(_)
+                | OverlayState::LinkPrompt(_)
+                | OverlayState::Browser(_)
+        )
+    }
+
+    fn is_command(&self) -> bool {
+        matches!(self, OverlayState::Command(_))
+    }
+
+    fn command_mut(&mut self) -> Option<&mut CommandOverlay> {
+        match self {
+            OverlayState::Command(cmd) => Some(cmd),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -489,57 +1683,52 @@ impl CommandOverlay {
         self.status = status;
     }
 
+    /// The part of `:` and the input that fits in `max_cols` cells around
+    /// the cursor, and the cell the cursor is in.
     fn visible_prompt(&self, max_cols: usize) -> (String, usize) {
         if max_cols == 0 {
             return (String::new(), 0);
         }
-        let mut chars: Vec<char> = Vec::with_capacity(self.buffer.chars().count() + 1);
-        chars.push(':');
-        chars.extend(self.buffer.chars());
-        let cursor_chars = 1 + self
-            .buffer
-            .get(..self.cursor.min(self.buffer.len()))
-            .map(|segment| segment.chars().count())
-            .unwrap_or(0);
-        if chars.len() <= max_cols {
-            let display: String = chars.iter().collect();
-            return (display, cursor_chars.min(chars.len()));
-        }
-
-        let total = chars.len();
-        let mut start = if cursor_chars <= max_cols {
-            0
-        } else if cursor_chars >= total {
-            total.saturating_sub(max_cols)
-        } else {
-            cursor_chars.saturating_sub(max_cols)
-        };
-        if start + max_cols > total {
-            start = total.saturating_sub(max_cols);
-        }
-        let end = (start + max_cols).min(total);
-        let display: String = chars[start..end].iter().collect();
-        let cursor_in_view = cursor_chars.saturating_sub(start).min(max_cols);
+        let mut clusters: Vec<&str> = vec![":"];
+        clusters.extend(graphemes(&self.buffer).map(|(_, cluster)| cluster));
+        let widths: Vec<usize> = clusters
+            .iter()
+            .map(|cluster| cluster_width(cluster))
+            .collect();
+        let cursor = self.cursor.min(self.buffer.len());
+        let cursor_chars = 1 + graphemes(&self.buffer)
+            .take_while(|(index, _)| *index < cursor)
+            .count();
+        let cells = |range: Range<usize>| widths[range].iter().sum::<usize>();
+
+        let total = clusters.len();
+        let mut start = 0;
+        while cells(start..cursor_chars) > max_cols {
+            start += 1;
+        }
+        // Fill the view when the cursor is near the end of a long input.
+        while start > 0 && cells(start - 1..total) <= max_cols {
+            start -= 1;
+        }
+        let mut end = start;
+        while end < total && cells(start..end + 1) <= max_cols {
+            end += 1;
+        }
+        let display: String = clusters[start..end].concat();
+        let cursor_in_view = cells(start..cursor_chars).min(max_cols);
         (display, cursor_in_view)
     }
 
-    fn write_status(&self, writer: &mut impl Write, available_cols: usize) -> io::Result<()> {
+    fn write_status(&self, buffer: &mut Buffer, column: u16, row: u16, available_cols: usize) {
         if available_cols == 0 || self.status.message.is_empty() {
-            return Ok(());
+            return;
         }
         let text = truncate_with_ellipsis(self.status.message.clone(), available_cols);
-        match self.status.kind {
-            CommandStatusKind::Info => {}
-            CommandStatusKind::Error => {
-                crossterm::execute!(writer, SetForegroundColor(Color::Red))?;
-            }
-            CommandStatusKind::Progress => {
-                crossterm::execute!(writer, SetForegroundColor(Color::Yellow))?;
-            }
-        }
-        write!(writer, "{}", text)?;
-        crossterm::execute!(writer, SetForegroundColor(Color::Reset))?;
-        Ok(())
+        let style = match self.status.kind {
+            CommandStatusKind::Info => Style::PLAIN,
+            kind => kind.style(),
+        };
+        buffer.set_str(column, row, &text, style);
     }
 }
 
@@ -576,7 +1765,7 @@ impl CommandStatus {
 
 impl Default for CommandStatus {
     fn default() -> Self {
-        CommandStatus::info("Command mode")
+        CommandStatus::info(t!("command-mode"))
     }
 }
 
@@ -587,63 +1776,140 @@ enum CommandStatusKind {
     Progress,
 }
 
+impl CommandStatusKind {
+    fn style(self) -> Style {
+        match self {
+            CommandStatusKind::Info => Style::fg(Color::White),
+            CommandStatusKind::Error => Style::fg(Color::Red),
+            CommandStatusKind::Progress => Style::fg(Color::Yellow),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MarkTarget {
+    Key(char),
+    Named(String),
+}
+
 #[derive(Debug, Clone)]
 struct MarkEntry {
-    name: String,
+    target: MarkTarget,
     page: usize,
+    /// Whether the mark points into the active document.
+    local: bool,
+    /// Outline heading at or before the page, or the file of a global mark
+    /// in another document.
+    context: Option<String>,
+}
+
+impl MarkEntry {
+    fn label(&self) -> String {
+        match &self.target {
+            MarkTarget::Key(key) => format!("'{}", key),
+            MarkTarget::Named(name) => name.clone(),
+        }
+    }
+
+    fn text(&self) -> String {
+        let mut text = format!("{} (p{})", self.label(), self.page + 1);
+        if let Some(context) = &self.context {
+            text.push_str("  ");
+            text.push_str(context);
+        }
+        text
+    }
+}
+
+/// Title of the last outline entry that starts at or before `page`.
+fn outline_heading(outline: &[OutlineItem], page: usize) -> Option<String> {
+    outline
+        .iter()
+        .rev()
+        .find(|item| item.page_index <= page)
+        .map(|item| item.title.trim().to_string())
+        .filter(|title| !title.is_empty())
 }
 
 #[derive(Debug, Clone)]
 struct MarkWindow {
     entries: Vec<MarkEntry>,
-    selected: usize,
+    list: ListView,
     current_index: Option<usize>,
-    scroll_offset: usize,
 }
 
 impl MarkWindow {
-    fn from_document(doc: &DocumentInstance) -> Option<Self> {
-        if doc.named_marks().is_empty() {
-            return None;
+    fn from_document(doc: &DocumentInstance, global_marks: &GlobalMarks) -> Option<Self> {
+        let local_entry = |target, page| MarkEntry {
+            target,
+            page,
+            local: true,
+            context: outline_heading(doc.outline(), page),
+        };
+        let mut keyed: Vec<MarkEntry> = doc
+            .marks()
+            .iter()
+            .filter(|(key, _)| !is_global_mark(**key))
+            .map(|(key, page)| local_entry(MarkTarget::Key(*key), *page))
+            .collect();
+        for (key, mark) in global_marks {
+            if document_id_for_path(&mark.path) == doc.info.id {
+                keyed.push(local_entry(MarkTarget::Key(*key), mark.page));
+            } else {
+                keyed.push(MarkEntry {
+                    target: MarkTarget::Key(*key),
+                    page: mark.page,
+                    local: false,
+                    context: mark
+                        .path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned()),
+                });
+            }
         }
-        let mut entries: Vec<MarkEntry> = doc
+        keyed.sort_by_key(|entry| entry.label());
+        let mut named: Vec<MarkEntry> = doc
             .named_marks()
             .iter()
-            .map(|(name, page)| MarkEntry {
-                name: name.clone(),
-                page: *page,
-            })
+            .map(|(name, page)| local_entry(MarkTarget::Named(name.clone()), *page))
             .collect();
-        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        named.sort_by_key(|entry| entry.label());
+        let mut entries = keyed;
+        entries.extend(named);
+        if entries.is_empty() {
+            return None;
+        }
+
         let current_page = doc.state.current_page;
-        let current_index = entries.iter().position(|entry| entry.page == current_page);
-        let selected = current_index.unwrap_or(0);
+        let current_index = entries
+            .iter()
+            .position(|entry| entry.local && entry.page == current_page);
         Some(Self {
+            list: ListView::new(entries.len()).with_selected(current_index.unwrap_or(0)),
             entries,
-            selected,
             current_index,
-            scroll_offset: 0,
         })
     }
 
-    fn rebuild(&mut self, doc: &DocumentInstance) -> bool {
-        let selected_name = self.selected_entry().map(|entry| entry.name.clone());
-        let Some(mut next_window) = Self::from_document(doc) else {
+    fn rebuild(&mut self, doc: &DocumentInstance, global_marks: &GlobalMarks) -> bool {
+        let selected_target = self.selected_entry().map(|entry| entry.target.clone());
+        let Some(next_window) = Self::from_document(doc, global_marks) else {
             return false;
         };
-        if let Some(name) = selected_name {
-            if let Some(idx) = next_window
+        let mut list = self.list;
+        list.set_len(next_window.entries.len());
+        if let Some(index) = selected_target.and_then(|target| {
+            next_window
                 .entries
                 .iter()
-                .position(|entry| entry.name == name)
-            {
-                next_window.selected = idx;
-            }
+                .position(|entry| entry.target == target)
+        }) {
+            list.select(index);
         }
-        next_window.scroll_offset = self
-            .scroll_offset
-            .min(next_window.entries.len().saturating_sub(1));
-        *self = next_window;
+        *self = Self {
+            list,
+            ..next_window
+        };
         true
     }
 
@@ -656,7 +1922,7 @@ impl MarkWindow {
     }
 
     fn selected_entry(&self) -> Option<&MarkEntry> {
-        self.entries.get(self.selected)
+        self.entries.get(self.list.selected())
     }
 
     fn current_index(&self) -> Option<usize> {
@@ -664,95 +1930,334 @@ impl MarkWindow {
     }
 
     fn update_current_page(&mut self, page: usize) {
-        self.current_index = self.entries.iter().position(|entry| entry.page == page);
+        self.current_index = self
+            .entries
+            .iter()
+            .position(|entry| entry.local && entry.page == page);
     }
+}
 
-    fn set_selected(&mut self, index: usize) -> bool {
-        if self.entries.is_empty() {
-            return false;
+/// Filterable list of every external URI in the active document.
+#[derive(Debug, Clone)]
+struct LinksWindow {
+    entries: Vec<UriLink>,
+    visible: Vec<usize>,
+    list: ListView,
+    filter: String,
+    filter_input: bool,
+    notice: Option<String>,
+}
+
+impl LinksWindow {
+    fn new(entries: Vec<UriLink>) -> Self {
+        let visible = (0..entries.len()).collect();
+        Self {
+            list: ListView::new(entries.len()),
+            entries,
+            visible,
+            filter: String::new(),
+            filter_input: false,
+            notice: None,
         }
-        let clamped = index.min(self.entries.len().saturating_sub(1));
-        if clamped != self.selected {
-            self.selected = clamped;
-            true
+    }
+
+    fn set_filter(&mut self, query: &str) {
+        let selected_entry = self.visible.get(self.list.selected()).copied();
+        let needle = query.to_lowercase();
+        self.filter = query.to_string();
+        self.visible = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.uri.to_lowercase().contains(&needle))
+            .map(|(idx, _)| idx)
+            .collect();
+        let selected = selected_entry
+            .and_then(|entry| self.visible.iter().position(|&idx| idx == entry))
+            .unwrap_or(0);
+        self.list = ListView::new(self.visible.len()).with_selected(selected);
+        self.notice = None;
+    }
+
+    fn visible_entries(&self) -> impl Iterator<Item = &UriLink> {
+        self.visible.iter().map(|&idx| &self.entries[idx])
+    }
+
+    fn selected_entry(&self) -> Option<&UriLink> {
+        self.visible
+            .get(self.list.selected())
+            .map(|&idx| &self.entries[idx])
+    }
+
+    fn title(&self) -> String {
+        let mut title = t!(
+            "links-title",
+            shown = self.visible.len(),
+            total = self.entries.len()
+        );
+        if self.filter_input || !self.filter.is_empty() {
+            title.push_str(&format!(" /{}", self.filter));
+        }
+        if let Some(notice) = &self.notice {
+            title.push_str(&format!(" [{}]", notice));
+        }
+        title
+    }
+}
+
+/// Reference entry for a citation, shown over the page without moving it.
+#[derive(Debug, Clone)]
+struct CitationPopup {
+    title: String,
+    text: String,
+    scroll: usize,
+    return_mode: InputMode,
+}
+
+impl CitationPopup {
+    fn new(preview: CitationPreview, return_mode: InputMode) -> Self {
+        let title = if preview.label.is_empty() {
+            t!("reference-title", page = preview.page + 1)
         } else {
-            false
+            t!(
+                "reference-title-labelled",
+                label = preview.label,
+                page = preview.page + 1
+            )
+        };
+        Self {
+            title,
+            text: preview.text,
+            scroll: 0,
+            return_mode,
         }
     }
 
-    fn move_selection(&mut self, delta: isize) -> bool {
-        if self.entries.is_empty() {
-            return false;
+    fn scroll_by(&mut self, delta: isize) -> bool {
+        scroll_popup(&mut self.scroll, delta)
+    }
+}
+
+/// Asks before an external link starts another program.
+#[derive(Debug, Clone)]
+struct LinkPrompt {
+    target: ExternalLink,
+    action: LinkAction,
+    scroll: usize,
+    return_mode: InputMode,
+}
+
+impl LinkPrompt {
+    fn lines(&self, width: usize) -> Vec<String> {
+        let mut lines = wrap_chars(&links::display(&self.target), width);
+        lines.push(String::new());
+        if let LinkAction::Run(command) = &self.action {
+            lines.push(t!("link-prompt-handler", command = command.join(" ")));
         }
-        let len = self.entries.len() as isize;
-        let next = (self.selected as isize + delta).clamp(0, len - 1) as usize;
-        self.set_selected(next)
+        lines.push(t!("link-prompt-keys"));
+        lines
     }
 
-    fn select_first(&mut self) -> bool {
-        self.set_selected(0)
+    fn scroll_by(&mut self, delta: isize) -> bool {
+        scroll_popup(&mut self.scroll, delta)
     }
+}
 
-    fn select_last(&mut self) -> bool {
-        if self.entries.is_empty() {
-            return false;
+/// Fixed lines of text shown in a popup, such as `:cache-stats`.
+#[derive(Debug, Clone)]
+struct TextPopup {
+    title: String,
+    lines: Vec<String>,
+    scroll: usize,
+}
+
+impl TextPopup {
+    fn cache_stats(session: &Session) -> Self {
+        let mut lines = Vec::new();
+        for doc in session.documents() {
+            let stats = doc.cache_stats();
+            let name = doc
+                .info
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| doc.info.path.display().to_string());
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(name);
+            lines.push(t!(
+                "cache-stats-rendered",
+                pages = stats.rendered_pages,
+                capacity = stats.rendered_capacity,
+                size = format_bytes(stats.rendered_bytes)
+            ));
+            lines.push(t!(
+                "cache-stats-text",
+                pages = stats.text_pages,
+                size = format_bytes(stats.text_bytes),
+                capacity = format_bytes(stats.text_capacity)
+            ));
+        }
+        Self {
+            title: t!("cache-stats-title"),
+            lines,
+            scroll: 0,
         }
-        self.set_selected(self.entries.len() - 1)
     }
 
-    fn ensure_visible(&mut self, viewport_height: usize) {
-        if viewport_height == 0 || self.entries.is_empty() {
-            self.scroll_offset = 0;
-            return;
+    /// `today` is in days since the Unix epoch, as [`reading::today`].
+    fn reading_stats(doc: &DocumentInstance, today: i64) -> Self {
+        let stats = &doc.state.stats;
+        let page_count = doc.info.page_count;
+        let finish = match stats.time_to_finish(page_count) {
+            Some(time) => format_duration(time),
+            None => t!("stats-unknown"),
+        };
+        let mut lines = vec![
+            t!("stats-total", time = format_duration(stats.total())),
+            t!(
+                "stats-page",
+                page = doc.state.current_page + 1,
+                time = format_duration(stats.on_page(doc.state.current_page))
+            ),
+            t!(
+                "stats-pages-read",
+                read = stats.pages_read(),
+                count = page_count
+            ),
+            t!("stats-finish", time = finish),
+        ];
+        let today = stats.pages_read_on(today);
+        lines.push(match doc.state.daily_goal {
+            Some(goal) => reading::goal_text(today, goal),
+            None => t!("stats-today", read = today),
+        });
+        Self {
+            title: t!("stats-title"),
+            lines,
+            scroll: 0,
         }
-        let max_offset = self.entries.len().saturating_sub(viewport_height.max(1));
-        if self.scroll_offset > max_offset {
-            self.scroll_offset = max_offset;
+    }
+
+    /// The document's metadata and, with `--bibtex`, its bibliography
+    /// entry. Long lines are wrapped when drawn.
+    fn document_info(doc: &DocumentInstance) -> Self {
+        let info = &doc.info;
+        let mut lines = vec![info.path.display().to_string()];
+        let metadata = &info.metadata;
+        if let Some(title) = metadata.title.as_deref().filter(|title| !title.is_empty()) {
+            lines.push(t!("info-title", title = title));
         }
-        if self.selected < self.scroll_offset {
-            self.scroll_offset = self.selected;
-            return;
+        if let Some(author) = metadata
+            .author
+            .as_deref()
+            .filter(|author| !author.is_empty())
+        {
+            lines.push(t!("info-author", author = author));
+        }
+        if !metadata.keywords.is_empty() {
+            lines.push(t!("info-keywords", keywords = metadata.keywords.join(", ")));
+        }
+        lines.push(t!("info-pages", count = info.page_count));
+        let doi = doc.doi();
+        if let Some(doi) = &doi {
+            lines.push(t!("info-doi", doi = doi));
+        }
+        lines.push(String::new());
+        match bibtex::bibliography() {
+            None => lines.push(t!("info-no-bibtex")),
+            Some(bibliography) => match bibliography.find(info, doi.as_deref()) {
+                Some(entry) => {
+                    lines.push(t!("info-cite-key", key = entry.key));
+                    lines.push(t!("info-reference", reference = entry.reference()));
+                }
+                None => lines.push(t!("no-bib-entry")),
+            },
         }
-        let bottom = self.scroll_offset + viewport_height;
-        if self.selected >= bottom {
-            self.scroll_offset = self
-                .selected
-                .saturating_sub(viewport_height.saturating_sub(1));
+        Self {
+            title: t!("info-title-bar"),
+            lines,
+            scroll: 0,
         }
     }
+
+    fn scroll_by(&mut self, delta: isize) -> bool {
+        scroll_popup(&mut self.scroll, delta)
+    }
+}
+
+fn scroll_popup(scroll: &mut usize, delta: isize) -> bool {
+    let next = (*scroll as isize).saturating_add(delta).max(0) as usize;
+    let changed = next != *scroll;
+    *scroll = next;
+    changed
+}
+
+/// `2h 05m`, `3m 20s` or `40s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {:02}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const KIB: f64 = 1024.0;
+    let bytes = bytes as f64;
+    if bytes >= KIB * KIB {
+        format!("{:.1} MiB", bytes / (KIB * KIB))
+    } else if bytes >= KIB {
+        format!("{:.1} KiB", bytes / KIB)
+    } else {
+        format!("{} B", bytes)
+    }
 }
 
 struct StatusMessage {
     text: String,
     kind: CommandStatusKind,
-    expires_at: Option<Instant>,
+    ttl: Option<Duration>,
 }
 
 impl StatusMessage {
     fn new(text: impl Into<String>, kind: CommandStatusKind, ttl: Option<Duration>) -> Self {
-        let expires_at = ttl.map(|ttl| Instant::now() + ttl);
         Self {
             text: text.into(),
             kind,
-            expires_at,
-        }
-    }
-
-    fn is_expired(&self) -> bool {
-        match self.expires_at {
-            Some(deadline) => Instant::now() >= deadline,
-            None => false,
+            ttl,
         }
     }
 }
 
-#[derive(Default)]
 struct StatusBar {
     message: Option<StatusMessage>,
+    expires_at: Option<Instant>,
+    clock: SharedClock,
+}
+
+impl Default for StatusBar {
+    fn default() -> Self {
+        Self::new(system_clock())
+    }
 }
 
 impl StatusBar {
+    fn new(clock: SharedClock) -> Self {
+        Self {
+            message: None,
+            expires_at: None,
+            clock,
+        }
+    }
+
     fn set_message(&mut self, message: StatusMessage) {
+        self.expires_at = message.ttl.map(|ttl| self.clock.now() + ttl);
         self.message = Some(message);
     }
 
@@ -760,20 +2265,26 @@ impl StatusBar {
         self.message.as_ref()
     }
 
+    fn take_message(&mut self) -> Option<StatusMessage> {
+        self.expires_at = None;
+        self.message.take()
+    }
+
     fn prune_expired(&mut self) {
-        if let Some(message) = &self.message {
-            if message.is_expired() {
-                self.message = None;
-            }
+        if self
+            .expires_at
+            .is_some_and(|deadline| self.clock.now() >= deadline)
+        {
+            self.message = None;
+            self.expires_at = None;
         }
     }
 }
 
 struct TocWindow {
     entries: Vec<OutlineItem>,
-    selected: usize,
+    list: ListView,
     current: Option<usize>,
-    scroll_offset: usize,
     search_query: Option<String>,
     search_matches: Vec<usize>,
     search_input: Option<String>,
@@ -782,12 +2293,10 @@ struct TocWindow {
 impl TocWindow {
     fn from_outline(entries: Vec<OutlineItem>, current_page: usize) -> Self {
         let current = Self::entry_for_page(&entries, current_page);
-        let selected = current.unwrap_or(0);
         Self {
+            list: ListView::new(entries.len()).with_selected(current.unwrap_or(0)),
             entries,
-            selected,
             current,
-            scroll_offset: 0,
             search_query: None,
             search_matches: Vec::new(),
             search_input: None,
@@ -799,7 +2308,7 @@ impl TocWindow {
     }
 
     fn selected_entry(&self) -> Option<&OutlineItem> {
-        self.entries.get(self.selected)
+        self.entries.get(self.list.selected())
     }
 
     fn entry_for_page(entries: &[OutlineItem], current_page: usize) -> Option<usize> {
@@ -821,79 +2330,9 @@ impl TocWindow {
         self.current.filter(|&idx| idx < self.entries.len())
     }
 
-    fn set_selected(&mut self, index: usize) -> bool {
-        if self.entries.is_empty() {
-            return false;
-        }
-        let clamped = index.min(self.entries.len().saturating_sub(1));
-        if clamped != self.selected {
-            self.selected = clamped;
-            true
-        } else {
-            false
-        }
-    }
-
-    fn move_selection(&mut self, delta: isize) -> bool {
-        if self.entries.is_empty() {
-            return false;
-        }
-        let len = self.entries.len() as isize;
-        let next = (self.selected as isize + delta).clamp(0, len - 1) as usize;
-        self.set_selected(next)
-    }
-
-    fn select_first(&mut self) -> bool {
-        self.set_selected(0)
-    }
-
-    fn select_last(&mut self) -> bool {
-        if self.entries.is_empty() {
-            return false;
-        }
-        let last = self.entries.len() - 1;
-        self.set_selected(last)
-    }
-
-    fn ensure_visible(&mut self, viewport_height: usize) {
-        if viewport_height == 0 {
-            self.scroll_offset = 0;
-            return;
-        }
-        if self.entries.is_empty() {
-            self.scroll_offset = 0;
-            return;
-        }
-        let max_offset = self.entries.len().saturating_sub(viewport_height.max(1));
-        if self.scroll_offset > max_offset {
-            self.scroll_offset = max_offset;
-        }
-        if self.selected < self.scroll_offset {
-            self.scroll_offset = self.selected;
-            return;
-        }
-        let bottom = self.scroll_offset + viewport_height;
-        if self.selected >= bottom {
-            self.scroll_offset = self
-                .selected
-                .saturating_sub(viewport_height.saturating_sub(1));
-        }
-    }
-
     fn update_selection_for_page(&mut self, current_page: usize) {
-        if self.entries.is_empty() {
-            self.selected = 0;
-            self.scroll_offset = 0;
-            self.current = None;
-            return;
-        }
-        if let Some(next) = Self::entry_for_page(&self.entries, current_page) {
-            self.selected = next;
-            self.current = Some(next);
-        } else {
-            self.selected = 0;
-            self.current = None;
-        }
+        self.current = Self::entry_for_page(&self.entries, current_page);
+        self.list.select(self.current.unwrap_or(0));
     }
 
     fn begin_search(&mut self) -> bool {
@@ -913,11 +2352,7 @@ impl TocWindow {
     }
 
     fn finish_search_input(&mut self) -> bool {
-        if self.search_input.take().is_some() {
-            true
-        } else {
-            false
-        }
+        self.search_input.take().is_some()
     }
 
     fn cancel_search(&mut self) -> bool {
@@ -943,10 +2378,9 @@ impl TocWindow {
     }
 
     fn recompute_search_matches(&mut self, query: &str) -> bool {
-        let needle = query.to_lowercase();
         let mut matches = Vec::new();
         for (idx, entry) in self.entries.iter().enumerate() {
-            if entry.title.to_lowercase().contains(&needle) {
+            if matching::contains(&entry.title, query) {
                 matches.push(idx);
             }
         }
@@ -958,10 +2392,10 @@ impl TocWindow {
             .search_matches
             .iter()
             .copied()
-            .find(|&idx| idx >= self.selected)
+            .find(|&idx| idx >= self.list.selected())
             .or_else(|| self.search_matches.first().copied());
         if let Some(target) = target {
-            self.set_selected(target)
+            self.list.select(target)
         } else {
             false
         }
@@ -997,12 +2431,12 @@ impl TocWindow {
         let mut index = if forward {
             self.search_matches
                 .iter()
-                .position(|&idx| idx > self.selected)
+                .position(|&idx| idx > self.list.selected())
                 .unwrap_or(0)
         } else {
             self.search_matches
                 .iter()
-                .rposition(|&idx| idx < self.selected)
+                .rposition(|&idx| idx < self.list.selected())
                 .unwrap_or(len - 1)
         };
         if len > 0 {
@@ -1014,7 +2448,7 @@ impl TocWindow {
             }
         }
         let target = self.search_matches[index];
-        self.set_selected(target)
+        self.list.select(target)
     }
 
     fn rebuild_search_matches(&mut self) {
@@ -1026,16 +2460,26 @@ impl TocWindow {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_event(
     event: UiEvent,
     session: &mut Session,
     overlay: &mut OverlayState,
+    panes: &mut Panes,
     mapper: &mut EventMapper,
     search_manager: &mut SearchManager,
     status_bar: &mut StatusBar,
+    frame: Option<&ScreenFrame>,
 ) -> Result<LoopAction> {
     match event {
-        UiEvent::BeginSearch => Ok(LoopAction::Continue),
+        UiEvent::BeginSearch => {
+            search_manager.begin_prompt(session.search_scope());
+            Ok(LoopAction::Continue)
+        }
+        UiEvent::BeginPageSearch => {
+            search_manager.begin_prompt(SearchScope::Page);
+            Ok(LoopAction::Continue)
+        }
         UiEvent::SearchQueryChanged { query } => {
             handle_search_input(query, session, search_manager)?;
             Ok(LoopAction::ContinueRedraw)
@@ -1078,111 +2522,83 @@ fn handle_event(
                 return Ok(LoopAction::ContinueRedraw);
             }
 
-            let tokens = tokenize_command(&trimmed);
-            if tokens.is_empty() {
-                return Ok(LoopAction::ContinueRedraw);
+            execute_command_line(&trimmed, session, overlay, panes, mapper, status_bar, frame)
+        }
+        UiEvent::FocusOtherPane => {
+            if let Err(err) = panes.focus_other(session) {
+                status_bar.set_message(StatusMessage::new(
+                    err.to_string(),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
             }
-            let command_name = tokens[0].to_ascii_lowercase();
-            match command_name.as_str() {
-                "q" | "quit" => Ok(LoopAction::Quit),
-                "mark" => {
-                    if tokens.len() < 2 {
-                        status_bar.set_message(StatusMessage::new(
-                            "Usage: :mark <name>",
-                            CommandStatusKind::Error,
-                            Some(STATUS_MESSAGE_TTL),
-                        ));
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                    if session.active().is_none() {
-                        status_bar.set_message(StatusMessage::new(
-                            "No active document",
-                            CommandStatusKind::Error,
-                            Some(STATUS_MESSAGE_TTL),
-                        ));
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                    let name = tokens[1].clone();
-                    let page = session
-                        .active()
-                        .map(|doc| doc.state.current_page)
-                        .unwrap_or(0);
-                    session.apply(Command::SaveNamedMark { name: name.clone() })?;
-                    refresh_mark_overlay(overlay, session, mapper);
-                    status_bar.set_message(StatusMessage::new(
-                        format!("Saved mark '{}' at page {}", name, page + 1),
-                        CommandStatusKind::Info,
-                        Some(STATUS_MESSAGE_TTL),
-                    ));
-                    Ok(LoopAction::ContinueRedraw)
-                }
-                "goto" => {
-                    if tokens.len() < 2 {
-                        status_bar.set_message(StatusMessage::new(
-                            "Usage: :goto <name>",
-                            CommandStatusKind::Error,
-                            Some(STATUS_MESSAGE_TTL),
-                        ));
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                    let name = tokens[1].clone();
-                    let target_page = session.active().and_then(|doc| doc.named_mark_page(&name));
-                    let Some(page) = target_page else {
-                        status_bar.set_message(StatusMessage::new(
-                            format!("Unknown mark '{}'", name),
-                            CommandStatusKind::Error,
-                            Some(STATUS_MESSAGE_TTL),
-                        ));
-                        return Ok(LoopAction::ContinueRedraw);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        UiEvent::ShowCitation { count } => {
+            let Some(doc) = session.active() else {
+                return Ok(LoopAction::Continue);
+            };
+            match doc.citation_preview(count.saturating_sub(1)) {
+                Ok(Some(preview)) => {
+                    let return_mode = match mapper.mode() {
+                        InputMode::Link => InputMode::Link,
+                        _ => InputMode::Normal,
                     };
-                    session.apply(Command::GotoNamedMark { name: name.clone() })?;
-                    refresh_mark_overlay(overlay, session, mapper);
-                    status_bar.set_message(StatusMessage::new(
-                        format!("Jumped to mark '{}' (page {})", name, page + 1),
-                        CommandStatusKind::Info,
-                        Some(STATUS_MESSAGE_TTL),
-                    ));
-                    Ok(LoopAction::ContinueRedraw)
+                    *overlay = OverlayState::Citation(CitationPopup::new(preview, return_mode));
+                    mapper.set_mode(InputMode::Toc);
                 }
-                "listmarks" => {
-                    if matches!(overlay, OverlayState::Marks(_)) {
-                        overlay.deactivate();
-                        mapper.set_mode(InputMode::Normal);
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                    let Some(doc) = session.active() else {
+                Ok(None) => status_bar.set_message(StatusMessage::new(
+                    t!("no-citation-link"),
+                    CommandStatusKind::Info,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+                Err(err) => status_bar.set_message(StatusMessage::new(
+                    t!("citation-failed", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        UiEvent::FormActivate => {
+            let kind = session
+                .active()
+                .and_then(|doc| doc.current_form_field())
+                .map(|field| field.kind.clone());
+            match kind {
+                Some(FormFieldKind::Text { value }) => mapper.begin_form_input(&value),
+                Some(FormFieldKind::Checkbox { .. } | FormFieldKind::RadioButton { .. }) => {
+                    if let Err(err) = session.apply(Command::ToggleFormField) {
                         status_bar.set_message(StatusMessage::new(
-                            "No active document",
+                            t!("field-update-failed", error = err),
                             CommandStatusKind::Error,
                             Some(STATUS_MESSAGE_TTL),
                         ));
-                        return Ok(LoopAction::ContinueRedraw);
-                    };
-                    match MarkWindow::from_document(doc) {
-                        Some(window) => {
-                            *overlay = OverlayState::Marks(window);
-                            mapper.set_mode(InputMode::Toc);
-                            Ok(LoopAction::ContinueRedraw)
-                        }
-                        None => {
-                            status_bar.set_message(StatusMessage::new(
-                                "No marks saved",
-                                CommandStatusKind::Info,
-                                Some(STATUS_MESSAGE_TTL),
-                            ));
-                            Ok(LoopAction::ContinueRedraw)
-                        }
                     }
+                    process_session_events(session);
                 }
-                _ => {
-                    status_bar.set_message(StatusMessage::new(
-                        format!("Undefined command: {}", trimmed),
-                        CommandStatusKind::Error,
-                        Some(STATUS_MESSAGE_TTL),
-                    ));
-                    Ok(LoopAction::ContinueRedraw)
-                }
+                Some(FormFieldKind::Other) => status_bar.set_message(StatusMessage::new(
+                    t!("field-not-editable"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+                None => {}
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        UiEvent::FormInputChanged { .. } | UiEvent::FormInputCancel => {
+            Ok(LoopAction::ContinueRedraw)
+        }
+        UiEvent::FormInputSubmit { value } => {
+            if let Err(err) = session.apply(Command::SetFormFieldText { value }) {
+                status_bar.set_message(StatusMessage::new(
+                    t!("field-update-failed", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
             }
+            process_session_events(session);
+            Ok(LoopAction::ContinueRedraw)
         }
         UiEvent::BeginVisualMode => {
             session.apply(Command::EnterVisualMode)?;
@@ -1192,6 +2608,10 @@ fn handle_event(
             session.apply(Command::StartSelection)?;
             Ok(LoopAction::ContinueRedraw)
         }
+        UiEvent::VisualToggleBlock => {
+            session.apply(Command::ToggleBlockSelection)?;
+            Ok(LoopAction::ContinueRedraw)
+        }
         UiEvent::VisualMotion { motion, count } => {
             let mapped = map_visual_motion(motion);
             session.apply(Command::MoveVisualCursor {
@@ -1201,29 +2621,27 @@ fn handle_event(
             Ok(LoopAction::ContinueRedraw)
         }
         UiEvent::VisualYank => {
-            match session.selection_text() {
-                Some(text) => match copy_text_to_clipboard(&text) {
-                    Ok(_) => status_bar.set_message(StatusMessage::new(
-                        "Yanked selection",
-                        CommandStatusKind::Info,
-                        Some(STATUS_MESSAGE_TTL),
-                    )),
-                    Err(err) => status_bar.set_message(StatusMessage::new(
-                        format!("Clipboard error: {}", err),
-                        CommandStatusKind::Error,
-                        Some(STATUS_MESSAGE_TTL),
-                    )),
-                },
-                None => {
-                    status_bar.set_message(StatusMessage::new(
-                        "No active selection",
-                        CommandStatusKind::Error,
-                        Some(STATUS_MESSAGE_TTL),
-                    ));
-                    return Ok(LoopAction::ContinueRedraw);
-                }
-            }
+            let text = session.selection_text();
+            yank_selection(session, status_bar, text, t!("yanked-selection"))
+        }
+        UiEvent::VisualYankMarkdown => {
+            let text = session.selection_quote(QuoteFormat::Markdown);
+            yank_selection(session, status_bar, text, t!("yanked-quote"))
+        }
+        UiEvent::VisualYankLatex => {
+            let text = session.selection_quote(QuoteFormat::Latex);
+            yank_selection(session, status_bar, text, t!("yanked-quote"))
+        }
+        UiEvent::VisualZoom => {
+            let zoomed = zoom_to_selection(session);
             session.apply(Command::LeaveVisualMode)?;
+            if let Err(err) = zoomed {
+                status_bar.set_message(StatusMessage::new(
+                    err.to_string(),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+            }
             Ok(LoopAction::ContinueRedraw)
         }
         UiEvent::VisualClearSelection => {
@@ -1250,9 +2668,11 @@ fn handle_event(
                     UiEvent::Command(command),
                     session,
                     overlay,
+                    panes,
                     mapper,
                     search_manager,
                     status_bar,
+                    frame,
                 );
                 match action {
                     Err(_) => {
@@ -1278,10 +2698,19 @@ fn handle_event(
                     | Command::NextPage { .. }
                     | Command::PrevPage { .. }
                     | Command::ScaleBy { .. }
+                    | Command::SetScale { .. }
                     | Command::ResetScale
                     | Command::AdjustViewport { .. }
+                    | Command::ScrollLines { .. }
+                    | Command::ScrollScreens { .. }
+                    | Command::ZoomToRect { .. }
                     | Command::GotoMark { .. }
                     | Command::ToggleDarkMode
+                    | Command::SetDarkMode { .. }
+                    | Command::ToggleFitMode
+                    | Command::SetFitMode { .. }
+                    | Command::ToggleReflow
+                    | Command::SetReflow { .. }
                     | Command::Search { .. }
                     | Command::SearchNext { .. }
                     | Command::SearchPrev { .. }
@@ -1289,7 +2718,12 @@ fn handle_event(
                     | Command::LeaveLinkMode
                     | Command::LinkNext { .. }
                     | Command::LinkPrev { .. }
+                    | Command::ToggleLinkFilter { .. }
                     | Command::ActivateLink
+                    | Command::EnterFormMode
+                    | Command::LeaveFormMode
+                    | Command::FormNext { .. }
+                    | Command::FormPrev { .. }
                     | Command::JumpBackward
                     | Command::JumpForward
                     | Command::SwitchDocument { .. }
@@ -1304,8 +2738,24 @@ fn handle_event(
             let mark_goto = matches!(&cmd, Command::GotoNamedMark { .. });
 
             session.apply(cmd)?;
-            let event_redraw = process_session_events(session);
+            let mut followed = Vec::new();
+            let event_redraw = process_session_events_with(
+                session,
+                |boundary| {
+                    status_bar.set_message(StatusMessage::new(
+                        i18n::boundary(boundary),
+                        CommandStatusKind::Info,
+                        Some(STATUS_MESSAGE_TTL),
+                    ));
+                    feedback::boundary_reached();
+                },
+                |target| followed.push(target),
+            );
             redraw = redraw || event_redraw;
+            for target in followed {
+                follow_external_link(target, true, overlay, mapper, status_bar);
+                redraw = true;
+            }
 
             if resets_overlay {
                 overlay.deactivate();
@@ -1323,7 +2773,7 @@ fn handle_event(
                     OverlayState::Marks(window) => {
                         if mark_saved {
                             if let Some(doc) = session.active() {
-                                if !window.rebuild(doc) {
+                                if !window.rebuild(doc, session.global_marks()) {
                                     overlay.deactivate();
                                     mapper.set_mode(InputMode::Normal);
                                 } else {
@@ -1361,31 +2811,48 @@ fn handle_event(
             }
         }
         UiEvent::CloseOverlay => {
+            if matches!(overlay, OverlayState::Browser(_)) && session.active().is_none() {
+                // Nothing to go back to.
+                return Ok(LoopAction::Quit);
+            }
             if overlay.is_active() {
+                let mode = match overlay {
+                    OverlayState::Citation(popup) => popup.return_mode,
+                    OverlayState::LinkPrompt(prompt) => prompt.return_mode,
+                    _ => InputMode::Normal,
+                };
                 overlay.deactivate();
-                mapper.set_mode(InputMode::Normal);
+                mapper.set_mode(mode);
                 Ok(LoopAction::ContinueRedraw)
             } else {
                 Ok(LoopAction::Continue)
             }
         }
         UiEvent::TocMoveSelection { delta } => {
-            match overlay {
-                OverlayState::Toc(toc) => {
-                    if toc.move_selection(delta) {
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                }
-                OverlayState::Marks(window) => {
-                    if window.move_selection(delta) {
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                }
-                _ => {}
+            let changed = match overlay {
+                OverlayState::Citation(popup) => popup.scroll_by(delta),
+                OverlayState::CacheStats(popup) => popup.scroll_by(delta),
+                OverlayState::Info(popup) => popup.scroll_by(delta),
+                OverlayState::Stats(popup) => popup.scroll_by(delta),
+                OverlayState::LinkPrompt(prompt) => prompt.scroll_by(delta),
+                overlay => overlay
+                    .list_mut()
+                    .is_some_and(|list| list.move_selection(delta)),
+            };
+            if changed {
+                return Ok(LoopAction::ContinueRedraw);
             }
             Ok(LoopAction::Continue)
         }
         UiEvent::TocBeginSearch => {
+            if let OverlayState::Links(window) = overlay {
+                window.filter_input = true;
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            if let OverlayState::Browser(browser) = overlay {
+                browser.filter_input = true;
+                return Ok(LoopAction::ContinueRedraw);
+            }
             if let OverlayState::Toc(toc) = overlay {
                 if toc.begin_search() {
                     return Ok(LoopAction::ContinueRedraw);
@@ -1394,6 +2861,14 @@ fn handle_event(
             Ok(LoopAction::Continue)
         }
         UiEvent::TocSearchQueryChanged { query } => {
+            if let OverlayState::Links(window) = overlay {
+                window.set_filter(&query);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            if let OverlayState::Browser(browser) = overlay {
+                browser.set_filter(&query);
+                return Ok(LoopAction::ContinueRedraw);
+            }
             if let OverlayState::Toc(toc) = overlay {
                 toc.update_search_query(&query);
                 return Ok(LoopAction::ContinueRedraw);
@@ -1401,6 +2876,16 @@ fn handle_event(
             Ok(LoopAction::Continue)
         }
         UiEvent::TocSearchSubmit { query } => {
+            if let OverlayState::Links(window) = overlay {
+                window.set_filter(&query);
+                window.filter_input = false;
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            if let OverlayState::Browser(browser) = overlay {
+                browser.set_filter(&query);
+                browser.filter_input = false;
+                return Ok(LoopAction::ContinueRedraw);
+            }
             if let OverlayState::Toc(toc) = overlay {
                 let mut redraw = toc.apply_search_query(&query);
                 redraw |= toc.finish_search_input();
@@ -1411,6 +2896,16 @@ fn handle_event(
             Ok(LoopAction::Continue)
         }
         UiEvent::TocSearchCancel => {
+            if let OverlayState::Links(window) = overlay {
+                window.set_filter("");
+                window.filter_input = false;
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            if let OverlayState::Browser(browser) = overlay {
+                browser.set_filter("");
+                browser.filter_input = false;
+                return Ok(LoopAction::ContinueRedraw);
+            }
             if let OverlayState::Toc(toc) = overlay {
                 if toc.cancel_search() {
                     return Ok(LoopAction::ContinueRedraw);
@@ -1435,39 +2930,29 @@ fn handle_event(
             Ok(LoopAction::Continue)
         }
         UiEvent::TocGotoStart => {
-            match overlay {
-                OverlayState::Toc(toc) => {
-                    if toc.select_first() {
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                }
-                OverlayState::Marks(window) => {
-                    if window.select_first() {
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                }
-                _ => {}
+            let changed = overlay.list_mut().is_some_and(|list| list.select_first());
+            if changed {
+                return Ok(LoopAction::ContinueRedraw);
             }
             Ok(LoopAction::Continue)
         }
         UiEvent::TocGotoEnd => {
-            match overlay {
-                OverlayState::Toc(toc) => {
-                    if toc.select_last() {
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                }
-                OverlayState::Marks(window) => {
-                    if window.select_last() {
-                        return Ok(LoopAction::ContinueRedraw);
-                    }
-                }
-                _ => {}
+            let changed = overlay.list_mut().is_some_and(|list| list.select_last());
+            if changed {
+                return Ok(LoopAction::ContinueRedraw);
             }
             Ok(LoopAction::Continue)
         }
         UiEvent::TocActivateSelection => {
             match overlay {
+                OverlayState::LinkPrompt(prompt) => {
+                    let message = run_link_action(&prompt.action, &prompt.target);
+                    let mode = prompt.return_mode;
+                    status_bar.set_message(message);
+                    overlay.deactivate();
+                    mapper.set_mode(mode);
+                    return Ok(LoopAction::ContinueRedraw);
+                }
                 OverlayState::Toc(toc) => {
                     if let Some(entry) = toc.selected_entry() {
                         session.apply(Command::GotoPage {
@@ -1481,8 +2966,11 @@ fn handle_event(
                 }
                 OverlayState::Marks(window) => {
                     if let Some(entry) = window.selected_entry() {
-                        session.apply(Command::GotoNamedMark {
-                            name: entry.name.clone(),
+                        session.apply(match &entry.target {
+                            MarkTarget::Key(key) => Command::GotoMark { key: *key },
+                            MarkTarget::Named(name) => {
+                                Command::GotoNamedMark { name: name.clone() }
+                            }
                         })?;
                         let _ = process_session_events(session);
                         overlay.deactivate();
@@ -1490,908 +2978,3706 @@ fn handle_event(
                         return Ok(LoopAction::ContinueRedraw);
                     }
                 }
+                OverlayState::Jumps(window) => {
+                    if let Some(entry) = window.selected_entry() {
+                        session.apply(Command::GotoJump { index: entry.index })?;
+                        let _ = process_session_events(session);
+                        overlay.deactivate();
+                        mapper.set_mode(InputMode::Normal);
+                        return Ok(LoopAction::ContinueRedraw);
+                    }
+                }
+                OverlayState::History(window) => {
+                    if let Some(entry) = window.selected_entry() {
+                        session.apply(Command::GotoPage { page: entry.page })?;
+                        let _ = process_session_events(session);
+                        overlay.deactivate();
+                        mapper.set_mode(InputMode::Normal);
+                        return Ok(LoopAction::ContinueRedraw);
+                    }
+                }
+                OverlayState::Links(window) => {
+                    if let Some(entry) = window.selected_entry() {
+                        // The list already shows the URL, so there is nothing
+                        // to confirm.
+                        let target = ExternalLink::Url(entry.uri.clone());
+                        overlay.deactivate();
+                        mapper.set_mode(InputMode::Normal);
+                        follow_external_link(target, false, overlay, mapper, status_bar);
+                        return Ok(LoopAction::ContinueRedraw);
+                    }
+                }
+                OverlayState::Browser(browser) => {
+                    // A chosen document is opened by the main loop.
+                    if let Err(err) = browser.activate() {
+                        status_bar.set_message(StatusMessage::new(
+                            format!("{:#}", err),
+                            CommandStatusKind::Error,
+                            Some(STATUS_MESSAGE_TTL),
+                        ));
+                    }
+                    return Ok(LoopAction::ContinueRedraw);
+                }
                 _ => {}
             }
             Ok(LoopAction::Continue)
         }
+        UiEvent::TocDeleteSelection => {
+            let OverlayState::Marks(window) = overlay else {
+                return Ok(LoopAction::Continue);
+            };
+            let Some(entry) = window.selected_entry() else {
+                return Ok(LoopAction::Continue);
+            };
+            let label = entry.label();
+            session.apply(match &entry.target {
+                MarkTarget::Key(key) => Command::DeleteMark { key: *key },
+                MarkTarget::Named(name) => Command::DeleteNamedMark { name: name.clone() },
+            })?;
+            refresh_mark_overlay(overlay, session, mapper);
+            status_bar.set_message(StatusMessage::new(
+                t!("deleted-mark", name = label),
+                CommandStatusKind::Info,
+                Some(STATUS_MESSAGE_TTL),
+            ));
+            Ok(LoopAction::ContinueRedraw)
+        }
+        UiEvent::TocYank => {
+            if let OverlayState::LinkPrompt(prompt) = overlay {
+                let message = run_link_action(&LinkAction::Copy, &prompt.target);
+                let mode = prompt.return_mode;
+                status_bar.set_message(message);
+                overlay.deactivate();
+                mapper.set_mode(mode);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            yank_links(overlay, false)
+        }
+        UiEvent::TocYankAll => yank_links(overlay, true),
+        UiEvent::Suspend => Ok(LoopAction::Suspend),
         UiEvent::Quit => Ok(LoopAction::Quit),
         UiEvent::None => Ok(LoopAction::Continue),
     }
 }
 
-fn handle_search_input(
-    query: String,
+/// Copies `text` (the selection, possibly wrapped) and leaves visual mode.
+fn yank_selection(
     session: &mut Session,
-    search_manager: &mut SearchManager,
-) -> Result<()> {
-    if query.trim().is_empty() {
-        search_manager.cancel_pending();
-        session.apply(Command::Search {
-            query: String::new(),
-        })?;
-        let _ = process_session_events(session);
-    } else {
-        search_manager.start_search(session, query);
+    status_bar: &mut StatusBar,
+    text: Option<String>,
+    done: String,
+) -> Result<LoopAction> {
+    match text {
+        Some(text) => match copy_text_to_clipboard(&text) {
+            Ok(_) => status_bar.set_message(StatusMessage::new(
+                done,
+                CommandStatusKind::Info,
+                Some(STATUS_MESSAGE_TTL),
+            )),
+            Err(err) => status_bar.set_message(StatusMessage::new(
+                t!("clipboard-error", error = err),
+                CommandStatusKind::Error,
+                Some(STATUS_MESSAGE_TTL),
+            )),
+        },
+        None => {
+            status_bar.set_message(StatusMessage::new(
+                t!("no-active-selection"),
+                CommandStatusKind::Error,
+                Some(STATUS_MESSAGE_TTL),
+            ));
+            return Ok(LoopAction::ContinueRedraw);
+        }
     }
-    Ok(())
+    session.apply(Command::LeaveVisualMode)?;
+    Ok(LoopAction::ContinueRedraw)
 }
 
-fn process_session_events(session: &Session) -> bool {
-    let mut redraw = false;
-    for event in session.drain_events() {
-        match event {
-            SessionEvent::RedrawNeeded(_) => redraw = true,
-            SessionEvent::FollowExternalLink { target } => {
-                if let Err(err) = open_external_link(&target) {
-                    warn!(?err, "failed to open external link");
-                }
-            }
-            SessionEvent::DocumentOpened(_)
-            | SessionEvent::DocumentClosed(_)
-            | SessionEvent::ActiveDocumentChanged(_) => {}
-        }
-    }
-    redraw
+fn yank_links(overlay: &mut OverlayState, all: bool) -> Result<LoopAction> {
+    let OverlayState::Links(window) = overlay else {
+        return Ok(LoopAction::Continue);
+    };
+    let uris: Vec<&str> = if all {
+        window
+            .visible_entries()
+            .map(|entry| entry.uri.as_str())
+            .collect()
+    } else {
+        window
+            .selected_entry()
+            .map(|entry| entry.uri.as_str())
+            .into_iter()
+            .collect()
+    };
+    if uris.is_empty() {
+        return Ok(LoopAction::Continue);
+    }
+    window.notice = Some(match copy_text_to_clipboard(&uris.join("\n")) {
+        Ok(()) if uris.len() == 1 => t!("copied-one-link"),
+        Ok(()) => t!("copied-links", count = uris.len()),
+        Err(err) => t!("clipboard-error", error = err),
+    });
+    Ok(LoopAction::ContinueRedraw)
 }
 
-fn redraw(
-    renderer: &mut KittyRenderer<io::Stdout>,
-    session: &Session,
-    pending_input: Option<&str>,
+fn execute_command_line(
+    line: &str,
+    session: &mut Session,
     overlay: &mut OverlayState,
-    status_bar: &StatusBar,
-) -> Result<()> {
-    let window = terminal::window_size()?;
-    let total_cols = u32::from(window.columns).max(1);
-    let total_rows = u32::from(window.rows).max(1);
-    let pixel_width = u32::from(window.width);
-    let pixel_height = u32::from(window.height);
-    let image_rows_available = total_rows.saturating_sub(1).max(1);
-
-    if let Some(doc) = session.active() {
-        if matches!(overlay, OverlayState::Toc(_) | OverlayState::Marks(_)) {
-            {
-                let mut writer = renderer.writer();
-                crossterm::execute!(&mut writer, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+    panes: &mut Panes,
+    mapper: &mut EventMapper,
+    status_bar: &mut StatusBar,
+    frame: Option<&ScreenFrame>,
+) -> Result<LoopAction> {
+    let tokens = tokenize_command(line);
+    if tokens.is_empty() {
+        return Ok(LoopAction::ContinueRedraw);
+    }
+    let command_name = tokens[0].to_ascii_lowercase();
+    match command_name.as_str() {
+        "q" | "quit" => Ok(LoopAction::Quit),
+        "suspend" | "st" => Ok(LoopAction::Suspend),
+        "mark" => {
+            if tokens.len() < 2 {
+                status_bar.set_message(StatusMessage::new(
+                    t!("usage-mark"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
             }
-            draw_overlay(
-                renderer,
-                overlay,
-                total_cols,
-                total_rows,
-                image_rows_available,
-            )?;
-            return Ok(());
-        }
-
-        let margin_cols = total_cols.min(2);
-        let margin_rows = image_rows_available.min(2);
-        let available_cols = total_cols.saturating_sub(margin_cols).max(1);
-        let available_rows = image_rows_available.saturating_sub(margin_rows).max(1);
-
-        let base_scale = doc.state.scale;
-        let mut render_scale = base_scale;
-        let search_highlights = doc.search_highlights_for_current_page();
-        let link_highlights = doc.link_highlights_for_current_page();
-        let selection_highlights = doc.selection_highlights_for_current_page();
-        let mut image = doc.render_with_scale(base_scale)?;
-        let mut highlight_geom = HighlightGeometry::new(image.width, image.height);
-
-        let cell_width = if total_cols > 0 {
-            pixel_width as f32 / total_cols as f32
-        } else {
-            0.0
-        };
-        let cell_height = if total_rows > 0 {
-            pixel_height as f32 / total_rows as f32
-        } else {
-            0.0
-        };
-
-        if cell_width > 0.0
-            && cell_height > 0.0
-            && image.width > 0
-            && image.height > 0
-            && pixel_width > 0
-            && pixel_height > 0
-        {
-            let desired_pixel_width = cell_width * available_cols as f32;
-            let desired_pixel_height = cell_height * available_rows as f32;
-            if desired_pixel_width > 0.0 && desired_pixel_height > 0.0 {
-                let width_ratio = desired_pixel_width / image.width as f32;
-                let height_ratio = desired_pixel_height / image.height as f32;
-                let scale_ratio = width_ratio.min(height_ratio);
-                if scale_ratio > 1.05 {
-                    let target_scale = (base_scale * scale_ratio).min(8.0);
-                    render_scale = target_scale;
-                    image = doc.render_with_scale(target_scale)?;
-                    highlight_geom.set_base(image.width, image.height);
-                }
+            if session.active().is_none() {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
             }
+            let name = tokens[1].clone();
+            let page = session
+                .active()
+                .map(|doc| doc.state.current_page)
+                .unwrap_or(0);
+            session.apply(Command::SaveNamedMark { name: name.clone() })?;
+            refresh_mark_overlay(overlay, session, mapper);
+            status_bar.set_message(StatusMessage::new(
+                t!("saved-mark", name = name, page = page + 1),
+                CommandStatusKind::Info,
+                Some(STATUS_MESSAGE_TTL),
+            ));
+            Ok(LoopAction::ContinueRedraw)
         }
-
-        let zoom_scale = doc.state.scale;
-        let mut display_image = image;
-
-        if zoom_scale <= 1.0 {
-            highlight_geom.set_base(display_image.width, display_image.height);
-        }
-
-        if zoom_scale > 1.0 {
-            let crop_ratio = (1.0 / zoom_scale).min(1.0);
-            if crop_ratio.is_finite() && crop_ratio > 0.0 {
-                let crop_width = (display_image.width as f32 * crop_ratio)
-                    .round()
-                    .clamp(1.0, display_image.width as f32) as u32;
-                let crop_height = (display_image.height as f32 * crop_ratio)
-                    .round()
-                    .clamp(1.0, display_image.height as f32)
-                    as u32;
-                if crop_width < display_image.width || crop_height < display_image.height {
-                    let viewport = doc.state.viewport;
-                    let offset_x =
-                        compute_viewport_origin(display_image.width, crop_width, viewport.x);
-                    let offset_y =
-                        compute_viewport_origin(display_image.height, crop_height, viewport.y);
-                    highlight_geom.set_crop(offset_x, offset_y, crop_width, crop_height);
-                    display_image = crop_render_image(
-                        &display_image,
-                        offset_x,
-                        offset_y,
-                        crop_width,
-                        crop_height,
-                    );
+        "goto" | "go" => {
+            if tokens.len() < 2 {
+                status_bar.set_message(StatusMessage::new(
+                    t!("usage-goto"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let name = tokens[1].clone();
+            let target_page = session.active().and_then(|doc| doc.named_mark_page(&name));
+            let Some(page) = target_page else {
+                // Not a mark, so maybe a page label such as `iv` or `37`.
+                if session
+                    .active()
+                    .and_then(|doc| doc.page_for_label(&name))
+                    .is_some()
+                {
+                    session.apply(Command::GotoPageLabel { label: name })?;
+                    return Ok(LoopAction::ContinueRedraw);
+                }
+                status_bar.set_message(StatusMessage::new(
+                    t!("unknown-goto-target", name = name),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            session.apply(Command::GotoNamedMark { name: name.clone() })?;
+            refresh_mark_overlay(overlay, session, mapper);
+            status_bar.set_message(StatusMessage::new(
+                t!("jumped-to-mark", name = name, page = page + 1),
+                CommandStatusKind::Info,
+                Some(STATUS_MESSAGE_TTL),
+            ));
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "listmarks" | "marks" => {
+            if matches!(overlay, OverlayState::Marks(_)) {
+                overlay.deactivate();
+                mapper.set_mode(InputMode::Normal);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let Some(doc) = session.active() else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            match MarkWindow::from_document(doc, session.global_marks()) {
+                Some(window) => {
+                    *overlay = OverlayState::Marks(window);
+                    mapper.set_mode(InputMode::Toc);
+                    Ok(LoopAction::ContinueRedraw)
+                }
+                None => {
+                    status_bar.set_message(StatusMessage::new(
+                        t!("no-marks-saved"),
+                        CommandStatusKind::Info,
+                        Some(STATUS_MESSAGE_TTL),
+                    ));
+                    Ok(LoopAction::ContinueRedraw)
+                }
+            }
+        }
+        "jumps" | "ju" => {
+            if matches!(overlay, OverlayState::Jumps(_)) {
+                overlay.deactivate();
+                mapper.set_mode(InputMode::Normal);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let Some(doc) = session.active() else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            match JumpsWindow::from_document(doc) {
+                Some(window) => {
+                    *overlay = OverlayState::Jumps(window);
+                    mapper.set_mode(InputMode::Toc);
+                }
+                None => status_bar.set_message(StatusMessage::new(
+                    t!("no-jumps"),
+                    CommandStatusKind::Info,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "history" | "his" => {
+            if matches!(overlay, OverlayState::History(_)) {
+                overlay.deactivate();
+                mapper.set_mode(InputMode::Normal);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let Some(doc) = session.active() else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            match HistoryWindow::from_document(doc) {
+                Some(window) => {
+                    *overlay = OverlayState::History(window);
+                    mapper.set_mode(InputMode::Toc);
+                }
+                None => status_bar.set_message(StatusMessage::new(
+                    t!("no-history"),
+                    CommandStatusKind::Info,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "vsplit" | "vs" | "split" | "sp" => {
+            let orientation = if command_name.starts_with('v') {
+                Orientation::Vertical
+            } else {
+                Orientation::Horizontal
+            };
+            if let Some(path) = tokens.get(1) {
+                panes.request_open(expand_home(path), orientation);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            // Without a file the active document is shown a second time.
+            match session.active().map(|doc| doc.info.id) {
+                Some(active) => {
+                    session.apply(Command::SplitView)?;
+                    panes.split(orientation, active);
+                }
+                None => status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "only" | "on" => {
+            panes.close(session)?;
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "browse" => {
+            let dir = match tokens.get(1) {
+                Some(dir) => expand_home(dir),
+                None => session
+                    .active()
+                    .and_then(|doc| doc.info.path.parent().map(Path::to_path_buf))
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .unwrap_or_else(|| PathBuf::from(".")),
+            };
+            match FileBrowser::new(&dir) {
+                Ok(browser) => {
+                    *overlay = OverlayState::Browser(browser);
+                    mapper.set_mode(InputMode::Toc);
+                }
+                Err(err) => status_bar.set_message(StatusMessage::new(
+                    format!("{:#}", err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "cache-stats" => {
+            if matches!(overlay, OverlayState::CacheStats(_)) {
+                overlay.deactivate();
+                mapper.set_mode(InputMode::Normal);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            if session.documents().is_empty() {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            *overlay = OverlayState::CacheStats(TextPopup::cache_stats(session));
+            mapper.set_mode(InputMode::Toc);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "info" => {
+            if matches!(overlay, OverlayState::Info(_)) {
+                overlay.deactivate();
+                mapper.set_mode(InputMode::Normal);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let Some(doc) = session.active() else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            *overlay = OverlayState::Info(TextPopup::document_info(doc));
+            mapper.set_mode(InputMode::Toc);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "cite" => {
+            let message = match session.active() {
+                None => StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+                Some(doc) => yank_citation(doc),
+            };
+            status_bar.set_message(message);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "stats" => {
+            if matches!(overlay, OverlayState::Stats(_)) {
+                overlay.deactivate();
+                mapper.set_mode(InputMode::Normal);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let Some(doc) = session.active() else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            let today = reading::today(session.clock().as_ref());
+            *overlay = OverlayState::Stats(TextPopup::reading_stats(doc, today));
+            mapper.set_mode(InputMode::Toc);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "doi" => {
+            let Some(doc) = session.active() else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            let Some(doi) = doc.doi() else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-doi-found"),
+                    CommandStatusKind::Info,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            match tokens.get(1).map(|arg| arg.to_ascii_lowercase()).as_deref() {
+                None | Some("copy" | "yank") => {
+                    let message = match copy_text_to_clipboard(&doi) {
+                        Ok(()) => StatusMessage::new(
+                            t!("copied-doi", doi = doi),
+                            CommandStatusKind::Info,
+                            Some(STATUS_MESSAGE_TTL),
+                        ),
+                        Err(err) => StatusMessage::new(
+                            t!("clipboard-error", error = err),
+                            CommandStatusKind::Error,
+                            Some(STATUS_MESSAGE_TTL),
+                        ),
+                    };
+                    status_bar.set_message(message);
+                }
+                Some("open") => {
+                    let target = ExternalLink::Url(format!("{}{}", DOI_RESOLVER, doi));
+                    follow_external_link(target, true, overlay, mapper, status_bar);
+                }
+                Some(_) => status_bar.set_message(StatusMessage::new(
+                    t!("usage-doi"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "links" => {
+            if matches!(overlay, OverlayState::Links(_)) {
+                overlay.deactivate();
+                mapper.set_mode(InputMode::Normal);
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let Some(doc) = session.active() else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            let entries = doc.uri_links();
+            if entries.is_empty() {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-links-found"),
+                    CommandStatusKind::Info,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            *overlay = OverlayState::Links(LinksWindow::new(entries));
+            mapper.set_mode(InputMode::Toc);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "highlight" | "hl" => {
+            let result = match tokens.get(1).map(|arg| arg.to_ascii_lowercase()).as_deref() {
+                Some("add") => parse_highlight_add(&tokens[2..]).and_then(|(page, rects)| {
+                    let count = rects.len();
+                    session.apply(Command::AddExternalHighlights { page, rects })?;
+                    Ok(format!("Added {} highlight(s) on page {}", count, page + 1))
+                }),
+                Some("clear") => parse_highlight_page(tokens.get(2)).and_then(|page| {
+                    session.apply(Command::ClearExternalHighlights { page })?;
+                    Ok(match page {
+                        Some(page) => format!("Cleared highlights on page {}", page + 1),
+                        None => "Cleared highlights".to_string(),
+                    })
+                }),
+                _ => Err(anyhow!(
+                    "Usage: :highlight add <page> <left> <top> <right> <bottom> ... | :highlight clear [page]"
+                )),
+            };
+            process_session_events(session);
+            let message = match result {
+                Ok(text) => {
+                    StatusMessage::new(text, CommandStatusKind::Info, Some(STATUS_MESSAGE_TTL))
+                }
+                Err(err) => StatusMessage::new(
+                    err.to_string(),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+            };
+            status_bar.set_message(message);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "zoom-selection" | "zoom-rect" => {
+            let result = if command_name == "zoom-selection" {
+                zoom_to_selection(session)
+            } else {
+                parse_zoom_rect(&tokens[1..])
+                    .and_then(|rect| session.apply(Command::ZoomToRect { rect }))
+            };
+            process_session_events(session);
+            if let Err(err) = result {
+                status_bar.set_message(StatusMessage::new(
+                    err.to_string(),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "fit" => {
+            let Some(current) = session.active().map(|doc| doc.state.fit_mode) else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            let value = tokens.get(1).map(String::as_str).unwrap_or("toggle");
+            match parse_fit_mode(value, current) {
+                Ok(mode) => {
+                    session.apply(Command::SetFitMode { mode })?;
+                    process_session_events(session);
+                    status_bar.set_message(StatusMessage::new(
+                        format!("fit={}", mode.label()),
+                        CommandStatusKind::Info,
+                        Some(STATUS_MESSAGE_TTL),
+                    ));
+                }
+                Err(err) => status_bar.set_message(StatusMessage::new(
+                    t!("usage-fit", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                )),
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "zoom" => {
+            if session.active().is_none() {
+                status_bar.set_message(StatusMessage::new(
+                    t!("no-active-document"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            if let Some(value) = tokens.get(1) {
+                match parse_zoom(value) {
+                    Ok(command) => {
+                        session.apply(command)?;
+                        process_session_events(session);
+                    }
+                    Err(err) => {
+                        status_bar.set_message(StatusMessage::new(
+                            t!("usage-zoom", error = err),
+                            CommandStatusKind::Error,
+                            Some(STATUS_MESSAGE_TTL),
+                        ));
+                        return Ok(LoopAction::ContinueRedraw);
+                    }
+                }
+            }
+            let scale = session.active().map_or(1.0, |doc| doc.state.scale);
+            status_bar.set_message(StatusMessage::new(
+                format!("zoom={}%", (scale * 100.0).round()),
+                CommandStatusKind::Info,
+                Some(STATUS_MESSAGE_TTL),
+            ));
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "set" => {
+            if tokens.len() < 2 {
+                status_bar.set_message(StatusMessage::new(
+                    t!("usage-set"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let mut reports = Vec::new();
+            for (option, value) in parse_set_arguments(&tokens[1..]) {
+                match apply_set_option(session, &option, value.as_deref()) {
+                    Ok(report) => reports.push(report),
+                    Err(err) => {
+                        status_bar.set_message(StatusMessage::new(
+                            err.to_string(),
+                            CommandStatusKind::Error,
+                            Some(STATUS_MESSAGE_TTL),
+                        ));
+                        return Ok(LoopAction::ContinueRedraw);
+                    }
+                }
+            }
+            status_bar.set_message(StatusMessage::new(
+                reports.join(" "),
+                CommandStatusKind::Info,
+                Some(STATUS_MESSAGE_TTL),
+            ));
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "next" | "prev" | "previous" => {
+            let count = match tokens.get(1).map(|value| value.parse::<usize>()) {
+                None => 1,
+                Some(Ok(count)) if count > 0 => count,
+                Some(_) => {
+                    status_bar.set_message(StatusMessage::new(
+                        t!("usage-count", command = command_name),
+                        CommandStatusKind::Error,
+                        Some(STATUS_MESSAGE_TTL),
+                    ));
+                    return Ok(LoopAction::ContinueRedraw);
+                }
+            };
+            if command_name == "next" {
+                session.apply(Command::NextPage { count })?;
+            } else {
+                session.apply(Command::PrevPage { count })?;
+            }
+            Ok(LoopAction::ContinueRedraw)
+        }
+        // `:15` goes to page 15, like vim's `:15` goes to line 15.
+        number if !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit()) => {
+            let page = number.parse::<usize>().unwrap_or(usize::MAX);
+            session.apply(Command::GotoPage {
+                page: page.saturating_sub(1),
+            })?;
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "page" | "first" | "last" | "$" => {
+            let page = match command_name.as_str() {
+                "first" => Some(0),
+                "last" | "$" => session
+                    .active()
+                    .map(|doc| doc.info.page_count.saturating_sub(1)),
+                _ => tokens
+                    .get(1)
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|page| *page > 0)
+                    .map(|page| page - 1),
+            };
+            let Some(page) = page else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("usage-page"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            session.apply(Command::GotoPage { page })?;
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "export-page" | "export-selection" => {
+            let selection_only = command_name == "export-selection";
+            let message = match export_png(session, &tokens[1..], selection_only) {
+                Ok(message) => {
+                    notifications::exported(Path::new(&tokens[1]));
+                    StatusMessage::new(message, CommandStatusKind::Info, Some(STATUS_MESSAGE_TTL))
+                }
+                Err(err) => StatusMessage::new(
+                    t!("export-failed", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+            };
+            status_bar.set_message(message);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "export-view" => {
+            let message = match export_view(frame, &tokens[1..]) {
+                Ok(message) => {
+                    notifications::exported(Path::new(&tokens[1]));
+                    StatusMessage::new(message, CommandStatusKind::Info, Some(STATUS_MESSAGE_TTL))
+                }
+                Err(err) => StatusMessage::new(
+                    t!("export-failed", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+            };
+            status_bar.set_message(message);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "mksession" | "mks" => {
+            let name = tokens.get(1).cloned();
+            let message = match session.apply(Command::SaveSession { name }) {
+                Ok(()) => StatusMessage::new(
+                    t!(
+                        "saved-session",
+                        name = session.session_name().unwrap_or_default()
+                    ),
+                    CommandStatusKind::Info,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+                Err(err) => StatusMessage::new(
+                    t!("session-not-saved", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+            };
+            status_bar.set_message(message);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "extract" => {
+            let pages = tokens.get(1).map(|value| parse_page_range(value));
+            let (Some(Ok(pages)), Some(path)) = (pages, tokens.get(2)) else {
+                status_bar.set_message(StatusMessage::new(
+                    t!("usage-extract"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            };
+            let path = PathBuf::from(path);
+            let count = pages.end() - pages.start() + 1;
+            let message = match session.apply(Command::ExtractPages {
+                pages,
+                path: path.clone(),
+            }) {
+                Ok(()) => StatusMessage::new(
+                    t!("wrote-pages", count = count, path = path.display()),
+                    CommandStatusKind::Info,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+                Err(err) => StatusMessage::new(
+                    t!("extract-failed", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+            };
+            status_bar.set_message(message);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        "saveas" | "w" => {
+            if tokens.len() < 2 {
+                status_bar.set_message(StatusMessage::new(
+                    t!("usage-saveas"),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ));
+                return Ok(LoopAction::ContinueRedraw);
+            }
+            let path = PathBuf::from(&tokens[1]);
+            let message = match session.apply(Command::SaveDocumentAs { path: path.clone() }) {
+                Ok(()) => StatusMessage::new(
+                    t!("saved-path", path = path.display()),
+                    CommandStatusKind::Info,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+                Err(err) => StatusMessage::new(
+                    t!("save-failed", error = err),
+                    CommandStatusKind::Error,
+                    Some(STATUS_MESSAGE_TTL),
+                ),
+            };
+            status_bar.set_message(message);
+            Ok(LoopAction::ContinueRedraw)
+        }
+        _ => {
+            status_bar.set_message(StatusMessage::new(
+                t!("undefined-command", command = line),
+                CommandStatusKind::Error,
+                Some(STATUS_MESSAGE_TTL),
+            ));
+            Ok(LoopAction::ContinueRedraw)
+        }
+    }
+}
+
+fn handle_ipc_request(
+    request: IpcRequest,
+    session: &mut Session,
+    overlay: &mut OverlayState,
+    panes: &mut Panes,
+    mapper: &mut EventMapper,
+    status_bar: &mut StatusBar,
+    frame: Option<&ScreenFrame>,
+) -> LoopAction {
+    let mut scratch = StatusBar::default();
+    let (action, reply) = match execute_command_line(
+        &request.line,
+        session,
+        overlay,
+        panes,
+        mapper,
+        &mut scratch,
+        frame,
+    ) {
+        Ok(action) => {
+            let reply = match scratch.take_message() {
+                Some(message) => {
+                    let reply = if message.kind == CommandStatusKind::Error {
+                        IpcReply::Error(message.text.clone())
+                    } else {
+                        IpcReply::Ok(Some(message.text.clone()))
+                    };
+                    status_bar.set_message(message);
+                    reply
+                }
+                None => IpcReply::Ok(None),
+            };
+            (action, reply)
+        }
+        Err(err) => (LoopAction::ContinueRedraw, IpcReply::Error(err.to_string())),
+    };
+    let _ = request.reply.send(reply);
+    match action {
+        LoopAction::Continue if process_session_events(session) => LoopAction::ContinueRedraw,
+        action => action,
+    }
+}
+
+fn handle_search_input(
+    query: String,
+    session: &mut Session,
+    search_manager: &mut SearchManager,
+) -> Result<()> {
+    if query.trim().is_empty() {
+        search_manager.cancel_pending();
+        session.apply(Command::Search {
+            query: String::new(),
+        })?;
+        let _ = process_session_events(session);
+    } else {
+        search_manager.start_search(session, query);
+    }
+    Ok(())
+}
+
+/// Metadata title of the document, falling back to its file name.
+fn document_title(doc: &DocumentInstance) -> String {
+    doc.info
+        .metadata
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .map(str::to_string)
+        .or_else(|| {
+            doc.info
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| doc.info.path.display().to_string())
+}
+
+fn process_session_events(session: &Session) -> bool {
+    process_session_events_with(
+        session,
+        |_| {},
+        |target| {
+            // There is no popup to confirm in here.
+            let policy = links::policy();
+            let action = policy.action_for(&target);
+            if policy.confirm && action.launches() {
+                warn!(?target, "not following unconfirmed external link");
+                return;
+            }
+            let message = run_link_action(&action, &target);
+            if message.kind == CommandStatusKind::Error {
+                warn!(text = %message.text, "failed to follow external link");
+            }
+        },
+    )
+}
+
+/// Like [`process_session_events`], reporting motions that were blocked at a
+/// boundary to `on_blocked` and external links to follow to `on_link`.
+fn process_session_events_with(
+    session: &Session,
+    mut on_blocked: impl FnMut(MotionBoundary),
+    mut on_link: impl FnMut(ExternalLink),
+) -> bool {
+    let mut redraw = false;
+    for event in session.drain_events() {
+        match event {
+            SessionEvent::RedrawNeeded(_) => redraw = true,
+            SessionEvent::FollowExternalLink { target } => on_link(target),
+            SessionEvent::DocumentReloaded { id, .. }
+            | SessionEvent::SearchCompleted { id, .. } => {
+                let name = session.document(id).map(document_title);
+                notifications::dispatch(&event, name.as_deref());
+            }
+            SessionEvent::DocumentSaved { .. } | SessionEvent::PagesExtracted { .. } => {
+                notifications::dispatch(&event, None)
+            }
+            SessionEvent::MotionBlocked(boundary) => {
+                on_blocked(boundary);
+                redraw = true;
+            }
+            SessionEvent::DocumentOpened(_)
+            | SessionEvent::DocumentClosed(_)
+            | SessionEvent::ActiveDocumentChanged(_) => {}
+        }
+    }
+    redraw
+}
+
+/// Terminal size in cells and pixels, read once per redraw.
+#[derive(Debug, Clone, Copy)]
+struct WindowMetrics {
+    cols: u32,
+    rows: u32,
+    pixel_width: u32,
+    pixel_height: u32,
+}
+
+/// Renders beyond this scale are stretched by the terminal instead.
+const MAX_RENDER_SCALE: f32 = 8.0;
+
+/// Scale at which a page of `size` fills `desired` pixels, by its width
+/// with fit-width and otherwise by whichever side runs out first, times
+/// `zoom`. Zooming out below 1 still fills the view.
+fn exact_render_scale(size: PageSize, desired: (f32, f32), fit_width: bool, zoom: f32) -> f32 {
+    if size.width <= 0.0 || size.height <= 0.0 {
+        return zoom;
+    }
+    let width_scale = desired.0 / size.width;
+    let fit = if fit_width {
+        width_scale
+    } else {
+        width_scale.min(desired.1 / size.height)
+    };
+    fit * zoom.max(1.0)
+}
+
+/// A page as drawn into one pane.
+struct PageView {
+    /// What the pane shows, with highlights composited.
+    image: RenderImage,
+    render_scale: f32,
+    /// The image is a low-resolution stand-in while the page renders.
+    preview: bool,
+    geometry: ViewGeometry,
+}
+
+/// Renders the current page of `doc` into `area` of the selected renderer
+/// pane. Highlights belong to the focused pane only, where keys act.
+#[allow(clippy::too_many_arguments)]
+fn draw_page_view(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    session: &Session,
+    doc: &DocumentInstance,
+    view: DocumentPosition,
+    area: PaneArea,
+    window: WindowMetrics,
+    focused: bool,
+    render_failure: &mut Option<RenderFailure>,
+) -> Result<PageView> {
+    let WindowMetrics {
+        cols: total_cols,
+        rows: total_rows,
+        pixel_width,
+        pixel_height,
+    } = window;
+    let margin_cols = area.cols.min(2);
+    let margin_rows = area.rows.min(2);
+    let available_cols = area.cols.saturating_sub(margin_cols).max(1);
+    let available_rows = area.rows.saturating_sub(margin_rows).max(1);
+
+    let base_scale = view.scale;
+    let search_highlights = doc.search_highlights_for_current_page().filter(|_| focused);
+    let link_highlights = doc.link_highlights_for_current_page().filter(|_| focused);
+    let selection_highlights = doc
+        .selection_highlights_for_current_page()
+        .filter(|_| focused);
+    let external_highlights = doc
+        .external_highlights_for_current_page()
+        .filter(|_| focused);
+    let form_highlights = doc.form_highlights_for_current_page().filter(|_| focused);
+    let progressive = session.progressive();
+    // Pages may be rendered below the display resolution to save memory.
+    let resolution = session.memory_limits().resolution;
+    let requested = |scale: f32| scale.min(MAX_RENDER_SCALE) * resolution;
+
+    let cell_width = if total_cols > 0 {
+        pixel_width as f32 / total_cols as f32
+    } else {
+        0.0
+    };
+    let cell_height = if total_rows > 0 {
+        pixel_height as f32 / total_rows as f32
+    } else {
+        0.0
+    };
+
+    let fit_width = doc.state.fit_mode == FitMode::Width;
+    let desired_pixels = (cell_width > 0.0 && cell_height > 0.0).then_some((
+        cell_width * available_cols as f32,
+        cell_height * available_rows as f32,
+    ));
+    // With the cell size known the page is rendered at exactly the pixels
+    // of the cells it fills, zoomed in or not, so the terminal does not
+    // have to scale it.
+    // Without a size from the backend, the page is drawn at its zoom until
+    // the first render has told the document how big it is.
+    let render_scale = match (desired_pixels, doc.page_size(view.page)) {
+        (Some(desired), Some(size)) => exact_render_scale(size, desired, fit_width, base_scale),
+        _ => base_scale,
+    };
+    let (image, image_scale) = render_or_placeholder(
+        doc,
+        view,
+        requested(render_scale),
+        progressive,
+        render_failure,
+    );
+    // A low-resolution image is sized as a full render would be.
+    let upscale = render_scale / image_scale;
+    let mut highlight_geom = HighlightGeometry::new(image.width, image.height);
+
+    let zoom_scale = view.scale;
+    let mut display_image = image;
+
+    if zoom_scale <= 1.0 {
+        highlight_geom.set_base(display_image.width, display_image.height);
+    }
+
+    let visible_width = (1.0 / zoom_scale).min(1.0);
+    let mut visible_height = visible_width;
+    if fit_width && display_image.width > 0 && display_image.height > 0 {
+        if let Some((desired_width, desired_height)) = desired_pixels {
+            // Height of the whole page once its width fills the available columns.
+            let page_height =
+                desired_width * display_image.height as f32 / display_image.width as f32;
+            visible_height = (desired_height / page_height / zoom_scale.max(1.0)).min(1.0);
+        }
+    }
+
+    let mut view_geometry = ViewGeometry {
+        cols: 0,
+        rows: 0,
+        visible_width: 1.0,
+        visible_height: 1.0,
+    };
+    if visible_width < 1.0 || visible_height < 1.0 {
+        if visible_width.is_finite()
+            && visible_width > 0.0
+            && visible_height.is_finite()
+            && visible_height > 0.0
+        {
+            let crop_width = (display_image.width as f32 * visible_width)
+                .round()
+                .clamp(1.0, display_image.width as f32) as u32;
+            let crop_height = (display_image.height as f32 * visible_height)
+                .round()
+                .clamp(1.0, display_image.height as f32) as u32;
+            if crop_width < display_image.width || crop_height < display_image.height {
+                let viewport = view.viewport;
+                let offset_x = viewport_origin(display_image.width, crop_width, viewport.x);
+                let offset_y = viewport_origin(display_image.height, crop_height, viewport.y);
+                view_geometry.visible_width = crop_width as f32 / display_image.width as f32;
+                view_geometry.visible_height = crop_height as f32 / display_image.height as f32;
+                highlight_geom.set_crop(offset_x, offset_y, crop_width, crop_height);
+                display_image =
+                    crop_render_image(&display_image, offset_x, offset_y, crop_width, crop_height);
+            }
+        }
+    } else {
+        highlight_geom.clear_crop();
+    }
+
+    let (effective_pixel_width, effective_pixel_height) = match desired_pixels {
+        Some((desired_width, _)) if fit_width && display_image.width > 0 => (
+            desired_width,
+            display_image.height as f32 * desired_width / display_image.width as f32,
+        ),
+        _ => (
+            display_image.width as f32 * upscale,
+            display_image.height as f32 * upscale,
+        ),
+    };
+
+    let (draw_cols, draw_rows) = compute_scaled_dimensions(
+        &display_image,
+        effective_pixel_width,
+        effective_pixel_height,
+        available_cols,
+        available_rows,
+        total_cols,
+        total_rows,
+        pixel_width,
+        pixel_height,
+    );
+
+    let start_col = area.col + area.cols.saturating_sub(draw_cols) / 2;
+    let start_row = area.row + area.rows.saturating_sub(draw_rows) / 2;
+
+    // Highlights go on a transparent layer above the page, so changing
+    // them leaves the page image in the terminal untouched.
+    let cursor_highlight = doc.visual_cursor_highlight().filter(|_| focused);
+    let mut layer = RenderImage {
+        width: display_image.width,
+        height: display_image.height,
+        pixels: vec![0; display_image.pixels.len()],
+    };
+    let has_highlights = external_highlights.is_some()
+        || form_highlights.is_some()
+        || link_highlights.is_some()
+        || search_highlights.is_some()
+        || selection_highlights.is_some()
+        || cursor_highlight.is_some();
+    let stroke = HighlightStroke::for_scale(session.highlight_style(), image_scale);
+    if let Some(highlights) = external_highlights.as_ref() {
+        apply_highlights(
+            &mut layer,
+            highlights,
+            &highlight_geom,
+            &EXTERNAL_HIGHLIGHT_PALETTE,
+            stroke,
+        );
+    }
+    if let Some(highlights) = form_highlights.as_ref() {
+        apply_highlights(
+            &mut layer,
+            highlights,
+            &highlight_geom,
+            &FORM_HIGHLIGHT_PALETTE,
+            stroke,
+        );
+    }
+    if let Some(highlights) = link_highlights.as_ref() {
+        apply_highlights(
+            &mut layer,
+            highlights,
+            &highlight_geom,
+            &SEARCH_HIGHLIGHT_PALETTE,
+            stroke,
+        );
+    }
+    if let Some(highlights) = search_highlights.as_ref() {
+        apply_highlights(
+            &mut layer,
+            highlights,
+            &highlight_geom,
+            &SEARCH_HIGHLIGHT_PALETTE,
+            stroke,
+        );
+    }
+    if let Some(highlights) = selection_highlights.as_ref() {
+        apply_highlights(
+            &mut layer,
+            highlights,
+            &highlight_geom,
+            &SELECTION_HIGHLIGHT_PALETTE,
+            stroke,
+        );
+    }
+    if let Some(rect) = cursor_highlight {
+        let mut cursor_highlight = Highlights::default();
+        cursor_highlight.current.push(rect);
+        apply_highlights(
+            &mut layer,
+            &cursor_highlight,
+            &highlight_geom,
+            &CURSOR_HIGHLIGHT_PALETTE,
+            stroke,
+        );
+    }
+
+    let placement = Placement {
+        column: start_col as u16,
+        row: start_row as u16,
+        columns: draw_cols.max(1),
+        rows: draw_rows.max(1),
+    };
+    renderer.place(&display_image, placement)?;
+    if has_highlights {
+        renderer.place_layer(Layer::Highlights, &layer, placement)?;
+        composite_layer(&mut display_image, &layer);
+    } else {
+        renderer.hide_layer(Layer::Highlights)?;
+    }
+    view_geometry.cols = draw_cols;
+    view_geometry.rows = draw_rows;
+    Ok(PageView {
+        image: display_image,
+        render_scale: requested(render_scale),
+        preview: image_scale < requested(render_scale),
+        geometry: view_geometry,
+    })
+}
+
+/// Names the document of a pane above it; the focused pane's title is
+/// drawn in reverse video.
+fn draw_pane_title(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    area: PaneArea,
+    row: u32,
+    doc: &DocumentInstance,
+    page: usize,
+    focused: bool,
+) -> Result<()> {
+    let title = format!(
+        " {} — {}/{}",
+        document_title(doc),
+        page + 1,
+        doc.info.page_count
+    );
+    // Padded to the pane width so a shorter title covers the previous one.
+    let text = truncate_with_ellipsis(title, area.cols as usize);
+    let style = if focused {
+        Style::REVERSE
+    } else {
+        Style::PLAIN
+    };
+    renderer
+        .screen()
+        .buffer()
+        .set_str(area.col as u16, row as u16, &text, style);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn redraw(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    session: &Session,
+    pending_input: Option<&str>,
+    overlay: &mut OverlayState,
+    panes: &Panes,
+    status_bar: &StatusBar,
+    render_failure: &mut Option<RenderFailure>,
+    last_frame: &mut Option<ScreenFrame>,
+) -> Result<Option<ViewGeometry>> {
+    *last_frame = None;
+    let window = terminal::window_size()?;
+    let total_cols = u32::from(window.columns).max(1);
+    let total_rows = u32::from(window.rows).max(1);
+    let (pixel_width, pixel_height) = window_pixels(&window);
+    let image_rows_available = total_rows.saturating_sub(1).max(1);
+    renderer
+        .screen()
+        .begin_frame(total_cols as u16, total_rows as u16);
+
+    if let Some(doc) = session.active() {
+        if matches!(
+            overlay,
+            OverlayState::Toc(_)
+                | OverlayState::Marks(_)
+                | OverlayState::Jumps(_)
+                | OverlayState::History(_)
+                | OverlayState::Links(_)
+                | OverlayState::Citation(_)
+                | OverlayState::CacheStats(_)
+                | OverlayState::Info(_)
+                | OverlayState::Stats(_)
+                | OverlayState::LinkPrompt(_)
+                | OverlayState::Browser(_)
+        ) {
+            renderer.hide_images()?;
+            draw_overlay(
+                renderer,
+                overlay,
+                total_cols,
+                total_rows,
+                image_rows_available,
+            )?;
+            return Ok(None);
+        }
+
+        if session.reflow() {
+            renderer.delete_image()?;
+            let geometry = draw_reflow(renderer, doc, total_cols, image_rows_available)?;
+            if matches!(overlay, OverlayState::Command(_)) {
+                // Command overlay owns the status row; nothing else to draw here.
+            } else if let Some(message) = status_bar.message() {
+                draw_status_message(renderer, message)?;
+            } else {
+                let status_text = t!("status-reflow", status = format_document_status(doc));
+                if let Some(status) = combine_status(Some(status_text), pending_input) {
+                    draw_status_line(renderer, &status)?;
+                }
+            }
+            draw_overlay(
+                renderer,
+                overlay,
+                total_cols,
+                total_rows,
+                image_rows_available,
+            )?;
+            // Screen-wise scrolling goes by the text on screen.
+            return Ok(Some(geometry));
+        }
+
+        let window = WindowMetrics {
+            cols: total_cols,
+            rows: total_rows,
+            pixel_width,
+            pixel_height,
+        };
+        let layout = panes.layout(total_cols, image_rows_available);
+        let mut focused_view = None;
+        for (index, pane) in layout.iter().enumerate() {
+            let pane_view = match pane.doc {
+                // A second view of the active document.
+                Some(id) if id == doc.info.id => doc.other_view().map(|view| (doc, view)),
+                Some(id) => session
+                    .document(id)
+                    .map(|other| (other, other.current_position())),
+                None => Some((doc, doc.current_position())),
+            };
+            let Some((pane_doc, pane_position)) = pane_view else {
+                continue;
+            };
+            renderer.select_pane(index);
+            let view = draw_page_view(
+                renderer,
+                session,
+                pane_doc,
+                pane_position,
+                pane.area,
+                window,
+                pane.focused,
+                render_failure,
+            )?;
+            if let Some(row) = pane.title_row {
+                draw_pane_title(
+                    renderer,
+                    pane.area,
+                    row,
+                    pane_doc,
+                    pane_position.page,
+                    pane.focused,
+                )?;
+            }
+            if pane.focused {
+                focused_view = Some(view);
+            }
+        }
+        renderer.select_pane(0);
+        renderer.hide_pane(layout.len())?;
+        let Some(view) = focused_view else {
+            return Ok(None);
+        };
+
+        if matches!(overlay, OverlayState::Command(_)) {
+            // Command overlay owns the status row; nothing else to draw here.
+        } else if let Some(message) = status_bar.message() {
+            draw_status_message(renderer, message)?;
+        } else {
+            let status_text = format_document_status(doc);
+            if let Some(status) = combine_status(Some(status_text), pending_input) {
+                draw_status_line(renderer, &status)?;
+            }
+        }
+
+        // Neighbours of a page still rendering would hold up its full render.
+        if !view.preview {
+            if let Err(err) = doc.prefetch_neighbors(session.prefetch(), view.render_scale) {
+                warn!(
+                    ?err,
+                    page = doc.state.current_page,
+                    "failed to prefetch neighboring pages"
+                );
+            }
+        }
+
+        draw_overlay(
+            renderer,
+            overlay,
+            total_cols,
+            total_rows,
+            image_rows_available,
+        )?;
+        let geometry = view.geometry;
+        *last_frame = Some(ScreenFrame {
+            image: view.image,
+            render_scale: view.render_scale,
+            plain: !view.preview && geometry.visible_width >= 1.0 && geometry.visible_height >= 1.0,
+        });
+        Ok(Some(geometry))
+    } else {
+        // Nothing is open yet, e.g. while browsing for a first document.
+        renderer.hide_images()?;
+        if !matches!(overlay, OverlayState::Browser(_) | OverlayState::Command(_)) {
+            overlay.deactivate();
+        }
+        if !overlay.is_command() {
+            if let Some(message) = status_bar.message() {
+                draw_status_message(renderer, message)?;
+            }
+        }
+        draw_overlay(
+            renderer,
+            overlay,
+            total_cols,
+            total_rows,
+            image_rows_available,
+        )?;
+        Ok(None)
+    }
+}
+
+fn document_status(session: &Session) -> Option<String> {
+    session.active().map(format_document_status)
+}
+
+fn combine_status(base: Option<String>, pending_input: Option<&str>) -> Option<String> {
+    match (base, pending_input.filter(|s| !s.is_empty())) {
+        (Some(mut base), Some(pending)) => {
+            base.push_str(" | ");
+            base.push_str(pending);
+            Some(base)
+        }
+        (Some(base), None) => Some(base),
+        (None, Some(pending)) => Some(pending.to_string()),
+        (None, None) => None,
+    }
+}
+
+fn draw_status_line(renderer: &mut KittyRenderer<io::Stdout>, status: &str) -> Result<()> {
+    let buffer = renderer.screen().buffer();
+    let status_row = buffer.rows().saturating_sub(1);
+    buffer.clear_row(status_row);
+    buffer.set_str(0, status_row, status, Style::PLAIN);
+    Ok(())
+}
+
+fn draw_status_message(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    message: &StatusMessage,
+) -> Result<()> {
+    let buffer = renderer.screen().buffer();
+    let status_row = buffer.rows().saturating_sub(1);
+    buffer.clear_row(status_row);
+    let clipped = truncate_with_ellipsis(message.text.clone(), buffer.columns() as usize);
+    buffer.set_str(0, status_row, &clipped, message.kind.style());
+    Ok(())
+}
+
+fn draw_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    overlay: &mut OverlayState,
+    total_cols: u32,
+    total_rows: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    match overlay {
+        OverlayState::Toc(toc) => draw_toc_overlay(renderer, toc, total_cols, image_rows_available),
+        OverlayState::Marks(window) => {
+            draw_marks_overlay(renderer, window, total_cols, image_rows_available)
+        }
+        OverlayState::Jumps(window) => {
+            draw_jumps_overlay(renderer, window, total_cols, image_rows_available)
+        }
+        OverlayState::History(window) => {
+            draw_history_overlay(renderer, window, total_cols, image_rows_available)
+        }
+        OverlayState::Links(window) => {
+            draw_links_overlay(renderer, window, total_cols, image_rows_available)
+        }
+        OverlayState::Browser(browser) => {
+            draw_browser_overlay(renderer, browser, total_cols, image_rows_available)
+        }
+        OverlayState::Citation(popup) => {
+            draw_citation_overlay(renderer, popup, total_cols, image_rows_available)
+        }
+        OverlayState::CacheStats(popup) | OverlayState::Stats(popup) => draw_text_popup(
+            renderer,
+            &popup.title,
+            &popup.lines,
+            &mut popup.scroll,
+            total_cols,
+            image_rows_available,
+        ),
+        OverlayState::Info(popup) => {
+            let width = popup_inner_width(total_cols).saturating_sub(2);
+            let lines: Vec<String> = popup
+                .lines
+                .iter()
+                .flat_map(|line| {
+                    // Keep wrapped lines under their field's indentation.
+                    let indent = &line[..line.len() - line.trim_start().len()];
+                    match wrap_text(line, width.saturating_sub(indent.len())) {
+                        wrapped if wrapped.is_empty() => vec![String::new()],
+                        wrapped => wrapped
+                            .into_iter()
+                            .map(|text| format!("{}{}", indent, text))
+                            .collect(),
+                    }
+                })
+                .collect();
+            draw_text_popup(
+                renderer,
+                &popup.title,
+                &lines,
+                &mut popup.scroll,
+                total_cols,
+                image_rows_available,
+            )
+        }
+        OverlayState::LinkPrompt(prompt) => {
+            let lines = prompt.lines(popup_inner_width(total_cols).saturating_sub(2));
+            draw_text_popup(
+                renderer,
+                &t!("link-prompt-title"),
+                &lines,
+                &mut prompt.scroll,
+                total_cols,
+                image_rows_available,
+            )
+        }
+        OverlayState::Command(command) => {
+            draw_command_overlay(renderer, command, total_cols, total_rows)
+        }
+        OverlayState::None => Ok(()),
+    }
+}
+
+fn draw_command_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    overlay: &CommandOverlay,
+    total_cols: u32,
+    total_rows: u32,
+) -> Result<()> {
+    if total_cols == 0 || total_rows == 0 {
+        return Ok(());
+    }
+    let status_row = total_rows.saturating_sub(1) as u16;
+    let max_cols = total_cols as usize;
+    let screen = renderer.screen();
+    let buffer = screen.buffer();
+    buffer.clear_row(status_row);
+
+    let (prompt, cursor_col) = overlay.visible_prompt(max_cols);
+    let mut used_cols = buffer.set_str(0, status_row, &prompt, Style::PLAIN) as usize;
+    if used_cols < max_cols {
+        used_cols += 1;
+    }
+    if used_cols < max_cols {
+        overlay.write_status(buffer, used_cols as u16, status_row, max_cols - used_cols);
+    }
+
+    let cursor_limit = max_cols.saturating_sub(1);
+    screen.set_cursor(Some((cursor_col.min(cursor_limit) as u16, status_row)));
+
+    Ok(())
+}
+
+fn refresh_mark_overlay(overlay: &mut OverlayState, session: &Session, mapper: &mut EventMapper) {
+    if let OverlayState::Marks(window) = overlay {
+        if let Some(doc) = session.active() {
+            if !window.rebuild(doc, session.global_marks()) {
+                overlay.deactivate();
+                mapper.set_mode(InputMode::Normal);
+            } else {
+                window.update_current_page(doc.state.current_page);
+            }
+        } else {
+            overlay.deactivate();
+            mapper.set_mode(InputMode::Normal);
+        }
+    }
+}
+
+fn tokenize_command(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    // Whether `current` holds a token, which may be empty (`''`).
+    let mut in_token = false;
+    let mut chars = input.chars().peekable();
+    let mut quote: Option<char> = None;
+
+    while let Some(ch) = chars.next() {
+        if let Some(q) = quote {
+            if ch == '\\' {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                continue;
+            }
+            if ch == q {
+                quote = None;
+            } else {
+                current.push(ch);
+            }
+            continue;
+        }
+
+        match ch {
+            // Quotes may start mid-token, as in `name='a value'`.
+            '\'' | '"' => {
+                in_token = true;
+                quote = Some(ch);
+            }
+            c if c.is_whitespace() => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                in_token = true;
+                current.push(c);
+            }
+        }
+    }
+
+    if in_token {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+const EXPORT_DEFAULT_SCALE: f32 = 2.0;
+const POINTS_PER_INCH: f32 = 72.0;
+
+/// Renders the current page, or the part of it covered by the selection, and
+/// writes it as a PNG. `args` is `<path> [scale|<n>dpi]`.
+fn export_png(session: &Session, args: &[String], selection_only: bool) -> Result<String> {
+    let usage = if selection_only {
+        "Usage: :export-selection <file.png> [scale|<n>dpi]"
+    } else {
+        "Usage: :export-page <file.png> [scale|<n>dpi]"
+    };
+    let path = args
+        .first()
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!(usage))?;
+    let scale = parse_export_scale(args.get(1).map(String::as_str))?;
+    let doc = session
+        .active()
+        .ok_or_else(|| anyhow!(t!("no-active-document")))?;
+    let page = doc.state.current_page;
+    let crop = if selection_only {
+        let bounds = doc
+            .selection_bounds(page)
+            .ok_or_else(|| anyhow!(t!("no-selection-on-page")))?;
+        Some(bounds)
+    } else {
+        None
+    };
+
+    let mut image = doc.backend.render_page(RenderRequest {
+        page_index: page,
+        scale,
+        dark_mode: false,
+    })?;
+    if let Some(bounds) = crop {
+        let pixels = rect_to_pixels(&bounds, image.width, image.height, None)
+            .ok_or_else(|| anyhow!(t!("selection-empty")))?;
+        image = crop_render_image(
+            &image,
+            pixels.x0,
+            pixels.y0,
+            pixels.x1 - pixels.x0,
+            pixels.y1 - pixels.y0,
+        );
+    }
+    write_png(&path, &image, scale * POINTS_PER_INCH)?;
+    Ok(format!(
+        "Exported {}x{} px to {}",
+        image.width,
+        image.height,
+        path.display()
+    ))
+}
+
+/// The page image as last drawn: cropped to the viewport, with dark mode and
+/// highlights applied.
+struct ScreenFrame {
+    image: RenderImage,
+    render_scale: f32,
+    /// Whether the image is the whole page exactly as rendered, so the
+    /// neighbouring pages will be drawn the same way.
+    plain: bool,
+}
+
+/// Sends the pages next to the current one to the terminal ahead of time,
+/// so turning to them is a single placement. Skipped while keys are waiting.
+fn transmit_neighbors(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    session: &Session,
+    render_scale: f32,
+) -> Result<()> {
+    let Some(doc) = session.active() else {
+        return Ok(());
+    };
+    let current = doc.state.current_page;
+    for page in session.prefetch().pages(current, doc.info.page_count) {
+        if page.abs_diff(current) != 1 || event::poll(Duration::ZERO)? {
+            continue;
+        }
+        match doc.render_page_with_scale(page, render_scale) {
+            Ok(image) => {
+                renderer.transmit(&image)?;
+            }
+            Err(err) => warn!(?err, page, "failed to render neighbouring page"),
+        }
+    }
+    renderer.writer().flush()?;
+    Ok(())
+}
+
+/// Writes the last drawn page view as a PNG. `args` is `<path>`.
+fn export_view(frame: Option<&ScreenFrame>, args: &[String]) -> Result<String> {
+    let path = args
+        .first()
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!(t!("usage-export-view")))?;
+    let frame = frame.ok_or_else(|| anyhow!(t!("no-page-view")))?;
+    write_png(&path, &frame.image, frame.render_scale * POINTS_PER_INCH)?;
+    Ok(format!(
+        "Exported {}x{} px view to {}",
+        frame.image.width,
+        frame.image.height,
+        path.display()
+    ))
+}
+
+/// Accepts a render scale (`2`, `1.5`) or a resolution (`300dpi`).
+fn parse_export_scale(value: Option<&str>) -> Result<f32> {
+    let Some(value) = value else {
+        return Ok(EXPORT_DEFAULT_SCALE);
+    };
+    let lower = value.trim().to_ascii_lowercase();
+    let scale = match lower.strip_suffix("dpi") {
+        Some(dpi) => dpi
+            .trim()
+            .parse::<f32>()
+            .ok()
+            .map(|dpi| dpi / POINTS_PER_INCH),
+        None => lower.parse::<f32>().ok(),
+    };
+    scale
+        .filter(|scale| scale.is_finite() && (0.1..=16.0).contains(scale))
+        .ok_or_else(|| anyhow!("Invalid scale '{}': use 0.1-16 or e.g. 300dpi", value))
+}
+
+/// Parses a 1-based page range (`10-25`, or `7` for a single page) into
+/// 0-based page indices.
+fn parse_page_range(value: &str) -> Result<RangeInclusive<usize>> {
+    let (first, last) = value.split_once('-').unwrap_or((value, value));
+    let parse = |page: &str| page.trim().parse::<usize>().ok().filter(|page| *page > 0);
+    match (parse(first), parse(last)) {
+        (Some(first), Some(last)) if first <= last => Ok(first - 1..=last - 1),
+        _ => Err(anyhow!("Invalid page range '{}'", value)),
+    }
+}
+
+fn write_png(path: &Path, image: &RenderImage, dpi: f32) -> Result<()> {
+    let file =
+        fs::File::create(path).with_context(|| format!("cannot create {}", path.display()))?;
+    let mut encoder = png::Encoder::new(io::BufWriter::new(file), image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let pixels_per_metre = (dpi / 0.0254).round() as u32;
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_metre,
+        yppu: pixels_per_metre,
+        unit: png::Unit::Meter,
+    }));
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&image.pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Parses a 1-based page argument; `all` or a missing argument selects every page.
+fn parse_highlight_page(arg: Option<&String>) -> Result<Option<usize>> {
+    match arg.map(String::as_str) {
+        None | Some("all") => Ok(None),
+        Some(value) => {
+            let page = value
+                .parse::<usize>()
+                .ok()
+                .filter(|page| *page > 0)
+                .ok_or_else(|| anyhow!("Invalid page number: {}", value))?;
+            Ok(Some(page - 1))
+        }
+    }
+}
+
+/// Parses `<page> <left> <top> <right> <bottom> [...]`, where coordinates are
+/// fractions of the page measured from its top-left corner.
+fn parse_highlight_add(args: &[String]) -> Result<(usize, Vec<NormalizedRect>)> {
+    let page =
+        parse_highlight_page(args.first())?.ok_or_else(|| anyhow!("A page number is required"))?;
+    let coords = &args[1..];
+    if coords.is_empty() || !coords.len().is_multiple_of(4) {
+        return Err(anyhow!("Expected groups of <left> <top> <right> <bottom>"));
+    }
+    Ok((page, parse_rects(coords)?))
+}
+
+/// Parses `<left> <top> <right> <bottom>` groups of page fractions.
+fn parse_rects(coords: &[String]) -> Result<Vec<NormalizedRect>> {
+    let mut values = Vec::with_capacity(coords.len());
+    for value in coords {
+        let parsed = value
+            .parse::<f32>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| anyhow!("Invalid coordinate: {}", value))?;
+        values.push(parsed);
+    }
+    Ok(values
+        .chunks_exact(4)
+        .map(|chunk| NormalizedRect {
+            left: chunk[0],
+            top: chunk[1],
+            right: chunk[2],
+            bottom: chunk[3],
+        })
+        .collect())
+}
+
+fn parse_zoom_rect(args: &[String]) -> Result<NormalizedRect> {
+    let rect = match args.len() {
+        4 => parse_rects(args)?.pop(),
+        _ => None,
+    };
+    rect.filter(|rect| rect.clamp().is_valid())
+        .ok_or_else(|| anyhow!(t!("usage-zoom-rect")))
+}
+
+/// Zooms the active document in on the selection on its current page.
+fn zoom_to_selection(session: &mut Session) -> Result<()> {
+    let doc = session
+        .active()
+        .ok_or_else(|| anyhow!(t!("no-active-document")))?;
+    let rect = doc
+        .selection_bounds(doc.state.current_page)
+        .ok_or_else(|| anyhow!(t!("no-selection-on-page")))?;
+    session.apply(Command::ZoomToRect { rect })
+}
+
+const SET_OPTIONS: &[&str] = &["scrolloff", "so", "dark", "fit"];
+
+fn is_set_option(token: &str) -> bool {
+    let name = token
+        .split('=')
+        .next()
+        .unwrap_or(token)
+        .trim_end_matches(['?', '!'])
+        .to_ascii_lowercase();
+    SET_OPTIONS.contains(&name.as_str())
+        || name
+            .strip_prefix("no")
+            .is_some_and(|name| SET_OPTIONS.contains(&name))
+}
+
+/// Splits `:set` arguments into `(option, value)` pairs. Values may be given
+/// as `name=value` or as a separate token following the option name.
+fn parse_set_arguments(args: &[String]) -> Vec<(String, Option<String>)> {
+    let mut pairs = Vec::new();
+    let mut iter = args.iter().peekable();
+    while let Some(token) = iter.next() {
+        if let Some((name, value)) = token.split_once('=') {
+            pairs.push((name.to_string(), Some(value.to_string())));
+            continue;
+        }
+        let value = iter
+            .next_if(|next| !next.contains('=') && !is_set_option(next))
+            .cloned();
+        pairs.push((token.clone(), value));
+    }
+    pairs
+}
+
+fn parse_switch(value: &str, current: bool) -> Result<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        "toggle" => Ok(!current),
+        other => Err(anyhow!("Expected on, off or toggle, got '{}'", other)),
+    }
+}
+
+fn parse_highlight_width(value: &str) -> Result<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|width| (0.0..=20.0).contains(width))
+        .ok_or_else(|| anyhow!("Invalid highlight width '{}': use 0-20 points", value))
+}
+
+fn parse_highlight_opacity(value: &str) -> Result<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|opacity| (0.0..=3.0).contains(opacity))
+        .ok_or_else(|| anyhow!("Invalid highlight opacity '{}': use 0-3", value))
+}
+
+fn parse_pan_step(value: &str) -> Result<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|step| *step > 0.0 && *step <= 1.0)
+        .ok_or_else(|| anyhow!("Invalid pan step '{}': use a fraction up to 1", value))
+}
+
+fn parse_zoom_step(value: &str) -> Result<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|step| *step > 0.0 && *step < 1.0)
+        .ok_or_else(|| anyhow!("Invalid zoom step '{}': use a fraction below 1", value))
+}
+
+fn parse_transfer(value: &str) -> Result<TransferMedium> {
+    value.parse()
+}
+
+fn parse_passthrough(value: &str) -> Result<Passthrough> {
+    value.parse()
+}
+
+fn parse_encoding(value: &str) -> Result<ImageEncoding> {
+    value.parse()
+}
+
+fn parse_prefetch_direction(value: &str) -> Result<PrefetchDirection> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "both" => Ok(PrefetchDirection::Both),
+        "forward" => Ok(PrefetchDirection::Forward),
+        "backward" => Ok(PrefetchDirection::Backward),
+        other => Err(anyhow!(
+            "Expected both, forward or backward, got '{}'",
+            other
+        )),
+    }
+}
+
+/// The `[links]` config table with the link flags on top of it.
+fn link_policy(args: &Args, config: &config::LinksConfig) -> Result<LinkPolicy> {
+    let mut handlers = config
+        .handlers
+        .iter()
+        .map(|(scheme, action)| {
+            links::parse_handler(&format!("{}={}", scheme, action)).with_context(|| {
+                format!("invalid link handler for `{}` in the config file", scheme)
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    handlers.extend(args.link_handlers.iter().cloned());
+    let confirm = !args.no_confirm_links && config.confirm.unwrap_or(true);
+    let list = |flag: &[LinkPattern], configured: &[String], key: &str| {
+        if !flag.is_empty() {
+            return Ok(flag.to_vec());
+        }
+        configured
+            .iter()
+            .map(|pattern| LinkPattern::parse(pattern))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("invalid `{}` list under [links] in the config file", key))
+    };
+    Ok(LinkPolicy::new(confirm, handlers).with_lists(
+        list(&args.allow_links, &config.allow, "allow")?,
+        list(&args.deny_links, &config.deny, "deny")?,
+    ))
+}
+
+/// Below this much available memory, pages are not prefetched by default.
+const LOW_MEMORY_BYTES: u64 = 512 * 1024 * 1024;
+
+/// Memory available to new allocations, where the platform reports it.
+fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    parse_mem_available(&meminfo)
+}
+
+fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    let line = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))?;
+    let kib = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kib * 1024)
+}
+
+/// `+25%` and `-25%` zoom relative to the current scale; `150%` and `1.5`
+/// set it.
+fn parse_zoom(value: &str) -> Result<Command> {
+    let value = value.trim();
+    let (sign, rest) = match value.strip_prefix('+') {
+        Some(rest) => (1.0, rest),
+        None => match value.strip_prefix('-') {
+            Some(rest) => (-1.0, rest),
+            None => (0.0, value),
+        },
+    };
+    let (number, percent) = match rest.strip_suffix('%') {
+        Some(number) => (number, true),
+        None => (rest, false),
+    };
+    let amount = number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|amount| amount.is_finite() && *amount > 0.0)
+        .ok_or_else(|| anyhow!("Invalid zoom '{}'", value))?;
+    match (sign != 0.0, percent) {
+        (true, true) => {
+            let factor = 1.0 + sign * amount / 100.0;
+            if factor <= 0.0 {
+                return Err(anyhow!("Cannot zoom out by {}", value));
+            }
+            Ok(Command::ScaleBy { factor })
+        }
+        (true, false) => Err(anyhow!("Relative zoom needs a percentage, e.g. +25%")),
+        (false, true) => Ok(Command::SetScale {
+            scale: amount / 100.0,
+        }),
+        (false, false) => Ok(Command::SetScale { scale: amount }),
+    }
+}
+
+fn parse_state_key(value: &str) -> Result<StateKey> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "path" => Ok(StateKey::Path),
+        "content" => Ok(StateKey::Content),
+        other => Err(anyhow!("Expected path or content, got '{}'", other)),
+    }
+}
+
+fn parse_fit_mode(value: &str, current: FitMode) -> Result<FitMode> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "page" => Ok(FitMode::Page),
+        "width" => Ok(FitMode::Width),
+        "toggle" => Ok(match current {
+            FitMode::Page => FitMode::Width,
+            FitMode::Width => FitMode::Page,
+        }),
+        other => Err(anyhow!("Expected page, width or toggle, got '{}'", other)),
+    }
+}
+
+fn apply_set_option(session: &mut Session, option: &str, value: Option<&str>) -> Result<String> {
+    let query = option.ends_with('?');
+    let invert = option.ends_with('!');
+    let name = option.trim_end_matches(['?', '!']).to_ascii_lowercase();
+    match name.as_str() {
+        "scrolloff" | "so" => {
+            if let Some(value) = value.filter(|_| !query) {
+                let cells = value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|_| anyhow!("Invalid scrolloff value: {}", value))?;
+                session.set_scrolloff(cells);
+            }
+            Ok(format!("scrolloff={}", session.scrolloff()))
+        }
+        "dark" | "nodark" | "invdark" => {
+            let current = session
+                .active()
+                .map(|doc| doc.state.dark_mode)
+                .ok_or_else(|| anyhow!(t!("no-active-document")))?;
+            if !query {
+                let enabled = match value {
+                    Some(value) => parse_switch(value, current)?,
+                    None if invert || name == "invdark" => !current,
+                    None => name == "dark",
+                };
+                session.apply(Command::SetDarkMode { enabled })?;
+            }
+            let enabled = session.active().is_some_and(|doc| doc.state.dark_mode);
+            Ok(if enabled { "dark" } else { "nodark" }.to_string())
+        }
+        "fit" => {
+            let current = session
+                .active()
+                .map(|doc| doc.state.fit_mode)
+                .ok_or_else(|| anyhow!(t!("no-active-document")))?;
+            if let Some(value) = value.filter(|_| !query) {
+                let mode = parse_fit_mode(value, current)?;
+                session.apply(Command::SetFitMode { mode })?;
+            }
+            let mode = session
+                .active()
+                .map(|doc| doc.state.fit_mode)
+                .unwrap_or(current);
+            Ok(format!("fit={}", mode.label()))
+        }
+        "highlightwidth" | "hlw" => {
+            let mut style = session.highlight_style();
+            if let Some(value) = value.filter(|_| !query) {
+                style.stroke_width = parse_highlight_width(value)?;
+                session.set_highlight_style(style);
+            }
+            Ok(format!("highlightwidth={}", style.stroke_width))
+        }
+        "highlightopacity" | "hlo" => {
+            let mut style = session.highlight_style();
+            if let Some(value) = value.filter(|_| !query) {
+                style.fill_opacity = parse_highlight_opacity(value)?;
+                session.set_highlight_style(style);
+            }
+            Ok(format!("highlightopacity={}", style.fill_opacity))
+        }
+        "pan-step" | "panstep" => {
+            let mut steps = session.step_sizes();
+            if let Some(value) = value.filter(|_| !query) {
+                steps.pan = parse_pan_step(value)?;
+                session.set_step_sizes(steps);
+            }
+            Ok(format!("pan-step={}", steps.pan))
+        }
+        "zoom-step" | "zoomstep" => {
+            let mut steps = session.step_sizes();
+            if let Some(value) = value.filter(|_| !query) {
+                steps.zoom = parse_zoom_step(value)?;
+                session.set_step_sizes(steps);
+            }
+            Ok(format!("zoom-step={}", steps.zoom))
+        }
+        "prefetch" | "noprefetch" | "invprefetch" => {
+            let mut policy = session.prefetch();
+            if !query {
+                match value {
+                    Some(value) => {
+                        policy.radius = value
+                            .trim()
+                            .parse::<usize>()
+                            .map_err(|_| anyhow!("Invalid prefetch value: {}", value))?;
+                        policy.enabled = true;
+                    }
+                    None if invert || name == "invprefetch" => policy.enabled = !policy.enabled,
+                    None => policy.enabled = name == "prefetch",
                 }
+                session.set_prefetch(policy);
             }
-        } else {
-            highlight_geom.clear_crop();
+            Ok(if policy.enabled {
+                format!("prefetch={}", policy.radius)
+            } else {
+                "noprefetch".to_string()
+            })
+        }
+        "searchscope" => {
+            if let Some(value) = value.filter(|_| !query) {
+                let scope = match value.trim().to_ascii_lowercase().as_str() {
+                    "document" | "doc" => SearchScope::Document,
+                    "page" => SearchScope::Page,
+                    other => return Err(anyhow!("Expected document or page, got '{}'", other)),
+                };
+                session.set_search_scope(scope);
+            }
+            Ok(format!("searchscope={}", session.search_scope().label()))
+        }
+        "prefetchdir" => {
+            let mut policy = session.prefetch();
+            if let Some(value) = value.filter(|_| !query) {
+                policy.direction = parse_prefetch_direction(value)?;
+                session.set_prefetch(policy);
+            }
+            Ok(format!("prefetchdir={}", policy.direction.label()))
+        }
+        "follow" | "nofollow" | "invfollow" => {
+            let current = session.follow();
+            if !query {
+                let enabled = match value {
+                    Some(value) => parse_switch(value, current)?,
+                    None if invert || name == "invfollow" => !current,
+                    None => name == "follow",
+                };
+                session.set_follow(enabled);
+            }
+            Ok(if session.follow() {
+                "follow"
+            } else {
+                "nofollow"
+            }
+            .to_string())
+        }
+        "jumptochange" | "nojumptochange" | "invjumptochange" => {
+            let current = session.jump_to_change();
+            if !query {
+                let enabled = match value {
+                    Some(value) => parse_switch(value, current)?,
+                    None if invert || name == "invjumptochange" => !current,
+                    None => name == "jumptochange",
+                };
+                session.set_jump_to_change(enabled);
+            }
+            Ok(if session.jump_to_change() {
+                "jumptochange"
+            } else {
+                "nojumptochange"
+            }
+            .to_string())
+        }
+        "copy-reflow" | "copyreflow" | "nocopy-reflow" | "nocopyreflow" | "invcopy-reflow"
+        | "invcopyreflow" => {
+            let current = session.copy_reflow();
+            if !query {
+                let enabled = match value {
+                    Some(value) => parse_switch(value, current)?,
+                    None if invert || name.starts_with("inv") => !current,
+                    None => !name.starts_with("no"),
+                };
+                session.set_copy_reflow(enabled);
+            }
+            Ok(if session.copy_reflow() {
+                "copy-reflow"
+            } else {
+                "nocopy-reflow"
+            }
+            .to_string())
+        }
+        "first-page-number" | "firstpagenumber" | "nofirst-page-number" | "nofirstpagenumber" => {
+            let current = session
+                .active()
+                .ok_or_else(|| anyhow!("No document open"))?
+                .state
+                .first_page_number;
+            if !query {
+                let number = if name.starts_with("no") {
+                    None
+                } else {
+                    let value = value.ok_or_else(|| anyhow!("Expected a page number"))?;
+                    Some(
+                        value
+                            .trim()
+                            .parse::<i64>()
+                            .map_err(|_| anyhow!("Invalid page number: {}", value))?,
+                    )
+                };
+                if number != current {
+                    session.apply(Command::SetFirstPageNumber { number })?;
+                }
+            }
+            let current = session.active().and_then(|doc| doc.state.first_page_number);
+            Ok(match current {
+                Some(number) => format!("first-page-number={}", number),
+                None => "nofirst-page-number".to_string(),
+            })
+        }
+        "daily-goal" | "dailygoal" | "nodaily-goal" | "nodailygoal" => {
+            let current = session
+                .active()
+                .ok_or_else(|| anyhow!("No document open"))?
+                .state
+                .daily_goal;
+            if !query {
+                let pages = if name.starts_with("no") {
+                    None
+                } else {
+                    let value = value.ok_or_else(|| anyhow!("Expected a number of pages"))?;
+                    Some(
+                        value
+                            .trim()
+                            .parse::<usize>()
+                            .map_err(|_| anyhow!("Invalid number of pages: {}", value))?,
+                    )
+                };
+                if pages != current {
+                    session.apply(Command::SetDailyGoal { pages })?;
+                }
+            }
+            Ok(reading::goal_progress(session).unwrap_or_else(|| "nodaily-goal".to_string()))
+        }
+        "progressive" | "noprogressive" | "invprogressive" => {
+            let current = session.progressive();
+            if !query {
+                let enabled = match value {
+                    Some(value) => parse_switch(value, current)?,
+                    None if invert || name == "invprogressive" => !current,
+                    None => name == "progressive",
+                };
+                session.set_progressive(enabled);
+            }
+            Ok(if session.progressive() {
+                "progressive"
+            } else {
+                "noprogressive"
+            }
+            .to_string())
+        }
+        "quote-markdown" | "quotemarkdown" | "quote-latex" | "quotelatex" => {
+            let latex = name.ends_with("latex");
+            let mut templates = session.quote_templates().clone();
+            if let Some(value) = value.filter(|_| !query) {
+                if latex {
+                    templates.latex = value.to_string();
+                } else {
+                    templates.markdown = value.to_string();
+                }
+                session.set_quote_templates(templates);
+            }
+            let templates = session.quote_templates();
+            Ok(if latex {
+                format!("quote-latex={}", templates.latex)
+            } else {
+                format!("quote-markdown={}", templates.markdown)
+            })
+        }
+        "reflow" | "noreflow" | "invreflow" => {
+            let current = session.reflow();
+            if !query {
+                let enabled = match value {
+                    Some(value) => parse_switch(value, current)?,
+                    None if invert || name == "invreflow" => !current,
+                    None => name == "reflow",
+                };
+                session.apply(Command::SetReflow { enabled })?;
+            }
+            Ok(if session.reflow() {
+                "reflow"
+            } else {
+                "noreflow"
+            }
+            .to_string())
+        }
+        _ => Err(anyhow!(t!("unknown-option", name = name))),
+    }
+}
+
+fn draw_toc_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    toc: &mut TocWindow,
+    total_cols: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    let title = t!("toc-title");
+    let empty_message = t!("toc-empty");
+    let search_prompt = toc.search_prompt();
+    let position = (!toc.is_empty()).then(|| {
+        t!(
+            "toc-position",
+            index = toc.list.selected() + 1,
+            count = toc.entries.len()
+        )
+    });
+    // Matches off screen show up along the scrollbar.
+    let window = BorderedWindow::new(&title)
+        .with_centered_title()
+        .with_prompt(search_prompt.as_deref())
+        .with_footer(position.as_deref())
+        .with_marks(&toc.search_matches);
+
+    let (width, len) = if toc.is_empty() {
+        (display_width(&empty_message) + 2, 1)
+    } else {
+        let widest = toc.entries.iter().map(toc_line_length).max().unwrap_or(0);
+        (widest, toc.entries.len())
+    };
+    let area = Area {
+        columns: total_cols,
+        rows: image_rows_available,
+    };
+    let Some(layout) = window.layout(area, width, len) else {
+        return Ok(());
+    };
+    toc.list.ensure_visible(layout.content_height);
+
+    let active_query = toc.active_query().map(|q| q.to_string());
+    // The empty outline still gets a row for its message.
+    let list = if toc.is_empty() {
+        ListView::new(1)
+    } else {
+        toc.list
+    };
+    window.draw(renderer.screen().buffer(), &layout, &list, |idx| {
+        if toc.is_empty() {
+            return ListRow::new(format!("  {}", empty_message), false);
+        }
+        let matching = toc.entry_matches(idx);
+        let text = format_toc_line(
+            &toc.entries[idx],
+            idx == toc.list.selected(),
+            toc.current_index() == Some(idx),
+            matching,
+            active_query.as_deref(),
+            layout.inner_width,
+        );
+        ListRow::new(text, matching)
+    });
+
+    Ok(())
+}
+
+fn draw_marks_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    marks: &mut MarkWindow,
+    total_cols: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    if marks.is_empty() {
+        return Ok(());
+    }
+
+    let title = t!("marks-title");
+    let window = BorderedWindow::new(&title);
+    let width = marks
+        .entries
+        .iter()
+        .map(mark_line_length)
+        .max()
+        .unwrap_or(0);
+    let area = Area {
+        columns: total_cols,
+        rows: image_rows_available,
+    };
+    let Some(layout) = window.layout(area, width, marks.entries_len()) else {
+        return Ok(());
+    };
+    marks.list.ensure_visible(layout.content_height);
+
+    window.draw(renderer.screen().buffer(), &layout, &marks.list, |idx| {
+        let selected = idx == marks.list.selected();
+        let current = marks.current_index() == Some(idx);
+        let text = format_mark_line(&marks.entries[idx], selected, current, layout.inner_width);
+        ListRow::new(text, selected || current)
+    });
+
+    Ok(())
+}
+
+/// Page of a TOC entry: its label when the document has page labels.
+fn toc_page_suffix(entry: &OutlineItem) -> String {
+    match &entry.page_label {
+        Some(label) => format!(" (p. {})", label),
+        None => format!(" (p{})", entry.page_index + 1),
+    }
+}
+
+fn toc_line_length(entry: &OutlineItem) -> usize {
+    let indent_levels = entry.depth.min(8);
+    let indent_width = indent_levels * 2;
+    4 + indent_width + display_width(&entry.title) + display_width(&toc_page_suffix(entry))
+}
+
+fn format_toc_line(
+    entry: &OutlineItem,
+    selected: bool,
+    current: bool,
+    matching: bool,
+    active_query: Option<&str>,
+    inner_width: usize,
+) -> String {
+    let selected_marker = if selected { '>' } else { ' ' };
+    let current_marker = if current { '*' } else { ' ' };
+    let match_marker = if matching { '+' } else { ' ' };
+    let indent_levels = entry.depth.min(8);
+    let indent = "  ".repeat(indent_levels);
+    let page_suffix = toc_page_suffix(entry);
+
+    let title = if matching {
+        highlight_search_segment(&entry.title, active_query)
+    } else {
+        entry.title.clone()
+    };
+
+    let mut text = String::new();
+    text.push(selected_marker);
+    text.push(current_marker);
+    text.push(match_marker);
+    text.push(' ');
+    text.push_str(&indent);
+    text.push_str(&title);
+    text.push_str(&page_suffix);
+
+    truncate_with_ellipsis(text, inner_width)
+}
+
+fn highlight_search_segment(title: &str, query: Option<&str>) -> String {
+    if let Some(query) = query {
+        if let Some(range) = matching::find_match(title, query) {
+            let mut highlighted = String::with_capacity(title.len() + 2);
+            highlighted.push_str(&title[..range.start]);
+            highlighted.push('[');
+            highlighted.push_str(&title[range.clone()]);
+            highlighted.push(']');
+            highlighted.push_str(&title[range.end..]);
+            return highlighted;
         }
+    }
+    title.to_string()
+}
+
+fn draw_links_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    window: &mut LinksWindow,
+    total_cols: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    let title = window.title();
+    let frame = BorderedWindow::new(&title);
+    let width = window
+        .entries
+        .iter()
+        .map(link_line_length)
+        .max()
+        .unwrap_or(0);
+    let area = Area {
+        columns: total_cols,
+        rows: image_rows_available,
+    };
+    let Some(layout) = frame.layout(area, width, window.visible.len()) else {
+        return Ok(());
+    };
+    window.list.ensure_visible(layout.content_height);
+
+    frame.draw(renderer.screen().buffer(), &layout, &window.list, |idx| {
+        let selected = idx == window.list.selected();
+        let entry = &window.entries[window.visible[idx]];
+        ListRow::new(
+            format_link_line(entry, selected, layout.inner_width),
+            selected,
+        )
+    });
+
+    Ok(())
+}
+
+fn draw_jumps_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    window: &mut JumpsWindow,
+    total_cols: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    let title = t!("jumps-title");
+    let frame = BorderedWindow::new(&title);
+    let width = window
+        .entries
+        .iter()
+        .map(|entry| display_width(&entry.text()) + 3)
+        .max()
+        .unwrap_or(0);
+    let area = Area {
+        columns: total_cols,
+        rows: image_rows_available,
+    };
+    let Some(layout) = frame.layout(area, width, window.entries.len()) else {
+        return Ok(());
+    };
+    window.list.ensure_visible(layout.content_height);
+
+    frame.draw(renderer.screen().buffer(), &layout, &window.list, |idx| {
+        let selected = idx == window.list.selected();
+        let text = format_jump_line(
+            &window.entries[idx],
+            selected,
+            idx == window.current,
+            layout.inner_width,
+        );
+        ListRow::new(text, selected)
+    });
+
+    Ok(())
+}
+
+fn format_jump_line(
+    entry: &JumpEntry,
+    selected: bool,
+    current: bool,
+    inner_width: usize,
+) -> String {
+    let mut text = String::new();
+    text.push(if selected { '>' } else { ' ' });
+    text.push(if current { '*' } else { ' ' });
+    text.push(' ');
+    text.push_str(&entry.text());
+    truncate_with_ellipsis(text, inner_width)
+}
+
+fn draw_history_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    window: &mut HistoryWindow,
+    total_cols: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    let title = t!("history-title");
+    let frame = BorderedWindow::new(&title);
+    // Previews are read as rows come on screen, so leave room for them
+    // up front rather than widening the window while scrolling.
+    let width = popup_inner_width(total_cols);
+    let area = Area {
+        columns: total_cols,
+        rows: image_rows_available,
+    };
+    let Some(layout) = frame.layout(area, width, window.entries.len()) else {
+        return Ok(());
+    };
+    window.list.ensure_visible(layout.content_height);
+    let offset = window.list.offset();
+    window.load_previews(offset..offset + layout.content_height);
+
+    frame.draw(renderer.screen().buffer(), &layout, &window.list, |idx| {
+        let selected = idx == window.list.selected();
+        let marker = if selected { "> " } else { "  " };
+        ListRow::new(
+            format!("{}{}", marker, window.entries[idx].text()),
+            selected,
+        )
+    });
+
+    Ok(())
+}
+
+fn draw_browser_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    browser: &mut FileBrowser,
+    total_cols: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    let title = browser.title();
+    let frame = BorderedWindow::new(&title);
+    let width = browser
+        .entries
+        .iter()
+        .map(|entry| display_width(&entry.label()) + 3)
+        .max()
+        .unwrap_or(0);
+    let area = Area {
+        columns: total_cols,
+        rows: image_rows_available,
+    };
+    let Some(layout) = frame.layout(area, width, browser.visible.len()) else {
+        return Ok(());
+    };
+    browser.list.ensure_visible(layout.content_height);
 
-        let effective_pixel_width = if zoom_scale > 1.0 {
-            display_image.width as f32 * zoom_scale
-        } else {
-            display_image.width as f32
-        };
-        let effective_pixel_height = if zoom_scale > 1.0 {
-            display_image.height as f32 * zoom_scale
-        } else {
-            display_image.height as f32
-        };
+    frame.draw(renderer.screen().buffer(), &layout, &browser.list, |idx| {
+        let selected = idx == browser.list.selected();
+        let marker = if selected { '>' } else { ' ' };
+        let label = browser.entries[browser.visible[idx]].label();
+        ListRow::new(format!("{} {}", marker, label), selected)
+    });
 
-        let (draw_cols, draw_rows) = compute_scaled_dimensions(
-            &display_image,
-            effective_pixel_width,
-            effective_pixel_height,
-            available_cols,
-            available_rows,
-            total_cols,
-            total_rows,
-            pixel_width,
-            pixel_height,
-        );
+    Ok(())
+}
 
-        let start_col = (total_cols.saturating_sub(draw_cols)) / 2;
-        let start_row = (image_rows_available.saturating_sub(draw_rows)) / 2;
+fn draw_citation_overlay(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    popup: &mut CitationPopup,
+    total_cols: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    let inner_width = popup_inner_width(total_cols);
+    let lines = wrap_text(&popup.text, inner_width.saturating_sub(2));
+    draw_text_popup(
+        renderer,
+        &popup.title,
+        &lines,
+        &mut popup.scroll,
+        total_cols,
+        image_rows_available,
+    )
+}
 
-        {
-            let mut writer = renderer.writer();
-            crossterm::execute!(
-                &mut writer,
-                cursor::MoveTo(start_col as u16, start_row as u16)
-            )?;
-        }
+fn popup_inner_width(total_cols: u32) -> usize {
+    const MAX_WIDTH: usize = 80;
+    (total_cols.saturating_sub(6) as usize).min(MAX_WIDTH)
+}
 
-        if let Some(highlights) = link_highlights.as_ref() {
-            apply_highlights(
-                &mut display_image,
-                highlights,
-                &highlight_geom,
-                &SEARCH_HIGHLIGHT_PALETTE,
-            );
+/// Draws a bordered popup with a title in the middle of the page area,
+/// showing as many of `lines` from `scroll` on as fit.
+fn draw_text_popup(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    title: &str,
+    lines: &[String],
+    scroll: &mut usize,
+    total_cols: u32,
+    image_rows_available: u32,
+) -> Result<()> {
+    let window = BorderedWindow::new(title).with_bold_title();
+    let area = Area {
+        columns: total_cols,
+        rows: image_rows_available,
+    };
+    let Some(layout) = window.layout(area, popup_inner_width(total_cols), lines.len()) else {
+        return Ok(());
+    };
+    let mut list = ListView::new(lines.len());
+    list.scroll_to(*scroll, layout.content_height);
+    *scroll = list.offset();
+    window.draw(renderer.screen().buffer(), &layout, &list, |idx| {
+        ListRow::new(format!(" {}", lines[idx]), false)
+    });
+
+    Ok(())
+}
+
+/// Draws the current page as text wrapped to the terminal. The viewport's
+/// vertical offset scrolls through it and search terms are shown reversed.
+/// Returns how much of the text is on screen.
+fn draw_reflow(
+    renderer: &mut KittyRenderer<io::Stdout>,
+    doc: &DocumentInstance,
+    total_cols: u32,
+    rows: u32,
+) -> Result<ViewGeometry> {
+    const MAX_WIDTH: usize = 100;
+
+    let width = (total_cols.saturating_sub(4) as usize).clamp(10, MAX_WIDTH);
+    let start_col = ((total_cols as usize).saturating_sub(width) / 2) as u16;
+    let mut lines = match doc.current_page_text() {
+        Ok(text) => reflow_lines(&text.paragraphs(), width),
+        Err(err) => vec![t!("extract-text-failed", error = err)],
+    };
+    if lines.is_empty() {
+        lines.push(t!("no-text-on-page"));
+    }
+
+    let visible = rows.max(1) as usize;
+    let max_scroll = lines.len().saturating_sub(visible);
+    let scroll = (doc.state.viewport.y * max_scroll as f32).round() as usize;
+    let query = doc
+        .search_summary()
+        .map(|summary| summary.query.to_lowercase())
+        .filter(|query| !query.is_empty());
+
+    let buffer = renderer.screen().buffer();
+    for (row, line) in lines.iter().skip(scroll).take(visible).enumerate() {
+        let row = row as u16;
+        let mut column = start_col;
+        let lower = line.to_lowercase();
+        let mut offset = 0;
+        if let Some(query) = query.as_deref().filter(|_| lower.len() == line.len()) {
+            while let Some(pos) = lower[offset..].find(query) {
+                let start = offset + pos;
+                let end = start + query.len();
+                column = buffer.set_str(column, row, &line[offset..start], Style::PLAIN);
+                column = buffer.set_str(column, row, &line[start..end], Style::REVERSE);
+                offset = end;
+            }
         }
-        if let Some(highlights) = search_highlights.as_ref() {
-            apply_highlights(
-                &mut display_image,
-                highlights,
-                &highlight_geom,
-                &SEARCH_HIGHLIGHT_PALETTE,
-            );
+        buffer.set_str(column, row, &line[offset..], Style::PLAIN);
+    }
+    Ok(reflow_geometry(lines.len(), visible, width))
+}
+
+/// The part of `line_count` reflowed lines that `rows` rows show.
+fn reflow_geometry(line_count: usize, rows: usize, width: usize) -> ViewGeometry {
+    ViewGeometry {
+        cols: width as u32,
+        rows: rows as u32,
+        visible_width: 1.0,
+        visible_height: (rows as f32 / line_count.max(1) as f32).min(1.0),
+    }
+}
+
+/// Wraps each paragraph to `width`, separating paragraphs with a blank line.
+fn reflow_lines(paragraphs: &[String], width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in paragraphs {
+        if !lines.is_empty() {
+            lines.push(String::new());
         }
-        if let Some(highlights) = selection_highlights.as_ref() {
-            apply_highlights(
-                &mut display_image,
-                highlights,
-                &highlight_geom,
-                &SELECTION_HIGHLIGHT_PALETTE,
-            );
+        lines.extend(wrap_text(paragraph, width));
+    }
+    lines
+}
+
+/// Splits `text` into lines of `width` characters, for text without spaces
+/// such as URLs.
+fn wrap_chars(text: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(width.max(1))
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        if !current.is_empty() && display_width(&current) + 1 + display_width(word) > width {
+            lines.push(std::mem::take(&mut current));
         }
-        if let Some(rect) = doc.visual_cursor_highlight() {
-            let mut cursor_highlight = Highlights::default();
-            cursor_highlight.current.push(rect);
-            apply_highlights(
-                &mut display_image,
-                &cursor_highlight,
-                &highlight_geom,
-                &CURSOR_HIGHLIGHT_PALETTE,
-            );
+        if !current.is_empty() {
+            current.push(' ');
         }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
 
-        renderer.draw(&display_image, DrawParams::clamped(draw_cols, draw_rows))?;
-        if matches!(overlay, OverlayState::Command(_)) {
-            // Command overlay owns the status row; nothing else to draw here.
-        } else if let Some(message) = status_bar.message() {
-            draw_status_message(renderer, message)?;
+fn link_line_length(entry: &UriLink) -> usize {
+    display_width(format_link_line(entry, false, 0).trim_end())
+}
+
+fn format_link_line(entry: &UriLink, selected: bool, inner_width: usize) -> String {
+    let marker = if selected { '>' } else { ' ' };
+    let text = format!("{} p{:<4} {}", marker, entry.page + 1, entry.uri);
+    if inner_width == 0 {
+        return text;
+    }
+    truncate_with_ellipsis(text, inner_width)
+}
+
+fn mark_line_length(entry: &MarkEntry) -> usize {
+    4 + display_width(&entry.text())
+}
+
+fn format_mark_line(
+    entry: &MarkEntry,
+    selected: bool,
+    current: bool,
+    inner_width: usize,
+) -> String {
+    let selected_marker = if selected { '>' } else { ' ' };
+    let current_marker = if current { '*' } else { ' ' };
+    let mut text = String::new();
+    text.push(selected_marker);
+    text.push(current_marker);
+    text.push(' ');
+    text.push_str(&entry.text());
+    truncate_with_ellipsis(text, inner_width)
+}
+
+/// Resolves a leading `~` in paths typed at the command prompt, which no
+/// shell expands for us.
+fn expand_home(path: &str) -> PathBuf {
+    let rest = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest.trim_start_matches('/'),
+        _ => return PathBuf::from(path),
+    };
+    match directories::BaseDirs::new() {
+        Some(dirs) => dirs.home_dir().join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+fn init_logging(project_dirs: &ProjectDirs) -> Result<WorkerGuard> {
+    let log_dir = project_dirs.data_local_dir().join("logs");
+    fs::create_dir_all(&log_dir)?;
+
+    let file_appender = tracing_appender::rolling::never(log_dir, "termpdf.log");
+    let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let file_layer = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(file_writer);
+    let console_layer = tracing_subscriber::fmt::layer();
+
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(file_layer)
+        .with(console_layer)
+        .try_init()
+        .map_err(|err| anyhow!(err))?;
+
+    Ok(guard)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compute_scaled_dimensions(
+    image: &RenderImage,
+    effective_pixel_width: f32,
+    effective_pixel_height: f32,
+    available_cols: u32,
+    available_rows: u32,
+    total_cols: u32,
+    total_rows: u32,
+    pixel_width: u32,
+    pixel_height: u32,
+) -> (u32, u32) {
+    let mut draw_cols = available_cols.max(1);
+    let mut draw_rows = available_rows.max(1);
+
+    if image.width == 0 || image.height == 0 {
+        return (draw_cols, draw_rows);
+    }
+
+    if pixel_width > 0
+        && pixel_height > 0
+        && total_cols > 0
+        && total_rows > 0
+        && effective_pixel_width.is_finite()
+        && effective_pixel_height.is_finite()
+        && effective_pixel_width > 0.0
+        && effective_pixel_height > 0.0
+    {
+        let cell_width = pixel_width as f32 / total_cols as f32;
+        let cell_height = pixel_height as f32 / total_rows as f32;
+
+        if cell_width > 0.0 && cell_height > 0.0 {
+            let mut cols = (effective_pixel_width / cell_width).round().max(1.0);
+            let mut rows = (effective_pixel_height / cell_height).round().max(1.0);
+
+            if cols > available_cols as f32 {
+                cols = available_cols as f32;
+            }
+            if rows > available_rows as f32 {
+                rows = available_rows as f32;
+            }
+
+            draw_cols = cols as u32;
+            draw_rows = rows as u32;
+        }
+    } else {
+        let ratio = if effective_pixel_height > 0.0 {
+            effective_pixel_width / effective_pixel_height
         } else {
-            let status_text = format_document_status(doc);
-            if let Some(status) = combine_status(Some(status_text), pending_input) {
-                draw_status_line(renderer, &status)?;
+            image.width as f32 / image.height as f32
+        };
+        if ratio.is_finite() && ratio > 0.0 {
+            let mut cols = available_cols as f32;
+            let mut rows = (cols / ratio).round().max(1.0);
+
+            if rows > available_rows as f32 {
+                rows = available_rows as f32;
+                cols = (rows * ratio).round().max(1.0);
             }
+
+            draw_cols = cols.min(available_cols as f32) as u32;
+            draw_rows = rows.min(available_rows as f32) as u32;
         }
+    }
+
+    draw_cols = draw_cols.max(1).min(available_cols);
+    draw_rows = draw_rows.max(1).min(available_rows);
+
+    (draw_cols, draw_rows)
+}
+
+fn map_visual_motion(motion: TtyVisualMotion) -> SelectionMotion {
+    match motion {
+        TtyVisualMotion::Left => SelectionMotion::Left,
+        TtyVisualMotion::Right => SelectionMotion::Right,
+        TtyVisualMotion::Up => SelectionMotion::Up,
+        TtyVisualMotion::Down => SelectionMotion::Down,
+        TtyVisualMotion::WordForward => SelectionMotion::WordForward,
+        TtyVisualMotion::WordBackward => SelectionMotion::WordBackward,
+        TtyVisualMotion::LineStart => SelectionMotion::LineStart,
+        TtyVisualMotion::LineEnd => SelectionMotion::LineEnd,
+        TtyVisualMotion::DocumentStart => SelectionMotion::DocumentStart,
+        TtyVisualMotion::DocumentEnd => SelectionMotion::DocumentEnd,
+        TtyVisualMotion::PageForward => SelectionMotion::PageForward,
+        TtyVisualMotion::PageBackward => SelectionMotion::PageBackward,
+        TtyVisualMotion::SentenceForward => SelectionMotion::SentenceForward,
+        TtyVisualMotion::SentenceBackward => SelectionMotion::SentenceBackward,
+        TtyVisualMotion::InnerWord => SelectionMotion::InnerWord,
+        TtyVisualMotion::AWord => SelectionMotion::AWord,
+        TtyVisualMotion::InnerParagraph => SelectionMotion::InnerParagraph,
+    }
+}
 
-        if let Err(err) = doc.prefetch_neighbors(2, render_scale) {
-            warn!(
-                ?err,
-                page = doc.state.current_page,
-                "failed to prefetch neighboring pages"
+/// Page size, in PDF points, assumed for placeholders (US Letter).
+const PLACEHOLDER_PAGE_SIZE: (f32, f32) = (612.0, 792.0);
+
+/// Why a page view shows a placeholder.
+enum RenderFailure {
+    Error(String),
+    /// Input arrived while the page was rendering; it is drawn again once
+    /// the input is handled.
+    Interrupted,
+}
+
+/// Renders `view` at `scale`, or at a lower scale while a slow page renders
+/// in the background when `progressive` is on. Returns the image with the
+/// scale it was rendered at.
+fn render_or_placeholder(
+    doc: &DocumentInstance,
+    view: DocumentPosition,
+    scale: f32,
+    progressive: bool,
+    failure: &mut Option<RenderFailure>,
+) -> (RenderImage, f32) {
+    let rendered = if progressive {
+        doc.render_view_progressive(view, scale)
+    } else {
+        doc.render_view(view, scale).map(|image| (image, scale))
+    };
+    match rendered {
+        Ok(rendered) => rendered,
+        Err(err) => {
+            *failure = Some(if err.is::<RenderCancelled>() {
+                RenderFailure::Interrupted
+            } else {
+                RenderFailure::Error(format!("Render failed: {}", err))
+            });
+            let image = RenderImage::placeholder(
+                (PLACEHOLDER_PAGE_SIZE.0 * scale) as u32,
+                (PLACEHOLDER_PAGE_SIZE.1 * scale) as u32,
             );
+            (image, scale)
         }
+    }
+}
 
-        draw_overlay(
-            renderer,
-            overlay,
-            total_cols,
-            total_rows,
-            image_rows_available,
-        )?;
-    } else {
-        overlay.deactivate();
+/// Copies `\cite{key}` for the document's `--bibtex` entry.
+fn yank_citation(doc: &DocumentInstance) -> StatusMessage {
+    let Some(bibliography) = bibtex::bibliography() else {
+        return StatusMessage::new(
+            t!("info-no-bibtex"),
+            CommandStatusKind::Error,
+            Some(STATUS_MESSAGE_TTL),
+        );
+    };
+    let Some(entry) = bibliography.find(&doc.info, doc.doi().as_deref()) else {
+        return StatusMessage::new(
+            t!("no-bib-entry"),
+            CommandStatusKind::Error,
+            Some(STATUS_MESSAGE_TTL),
+        );
+    };
+    let cite = entry.cite();
+    match copy_text_to_clipboard(&cite) {
+        Ok(()) => StatusMessage::new(
+            t!("yanked-cite", cite = cite),
+            CommandStatusKind::Info,
+            Some(STATUS_MESSAGE_TTL),
+        ),
+        Err(err) => StatusMessage::new(
+            t!("clipboard-error", error = err),
+            CommandStatusKind::Error,
+            Some(STATUS_MESSAGE_TTL),
+        ),
+    }
+}
+
+fn copy_text_to_clipboard(text: &str) -> Result<()> {
+    let mut clipboard = Clipboard::new().context("clipboard unavailable")?;
+    clipboard
+        .set_text(text.to_string())
+        .context("failed to copy selection")
+}
+
+fn crop_render_image(
+    image: &RenderImage,
+    origin_x: u32,
+    origin_y: u32,
+    width: u32,
+    height: u32,
+) -> RenderImage {
+    if image.width == 0 || image.height == 0 {
+        return RenderImage {
+            width: 0,
+            height: 0,
+            pixels: Vec::new(),
+        };
+    }
+
+    let width = width.min(image.width).max(1);
+    let height = height.min(image.height).max(1);
+    let max_origin_x = image.width.saturating_sub(width);
+    let max_origin_y = image.height.saturating_sub(height);
+    let origin_x = origin_x.min(max_origin_x);
+    let origin_y = origin_y.min(max_origin_y);
+
+    let stride = image.width as usize * 4;
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+
+    for row in 0..height {
+        let src_y = origin_y + row;
+        let start = src_y as usize * stride + origin_x as usize * 4;
+        let end = start + width as usize * 4;
+        pixels.extend_from_slice(&image.pixels[start..end]);
     }
 
-    Ok(())
+    RenderImage {
+        width,
+        height,
+        pixels,
+    }
 }
 
-fn document_status(session: &Session) -> Option<String> {
-    session.active().map(format_document_status)
+struct HighlightGeometry {
+    base_width: u32,
+    base_height: u32,
+    crop: Option<PixelCrop>,
 }
 
-fn combine_status(base: Option<String>, pending_input: Option<&str>) -> Option<String> {
-    match (base, pending_input.filter(|s| !s.is_empty())) {
-        (Some(mut base), Some(pending)) => {
-            base.push_str(" | ");
-            base.push_str(pending);
-            Some(base)
+impl HighlightGeometry {
+    fn new(base_width: u32, base_height: u32) -> Self {
+        Self {
+            base_width,
+            base_height,
+            crop: None,
         }
-        (Some(base), None) => Some(base),
-        (None, Some(pending)) => Some(pending.to_string()),
-        (None, None) => None,
     }
-}
 
-fn draw_status_line(renderer: &mut KittyRenderer<io::Stdout>, status: &str) -> Result<()> {
-    let window = terminal::window_size()?;
-    let total_rows = u32::from(window.rows).max(1);
-    let status_row = total_rows.saturating_sub(1);
-    let mut writer = renderer.writer();
-    crossterm::execute!(
-        &mut writer,
-        cursor::MoveTo(0, status_row as u16),
-        Clear(ClearType::CurrentLine)
-    )?;
-    write_status_line(&mut writer, status)?;
-    Ok(())
+    fn set_base(&mut self, width: u32, height: u32) {
+        self.base_width = width.max(1);
+        self.base_height = height.max(1);
+    }
+
+    fn set_crop(&mut self, offset_x: u32, offset_y: u32, width: u32, height: u32) {
+        self.crop = Some(PixelCrop {
+            offset_x,
+            offset_y,
+            width,
+            height,
+        });
+    }
+
+    fn clear_crop(&mut self) {
+        self.crop = None;
+    }
 }
 
-fn draw_status_message(
-    renderer: &mut KittyRenderer<io::Stdout>,
-    message: &StatusMessage,
-) -> Result<()> {
-    let window = terminal::window_size()?;
-    let total_cols = u32::from(window.columns).max(1) as usize;
-    let total_rows = u32::from(window.rows).max(1);
-    let status_row = total_rows.saturating_sub(1);
-    let mut writer = renderer.writer();
-    crossterm::execute!(
-        &mut writer,
-        cursor::MoveTo(0, status_row as u16),
-        Clear(ClearType::CurrentLine)
-    )?;
-    let clipped = truncate_with_ellipsis(message.text.clone(), total_cols);
-    let color = match message.kind {
-        CommandStatusKind::Info => Color::White,
-        CommandStatusKind::Error => Color::Red,
-        CommandStatusKind::Progress => Color::Yellow,
-    };
-    crossterm::execute!(&mut writer, SetForegroundColor(color))?;
-    write!(writer, "{}", clipped)?;
-    crossterm::execute!(&mut writer, SetForegroundColor(Color::Reset))?;
-    writer.flush()?;
-    Ok(())
+/// Render scale the palettes were tuned at; the default 1pt outline is 2px here.
+const HIGHLIGHT_REFERENCE_SCALE: f32 = 2.0;
+
+/// Highlight style resolved to the pixels of one rendered page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HighlightStroke {
+    thickness: u32,
+    opacity: f32,
 }
 
-fn draw_overlay(
-    renderer: &mut KittyRenderer<io::Stdout>,
-    overlay: &mut OverlayState,
-    total_cols: u32,
-    total_rows: u32,
-    image_rows_available: u32,
-) -> Result<()> {
-    match overlay {
-        OverlayState::Toc(toc) => {
-            {
-                let mut writer = renderer.writer();
-                crossterm::execute!(&mut writer, cursor::Hide)?;
-            }
-            draw_toc_overlay(renderer, toc, total_cols, image_rows_available)
-        }
-        OverlayState::Marks(window) => {
-            {
-                let mut writer = renderer.writer();
-                crossterm::execute!(&mut writer, cursor::Hide)?;
-            }
-            draw_marks_overlay(renderer, window, total_cols, image_rows_available)
-        }
-        OverlayState::Command(command) => {
-            draw_command_overlay(renderer, command, total_cols, total_rows)
-        }
-        OverlayState::None => {
-            let mut writer = renderer.writer();
-            crossterm::execute!(&mut writer, cursor::Hide)?;
-            Ok(())
-        }
+impl HighlightStroke {
+    /// `scale` is the render scale of the page image (pixels per point).
+    fn for_scale(style: HighlightStyle, scale: f32) -> Self {
+        let scale = if scale.is_finite() && scale > 0.0 {
+            scale
+        } else {
+            HIGHLIGHT_REFERENCE_SCALE
+        };
+        let thickness = if style.stroke_width > 0.0 {
+            (style.stroke_width * scale).round().clamp(1.0, 64.0) as u32
+        } else {
+            0
+        };
+        // Small renders are upscaled by the terminal, which softens the text
+        // under a fill; large ones stay crisp. Tint the former a bit more.
+        let opacity =
+            style.fill_opacity * (HIGHLIGHT_REFERENCE_SCALE / scale).sqrt().clamp(0.75, 1.25);
+        Self { thickness, opacity }
     }
 }
 
-fn draw_command_overlay(
-    renderer: &mut KittyRenderer<io::Stdout>,
-    overlay: &CommandOverlay,
-    total_cols: u32,
-    total_rows: u32,
-) -> Result<()> {
-    if total_cols == 0 || total_rows == 0 {
-        return Ok(());
+struct HighlightPalette {
+    current_fill: [u8; 3],
+    current_alpha: f32,
+    current_stroke: [u8; 3],
+    other_stroke: [u8; 3],
+}
+
+const SEARCH_HIGHLIGHT_PALETTE: HighlightPalette = HighlightPalette {
+    current_fill: [255, 235, 0],
+    current_alpha: 0.35,
+    current_stroke: [255, 235, 0],
+    other_stroke: [255, 200, 0],
+};
+
+const SELECTION_HIGHLIGHT_PALETTE: HighlightPalette = HighlightPalette {
+    current_fill: [0, 200, 255],
+    current_alpha: 0.35,
+    current_stroke: [0, 200, 255],
+    other_stroke: [0, 150, 220],
+};
+
+const EXTERNAL_HIGHLIGHT_PALETTE: HighlightPalette = HighlightPalette {
+    current_fill: [255, 90, 200],
+    current_alpha: 0.3,
+    current_stroke: [255, 90, 200],
+    other_stroke: [220, 70, 170],
+};
+
+const FORM_HIGHLIGHT_PALETTE: HighlightPalette = HighlightPalette {
+    current_fill: [80, 220, 120],
+    current_alpha: 0.3,
+    current_stroke: [80, 220, 120],
+    other_stroke: [60, 170, 90],
+};
+
+const CURSOR_HIGHLIGHT_PALETTE: HighlightPalette = HighlightPalette {
+    current_fill: [180, 180, 255],
+    current_alpha: 0.25,
+    current_stroke: [180, 180, 255],
+    other_stroke: [180, 180, 255],
+};
+
+fn apply_highlights(
+    image: &mut RenderImage,
+    highlights: &Highlights,
+    geom: &HighlightGeometry,
+    palette: &HighlightPalette,
+    stroke: HighlightStroke,
+) {
+    if image.width == 0 || image.height == 0 {
+        return;
     }
-    let status_row = total_rows.saturating_sub(1);
-    let max_cols = total_cols as usize;
-    let mut writer = renderer.writer();
-    crossterm::execute!(
-        &mut writer,
-        cursor::MoveTo(0, status_row as u16),
-        Clear(ClearType::CurrentLine)
-    )?;
 
-    if max_cols == 0 {
-        crossterm::execute!(&mut writer, cursor::Show)?;
-        return Ok(());
+    if highlights.is_empty() {
+        return;
     }
 
-    let (prompt, cursor_col) = overlay.visible_prompt(max_cols);
-    write!(writer, "{}", prompt)?;
-    let mut used_cols = prompt.chars().count().min(max_cols);
+    let other_rects: Vec<PixelRect> = highlights
+        .others
+        .iter()
+        .filter_map(|rect| normalized_to_pixel_rect(*rect, geom))
+        .collect();
+    let current_rects: Vec<PixelRect> = highlights
+        .current
+        .iter()
+        .filter_map(|rect| normalized_to_pixel_rect(*rect, geom))
+        .collect();
 
-    if used_cols < max_cols {
-        write!(writer, " ")?;
-        used_cols = (used_cols + 1).min(max_cols);
+    for rect in other_rects {
+        stroke_rect(image, rect, palette.other_stroke, stroke.thickness);
+    }
+    for rect in current_rects {
+        fill_rect(
+            image,
+            rect,
+            palette.current_fill,
+            palette.current_alpha * stroke.opacity,
+        );
+        stroke_rect(image, rect, palette.current_stroke, stroke.thickness);
     }
+}
 
-    if used_cols < max_cols {
-        let available = max_cols - used_cols;
-        overlay.write_status(&mut writer, available)?;
+/// Follows `target` as the handler for its scheme says. With `ask`, and
+/// unless `--no-confirm-links` was given, starting a program waits for the
+/// user to confirm in a popup.
+fn follow_external_link(
+    target: ExternalLink,
+    ask: bool,
+    overlay: &mut OverlayState,
+    mapper: &mut EventMapper,
+    status_bar: &mut StatusBar,
+) {
+    let policy = links::policy();
+    let action = policy.action_for(&target);
+    if ask && policy.confirm && action.launches() {
+        *overlay = OverlayState::LinkPrompt(LinkPrompt {
+            target,
+            action,
+            scroll: 0,
+            return_mode: mapper.mode(),
+        });
+        mapper.set_mode(InputMode::Toc);
+        return;
     }
+    status_bar.set_message(run_link_action(&action, &target));
+}
 
-    let cursor_limit = max_cols.saturating_sub(1);
-    let cursor_position = cursor_col.min(cursor_limit) as u16;
-    crossterm::execute!(
-        &mut writer,
-        cursor::MoveTo(cursor_position, status_row as u16),
-        cursor::Show
-    )?;
+fn run_link_action(action: &LinkAction, target: &ExternalLink) -> StatusMessage {
+    let uri = links::display(target);
+    let result = match action {
+        LinkAction::Open => open_external_link(target).map(|()| t!("opened-link", uri = uri)),
+        LinkAction::Run(command) => {
+            links::spawn(command, target).map(|()| t!("opened-link", uri = uri))
+        }
+        LinkAction::Copy => copy_text_to_clipboard(&uri).map(|()| t!("copied-link", uri = uri)),
+        LinkAction::Ignore => Ok(t!("ignored-link", uri = uri)),
+        LinkAction::Blocked => copy_text_to_clipboard(&uri).map(|()| t!("blocked-link", uri = uri)),
+    };
+    match result {
+        Ok(text) => StatusMessage::new(text, CommandStatusKind::Info, Some(STATUS_MESSAGE_TTL)),
+        Err(err) => StatusMessage::new(
+            t!("link-failed", error = err),
+            CommandStatusKind::Error,
+            Some(STATUS_MESSAGE_TTL),
+        ),
+    }
+}
 
-    Ok(())
+fn open_external_link(target: &ExternalLink) -> Result<()> {
+    match target {
+        ExternalLink::Url(uri) => open_uri(uri),
+        ExternalLink::File(path) => open_path(path),
+    }
 }
 
-fn refresh_mark_overlay(overlay: &mut OverlayState, session: &Session, mapper: &mut EventMapper) {
-    if let OverlayState::Marks(window) = overlay {
-        if let Some(doc) = session.active() {
-            if !window.rebuild(doc) {
-                overlay.deactivate();
-                mapper.set_mode(InputMode::Normal);
-            } else {
-                window.update_current_page(doc.state.current_page);
+fn open_uri(uri: &str) -> Result<()> {
+    if let Ok(url) = Url::parse(uri) {
+        if url.scheme() == "file" {
+            if let Ok(path) = url.to_file_path() {
+                return open_path(&path);
             }
-        } else {
-            overlay.deactivate();
-            mapper.set_mode(InputMode::Normal);
         }
     }
+    spawn_open_command(OsStr::new(uri))
 }
 
-fn tokenize_command(input: &str) -> Vec<String> {
-    let mut tokens = Vec::new();
-    let mut current = String::new();
-    let mut chars = input.chars().peekable();
-    let mut quote: Option<char> = None;
+fn open_path(path: &Path) -> Result<()> {
+    if !path.exists() {
+        return Err(anyhow!("link target {:?} does not exist", path));
+    }
+    spawn_open_command(path.as_os_str())
+}
 
-    while let Some(ch) = chars.next() {
-        if let Some(q) = quote {
-            if ch == '\\' {
-                if let Some(next) = chars.next() {
-                    current.push(next);
-                }
-                continue;
-            }
-            if ch == q {
-                tokens.push(current.clone());
-                current.clear();
-                quote = None;
-            } else {
-                current.push(ch);
-            }
-            continue;
+fn spawn_open_command(target: &OsStr) -> Result<()> {
+    #[cfg(target_os = "windows")]
+    {
+        let status = ProcessCommand::new("cmd")
+            .arg("/C")
+            .arg("start")
+            .arg("")
+            .arg(target)
+            .status()
+            .with_context(|| format!("failed to spawn open command for {:?}", target))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "open command exited with status {:?}",
+                status.code()
+            ));
         }
-
-        match ch {
-            '\'' | '"' => {
-                if !current.is_empty() {
-                    tokens.push(current.clone());
-                    current.clear();
-                }
-                quote = Some(ch);
-            }
-            c if c.is_whitespace() => {
-                if !current.is_empty() {
-                    tokens.push(current.clone());
-                    current.clear();
-                }
-            }
-            c => current.push(c),
+        Ok(())
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let status = ProcessCommand::new(OPEN_COMMAND)
+            .arg(target)
+            .status()
+            .with_context(|| format!("failed to spawn '{}' for {:?}", OPEN_COMMAND, target))?;
+        if !status.success() {
+            return Err(anyhow!(
+                "'{}' exited with status {:?}",
+                OPEN_COMMAND,
+                status.code()
+            ));
         }
+        Ok(())
     }
-
-    if !current.is_empty() {
-        tokens.push(current);
-    } else if quote.is_some() {
-        tokens.push(String::new());
-    }
-
-    tokens
 }
 
-fn draw_toc_overlay(
-    renderer: &mut KittyRenderer<io::Stdout>,
-    toc: &mut TocWindow,
-    total_cols: u32,
-    image_rows_available: u32,
-) -> Result<()> {
-    const TITLE: &str = "Table of Contents";
-    const EMPTY_MESSAGE: &str = "No table of contents available";
+fn normalized_to_pixel_rect(rect: NormalizedRect, geom: &HighlightGeometry) -> Option<PixelRect> {
+    rect_to_pixels(&rect, geom.base_width, geom.base_height, geom.crop)
+}
 
-    if total_cols < 20 || image_rows_available < 6 {
-        return Ok(());
+fn fill_rect(image: &mut RenderImage, rect: PixelRect, color: [u8; 3], alpha: f32) {
+    if rect.x0 >= rect.x1 || rect.y0 >= rect.y1 {
+        return;
     }
-
-    let max_inner_width = total_cols.saturating_sub(6) as usize;
-    if max_inner_width < 10 {
-        return Ok(());
+    let width = image.width as usize;
+    let height = image.height as usize;
+    if width == 0 || height == 0 {
+        return;
     }
 
-    let base_width = if toc.is_empty() {
-        EMPTY_MESSAGE.len() + 2
-    } else {
-        toc.entries
-            .iter()
-            .map(toc_line_length)
-            .max()
-            .unwrap_or(0)
-            .max(TITLE.len())
-    };
+    let x1 = rect.x1.min(image.width);
+    let y1 = rect.y1.min(image.height);
+    let x0 = rect.x0.min(x1);
+    let y0 = rect.y0.min(y1);
 
-    let mut inner_width = base_width.min(max_inner_width);
-    let min_inner_width = 20.min(max_inner_width);
-    if inner_width < min_inner_width {
-        inner_width = min_inner_width;
+    for y in y0..y1 {
+        let row_start = (y as usize) * width * 4;
+        for x in x0..x1 {
+            let idx = row_start + (x as usize) * 4;
+            blend_pixel(&mut image.pixels[idx..idx + 4], color, alpha);
+        }
     }
+}
 
-    let max_window_height = image_rows_available.saturating_sub(2);
-    if max_window_height < 6 {
-        return Ok(());
+/// Paints `color` over `pixel` ("over" compositing), so it works both on
+/// opaque pages and on the transparent highlight layer.
+fn blend_pixel(pixel: &mut [u8], color: [u8; 3], alpha: f32) {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let below = pixel[3] as f32 / 255.0 * (1.0 - alpha);
+    let out = alpha + below;
+    if out <= 0.0 {
+        return;
     }
-
-    let search_prompt = toc.search_prompt();
-    let extra_header_rows = if search_prompt.is_some() { 1u32 } else { 0u32 };
-    let header_rows = 4 + extra_header_rows;
-    if max_window_height < header_rows {
-        return Ok(());
+    for channel in 0..3 {
+        pixel[channel] = ((pixel[channel] as f32 * below + color[channel] as f32 * alpha) / out)
+            .round()
+            .clamp(0.0, 255.0) as u8;
     }
+    pixel[3] = (out * 255.0).round().clamp(0.0, 255.0) as u8;
+}
 
-    let max_content_height = max_window_height.saturating_sub(header_rows) as usize;
-    if max_content_height == 0 {
-        return Ok(());
+/// Flattens the highlight layer onto the page, as the terminal shows them.
+fn composite_layer(image: &mut RenderImage, layer: &RenderImage) {
+    if (image.width, image.height) != (layer.width, layer.height) {
+        return;
     }
-
-    let total_entries = if toc.is_empty() { 1 } else { toc.entries.len() };
-    let content_height = total_entries.min(max_content_height).max(1);
-    toc.ensure_visible(content_height);
-    let max_scroll = total_entries.saturating_sub(content_height);
-    if toc.scroll_offset > max_scroll {
-        toc.scroll_offset = max_scroll;
+    for (pixel, top) in image
+        .pixels
+        .chunks_exact_mut(4)
+        .zip(layer.pixels.chunks_exact(4))
+    {
+        if top[3] > 0 {
+            blend_pixel(pixel, [top[0], top[1], top[2]], top[3] as f32 / 255.0);
+        }
     }
+}
 
-    let window_height = (content_height as u32).saturating_add(header_rows);
-    if window_height > max_window_height {
-        return Ok(());
-    }
-    let window_width = (inner_width + 2) as u32;
-    if window_width > total_cols {
-        return Ok(());
+fn stroke_rect(image: &mut RenderImage, rect: PixelRect, color: [u8; 3], thickness: u32) {
+    if thickness == 0 || rect.x0 >= rect.x1 || rect.y0 >= rect.y1 {
+        return;
     }
-
-    let start_col = (total_cols.saturating_sub(window_width)) / 2;
-    let start_row = (image_rows_available.saturating_sub(window_height)) / 2;
-
-    let mut writer = renderer.writer();
-    let mut current_row = start_row as u16;
-    let start_col_u16 = start_col as u16;
-    let horizontal_border = "-".repeat(inner_width);
-
-    print_inverted(
-        &mut writer,
-        start_col_u16,
-        current_row,
-        &format!("+{}+", horizontal_border),
-        false,
-    )?;
-    current_row = current_row.saturating_add(1);
-
-    let title_line = format!("|{: ^inner_width$}|", TITLE, inner_width = inner_width);
-    print_inverted(&mut writer, start_col_u16, current_row, &title_line, false)?;
-    current_row = current_row.saturating_add(1);
-
-    if let Some(prompt) = search_prompt.as_ref() {
-        let content = truncate_with_ellipsis(format!("  {}", prompt), inner_width);
-        let line = format!("|{}|", content);
-        print_inverted(&mut writer, start_col_u16, current_row, &line, false)?;
-        current_row = current_row.saturating_add(1);
+    let width = image.width as usize;
+    if width == 0 {
+        return;
     }
+    let x0 = rect.x0.min(image.width - 1);
+    let x1 = rect.x1.min(image.width);
+    let y0 = rect.y0.min(image.height - 1);
+    let y1 = rect.y1.min(image.height);
 
-    let divider = format!("|{}|", "-".repeat(inner_width));
-    print_inverted(&mut writer, start_col_u16, current_row, &divider, false)?;
-    current_row = current_row.saturating_add(1);
-
-    let active_query = toc.active_query().map(|q| q.to_string());
-
-    if toc.is_empty() {
-        let content = truncate_with_ellipsis(format!("  {}", EMPTY_MESSAGE), inner_width);
-        let line = format!("|{}|", content);
-        print_inverted(&mut writer, start_col_u16, current_row, &line, false)?;
-        current_row = current_row.saturating_add(1);
-    } else {
-        let start_index = toc.scroll_offset;
-        let end_index = (start_index + content_height).min(toc.entries.len());
-        for idx in start_index..end_index {
-            let entry = &toc.entries[idx];
-            let selected = idx == toc.selected;
-            let current = toc
-                .current_index()
-                .map(|current| current == idx)
-                .unwrap_or(false);
-            let matching = toc.entry_matches(idx);
-            let content = format_toc_line(
-                entry,
-                selected,
-                current,
-                matching,
-                active_query.as_deref(),
-                inner_width,
+    for y in y0..y1 {
+        let row_start = (y as usize) * width * 4;
+        for x in x0..(x0 + thickness.min(x1 - x0)) {
+            overwrite_pixel(
+                &mut image.pixels[row_start + (x as usize) * 4..][..4],
+                color,
             );
-            let line = format!("|{}|", content);
-            print_inverted(&mut writer, start_col_u16, current_row, &line, matching)?;
-            current_row = current_row.saturating_add(1);
         }
-
-        let rendered = end_index - start_index;
-        for _ in rendered..content_height {
-            let line = format!("|{}|", " ".repeat(inner_width));
-            print_inverted(&mut writer, start_col_u16, current_row, &line, false)?;
-            current_row = current_row.saturating_add(1);
+        if x1 > x0 + thickness {
+            for x in (x1.saturating_sub(thickness))..x1 {
+                overwrite_pixel(
+                    &mut image.pixels[row_start + (x as usize) * 4..][..4],
+                    color,
+                );
+            }
         }
     }
 
-    print_inverted(
-        &mut writer,
-        start_col_u16,
-        current_row,
-        &format!("+{}+", horizontal_border),
-        false,
-    )?;
-
-    Ok(())
-}
-
-fn draw_marks_overlay(
-    renderer: &mut KittyRenderer<io::Stdout>,
-    marks: &mut MarkWindow,
-    total_cols: u32,
-    image_rows_available: u32,
-) -> Result<()> {
-    const TITLE: &str = "Marks";
-    const EMPTY_MESSAGE: &str = "No marks saved";
-
-    if total_cols < 20 || image_rows_available < 4 {
-        return Ok(());
-    }
-
-    if marks.is_empty() {
-        return Ok(());
-    }
-
-    let max_inner_width = total_cols.saturating_sub(6) as usize;
-    if max_inner_width < 10 {
-        return Ok(());
+    for y in y0..(y0 + thickness.min(y1 - y0)) {
+        let row_start = (y as usize) * width * 4;
+        for x in x0..x1 {
+            overwrite_pixel(
+                &mut image.pixels[row_start + (x as usize) * 4..][..4],
+                color,
+            );
+        }
     }
-
-    let base_width = marks
-        .entries
-        .iter()
-        .map(mark_line_length)
-        .max()
-        .unwrap_or(EMPTY_MESSAGE.len())
-        .max(TITLE.len());
-
-    let mut inner_width = base_width.min(max_inner_width);
-    let min_inner_width = 20.min(max_inner_width);
-    if inner_width < min_inner_width {
-        inner_width = min_inner_width;
+    if y1 > y0 + thickness {
+        for y in (y1.saturating_sub(thickness))..y1 {
+            let row_start = (y as usize) * width * 4;
+            for x in x0..x1 {
+                overwrite_pixel(
+                    &mut image.pixels[row_start + (x as usize) * 4..][..4],
+                    color,
+                );
+            }
+        }
     }
+}
 
-    let max_window_height = image_rows_available.saturating_sub(2);
-    if max_window_height < 5 {
-        return Ok(());
-    }
+fn overwrite_pixel(pixel: &mut [u8], color: [u8; 3]) {
+    pixel[0] = color[0];
+    pixel[1] = color[1];
+    pixel[2] = color[2];
+    pixel[3] = 255;
+}
 
-    let header_rows = 3u32;
-    if max_window_height < header_rows {
-        return Ok(());
-    }
+fn format_document_status(doc: &DocumentInstance) -> String {
+    let zoom_percent = doc.state.scale * 100.0;
+    let mut zoom_display = if zoom_percent.is_finite() {
+        format!("{:.0}%", zoom_percent)
+    } else {
+        "—".to_string()
+    };
+    if doc.state.fit_mode == FitMode::Width {
+        zoom_display = t!("status-fit-width", zoom = zoom_display);
+    }
+
+    let page = match doc.page_label(doc.state.current_page) {
+        Some(label) => format!(
+            "{} ({}/{})",
+            label,
+            doc.state.current_page + 1,
+            doc.info.page_count
+        ),
+        None => format!("{}/{}", doc.state.current_page + 1, doc.info.page_count),
+    };
+    let mut status = t!(
+        "status-document",
+        name = doc
+            .info
+            .path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("<unknown>"),
+        page = page,
+        zoom = zoom_display
+    );
 
-    let total_entries = marks.entries_len().max(1);
-    let max_content_height = max_window_height.saturating_sub(header_rows) as usize;
-    let content_height = total_entries.min(max_content_height).max(1);
-    marks.ensure_visible(content_height);
-    let max_scroll = total_entries.saturating_sub(content_height);
-    if marks.scroll_offset > max_scroll {
-        marks.scroll_offset = max_scroll;
+    if doc.is_rebuilding() {
+        push_status_part(&mut status, &t!("status-rebuilding"));
     }
 
-    let window_height = (content_height as u32).saturating_add(header_rows);
-    if window_height > max_window_height {
-        return Ok(());
-    }
-    let window_width = (inner_width + 2) as u32;
-    if window_width > total_cols {
-        return Ok(());
+    if let Some(summary) = doc.search_summary() {
+        push_status_part(
+            &mut status,
+            match summary.scope {
+                SearchScope::Document => "/",
+                SearchScope::Page => "?",
+            },
+        );
+        status.push_str(&summary.query);
+        if summary.total == 0 {
+            push_status_count(&mut status, &t!("status-no-matches"));
+        } else if let Some((index, page)) = summary.current_index.zip(summary.current_page) {
+            let page = doc
+                .page_label(page)
+                .unwrap_or_else(|| (page + 1).to_string());
+            status.push(' ');
+            status.push_str(&t!(
+                "status-match",
+                index = index + 1,
+                total = summary.total,
+                page = page
+            ));
+        } else {
+            push_status_count(&mut status, &format!("0/{}", summary.total));
+        }
     }
 
-    let start_col = (total_cols.saturating_sub(window_width)) / 2;
-    let start_row = (image_rows_available.saturating_sub(window_height)) / 2;
-
-    let mut writer = renderer.writer();
-    let mut current_row = start_row as u16;
-    let start_col_u16 = start_col as u16;
-    let horizontal_border = "-".repeat(inner_width);
-
-    print_inverted(
-        &mut writer,
-        start_col_u16,
-        current_row,
-        &format!("+{}+", horizontal_border),
-        false,
-    )?;
-    current_row = current_row.saturating_add(1);
-
-    let title = truncate_with_ellipsis(format!(" {TITLE}"), inner_width);
-    let title_line = format!("|{}|", title);
-    print_inverted(&mut writer, start_col_u16, current_row, &title_line, false)?;
-    current_row = current_row.saturating_add(1);
-
-    let separator = format!("+{}+", horizontal_border);
-    print_inverted(&mut writer, start_col_u16, current_row, &separator, false)?;
-    current_row = current_row.saturating_add(1);
-
-    let start_index = marks.scroll_offset;
-    let end_index = (start_index + content_height).min(marks.entries_len());
-    for idx in start_index..end_index {
-        let entry = &marks.entries[idx];
-        let selected = idx == marks.selected;
-        let current = marks
-            .current_index()
-            .map(|current| current == idx)
-            .unwrap_or(false);
-        let content = format_mark_line(entry, selected, current, inner_width);
-        let line = format!("|{}|", content);
-        print_inverted(
-            &mut writer,
-            start_col_u16,
-            current_row,
-            &line,
-            selected || current,
-        )?;
-        current_row = current_row.saturating_add(1);
+    if let Some(summary) = doc.link_summary() {
+        push_status_part(&mut status, &t!("status-link-mode"));
+        if summary.filter != LinkFilter::All {
+            status.push_str(&format!(" [{}]", summary.filter.label()));
+        }
+        if summary.total == 0 {
+            push_status_count(&mut status, &t!("status-no-links"));
+        } else {
+            let index = summary.current_index.map_or(0, |index| index + 1);
+            push_status_count(&mut status, &format!("{}/{}", index, summary.total));
+        }
     }
 
-    for _ in end_index..(start_index + content_height) {
-        let line = format!("|{}|", " ".repeat(inner_width));
-        print_inverted(&mut writer, start_col_u16, current_row, &line, false)?;
-        current_row = current_row.saturating_add(1);
+    if let Some(summary) = doc.form_summary() {
+        push_status_part(&mut status, &t!("status-form-mode"));
+        if summary.total == 0 {
+            push_status_count(&mut status, &t!("status-no-fields"));
+        } else if let Some(index) = summary.current_index {
+            push_status_count(&mut status, &format!("{}/{}", index + 1, summary.total));
+            if let Some(field) = doc.current_form_field() {
+                status.push(' ');
+                status.push_str(&describe_form_field(&field.name, &field.kind));
+            }
+        }
     }
 
-    print_inverted(
-        &mut writer,
-        start_col_u16,
-        current_row,
-        &format!("+{}+", horizontal_border),
-        false,
-    )?;
-
-    Ok(())
-}
-
-fn print_inverted(
-    writer: &mut impl Write,
-    col: u16,
-    row: u16,
-    content: &str,
-    highlight: bool,
-) -> Result<()> {
-    if highlight {
-        crossterm::execute!(
-            writer,
-            cursor::MoveTo(col, row),
-            SetAttribute(Attribute::Reverse),
-            SetAttribute(Attribute::Bold),
-            Print(content),
-            SetAttribute(Attribute::Reset)
-        )?;
-    } else {
-        crossterm::execute!(
-            writer,
-            cursor::MoveTo(col, row),
-            SetAttribute(Attribute::Reverse),
-            Print(content),
-            SetAttribute(Attribute::Reset)
-        )?;
-    }
-    Ok(())
+    status
 }
 
-fn toc_line_length(entry: &OutlineItem) -> usize {
-    let indent_levels = entry.depth.min(8);
-    let indent_width = indent_levels * 2;
-    let page_suffix = format!(" (p{})", entry.page_index + 1);
-    4 + indent_width + entry.title.len() + page_suffix.len()
+/// Appends a part of the status line, such as the mode, after a dash.
+fn push_status_part(status: &mut String, part: &str) {
+    status.push_str(" — ");
+    status.push_str(part);
 }
 
-fn format_toc_line(
-    entry: &OutlineItem,
-    selected: bool,
-    current: bool,
-    matching: bool,
-    active_query: Option<&str>,
-    inner_width: usize,
-) -> String {
-    let selected_marker = if selected { '>' } else { ' ' };
-    let current_marker = if current { '*' } else { ' ' };
-    let match_marker = if matching { '+' } else { ' ' };
-    let indent_levels = entry.depth.min(8);
-    let indent = "  ".repeat(indent_levels);
-    let page_suffix = format!(" (p{})", entry.page_index + 1);
-
-    let title = if matching {
-        highlight_search_segment(&entry.title, active_query)
-    } else {
-        entry.title.clone()
-    };
-
-    let mut text = String::new();
-    text.push(selected_marker);
-    text.push(current_marker);
-    text.push(match_marker);
-    text.push(' ');
-    text.push_str(&indent);
-    text.push_str(&title);
-    text.push_str(&page_suffix);
-
-    truncate_with_ellipsis(text, inner_width)
+/// Appends how far along a mode is, in parentheses.
+fn push_status_count(status: &mut String, count: &str) {
+    status.push_str(" (");
+    status.push_str(count);
+    status.push(')');
 }
 
-fn highlight_search_segment(title: &str, query: Option<&str>) -> String {
-    if let Some(query) = query {
-        if !query.is_empty() && title.is_ascii() && query.is_ascii() {
-            if let Some((start, end)) = find_ascii_match_range(title, query) {
-                let mut highlighted = String::with_capacity(title.len() + 2);
-                highlighted.push_str(&title[..start]);
-                highlighted.push('[');
-                highlighted.push_str(&title[start..end]);
-                highlighted.push(']');
-                highlighted.push_str(&title[end..]);
-                return highlighted;
-            }
+fn describe_form_field(name: &Option<String>, kind: &FormFieldKind) -> String {
+    let name = name.as_deref().unwrap_or("<unnamed>");
+    match kind {
+        FormFieldKind::Text { value } => format!("{} = \"{}\"", name, value),
+        FormFieldKind::Checkbox { checked } | FormFieldKind::RadioButton { checked } => {
+            format!("{} [{}]", name, if *checked { "x" } else { " " })
         }
+        FormFieldKind::Other => name.to_string(),
     }
-    title.to_string()
-}
-
-fn mark_line_length(entry: &MarkEntry) -> usize {
-    let name_len = entry.name.len();
-    let suffix = format!(" (p{})", entry.page + 1);
-    4 + name_len + suffix.len()
 }
 
-fn format_mark_line(
-    entry: &MarkEntry,
-    selected: bool,
-    current: bool,
-    inner_width: usize,
-) -> String {
-    let selected_marker = if selected { '>' } else { ' ' };
-    let current_marker = if current { '*' } else { ' ' };
-    let mut text = String::new();
-    text.push(selected_marker);
-    text.push(current_marker);
-    text.push(' ');
-    text.push_str(&entry.name);
-    text.push_str(&format!(" (p{})", entry.page + 1));
-    truncate_with_ellipsis(text, inner_width)
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termpdf_core::{ManualClock, MemoryStateStore};
 
-fn find_ascii_match_range(haystack: &str, needle: &str) -> Option<(usize, usize)> {
-    if needle.is_empty() {
-        return None;
-    }
-    if !haystack.is_ascii() || !needle.is_ascii() {
-        return None;
-    }
-    let hay = haystack.as_bytes();
-    let needle_bytes = needle.as_bytes();
-    if needle_bytes.len() > hay.len() {
-        return None;
-    }
-    for start in 0..=hay.len() - needle_bytes.len() {
-        if hay[start..start + needle_bytes.len()]
-            .iter()
-            .zip(needle_bytes)
-            .all(|(&h, &n)| h.to_ascii_lowercase() == n.to_ascii_lowercase())
-        {
-            return Some((start, start + needle_bytes.len()));
-        }
+    #[test]
+    fn link_handlers_come_from_the_config_file_and_flags_override_them() {
+        let config = Config::parse(
+            "[links]\nconfirm = false\nhandlers = { http = \"firefox --new-tab\", mailto = \"ignore\" }\n",
+        )
+        .unwrap();
+        let http = ExternalLink::Url("http://example.com".to_string());
+        let mailto = ExternalLink::Url("mailto:me@example.com".to_string());
+
+        let args = Args::try_parse_from(["termpdf", "a.pdf"]).unwrap();
+        let policy = link_policy(&args, &config.links).unwrap();
+        assert!(!policy.confirm);
+        assert_eq!(
+            policy.action_for(&http),
+            LinkAction::Run(vec!["firefox".to_string(), "--new-tab".to_string()])
+        );
+        assert_eq!(policy.action_for(&mailto), LinkAction::Ignore);
+
+        let args =
+            Args::try_parse_from(["termpdf", "--link-handler", "mailto=copy", "a.pdf"]).unwrap();
+        let policy = link_policy(&args, &config.links).unwrap();
+        assert_eq!(policy.action_for(&mailto), LinkAction::Copy);
+        assert!(
+            link_policy(&args, &Config::default().links)
+                .unwrap()
+                .confirm
+        );
+
+        let broken = Config::parse("[links]\nhandlers = { http = \"\" }\n").unwrap();
+        assert!(link_policy(&args, &broken.links).is_err());
     }
-    None
-}
 
-fn truncate_with_ellipsis(mut text: String, width: usize) -> String {
-    if text.len() > width {
-        if width <= 3 {
-            text.truncate(width);
-        } else {
-            let mut truncated = text.chars().take(width - 3).collect::<String>();
-            truncated.push_str("...");
-            text = truncated;
-        }
+    #[test]
+    fn schemes_denied_in_the_config_file_are_copied_without_flags() {
+        let config = Config::parse(
+            "[links]\nallow = [\"https:\"]\ndeny = [\"file:\", \"tracker.example\"]\n",
+        )
+        .unwrap();
+        let file = ExternalLink::File(PathBuf::from("/tmp/run.sh"));
+        let tracker = ExternalLink::Url("https://tracker.example/p".to_string());
+        let paper = ExternalLink::Url("https://arxiv.org/abs/1706.03762".to_string());
+        let mailto = ExternalLink::Url("mailto:me@example.com".to_string());
+
+        let args = Args::try_parse_from(["termpdf", "a.pdf"]).unwrap();
+        let policy = link_policy(&args, &config.links).unwrap();
+        assert_eq!(policy.action_for(&file), LinkAction::Blocked);
+        assert_eq!(policy.action_for(&tracker), LinkAction::Blocked);
+        assert_eq!(policy.action_for(&mailto), LinkAction::Blocked);
+        assert_eq!(policy.action_for(&paper), LinkAction::Open);
+
+        let args = Args::try_parse_from(["termpdf", "--allow-links", "mailto:", "a.pdf"]).unwrap();
+        let policy = link_policy(&args, &config.links).unwrap();
+        assert_eq!(policy.action_for(&mailto), LinkAction::Open);
+        assert_eq!(policy.action_for(&paper), LinkAction::Blocked);
+        assert_eq!(policy.action_for(&file), LinkAction::Blocked);
+
+        let broken = Config::parse("[links]\ndeny = [\":\"]\n").unwrap();
+        assert!(link_policy(&args, &broken.links).is_err());
     }
-    if text.len() < width {
-        text.push_str(&" ".repeat(width - text.len()));
+
+    #[test]
+    fn file_args_accept_page_suffix() {
+        assert_eq!(
+            parse_file_arg("a.pdf:12").unwrap(),
+            FileArg {
+                path: PathBuf::from("a.pdf"),
+                page: Some(11),
+            }
+        );
+        assert_eq!(parse_file_arg("b.pdf").unwrap().page, None);
+        assert_eq!(parse_file_arg("notes:draft.pdf").unwrap().page, None);
+        assert!(parse_file_arg("a.pdf:0").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let literal = dir.path().join("scan:2");
+        std::fs::write(&literal, b"%PDF-").unwrap();
+        let parsed = parse_file_arg(literal.to_str().unwrap()).unwrap();
+        assert_eq!(parsed.path, literal);
+        assert_eq!(parsed.page, None);
     }
-    text
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn piped_documents_are_stashed_by_content() {
+        assert_eq!(
+            parse_file_arg("-:3").unwrap(),
+            FileArg {
+                path: PathBuf::from(STDIN_ARG),
+                page: Some(2),
+            }
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let stash = dir.path().join("stdin");
+        let first = stash_bytes(&stash, b"%PDF-1.7 paper").unwrap();
+        assert_eq!(std::fs::read(&first).unwrap(), b"%PDF-1.7 paper");
+        assert_eq!(stash_bytes(&stash, b"%PDF-1.7 paper").unwrap(), first);
+        assert_ne!(stash_bytes(&stash, b"%PDF-1.7 other").unwrap(), first);
+        assert!(stash_bytes(&stash, b"").is_err());
+    }
 
     fn outline(title: &str, page_index: usize) -> OutlineItem {
         OutlineItem {
             title: title.to_string(),
             page_index,
             depth: 0,
+            page_label: None,
         }
     }
 
@@ -2405,13 +6691,35 @@ mod tests {
         let mut toc = TocWindow::from_outline(entries, 0);
         assert!(toc.begin_search());
         assert!(toc.update_search_query("chapter"));
-        assert_eq!(toc.selected, 1);
+        assert_eq!(toc.list.selected(), 1);
         assert!(toc.search_next(1));
-        assert_eq!(toc.selected, 2);
+        assert_eq!(toc.list.selected(), 2);
         assert!(toc.search_next(1));
-        assert_eq!(toc.selected, 1);
+        assert_eq!(toc.list.selected(), 1);
         assert!(toc.search_prev(1));
-        assert_eq!(toc.selected, 2);
+        assert_eq!(toc.list.selected(), 2);
+    }
+
+    #[test]
+    fn mark_entries_show_the_enclosing_heading() {
+        let entries = vec![
+            outline("Intro", 0),
+            outline("Methods", 4),
+            outline("Results", 9),
+        ];
+        assert_eq!(outline_heading(&entries, 6).as_deref(), Some("Methods"));
+        assert_eq!(outline_heading(&entries, 9).as_deref(), Some("Results"));
+        assert_eq!(outline_heading(&[outline("Late", 3)], 1), None);
+
+        let entry = MarkEntry {
+            target: MarkTarget::Key('a'),
+            page: 6,
+            local: true,
+            context: outline_heading(&entries, 6),
+        };
+        assert_eq!(entry.text(), "'a (p7)  Methods");
+        let line = format_mark_line(&entry, true, false, 40);
+        assert_eq!(line.trim_end(), ">  'a (p7)  Methods");
     }
 
     #[test]
@@ -2439,534 +6747,549 @@ mod tests {
         let tokens = tokenize_command("mark 'foo\\'bar'");
         assert_eq!(tokens, vec!["mark", "foo'bar"]);
     }
-}
-
-fn init_logging(project_dirs: &ProjectDirs) -> Result<WorkerGuard> {
-    let log_dir = project_dirs.data_local_dir().join("logs");
-    fs::create_dir_all(&log_dir)?;
-
-    let file_appender = tracing_appender::rolling::never(log_dir, "termpdf.log");
-    let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
-    let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-
-    let file_layer = tracing_subscriber::fmt::layer()
-        .with_ansi(false)
-        .with_writer(file_writer);
-    let console_layer = tracing_subscriber::fmt::layer();
-
-    tracing_subscriber::registry()
-        .with(env_filter)
-        .with(file_layer)
-        .with(console_layer)
-        .try_init()
-        .map_err(|err| anyhow!(err))?;
-
-    Ok(guard)
-}
-
-fn compute_scaled_dimensions(
-    image: &RenderImage,
-    effective_pixel_width: f32,
-    effective_pixel_height: f32,
-    available_cols: u32,
-    available_rows: u32,
-    total_cols: u32,
-    total_rows: u32,
-    pixel_width: u32,
-    pixel_height: u32,
-) -> (u32, u32) {
-    let mut draw_cols = available_cols.max(1);
-    let mut draw_rows = available_rows.max(1);
-
-    if image.width == 0 || image.height == 0 {
-        return (draw_cols, draw_rows);
-    }
-
-    if pixel_width > 0
-        && pixel_height > 0
-        && total_cols > 0
-        && total_rows > 0
-        && effective_pixel_width.is_finite()
-        && effective_pixel_height.is_finite()
-        && effective_pixel_width > 0.0
-        && effective_pixel_height > 0.0
-    {
-        let cell_width = pixel_width as f32 / total_cols as f32;
-        let cell_height = pixel_height as f32 / total_rows as f32;
-
-        if cell_width > 0.0 && cell_height > 0.0 {
-            let mut cols = (effective_pixel_width / cell_width).round().max(1.0);
-            let mut rows = (effective_pixel_height / cell_height).round().max(1.0);
-
-            if cols > available_cols as f32 {
-                cols = available_cols as f32;
-            }
-            if rows > available_rows as f32 {
-                rows = available_rows as f32;
-            }
-
-            draw_cols = cols as u32;
-            draw_rows = rows as u32;
-        }
-    } else {
-        let ratio = if effective_pixel_height > 0.0 {
-            effective_pixel_width / effective_pixel_height
-        } else {
-            image.width as f32 / image.height as f32
-        };
-        if ratio.is_finite() && ratio > 0.0 {
-            let mut cols = available_cols as f32;
-            let mut rows = (cols / ratio).round().max(1.0);
-
-            if rows > available_rows as f32 {
-                rows = available_rows as f32;
-                cols = (rows * ratio).round().max(1.0);
-            }
-
-            draw_cols = cols.min(available_cols as f32) as u32;
-            draw_rows = rows.min(available_rows as f32) as u32;
-        }
-    }
-
-    draw_cols = draw_cols.max(1).min(available_cols);
-    draw_rows = draw_rows.max(1).min(available_rows);
-
-    (draw_cols, draw_rows)
-}
-
-fn compute_viewport_origin(total: u32, viewport: u32, fraction: f32) -> u32 {
-    if viewport >= total || total == 0 {
-        return 0;
-    }
-    let max_offset = total - viewport;
-    if max_offset == 0 {
-        return 0;
-    }
-    let clamped = fraction.clamp(0.0, 1.0);
-    let raw = (max_offset as f32 * clamped).round();
-    raw.max(0.0).min(max_offset as f32) as u32
-}
-
-fn map_visual_motion(motion: TtyVisualMotion) -> SelectionMotion {
-    match motion {
-        TtyVisualMotion::Left => SelectionMotion::Left,
-        TtyVisualMotion::Right => SelectionMotion::Right,
-        TtyVisualMotion::Up => SelectionMotion::Up,
-        TtyVisualMotion::Down => SelectionMotion::Down,
-        TtyVisualMotion::WordForward => SelectionMotion::WordForward,
-        TtyVisualMotion::WordBackward => SelectionMotion::WordBackward,
-        TtyVisualMotion::LineStart => SelectionMotion::LineStart,
-        TtyVisualMotion::LineEnd => SelectionMotion::LineEnd,
-        TtyVisualMotion::DocumentStart => SelectionMotion::DocumentStart,
-        TtyVisualMotion::DocumentEnd => SelectionMotion::DocumentEnd,
-        TtyVisualMotion::PageForward => SelectionMotion::PageForward,
-        TtyVisualMotion::PageBackward => SelectionMotion::PageBackward,
-    }
-}
-
-fn copy_text_to_clipboard(text: &str) -> Result<()> {
-    let mut clipboard = Clipboard::new().context("clipboard unavailable")?;
-    clipboard
-        .set_text(text.to_string())
-        .context("failed to copy selection")
-}
-
-fn crop_render_image(
-    image: &RenderImage,
-    origin_x: u32,
-    origin_y: u32,
-    width: u32,
-    height: u32,
-) -> RenderImage {
-    if image.width == 0 || image.height == 0 {
-        return RenderImage {
-            width: 0,
-            height: 0,
-            pixels: Vec::new(),
-        };
-    }
-
-    let width = width.min(image.width).max(1);
-    let height = height.min(image.height).max(1);
-    let max_origin_x = image.width.saturating_sub(width);
-    let max_origin_y = image.height.saturating_sub(height);
-    let origin_x = origin_x.min(max_origin_x);
-    let origin_y = origin_y.min(max_origin_y);
-
-    let stride = image.width as usize * 4;
-    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
-
-    for row in 0..height {
-        let src_y = origin_y + row;
-        let start = src_y as usize * stride + origin_x as usize * 4;
-        let end = start + width as usize * 4;
-        pixels.extend_from_slice(&image.pixels[start..end]);
-    }
 
-    RenderImage {
-        width,
-        height,
-        pixels,
+    #[test]
+    fn tokenize_command_joins_quotes_inside_a_token() {
+        let tokens = tokenize_command("set quote-markdown='> %s (%t)' so=2 ''");
+        assert_eq!(tokens, vec!["set", "quote-markdown=> %s (%t)", "so=2", ""]);
     }
-}
 
-struct HighlightGeometry {
-    base_width: u32,
-    base_height: u32,
-    crop: Option<CropRegion>,
-}
+    #[test]
+    fn command_history_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(COMMAND_HISTORY_NAME);
+        assert!(load_command_history(&path).unwrap().is_empty());
 
-impl HighlightGeometry {
-    fn new(base_width: u32, base_height: u32) -> Self {
-        Self {
-            base_width,
-            base_height,
-            crop: None,
-        }
-    }
+        let mut mapper = EventMapper::new();
+        mapper.push_command_history("set so=3");
+        mapper.push_command_history("mark 'a b'");
+        save_command_history(&path, mapper.command_history()).unwrap();
 
-    fn set_base(&mut self, width: u32, height: u32) {
-        self.base_width = width.max(1);
-        self.base_height = height.max(1);
+        let mut restarted = EventMapper::new();
+        restarted.set_command_history(load_command_history(&path).unwrap());
+        assert_eq!(restarted.command_history(), ["set so=3", "mark 'a b'"]);
     }
 
-    fn set_crop(&mut self, offset_x: u32, offset_y: u32, width: u32, height: u32) {
-        self.crop = Some(CropRegion {
-            offset_x,
-            offset_y,
-            width,
-            height,
-        });
+    #[test]
+    fn quote_templates_can_be_set() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        assert_eq!(
+            apply_set_option(&mut session, "quote-latex", Some("\\enquote{%s}")).unwrap(),
+            "quote-latex=\\enquote{%s}"
+        );
+        assert_eq!(session.quote_templates().latex, "\\enquote{%s}");
+        assert_eq!(
+            session.quote_templates().markdown,
+            QuoteTemplates::default().markdown
+        );
+        assert_eq!(
+            apply_set_option(&mut session, "quote-latex?", Some("ignored")).unwrap(),
+            "quote-latex=\\enquote{%s}"
+        );
     }
 
-    fn clear_crop(&mut self) {
-        self.crop = None;
+    #[test]
+    fn first_page_number_needs_a_document() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        assert!(apply_set_option(&mut session, "first-page-number", Some("13")).is_err());
+        assert!(apply_set_option(&mut session, "nofirst-page-number", None).is_err());
     }
-}
-
-#[derive(Clone, Copy)]
-struct CropRegion {
-    offset_x: u32,
-    offset_y: u32,
-    width: u32,
-    height: u32,
-}
-
-#[derive(Clone, Copy)]
-struct PixelRect {
-    x0: u32,
-    y0: u32,
-    x1: u32,
-    y1: u32,
-}
-
-struct HighlightPalette {
-    current_fill: [u8; 3],
-    current_alpha: f32,
-    current_stroke: [u8; 3],
-    other_stroke: [u8; 3],
-}
-
-const SEARCH_HIGHLIGHT_PALETTE: HighlightPalette = HighlightPalette {
-    current_fill: [255, 235, 0],
-    current_alpha: 0.35,
-    current_stroke: [255, 235, 0],
-    other_stroke: [255, 200, 0],
-};
 
-const SELECTION_HIGHLIGHT_PALETTE: HighlightPalette = HighlightPalette {
-    current_fill: [0, 200, 255],
-    current_alpha: 0.35,
-    current_stroke: [0, 200, 255],
-    other_stroke: [0, 150, 220],
-};
+    #[test]
+    fn set_option_updates_scrolloff() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        assert_eq!(
+            apply_set_option(&mut session, "scrolloff", Some("3")).unwrap(),
+            "scrolloff=3"
+        );
+        assert_eq!(session.scrolloff(), 3);
+        assert_eq!(
+            apply_set_option(&mut session, "so?", None).unwrap(),
+            "scrolloff=3"
+        );
+        assert!(apply_set_option(&mut session, "so", Some("abc")).is_err());
+        assert!(apply_set_option(&mut session, "bogus", Some("1")).is_err());
+        assert!(apply_set_option(&mut session, "dark", Some("on")).is_err());
 
-const CURSOR_HIGHLIGHT_PALETTE: HighlightPalette = HighlightPalette {
-    current_fill: [180, 180, 255],
-    current_alpha: 0.25,
-    current_stroke: [180, 180, 255],
-    other_stroke: [180, 180, 255],
-};
+        assert_eq!(
+            apply_set_option(&mut session, "follow", None).unwrap(),
+            "follow"
+        );
+        assert!(session.follow());
+        assert_eq!(
+            apply_set_option(&mut session, "jumptochange", None).unwrap(),
+            "jumptochange"
+        );
+        assert!(session.jump_to_change());
+        assert_eq!(
+            apply_set_option(&mut session, "nojumptochange", None).unwrap(),
+            "nojumptochange"
+        );
+        assert_eq!(
+            apply_set_option(&mut session, "follow!", None).unwrap(),
+            "nofollow"
+        );
+        assert_eq!(
+            apply_set_option(&mut session, "copy-reflow", None).unwrap(),
+            "copy-reflow"
+        );
+        assert!(session.copy_reflow());
+        assert_eq!(
+            apply_set_option(&mut session, "nocopyreflow", None).unwrap(),
+            "nocopy-reflow"
+        );
+        assert!(session.progressive());
+        assert_eq!(
+            apply_set_option(&mut session, "noprogressive", None).unwrap(),
+            "noprogressive"
+        );
+        assert!(!session.progressive());
+    }
 
-fn apply_highlights(
-    image: &mut RenderImage,
-    highlights: &Highlights,
-    geom: &HighlightGeometry,
-    palette: &HighlightPalette,
-) {
-    if image.width == 0 || image.height == 0 {
-        return;
+    #[test]
+    fn form_fields_are_described_for_the_status_line() {
+        assert_eq!(
+            describe_form_field(
+                &Some("name".into()),
+                &FormFieldKind::Text {
+                    value: "Ada".into()
+                }
+            ),
+            "name = \"Ada\""
+        );
+        assert_eq!(
+            describe_form_field(&None, &FormFieldKind::Checkbox { checked: true }),
+            "<unnamed> [x]"
+        );
     }
 
-    if highlights.is_empty() {
-        return;
+    #[test]
+    fn links_window_filters_and_keeps_selection() {
+        let uri = |page, uri: &str| UriLink {
+            page,
+            uri: uri.to_string(),
+        };
+        let mut window = LinksWindow::new(vec![
+            uri(0, "https://arxiv.org/abs/1"),
+            uri(1, "https://example.com"),
+            uri(2, "https://arxiv.org/abs/2"),
+        ]);
+        assert!(window.list.select_last());
+        window.set_filter("ARXIV");
+        assert_eq!(window.visible_entries().count(), 2);
+        assert_eq!(window.selected_entry().map(|entry| entry.page), Some(2));
+        assert_eq!(window.title(), "Links (2/3) /ARXIV");
+
+        window.set_filter("nothing");
+        assert!(window.selected_entry().is_none());
+        assert!(!window.list.move_selection(1));
+        window.set_filter("");
+        assert_eq!(window.visible_entries().count(), 3);
     }
 
-    let other_rects: Vec<PixelRect> = highlights
-        .others
-        .iter()
-        .filter_map(|rect| normalized_to_pixel_rect(*rect, geom))
-        .collect();
-    let current_rects: Vec<PixelRect> = highlights
-        .current
-        .iter()
-        .filter_map(|rect| normalized_to_pixel_rect(*rect, geom))
-        .collect();
+    #[test]
+    fn reflow_lines_separate_paragraphs() {
+        let paragraphs = vec![
+            "Scanned books are hard to read".to_string(),
+            "Second".to_string(),
+        ];
+        assert_eq!(
+            reflow_lines(&paragraphs, 16),
+            vec!["Scanned books", "are hard to read", "", "Second"]
+        );
+    }
 
-    for rect in other_rects {
-        stroke_rect(image, rect, palette.other_stroke);
+    #[test]
+    fn export_scale_accepts_factor_or_dpi() {
+        assert_eq!(parse_export_scale(None).unwrap(), EXPORT_DEFAULT_SCALE);
+        assert_eq!(parse_export_scale(Some("3")).unwrap(), 3.0);
+        assert_eq!(parse_export_scale(Some("144dpi")).unwrap(), 2.0);
+        assert!(parse_export_scale(Some("0")).is_err());
+        assert!(parse_export_scale(Some("fast")).is_err());
     }
-    for rect in current_rects {
-        fill_rect(image, rect, palette.current_fill, palette.current_alpha);
-        stroke_rect(image, rect, palette.current_stroke);
+
+    #[test]
+    fn page_ranges_are_one_based_and_inclusive() {
+        assert_eq!(parse_page_range("10-25").unwrap(), 9..=24);
+        assert_eq!(parse_page_range("7").unwrap(), 6..=6);
+        assert!(parse_page_range("0-3").is_err());
+        assert!(parse_page_range("5-2").is_err());
+        assert!(parse_page_range("a-b").is_err());
     }
-}
 
-fn open_external_link(target: &ExternalLink) -> Result<()> {
-    match target {
-        ExternalLink::Url(uri) => open_uri(uri),
-        ExternalLink::File(path) => open_path(path),
+    #[test]
+    fn highlight_strokes_follow_render_scale() {
+        let style = HighlightStyle::default();
+        let reference = HighlightStroke::for_scale(style, HIGHLIGHT_REFERENCE_SCALE);
+        assert_eq!(reference.thickness, 2);
+        assert_eq!(reference.opacity, 1.0);
+        assert_eq!(HighlightStroke::for_scale(style, 6.0).thickness, 6);
+        assert_eq!(HighlightStroke::for_scale(style, 0.5).thickness, 1);
+        assert!(HighlightStroke::for_scale(style, 8.0).opacity < 1.0);
+        assert!(HighlightStroke::for_scale(style, 0.5).opacity > 1.0);
+
+        let no_outline = HighlightStyle {
+            stroke_width: 0.0,
+            ..style
+        };
+        let stroke = HighlightStroke::for_scale(no_outline, 4.0);
+        assert_eq!(stroke.thickness, 0);
+        let mut image = RenderImage::placeholder(12, 12);
+        let before = image.pixels.clone();
+        let rect = PixelRect {
+            x0: 1,
+            y0: 1,
+            x1: 11,
+            y1: 11,
+        };
+        stroke_rect(&mut image, rect, [255, 0, 0], stroke.thickness);
+        assert_eq!(image.pixels, before);
+        stroke_rect(&mut image, rect, [255, 0, 0], 3);
+        let pixel = |x: usize, y: usize| &image.pixels[(y * 12 + x) * 4..][..3];
+        assert_eq!(pixel(5, 1), &[255, 0, 0]);
+        assert_eq!(pixel(5, 3), &[255, 0, 0]);
+        assert_ne!(pixel(5, 4), &[255, 0, 0]);
+        assert_eq!(pixel(5, 8), &[255, 0, 0]);
     }
-}
 
-fn open_uri(uri: &str) -> Result<()> {
-    if let Ok(url) = Url::parse(uri) {
-        if url.scheme() == "file" {
-            if let Ok(path) = url.to_file_path() {
-                return open_path(&path);
-            }
+    #[test]
+    fn highlight_layer_composites_like_painting_the_page() {
+        let rect = PixelRect {
+            x0: 2,
+            y0: 2,
+            x1: 8,
+            y1: 8,
+        };
+        let paint = |image: &mut RenderImage| {
+            fill_rect(image, rect, [255, 200, 0], 0.35);
+            fill_rect(image, rect, [0, 0, 255], 0.2);
+            stroke_rect(image, rect, [255, 0, 0], 1);
+        };
+        let mut direct = RenderImage::placeholder(10, 10);
+        let mut layered = direct.clone();
+        paint(&mut direct);
+
+        let mut layer = RenderImage {
+            width: 10,
+            height: 10,
+            pixels: vec![0; 400],
+        };
+        paint(&mut layer);
+        assert_eq!(layer.pixels[3], 0);
+        composite_layer(&mut layered, &layer);
+        for (a, b) in direct.pixels.iter().zip(&layered.pixels) {
+            assert!(a.abs_diff(*b) <= 1, "{a} vs {b}");
         }
     }
-    spawn_open_command(OsStr::new(uri))
-}
 
-fn open_path(path: &Path) -> Result<()> {
-    if !path.exists() {
-        return Err(anyhow!("link target {:?} does not exist", path));
+    #[test]
+    fn step_sizes_can_be_set() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        assert_eq!(
+            apply_set_option(&mut session, "pan-step", Some("0.05")).unwrap(),
+            "pan-step=0.05"
+        );
+        assert_eq!(
+            apply_set_option(&mut session, "zoomstep", Some("0.25")).unwrap(),
+            "zoom-step=0.25"
+        );
+        assert_eq!(
+            session.step_sizes(),
+            StepSizes {
+                pan: 0.05,
+                zoom: 0.25
+            }
+        );
+        assert!(apply_set_option(&mut session, "pan-step", Some("0")).is_err());
+        assert!(apply_set_option(&mut session, "zoom-step", Some("1")).is_err());
+        assert_eq!(
+            apply_set_option(&mut session, "pan-step?", None).unwrap(),
+            "pan-step=0.05"
+        );
     }
-    spawn_open_command(path.as_os_str())
-}
 
-fn spawn_open_command(target: &OsStr) -> Result<()> {
-    #[cfg(target_os = "windows")]
-    {
-        let status = ProcessCommand::new("cmd")
-            .arg("/C")
-            .arg("start")
-            .arg("")
-            .arg(target)
-            .status()
-            .with_context(|| format!("failed to spawn open command for {:?}", target))?;
-        if !status.success() {
-            return Err(anyhow!(
-                "open command exited with status {:?}",
-                status.code()
-            ));
-        }
-        Ok(())
+    #[test]
+    fn prefetch_options_can_be_set() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        assert_eq!(
+            apply_set_option(&mut session, "prefetch", Some("4")).unwrap(),
+            "prefetch=4"
+        );
+        assert_eq!(
+            apply_set_option(&mut session, "noprefetch", None).unwrap(),
+            "noprefetch"
+        );
+        assert_eq!(
+            apply_set_option(&mut session, "prefetch!", None).unwrap(),
+            "prefetch=4"
+        );
+        assert_eq!(
+            apply_set_option(&mut session, "prefetchdir", Some("forward")).unwrap(),
+            "prefetchdir=forward"
+        );
+        assert_eq!(session.prefetch().direction, PrefetchDirection::Forward);
+        assert!(apply_set_option(&mut session, "prefetchdir", Some("up")).is_err());
     }
-    #[cfg(not(target_os = "windows"))]
-    {
-        let status = ProcessCommand::new(OPEN_COMMAND)
-            .arg(target)
-            .status()
-            .with_context(|| format!("failed to spawn '{}' for {:?}", OPEN_COMMAND, target))?;
-        if !status.success() {
-            return Err(anyhow!(
-                "'{}' exited with status {:?}",
-                OPEN_COMMAND,
-                status.code()
-            ));
+
+    #[test]
+    fn mem_available_is_read_from_meminfo() {
+        let meminfo = "MemTotal:       16318412 kB\nMemFree:         1203400 kB\nMemAvailable:    8123456 kB\n";
+        assert_eq!(parse_mem_available(meminfo), Some(8123456 * 1024));
+        assert_eq!(parse_mem_available("MemTotal: 1 kB\n"), None);
+    }
+
+    #[test]
+    fn zoom_accepts_relative_and_absolute_values() {
+        match parse_zoom("+25%").unwrap() {
+            Command::ScaleBy { factor } => assert!((factor - 1.25).abs() < 1e-6),
+            other => panic!("unexpected command: {:?}", other),
         }
-        Ok(())
+        match parse_zoom("-25%").unwrap() {
+            Command::ScaleBy { factor } => assert!((factor - 0.75).abs() < 1e-6),
+            other => panic!("unexpected command: {:?}", other),
+        }
+        match parse_zoom("150%").unwrap() {
+            Command::SetScale { scale } => assert!((scale - 1.5).abs() < 1e-6),
+            other => panic!("unexpected command: {:?}", other),
+        }
+        match parse_zoom("2").unwrap() {
+            Command::SetScale { scale } => assert!((scale - 2.0).abs() < 1e-6),
+            other => panic!("unexpected command: {:?}", other),
+        }
+        assert!(parse_zoom("-100%").is_err());
+        assert!(parse_zoom("+2").is_err());
+        assert!(parse_zoom("big").is_err());
     }
-}
 
-fn normalized_to_pixel_rect(rect: NormalizedRect, geom: &HighlightGeometry) -> Option<PixelRect> {
-    let width_f = geom.base_width as f32;
-    let height_f = geom.base_height as f32;
-    if width_f <= 0.0 || height_f <= 0.0 {
-        return None;
-    }
-
-    let mut x0 = (rect.left * width_f).floor() as i32;
-    let mut x1 = (rect.right * width_f).ceil() as i32;
-    let mut y0 = (rect.top * height_f).floor() as i32;
-    let mut y1 = (rect.bottom * height_f).ceil() as i32;
-
-    let max_x = geom.base_width as i32;
-    let max_y = geom.base_height as i32;
-    x0 = x0.clamp(0, max_x);
-    x1 = x1.clamp(0, max_x);
-    y0 = y0.clamp(0, max_y);
-    y1 = y1.clamp(0, max_y);
-
-    if let Some(crop) = &geom.crop {
-        x0 -= crop.offset_x as i32;
-        x1 -= crop.offset_x as i32;
-        y0 -= crop.offset_y as i32;
-        y1 -= crop.offset_y as i32;
-
-        let crop_max_x = crop.width as i32;
-        let crop_max_y = crop.height as i32;
-        x0 = x0.clamp(0, crop_max_x);
-        x1 = x1.clamp(0, crop_max_x);
-        y0 = y0.clamp(0, crop_max_y);
-        y1 = y1.clamp(0, crop_max_y);
-    }
-
-    if x1 - x0 <= 0 || y1 - y0 <= 0 {
-        return None;
-    }
-
-    Some(PixelRect {
-        x0: x0 as u32,
-        y0: y0 as u32,
-        x1: x1 as u32,
-        y1: y1 as u32,
-    })
-}
+    #[test]
+    fn recent_choice_defaults_to_the_newest_document() {
+        assert_eq!(parse_recent_choice("\n", 3).unwrap(), Some(0));
+        assert_eq!(parse_recent_choice(" 3 \n", 3).unwrap(), Some(2));
+        assert_eq!(parse_recent_choice("q\n", 3).unwrap(), None);
+        assert!(parse_recent_choice("4", 3).is_err());
+        assert!(parse_recent_choice("0", 3).is_err());
+        assert_eq!(format_age(5), "just now");
+        assert_eq!(format_age(60), "1 minute ago");
+        assert_eq!(format_age(3 * 3600 + 10), "3 hours ago");
+        assert_eq!(format_age(2 * 86400), "2 days ago");
+    }
 
-fn fill_rect(image: &mut RenderImage, rect: PixelRect, color: [u8; 3], alpha: f32) {
-    if rect.x0 >= rect.x1 || rect.y0 >= rect.y1 {
-        return;
+    #[test]
+    fn highlight_options_can_be_set() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        assert_eq!(
+            apply_set_option(&mut session, "highlightwidth", Some("1.5")).unwrap(),
+            "highlightwidth=1.5"
+        );
+        assert_eq!(
+            apply_set_option(&mut session, "hlo", Some("0.5")).unwrap(),
+            "highlightopacity=0.5"
+        );
+        assert_eq!(session.highlight_style().stroke_width, 1.5);
+        assert!(apply_set_option(&mut session, "hlw", Some("-1")).is_err());
     }
-    let width = image.width as usize;
-    let height = image.height as usize;
-    if width == 0 || height == 0 {
-        return;
+
+    #[test]
+    fn write_png_round_trips_pixels() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.png");
+        let image = RenderImage::placeholder(8, 4);
+        write_png(&path, &image, 144.0).unwrap();
+
+        let decoder = png::Decoder::new(fs::File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (8, 4));
+        assert_eq!(pixels, image.pixels);
     }
 
-    let x1 = rect.x1.min(image.width);
-    let y1 = rect.y1.min(image.height);
-    let x0 = rect.x0.min(x1);
-    let y0 = rect.y0.min(y1);
+    #[test]
+    fn export_view_writes_the_last_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("view.png");
+        let args = vec![path.display().to_string()];
+        assert!(export_view(None, &args).is_err());
+
+        let frame = ScreenFrame {
+            image: RenderImage::placeholder(6, 3),
+            render_scale: 2.0,
+            plain: true,
+        };
+        assert!(export_view(Some(&frame), &[]).is_err());
+        let message = export_view(Some(&frame), &args).unwrap();
+        assert!(message.starts_with("Exported 6x3 px view"));
+        let decoder = png::Decoder::new(fs::File::open(&path).unwrap());
+        let info = decoder.read_info().unwrap();
+        assert_eq!((info.info().width, info.info().height), (6, 3));
+    }
 
-    for y in y0..y1 {
-        let row_start = (y as usize) * width * 4;
-        for x in x0..x1 {
-            let idx = row_start + (x as usize) * 4;
-            blend_pixel(&mut image.pixels[idx..idx + 4], color, alpha);
-        }
+    #[test]
+    fn status_messages_expire_on_the_injected_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let mut status_bar = StatusBar::new(clock.clone());
+        status_bar.set_message(StatusMessage::new(
+            "Saved",
+            CommandStatusKind::Info,
+            Some(STATUS_MESSAGE_TTL),
+        ));
+        clock.advance(STATUS_MESSAGE_TTL - Duration::from_millis(1));
+        status_bar.prune_expired();
+        assert!(status_bar.message().is_some());
+        clock.advance(Duration::from_millis(1));
+        status_bar.prune_expired();
+        assert!(status_bar.message().is_none());
+
+        status_bar.set_message(StatusMessage::new("sticky", CommandStatusKind::Info, None));
+        clock.advance(Duration::from_secs(3600));
+        status_bar.prune_expired();
+        assert!(status_bar.message().is_some());
     }
-}
 
-fn blend_pixel(pixel: &mut [u8], color: [u8; 3], alpha: f32) {
-    let alpha = alpha.clamp(0.0, 1.0);
-    let inv = 1.0 - alpha;
-    pixel[0] = ((pixel[0] as f32 * inv) + (color[0] as f32 * alpha))
-        .round()
-        .clamp(0.0, 255.0) as u8;
-    pixel[1] = ((pixel[1] as f32 * inv) + (color[1] as f32 * alpha))
-        .round()
-        .clamp(0.0, 255.0) as u8;
-    pixel[2] = ((pixel[2] as f32 * inv) + (color[2] as f32 * alpha))
-        .round()
-        .clamp(0.0, 255.0) as u8;
-}
-
-fn stroke_rect(image: &mut RenderImage, rect: PixelRect, color: [u8; 3]) {
-    if rect.x0 >= rect.x1 || rect.y0 >= rect.y1 {
-        return;
+    #[test]
+    fn pages_are_rendered_at_the_pixels_of_their_cells() {
+        let letter = PageSize {
+            width: 612.0,
+            height: 792.0,
+        };
+        // 100x40 cells of 10x20 pixels: the height runs out first.
+        let desired = (1000.0, 800.0);
+        let scale = exact_render_scale(letter, desired, false, 1.0);
+        assert!((letter.height * scale - 800.0).abs() < 0.01);
+        let width_scale = exact_render_scale(letter, desired, true, 1.0);
+        assert!((letter.width * width_scale - 1000.0).abs() < 0.01);
+        // Zooming renders more pixels instead of stretching the fit.
+        assert!((exact_render_scale(letter, desired, false, 2.0) - scale * 2.0).abs() < 1e-4);
+        assert_eq!(exact_render_scale(letter, desired, false, 0.5), scale);
     }
-    let width = image.width as usize;
-    if width == 0 {
-        return;
+
+    #[test]
+    fn reflowed_text_reports_the_share_on_screen() {
+        let geometry = reflow_geometry(120, 30, 80);
+        assert_eq!(geometry.visible_height, 0.25);
+        assert_eq!((geometry.cols, geometry.rows), (80, 30));
+        assert_eq!(reflow_geometry(10, 30, 80).visible_height, 1.0);
+        assert_eq!(reflow_geometry(0, 30, 80).visible_height, 1.0);
     }
-    let x0 = rect.x0.min(image.width - 1);
-    let x1 = rect.x1.min(image.width);
-    let y0 = rect.y0.min(image.height - 1);
-    let y1 = rect.y1.min(image.height);
-    let thickness = 2u32;
 
-    for y in y0..y1 {
-        let row_start = (y as usize) * width * 4;
-        for x in x0..(x0 + thickness.min(x1 - x0)) {
-            overwrite_pixel(
-                &mut image.pixels[row_start + (x as usize) * 4..][..4],
-                color,
-            );
-        }
-        if x1 > x0 + thickness {
-            for x in (x1.saturating_sub(thickness))..x1 {
-                overwrite_pixel(
-                    &mut image.pixels[row_start + (x as usize) * 4..][..4],
-                    color,
-                );
-            }
-        }
+    #[test]
+    fn format_bytes_picks_a_readable_unit() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(32 * 1024 * 1024), "32.0 MiB");
     }
 
-    for y in y0..(y0 + thickness.min(y1 - y0)) {
-        let row_start = (y as usize) * width * 4;
-        for x in x0..x1 {
-            overwrite_pixel(
-                &mut image.pixels[row_start + (x as usize) * 4..][..4],
-                color,
-            );
-        }
+    #[test]
+    fn format_duration_shows_the_two_largest_units() {
+        assert_eq!(format_duration(Duration::from_secs(40)), "40s");
+        assert_eq!(format_duration(Duration::from_secs(200)), "3m 20s");
+        assert_eq!(format_duration(Duration::from_secs(7500)), "2h 05m");
     }
-    if y1 > y0 + thickness {
-        for y in (y1.saturating_sub(thickness))..y1 {
-            let row_start = (y as usize) * width * 4;
-            for x in x0..x1 {
-                overwrite_pixel(
-                    &mut image.pixels[row_start + (x as usize) * 4..][..4],
-                    color,
-                );
-            }
-        }
+
+    #[test]
+    fn recovered_worker_panics_leave_the_terminal_alone() {
+        use std::sync::atomic::AtomicUsize;
+
+        let restored = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&restored);
+        restore_on_panic(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        // A worker catching a panic, as the render watchdog does.
+        thread::spawn(|| std::panic::catch_unwind(|| panic!("page crashed")).is_err())
+            .join()
+            .unwrap();
+        assert_eq!(restored.load(Ordering::SeqCst), 0);
+        let _ = std::panic::catch_unwind(|| panic!("main loop crashed"));
+        assert_eq!(restored.load(Ordering::SeqCst), 1);
+        let _ = std::panic::take_hook();
     }
-}
 
-fn overwrite_pixel(pixel: &mut [u8], color: [u8; 3]) {
-    pixel[0] = color[0];
-    pixel[1] = color[1];
-    pixel[2] = color[2];
-}
+    #[test]
+    fn wrap_text_breaks_on_word_boundaries() {
+        assert_eq!(
+            wrap_text("A. Author, Some title, 2020.", 12),
+            vec!["A. Author,", "Some title,", "2020."]
+        );
+        assert!(wrap_text("   ", 10).is_empty());
+    }
 
-fn format_document_status(doc: &DocumentInstance) -> String {
-    let zoom_percent = doc.state.scale * 100.0;
-    let zoom_display = if zoom_percent.is_finite() {
-        format!("{:.0}%", zoom_percent)
-    } else {
-        "—".to_string()
-    };
+    #[test]
+    fn command_prompt_scrolls_by_display_width() {
+        let input = "mark 第一章".to_string();
+        let overlay = CommandOverlay::new(input.clone(), input.len());
+        assert_eq!(overlay.visible_prompt(20), (":mark 第一章".into(), 12));
+        // The cursor after the input still fits, one wide character less.
+        assert_eq!(overlay.visible_prompt(8), ("k 第一章".into(), 8));
 
-    let mut status = format!(
-        "{} — page {}/{} — {}",
-        doc.info
-            .path
-            .file_name()
-            .and_then(|s| s.to_str())
-            .unwrap_or("<unknown>"),
-        doc.state.current_page + 1,
-        doc.info.page_count,
-        zoom_display
-    );
+        let overlay = CommandOverlay::new(input, 0);
+        assert_eq!(overlay.visible_prompt(8), (":mark 第".into(), 1));
+    }
 
-    if let Some(summary) = doc.search_summary() {
-        status.push_str(" — /");
-        status.push_str(&summary.query);
-        if summary.total == 0 {
-            status.push_str(" (no matches)");
-        } else if let Some(index) = summary.current_index {
-            status.push_str(&format!(" ({}/{})", index + 1, summary.total));
-        } else {
-            status.push_str(&format!(" (0/{})", summary.total));
-        }
+    #[test]
+    fn link_prompt_wraps_the_url_and_names_the_handler() {
+        let prompt = LinkPrompt {
+            target: ExternalLink::Url("https://example.com/a".into()),
+            action: LinkAction::Run(vec!["firefox".into()]),
+            scroll: 0,
+            return_mode: InputMode::Link,
+        };
+        let lines = prompt.lines(10);
+        assert_eq!(lines[..3], ["https://ex", "ample.com/", "a"]);
+        assert_eq!(lines[4], t!("link-prompt-handler", command = "firefox"));
+        assert_eq!(lines.last(), Some(&t!("link-prompt-keys")));
     }
 
-    if let Some(summary) = doc.link_summary() {
-        status.push_str(" — link");
-        if summary.total == 0 {
-            status.push_str(" (no links)");
-        } else if let Some(index) = summary.current_index {
-            status.push_str(&format!(" ({}/{})", index + 1, summary.total));
-        } else {
-            status.push_str(&format!(" (0/{})", summary.total));
-        }
+    #[test]
+    fn zoom_rect_takes_one_rect_with_an_area() {
+        let args = |values: &[&str]| values.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let rect = parse_zoom_rect(&args(&["0.1", "0.2", "0.5", "0.6"])).unwrap();
+        assert_eq!((rect.left, rect.bottom), (0.1, 0.6));
+        assert!(parse_zoom_rect(&args(&["0.1", "0.2", "0.5"])).is_err());
+        assert!(parse_zoom_rect(&args(&["0.5", "0.2", "0.1", "0.6"])).is_err());
+        assert!(parse_zoom_rect(&args(&["0.1", "0.2", "0.5", "x"])).is_err());
     }
 
-    status
+    #[test]
+    fn highlight_arguments_use_one_based_pages() {
+        let args: Vec<String> = ["3", "0.1", "0.2", "0.3", "0.4"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (page, rects) = parse_highlight_add(&args).unwrap();
+        assert_eq!(page, 2);
+        assert_eq!(rects.len(), 1);
+        assert_eq!(rects[0].bottom, 0.4);
+
+        assert!(parse_highlight_add(&args[..4]).is_err());
+        assert!(parse_highlight_page(Some(&"0".to_string())).is_err());
+        assert_eq!(
+            parse_highlight_page(Some(&"all".to_string())).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn set_arguments_accept_separate_values() {
+        let args: Vec<String> = ["dark", "on", "so=2", "fit", "width", "nodark"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let pairs = parse_set_arguments(&args);
+        assert_eq!(
+            pairs,
+            vec![
+                ("dark".to_string(), Some("on".to_string())),
+                ("so".to_string(), Some("2".to_string())),
+                ("fit".to_string(), Some("width".to_string())),
+                ("nodark".to_string(), None),
+            ]
+        );
+        assert!(parse_switch("off", true).is_ok_and(|value| !value));
+        assert!(parse_switch("toggle", false).is_ok_and(|value| value));
+        assert_eq!(
+            parse_fit_mode("toggle", FitMode::Page).unwrap(),
+            FitMode::Width
+        );
+    }
 }
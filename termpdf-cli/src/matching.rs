@@ -0,0 +1,163 @@
+use std::ops::Range;
+
+use termpdf_tty::char_width;
+
+/// Precomposed letters by the combining mark they carry, as
+/// `(mark, letters, bases)`: the n-th letter is the n-th base with the
+/// mark on it. Covers Latin (including Vietnamese) and kana, from the
+/// canonical decompositions in the Unicode character database.
+const COMPOSED: &[(char, &str, &str)] = &[
+    ('\u{300}', "ÀÈÌÒÙàèìòùǛǜǸǹḔḕṐṑẀẁ", "AEIOUaeiouÜüNnĒēŌōWw"),
+    ('\u{300}', "ẦầẰằỀềỒồỜờỪừỲỳ", "ÂâĂăÊêÔôƠơƯưYy"),
+    ('\u{301}', "ÁÉÍÓÚÝáéíóúýĆćĹĺŃńŔŕ", "AEIOUYaeiouyCcLlNnRr"),
+    ('\u{301}', "ŚśŹźǗǘǴǵǺǻǼǽǾǿḈḉḖḗḮḯ", "SsZzÜüGgÅåÆæØøÇçĒēÏï"),
+    ('\u{301}', "ḰḱḾḿṌṍṒṓṔṕṸṹẂẃẤấẮắẾế", "KkMmÕõŌōPpŨũWwÂâĂăÊê"),
+    ('\u{301}', "ỐốỚớỨứ", "ÔôƠơƯư"),
+    ('\u{302}', "ÂÊÎÔÛâêîôûĈĉĜĝĤĥĴĵŜŝ", "AEIOUaeiouCcGgHhJjSs"),
+    ('\u{302}', "ŴŵŶŷẐẑẬậỆệỘộ", "WwYyZzẠạẸẹỌọ"),
+    ('\u{303}', "ÃÑÕãñõĨĩŨũṼṽẪẫẴẵẼẽỄễ", "ANOanoIiUuVvÂâĂăEeÊê"),
+    ('\u{303}', "ỖỗỠỡỮữỸỹ", "ÔôƠơƯưYy"),
+    ('\u{308}', "ÄËÏÖÜäëïöüÿŸḦḧṎṏṺṻẄẅ", "AEIOUaeiouyYHhÕõŪūWw"),
+    ('\u{308}', "Ẍẍẗ", "Xxt"),
+    ('\u{30a}', "ÅåŮůẘẙ", "AaUuwy"),
+    ('\u{327}', "ÇçĢģĶķĻļŅņŖŗŞşŢţȨȩḐḑ", "CcGgKkLlNnRrSsTtEeDd"),
+    ('\u{327}', "Ḩḩ", "Hh"),
+    ('\u{304}', "ĀāĒēĪīŌōŪūǕǖǞǟǠǡǢǣǬǭ", "AaEeIiOoUuÜüÄäȦȧÆæǪǫ"),
+    ('\u{304}', "ȪȫȬȭȰȱȲȳḠḡḸḹṜṝ", "ÖöÕõȮȯYyGgḶḷṚṛ"),
+    ('\u{306}', "ĂăĔĕĞğĬĭŎŏŬŭḜḝẶặ", "AaEeGgIiOoUuȨȩẠạ"),
+    ('\u{328}', "ĄąĘęĮįŲųǪǫ", "AaEeIiUuOo"),
+    ('\u{307}', "ĊċĖėĠġİŻżȦȧȮȯḂḃḊḋḞḟḢ", "CcEeGgIZzAaOoBbDdFfH"),
+    ('\u{307}', "ḣṀṁṄṅṖṗṘṙṠṡṤṥṦṧṨṩṪṫẆ", "hMmNnPpRrSsŚśŠšṢṣTtW"),
+    ('\u{307}', "ẇẊẋẎẏẛ", "wXxYyſ"),
+    ('\u{30c}', "ČčĎďĚěĽľŇňŘřŠšŤťŽžǍǎ", "CcDdEeLlNnRrSsTtZzAa"),
+    ('\u{30c}', "ǏǐǑǒǓǔǙǚǦǧǨǩǮǯǰȞȟ", "IiOoUuÜüGgKkƷʒjHh"),
+    ('\u{30b}', "ŐőŰű", "OoUu"),
+    ('\u{31b}', "ƠơƯư", "OoUu"),
+    ('\u{30f}', "ȀȁȄȅȈȉȌȍȐȑȔȕ", "AaEeIiOoRrUu"),
+    ('\u{311}', "ȂȃȆȇȊȋȎȏȒȓȖȗ", "AaEeIiOoRrUu"),
+    ('\u{326}', "ȘșȚț", "SsTt"),
+    ('\u{325}', "Ḁḁ", "Aa"),
+    ('\u{323}', "ḄḅḌḍḤḥḲḳḶḷṂṃṆṇṚṛṢṣṬṭ", "BbDdHhKkLlMmNnRrSsTt"),
+    ('\u{323}', "ṾṿẈẉẒẓẠạẸẹỊịỌọỢợỤụỰự", "VvWwZzAaEeIiOoƠơUuƯư"),
+    ('\u{323}', "Ỵỵ", "Yy"),
+    ('\u{331}', "ḆḇḎḏḴḵḺḻṈṉṞṟṮṯẔẕẖ", "BbDdKkLlNnRrTtZzh"),
+    ('\u{32d}', "ḒḓḘḙḼḽṊṋṰṱṶṷ", "DdEeLlNnTtUu"),
+    ('\u{330}', "ḚḛḬḭṴṵ", "EeIiUu"),
+    ('\u{32e}', "Ḫḫ", "Hh"),
+    ('\u{324}', "Ṳṳ", "Uu"),
+    ('\u{309}', "ẢảẨẩẲẳẺẻỂểỈỉỎỏỔổỞởỦủ", "AaÂâĂăEeÊêIiOoÔôƠơUu"),
+    ('\u{309}', "ỬửỶỷ", "ƯưYy"),
+    (
+        '\u{3099}',
+        "がぎぐげござじずぜぞだぢづでどばびぶべぼ",
+        "かきくけこさしすせそたちつてとはひふへほ",
+    ),
+    (
+        '\u{3099}',
+        "ゔゞガギグゲゴザジズゼゾダヂヅデドバビブ",
+        "うゝカキクケコサシスセソタチツテトハヒフ",
+    ),
+    ('\u{3099}', "ベボヴヷヸヹヺヾ", "ヘホウワヰヱヲヽ"),
+    ('\u{309a}', "ぱぴぷぺぽパピプペポ", "はひふへほハヒフヘホ"),
+];
+
+/// Finds `needle` in `haystack` ignoring case, and returns where it is in
+/// `haystack`. Both are compared in canonical decomposition, so "é" typed
+/// as one character matches an "e" followed by a combining accent, and
+/// fullwidth letters match their ASCII forms. A match covers whole
+/// characters: "stras" is not found in "Straße", nor "cafe" in "café".
+pub fn find_match(haystack: &str, needle: &str) -> Option<Range<usize>> {
+    let needle: Vec<char> = fold(needle).into_iter().map(|(c, _)| c).collect();
+    if needle.is_empty() {
+        return None;
+    }
+    let hay = fold(haystack);
+    if needle.len() > hay.len() {
+        return None;
+    }
+    (0..=hay.len() - needle.len()).find_map(|start| {
+        let end = start + needle.len();
+        let starts_char = start == 0 || hay[start - 1].1 != hay[start].1;
+        let ends_char =
+            end == hay.len() || (hay[end].1 != hay[end - 1].1 && !is_combining_mark(hay[end].0));
+        let found = starts_char
+            && ends_char
+            && hay[start..end]
+                .iter()
+                .zip(&needle)
+                .all(|((c, _), n)| c == n);
+        found.then(|| hay[start].1.start..hay[end - 1].1.end)
+    })
+}
+
+/// Whether `needle` is in `haystack`, compared as in [`find_match`].
+pub fn contains(haystack: &str, needle: &str) -> bool {
+    find_match(haystack, needle).is_some()
+}
+
+/// The characters `text` is compared as, each with the bytes of `text` it
+/// comes from.
+fn fold(text: &str) -> Vec<(char, Range<usize>)> {
+    let mut folded = Vec::with_capacity(text.len());
+    for (offset, c) in text.char_indices() {
+        let source = offset..offset + c.len_utf8();
+        let mut decomposed = Vec::new();
+        decompose(c, &mut decomposed);
+        for c in decomposed {
+            match c {
+                'ß' | 'ẞ' => {
+                    folded.push(('s', source.clone()));
+                    folded.push(('s', source.clone()));
+                }
+                // Fullwidth forms of ASCII.
+                '\u{FF01}'..='\u{FF5E}' => {
+                    let ascii = char::from_u32(c as u32 - 0xFEE0).unwrap_or(c);
+                    folded.push((ascii.to_ascii_lowercase(), source.clone()));
+                }
+                c => folded.extend(c.to_lowercase().map(|c| (c, source.clone()))),
+            }
+        }
+    }
+    folded
+}
+
+fn decompose(c: char, out: &mut Vec<char>) {
+    for (mark, letters, bases) in COMPOSED {
+        if let Some(position) = letters.chars().position(|letter| letter == c) {
+            if let Some(base) = bases.chars().nth(position) {
+                decompose(base, out);
+                out.push(*mark);
+                return;
+            }
+        }
+    }
+    out.push(c);
+}
+
+fn is_combining_mark(c: char) -> bool {
+    char_width(c) == 0 && !c.is_control()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_ignore_case_and_composition() {
+        for (mark, letters, bases) in COMPOSED {
+            assert_eq!(letters.chars().count(), bases.chars().count(), "{mark:?}");
+        }
+        assert_eq!(find_match("Chapter 2", "CHAP"), Some(0..4));
+        assert_eq!(find_match("Élément", "éLÉ"), Some(0..5));
+        // Precomposed in the query, decomposed in the title.
+        assert_eq!(find_match("Cafe\u{301} noir", "café"), Some(0..6));
+        assert_eq!(find_match("Café", "cafe"), None);
+        assert_eq!(find_match("Die Straße", "STRASSE"), Some(4..11));
+        assert_eq!(find_match("Die Straße", "stras"), None);
+        assert_eq!(find_match("Über Größe", "grösse"), Some(6..13));
+        assert_eq!(find_match("第２章 がくせい", "2章"), Some(3..9));
+        assert_eq!(find_match("がくせい", "か"), None);
+        assert_eq!(find_match("か\u{3099}くせい", "がく"), Some(0..9));
+        assert!(!contains("anything", ""));
+    }
+}
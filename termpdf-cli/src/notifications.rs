@@ -0,0 +1,252 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use crossterm::event::Event;
+use termpdf_core::SessionEvent;
+
+/// Kinds of session events that can raise a desktop notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+pub enum NotifyEvent {
+    /// A watched document changed on disk and was reloaded
+    Reload,
+    /// `:saveas` finished writing a copy of the document
+    Save,
+    /// `:extract` or a PNG export finished writing its file
+    Export,
+    /// A search over the whole document completed
+    Search,
+}
+
+/// Searches taking at least this long notify even while the terminal has
+/// focus; everything else only notifies while it does not.
+const LONG_SEARCH: Duration = Duration::from_secs(5);
+
+static ENABLED: OnceLock<HashSet<NotifyEvent>> = OnceLock::new();
+
+/// Whether the terminal has focus, as its focus reports say. Assumed until
+/// the first report, since termpdf was just started from it.
+static FOCUSED: AtomicBool = AtomicBool::new(true);
+
+/// Selects which events notify; called once at startup from `--notify`.
+pub fn init(events: &[NotifyEvent]) -> Result<()> {
+    if !events.is_empty() && !cfg!(feature = "notifications") {
+        anyhow::bail!("--notify requires termpdf to be built with `--features notifications`");
+    }
+    let _ = ENABLED.set(events.iter().copied().collect());
+    Ok(())
+}
+
+/// Follows the terminal's focus from its focus reports.
+pub fn observe(event: &Event) {
+    match event {
+        Event::FocusGained => FOCUSED.store(true, Ordering::Relaxed),
+        Event::FocusLost => FOCUSED.store(false, Ordering::Relaxed),
+        _ => {}
+    }
+}
+
+pub fn dispatch(event: &SessionEvent, document: Option<&str>) {
+    if let Some(notification) = describe(event, document) {
+        notify(notification);
+    }
+}
+
+/// Notifies that a PNG export finished writing `path`.
+pub fn exported(path: &Path) {
+    notify(Notification {
+        kind: NotifyEvent::Export,
+        summary: "Export finished".to_string(),
+        body: path.display().to_string(),
+        long_running: false,
+    });
+}
+
+fn notify(notification: Notification) {
+    let Some(enabled) = ENABLED.get() else {
+        return;
+    };
+    if enabled.contains(&notification.kind)
+        && (notification.long_running || !FOCUSED.load(Ordering::Relaxed))
+    {
+        send(notification);
+    }
+}
+
+/// A desktop notification, in plain text.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "notifications"), allow(dead_code))]
+struct Notification {
+    kind: NotifyEvent,
+    summary: String,
+    body: String,
+    /// Worth showing even while the terminal has focus.
+    long_running: bool,
+}
+
+fn describe(event: &SessionEvent, document: Option<&str>) -> Option<Notification> {
+    let name = document.unwrap_or("document");
+    let (kind, summary, body, long_running) = match event {
+        SessionEvent::DocumentReloaded {
+            previous_page_count,
+            page_count,
+            changed_pages,
+            ..
+        } => {
+            let mut pages = match page_count.cmp(previous_page_count) {
+                std::cmp::Ordering::Equal => format!("{} pages", page_count),
+                _ => format!("{} pages, was {}", page_count, previous_page_count),
+            };
+            if let Some(changed) = changed_pages {
+                pages.push_str(&format!("; {} changed", changed));
+            }
+            (
+                NotifyEvent::Reload,
+                "Document reloaded",
+                format!("{} ({})", name, pages),
+                false,
+            )
+        }
+        SessionEvent::DocumentSaved { path } => (
+            NotifyEvent::Save,
+            "Save finished",
+            path.display().to_string(),
+            false,
+        ),
+        SessionEvent::PagesExtracted { path, pages } => (
+            NotifyEvent::Export,
+            "Export finished",
+            format!("{} page(s) to {}", pages, path.display()),
+            false,
+        ),
+        SessionEvent::SearchCompleted {
+            query,
+            matches,
+            elapsed,
+            ..
+        } => (
+            NotifyEvent::Search,
+            "Search finished",
+            format!("{} match(es) for \"{}\" in {}", matches, query, name),
+            *elapsed >= LONG_SEARCH,
+        ),
+        _ => return None,
+    };
+    Some(Notification {
+        kind,
+        summary: summary.to_string(),
+        body,
+        long_running,
+    })
+}
+
+/// Escapes `text` for notification servers that read the body as
+/// freedesktop markup, a small subset of HTML.
+#[cfg_attr(
+    any(not(feature = "notifications"), target_os = "macos", windows),
+    allow(dead_code)
+)]
+fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(feature = "notifications")]
+fn send(notification: Notification) {
+    let body = if cfg!(any(target_os = "macos", windows)) {
+        notification.body
+    } else {
+        escape_markup(&notification.body)
+    };
+    // Showing a notification talks to the desktop, which may be slow.
+    std::thread::spawn(move || {
+        let shown = notify_rust::Notification::new()
+            .appname("termpdf")
+            .summary(&format!("termpdf: {}", notification.summary))
+            .body(&body)
+            .show();
+        if let Err(err) = shown {
+            tracing::warn!(?err, "failed to send desktop notification");
+        }
+    });
+}
+
+#[cfg(not(feature = "notifications"))]
+fn send(_notification: Notification) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use termpdf_core::document_id_for_path;
+
+    #[test]
+    fn reload_notification_mentions_page_count_change() {
+        let id = document_id_for_path(&PathBuf::from("/tmp/thesis.pdf"));
+        let event = SessionEvent::DocumentReloaded {
+            id,
+            previous_page_count: 40,
+            page_count: 42,
+            changed_pages: None,
+        };
+        let notification = describe(&event, Some("thesis.pdf")).unwrap();
+        assert_eq!(notification.kind, NotifyEvent::Reload);
+        assert_eq!(notification.summary, "Document reloaded");
+        assert_eq!(notification.body, "thesis.pdf (42 pages, was 40)");
+        let event = SessionEvent::DocumentReloaded {
+            id,
+            previous_page_count: 42,
+            page_count: 42,
+            changed_pages: Some(3),
+        };
+        let notification = describe(&event, Some("thesis.pdf")).unwrap();
+        assert_eq!(notification.body, "thesis.pdf (42 pages; 3 changed)");
+        assert!(describe(&SessionEvent::RedrawNeeded(id), None).is_none());
+    }
+
+    #[test]
+    fn exports_notify_and_only_long_searches_notify_while_focused() {
+        let event = SessionEvent::PagesExtracted {
+            path: PathBuf::from("/tmp/chapter.pdf"),
+            pages: 12,
+        };
+        let notification = describe(&event, None).unwrap();
+        assert_eq!(notification.kind, NotifyEvent::Export);
+        assert_eq!(notification.body, "12 page(s) to /tmp/chapter.pdf");
+        assert!(!notification.long_running);
+
+        let search = |elapsed| SessionEvent::SearchCompleted {
+            id: document_id_for_path(&PathBuf::from("/tmp/thesis.pdf")),
+            query: "lemma".to_string(),
+            matches: 4,
+            elapsed,
+        };
+        let quick = describe(&search(Duration::from_millis(300)), None).unwrap();
+        assert!(!quick.long_running);
+        let slow = describe(&search(Duration::from_secs(12)), None).unwrap();
+        assert!(slow.long_running);
+    }
+
+    #[test]
+    fn notification_bodies_are_escaped_as_markup() {
+        assert_eq!(escape_markup("plain.pdf"), "plain.pdf");
+        assert_eq!(
+            escape_markup("R&D <draft> \"v2\" it's.pdf"),
+            "R&amp;D &lt;draft&gt; &quot;v2&quot; it&apos;s.pdf"
+        );
+    }
+}
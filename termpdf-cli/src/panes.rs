@@ -0,0 +1,239 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use termpdf_core::{Command, DocumentId, Session};
+
+use crate::i18n::t;
+
+/// How a split divides the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    /// Side by side (`:vsplit`).
+    Vertical,
+    /// One above the other (`:split`).
+    Horizontal,
+}
+
+/// Cells of the screen a page view is drawn into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaneArea {
+    pub col: u32,
+    pub row: u32,
+    pub cols: u32,
+    pub rows: u32,
+}
+
+/// A page view on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pane {
+    /// Where the page goes.
+    pub area: PaneArea,
+    /// Row above the page naming the document, when the screen is split.
+    pub title_row: Option<u32>,
+    /// Whether keys act on this pane; it shows the active document.
+    pub focused: bool,
+    /// Document shown by an unfocused pane.
+    pub doc: Option<DocumentId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Split {
+    orientation: Orientation,
+    /// Document in the pane without focus.
+    other: DocumentId,
+    /// Whether the focused pane is the left or top one.
+    focus_first: bool,
+}
+
+/// The split layout: one page view, or two with the focus on one of them.
+/// The focused pane always shows the session's active document.
+#[derive(Debug, Default)]
+pub struct Panes {
+    split: Option<Split>,
+    /// Document to open into a new split, waiting for the main loop.
+    pending_open: Option<(PathBuf, Orientation)>,
+}
+
+impl Panes {
+    /// How the screen is split, if it is.
+    pub fn orientation(&self) -> Option<Orientation> {
+        self.split.map(|split| split.orientation)
+    }
+
+    /// Shows `other` next to the active document; the new pane gets the
+    /// focus, as in vim.
+    pub fn split(&mut self, orientation: Orientation, other: DocumentId) {
+        self.split = Some(Split {
+            orientation,
+            other,
+            focus_first: false,
+        });
+    }
+
+    /// Closes the pane without focus (`:only`).
+    pub fn close(&mut self, session: &mut Session) -> Result<bool> {
+        let Some(split) = self.split.take() else {
+            return Ok(false);
+        };
+        if session.active().map(|doc| doc.info.id) == Some(split.other) {
+            session.apply(Command::CloseOtherView)?;
+        }
+        Ok(true)
+    }
+
+    /// Asks the main loop to open `path` and split the screen with it.
+    pub fn request_open(&mut self, path: PathBuf, orientation: Orientation) {
+        self.pending_open = Some((path, orientation));
+    }
+
+    pub fn take_pending_open(&mut self) -> Option<(PathBuf, Orientation)> {
+        self.pending_open.take()
+    }
+
+    /// Moves the focus to the other pane by making its document active, or
+    /// by swapping the positions of two views of the active document.
+    pub fn focus_other(&mut self, session: &mut Session) -> Result<()> {
+        let split = self
+            .split
+            .as_mut()
+            .ok_or_else(|| anyhow!(t!("not-split")))?;
+        let current = session
+            .active()
+            .map(|doc| doc.info.id)
+            .ok_or_else(|| anyhow!(t!("no-active-document")))?;
+        if split.other == current {
+            session.apply(Command::SwapViews)?;
+            split.focus_first = !split.focus_first;
+            return Ok(());
+        }
+        let index = session
+            .documents()
+            .iter()
+            .position(|doc| doc.info.id == split.other)
+            .ok_or_else(|| anyhow!(t!("other-pane-closed")))?;
+        session.apply(Command::SwitchDocument { index })?;
+        split.other = current;
+        split.focus_first = !split.focus_first;
+        Ok(())
+    }
+
+    /// Drops the split once the other pane's document has been closed, or
+    /// has become the active one without a second view.
+    pub fn retain_open(&mut self, session: &Session) -> bool {
+        let Some(split) = self.split else {
+            return false;
+        };
+        let lost_view = session
+            .active()
+            .is_some_and(|doc| doc.info.id == split.other && doc.other_view().is_none());
+        if session.contains_document(split.other) && !lost_view {
+            return false;
+        }
+        self.split = None;
+        true
+    }
+
+    /// The page views for a screen area of `cols` x `rows` cells, in screen
+    /// order. A split gives each pane a title row.
+    pub fn layout(&self, cols: u32, rows: u32) -> Vec<Pane> {
+        let Some(split) = self.split else {
+            return vec![Pane {
+                area: PaneArea {
+                    col: 0,
+                    row: 0,
+                    cols,
+                    rows,
+                },
+                title_row: None,
+                focused: true,
+                doc: None,
+            }];
+        };
+        let (first, second) = match split.orientation {
+            Orientation::Vertical => {
+                // One blank column between the panes.
+                let left = cols.saturating_sub(1) / 2;
+                (
+                    PaneArea {
+                        col: 0,
+                        row: 0,
+                        cols: left,
+                        rows,
+                    },
+                    PaneArea {
+                        col: left + 1,
+                        row: 0,
+                        cols: cols.saturating_sub(left + 1),
+                        rows,
+                    },
+                )
+            }
+            Orientation::Horizontal => {
+                let top = rows / 2;
+                (
+                    PaneArea {
+                        col: 0,
+                        row: 0,
+                        cols,
+                        rows: top,
+                    },
+                    PaneArea {
+                        col: 0,
+                        row: top,
+                        cols,
+                        rows: rows.saturating_sub(top),
+                    },
+                )
+            }
+        };
+        [(first, split.focus_first), (second, !split.focus_first)]
+            .into_iter()
+            .map(|(area, focused)| Pane {
+                area: PaneArea {
+                    row: area.row + 1,
+                    rows: area.rows.saturating_sub(1).max(1),
+                    ..area
+                },
+                title_row: Some(area.row),
+                focused,
+                doc: (!focused).then_some(split.other),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::Arc;
+    use termpdf_core::{document_id_for_path, MemoryStateStore};
+
+    #[test]
+    fn split_layout_divides_the_screen() {
+        let mut panes = Panes::default();
+        assert_eq!(panes.layout(80, 23).len(), 1);
+
+        let other = document_id_for_path(Path::new("supplement.pdf"));
+        panes.split(Orientation::Vertical, other);
+        let layout = panes.layout(81, 23);
+        assert_eq!(layout[0].area.cols, 40);
+        assert_eq!(layout[1].area.col, 41);
+        assert_eq!(layout[1].area.cols, 40);
+        assert_eq!(layout[0].title_row, Some(0));
+        assert_eq!(layout[0].area.row, 1);
+        assert_eq!(layout[0].area.rows, 22);
+        assert!(!layout[0].focused && layout[1].focused);
+        assert_eq!(layout[0].doc, Some(other));
+        assert_eq!(layout[1].doc, None);
+
+        panes.split(Orientation::Horizontal, other);
+        let layout = panes.layout(80, 23);
+        assert_eq!(layout[0].area.rows, 10);
+        assert_eq!(layout[1].title_row, Some(11));
+        assert_eq!(layout[1].area.rows, 11);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        assert!(panes.close(&mut session).unwrap());
+        assert_eq!(panes.orientation(), None);
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use crossterm::event::Event;
+use termpdf_core::{Clock, Session};
+
+use crate::i18n::t;
+
+/// Without a key press for this long the reader has probably stepped away.
+const IDLE_AFTER: Duration = Duration::from_secs(5 * 60);
+/// Longest gap between samples that still counts in full; anything longer
+/// means the process was stopped or the loop stalled.
+const MAX_SAMPLE: Duration = Duration::from_secs(5);
+
+/// Measures how long the active page is read: the time the terminal has
+/// focus and keys were pressed recently.
+#[derive(Debug)]
+pub struct ReadingTimer {
+    focused: bool,
+    last_sample: Instant,
+    last_input: Instant,
+}
+
+impl ReadingTimer {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            // Terminals that do not report focus changes count as focused.
+            focused: true,
+            last_sample: now,
+            last_input: now,
+        }
+    }
+
+    /// Notes focus changes and key presses.
+    pub fn observe(&mut self, event: &Event, now: Instant) {
+        match event {
+            Event::FocusGained => {
+                self.focused = true;
+                self.last_sample = now;
+                self.last_input = now;
+            }
+            Event::FocusLost => self.focused = false,
+            Event::Key(_) | Event::Paste(_) => self.last_input = now,
+            _ => {}
+        }
+    }
+
+    /// Adds the time since the previous sample to the active document.
+    pub fn sample(&mut self, session: &mut Session) {
+        let elapsed = self.reading_time(session.clock().now());
+        if !elapsed.is_zero() {
+            let day = today(session.clock().as_ref());
+            session.record_reading_time(elapsed, day);
+        }
+    }
+
+    fn reading_time(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last_sample);
+        self.last_sample = now;
+        if !self.focused || now.saturating_duration_since(self.last_input) > IDLE_AFTER {
+            return Duration::ZERO;
+        }
+        elapsed.min(MAX_SAMPLE)
+    }
+}
+
+/// Today by `clock` in days since the Unix epoch, with days starting at
+/// local midnight.
+pub fn today(clock: &dyn Clock) -> i64 {
+    let now: DateTime<Local> = clock.wall_time().into();
+    i64::from(now.date_naive().to_epoch_days())
+}
+
+/// How far the active document's daily goal is along, e.g. `12/20 pages
+/// today`; `None` without a goal.
+pub fn goal_progress(session: &Session) -> Option<String> {
+    let doc = session.active()?;
+    let goal = doc.state.daily_goal?;
+    let read = doc
+        .state
+        .stats
+        .pages_read_on(today(session.clock().as_ref()));
+    Some(goal_text(read, goal))
+}
+
+pub fn goal_text(read: usize, goal: usize) -> String {
+    if read >= goal {
+        t!("goal-reached", read = read, goal = goal)
+    } else {
+        t!("goal-progress", read = read, goal = goal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+    use termpdf_core::ManualClock;
+
+    #[test]
+    fn only_focused_recent_time_counts() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut timer = ReadingTimer::new(start);
+
+        assert_eq!(timer.reading_time(at(2)), Duration::from_secs(2));
+        // A stopped process does not count as hours of reading.
+        assert_eq!(timer.reading_time(at(3602)), Duration::ZERO);
+
+        timer.observe(&Event::FocusGained, at(3602));
+        assert_eq!(timer.reading_time(at(3650)), MAX_SAMPLE);
+        timer.observe(&Event::FocusLost, at(3650));
+        assert_eq!(timer.reading_time(at(3651)), Duration::ZERO);
+        timer.observe(&Event::FocusGained, at(3700));
+        assert_eq!(timer.reading_time(at(3701)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn days_turn_over_at_local_midnight() {
+        let local = |hour: u32, minute: u32| {
+            let time = NaiveDate::from_ymd_opt(2026, 3, 14)
+                .unwrap()
+                .and_hms_opt(hour, minute, 0)
+                .unwrap();
+            Local.from_local_datetime(&time).single().unwrap().into()
+        };
+        let clock = ManualClock::at(local(23, 59));
+        let day = today(&clock);
+        assert_eq!(
+            day,
+            i64::from(
+                NaiveDate::from_ymd_opt(2026, 3, 14)
+                    .unwrap()
+                    .to_epoch_days()
+            )
+        );
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(today(&clock), day + 1);
+    }
+}
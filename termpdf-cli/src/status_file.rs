@@ -0,0 +1,157 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use termpdf_core::Session;
+
+/// Layout of the file written with `--status-file`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum StatusFormat {
+    /// An object with the path, title, page and page count
+    #[default]
+    Json,
+    /// One line: title (or file name) and `page/pages`
+    Plain,
+}
+
+/// What is being read, as published for status bars and scripts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct ReadingStatus {
+    path: PathBuf,
+    title: Option<String>,
+    /// 1-based.
+    page: usize,
+    pages: usize,
+}
+
+impl ReadingStatus {
+    fn of(session: &Session) -> Option<Self> {
+        let doc = session.active()?;
+        Some(Self {
+            path: doc.info.path.clone(),
+            title: doc
+                .info
+                .metadata
+                .title
+                .clone()
+                .filter(|title| !title.trim().is_empty()),
+            page: doc.state.current_page + 1,
+            pages: doc.info.page_count,
+        })
+    }
+
+    fn render(&self, format: StatusFormat) -> Result<String> {
+        Ok(match format {
+            StatusFormat::Json => serde_json::to_string(self)? + "\n",
+            StatusFormat::Plain => {
+                let name = self.title.clone().unwrap_or_else(|| {
+                    self.path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                });
+                format!("{} {}/{}\n", name, self.page, self.pages)
+            }
+        })
+    }
+}
+
+/// Keeps a small file up to date with the active document and page.
+pub struct StatusFile {
+    path: PathBuf,
+    format: StatusFormat,
+    written: Option<String>,
+}
+
+impl StatusFile {
+    pub fn new(path: PathBuf, format: StatusFormat) -> Self {
+        Self {
+            path,
+            format,
+            written: None,
+        }
+    }
+
+    /// Rewrites the file if what is being read changed since the last call.
+    pub fn update(&mut self, session: &Session) -> Result<()> {
+        let contents = match ReadingStatus::of(session) {
+            Some(status) => status.render(self.format)?,
+            None => String::new(),
+        };
+        if self.written.as_deref() == Some(contents.as_str()) {
+            return Ok(());
+        }
+        write_atomically(&self.path, &contents)?;
+        self.written = Some(contents);
+        Ok(())
+    }
+
+    /// Removes the file so nothing reports a document that is no longer open.
+    pub fn remove(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                Err(err).with_context(|| format!("failed to remove {:?}", self.path))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Replaces `path` in one step so readers never see a half-written file.
+fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = File::create(&tmp).with_context(|| format!("failed to create {:?}", tmp))?;
+    file.write_all(contents.as_bytes())?;
+    file.flush()?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to write {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_renders_as_json_or_a_line() {
+        let status = ReadingStatus {
+            path: PathBuf::from("/papers/attention.pdf"),
+            title: None,
+            page: 3,
+            pages: 15,
+        };
+        assert_eq!(
+            status.render(StatusFormat::Json).unwrap(),
+            "{\"path\":\"/papers/attention.pdf\",\"title\":null,\"page\":3,\"pages\":15}\n"
+        );
+        assert_eq!(
+            status.render(StatusFormat::Plain).unwrap(),
+            "attention.pdf 3/15\n"
+        );
+        let titled = ReadingStatus {
+            title: Some("Attention Is All You Need".to_string()),
+            ..status
+        };
+        assert_eq!(
+            titled.render(StatusFormat::Plain).unwrap(),
+            "Attention Is All You Need 3/15\n"
+        );
+    }
+
+    #[test]
+    fn status_file_is_written_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reading");
+        let mut file = StatusFile::new(path.clone(), StatusFormat::Plain);
+        let session = Session::new(std::sync::Arc::new(termpdf_core::MemoryStateStore::new()));
+        file.update(&session).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        file.remove().unwrap();
+        assert!(!path.exists());
+        file.remove().unwrap();
+    }
+}
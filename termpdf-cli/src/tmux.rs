@@ -0,0 +1,69 @@
+use std::process::Command;
+
+use tracing::warn;
+
+/// The cell of the outer terminal where the current tmux pane starts, as
+/// `(column, row)`.
+pub fn pane_origin() -> Option<(u16, u16)> {
+    let output = display("#{pane_left} #{pane_top}")?;
+    parse_origin(&output)
+}
+
+/// Whether tmux forwards passthrough escapes from this pane. `None` when
+/// tmux could not be asked.
+pub fn allows_passthrough() -> Option<bool> {
+    let output = Command::new("tmux")
+        .args(["show-options", "-Apv", "allow-passthrough"])
+        .output()
+        .map_err(|err| warn!(?err, "failed to run tmux"))
+        .ok()?;
+    // tmux before 3.3 has no such option and forwards everything.
+    if !output.status.success() {
+        return Some(true);
+    }
+    Some(passthrough_enabled(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+fn display(format: &str) -> Option<String> {
+    let output = Command::new("tmux")
+        .args(["display-message", "-p", format])
+        .output()
+        .map_err(|err| warn!(?err, "failed to run tmux"))
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_origin(output: &str) -> Option<(u16, u16)> {
+    let (left, top) = output.trim().split_once(' ')?;
+    Some((left.parse().ok()?, top.parse().ok()?))
+}
+
+/// `show-options -A` lists the pane's value and those it inherits, most
+/// specific first.
+fn passthrough_enabled(output: &str) -> bool {
+    output
+        .lines()
+        .map(str::trim)
+        .find(|value| !value.is_empty())
+        .is_some_and(|value| value == "on" || value == "all")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmux_replies_are_parsed() {
+        assert_eq!(parse_origin("81 0\n"), Some((81, 0)));
+        assert_eq!(parse_origin("\n"), None);
+        assert!(passthrough_enabled("on\n"));
+        assert!(passthrough_enabled("all\noff\n"));
+        assert!(!passthrough_enabled("off\n"));
+        assert!(!passthrough_enabled(""));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use termpdf_core::DocumentId;
+use tracing::{trace, warn};
+
+/// Quiet time after the last change before a document is reloaded, so a
+/// build that writes the file in several steps causes a single reload.
+pub const RELOAD_DEBOUNCE: Duration = Duration::from_millis(150);
+/// Used when the platform's change notifications are unavailable.
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_millis(300);
+/// Delay before the first retry of a failed reload; doubled on each retry.
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+/// Retries of a failed reload before giving up, about 12 seconds in total.
+const MAX_RELOAD_RETRIES: u32 = 6;
+
+struct WatchedFile {
+    id: DocumentId,
+    path: PathBuf,
+}
+
+/// Watches the directories of open documents and reports the documents whose
+/// file changed once writes to it have settled. Directories rather than files
+/// are watched so that tools replacing the file (latexmk, editors) are seen.
+pub struct FileWatcher {
+    watcher: Box<dyn Watcher>,
+    events: Receiver<notify::Result<Event>>,
+    files: Vec<WatchedFile>,
+    /// Watched directories and the number of documents in each.
+    directories: HashMap<PathBuf, usize>,
+    /// Documents with unreported changes and when to report them.
+    pending: HashMap<DocumentId, Instant>,
+    /// Failed reload attempts since the file last changed.
+    retries: HashMap<DocumentId, u32>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let watcher: Box<dyn Watcher> =
+            match RecommendedWatcher::new(sender.clone(), Config::default()) {
+                Ok(watcher) => Box::new(watcher),
+                Err(err) => {
+                    warn!(
+                        ?err,
+                        "file change notifications unavailable; polling instead"
+                    );
+                    let config = Config::default().with_poll_interval(FALLBACK_POLL_INTERVAL);
+                    Box::new(
+                        PollWatcher::new(sender, config).context("failed to start file watcher")?,
+                    )
+                }
+            };
+        Ok(Self {
+            watcher,
+            events,
+            files: Vec::new(),
+            directories: HashMap::new(),
+            pending: HashMap::new(),
+            retries: HashMap::new(),
+        })
+    }
+
+    pub fn is_watching(&self, id: DocumentId) -> bool {
+        self.files.iter().any(|file| file.id == id)
+    }
+
+    /// Starts reporting changes to `path`. Failures are logged; the document
+    /// then simply does not reload.
+    pub fn watch(&mut self, id: DocumentId, path: &Path) {
+        if self.is_watching(id) {
+            return;
+        }
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let Some(directory) = path.parent().map(Path::to_path_buf) else {
+            return;
+        };
+        if !self.directories.contains_key(&directory) {
+            if let Err(err) = self.watcher.watch(&directory, RecursiveMode::NonRecursive) {
+                warn!(?err, dir = %directory.display(), "failed to watch directory");
+                return;
+            }
+        }
+        *self.directories.entry(directory).or_default() += 1;
+        self.files.push(WatchedFile { id, path });
+    }
+
+    /// Stops watching documents for which `keep` returns false.
+    pub fn retain(&mut self, keep: impl Fn(DocumentId) -> bool) {
+        let (kept, dropped): (Vec<_>, Vec<_>) = std::mem::take(&mut self.files)
+            .into_iter()
+            .partition(|file| keep(file.id));
+        self.files = kept;
+        for file in dropped {
+            self.pending.remove(&file.id);
+            self.retries.remove(&file.id);
+            let Some(directory) = file.path.parent() else {
+                continue;
+            };
+            let Some(count) = self.directories.get_mut(directory) else {
+                continue;
+            };
+            *count -= 1;
+            if *count == 0 {
+                self.directories.remove(directory);
+                if let Err(err) = self.watcher.unwatch(directory) {
+                    trace!(?err, dir = %directory.display(), "failed to unwatch directory");
+                }
+            }
+        }
+    }
+
+    /// Schedules another reload after one failed, typically because the file
+    /// is only partly written. Returns false once the retries are used up.
+    pub fn retry_later(&mut self, id: DocumentId, now: Instant) -> bool {
+        let attempts = self.retries.entry(id).or_default();
+        if *attempts >= MAX_RELOAD_RETRIES {
+            self.retries.remove(&id);
+            return false;
+        }
+        let delay = RETRY_DELAY * 2u32.pow(*attempts);
+        *attempts += 1;
+        // A fresh change is already scheduled sooner.
+        self.pending.entry(id).or_insert(now + delay);
+        true
+    }
+
+    /// Resets the retries after `id` reloaded successfully.
+    pub fn reloaded(&mut self, id: DocumentId) {
+        self.retries.remove(&id);
+    }
+
+    /// Documents that changed and have been quiet for [`RELOAD_DEBOUNCE`],
+    /// and failed reloads that are due for another attempt.
+    pub fn take_changed(&mut self, now: Instant) -> Vec<DocumentId> {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Ok(event) => self.record(&event, now),
+                Err(err) => trace!(?err, "file watcher error"),
+            }
+        }
+        let ready: Vec<DocumentId> = self
+            .pending
+            .iter()
+            .filter(|(_, due)| now >= **due)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &ready {
+            self.pending.remove(id);
+        }
+        ready
+    }
+
+    fn record(&mut self, event: &Event, now: Instant) {
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        for path in &event.paths {
+            for file in self.files.iter().filter(|file| file.path == *path) {
+                self.pending.insert(file.id, now + RELOAD_DEBOUNCE);
+                self.retries.remove(&file.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, ModifyKind};
+    use termpdf_core::{document_id_for_path, Clock, ManualClock};
+
+    #[test]
+    fn reloads_are_debounced_and_retried() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("paper.pdf");
+        std::fs::write(&path, b"%PDF").unwrap();
+        let id = document_id_for_path(&path);
+        let clock = ManualClock::new();
+        let mut watcher = FileWatcher::new().unwrap();
+        watcher.watch(id, &path);
+        let canonical = path.canonicalize().unwrap();
+
+        let access = Event::new(EventKind::Access(AccessKind::Any)).add_path(canonical.clone());
+        watcher.record(&access, clock.now());
+        assert!(watcher.pending.is_empty());
+
+        let write = Event::new(EventKind::Modify(ModifyKind::Any)).add_path(canonical);
+        watcher.record(&write, clock.now());
+        clock.advance(RELOAD_DEBOUNCE / 2);
+        watcher.record(&write, clock.now());
+        clock.advance(RELOAD_DEBOUNCE / 2);
+        assert!(!watcher.take_changed(clock.now()).contains(&id));
+        clock.advance(RELOAD_DEBOUNCE);
+        assert_eq!(watcher.take_changed(clock.now()), vec![id]);
+        assert!(watcher.take_changed(clock.now()).is_empty());
+
+        for attempt in 0..MAX_RELOAD_RETRIES {
+            assert!(watcher.retry_later(id, clock.now()));
+            clock.advance(RETRY_DELAY * 2u32.pow(attempt) - Duration::from_millis(1));
+            assert!(watcher.take_changed(clock.now()).is_empty());
+            clock.advance(Duration::from_millis(1));
+            assert_eq!(watcher.take_changed(clock.now()), vec![id]);
+        }
+        assert!(!watcher.retry_later(id, clock.now()));
+        // A new write starts the backoff over.
+        watcher.record(&write, clock.now());
+        assert!(watcher.retry_later(id, clock.now()));
+
+        watcher.retain(|_| false);
+        assert!(!watcher.is_watching(id));
+        assert!(watcher.directories.is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use parking_lot::Mutex;
+
+/// Source of the current time for anything that waits, debounces or
+/// expires, so tests can drive time explicitly instead of sleeping.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// The wall-clock time, for what is dated, such as `:history`.
+    fn wall_time(&self) -> SystemTime;
+}
+
+/// Seconds since the Unix epoch at `time`; 0 before it.
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real monotonic clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// Clock that only moves when told to.
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<(Instant, SystemTime)>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            now: Mutex::new((Instant::now(), SystemTime::now())),
+        }
+    }
+
+    /// Clock whose wall time starts at `wall_time`.
+    pub fn at(wall_time: SystemTime) -> Self {
+        Self {
+            now: Mutex::new((Instant::now(), wall_time)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock();
+        now.0 += by;
+        now.1 += by;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.lock().0
+    }
+
+    fn wall_time(&self) -> SystemTime {
+        self.now.lock().1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_moves_only_when_advanced() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(clock.now() - start, Duration::from_millis(250));
+
+        let dated = ManualClock::at(SystemTime::UNIX_EPOCH + Duration::from_secs(100));
+        dated.advance(Duration::from_secs(20));
+        assert_eq!(unix_seconds(dated.wall_time()), 120);
+    }
+}
@@ -0,0 +1,61 @@
+/// The first DOI in `text`, such as the `10.1103/PhysRevD.76.044016` in
+/// `doi:10.1103/PhysRevD.76.044016.` or in a `https://doi.org/...` link.
+pub fn find_doi(text: &str) -> Option<String> {
+    text.match_indices("10.").find_map(|(start, _)| {
+        if text[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '.')
+        {
+            return None;
+        }
+        let doi: String = text[start..]
+            .chars()
+            .take_while(|c| !c.is_whitespace() && !matches!(c, ',' | ';' | '"' | '<' | '>'))
+            .collect();
+        let doi = trim_doi(&doi);
+        let (prefix, suffix) = doi.split_once('/')?;
+        let registrant = &prefix[3..];
+        (!registrant.is_empty()
+            && registrant.chars().all(|c| c.is_ascii_digit() || c == '.')
+            && !suffix.is_empty())
+        .then(|| doi.to_string())
+    })
+}
+
+/// Drops the punctuation of the surrounding sentence: a final period and a
+/// closing parenthesis that has no opening one in the DOI.
+fn trim_doi(mut doi: &str) -> &str {
+    loop {
+        if let Some(rest) = doi.strip_suffix(['.', ']']) {
+            doi = rest;
+        } else if doi.ends_with(')') && doi.matches('(').count() < doi.matches(')').count() {
+            doi = &doi[..doi.len() - 1];
+        } else {
+            return doi;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_dois_in_running_text() {
+        assert_eq!(
+            find_doi("Physics; doi:10.1103/PhysRevD.76.044016."),
+            Some("10.1103/PhysRevD.76.044016".into())
+        );
+        assert_eq!(
+            find_doi("(see https://doi.org/10.1016/S0140-6736(20)30183-5)"),
+            Some("10.1016/S0140-6736(20)30183-5".into())
+        );
+        assert_eq!(
+            find_doi("version 10.2, see 10.5555/12345678"),
+            Some("10.5555/12345678".into())
+        );
+        assert_eq!(find_doi("page 110.5/3 and version 10.2"), None);
+        assert_eq!(find_doi("no identifier"), None);
+    }
+}
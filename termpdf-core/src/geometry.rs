@@ -0,0 +1,299 @@
+//! Conversions between the coordinate spaces used across the viewer:
+//!
+//! * page space: backend units (PDF points), origin bottom-left, y up, before
+//!   the page's `/Rotate` is applied;
+//! * normalized space: 0..1 fractions of the displayed page, origin top-left
+//!   ([`NormalizedRect`]);
+//! * pixel space: pixels of a rendered page image, optionally cropped to the
+//!   part of the page visible in the viewport.
+
+use crate::NormalizedRect;
+
+/// Clockwise quarter-turn rotation applied to a page when it is displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageRotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl PageRotation {
+    /// Rotation for a `/Rotate` value; anything that is not a multiple of 90
+    /// degrees is treated as unrotated, as PDF viewers do.
+    pub fn from_degrees(degrees: i32) -> Self {
+        match degrees.rem_euclid(360) {
+            90 => Self::Quarter,
+            180 => Self::Half,
+            270 => Self::ThreeQuarters,
+            _ => Self::None,
+        }
+    }
+
+    fn swaps_axes(self) -> bool {
+        matches!(self, Self::Quarter | Self::ThreeQuarters)
+    }
+}
+
+/// Maps page-space coordinates of one page into normalized display space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSpace {
+    width: f32,
+    height: f32,
+    rotation: PageRotation,
+}
+
+impl PageSpace {
+    /// `width` and `height` are the size of the page as displayed, i.e. after
+    /// rotation. Returns `None` for degenerate pages.
+    pub fn new(width: f32, height: f32, rotation: PageRotation) -> Option<Self> {
+        if !(width > 0.0 && height > 0.0) {
+            return None;
+        }
+        let (width, height) = if rotation.swaps_axes() {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        Some(Self {
+            width,
+            height,
+            rotation,
+        })
+    }
+
+    pub fn normalize_point(&self, x: f32, y: f32) -> (f32, f32) {
+        let u = x / self.width;
+        let v = 1.0 - y / self.height;
+        match self.rotation {
+            PageRotation::None => (u, v),
+            PageRotation::Quarter => (1.0 - v, u),
+            PageRotation::Half => (1.0 - u, 1.0 - v),
+            PageRotation::ThreeQuarters => (v, 1.0 - u),
+        }
+    }
+
+    /// Normalized rectangle covering a page-space box, clamped to the page.
+    /// Returns `None` when nothing of it is left.
+    pub fn normalize_rect(
+        &self,
+        left: f32,
+        top: f32,
+        right: f32,
+        bottom: f32,
+    ) -> Option<NormalizedRect> {
+        let (x0, y0) = self.normalize_point(left, top);
+        let (x1, y1) = self.normalize_point(right, bottom);
+        let rect = NormalizedRect {
+            left: x0.min(x1),
+            top: y0.min(y1),
+            right: x0.max(x1),
+            bottom: y0.max(y1),
+        }
+        .clamp();
+        rect.is_valid().then_some(rect)
+    }
+
+    /// Normalized vertical position of a page-space `y`, as used by link
+    /// destinations. Rotated by a quarter turn, `y` runs horizontally on
+    /// screen, so there is no vertical position to report.
+    pub fn normalize_top(&self, y: f32) -> Option<f32> {
+        match self.rotation {
+            PageRotation::None | PageRotation::Half => {
+                Some(self.normalize_point(0.0, y).1.clamp(0.0, 1.0))
+            }
+            PageRotation::Quarter | PageRotation::ThreeQuarters => None,
+        }
+    }
+}
+
+/// Part of a rendered image that is shown, in pixels of the full image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelCrop {
+    pub offset_x: u32,
+    pub offset_y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Half-open pixel box `x0..x1` × `y0..y1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelRect {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+/// Pixels covered by `rect` on a `width`×`height` image, rounded outwards.
+/// With a crop the result is relative to the crop and clipped to it.
+pub fn rect_to_pixels(
+    rect: &NormalizedRect,
+    width: u32,
+    height: u32,
+    crop: Option<PixelCrop>,
+) -> Option<PixelRect> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let to_pixels = |value: f32, size: u32, round: fn(f32) -> f32| {
+        (round(value * size as f32) as i64).clamp(0, i64::from(size))
+    };
+    let mut x0 = to_pixels(rect.left, width, f32::floor);
+    let mut x1 = to_pixels(rect.right, width, f32::ceil);
+    let mut y0 = to_pixels(rect.top, height, f32::floor);
+    let mut y1 = to_pixels(rect.bottom, height, f32::ceil);
+
+    if let Some(crop) = crop {
+        let clip = |value: i64, offset: u32, size: u32| {
+            (value - i64::from(offset)).clamp(0, i64::from(size))
+        };
+        x0 = clip(x0, crop.offset_x, crop.width);
+        x1 = clip(x1, crop.offset_x, crop.width);
+        y0 = clip(y0, crop.offset_y, crop.height);
+        y1 = clip(y1, crop.offset_y, crop.height);
+    }
+
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+    Some(PixelRect {
+        x0: x0 as u32,
+        y0: y0 as u32,
+        x1: x1 as u32,
+        y1: y1 as u32,
+    })
+}
+
+/// Offset of a `visible`-sized window into `total` for a scroll fraction,
+/// where 0 shows the start and 1 the end.
+pub fn viewport_origin(total: u32, visible: u32, fraction: f32) -> u32 {
+    if visible >= total {
+        return 0;
+    }
+    let max_offset = total - visible;
+    let raw = (max_offset as f32 * fraction.clamp(0.0, 1.0)).round();
+    (raw.max(0.0) as u32).min(max_offset)
+}
+
+/// Scroll fraction that keeps the normalized span `start..end` (plus a
+/// `margin` of cells out of `cells`) inside a window showing `visible` of the
+/// page, moving as little as possible from `fraction`.
+pub fn follow_span(
+    fraction: f32,
+    visible: f32,
+    start: f32,
+    end: f32,
+    margin: usize,
+    cells: u32,
+) -> f32 {
+    let visible = visible.clamp(0.0, 1.0);
+    let travel = 1.0 - visible;
+    if travel <= f32::EPSILON || cells == 0 {
+        return fraction;
+    }
+    let span = (end - start).max(0.0);
+    let max_margin = ((visible - span) * 0.5).max(0.0);
+    let margin = (margin as f32 / cells as f32 * visible).min(max_margin);
+    let mut origin = fraction.clamp(0.0, 1.0) * travel;
+    if start - margin < origin {
+        origin = start - margin;
+    } else if end + margin > origin + visible {
+        origin = end + margin - visible;
+    }
+    (origin / travel).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5
+    }
+
+    #[test]
+    fn page_space_follows_rotation() {
+        // A 200x100 (unrotated) page; the top-left corner of the page content
+        // is (0, 100) in page space.
+        let upright = PageSpace::new(200.0, 100.0, PageRotation::None).unwrap();
+        assert!(approx(upright.normalize_point(0.0, 100.0), (0.0, 0.0)));
+        assert!(approx(upright.normalize_point(50.0, 25.0), (0.25, 0.75)));
+
+        // Displayed as 100x200 once rotated a quarter turn clockwise: the
+        // content's top edge becomes the right edge.
+        let quarter = PageSpace::new(100.0, 200.0, PageRotation::Quarter).unwrap();
+        assert!(approx(quarter.normalize_point(0.0, 100.0), (1.0, 0.0)));
+        assert!(approx(quarter.normalize_point(0.0, 0.0), (0.0, 0.0)));
+        assert_eq!(quarter.normalize_top(50.0), None);
+
+        let half = PageSpace::new(200.0, 100.0, PageRotation::Half).unwrap();
+        assert!(approx(half.normalize_point(0.0, 100.0), (1.0, 1.0)));
+        assert_eq!(half.normalize_top(100.0), Some(1.0));
+
+        let three = PageSpace::new(100.0, 200.0, PageRotation::ThreeQuarters).unwrap();
+        assert!(approx(three.normalize_point(0.0, 100.0), (0.0, 1.0)));
+
+        let rect = quarter.normalize_rect(0.0, 100.0, 100.0, 50.0).unwrap();
+        assert_eq!(
+            rect,
+            NormalizedRect {
+                left: 0.5,
+                top: 0.0,
+                right: 1.0,
+                bottom: 0.5,
+            }
+        );
+        assert!(PageSpace::new(0.0, 10.0, PageRotation::None).is_none());
+        assert_eq!(PageRotation::from_degrees(-90), PageRotation::ThreeQuarters);
+    }
+
+    #[test]
+    fn pixel_rects_round_outwards_and_clip_to_crop() {
+        let rect = NormalizedRect {
+            left: 0.101,
+            top: 0.5,
+            right: 0.299,
+            bottom: 1.2,
+        };
+        assert_eq!(
+            rect_to_pixels(&rect, 100, 10, None),
+            Some(PixelRect {
+                x0: 10,
+                y0: 5,
+                x1: 30,
+                y1: 10,
+            })
+        );
+        let crop = PixelCrop {
+            offset_x: 20,
+            offset_y: 0,
+            width: 50,
+            height: 8,
+        };
+        assert_eq!(
+            rect_to_pixels(&rect, 100, 10, Some(crop)),
+            Some(PixelRect {
+                x0: 0,
+                y0: 5,
+                x1: 10,
+                y1: 8,
+            })
+        );
+        let outside = PixelCrop {
+            offset_x: 40,
+            ..crop
+        };
+        assert_eq!(rect_to_pixels(&rect, 100, 10, Some(outside)), None);
+    }
+
+    #[test]
+    fn viewport_origin_stays_in_bounds() {
+        assert_eq!(viewport_origin(100, 40, 0.0), 0);
+        assert_eq!(viewport_origin(100, 40, 1.0), 60);
+        assert_eq!(viewport_origin(100, 40, 2.0), 60);
+        assert_eq!(viewport_origin(100, 40, 0.5), 30);
+        assert_eq!(viewport_origin(40, 100, 0.5), 0);
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// Reading sessions kept in a document's saved state.
+const HISTORY_CAPACITY: usize = 100;
+
+/// The page a document was left on when a session ended, for `:history`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub page: usize,
+    /// Seconds since the Unix epoch.
+    pub ended_at: u64,
+}
+
+/// Adds a session that ended on `page` to `history`, oldest first. Ending
+/// on the page the previous session ended on only moves its time.
+pub(crate) fn record(history: &mut Vec<HistoryEntry>, page: usize, ended_at: u64) {
+    match history.last_mut() {
+        Some(last) if last.page == page => last.ended_at = ended_at.max(last.ended_at),
+        _ => history.push(HistoryEntry { page, ended_at }),
+    }
+    trim(history);
+}
+
+/// Both copies' sessions in the order they ended.
+pub(crate) fn merge(local: &[HistoryEntry], remote: &[HistoryEntry]) -> Vec<HistoryEntry> {
+    let mut merged: Vec<HistoryEntry> = local.iter().chain(remote).copied().collect();
+    merged.sort_by_key(|entry| (entry.ended_at, entry.page));
+    merged.dedup();
+    trim(&mut merged);
+    merged
+}
+
+fn trim(history: &mut Vec<HistoryEntry>) {
+    let excess = history.len().saturating_sub(HISTORY_CAPACITY);
+    history.drain(..excess);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sessions_are_recorded_and_merged_in_order() {
+        let mut laptop = Vec::new();
+        record(&mut laptop, 3, 100);
+        record(&mut laptop, 3, 150);
+        record(&mut laptop, 12, 300);
+        assert_eq!(
+            laptop,
+            vec![
+                HistoryEntry {
+                    page: 3,
+                    ended_at: 150
+                },
+                HistoryEntry {
+                    page: 12,
+                    ended_at: 300
+                },
+            ]
+        );
+
+        let mut desktop = laptop[..1].to_vec();
+        record(&mut desktop, 7, 200);
+        let pages: Vec<usize> = merge(&laptop, &desktop)
+            .iter()
+            .map(|entry| entry.page)
+            .collect();
+        assert_eq!(pages, vec![3, 7, 12]);
+
+        let mut long = Vec::new();
+        for page in 0..HISTORY_CAPACITY + 10 {
+            record(&mut long, page, page as u64);
+        }
+        assert_eq!(long.len(), HISTORY_CAPACITY);
+        assert_eq!(long[0].page, 10);
+    }
+}
@@ -1,10 +1,13 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
 use std::io::{Read, Write};
-use std::ops::Range;
+use std::ops::{Range, RangeInclusive};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Error, Result};
 use once_cell::sync::Lazy;
@@ -13,6 +16,35 @@ use serde::{Deserialize, Serialize};
 use tracing::{instrument, trace, warn};
 use uuid::Uuid;
 
+mod clock;
+mod doi;
+pub mod geometry;
+mod history;
+mod migrate;
+mod reading_order;
+mod recent;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod watchdog;
+
+pub use clock::{system_clock, unix_seconds, Clock, ManualClock, SharedClock, SystemClock};
+pub use doi::find_doi;
+pub use history::HistoryEntry;
+pub use migrate::{decode_state, STATE_VERSION};
+pub use recent::{RecentFile, RecentFiles};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStateStore;
+pub use stats::ReadingStats;
+pub use sync::FieldTimestamps;
+pub use watchdog::{CancelToken, RenderCancelled, SupervisedBackend, SupervisedProvider};
+
+use geometry::follow_span;
+use sync::{merge_states, stamp_changes, unix_millis};
+
 pub type DocumentId = Uuid;
 
 static DOCUMENT_NAMESPACE: Lazy<Uuid> = Lazy::new(|| {
@@ -31,7 +63,48 @@ pub fn document_id_for_path(path: &Path) -> DocumentId {
         })
         .unwrap_or_else(|_| path.to_path_buf());
     let rendered = resolved.to_string_lossy();
-    Uuid::new_v5(&*DOCUMENT_NAMESPACE, rendered.as_bytes())
+    Uuid::new_v5(&DOCUMENT_NAMESPACE, rendered.as_bytes())
+}
+
+static CONTENT_NAMESPACE: Lazy<Uuid> = Lazy::new(|| {
+    Uuid::parse_str("d4a0c3e2-5b7f-5e61-9c1a-3f8e2b6d7a90").expect("valid namespace UUID")
+});
+
+/// Bytes from the start of a file that go into [`document_id_for_content`].
+pub const CONTENT_ID_PREFIX_BYTES: u64 = 1024 * 1024;
+
+/// Identifier derived from the size and leading bytes of the file at `path`,
+/// so it survives renames, moves and bind mounts.
+pub fn document_id_for_content(path: &Path) -> Result<DocumentId> {
+    let file = File::open(path).with_context(|| format!("failed to open {:?}", path))?;
+    let size = file.metadata()?.len();
+    let mut buf = Vec::with_capacity(CONTENT_ID_PREFIX_BYTES.min(size) as usize + 8);
+    file.take(CONTENT_ID_PREFIX_BYTES)
+        .read_to_end(&mut buf)
+        .with_context(|| format!("failed to read {:?}", path))?;
+    buf.extend_from_slice(&size.to_le_bytes());
+    Ok(Uuid::new_v5(&CONTENT_NAMESPACE, &buf))
+}
+
+/// [`document_id_for_content`] for a document held in memory, e.g. one read
+/// from a pipe. Matches the id of the same bytes stored in a file.
+pub fn document_id_for_bytes(bytes: &[u8]) -> DocumentId {
+    let prefix = &bytes[..bytes.len().min(CONTENT_ID_PREFIX_BYTES as usize)];
+    let mut buf = Vec::with_capacity(prefix.len() + 8);
+    buf.extend_from_slice(prefix);
+    buf.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    Uuid::new_v5(&CONTENT_NAMESPACE, &buf)
+}
+
+/// How a [`FileStateStore`] identifies the document a state belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StateKey {
+    /// By canonical path ([`document_id_for_path`]).
+    #[default]
+    Path,
+    /// By file content ([`document_id_for_content`]), so state follows a
+    /// file across renames and matches copies synced to other machines.
+    Content,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -46,6 +119,8 @@ pub struct OutlineItem {
     pub title: String,
     pub page_index: usize,
     pub depth: usize,
+    /// Label of the target page, filled in from the document's page labels.
+    pub page_label: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -101,6 +176,14 @@ pub struct DocumentInfo {
     pub metadata: DocumentMetadata,
 }
 
+/// Size of a page in points as rendered, i.e. after its rotation; a
+/// render at scale 1 is this many pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSize {
+    pub width: f32,
+    pub height: f32,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct RenderRequest {
     pub page_index: usize,
@@ -125,6 +208,28 @@ pub struct RenderImage {
     pub pixels: Vec<u8>,
 }
 
+impl RenderImage {
+    /// Grey page with a darker frame, shown in place of a page that failed to render.
+    pub fn placeholder(width: u32, height: u32) -> Self {
+        let width = width.max(1);
+        let height = height.max(1);
+        let frame = (width.min(height) / 50).max(1);
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let edge = x < frame || y < frame || x >= width - frame || y >= height - frame;
+                let shade = if edge { 96 } else { 200 };
+                pixels.extend_from_slice(&[shade, shade, shade, 255]);
+            }
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct TextGlyph {
     pub range: Range<usize>,
@@ -141,7 +246,24 @@ pub struct PageText {
 }
 
 impl PageText {
+    /// Roughly how much memory the page's text and index take.
+    fn heap_bytes(&self) -> usize {
+        self.text.capacity()
+            + self.glyphs.capacity() * std::mem::size_of::<TextGlyph>()
+            + self.boundary_offsets.capacity() * std::mem::size_of::<usize>()
+            + self.line_map.capacity() * std::mem::size_of::<PageLine>()
+            + self.glyph_line_index.capacity() * std::mem::size_of::<usize>()
+    }
+
+    /// Builds the page's text index. Glyphs that arrive visibly out of
+    /// reading order, or that run across the columns of a multi-column page,
+    /// are sorted into columns and lines first.
     pub fn new(text: String, glyphs: Vec<TextGlyph>) -> Self {
+        let (text, glyphs) = if reading_order::needs_reordering(&text, &glyphs) {
+            reading_order::reorder(&text, &glyphs)
+        } else {
+            (text, glyphs)
+        };
         let mut boundary_offsets = Vec::with_capacity(glyphs.len().saturating_add(1));
         boundary_offsets.push(0);
         for glyph in &glyphs {
@@ -176,10 +298,456 @@ impl PageText {
         self.line_map.get(index)
     }
 
+    /// Median distance between consecutive text lines, in normalized page
+    /// units.
+    pub fn median_line_height(&self) -> Option<f32> {
+        let mut gaps: Vec<f32> = self
+            .line_map
+            .windows(2)
+            .map(|pair| (pair[1].center_y - pair[0].center_y).abs())
+            .filter(|gap| *gap > f32::EPSILON)
+            .collect();
+        if gaps.is_empty() {
+            return None;
+        }
+        gaps.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        Some(gaps[gaps.len() / 2])
+    }
+
+    /// Mean width of the visible glyphs, in normalized page units.
+    pub fn average_glyph_width(&self) -> Option<f32> {
+        let widths: Vec<f32> = self
+            .glyphs
+            .iter()
+            .filter(|glyph| glyph.rect.is_valid())
+            .filter(|glyph| {
+                self.text
+                    .get(glyph.range.clone())
+                    .is_some_and(|value| !value.trim().is_empty())
+            })
+            .map(|glyph| glyph.rect.right - glyph.rect.left)
+            .collect();
+        if widths.is_empty() {
+            return None;
+        }
+        Some(widths.iter().sum::<f32>() / widths.len() as f32)
+    }
+
+    /// Text of the glyphs in `glyphs`. Where the text layer has nothing
+    /// between two glyphs, a space is inserted if they are visibly apart and a
+    /// newline if they sit on different lines, so words do not run together.
+    pub fn glyph_text(&self, glyphs: Range<usize>) -> String {
+        let end = glyphs.end.min(self.glyphs.len());
+        let start = glyphs.start.min(end);
+        let word_gap = self
+            .average_glyph_width()
+            .map(|width| width * WORD_GAP_RATIO);
+        let mut buffer = String::new();
+        for index in start..end {
+            let glyph = &self.glyphs[index];
+            let value = self.text.get(glyph.range.clone()).unwrap_or("");
+            if index > start {
+                let previous = &self.glyphs[index - 1];
+                let between = self
+                    .text
+                    .get(previous.range.end..glyph.range.start)
+                    .unwrap_or("");
+                if !between.is_empty() {
+                    buffer.push_str(between);
+                } else if !buffer.ends_with(char::is_whitespace)
+                    && !value.starts_with(char::is_whitespace)
+                {
+                    if self.line_index_for_glyph(index) != self.line_index_for_glyph(index - 1) {
+                        buffer.push('\n');
+                    } else if let Some(word_gap) = word_gap {
+                        let gap = glyph.rect.left - previous.rect.right;
+                        if glyph.rect.is_valid() && previous.rect.is_valid() && gap > word_gap {
+                            buffer.push(' ');
+                        }
+                    }
+                }
+            }
+            buffer.push_str(value);
+        }
+        buffer
+    }
+
     pub fn glyph_char(&self, index: usize) -> Option<char> {
         let glyph = self.glyphs.get(index)?;
         self.text[glyph.range.clone()].chars().next()
     }
+
+    /// Text of the glyphs whose centre lies inside `rect`.
+    pub fn text_in_rect(&self, rect: &NormalizedRect) -> String {
+        self.glyphs
+            .iter()
+            .filter(|glyph| {
+                let x = (glyph.rect.left + glyph.rect.right) * 0.5;
+                let y = (glyph.rect.top + glyph.rect.bottom) * 0.5;
+                x >= rect.left && x <= rect.right && y >= rect.top && y <= rect.bottom
+            })
+            .map(|glyph| &self.text[glyph.range.clone()])
+            .collect::<String>()
+            .trim()
+            .to_string()
+    }
+
+    /// Runs of consecutive glyphs on one line whose centre lies inside
+    /// `rect`, for block selections.
+    pub fn block_runs(&self, rect: &NormalizedRect) -> Vec<Range<usize>> {
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for (index, glyph) in self.glyphs.iter().enumerate() {
+            let (x, y) = glyph.rect.center();
+            if !glyph.rect.is_valid() || !rect.contains(x, y) {
+                continue;
+            }
+            match runs.last_mut() {
+                Some(run)
+                    if run.end == index
+                        && self.line_index_for_glyph(run.start)
+                            == self.line_index_for_glyph(index) =>
+                {
+                    run.end = index + 1
+                }
+                _ => runs.push(index..index + 1),
+            }
+        }
+        runs
+    }
+
+    /// Text of a block selection: the part of every line inside `rect`, one
+    /// line per row.
+    pub fn block_text(&self, rect: &NormalizedRect) -> String {
+        let mut lines: Vec<(Option<usize>, String)> = Vec::new();
+        for run in self.block_runs(rect) {
+            let line = self.line_index_for_glyph(run.start);
+            let text = self.glyph_text(run);
+            let text = text.trim();
+            match lines.last_mut() {
+                Some((previous, current)) if *previous == line => {
+                    current.push(' ');
+                    current.push_str(text);
+                }
+                _ => lines.push((line, text.to_string())),
+            }
+        }
+        lines
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Byte range spanned by the glyphs whose centre lies in one of `rects`.
+    pub fn range_in_rects(&self, rects: &[NormalizedRect]) -> Option<Range<usize>> {
+        self.glyphs
+            .iter()
+            .filter(|glyph| {
+                let (x, y) = glyph.rect.center();
+                rects.iter().any(|rect| rect.contains(x, y))
+            })
+            .map(|glyph| glyph.range.clone())
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+    }
+
+    /// Bounding boxes of the glyphs covering the byte `range`, one per line.
+    pub fn rects_for_range(&self, range: Range<usize>) -> Vec<NormalizedRect> {
+        let mut rects: Vec<(usize, NormalizedRect)> = Vec::new();
+        for (index, glyph) in self.glyphs.iter().enumerate() {
+            if glyph.range.start < range.start || glyph.range.start >= range.end {
+                continue;
+            }
+            if !glyph.rect.is_valid() {
+                continue;
+            }
+            let line = self.glyph_line_index.get(index).copied().unwrap_or(0);
+            match rects.last_mut() {
+                Some((last_line, rect)) if *last_line == line => {
+                    rect.left = rect.left.min(glyph.rect.left);
+                    rect.top = rect.top.min(glyph.rect.top);
+                    rect.right = rect.right.max(glyph.rect.right);
+                    rect.bottom = rect.bottom.max(glyph.rect.bottom);
+                }
+                _ => rects.push((line, glyph.rect)),
+            }
+        }
+        rects.into_iter().map(|(_, rect)| rect).collect()
+    }
+
+    /// Page text regrouped into paragraphs for reflowing: lines are joined,
+    /// words hyphenated across a line break are rejoined, and a paragraph ends
+    /// wherever the gap to the next line is clearly wider than usual.
+    pub fn paragraphs(&self) -> Vec<String> {
+        self.paragraph_lines()
+            .into_iter()
+            .map(|lines| {
+                let mut paragraph = String::new();
+                for (_, text) in lines {
+                    join_wrapped_line(&mut paragraph, &text);
+                }
+                paragraph
+            })
+            .collect()
+    }
+
+    /// The paragraphs of the glyphs in `glyphs`, with their lines joined the
+    /// way [`paragraphs`](Self::paragraphs) joins them.
+    pub fn paragraphs_in(&self, glyphs: Range<usize>) -> Vec<String> {
+        self.paragraph_lines()
+            .into_iter()
+            .filter_map(|lines| {
+                let mut paragraph = String::new();
+                for (line, _) in lines {
+                    let start = line.glyph_range.start.max(glyphs.start);
+                    let end = line.glyph_range.end.min(glyphs.end);
+                    if start < end {
+                        join_wrapped_line(&mut paragraph, self.glyph_text(start..end).trim());
+                    }
+                }
+                (!paragraph.is_empty()).then_some(paragraph)
+            })
+            .collect()
+    }
+
+    /// The non-empty lines of each paragraph, as found by
+    /// [`paragraphs`](Self::paragraphs).
+    fn paragraph_lines(&self) -> Vec<Vec<(&PageLine, String)>> {
+        let lines: Vec<(&PageLine, String)> = self
+            .line_map
+            .iter()
+            .map(|line| (line, self.line_text(line)))
+            .filter(|(_, text)| !text.is_empty())
+            .collect();
+        let mut gaps: Vec<f32> = lines
+            .windows(2)
+            .map(|pair| pair[1].0.center_y - pair[0].0.center_y)
+            .filter(|gap| *gap > 0.0)
+            .collect();
+        gaps.sort_by(|a, b| a.total_cmp(b));
+        let typical = gaps.get(gaps.len() / 2).copied().unwrap_or(0.0);
+
+        let mut paragraphs: Vec<Vec<(&PageLine, String)>> = Vec::new();
+        let mut previous_y: Option<f32> = None;
+        for (line, text) in lines {
+            let breaks = previous_y.is_none_or(|prev| {
+                let gap = line.center_y - prev;
+                gap < 0.0 || (typical > 0.0 && gap > typical * 1.6)
+            });
+            previous_y = Some(line.center_y);
+            match paragraphs.last_mut() {
+                Some(current) if !breaks => current.push((line, text)),
+                _ => paragraphs.push(vec![(line, text)]),
+            }
+        }
+        paragraphs
+    }
+
+    /// Glyphs of the paragraph around glyph `index` and the `count - 1`
+    /// paragraphs after it (vim's `ip`).
+    pub fn paragraph_range(&self, index: usize, count: usize) -> Option<Range<usize>> {
+        let ranges: Vec<Range<usize>> = self
+            .paragraph_lines()
+            .iter()
+            .filter_map(|lines| {
+                let first = lines.first()?.0.glyph_range.start;
+                let last = lines.last()?.0.glyph_range.end;
+                Some(first..last)
+            })
+            .collect();
+        let first = ranges
+            .iter()
+            .position(|range| index < range.end)
+            .unwrap_or(ranges.len().checked_sub(1)?);
+        let last = (first + count.max(1) - 1).min(ranges.len() - 1);
+        Some(ranges[first].start..ranges[last].end)
+    }
+
+    /// Glyphs of the word, run of punctuation or run of blanks around glyph
+    /// `index`, and the `count - 1` runs after it (vim's `iw`). With
+    /// `around` the blanks after the word are included, or those before it
+    /// when there are none after (vim's `aw`).
+    pub fn word_range(&self, index: usize, count: usize, around: bool) -> Option<Range<usize>> {
+        let glyph_count = self.glyph_count();
+        if glyph_count == 0 {
+            return None;
+        }
+        let index = index.min(glyph_count - 1);
+        let class = |idx: usize| self.glyph_char(idx).map_or(CharClass::Blank, CharClass::of);
+        let same_run = |a: usize, b: usize| {
+            class(a) == class(b) && self.line_index_for_glyph(a) == self.line_index_for_glyph(b)
+        };
+        let run_end = |mut idx: usize| {
+            let first = idx;
+            while idx < glyph_count && same_run(first, idx) {
+                idx += 1;
+            }
+            idx
+        };
+
+        let mut start = index;
+        while start > 0 && same_run(start - 1, index) {
+            start -= 1;
+        }
+        let mut end = index;
+        for _ in 0..count.max(1) {
+            if end >= glyph_count {
+                break;
+            }
+            end = run_end(end);
+        }
+        if around {
+            if class(index) == CharClass::Blank {
+                if end < glyph_count {
+                    end = run_end(end);
+                }
+            } else if end < glyph_count && class(end) == CharClass::Blank {
+                end = run_end(end);
+            } else {
+                while start > 0 && class(start - 1) == CharClass::Blank {
+                    start -= 1;
+                }
+            }
+        }
+        Some(start..end)
+    }
+
+    /// Glyphs where a sentence starts: after `.`, `!` or `?` (and any closing
+    /// brackets or quotes) followed by a blank or a line break, at the start
+    /// of every paragraph and at the first word of the page.
+    pub fn sentence_starts(&self) -> Vec<usize> {
+        let paragraph_starts: Vec<usize> = self
+            .paragraph_lines()
+            .iter()
+            .filter_map(|lines| lines.first().map(|(line, _)| line.glyph_range.start))
+            .collect();
+        let is_blank = |idx: usize| self.glyph_char(idx).is_none_or(char::is_whitespace);
+        (0..self.glyph_count())
+            .filter(|&idx| !is_blank(idx))
+            .filter(|&idx| {
+                let mut before = idx;
+                while before > 0 && is_blank(before - 1) {
+                    before -= 1;
+                }
+                if before == 0 || paragraph_starts.contains(&idx) {
+                    return true;
+                }
+                let separated = before < idx
+                    || self.line_index_for_glyph(before - 1) != self.line_index_for_glyph(idx);
+                while before > 0
+                    && self
+                        .glyph_char(before - 1)
+                        .is_some_and(is_closing_punctuation)
+                {
+                    before -= 1;
+                }
+                separated
+                    && before > 0
+                    && self
+                        .glyph_char(before - 1)
+                        .is_some_and(|ch| matches!(ch, '.' | '!' | '?'))
+            })
+            .collect()
+    }
+
+    fn line_text(&self, line: &PageLine) -> String {
+        let (Some(first), Some(last)) = (
+            self.glyphs.get(line.glyph_range.start),
+            line.glyph_range
+                .end
+                .checked_sub(1)
+                .and_then(|idx| self.glyphs.get(idx)),
+        ) else {
+            return String::new();
+        };
+        self.text[first.range.start..last.range.end.max(first.range.start)]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Extracts a bibliography entry: it starts at the first line below `top`
+    /// (or, failing that, the line beginning with `label`) and runs until the
+    /// next entry marker, a paragraph gap, or a length cap.
+    pub fn reference_entry(&self, top: Option<f32>, label: &str) -> Option<String> {
+        const MAX_LINES: usize = 12;
+
+        let lines: Vec<(f32, String)> = self
+            .line_map
+            .iter()
+            .map(|line| (line.center_y, self.line_text(line)))
+            .filter(|(_, text)| !text.is_empty())
+            .collect();
+        let label = label.trim();
+        let by_top = top.and_then(|top| lines.iter().position(|(center, _)| *center >= top));
+        let by_label = if label.is_empty() {
+            None
+        } else {
+            lines
+                .iter()
+                .skip(by_top.unwrap_or(0))
+                .position(|(_, text)| text.starts_with(label))
+                .map(|idx| idx + by_top.unwrap_or(0))
+        };
+        let start = by_label.or(by_top)?;
+
+        let spacing = lines
+            .windows(2)
+            .map(|pair| pair[1].0 - pair[0].0)
+            .filter(|gap| *gap > 0.0)
+            .fold(f32::INFINITY, f32::min);
+        let starts_with_marker = is_reference_marker(&lines[start].1);
+
+        let mut entry = lines[start].1.clone();
+        for idx in start + 1..lines.len().min(start + MAX_LINES) {
+            let (center, text) = &lines[idx];
+            if spacing.is_finite() && center - lines[idx - 1].0 > spacing * 1.8 {
+                break;
+            }
+            if starts_with_marker && is_reference_marker(text) {
+                break;
+            }
+            if entry.ends_with('-') {
+                entry.pop();
+            } else {
+                entry.push(' ');
+            }
+            entry.push_str(text);
+        }
+        Some(entry)
+    }
+}
+
+/// True for strings like `[12]`, `[3, 4]`, `[5-7]` or a bare `12`.
+pub fn is_citation_label(label: &str) -> bool {
+    let label = label.trim();
+    let inner = label
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(label);
+    !inner.is_empty()
+        && inner.chars().any(|ch| ch.is_ascii_digit())
+        && inner
+            .chars()
+            .all(|ch| ch.is_ascii_digit() || matches!(ch, ',' | '-' | '\u{2013}' | ' '))
+}
+
+fn is_reference_marker(line: &str) -> bool {
+    if let Some(rest) = line.strip_prefix('[') {
+        return rest
+            .split_once(']')
+            .is_some_and(|(number, _)| is_citation_label(number));
+    }
+    line.split_once('.').is_some_and(|(number, _)| {
+        !number.is_empty() && number.chars().all(|ch| ch.is_ascii_digit())
+    })
+}
+
+/// Bibliography entry behind a citation link, shown without leaving the page.
+#[derive(Debug, Clone)]
+pub struct CitationPreview {
+    pub label: String,
+    pub page: usize,
+    pub text: String,
 }
 
 #[derive(Debug, Clone)]
@@ -188,6 +756,12 @@ pub struct PageLine {
     pub center_y: f32,
 }
 
+/// Vertical distance under which two glyph centres belong to the same line.
+const LINE_THRESHOLD: f32 = 0.015;
+/// Horizontal gap, relative to the average glyph width, that separates words
+/// when the text layer has no space between them.
+const WORD_GAP_RATIO: f32 = 0.3;
+
 fn build_line_map(glyphs: &[TextGlyph]) -> (Vec<PageLine>, Vec<usize>) {
     if glyphs.is_empty() {
         return (Vec::new(), Vec::new());
@@ -195,12 +769,11 @@ fn build_line_map(glyphs: &[TextGlyph]) -> (Vec<PageLine>, Vec<usize>) {
     let mut lines = Vec::new();
     let mut glyph_line_index = Vec::with_capacity(glyphs.len());
     let mut last_center: Option<f32> = None;
-    let threshold = 0.015;
 
     for (idx, glyph) in glyphs.iter().enumerate() {
         let center = (glyph.rect.top + glyph.rect.bottom) * 0.5;
         let new_line = match last_center {
-            Some(prev) => (prev - center).abs() > threshold,
+            Some(prev) => (prev - center).abs() > LINE_THRESHOLD,
             None => true,
         };
         if new_line {
@@ -220,8 +793,332 @@ fn build_line_map(glyphs: &[TextGlyph]) -> (Vec<PageLine>, Vec<usize>) {
     (lines, glyph_line_index)
 }
 
+/// Line height assumed for pages without extractable text.
+const DEFAULT_LINE_HEIGHT: f32 = 0.02;
+
+/// Pages that differ between two versions, in order: pages read in both
+/// whose text fingerprint changed, then the pages only one version has.
+/// Pages not read in both versions count as unchanged.
+fn changed_pages(
+    previous: &HashMap<usize, u64>,
+    previous_count: usize,
+    current: &HashMap<usize, u64>,
+    current_count: usize,
+) -> Vec<usize> {
+    let shared = previous_count.min(current_count);
+    let mut pages: Vec<usize> = previous
+        .iter()
+        .filter(|(page, digest)| {
+            **page < shared && current.get(page).is_some_and(|now| now != *digest)
+        })
+        .map(|(page, _)| *page)
+        .collect();
+    pages.sort_unstable();
+    pages.extend(shared..previous_count.max(current_count));
+    pages
+}
+
+/// Share of the view a zoomed-to rectangle fills, leaving a little margin.
+const ZOOM_TO_RECT_FILL: f32 = 0.9;
+
+/// How far from the margins of a column of text, as a fraction of its width,
+/// a link may end and the next one start and still count as wrapped; lines
+/// rarely reach the right margin exactly.
+const WRAP_MARGIN: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FitMode {
+    #[default]
+    Page,
+    Width,
+}
+
+impl FitMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            FitMode::Page => "page",
+            FitMode::Width => "width",
+        }
+    }
+}
+
+/// Visible part of the page as last laid out by the frontend.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ViewGeometry {
+    pub cols: u32,
+    pub rows: u32,
+    pub visible_width: f32,
+    pub visible_height: f32,
+}
+
+/// Which side of the current page is rendered ahead of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PrefetchDirection {
+    #[default]
+    Both,
+    Forward,
+    Backward,
+}
+
+impl PrefetchDirection {
+    pub fn label(self) -> &'static str {
+        match self {
+            PrefetchDirection::Both => "both",
+            PrefetchDirection::Forward => "forward",
+            PrefetchDirection::Backward => "backward",
+        }
+    }
+}
+
+/// Neighbouring pages to render in the background after each draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrefetchPolicy {
+    pub enabled: bool,
+    pub radius: usize,
+    pub direction: PrefetchDirection,
+}
+
+impl Default for PrefetchPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 2,
+            direction: PrefetchDirection::Both,
+        }
+    }
+}
+
+impl PrefetchPolicy {
+    /// Pages to prefetch around `current`, nearest first.
+    pub fn pages(&self, current: usize, page_count: usize) -> Vec<usize> {
+        let mut pages = Vec::new();
+        if !self.enabled {
+            return pages;
+        }
+        for offset in 1..=self.radius {
+            if self.direction != PrefetchDirection::Forward {
+                if let Some(prev) = current.checked_sub(offset) {
+                    pages.push(prev);
+                }
+            }
+            if self.direction != PrefetchDirection::Backward {
+                pages.push(current + offset);
+            }
+        }
+        pages.retain(|page| *page < page_count);
+        pages
+    }
+}
+
+/// How much each document may keep in memory, and how finely pages are
+/// rendered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryLimits {
+    /// Rendered page images kept per document.
+    pub rendered_pages: usize,
+    /// Bytes of extracted page text kept per document.
+    pub text_bytes: usize,
+    /// Fraction of the display resolution pages are rendered at; the
+    /// terminal scales the image up to fill the view.
+    pub resolution: f32,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> Self {
+        Self {
+            rendered_pages: CACHE_CAPACITY,
+            text_bytes: TEXT_CACHE_BYTES,
+            resolution: 1.0,
+        }
+    }
+}
+
+impl MemoryLimits {
+    /// For small containers and phones: only the pages on screen are kept,
+    /// rendered at half resolution, and little page text is held on to.
+    pub fn low() -> Self {
+        Self {
+            rendered_pages: 2,
+            text_bytes: 1024 * 1024,
+            resolution: 0.5,
+        }
+    }
+}
+
+/// How highlight overlays are drawn. Sizes are in page units so highlights
+/// look the same at every render scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HighlightStyle {
+    /// Outline width in PDF points; `0` draws no outline.
+    pub stroke_width: f32,
+    /// Multiplier applied to the fill opacity of every highlight kind.
+    pub fill_opacity: f32,
+}
+
+impl Default for HighlightStyle {
+    fn default() -> Self {
+        Self {
+            stroke_width: 1.0,
+            fill_opacity: 1.0,
+        }
+    }
+}
+
+/// How far one keypress pans or zooms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepSizes {
+    /// Pan distance as a fraction of the page.
+    pub pan: f32,
+    /// Zoom change as a fraction of the current scale: `+` multiplies the
+    /// scale by `1 + zoom`, `-` by `1 - zoom`.
+    pub zoom: f32,
+}
+
+impl Default for StepSizes {
+    fn default() -> Self {
+        Self {
+            pan: 0.1,
+            zoom: 0.1,
+        }
+    }
+}
+
+/// How a selection is wrapped when it is copied as a quote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteFormat {
+    Markdown,
+    Latex,
+}
+
+/// Templates for copying a selection as a quote. `%s` is the selected text,
+/// `%q` the text with every line prefixed by `> `, `%t` the title (or the
+/// file name), `%a` the author, `%p` the page or page range, `%k` the file
+/// name without its extension (for use as a citation key), `%n` a newline
+/// and `%%` a percent sign. In LaTeX quotes the text, title and author are
+/// escaped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuoteTemplates {
+    pub markdown: String,
+    pub latex: String,
+}
+
+impl Default for QuoteTemplates {
+    fn default() -> Self {
+        Self {
+            markdown: "%q%n>%n> — %a, *%t*, p. %p".to_string(),
+            latex: "%% %a, %t%n\\textcite[%p]{%k}:%n\\begin{quote}%n%s%n\\end{quote}".to_string(),
+        }
+    }
+}
+
+impl QuoteTemplates {
+    pub fn get(&self, format: QuoteFormat) -> &str {
+        match format {
+            QuoteFormat::Markdown => &self.markdown,
+            QuoteFormat::Latex => &self.latex,
+        }
+    }
+}
+
+fn escape_latex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(ch);
+            }
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Fills `template` (see [`QuoteTemplates`]) for `text` taken from `pages`
+/// (0-based, inclusive) of the document described by `info`.
+fn fill_quote_template(
+    template: &str,
+    format: QuoteFormat,
+    text: &str,
+    info: &DocumentInfo,
+    pages: (usize, usize),
+) -> String {
+    let escape = |value: &str| match format {
+        QuoteFormat::Markdown => value.to_string(),
+        QuoteFormat::Latex => escape_latex(value),
+    };
+    let stem = info
+        .path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let title = info
+        .metadata
+        .title
+        .as_deref()
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .unwrap_or(&stem);
+    let author = info
+        .metadata
+        .author
+        .as_deref()
+        .map(str::trim)
+        .filter(|author| !author.is_empty())
+        .unwrap_or("unknown author");
+    let page = match (pages, format) {
+        ((first, last), _) if first == last => (first + 1).to_string(),
+        ((first, last), QuoteFormat::Markdown) => format!("{}–{}", first + 1, last + 1),
+        ((first, last), QuoteFormat::Latex) => format!("{}--{}", first + 1, last + 1),
+    };
+
+    let mut out = String::with_capacity(template.len() + text.len());
+    let mut chars = template.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push_str(&escape(text)),
+            Some('q') => {
+                let quoted: Vec<String> = text
+                    .lines()
+                    .map(|line| {
+                        if line.is_empty() {
+                            ">".to_string()
+                        } else {
+                            format!("> {}", escape(line))
+                        }
+                    })
+                    .collect();
+                out.push_str(&quoted.join("\n"));
+            }
+            Some('t') => out.push_str(&escape(title)),
+            Some('a') => out.push_str(&escape(author)),
+            Some('p') => out.push_str(&page),
+            Some('k') => out.push_str(&stem),
+            Some('n') => out.push('\n'),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PersistedDocumentState {
+    /// Layout version the state was written with; see [`decode_state`].
+    #[serde(default)]
+    pub version: u32,
     pub current_page: usize,
     pub scale: f32,
     pub dark_mode: bool,
@@ -230,28 +1127,88 @@ pub struct PersistedDocumentState {
     pub named_marks: HashMap<String, usize>,
     #[serde(default)]
     pub viewport: ViewportOffset,
+    #[serde(default)]
+    pub fit_mode: FitMode,
+    #[serde(default)]
+    pub modified: FieldTimestamps,
+    /// Jump list, oldest first, without the current position.
+    #[serde(default)]
+    pub jumps: Vec<DocumentPosition>,
+    /// Number printed on the first page, for documents whose page numbers
+    /// are offset from their position in the file. Overrides page labels.
+    #[serde(default)]
+    pub first_page_number: Option<i64>,
+    /// Time spent reading, for `:stats`.
+    #[serde(default)]
+    pub stats: ReadingStats,
+    /// Pages to read each day.
+    #[serde(default)]
+    pub daily_goal: Option<usize>,
+    /// Where past sessions ended, oldest first.
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+    /// Fields written by a newer termpdf, kept so saving does not drop them.
+    #[serde(flatten)]
+    pub unknown: serde_json::Map<String, serde_json::Value>,
 }
 
 impl Default for PersistedDocumentState {
     fn default() -> Self {
         Self {
+            version: STATE_VERSION,
             current_page: 0,
             scale: 1.0,
             dark_mode: false,
             marks: HashMap::new(),
             named_marks: HashMap::new(),
             viewport: ViewportOffset::default(),
+            fit_mode: FitMode::default(),
+            modified: FieldTimestamps::default(),
+            jumps: Vec::new(),
+            first_page_number: None,
+            stats: ReadingStats::default(),
+            daily_goal: None,
+            history: Vec::new(),
+            unknown: serde_json::Map::new(),
         }
     }
 }
 
+/// Position remembered by an uppercase mark, which is shared by all documents.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GlobalMark {
+    pub path: PathBuf,
+    pub page: usize,
+    #[serde(default)]
+    pub viewport: ViewportOffset,
+}
+
+pub type GlobalMarks = HashMap<char, GlobalMark>;
+
+/// Uppercase mark keys are global, like vim's file marks.
+pub fn is_global_mark(key: char) -> bool {
+    key.is_ascii_uppercase()
+}
+
 const JUMP_HISTORY_CAPACITY: usize = 128;
+/// Jumps kept in a document's saved state.
+const PERSISTED_JUMPS: usize = 50;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-struct DocumentPosition {
-    page: usize,
-    scale: f32,
-    viewport: ViewportOffset,
+/// Where a view of a document is: its page, zoom and scroll position.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DocumentPosition {
+    pub page: usize,
+    pub scale: f32,
+    #[serde(default)]
+    pub viewport: ViewportOffset,
+}
+
+/// A document's jump list as `:jumps` shows it, oldest first. `current` is
+/// the index of the current position, which is part of `entries`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JumpList {
+    pub entries: Vec<DocumentPosition>,
+    pub current: usize,
 }
 
 #[derive(Debug, Default)]
@@ -303,6 +1260,50 @@ impl JumpHistory {
         None
     }
 
+    /// Every position, oldest first, with `current` between the older and
+    /// the newer ones.
+    fn list(&self, current: DocumentPosition) -> JumpList {
+        let mut entries = self.back_stack.clone();
+        entries.push(current);
+        entries.extend(self.forward_stack.iter().rev());
+        JumpList {
+            entries,
+            current: self.back_stack.len(),
+        }
+    }
+
+    /// Moves to entry `index` of [`list`](Self::list), keeping the positions
+    /// before it to jump back to and those after it to jump forward to.
+    fn jump_to(&mut self, index: usize, current: DocumentPosition) -> Option<DocumentPosition> {
+        let JumpList {
+            mut entries,
+            current: current_index,
+        } = self.list(current);
+        if index == current_index || index >= entries.len() {
+            return None;
+        }
+        let newer = entries.split_off(index + 1);
+        let target = entries.pop()?;
+        self.back_stack = entries;
+        self.forward_stack = newer.into_iter().rev().collect();
+        self.last_known = Some(target);
+        Some(target)
+    }
+
+    /// Positions to save with the document, oldest first: the older and
+    /// the newer jumps without the current position.
+    fn persisted(&self) -> Vec<DocumentPosition> {
+        let mut jumps: Vec<DocumentPosition> = self
+            .back_stack
+            .iter()
+            .chain(self.forward_stack.iter().rev())
+            .copied()
+            .collect();
+        let overflow = jumps.len().saturating_sub(PERSISTED_JUMPS);
+        jumps.drain(..overflow);
+        jumps
+    }
+
     fn push_back(&mut self, position: DocumentPosition) {
         if self.back_stack.last().copied() == Some(position) {
             return;
@@ -332,18 +1333,57 @@ pub struct SearchMatch {
     rects: Vec<NormalizedRect>,
 }
 
+/// Pages a search looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchScope {
+    #[default]
+    Document,
+    /// Only the page that was current when the search started.
+    Page,
+}
+
+impl SearchScope {
+    pub fn label(self) -> &'static str {
+        match self {
+            SearchScope::Document => "document",
+            SearchScope::Page => "page",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct SearchState {
     query: String,
+    scope: SearchScope,
     matches: Vec<SearchMatch>,
+    /// Matches per page as in [`SearchSummary::pages`], counted once when
+    /// the matches arrive.
+    pages: Arc<[(usize, usize)]>,
     current_index: Option<usize>,
 }
 
 #[derive(Debug, Clone)]
 pub struct SearchSummary {
     pub query: String,
+    pub scope: SearchScope,
     pub total: usize,
     pub current_index: Option<usize>,
+    /// The page of the current match.
+    pub current_page: Option<usize>,
+    /// Pages with matches as `(page, matches)`, in the order the matches
+    /// are visited.
+    pub pages: Arc<[(usize, usize)]>,
+}
+
+impl SearchSummary {
+    /// How many matches are on `page`.
+    pub fn matches_on(&self, page: usize) -> usize {
+        self.pages
+            .iter()
+            .filter(|(matched, _)| *matched == page)
+            .map(|(_, count)| count)
+            .sum()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -352,17 +1392,168 @@ pub struct LinkDefinition {
     pub action: LinkAction,
 }
 
-#[derive(Debug, Clone)]
+impl LinkDefinition {
+    /// Joins links that go to the same place when one wraps into the next:
+    /// it ends at the right margin of its column of text and the next starts
+    /// at the left margin of the following line. Documents often split a
+    /// link that wraps across lines into one annotation per line.
+    /// `text` holds the positions of the page's glyphs, from which the
+    /// columns and their margins are worked out; without text nothing is
+    /// joined.
+    pub fn merge_adjacent(
+        links: Vec<LinkDefinition>,
+        text: &[NormalizedRect],
+    ) -> Vec<LinkDefinition> {
+        let columns = reading_order::column_extents(text);
+        if columns.is_empty() {
+            return links;
+        }
+        let mut merged: Vec<LinkDefinition> = Vec::with_capacity(links.len());
+        for link in links {
+            if let Some(previous) = merged.last_mut() {
+                if previous.action == link.action && previous.wraps_into(&link, &columns) {
+                    previous.rects.extend(link.rects);
+                    continue;
+                }
+            }
+            merged.push(link);
+        }
+        merged
+    }
+
+    fn wraps_into(&self, next: &LinkDefinition, columns: &[(f32, f32)]) -> bool {
+        let (Some(last), Some(first)) = (self.rects.last(), next.rects.first()) else {
+            return false;
+        };
+        let (Some(end), Some(start)) = (column_at(columns, last), column_at(columns, first)) else {
+            return false;
+        };
+        let slack = |(left, right): (f32, f32)| (right - left) * WRAP_MARGIN;
+        let line_height = last.bottom - last.top;
+        last.right >= end.1 - slack(end)
+            && first.left <= start.0 + slack(start)
+            && first.top > last.top + line_height * 0.5
+            && first.top <= last.bottom + line_height
+    }
+}
+
+/// The column of text, among `columns` as `(left, right)`, that `rect` lies
+/// in or is nearest to.
+fn column_at(columns: &[(f32, f32)], rect: &NormalizedRect) -> Option<(f32, f32)> {
+    let (x, _) = rect.center();
+    let distance = |(left, right): &(f32, f32)| (left - x).max(x - right).max(0.0);
+    columns
+        .iter()
+        .copied()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum LinkAction {
-    GoTo { page: usize },
-    Uri { uri: String },
+    /// Internal jump; `top` is the destination's distance from the top of the
+    /// page as a fraction of its height, when the document specifies one.
+    GoTo {
+        page: usize,
+        top: Option<f32>,
+    },
+    Uri {
+        uri: String,
+    },
     Unsupported,
 }
 
+/// External URI found in the document, reported once at the first page it
+/// appears on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UriLink {
+    pub page: usize,
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormFieldKind {
+    Text { value: String },
+    Checkbox { checked: bool },
+    RadioButton { checked: bool },
+    Other,
+}
+
+/// Interactive form field reported by a backend. `id` identifies the field on
+/// its page and is passed back to [`DocumentBackend::set_form_field`].
+#[derive(Debug, Clone)]
+pub struct FormField {
+    pub id: usize,
+    pub name: Option<String>,
+    pub rect: NormalizedRect,
+    pub kind: FormFieldKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormFieldValue {
+    Text(String),
+    Checked(bool),
+}
+
+#[derive(Debug, Clone)]
+pub struct FormSummary {
+    pub total: usize,
+    pub current_index: Option<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct LinkSummary {
     pub total: usize,
     pub current_index: Option<usize>,
+    pub filter: LinkFilter,
+}
+
+/// Kinds of links cycled through in link mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkFilter {
+    #[default]
+    All,
+    /// Web and other non-file URIs.
+    Uri,
+    /// Jumps within the document.
+    Internal,
+    /// `file:` URIs and relative paths to other files.
+    File,
+}
+
+impl LinkFilter {
+    pub fn label(self) -> &'static str {
+        match self {
+            LinkFilter::All => "all",
+            LinkFilter::Uri => "uri",
+            LinkFilter::Internal => "internal",
+            LinkFilter::File => "file",
+        }
+    }
+
+    pub fn matches(self, action: &LinkAction) -> bool {
+        match (self, action) {
+            (LinkFilter::All, _) => true,
+            (LinkFilter::Internal, LinkAction::GoTo { .. }) => true,
+            (LinkFilter::Uri, LinkAction::Uri { uri }) => !is_file_uri(uri),
+            (LinkFilter::File, LinkAction::Uri { uri }) => is_file_uri(uri),
+            _ => false,
+        }
+    }
+}
+
+/// `file:` URIs, and references without any scheme, which PDF writers use
+/// for relative links to neighbouring files.
+fn is_file_uri(uri: &str) -> bool {
+    let uri = uri.trim();
+    match uri.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.eq_ignore_ascii_case("file")
+                || scheme.contains('/')
+                // A Windows drive letter rather than a scheme.
+                || (scheme.len() == 1 && scheme.chars().all(|ch| ch.is_ascii_alphabetic()))
+        }
+        None => true,
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -446,6 +1637,16 @@ pub enum SelectionMotion {
     DocumentEnd,
     PageForward,
     PageBackward,
+    /// Start of the next sentence (vim's `)`).
+    SentenceForward,
+    /// Start of the current or previous sentence (vim's `(`).
+    SentenceBackward,
+    /// Selects the word under the cursor (vim's `iw`).
+    InnerWord,
+    /// Selects the word under the cursor and the blanks after it (`aw`).
+    AWord,
+    /// Selects the paragraph under the cursor (`ip`).
+    InnerParagraph,
 }
 
 #[derive(Debug, Clone)]
@@ -455,9 +1656,24 @@ struct LinkEntry {
     action: LinkAction,
 }
 
+#[derive(Debug, Clone)]
+struct FormEntry {
+    page: usize,
+    field: FormField,
+}
+
+#[derive(Debug, Clone)]
+struct FormState {
+    fields: Vec<FormEntry>,
+    current_index: Option<usize>,
+}
+
 #[derive(Debug, Clone)]
 struct LinkState {
+    all: Vec<LinkEntry>,
+    /// The entries of `all` that pass `filter`.
     links: Vec<LinkEntry>,
+    filter: LinkFilter,
     current_index: Option<usize>,
 }
 
@@ -471,12 +1687,16 @@ struct SelectionPoint {
 struct SelectionState {
     anchor: SelectionPoint,
     head: SelectionPoint,
+    /// Selects the rectangle with `anchor` and `head` as corners instead of
+    /// the text in between.
+    block: bool,
 }
 
 #[derive(Debug, Clone)]
 struct SelectionSnapshot {
     start: SelectionPoint,
     end: SelectionPoint,
+    block: bool,
 }
 
 impl SelectionState {
@@ -485,11 +1705,13 @@ impl SelectionState {
             SelectionSnapshot {
                 start: self.head,
                 end: self.anchor,
+                block: self.block,
             }
         } else {
             SelectionSnapshot {
                 start: self.anchor,
                 end: self.head,
+                block: self.block,
             }
         }
     }
@@ -513,22 +1735,42 @@ pub struct DocumentInstance {
     pub backend: Arc<dyn DocumentBackend>,
     pub state: PersistedDocumentState,
     render_cache: Mutex<HashMap<CacheKey, RenderImage>>,
+    render_cache_capacity: usize,
+    render_times: Mutex<RenderTimes>,
+    /// Sizes of the pages rendered so far, for backends that cannot tell
+    /// a page's size without rendering it.
+    rendered_sizes: Mutex<HashMap<usize, PageSize>>,
+    background_renders: Mutex<BackgroundRenders>,
     outline: Vec<OutlineItem>,
+    /// Page labels read from the document by page index; empty when it
+    /// has none.
+    page_labels: Vec<Option<String>>,
     jump_history: JumpHistory,
-    text_cache: Arc<Mutex<HashMap<usize, Arc<PageText>>>>,
+    text_cache: Arc<Mutex<TextCache>>,
     search_state: Option<SearchState>,
     link_state: Option<LinkState>,
+    form_state: Option<FormState>,
     selection_state: Option<SelectionState>,
     visual_cursor: Option<SelectionPoint>,
     last_selection: Option<SelectionSnapshot>,
     visual_column_hint: f32,
+    external_highlights: HashMap<usize, Vec<NormalizedRect>>,
+    /// The second view of a split showing this document twice. It shares
+    /// the backend and caches; only its position differs.
+    other_view: Option<DocumentPosition>,
+    /// The file changed but could not be opened yet, typically because it is
+    /// still being written. The previous version stays on screen meanwhile.
+    rebuilding: bool,
+    /// The DOI found by [`doi`](Self::doi), looked up once per version of
+    /// the file.
+    doi: OnceLock<Option<String>>,
 }
 
 #[derive(Clone)]
 pub struct DocumentSearchContext {
     info: DocumentInfo,
     backend: Arc<dyn DocumentBackend>,
-    text_cache: Arc<Mutex<HashMap<usize, Arc<PageText>>>>,
+    text_cache: Arc<Mutex<TextCache>>,
 }
 
 impl DocumentSearchContext {
@@ -536,19 +1778,64 @@ impl DocumentSearchContext {
         load_cached_page_text(page_index, &self.info, &self.backend, &self.text_cache)
     }
 
-    pub fn build_search_matches(&self, query: &str) -> Result<Vec<SearchMatch>> {
-        let mut matches = Vec::new();
+    /// Like [`DocumentInstance::page_preview`], for overlays that read
+    /// previews only as their rows come on screen.
+    pub fn page_preview(&self, page: usize) -> Option<String> {
+        first_paragraph(&*self.load_page_text(page).ok()?)
+    }
 
+    /// Matches for `query` on `pages`, in page order. Pages go to the
+    /// backend a few at a time: pdfium answers one call at a time, so a
+    /// render asked for during a long search waits for the batch in progress
+    /// rather than for the whole search.
+    pub fn build_search_matches(
+        &self,
+        query: &str,
+        pages: Range<usize>,
+    ) -> Result<Vec<SearchMatch>> {
+        let mut matches = Vec::new();
         if query.is_empty() {
             return Ok(matches);
         }
+        let pages: Vec<usize> = (pages.start..pages.end.min(self.info.page_count)).collect();
+        for chunk in pages.chunks(SEARCH_CHUNK_PAGES) {
+            for (&page, found) in chunk.iter().zip(self.find_on_pages(query, chunk)) {
+                matches.extend(found.into_iter().map(|found| SearchMatch {
+                    page,
+                    rects: found.rects,
+                }));
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Lazily searches the document for `query`, one page at a time, so
+    /// callers can stop early or stream results as they arrive.
+    pub fn search_iter(&self, query: &str, options: SearchOptions) -> SearchIter {
+        let pages = options.pages.clone().unwrap_or(0..self.info.page_count);
+        let pages = pages.start..pages.end.min(self.info.page_count);
+        SearchIter {
+            context: self.clone(),
+            query: query.to_string(),
+            snippet_context: options.context,
+            pages: if query.is_empty() { 0..0 } else { pages },
+            pending: VecDeque::new(),
+        }
+    }
 
-        let query_lower = query.to_lowercase();
-        let step = query_lower.len().max(1);
+    fn find_on_page(&self, query: &str, page: usize) -> Vec<PageMatch> {
+        self.find_on_pages(query, &[page]).pop().unwrap_or_default()
+    }
 
-        for page in 0..self.info.page_count {
-            let mut page_matches = match self.backend.search_page(page, query) {
-                Ok(rect_sets) => rect_sets,
+    /// The matches on each of `pages`. Pages the backend finds nothing on are
+    /// searched in their text, which is loaded in one batch.
+    fn find_on_pages(&self, query: &str, pages: &[usize]) -> Vec<Vec<PageMatch>> {
+        let found = self.backend.search_pages(pages, query);
+        let mut unmatched = Vec::new();
+        for (&page, found) in pages.iter().zip(&found) {
+            match found {
+                Ok(rect_sets) if !rect_sets.is_empty() => {}
+                Ok(_) => unmatched.push(page),
                 Err(err) => {
                     warn!(
                         ?err,
@@ -556,73 +1843,241 @@ impl DocumentSearchContext {
                         path = %self.info.path.display(),
                         "backend search failed; falling back to text search"
                     );
-                    Vec::new()
-                }
-            };
-
-            if !page_matches.is_empty() {
-                for rects in page_matches.drain(..) {
-                    let rects: Vec<NormalizedRect> = rects
-                        .into_iter()
-                        .map(|rect| rect.clamp())
-                        .filter(|rect| rect.is_valid())
-                        .collect();
-                    matches.push(SearchMatch { page, rects });
+                    unmatched.push(page);
                 }
-                continue;
             }
+        }
+        let texts = self.load_page_texts(&unmatched);
 
-            match self.load_page_text(page) {
-                Ok(page_text) => {
-                    if page_text.text.is_empty() {
-                        continue;
-                    }
+        pages
+            .iter()
+            .zip(found)
+            .map(|(&page, found)| match found {
+                Ok(rect_sets) if !rect_sets.is_empty() => rect_sets
+                    .into_iter()
+                    .map(|rects| PageMatch {
+                        rects: rects
+                            .into_iter()
+                            .map(|rect| rect.clamp())
+                            .filter(|rect| rect.is_valid())
+                            .collect(),
+                        range: None,
+                    })
+                    .collect(),
+                _ => self.find_in_text(query, page, texts.get(&page).cloned()),
+            })
+            .collect()
+    }
 
-                    let lower = page_text.text.to_lowercase();
-                    let mut offset = 0usize;
-                    while offset < lower.len() {
-                        if let Some(pos) = lower[offset..].find(&query_lower) {
-                            let absolute = offset + pos;
-                            matches.push(SearchMatch {
-                                page,
-                                rects: Vec::new(),
-                            });
-                            let next = absolute.saturating_add(step);
-                            if next <= offset {
-                                break;
-                            }
-                            offset = next;
-                        } else {
-                            break;
-                        }
+    /// The text of `pages`, from the cache or else loaded in one batch.
+    /// Pages whose text failed to load are left out.
+    fn load_page_texts(&self, pages: &[usize]) -> HashMap<usize, Arc<PageText>> {
+        let mut texts = HashMap::new();
+        let mut missing = Vec::new();
+        {
+            let mut cache = self.text_cache.lock();
+            for &page in pages.iter().filter(|page| **page < self.info.page_count) {
+                match cache.get(page) {
+                    Some(text) => {
+                        texts.insert(page, text);
                     }
+                    None => missing.push(page),
                 }
-                Err(err) => {
-                    warn!(
-                        ?err,
-                        page,
-                        path = %self.info.path.display(),
-                        "failed to extract text for search"
-                    );
-                }
             }
         }
+        if missing.is_empty() {
+            return texts;
+        }
+        let loaded = self.backend.page_texts(&missing);
+        let mut cache = self.text_cache.lock();
+        for (page, text) in missing.into_iter().zip(loaded) {
+            // Failures are reported when the page's text is asked for.
+            if let Ok(text) = text {
+                let text = Arc::new(text);
+                cache.insert(page, Arc::clone(&text));
+                texts.insert(page, text);
+            }
+        }
+        texts
+    }
 
-        Ok(matches)
+    fn find_in_text(
+        &self,
+        query: &str,
+        page: usize,
+        loaded: Option<Arc<PageText>>,
+    ) -> Vec<PageMatch> {
+        let mut matches = Vec::new();
+        let page_text = match loaded.map_or_else(|| self.load_page_text(page), Ok) {
+            Ok(page_text) => page_text,
+            Err(err) => {
+                warn!(
+                    ?err,
+                    page,
+                    path = %self.info.path.display(),
+                    "failed to extract text for search"
+                );
+                return matches;
+            }
+        };
+        let query_lower = query.to_lowercase();
+        let step = query_lower.len().max(1);
+        let (lower, origins) = lowercase_with_origins(&page_text.text);
+        let mut offset = 0usize;
+        while offset < lower.len() {
+            let Some(pos) = lower[offset..].find(&query_lower) else {
+                break;
+            };
+            let absolute = offset + pos;
+            // Lowercasing can change the length of the text ("İ" becomes
+            // two characters), so the match is mapped back to the bytes of
+            // the page text that the glyphs point into.
+            let last = origins[absolute + step - 1];
+            let end = last
+                + page_text.text[last..]
+                    .chars()
+                    .next()
+                    .map_or(0, char::len_utf8);
+            let range = origins[absolute]..end;
+            matches.push(PageMatch {
+                rects: page_text.rects_for_range(range.clone()),
+                range: Some(range),
+            });
+            let next = absolute.saturating_add(step);
+            if next <= offset {
+                break;
+            }
+            offset = next;
+        }
+        matches
+    }
+
+    fn snippet(&self, page: usize, found: &PageMatch, context: usize) -> String {
+        let Ok(page_text) = self.load_page_text(page) else {
+            return String::new();
+        };
+        let range = found
+            .range
+            .clone()
+            .or_else(|| page_text.range_in_rects(&found.rects));
+        match range {
+            Some(range) => snippet_around(&page_text.text, range, context),
+            None => String::new(),
+        }
+    }
+}
+
+struct PageMatch {
+    rects: Vec<NormalizedRect>,
+    /// Byte range in the page text, when known.
+    range: Option<Range<usize>>,
+}
+
+/// Settings for [`DocumentSearchContext::search_iter`].
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Pages to search; the whole document when `None`.
+    pub pages: Option<Range<usize>>,
+    /// Characters of surrounding text on each side of a match in its snippet.
+    pub context: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            pages: None,
+            context: 30,
+        }
+    }
+}
+
+/// A match found by [`SearchIter`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub page: usize,
+    pub rects: Vec<NormalizedRect>,
+    /// The match and the text around it, on one line.
+    pub snippet: String,
+}
+
+/// Matches in page order. Pages are searched as the iterator advances, and
+/// pages whose text cannot be read are skipped.
+pub struct SearchIter {
+    context: DocumentSearchContext,
+    query: String,
+    snippet_context: usize,
+    pages: Range<usize>,
+    pending: VecDeque<SearchHit>,
+}
+
+impl Iterator for SearchIter {
+    type Item = SearchHit;
+
+    fn next(&mut self) -> Option<SearchHit> {
+        loop {
+            if let Some(hit) = self.pending.pop_front() {
+                return Some(hit);
+            }
+            let page = self.pages.next()?;
+            for found in self.context.find_on_page(&self.query, page) {
+                let snippet = self.context.snippet(page, &found, self.snippet_context);
+                self.pending.push_back(SearchHit {
+                    page,
+                    rects: found.rects,
+                    snippet,
+                });
+            }
+        }
+    }
+}
+
+/// `text` in lowercase, with the offset in `text` of the character each
+/// byte of the lowercase text comes from.
+fn lowercase_with_origins(text: &str) -> (String, Vec<usize>) {
+    let mut lower = String::with_capacity(text.len());
+    let mut origins = Vec::with_capacity(text.len());
+    for (offset, ch) in text.char_indices() {
+        for lower_ch in ch.to_lowercase() {
+            lower.push(lower_ch);
+            origins.resize(lower.len(), offset);
+        }
     }
+    (lower, origins)
+}
+
+/// `text[range]` with up to `context` characters on either side, whitespace
+/// collapsed to single spaces.
+fn snippet_around(text: &str, range: Range<usize>, context: usize) -> String {
+    let start = range.start.min(text.len());
+    let end = range.end.clamp(start, text.len());
+    let before = text[..start]
+        .char_indices()
+        .rev()
+        .take(context)
+        .last()
+        .map_or(start, |(index, _)| index);
+    let after = text[end..]
+        .char_indices()
+        .nth(context)
+        .map_or(text.len(), |(index, _)| end + index);
+    text.get(before..after)
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 fn load_cached_page_text(
     page_index: usize,
     info: &DocumentInfo,
     backend: &Arc<dyn DocumentBackend>,
-    cache: &Arc<Mutex<HashMap<usize, Arc<PageText>>>>,
+    cache: &Arc<Mutex<TextCache>>,
 ) -> Result<Arc<PageText>> {
     if page_index >= info.page_count {
         return Err(anyhow!("page {} out of range", page_index));
     }
 
-    if let Some(text) = cache.lock().get(&page_index).cloned() {
+    if let Some(text) = cache.lock().get(page_index) {
         return Ok(text);
     }
 
@@ -631,6 +2086,14 @@ fn load_cached_page_text(
     Ok(text)
 }
 
+/// The first paragraph of `text` on one line.
+fn first_paragraph(text: &PageText) -> Option<String> {
+    text.paragraphs()
+        .into_iter()
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .find(|paragraph| !paragraph.is_empty())
+}
+
 fn glyph_near_point(text: &PageText, x: f32, y: f32) -> usize {
     if text.glyphs.is_empty() {
         return 0;
@@ -660,11 +2123,174 @@ fn is_word_char(ch: char) -> bool {
     ch.is_alphanumeric() || ch == '_'
 }
 
+/// Appends a hard-wrapped line to `paragraph`, rejoining a word hyphenated
+/// across the break.
+fn join_wrapped_line(paragraph: &mut String, line: &str) {
+    if paragraph.is_empty() {
+        paragraph.push_str(line);
+    } else if paragraph.ends_with('-') && line.chars().next().is_some_and(char::is_lowercase) {
+        paragraph.pop();
+        paragraph.push_str(line);
+    } else {
+        paragraph.push(' ');
+        paragraph.push_str(line);
+    }
+}
+
+fn is_closing_punctuation(ch: char) -> bool {
+    matches!(ch, ')' | ']' | '"' | '\'' | '’' | '”')
+}
+
+/// What a glyph is for word text objects: vim treats runs of word
+/// characters, of other punctuation and of blanks as separate words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Punctuation,
+}
+
+impl CharClass {
+    fn of(ch: char) -> Self {
+        if ch.is_whitespace() {
+            Self::Blank
+        } else if is_word_char(ch) {
+            Self::Word
+        } else {
+            Self::Punctuation
+        }
+    }
+}
+
 impl DocumentInstance {
     fn page_text_entry(&self, page_index: usize) -> Result<Arc<PageText>> {
         load_cached_page_text(page_index, &self.info, &self.backend, &self.text_cache)
     }
 
+    /// Scrolls the zoomed page by `lines` text lines (negative scrolls up).
+    fn scroll_lines(&mut self, lines: isize, view: ViewGeometry) -> bool {
+        let scrollable = 1.0 - view.visible_height;
+        if lines == 0 || scrollable <= f32::EPSILON {
+            return false;
+        }
+        let line_height = self
+            .page_text_entry(self.state.current_page)
+            .ok()
+            .and_then(|text| text.median_line_height())
+            .unwrap_or(DEFAULT_LINE_HEIGHT);
+        let delta = lines as f32 * line_height / scrollable;
+        self.state.viewport.adjust(0.0, delta)
+    }
+
+    /// Applies [`Command::ScrollScreens`]: whether the view moved, or the
+    /// end of the document that stopped it.
+    fn scroll_screens(
+        &mut self,
+        screens: f32,
+        view: ViewGeometry,
+    ) -> std::result::Result<bool, MotionBoundary> {
+        if screens == 0.0 {
+            return Ok(false);
+        }
+        let scrollable = 1.0 - view.visible_height;
+        if scrollable > f32::EPSILON {
+            let delta = screens * view.visible_height / scrollable;
+            if self.state.viewport.adjust(0.0, delta) {
+                return Ok(true);
+            }
+        }
+        if screens > 0.0 {
+            if self.state.current_page + 1 >= self.info.page_count {
+                return Err(MotionBoundary::LastPage);
+            }
+            self.state.current_page += 1;
+            self.state.viewport.y = 0.0;
+        } else {
+            if self.state.current_page == 0 {
+                return Err(MotionBoundary::FirstPage);
+            }
+            self.state.current_page -= 1;
+            self.state.viewport.y = 1.0;
+        }
+        Ok(true)
+    }
+
+    /// Applies [`Command::ZoomToRect`]: zooms and scrolls so `rect` fills
+    /// the view laid out in `view`. Whether anything changed.
+    fn zoom_to_rect(&mut self, rect: NormalizedRect, view: ViewGeometry) -> bool {
+        let rect = rect.clamp();
+        if !rect.is_valid() {
+            return false;
+        }
+        let (base_width, base_height) = self.unzoomed_view(view);
+        let scale = (ZOOM_TO_RECT_FILL
+            * (base_width / (rect.right - rect.left)).min(base_height / (rect.bottom - rect.top)))
+        .clamp(1.0, 4.0);
+        let previous = (self.state.scale, self.state.viewport);
+        self.zoom_centered_on(scale, rect.center(), view);
+        previous != (self.state.scale, self.state.viewport)
+    }
+
+    /// Share of the page's width and height on screen without any zoom,
+    /// given the view laid out at the current scale.
+    fn unzoomed_view(&self, view: ViewGeometry) -> (f32, f32) {
+        let zoom = self.state.scale.max(1.0);
+        let base = |visible: f32| {
+            if visible > 0.0 {
+                (visible * zoom).min(1.0)
+            } else {
+                1.0
+            }
+        };
+        (base(view.visible_width), base(view.visible_height))
+    }
+
+    /// Sets the scale and scrolls so the page point `center` is in the
+    /// middle of the view, as far as the page edges allow.
+    fn zoom_centered_on(&mut self, scale: f32, center: (f32, f32), view: ViewGeometry) {
+        let (base_width, base_height) = self.unzoomed_view(view);
+        let zoom = scale.max(1.0);
+        let offset = |visible: f32, center: f32| {
+            if visible >= 1.0 {
+                0.0
+            } else {
+                ((center - visible / 2.0) / (1.0 - visible)).clamp(0.0, 1.0)
+            }
+        };
+        self.state.scale = scale;
+        self.state.viewport = ViewportOffset {
+            x: offset(base_width / zoom, center.0),
+            y: offset(base_height / zoom, center.1),
+        };
+        self.normalize_viewport();
+    }
+
+    /// What zooming keeps in place: the visual cursor, or else the current
+    /// search match, when on the current page.
+    fn zoom_focus(&self) -> Option<NormalizedRect> {
+        self.visual_cursor_rect()
+            .or_else(|| self.current_match_rect())
+    }
+
+    /// Bounding box of the current search match, if it is on this page.
+    fn current_match_rect(&self) -> Option<NormalizedRect> {
+        let state = self.search_state.as_ref()?;
+        let current = state.matches.get(state.current_index?)?;
+        if current.page != self.state.current_page {
+            return None;
+        }
+        current
+            .rects
+            .iter()
+            .copied()
+            .reduce(|bounds, rect| NormalizedRect {
+                left: bounds.left.min(rect.left),
+                top: bounds.top.min(rect.top),
+                right: bounds.right.max(rect.right),
+                bottom: bounds.bottom.max(rect.bottom),
+            })
+    }
+
     fn ensure_visual_cursor(&mut self) -> Result<bool> {
         if let Some(point) = self.visual_cursor {
             let clamped = self.clamp_point(point)?;
@@ -695,10 +2321,55 @@ impl DocumentInstance {
         self.selection_state = Some(SelectionState {
             anchor: point,
             head: point,
+            block: false,
         });
         Ok(true)
     }
 
+    /// Starts a block selection, or switches the current selection between
+    /// block and character-wise.
+    fn toggle_block_selection(&mut self) -> Result<bool> {
+        if let Some(state) = self.selection_state.as_mut() {
+            state.block = !state.block;
+            return Ok(true);
+        }
+        let started = self.start_selection()?;
+        if let Some(state) = self.selection_state.as_mut() {
+            state.block = true;
+        }
+        Ok(started)
+    }
+
+    /// Page and rectangle of a block selection. The rectangle has the glyphs
+    /// at both ends as corners and stays on the page where the selection
+    /// starts; when the end is on a later page it reaches the page bottom.
+    fn block_rect(&self, snapshot: &SelectionSnapshot) -> Option<(usize, NormalizedRect)> {
+        let corner = |point: SelectionPoint| {
+            let page_text = self.page_text_entry(point.page).ok()?;
+            let index = point
+                .glyph_index
+                .min(page_text.glyph_count().checked_sub(1)?);
+            Some(page_text.glyphs[index].rect).filter(NormalizedRect::is_valid)
+        };
+        let (start, end) = snapshot.points();
+        let first = corner(start)?;
+        let last = corner(end)?;
+        let bottom = if end.page > start.page {
+            1.0
+        } else {
+            first.bottom.max(last.bottom)
+        };
+        Some((
+            start.page,
+            NormalizedRect {
+                left: first.left.min(last.left),
+                top: first.top.min(last.top),
+                right: first.right.max(last.right),
+                bottom,
+            },
+        ))
+    }
+
     fn initial_cursor_point(&self) -> Result<SelectionPoint> {
         if let Some(point) = self.visual_cursor {
             return self.clamp_point(point);
@@ -900,11 +2571,101 @@ impl DocumentInstance {
         let steps = count.max(1);
         for _ in 0..steps {
             moved |= self.skip_while(point, |ch| !is_word_char(ch), forward)?;
-            moved |= self.skip_while(point, |ch| is_word_char(ch), forward)?;
+            moved |= self.skip_while(point, is_word_char, forward)?;
+        }
+        Ok(moved)
+    }
+
+    fn move_sentence(
+        &self,
+        point: &mut SelectionPoint,
+        count: usize,
+        forward: bool,
+    ) -> Result<bool> {
+        let mut moved = false;
+        for _ in 0..count.max(1) {
+            let mut page = point.page;
+            let target = loop {
+                let starts = self.page_text_entry(page)?.sentence_starts();
+                let found = match (forward, page == point.page) {
+                    (true, true) => starts.into_iter().find(|&idx| idx > point.glyph_index),
+                    (true, false) => starts.first().copied(),
+                    (false, true) => starts
+                        .into_iter()
+                        .rev()
+                        .find(|&idx| idx < point.glyph_index),
+                    (false, false) => starts.last().copied(),
+                };
+                if let Some(idx) = found {
+                    break Some((page, idx));
+                }
+                if forward && page + 1 < self.info.page_count {
+                    page += 1;
+                } else if !forward && page > 0 {
+                    page -= 1;
+                } else {
+                    break None;
+                }
+            };
+            let Some((page, glyph_index)) = target else {
+                break;
+            };
+            point.page = page;
+            point.glyph_index = glyph_index;
+            moved = true;
         }
         Ok(moved)
     }
 
+    /// Selects a text object around `cursor`. A selection that already
+    /// starts before the object is extended to cover it, as in vim.
+    fn select_text_object(
+        &mut self,
+        motion: SelectionMotion,
+        cursor: SelectionPoint,
+        count: usize,
+    ) -> Result<bool> {
+        let page_text = self.page_text_entry(cursor.page)?;
+        let range = match motion {
+            SelectionMotion::InnerWord => page_text.word_range(cursor.glyph_index, count, false),
+            SelectionMotion::AWord => page_text.word_range(cursor.glyph_index, count, true),
+            _ => page_text.paragraph_range(cursor.glyph_index, count),
+        };
+        let Some(range) = range else {
+            return Ok(false);
+        };
+        let mut anchor = SelectionPoint {
+            page: cursor.page,
+            glyph_index: range.start,
+        };
+        if let Some(state) = self.selection_state.as_ref() {
+            let start = state.normalized().start;
+            if state.anchor != state.head && compare_points(start, anchor) == Ordering::Less {
+                anchor = start;
+            }
+        }
+        let head = SelectionPoint {
+            page: cursor.page,
+            glyph_index: range.end,
+        };
+        let next = SelectionState {
+            anchor,
+            head,
+            block: false,
+        };
+        if self
+            .selection_state
+            .as_ref()
+            .is_some_and(|state| state.anchor == next.anchor && state.head == next.head)
+        {
+            return Ok(false);
+        }
+        self.selection_state = Some(next);
+        self.visual_cursor = Some(head);
+        self.update_column_hint(head);
+        Ok(true)
+    }
+
     fn move_to_line_boundary(&self, point: &mut SelectionPoint, to_start: bool) -> Result<bool> {
         let page_text = self.page_text_entry(point.page)?;
         if page_text.line_map.is_empty() {
@@ -1021,6 +2782,7 @@ impl DocumentInstance {
         self.selection_state = Some(SelectionState {
             anchor: start,
             head: end,
+            block: snapshot.block,
         });
         self.visual_cursor = Some(end);
         self.update_column_hint(end);
@@ -1044,9 +2806,26 @@ impl DocumentInstance {
         snapshot: &SelectionSnapshot,
         page_index: usize,
     ) -> Option<Highlights> {
-        let (start, end) = snapshot.points();
-        if page_index < start.page || page_index > end.page {
-            return None;
+        if snapshot.block {
+            let (page, rect) = self.block_rect(snapshot)?;
+            if page != page_index {
+                return None;
+            }
+            let page_text = self.page_text_entry(page).ok()?;
+            let mut highlights = Highlights::default();
+            for run in page_text.block_runs(&rect) {
+                highlights.current.extend(
+                    page_text.glyphs[run]
+                        .iter()
+                        .map(|glyph| glyph.rect)
+                        .filter(NormalizedRect::is_valid),
+                );
+            }
+            return (!highlights.is_empty()).then_some(highlights);
+        }
+        let (start, end) = snapshot.points();
+        if page_index < start.page || page_index > end.page {
+            return None;
         }
         let page_text = self.page_text_entry(page_index).ok()?;
         let mut highlights = Highlights::default();
@@ -1076,6 +2855,12 @@ impl DocumentInstance {
     }
 
     fn extract_selection_text(&self, snapshot: &SelectionSnapshot) -> Result<String> {
+        if snapshot.block {
+            let Some((page, rect)) = self.block_rect(snapshot) else {
+                return Ok(String::new());
+            };
+            return Ok(self.page_text_entry(page)?.block_text(&rect));
+        }
         let (start, end) = snapshot.points();
         let mut buffer = String::new();
         let mut page = start.page;
@@ -1093,13 +2878,12 @@ impl DocumentInstance {
                 glyph_count
             };
             if start_idx < end_idx {
-                let start_offset = page_text.boundary_offset(start_idx);
-                let end_offset = page_text.boundary_offset(end_idx);
-                if end_offset > start_offset && end_offset <= page_text.text.len() {
+                let text = page_text.glyph_text(start_idx..end_idx);
+                if !text.is_empty() {
                     if !buffer.is_empty() {
                         buffer.push('\n');
                     }
-                    buffer.push_str(&page_text.text[start_offset..end_offset]);
+                    buffer.push_str(&text);
                 }
             }
             if page == end.page {
@@ -1122,6 +2906,17 @@ impl DocumentInstance {
         let mut changed = false;
         let steps = count.max(1);
         match motion {
+            SelectionMotion::InnerWord
+            | SelectionMotion::AWord
+            | SelectionMotion::InnerParagraph => {
+                return self.select_text_object(motion, cursor, steps);
+            }
+            SelectionMotion::SentenceForward => {
+                changed = self.move_sentence(&mut cursor, steps, true)?;
+            }
+            SelectionMotion::SentenceBackward => {
+                changed = self.move_sentence(&mut cursor, steps, false)?;
+            }
             SelectionMotion::Left => {
                 changed = self.adjust_point(&mut cursor, -(steps as isize))?;
             }
@@ -1193,36 +2988,152 @@ impl DocumentInstance {
         Ok(changed)
     }
 
+    fn follow_visual_cursor(&mut self, scrolloff: usize, view: ViewGeometry) -> bool {
+        match self.visual_cursor_rect() {
+            Some(rect) => self.follow_rect(rect, scrolloff, view),
+            None => false,
+        }
+    }
+
+    /// Pans so the current search match on this page is on screen.
+    fn follow_search_match(&mut self, scrolloff: usize, view: ViewGeometry) -> bool {
+        let panned = match self.current_match_rect() {
+            Some(rect) => self.follow_rect(rect, scrolloff, view),
+            None => false,
+        };
+        if panned {
+            self.sync_jump_position();
+        }
+        panned
+    }
+
+    /// Pans as little as possible to bring `rect` (with `scrolloff` cells
+    /// around it) into the zoomed view.
+    fn follow_rect(&mut self, rect: NormalizedRect, scrolloff: usize, view: ViewGeometry) -> bool {
+        let viewport = self.state.viewport;
+        let next = ViewportOffset {
+            x: follow_span(
+                viewport.x,
+                view.visible_width,
+                rect.left,
+                rect.right,
+                scrolloff,
+                view.cols,
+            ),
+            y: follow_span(
+                viewport.y,
+                view.visible_height,
+                rect.top,
+                rect.bottom,
+                scrolloff,
+                view.rows,
+            ),
+        };
+        if (next.x - viewport.x).abs() > f32::EPSILON || (next.y - viewport.y).abs() > f32::EPSILON
+        {
+            self.state.viewport = next;
+            return true;
+        }
+        false
+    }
+
     pub fn new(
         info: DocumentInfo,
         backend: Arc<dyn DocumentBackend>,
         state: PersistedDocumentState,
         outline: Vec<OutlineItem>,
     ) -> Self {
+        let page_labels = load_page_labels(backend.as_ref());
         let mut instance = Self {
             info,
             backend,
             state,
             render_cache: Mutex::new(HashMap::new()),
+            render_cache_capacity: CACHE_CAPACITY,
+            render_times: Mutex::new(RenderTimes::default()),
+            rendered_sizes: Mutex::new(HashMap::new()),
+            background_renders: Mutex::new(BackgroundRenders::default()),
             outline,
+            page_labels,
             jump_history: JumpHistory::default(),
-            text_cache: Arc::new(Mutex::new(HashMap::new())),
+            text_cache: Arc::new(Mutex::new(TextCache::default())),
             search_state: None,
             link_state: None,
+            form_state: None,
             selection_state: None,
             visual_cursor: None,
             last_selection: None,
             visual_column_hint: 0.5,
+            external_highlights: HashMap::new(),
+            other_view: None,
+            rebuilding: false,
+            doi: OnceLock::new(),
         };
+        instance.label_outline();
         let initial = instance.current_position();
         instance.jump_history.record_initial(initial);
+        // Jumps saved by an earlier run are all older than where it left off.
+        let page_count = instance.info.page_count;
+        for position in instance.state.jumps.clone() {
+            if position.page < page_count {
+                instance.jump_history.push_back(position);
+            }
+        }
         instance
     }
 
+    pub fn is_rebuilding(&self) -> bool {
+        self.rebuilding
+    }
+
+    /// Fingerprints of the pages whose text was read since the last load,
+    /// handed over for comparison with the next version.
+    fn take_page_digests(&self) -> HashMap<usize, u64> {
+        std::mem::take(&mut self.text_cache.lock().digests)
+    }
+
+    /// Rereads the pages `previous` has fingerprints for and returns the
+    /// pages whose text differs from that version, with the pages only one
+    /// version has; pages never read are not compared.
+    fn changed_pages_since(
+        &self,
+        previous: &HashMap<usize, u64>,
+        previous_count: usize,
+    ) -> Vec<usize> {
+        let mut read: Vec<usize> = previous.keys().copied().collect();
+        read.sort_unstable();
+        self.search_context().load_page_texts(&read);
+        let current = self.text_cache.lock().digests.clone();
+        changed_pages(previous, previous_count, &current, self.info.page_count)
+    }
+
+    /// Moves to the first of `changed` pages. Whether the page changed.
+    fn jump_to_first_change(&mut self, changed: &[usize]) -> bool {
+        let Some(last) = self.info.page_count.checked_sub(1) else {
+            return false;
+        };
+        let Some(page) = changed.first().map(|page| (*page).min(last)) else {
+            return false;
+        };
+        if page == self.state.current_page {
+            return false;
+        }
+        let from = self.current_position();
+        self.state.current_page = page;
+        self.state.viewport.reset();
+        self.record_jump_from(from);
+        self.sync_jump_position();
+        true
+    }
+
     pub fn current_page(&self) -> usize {
         self.state.current_page
     }
 
+    pub fn current_page_text(&self) -> Result<Arc<PageText>> {
+        self.page_text_entry(self.state.current_page)
+    }
+
     pub fn search_context(&self) -> DocumentSearchContext {
         DocumentSearchContext {
             info: self.info.clone(),
@@ -1244,32 +3155,142 @@ impl DocumentInstance {
         )
     }
 
+    /// Renders the page of another view of this document, e.g. the
+    /// [`other_view`](Self::other_view) of a split.
+    pub fn render_view(&self, view: DocumentPosition, scale: f32) -> Result<RenderImage> {
+        self.render_page_internal(
+            view.page,
+            scale,
+            self.state.dark_mode,
+            self.state.current_page,
+        )
+    }
+
+    /// Renders another page the way [`render_with_scale`](Self::render_with_scale)
+    /// would once it is current, e.g. to get a neighbour ready for a page turn.
+    pub fn render_page_with_scale(&self, page: usize, scale: f32) -> Result<RenderImage> {
+        self.render_page_internal(page, scale, self.state.dark_mode, self.state.current_page)
+    }
+
     pub fn reload(
         &mut self,
         info: DocumentInfo,
         backend: Arc<dyn DocumentBackend>,
         outline: Vec<OutlineItem>,
     ) {
-        let previous_query = self.search_state.as_ref().map(|state| state.query.clone());
+        let previous_query = self
+            .search_state
+            .as_ref()
+            .map(|state| (state.query.clone(), state.scope));
+        // Glyph indices may shift in the new file; carry the selection over
+        // as page and text offset instead.
+        let selection = self.selection_state.take().and_then(|selection| {
+            Some((
+                self.text_offset(selection.anchor)?,
+                self.text_offset(selection.head)?,
+                selection.block,
+            ))
+        });
+        let cursor = self
+            .visual_cursor
+            .take()
+            .and_then(|point| self.text_offset(point));
+        let last_selection = self.last_selection.take().and_then(|snapshot| {
+            Some((
+                self.text_offset(snapshot.start)?,
+                self.text_offset(snapshot.end)?,
+                snapshot.block,
+            ))
+        });
 
+        self.page_labels = load_page_labels(backend.as_ref());
         self.info = info;
         self.backend = backend;
         self.outline = outline;
+        self.label_outline();
 
-        self.render_cache.lock().clear();
+        self.clear_renders();
+        *self.render_times.lock() = RenderTimes::default();
+        self.rendered_sizes.lock().clear();
         self.text_cache.lock().clear();
         self.search_state = None;
         self.link_state = None;
-        self.selection_state = None;
-        self.visual_cursor = None;
-        self.visual_column_hint = 0.5;
-        self.last_selection = None;
+        self.form_state = None;
+        self.rebuilding = false;
+        self.doi = OnceLock::new();
+
+        self.clamp_state_to_pages();
+        self.selection_state = selection.and_then(|(anchor, head, block)| {
+            Some(SelectionState {
+                anchor: self.point_at_offset(anchor)?,
+                head: self.point_at_offset(head)?,
+                block,
+            })
+        });
+        self.visual_cursor = cursor.and_then(|offset| self.point_at_offset(offset));
+        self.last_selection = last_selection.and_then(|(start, end, block)| {
+            Some(SelectionSnapshot {
+                start: self.point_at_offset(start)?,
+                end: self.point_at_offset(end)?,
+                block,
+            })
+        });
+        self.external_highlights
+            .retain(|page, _| *page < self.info.page_count);
+
+        if let Some((query, scope)) = previous_query {
+            if let Err(err) = self.perform_search(query, scope) {
+                trace!(
+                    ?err,
+                    path = %self.info.path.display(),
+                    "failed to rebuild search state after reload"
+                );
+            }
+        }
+
+        self.sync_jump_position();
+    }
+    /// Page and byte offset into the page text of a selection point.
+    fn text_offset(&self, point: SelectionPoint) -> Option<(usize, usize)> {
+        let page_text = self.page_text_entry(point.page).ok()?;
+        let glyph = page_text
+            .glyphs
+            .get(point.glyph_index)
+            .or(page_text.glyphs.last())?;
+        Some((point.page, glyph.range.start))
+    }
+
+    /// Selection point of the first glyph at or after `offset` on `page`.
+    fn point_at_offset(&self, (page, offset): (usize, usize)) -> Option<SelectionPoint> {
+        if page >= self.info.page_count {
+            return None;
+        }
+        let page_text = self.page_text_entry(page).ok()?;
+        let last = page_text.glyph_count().checked_sub(1)?;
+        let glyph_index = page_text
+            .glyphs
+            .iter()
+            .position(|glyph| glyph.range.start >= offset)
+            .unwrap_or(last);
+        Some(SelectionPoint { page, glyph_index })
+    }
+
+    /// Replaces the persisted state, e.g. with one saved in a session.
+    pub fn restore_state(&mut self, state: PersistedDocumentState) {
+        self.state = state;
+        self.clamp_state_to_pages();
+        self.sync_jump_position();
+    }
 
+    fn clamp_state_to_pages(&mut self) {
         if self.info.page_count == 0 {
             self.state.current_page = 0;
         } else if self.state.current_page >= self.info.page_count {
             self.state.current_page = self.info.page_count - 1;
         }
+        if let Some(view) = self.other_view.as_mut() {
+            view.page = view.page.min(self.info.page_count.saturating_sub(1));
+        }
         self.state
             .marks
             .retain(|_, page| *page < self.info.page_count);
@@ -1277,29 +3298,14 @@ impl DocumentInstance {
             .named_marks
             .retain(|_, page| *page < self.info.page_count);
 
-        if self.state.scale <= 1.0 + f32::EPSILON {
-            self.state.viewport.reset();
-        } else {
-            self.state.viewport.clamp();
-        }
-
-        if let Some(query) = previous_query {
-            if let Err(err) = self.perform_search(query) {
-                trace!(
-                    ?err,
-                    path = %self.info.path.display(),
-                    "failed to rebuild search state after reload"
-                );
-            }
-        }
-
-        self.sync_jump_position();
+        self.normalize_viewport();
     }
+
     pub fn add_mark(&mut self, mark: char, page: usize) {
         self.state.marks.insert(mark, page);
     }
     pub fn get_page_from_mark(&self, mark: char) -> Option<usize> {
-        self.state.marks.get(&mark).map(|v| *v)
+        self.state.marks.get(&mark).copied()
     }
 
     pub fn add_named_mark(&mut self, name: String, page: usize) {
@@ -1314,31 +3320,18 @@ impl DocumentInstance {
         &self.state.named_marks
     }
 
-    pub fn prefetch_neighbors(&self, range: usize, scale: f32) -> Result<()> {
-        if range == 0 {
-            return Ok(());
-        }
+    pub fn marks(&self) -> &HashMap<char, usize> {
+        &self.state.marks
+    }
 
+    pub fn prefetch_neighbors(&self, policy: PrefetchPolicy, scale: f32) -> Result<()> {
         let current_page = self.state.current_page;
         let dark_mode = self.state.dark_mode;
         let mut last_error: Option<Error> = None;
 
-        for offset in 1..=range {
-            if let Some(prev) = current_page.checked_sub(offset) {
-                if prev < self.info.page_count {
-                    if let Err(err) =
-                        self.render_page_internal(prev, scale, dark_mode, current_page)
-                    {
-                        last_error = Some(err);
-                    }
-                }
-            }
-
-            let next = current_page + offset;
-            if next < self.info.page_count {
-                if let Err(err) = self.render_page_internal(next, scale, dark_mode, current_page) {
-                    last_error = Some(err);
-                }
+        for page in policy.pages(current_page, self.info.page_count) {
+            if let Err(err) = self.render_page_internal(page, scale, dark_mode, current_page) {
+                last_error = Some(err);
             }
         }
 
@@ -1370,12 +3363,174 @@ impl DocumentInstance {
             scale,
             dark_mode,
         };
+        let started = Instant::now();
         let image = self.backend.render_page(request)?;
+        self.render_times
+            .lock()
+            .record(page_index, started.elapsed());
+        if image.width > 0 && image.height > 0 && scale > 0.0 {
+            self.rendered_sizes.lock().insert(
+                page_index,
+                PageSize {
+                    width: image.width as f32 / scale,
+                    height: image.height as f32 / scale,
+                },
+            );
+        }
         self.store_cached_render(key, &image, reference_page);
         Ok(image)
     }
 
-    fn current_position(&self) -> DocumentPosition {
+    /// Renders a view like [`render_view`](Self::render_view), except that a
+    /// page known to render slowly and not yet cached at `scale` is rendered
+    /// at a fraction of it instead, and the full render is queued for
+    /// [`take_background_renders`](Self::take_background_renders). Returns
+    /// the image along with the scale it was rendered at.
+    pub fn render_view_progressive(
+        &self,
+        view: DocumentPosition,
+        scale: f32,
+    ) -> Result<(RenderImage, f32)> {
+        let dark_mode = self.state.dark_mode;
+        let key = CacheKey::new(view.page, scale, dark_mode);
+        if view.page >= self.info.page_count
+            || !self.render_times.lock().is_slow(view.page)
+            || self.try_get_cached(&key).is_some()
+        {
+            return Ok((self.render_view(view, scale)?, scale));
+        }
+        self.background_renders.lock().want(RenderRequest {
+            page_index: view.page,
+            scale,
+            dark_mode,
+        });
+        let preview_scale = scale * PREVIEW_FRACTION;
+        let preview_key = CacheKey::new(view.page, preview_scale, dark_mode);
+        if let Some(image) = self.try_get_cached(&preview_key) {
+            return Ok((image, preview_scale));
+        }
+        let image = self.backend.render_page(RenderRequest {
+            page_index: view.page,
+            scale: preview_scale,
+            dark_mode,
+        })?;
+        self.store_cached_render(preview_key, &image, self.state.current_page);
+        Ok((image, preview_scale))
+    }
+
+    /// What the backend reports having done on its own, such as opening
+    /// the document again after a hang; see
+    /// [`DocumentBackend::take_notice`].
+    pub fn take_backend_notice(&self) -> Option<String> {
+        self.backend.take_notice()
+    }
+
+    /// Hands over the full renders queued by
+    /// [`render_view_progressive`](Self::render_view_progressive), to be
+    /// run off the main thread and given back to
+    /// [`finish_background_render`](Self::finish_background_render).
+    /// Called once per redraw: renders still running for pages the redraw
+    /// no longer showed are cancelled.
+    pub fn take_background_renders(&self) -> Vec<BackgroundRender> {
+        let mut renders = self.background_renders.lock();
+        let BackgroundRenders {
+            queued,
+            running,
+            wanted,
+            generation,
+        } = &mut *renders;
+        running.retain(|key, cancel| {
+            let keep = wanted.contains(key);
+            if !keep {
+                cancel.cancel();
+            }
+            keep
+        });
+        wanted.clear();
+        std::mem::take(queued)
+            .into_iter()
+            .map(|request| {
+                let cancel = CancelToken::new();
+                running.insert(CacheKey::from_request(&request), cancel.clone());
+                BackgroundRender {
+                    doc_id: self.info.id,
+                    backend: self.backend.clone(),
+                    request,
+                    generation: *generation,
+                    cancel,
+                }
+            })
+            .collect()
+    }
+
+    /// Caches a finished background render. Returns whether it was kept;
+    /// cancelled renders and those made before a reload are dropped. After a
+    /// failure the page is rendered in the foreground again, where the error
+    /// shows.
+    pub fn finish_background_render(&self, finished: FinishedRender) -> bool {
+        let key = CacheKey::from_request(&finished.request);
+        {
+            let mut renders = self.background_renders.lock();
+            if finished.generation != renders.generation || finished.cancel.is_cancelled() {
+                return false;
+            }
+            renders.running.remove(&key);
+        }
+        let page = finished.request.page_index;
+        match finished.result {
+            Ok(image) => {
+                self.render_times.lock().record(page, finished.elapsed);
+                self.store_cached_render(key, &image, self.state.current_page);
+            }
+            Err(err) => {
+                warn!(?err, page, "background render failed");
+                self.render_times.lock().pages.insert(page, Duration::ZERO);
+            }
+        }
+        true
+    }
+
+    fn clear_renders(&self) {
+        self.render_cache.lock().clear();
+        let mut renders = self.background_renders.lock();
+        renders.queued.clear();
+        renders.wanted.clear();
+        for (_, cancel) in renders.running.drain() {
+            cancel.cancel();
+        }
+        renders.generation = renders.generation.wrapping_add(1);
+    }
+
+    fn normalize_viewport(&mut self) {
+        if self.state.scale <= 1.0 + f32::EPSILON {
+            self.state.viewport.x = 0.0;
+            if self.state.fit_mode == FitMode::Page {
+                self.state.viewport.y = 0.0;
+            }
+        }
+        self.state.viewport.clamp();
+    }
+
+    pub fn set_dark_mode(&mut self, enabled: bool) -> bool {
+        if self.state.dark_mode == enabled {
+            return false;
+        }
+        self.state.dark_mode = enabled;
+        true
+    }
+
+    pub fn set_fit_mode(&mut self, mode: FitMode) -> bool {
+        if self.state.fit_mode == mode {
+            return false;
+        }
+        self.state.fit_mode = mode;
+        self.state.viewport.y = 0.0;
+        self.normalize_viewport();
+        self.sync_jump_position();
+        true
+    }
+
+    pub fn current_position(&self) -> DocumentPosition {
         DocumentPosition {
             page: self.state.current_page,
             scale: self.state.scale,
@@ -1386,6 +3541,16 @@ impl DocumentInstance {
     fn record_jump_from(&mut self, previous: DocumentPosition) {
         let current = self.current_position();
         self.jump_history.record_navigation(previous, current);
+        self.state.jumps = self.jump_history.persisted();
+    }
+
+    pub fn jump_list(&self) -> JumpList {
+        self.jump_history.list(self.current_position())
+    }
+
+    /// A line of text from `page` to recognise it by, such as in `:jumps`.
+    pub fn page_preview(&self, page: usize) -> Option<String> {
+        first_paragraph(&*self.page_text_entry(page).ok()?)
     }
 
     fn sync_jump_position(&mut self) {
@@ -1393,6 +3558,47 @@ impl DocumentInstance {
         self.jump_history.record_current(current);
     }
 
+    fn jump_to_global_mark(&mut self, mark: &GlobalMark) -> bool {
+        let previous = self.current_position();
+        let changed = self.apply_document_position(DocumentPosition {
+            page: mark.page,
+            scale: self.state.scale,
+            viewport: mark.viewport,
+        });
+        if changed {
+            self.record_jump_from(previous);
+        }
+        changed
+    }
+
+    pub fn other_view(&self) -> Option<DocumentPosition> {
+        self.other_view
+    }
+
+    /// Opens a second view at the current position.
+    fn split_view(&mut self) -> bool {
+        if self.other_view.is_some() {
+            return false;
+        }
+        self.other_view = Some(self.current_position());
+        true
+    }
+
+    /// Moves to the second view's position and keeps the current one as
+    /// the second view.
+    fn swap_views(&mut self) -> bool {
+        let Some(other) = self.other_view else {
+            return false;
+        };
+        self.other_view = Some(self.current_position());
+        self.apply_document_position(other);
+        true
+    }
+
+    fn close_other_view(&mut self) -> bool {
+        self.other_view.take().is_some()
+    }
+
     fn apply_document_position(&mut self, position: DocumentPosition) -> bool {
         let mut changed = false;
         let last_page = self.info.page_count.saturating_sub(1);
@@ -1419,11 +3625,7 @@ impl DocumentInstance {
             self.state.viewport = next_viewport;
         }
 
-        if self.state.scale <= 1.0 + f32::EPSILON {
-            self.state.viewport.reset();
-        } else {
-            self.state.viewport.clamp();
-        }
+        self.normalize_viewport();
 
         self.sync_jump_position();
         changed
@@ -1431,15 +3633,26 @@ impl DocumentInstance {
 
     fn pop_jump_backward(&mut self) -> Option<DocumentPosition> {
         let current = self.current_position();
-        self.jump_history.jump_backward(current)
+        let target = self.jump_history.jump_backward(current);
+        self.state.jumps = self.jump_history.persisted();
+        target
     }
 
     fn pop_jump_forward(&mut self) -> Option<DocumentPosition> {
         let current = self.current_position();
-        self.jump_history.jump_forward(current)
+        let target = self.jump_history.jump_forward(current);
+        self.state.jumps = self.jump_history.persisted();
+        target
+    }
+
+    fn take_jump(&mut self, index: usize) -> Option<DocumentPosition> {
+        let current = self.current_position();
+        let target = self.jump_history.jump_to(index, current);
+        self.state.jumps = self.jump_history.persisted();
+        target
     }
 
-    pub fn perform_search(&mut self, query: String) -> Result<bool> {
+    pub fn perform_search(&mut self, query: String, scope: SearchScope) -> Result<bool> {
         let trimmed = query.trim().to_string();
 
         if trimmed.is_empty() {
@@ -1449,13 +3662,25 @@ impl DocumentInstance {
         }
 
         let context = self.search_context();
-        let matches = context.build_search_matches(&trimmed)?;
-        Ok(self.apply_search_results(trimmed, matches, self.state.current_page))
+        let matches = context.build_search_matches(&trimmed, self.search_pages(scope))?;
+        Ok(self.apply_search_results(trimmed, scope, matches, self.state.current_page))
+    }
+
+    /// Pages covered by a search in `scope` started from the current page.
+    pub fn search_pages(&self, scope: SearchScope) -> Range<usize> {
+        match scope {
+            SearchScope::Document => 0..self.info.page_count,
+            SearchScope::Page => {
+                let page = self.state.current_page.min(self.info.page_count);
+                page..(page + 1).min(self.info.page_count)
+            }
+        }
     }
 
     pub fn apply_search_results(
         &mut self,
         query: String,
+        scope: SearchScope,
         matches: Vec<SearchMatch>,
         start_page: usize,
     ) -> bool {
@@ -1477,9 +3702,18 @@ impl DocumentInstance {
             )
         };
 
+        let mut pages: Vec<(usize, usize)> = Vec::new();
+        for found in &matches {
+            match pages.last_mut() {
+                Some((page, count)) if *page == found.page => *count += 1,
+                _ => pages.push((found.page, 1)),
+            }
+        }
         self.search_state = Some(SearchState {
             query,
+            scope,
             matches,
+            pages: pages.into(),
             current_index: next_index,
         });
 
@@ -1559,8 +3793,14 @@ impl DocumentInstance {
     pub fn search_summary(&self) -> Option<SearchSummary> {
         self.search_state.as_ref().map(|state| SearchSummary {
             query: state.query.clone(),
+            scope: state.scope,
             total: state.matches.len(),
             current_index: state.current_index,
+            current_page: state
+                .current_index
+                .and_then(|index| state.matches.get(index))
+                .map(|found| found.page),
+            pages: Arc::clone(&state.pages),
         })
     }
 
@@ -1591,15 +3831,84 @@ impl DocumentInstance {
         self.build_selection_highlights(&snapshot, self.state.current_page)
     }
 
+    /// Bounding box on `page` of the active selection, or of the last one
+    /// once Visual mode has been left.
+    pub fn selection_bounds(&self, page: usize) -> Option<NormalizedRect> {
+        let snapshot = match self.selection_state.as_ref() {
+            Some(selection) => selection.normalized(),
+            None => self.last_selection.clone()?,
+        };
+        let highlights = self.build_selection_highlights(&snapshot, page)?;
+        highlights
+            .current
+            .into_iter()
+            .reduce(|bounds, rect| NormalizedRect {
+                left: bounds.left.min(rect.left),
+                top: bounds.top.min(rect.top),
+                right: bounds.right.max(rect.right),
+                bottom: bounds.bottom.max(rect.bottom),
+            })
+    }
+
     pub fn selection_text(&self) -> Option<String> {
         let selection = self.selection_state.as_ref()?;
         self.extract_selection_text(&selection.normalized()).ok()
     }
 
+    /// First and last page of the selection.
+    pub fn selection_pages(&self) -> Option<(usize, usize)> {
+        let (start, end) = self.selection_state.as_ref()?.normalized().points();
+        Some((start.page, end.page))
+    }
+
+    /// The selected text with hard-wrapped lines joined into paragraphs and
+    /// a blank line between paragraphs. Block selections are left as they
+    /// are, one line per row.
+    pub fn selection_text_reflowed(&self) -> Option<String> {
+        let selection = self.selection_state.as_ref()?.normalized();
+        if selection.block {
+            return self.extract_selection_text(&selection).ok();
+        }
+        let (start, end) = selection.points();
+        let mut paragraphs: Vec<String> = Vec::new();
+        for page in start.page..=end.page {
+            let page_text = self.page_text_entry(page).ok()?;
+            let first = if page == start.page {
+                start.glyph_index
+            } else {
+                0
+            };
+            let last = if page == end.page {
+                end.glyph_index
+            } else {
+                page_text.glyph_count()
+            };
+            let mut page_paragraphs = page_text.paragraphs_in(first..last).into_iter();
+            // A paragraph that runs on from the previous page continues it.
+            if let Some(next) = page_paragraphs.next() {
+                match paragraphs.last_mut() {
+                    Some(previous)
+                        if !previous.ends_with(['.', '!', '?', ':'])
+                            && next.chars().next().is_some_and(char::is_lowercase) =>
+                    {
+                        join_wrapped_line(previous, &next)
+                    }
+                    _ => paragraphs.push(next),
+                }
+            }
+            paragraphs.extend(page_paragraphs);
+        }
+        Some(paragraphs.join("\n\n"))
+    }
+
     pub fn visual_cursor_highlight(&self) -> Option<NormalizedRect> {
         if self.selection_state.is_some() {
             return None;
         }
+        self.visual_cursor_rect()
+    }
+
+    fn visual_cursor_rect(&self) -> Option<NormalizedRect> {
         let point = self.visual_cursor?;
         if point.page != self.state.current_page {
             return None;
@@ -1621,16 +3930,161 @@ impl DocumentInstance {
     }
 
     pub fn start_link_mode(&mut self) -> Result<()> {
-        let entries = self.build_link_entries()?;
+        let entries = self.build_link_entries();
         let current_page = self.state.current_page;
         let current_index = entries.iter().position(|link| link.page == current_page);
         self.link_state = Some(LinkState {
-            links: entries,
+            links: entries.clone(),
+            all: entries,
+            filter: LinkFilter::All,
             current_index,
         });
         Ok(())
     }
 
+    /// Restricts link mode to `filter`, or shows every link again when that
+    /// filter is already active. The selected link is kept if it still
+    /// qualifies.
+    pub fn toggle_link_filter(&mut self, filter: LinkFilter) -> bool {
+        let current_page = self.state.current_page;
+        let Some(state) = self.link_state.as_mut() else {
+            return false;
+        };
+        let filter = if state.filter == filter {
+            LinkFilter::All
+        } else {
+            filter
+        };
+        let selected = state
+            .current_index
+            .and_then(|index| state.links.get(index))
+            .cloned();
+        state.filter = filter;
+        state.links = state
+            .all
+            .iter()
+            .filter(|link| filter.matches(&link.action))
+            .cloned()
+            .collect();
+        state.current_index = selected
+            .and_then(|selected| {
+                state
+                    .links
+                    .iter()
+                    .position(|link| link.page == selected.page && link.rects == selected.rects)
+            })
+            .or_else(|| {
+                state
+                    .links
+                    .iter()
+                    .position(|link| link.page == current_page)
+            });
+        true
+    }
+
+    /// The document's web links, each once. Pages whose links cannot be
+    /// read are skipped.
+    pub fn uri_links(&self) -> Vec<UriLink> {
+        let mut seen = std::collections::HashSet::new();
+        let mut links = Vec::new();
+        for page in 0..self.info.page_count {
+            for definition in self.readable_page_links(page) {
+                if let LinkAction::Uri { uri } = definition.action {
+                    if seen.insert(uri.clone()) {
+                        links.push(UriLink { page, uri });
+                    }
+                }
+            }
+        }
+        links
+    }
+
+    /// The links on `page`, or none when the backend fails to read them, so
+    /// that one broken page does not hide the links of the others.
+    fn readable_page_links(&self, page: usize) -> Vec<LinkDefinition> {
+        self.backend.page_links(page).unwrap_or_else(|err| {
+            warn!(page, ?err, "skipping a page whose links cannot be read");
+            Vec::new()
+        })
+    }
+
+    /// The document's DOI, from its title or keywords or else from the
+    /// text of its first pages, where articles print it.
+    pub fn doi(&self) -> Option<String> {
+        self.doi
+            .get_or_init(|| {
+                let metadata = &self.info.metadata;
+                metadata
+                    .title
+                    .iter()
+                    .chain(&metadata.keywords)
+                    .find_map(|text| find_doi(text))
+                    .or_else(|| {
+                        (0..self.info.page_count.min(DOI_SCAN_PAGES)).find_map(|page| {
+                            let text = self.page_text_entry(page).ok()?;
+                            find_doi(&text.text)
+                        })
+                    })
+            })
+            .clone()
+    }
+
+    /// Resolves a citation link to its reference entry. In link mode the
+    /// selected link is used; otherwise `index` picks among the citation-like
+    /// links on the current page.
+    pub fn citation_preview(&self, index: usize) -> Result<Option<CitationPreview>> {
+        let current = self.state.current_page;
+        let selected = self.link_state.as_ref().and_then(|state| {
+            state
+                .links
+                .get(state.current_index?)
+                .map(|link| (link.page, link.rects.clone(), link.action.clone()))
+        });
+        let (source_page, rects, action) = match selected {
+            Some(link) => link,
+            None => {
+                let text = self.page_text_entry(current)?;
+                let citation = self
+                    .backend
+                    .page_links(current)?
+                    .into_iter()
+                    .filter(|definition| matches!(definition.action, LinkAction::GoTo { .. }))
+                    .filter(|definition| {
+                        definition
+                            .rects
+                            .iter()
+                            .any(|rect| is_citation_label(&text.text_in_rect(rect)))
+                    })
+                    .nth(index);
+                let Some(definition) = citation else {
+                    return Ok(None);
+                };
+                (current, definition.rects, definition.action)
+            }
+        };
+        let LinkAction::GoTo { page, top } = action else {
+            return Ok(None);
+        };
+        let source_text = self.page_text_entry(source_page)?;
+        let label = rects
+            .iter()
+            .map(|rect| source_text.text_in_rect(rect))
+            .find(|label| !label.is_empty())
+            .unwrap_or_default();
+        let page = page.min(self.info.page_count.saturating_sub(1));
+        let target_text = self.page_text_entry(page)?;
+        let marker_label = if label.starts_with('[') {
+            label.clone()
+        } else if is_citation_label(&label) {
+            format!("[{}]", label)
+        } else {
+            String::new()
+        };
+        Ok(target_text
+            .reference_entry(top, &marker_label)
+            .map(|text| CitationPreview { label, page, text }))
+    }
+
     pub fn clear_link_state(&mut self) {
         self.link_state = None;
     }
@@ -1648,9 +4102,7 @@ impl DocumentInstance {
             return Some(false);
         }
 
-        let Some(state) = self.link_state.as_mut() else {
-            return None;
-        };
+        let state = self.link_state.as_mut()?;
 
         if state.links.is_empty() {
             return Some(false);
@@ -1726,15 +4178,57 @@ impl DocumentInstance {
         self.link_state.as_ref().map(|state| LinkSummary {
             total: state.links.len(),
             current_index: state.current_index,
+            filter: state.filter,
         })
     }
 
-    pub fn link_highlights_for_current_page(&self) -> Option<LinkHighlights> {
-        let state = self.link_state.as_ref()?;
-        let current_page = self.state.current_page;
-        let mut highlights = LinkHighlights::default();
-
-        for (idx, link) in state.links.iter().enumerate() {
+    pub fn add_external_highlights(&mut self, page: usize, rects: Vec<NormalizedRect>) -> bool {
+        if page >= self.info.page_count {
+            return false;
+        }
+        let rects: Vec<NormalizedRect> = rects
+            .into_iter()
+            .map(|rect| rect.clamp())
+            .filter(|rect| rect.is_valid())
+            .collect();
+        if rects.is_empty() {
+            return false;
+        }
+        self.external_highlights
+            .entry(page)
+            .or_default()
+            .extend(rects);
+        true
+    }
+
+    pub fn clear_external_highlights(&mut self, page: Option<usize>) -> bool {
+        match page {
+            Some(page) => self.external_highlights.remove(&page).is_some(),
+            None => {
+                let changed = !self.external_highlights.is_empty();
+                self.external_highlights.clear();
+                changed
+            }
+        }
+    }
+
+    pub fn external_highlights_for_current_page(&self) -> Option<Highlights> {
+        let rects = self.external_highlights.get(&self.state.current_page)?;
+        if rects.is_empty() {
+            return None;
+        }
+        Some(Highlights {
+            current: rects.clone(),
+            others: Vec::new(),
+        })
+    }
+
+    pub fn link_highlights_for_current_page(&self) -> Option<LinkHighlights> {
+        let state = self.link_state.as_ref()?;
+        let current_page = self.state.current_page;
+        let mut highlights = LinkHighlights::default();
+
+        for (idx, link) in state.links.iter().enumerate() {
             if link.page != current_page {
                 continue;
             }
@@ -1764,7 +4258,7 @@ impl DocumentInstance {
         };
 
         match &link.action {
-            LinkAction::GoTo { page } => {
+            LinkAction::GoTo { page, .. } => {
                 let target_page = (*page).min(self.info.page_count.saturating_sub(1));
                 let previous = self.current_position();
                 let page_changed = if target_page != self.state.current_page {
@@ -1785,10 +4279,154 @@ impl DocumentInstance {
         }
     }
 
-    fn build_link_entries(&self) -> Result<Vec<LinkEntry>> {
+    pub fn start_form_mode(&mut self) -> Result<()> {
+        let mut fields = Vec::new();
+        for page in 0..self.info.page_count {
+            fields.extend(
+                self.backend
+                    .form_fields(page)?
+                    .into_iter()
+                    .filter(|field| field.rect.is_valid())
+                    .map(|field| FormEntry { page, field }),
+            );
+        }
+        let current_page = self.state.current_page;
+        let current_index = fields
+            .iter()
+            .position(|entry| entry.page >= current_page)
+            .or(if fields.is_empty() { None } else { Some(0) });
+        self.form_state = Some(FormState {
+            fields,
+            current_index,
+        });
+        if let Some(index) = current_index {
+            self.apply_form_index(index);
+        }
+        Ok(())
+    }
+
+    pub fn clear_form_state(&mut self) {
+        self.form_state = None;
+    }
+
+    pub fn next_form_field(&mut self, count: usize) -> Option<bool> {
+        self.advance_form_field(SearchDirection::Forward, count)
+    }
+
+    pub fn previous_form_field(&mut self, count: usize) -> Option<bool> {
+        self.advance_form_field(SearchDirection::Backward, count)
+    }
+
+    fn advance_form_field(&mut self, direction: SearchDirection, count: usize) -> Option<bool> {
+        let state = self.form_state.as_ref()?;
+        let total = state.fields.len();
+        if total == 0 {
+            return Some(false);
+        }
+        let current = state.current_index.unwrap_or(0).min(total - 1);
+        let steps = count % total;
+        let target = match direction {
+            SearchDirection::Forward => (current + steps) % total,
+            SearchDirection::Backward => (current + total - steps) % total,
+        };
+        Some(self.apply_form_index(target))
+    }
+
+    fn apply_form_index(&mut self, index: usize) -> bool {
+        let Some(state) = self.form_state.as_mut() else {
+            return false;
+        };
+        let Some(entry) = state.fields.get(index) else {
+            state.current_index = None;
+            return false;
+        };
+        state.current_index = Some(index);
+        let target_page = entry.page.min(self.info.page_count.saturating_sub(1));
+        let previous = self.current_position();
+        let changed = if target_page != self.state.current_page {
+            self.state.current_page = target_page;
+            self.state.viewport.reset();
+            self.record_jump_from(previous);
+            true
+        } else {
+            false
+        };
+        self.sync_jump_position();
+        changed
+    }
+
+    pub fn form_summary(&self) -> Option<FormSummary> {
+        self.form_state.as_ref().map(|state| FormSummary {
+            total: state.fields.len(),
+            current_index: state.current_index,
+        })
+    }
+
+    pub fn current_form_field(&self) -> Option<&FormField> {
+        let state = self.form_state.as_ref()?;
+        state
+            .fields
+            .get(state.current_index?)
+            .map(|entry| &entry.field)
+    }
+
+    pub fn form_highlights_for_current_page(&self) -> Option<Highlights> {
+        let state = self.form_state.as_ref()?;
+        let mut highlights = Highlights::default();
+        for (idx, entry) in state.fields.iter().enumerate() {
+            if entry.page != self.state.current_page {
+                continue;
+            }
+            if Some(idx) == state.current_index {
+                highlights.current.push(entry.field.rect);
+            } else {
+                highlights.others.push(entry.field.rect);
+            }
+        }
+        if highlights.is_empty() {
+            None
+        } else {
+            Some(highlights)
+        }
+    }
+
+    /// Writes `value` into the selected form field and refreshes the fields of
+    /// its page, since changing one field (e.g. a radio button) can affect others.
+    pub fn set_current_form_field(&mut self, value: FormFieldValue) -> Result<bool> {
+        let Some(state) = self.form_state.as_ref() else {
+            return Ok(false);
+        };
+        let Some(entry) = state.current_index.and_then(|idx| state.fields.get(idx)) else {
+            return Ok(false);
+        };
+        let page = entry.page;
+        self.backend.set_form_field(page, entry.field.id, value)?;
+        self.clear_renders();
+
+        let refreshed = self.backend.form_fields(page)?;
+        if let Some(state) = self.form_state.as_mut() {
+            for entry in state.fields.iter_mut().filter(|entry| entry.page == page) {
+                if let Some(field) = refreshed.iter().find(|field| field.id == entry.field.id) {
+                    entry.field = field.clone();
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn toggle_current_form_field(&mut self) -> Result<bool> {
+        let value = match self.current_form_field().map(|field| &field.kind) {
+            Some(FormFieldKind::Checkbox { checked }) => FormFieldValue::Checked(!checked),
+            Some(FormFieldKind::RadioButton { .. }) => FormFieldValue::Checked(true),
+            _ => return Ok(false),
+        };
+        self.set_current_form_field(value)
+    }
+
+    fn build_link_entries(&self) -> Vec<LinkEntry> {
         let mut entries = Vec::new();
         for page in 0..self.info.page_count {
-            let definitions = self.backend.page_links(page)?;
+            let definitions = self.readable_page_links(page);
             if definitions.is_empty() {
                 continue;
             }
@@ -1809,7 +4447,46 @@ impl DocumentInstance {
                 });
             }
         }
-        Ok(entries)
+        entries
+    }
+
+    /// Applies `limits` to the caches, dropping what no longer fits.
+    pub fn set_memory_limits(&mut self, limits: MemoryLimits) {
+        self.render_cache_capacity = limits.rendered_pages.max(1);
+        {
+            let mut cache = self.render_cache.lock();
+            if cache.len() > self.render_cache_capacity {
+                let current = self.state.current_page;
+                let mut keys: Vec<_> = cache.keys().cloned().collect();
+                keys.sort_by_key(|key| key.distance(current));
+                for stale in keys.into_iter().skip(self.render_cache_capacity) {
+                    cache.remove(&stale);
+                }
+            }
+        }
+        self.text_cache.lock().set_capacity(limits.text_bytes);
+    }
+
+    /// Size of `page` in points, when known without rendering it.
+    /// The page's size from the backend, or else from an earlier render
+    /// of the page.
+    pub fn page_size(&self, page: usize) -> Option<PageSize> {
+        self.backend
+            .page_size(page)
+            .or_else(|| self.rendered_sizes.lock().get(&page).copied())
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        let renders = self.render_cache.lock();
+        let text = self.text_cache.lock();
+        CacheStats {
+            rendered_pages: renders.len(),
+            rendered_capacity: self.render_cache_capacity,
+            rendered_bytes: renders.values().map(|image| image.pixels.len()).sum(),
+            text_pages: text.pages.len(),
+            text_bytes: text.bytes,
+            text_capacity: text.capacity,
+        }
     }
 
     fn try_get_cached(&self, key: &CacheKey) -> Option<RenderImage> {
@@ -1820,10 +4497,15 @@ impl DocumentInstance {
         let mut cache = self.render_cache.lock();
         cache.insert(key, image.clone());
 
-        if cache.len() > CACHE_CAPACITY {
+        if cache.len() > self.render_cache_capacity {
             let mut keys: Vec<_> = cache.keys().cloned().collect();
-            keys.sort_by_key(|k| k.distance(reference_page));
-            for stale in keys.into_iter().skip(CACHE_CAPACITY) {
+            // Pages near a second view of the document are kept as well.
+            let other_page = self.other_view.map(|view| view.page);
+            keys.sort_by_key(|k| {
+                let distance = k.distance(reference_page);
+                other_page.map_or(distance, |page| distance.min(k.distance(page)))
+            });
+            for stale in keys.into_iter().skip(self.render_cache_capacity) {
                 cache.remove(&stale);
             }
         }
@@ -1832,10 +4514,297 @@ impl DocumentInstance {
     pub fn outline(&self) -> &[OutlineItem] {
         &self.outline
     }
+
+    /// The label printed on `page`, such as `iv` in the front matter: the
+    /// page's number when a first page number is set, otherwise the
+    /// document's page label.
+    pub fn page_label(&self, page: usize) -> Option<String> {
+        match self.state.first_page_number {
+            Some(first) => i64::try_from(page)
+                .ok()
+                .and_then(|page| first.checked_add(page))
+                .map(|number| number.to_string()),
+            None => self.page_labels.get(page)?.clone(),
+        }
+    }
+
+    /// The first page labelled `label` (ignoring case). Without such a
+    /// label a number is taken as the page counted from 1.
+    pub fn page_for_label(&self, label: &str) -> Option<usize> {
+        let label = label.trim();
+        let labelled = match self.state.first_page_number {
+            Some(first) => label
+                .parse::<i64>()
+                .ok()
+                .and_then(|number| number.checked_sub(first))
+                .and_then(|page| usize::try_from(page).ok())
+                .filter(|&page| page < self.info.page_count),
+            None => self.page_labels.iter().position(|candidate| {
+                candidate
+                    .as_deref()
+                    .is_some_and(|candidate| candidate.eq_ignore_ascii_case(label))
+            }),
+        };
+        labelled.or_else(|| {
+            label
+                .parse::<usize>()
+                .ok()
+                .filter(|&page| page > 0 && page <= self.info.page_count)
+                .map(|page| page - 1)
+        })
+    }
+
+    /// Sets the number printed on the first page, or goes back to the
+    /// document's own page labels with `None`.
+    fn set_first_page_number(&mut self, number: Option<i64>) -> bool {
+        if self.state.first_page_number == number {
+            return false;
+        }
+        self.state.first_page_number = number;
+        self.label_outline();
+        true
+    }
+
+    fn label_outline(&mut self) {
+        let labels: Vec<Option<String>> = self
+            .outline
+            .iter()
+            .map(|item| self.page_label(item.page_index))
+            .collect();
+        for (item, label) in self.outline.iter_mut().zip(labels) {
+            item.page_label = label;
+        }
+    }
+}
+
+/// Page labels from `backend`, or none when they only repeat the page
+/// numbers.
+fn load_page_labels(backend: &dyn DocumentBackend) -> Vec<Option<String>> {
+    let labels = match backend.page_labels() {
+        Ok(labels) => labels,
+        Err(err) => {
+            warn!(?err, path = %backend.info().path.display(), "failed to load page labels");
+            return Vec::new();
+        }
+    };
+    let plain = labels.iter().enumerate().all(|(index, label)| {
+        label
+            .as_deref()
+            .is_none_or(|label| label == (index + 1).to_string())
+    });
+    if plain {
+        Vec::new()
+    } else {
+        labels
+    }
 }
 
 const CACHE_CAPACITY: usize = 10;
 
+/// Pages whose text is searched for the document's DOI.
+const DOI_SCAN_PAGES: usize = 3;
+
+/// Memory given to a document's extracted page text; a search through a
+/// long document would otherwise keep the text of every page.
+const TEXT_CACHE_BYTES: usize = 32 * 1024 * 1024;
+
+/// Extracted page text, least recently used pages evicted first once it
+/// takes more than its capacity.
+#[derive(Debug)]
+struct TextCache {
+    pages: HashMap<usize, (Arc<PageText>, u64)>,
+    bytes: usize,
+    capacity: usize,
+    /// Counts lookups, to tell the last use of each page.
+    clock: u64,
+    /// Fingerprint of every page's text read since the last clear, kept
+    /// when the text itself is evicted, for comparing reloads.
+    digests: HashMap<usize, u64>,
+}
+
+impl Default for TextCache {
+    fn default() -> Self {
+        Self::with_capacity(TEXT_CACHE_BYTES)
+    }
+}
+
+impl TextCache {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            pages: HashMap::new(),
+            bytes: 0,
+            capacity,
+            clock: 0,
+            digests: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, page: usize) -> Option<Arc<PageText>> {
+        self.clock += 1;
+        let (text, used) = self.pages.get_mut(&page)?;
+        *used = self.clock;
+        Some(Arc::clone(text))
+    }
+
+    /// Adds a page, evicting others as needed; the page just added stays
+    /// even when it alone is over capacity.
+    fn insert(&mut self, page: usize, text: Arc<PageText>) {
+        let mut hasher = DefaultHasher::new();
+        text.text.hash(&mut hasher);
+        self.digests.insert(page, hasher.finish());
+        self.clock += 1;
+        self.bytes += text.heap_bytes();
+        if let Some((replaced, _)) = self.pages.insert(page, (text, self.clock)) {
+            self.bytes -= replaced.heap_bytes();
+        }
+        self.evict_except(page);
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let newest = self
+            .pages
+            .iter()
+            .max_by_key(|(_, (_, used))| *used)
+            .map(|(page, _)| *page);
+        if let Some(page) = newest {
+            self.evict_except(page);
+        }
+    }
+
+    /// Drops the least recently used pages other than `page` until the
+    /// cache fits its capacity.
+    fn evict_except(&mut self, page: usize) {
+        while self.bytes > self.capacity && self.pages.len() > 1 {
+            let Some(oldest) = self
+                .pages
+                .iter()
+                .filter(|(candidate, _)| **candidate != page)
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(candidate, _)| *candidate)
+            else {
+                break;
+            };
+            if let Some((evicted, _)) = self.pages.remove(&oldest) {
+                self.bytes -= evicted.heap_bytes();
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pages.clear();
+        self.digests.clear();
+        self.bytes = 0;
+    }
+}
+
+/// How full a document's caches are, for `:cache-stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub rendered_pages: usize,
+    pub rendered_capacity: usize,
+    pub rendered_bytes: usize,
+    pub text_pages: usize,
+    pub text_bytes: usize,
+    pub text_capacity: usize,
+}
+
+/// Pages a search hands to the backend at once; see
+/// [`DocumentSearchContext::build_search_matches`].
+const SEARCH_CHUNK_PAGES: usize = 8;
+
+/// Pages that took at least this long to render are drawn progressively.
+const SLOW_RENDER: Duration = Duration::from_millis(250);
+/// Scale of the quick render drawn while a slow page renders in full.
+const PREVIEW_FRACTION: f32 = 0.25;
+
+/// How long pages took to render at full resolution.
+#[derive(Debug, Default)]
+struct RenderTimes {
+    pages: HashMap<usize, Duration>,
+    /// The most recent render, which stands in for pages not rendered yet:
+    /// the pages of one document tend to be alike.
+    last: Option<Duration>,
+}
+
+impl RenderTimes {
+    fn record(&mut self, page: usize, elapsed: Duration) {
+        self.pages.insert(page, elapsed);
+        self.last = Some(elapsed);
+    }
+
+    fn is_slow(&self, page: usize) -> bool {
+        self.pages
+            .get(&page)
+            .copied()
+            .or(self.last)
+            .is_some_and(|elapsed| elapsed >= SLOW_RENDER)
+    }
+}
+
+#[derive(Debug, Default)]
+struct BackgroundRenders {
+    /// Requests not handed out yet, at most one per page.
+    queued: Vec<RenderRequest>,
+    running: HashMap<CacheKey, CancelToken>,
+    /// Renders asked for since the last hand-over.
+    wanted: HashSet<CacheKey>,
+    /// Bumped whenever cached renders go stale, e.g. on reload.
+    generation: u64,
+}
+
+impl BackgroundRenders {
+    fn want(&mut self, request: RenderRequest) {
+        let key = CacheKey::from_request(&request);
+        self.wanted.insert(key);
+        if self.running.contains_key(&key) {
+            return;
+        }
+        // Only the last request for a page is worth rendering; an earlier one
+        // was made at a scale that is no longer shown.
+        self.queued
+            .retain(|queued| queued.page_index != request.page_index);
+        self.queued.push(request);
+    }
+}
+
+/// A full page render queued by
+/// [`DocumentInstance::render_view_progressive`].
+pub struct BackgroundRender {
+    pub doc_id: DocumentId,
+    backend: Arc<dyn DocumentBackend>,
+    request: RenderRequest,
+    generation: u64,
+    cancel: CancelToken,
+}
+
+impl BackgroundRender {
+    /// Renders the page; this blocks, so call it off the main thread.
+    pub fn run(self) -> FinishedRender {
+        let started = Instant::now();
+        let result = self
+            .backend
+            .render_page_cancellable(self.request, &self.cancel);
+        FinishedRender {
+            doc_id: self.doc_id,
+            request: self.request,
+            generation: self.generation,
+            cancel: self.cancel,
+            result,
+            elapsed: started.elapsed(),
+        }
+    }
+}
+
+pub struct FinishedRender {
+    pub doc_id: DocumentId,
+    request: RenderRequest,
+    generation: u64,
+    cancel: CancelToken,
+    result: Result<RenderImage>,
+    elapsed: Duration,
+}
+
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 struct CacheKey {
     page_index: usize,
@@ -1852,6 +4821,10 @@ impl CacheKey {
         }
     }
 
+    fn from_request(request: &RenderRequest) -> Self {
+        Self::new(request.page_index, request.scale, request.dark_mode)
+    }
+
     fn distance(&self, reference_page: usize) -> usize {
         self.page_index.abs_diff(reference_page)
     }
@@ -1868,6 +4841,17 @@ fn quantize_scale(scale: f32) -> u32 {
     }
 }
 
+fn ensure_not_open_document(info: &DocumentInfo, path: &Path) -> Result<()> {
+    let same_file = match (path.canonicalize(), info.path.canonicalize()) {
+        (Ok(target), Ok(source)) => target == source,
+        _ => path == info.path,
+    };
+    if same_file {
+        anyhow::bail!("refusing to overwrite the open document; choose a new path");
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub enum Command {
     NextPage {
@@ -1882,11 +4866,29 @@ pub enum Command {
     ScaleBy {
         factor: f32,
     },
+    SetScale {
+        scale: f32,
+    },
     ResetScale,
     AdjustViewport {
         delta_x: f32,
         delta_y: f32,
     },
+    /// Scrolls the zoomed page by whole text lines; negative scrolls up.
+    ScrollLines {
+        lines: isize,
+    },
+    /// Scrolls by a multiple of the view's height, e.g. `0.5` for half a
+    /// screen; negative scrolls up. At the end of the page it goes on to
+    /// the next one, and at the start to the end of the previous one.
+    ScrollScreens {
+        screens: f32,
+    },
+    /// Zooms and scrolls so the given part of the current page fills the
+    /// view.
+    ZoomToRect {
+        rect: NormalizedRect,
+    },
     PutMark {
         key: char,
     },
@@ -1899,6 +4901,12 @@ pub enum Command {
     GotoNamedMark {
         name: String,
     },
+    DeleteMark {
+        key: char,
+    },
+    DeleteNamedMark {
+        name: String,
+    },
     Search {
         query: String,
     },
@@ -1910,6 +4918,9 @@ pub enum Command {
     },
     EnterVisualMode,
     StartSelection,
+    /// Starts a rectangular selection, or switches the selection between
+    /// rectangular and character-wise.
+    ToggleBlockSelection,
     MoveVisualCursor {
         motion: SelectionMotion,
         count: usize,
@@ -1926,8 +4937,52 @@ pub enum Command {
     LinkPrev {
         count: usize,
     },
+    ToggleLinkFilter {
+        filter: LinkFilter,
+    },
     ActivateLink,
+    EnterFormMode,
+    LeaveFormMode,
+    FormNext {
+        count: usize,
+    },
+    FormPrev {
+        count: usize,
+    },
+    ToggleFormField,
+    SetFormFieldText {
+        value: String,
+    },
+    SaveDocumentAs {
+        path: PathBuf,
+    },
+    /// Saves the open documents under `name`, or the current session name.
+    SaveSession {
+        name: Option<String>,
+    },
+    ExtractPages {
+        pages: RangeInclusive<usize>,
+        path: PathBuf,
+    },
     ToggleDarkMode,
+    SetDarkMode {
+        enabled: bool,
+    },
+    ToggleFitMode,
+    SetFitMode {
+        mode: FitMode,
+    },
+    ToggleReflow,
+    SetReflow {
+        enabled: bool,
+    },
+    AddExternalHighlights {
+        page: usize,
+        rects: Vec<NormalizedRect>,
+    },
+    ClearExternalHighlights {
+        page: Option<usize>,
+    },
     SwitchDocument {
         index: usize,
     },
@@ -1939,6 +4994,29 @@ pub enum Command {
     },
     JumpBackward,
     JumpForward,
+    /// Sets the active document's printed number of its first page; see
+    /// [`DocumentInstance::page_label`].
+    SetFirstPageNumber {
+        number: Option<i64>,
+    },
+    /// Sets how many pages of the active document to read each day.
+    SetDailyGoal {
+        pages: Option<usize>,
+    },
+    /// Goes to the page labelled `label`; see
+    /// [`DocumentInstance::page_for_label`].
+    GotoPageLabel {
+        label: String,
+    },
+    /// Jumps to entry `index` of the active document's [`JumpList`].
+    GotoJump {
+        index: usize,
+    },
+    /// Shows the active document a second time, for a split.
+    SplitView,
+    /// Swaps the active document's position with its second view.
+    SwapViews,
+    CloseOtherView,
 }
 
 #[derive(Debug, Clone)]
@@ -1947,49 +5025,281 @@ pub enum SessionEvent {
     DocumentClosed(DocumentId),
     ActiveDocumentChanged(DocumentId),
     RedrawNeeded(DocumentId),
-    FollowExternalLink { target: ExternalLink },
+    FollowExternalLink {
+        target: ExternalLink,
+    },
+    DocumentReloaded {
+        id: DocumentId,
+        previous_page_count: usize,
+        page_count: usize,
+        /// Pages whose text changed, when the session compares reloads; see
+        /// [`Session::set_count_changed_pages`].
+        changed_pages: Option<usize>,
+    },
+    DocumentSaved {
+        path: PathBuf,
+    },
+    /// `pages` pages were written to a new file at `path`.
+    PagesExtracted {
+        path: PathBuf,
+        pages: usize,
+    },
+    SearchCompleted {
+        id: DocumentId,
+        query: String,
+        matches: usize,
+        /// How long the search ran.
+        elapsed: Duration,
+    },
+    /// A motion could not move because it is already at a boundary.
+    MotionBlocked(MotionBoundary),
+}
+
+/// The boundary that stopped a motion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotionBoundary {
+    FirstPage,
+    LastPage,
+    NoMatches,
+    OldestJump,
+    NewestJump,
+}
+
+impl MotionBoundary {
+    pub fn message(self) -> &'static str {
+        match self {
+            MotionBoundary::FirstPage => "Already at first page",
+            MotionBoundary::LastPage => "Already at last page",
+            MotionBoundary::NoMatches => "No more matches",
+            MotionBoundary::OldestJump => "Already at oldest jump",
+            MotionBoundary::NewestJump => "Already at newest jump",
+        }
+    }
 }
 
 pub trait DocumentBackend: Send + Sync {
     fn info(&self) -> &DocumentInfo;
     fn render_page(&self, request: RenderRequest) -> Result<RenderImage>;
+    /// Renders like `render_page`, giving up once `cancel` is cancelled if
+    /// the backend is able to.
+    fn render_page_cancellable(
+        &self,
+        request: RenderRequest,
+        cancel: &CancelToken,
+    ) -> Result<RenderImage> {
+        if cancel.is_cancelled() {
+            return Err(RenderCancelled {
+                page_index: request.page_index,
+            }
+            .into());
+        }
+        self.render_page(request)
+    }
     fn outline(&self) -> Result<Vec<OutlineItem>> {
         Ok(Vec::new())
     }
+    /// The page's size, when the backend knows it without rendering.
+    fn page_size(&self, _page_index: usize) -> Option<PageSize> {
+        None
+    }
+    /// The label of every page, by page index; empty without page labels.
+    fn page_labels(&self) -> Result<Vec<Option<String>>> {
+        Ok(Vec::new())
+    }
     fn page_text(&self, _page_index: usize) -> Result<PageText> {
         Err(anyhow!("text extraction not supported"))
     }
     fn search_page(&self, _page_index: usize, _query: &str) -> Result<Vec<Vec<NormalizedRect>>> {
         Ok(Vec::new())
     }
+    /// `page_text` for several pages, for backends that do better with one
+    /// request than with many.
+    fn page_texts(&self, pages: &[usize]) -> Vec<Result<PageText>> {
+        pages.iter().map(|&page| self.page_text(page)).collect()
+    }
+    /// `search_page` for several pages; see [`page_texts`](Self::page_texts).
+    fn search_pages(&self, pages: &[usize], query: &str) -> Vec<Result<Vec<Vec<NormalizedRect>>>> {
+        pages
+            .iter()
+            .map(|&page| self.search_page(page, query))
+            .collect()
+    }
     fn page_links(&self, _page_index: usize) -> Result<Vec<LinkDefinition>> {
         Ok(Vec::new())
     }
+    fn form_fields(&self, _page_index: usize) -> Result<Vec<FormField>> {
+        Ok(Vec::new())
+    }
+    fn set_form_field(
+        &self,
+        _page_index: usize,
+        _field_id: usize,
+        _value: FormFieldValue,
+    ) -> Result<()> {
+        Err(anyhow!("form filling not supported"))
+    }
+    fn save_as(&self, _path: &Path) -> Result<()> {
+        Err(anyhow!("saving documents not supported"))
+    }
+    /// Writes the 0-based, inclusive page range `pages` to a new file.
+    fn extract_pages(&self, _pages: RangeInclusive<usize>, _path: &Path) -> Result<()> {
+        Err(anyhow!("extracting pages not supported"))
+    }
+    /// Something the backend did on its own that the user should hear
+    /// about, such as opening the document again; each is returned once.
+    fn take_notice(&self) -> Option<String> {
+        None
+    }
 }
 
 #[async_trait::async_trait]
 pub trait DocumentProvider: Send + Sync {
     async fn open(&self, path: &Path) -> Result<Arc<dyn DocumentBackend>>;
-}
 
-pub trait StateStore: Send + Sync {
-    fn load(&self, doc: &DocumentInfo) -> Result<Option<PersistedDocumentState>>;
-    fn save(&self, doc: &DocumentInfo, state: &PersistedDocumentState) -> Result<()>;
-}
+    /// Short format name used in error messages.
+    fn format_name(&self) -> &'static str {
+        "document"
+    }
 
+    /// Whether this provider can open a file starting with `header`. Used by
+    /// [`ProviderRegistry`] so formats are chosen by content, not extension.
+    fn sniff(&self, _header: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Number of leading bytes handed to [`DocumentProvider::sniff`].
+const SNIFF_LEN: usize = 1024;
+
+/// Dispatches `open` to the first registered provider that recognises the
+/// file's contents.
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Box<dyn DocumentProvider>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<P: DocumentProvider + 'static>(mut self, provider: P) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl DocumentProvider for ProviderRegistry {
+    async fn open(&self, path: &Path) -> Result<Arc<dyn DocumentBackend>> {
+        let mut header = Vec::with_capacity(SNIFF_LEN);
+        File::open(path)
+            .with_context(|| format!("failed to open {:?}", path))?
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut header)
+            .with_context(|| format!("failed to read {:?}", path))?;
+
+        match self
+            .providers
+            .iter()
+            .find(|provider| provider.sniff(&header))
+        {
+            Some(provider) => provider.open(path).await,
+            None => {
+                let known: Vec<&str> = self
+                    .providers
+                    .iter()
+                    .map(|provider| provider.format_name())
+                    .collect();
+                Err(anyhow!(
+                    "unsupported document format: {:?} is not recognised as {}",
+                    path,
+                    if known.is_empty() {
+                        "any known format".to_string()
+                    } else {
+                        known.join(" or ")
+                    }
+                ))
+            }
+        }
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        self.providers.iter().any(|provider| provider.sniff(header))
+    }
+}
+
+pub trait StateStore: Send + Sync {
+    fn load(&self, doc: &DocumentInfo) -> Result<Option<PersistedDocumentState>>;
+    fn save(&self, doc: &DocumentInfo, state: &PersistedDocumentState) -> Result<()>;
+    fn load_global_marks(&self) -> Result<GlobalMarks> {
+        Ok(GlobalMarks::new())
+    }
+    fn save_global_marks(&self, _marks: &GlobalMarks) -> Result<()> {
+        Ok(())
+    }
+    /// Saves several documents and the global marks, all at once where the
+    /// store supports it.
+    fn save_all(
+        &self,
+        docs: &[(&DocumentInfo, &PersistedDocumentState)],
+        marks: &GlobalMarks,
+    ) -> Result<()> {
+        for (doc, state) in docs {
+            self.save(doc, state)?;
+        }
+        self.save_global_marks(marks)
+    }
+    /// The recently opened documents, for stores that keep them; `None`
+    /// leaves the list to the caller.
+    fn load_recent(&self) -> Result<Option<RecentFiles>> {
+        Ok(None)
+    }
+    fn save_recent(&self, _recent: &RecentFiles) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The id a document's state is stored under.
+pub(crate) fn state_id(key: StateKey, doc: &DocumentInfo) -> DocumentId {
+    match key {
+        StateKey::Path => doc.id,
+        StateKey::Content => document_id_for_content(&doc.path).unwrap_or_else(|err| {
+            warn!(?err, path = %doc.path.display(), "falling back to path-based state");
+            doc.id
+        }),
+    }
+}
+
+/// Stores each document's state as JSON under `root`. Saving merges with
+/// whatever is on disk, so several machines can share `root` through a
+/// synced folder.
 pub struct FileStateStore {
     root: PathBuf,
+    key: StateKey,
+    /// State of each file as last read or written, to tell which fields this
+    /// process changed.
+    baselines: Mutex<HashMap<PathBuf, PersistedDocumentState>>,
 }
 
 impl FileStateStore {
     pub fn new(root: PathBuf) -> Result<Self> {
+        Self::with_key(root, StateKey::Path)
+    }
+
+    pub fn with_key(root: PathBuf, key: StateKey) -> Result<Self> {
         fs::create_dir_all(&root)
             .with_context(|| format!("failed to create state directory at {:?}", root))?;
-        Ok(Self { root })
+        Ok(Self {
+            root,
+            key,
+            baselines: Mutex::new(HashMap::new()),
+        })
     }
 
     fn state_path(&self, doc: &DocumentInfo) -> PathBuf {
-        let mut path = self.root.join(format!("{}.json", doc.id));
+        let id = state_id(self.key, doc);
+        let mut path = self.root.join(format!("{}.json", id));
         if let Some(ext) = path.extension() {
             if ext != "json" {
                 path.set_extension("json");
@@ -1997,29 +5307,152 @@ impl FileStateStore {
         }
         path
     }
+
+    fn global_marks_path(&self) -> PathBuf {
+        self.root.join("global_marks.json")
+    }
 }
 
 impl StateStore for FileStateStore {
     fn load(&self, doc: &DocumentInfo) -> Result<Option<PersistedDocumentState>> {
         let path = self.state_path(doc);
+        let state = read_state_file(&path)?;
+        self.baselines
+            .lock()
+            .insert(path, state.clone().unwrap_or_default());
+        Ok(state)
+    }
+
+    fn save(&self, doc: &DocumentInfo, state: &PersistedDocumentState) -> Result<()> {
+        let path = self.state_path(doc);
+        let mut baselines = self.baselines.lock();
+        let mut local = state.clone();
+        stamp_changes(&mut local, baselines.get(&path), unix_millis());
+        let merged = match read_state_file(&path) {
+            Ok(Some(remote)) => merge_states(local, remote),
+            Ok(None) => local,
+            Err(err) => {
+                warn!(?err, "overwriting unreadable state file");
+                local
+            }
+        };
+        write_json_atomically(&path, &merged)?;
+        baselines.insert(path, merged);
+        Ok(())
+    }
+
+    fn load_global_marks(&self) -> Result<GlobalMarks> {
+        let path = self.global_marks_path();
+        if !path.exists() {
+            return Ok(GlobalMarks::new());
+        }
+        let buf = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read global marks {:?}", path))?;
+        serde_json::from_str(&buf)
+            .with_context(|| format!("failed to decode global marks {:?}", path))
+    }
+
+    fn save_global_marks(&self, marks: &GlobalMarks) -> Result<()> {
+        write_json_atomically(&self.global_marks_path(), marks)
+    }
+}
+
+fn read_state_file(path: &Path) -> Result<Option<PersistedDocumentState>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut file =
+        File::open(path).with_context(|| format!("failed to open state file {:?}", path))?;
+    let mut buf = String::new();
+    file.read_to_string(&mut buf)?;
+    let state =
+        decode_state(&buf).with_context(|| format!("failed to decode state file {:?}", path))?;
+    Ok(Some(state))
+}
+
+fn write_json_atomically<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    let payload = serde_json::to_string_pretty(value)?;
+    let mut file =
+        File::create(&tmp).with_context(|| format!("failed to open temp state file {:?}", tmp))?;
+    file.write_all(payload.as_bytes())?;
+    file.flush()?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Open documents of a session, saved with [`Command::SaveSession`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub documents: Vec<SessionDocument>,
+    pub active: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDocument {
+    pub path: PathBuf,
+    #[serde(deserialize_with = "migrate::deserialize_state")]
+    pub state: PersistedDocumentState,
+}
+
+/// Named sessions (workspaces), kept separately from per-document state.
+pub trait SessionStore: Send + Sync {
+    fn load(&self, name: &str) -> Result<Option<SessionSnapshot>>;
+    fn save(&self, name: &str, snapshot: &SessionSnapshot) -> Result<()>;
+}
+
+/// Session names become file names, so they are limited to a safe alphabet.
+pub fn validate_session_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || matches!(ch, '-' | '_' | '.'))
+        && !name.starts_with('.');
+    if !valid {
+        anyhow::bail!(
+            "invalid session name {:?}; use letters, digits, '-', '_' and '.'",
+            name
+        );
+    }
+    Ok(())
+}
+
+pub struct FileSessionStore {
+    root: PathBuf,
+}
+
+impl FileSessionStore {
+    pub fn new(root: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&root)
+            .with_context(|| format!("failed to create session directory at {:?}", root))?;
+        Ok(Self { root })
+    }
+
+    fn session_path(&self, name: &str) -> Result<PathBuf> {
+        validate_session_name(name)?;
+        Ok(self.root.join(format!("{}.json", name)))
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self, name: &str) -> Result<Option<SessionSnapshot>> {
+        let path = self.session_path(name)?;
         if !path.exists() {
             return Ok(None);
         }
-        let mut file =
-            File::open(&path).with_context(|| format!("failed to open state file {:?}", path))?;
-        let mut buf = String::new();
-        file.read_to_string(&mut buf)?;
-        let state = serde_json::from_str(&buf)
-            .with_context(|| format!("failed to decode state file {:?}", path))?;
-        Ok(Some(state))
+        let buf = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read session file {:?}", path))?;
+        let snapshot = serde_json::from_str(&buf)
+            .with_context(|| format!("failed to decode session file {:?}", path))?;
+        Ok(Some(snapshot))
     }
 
-    fn save(&self, doc: &DocumentInfo, state: &PersistedDocumentState) -> Result<()> {
-        let path = self.state_path(doc);
+    fn save(&self, name: &str, snapshot: &SessionSnapshot) -> Result<()> {
+        let path = self.session_path(name)?;
         let tmp = path.with_extension("json.tmp");
-        let payload = serde_json::to_string_pretty(state)?;
+        let payload = serde_json::to_string_pretty(snapshot)?;
         let mut file = File::create(&tmp)
-            .with_context(|| format!("failed to open temp state file {:?}", tmp))?;
+            .with_context(|| format!("failed to open temp session file {:?}", tmp))?;
         file.write_all(payload.as_bytes())?;
         file.flush()?;
         fs::rename(tmp, path)?;
@@ -2032,15 +5465,315 @@ pub struct Session {
     active: usize,
     store: Arc<dyn StateStore>,
     events: Arc<Mutex<Vec<SessionEvent>>>,
+    scrolloff: usize,
+    reflow: bool,
+    follow: bool,
+    jump_to_change: bool,
+    count_changed_pages: bool,
+    copy_reflow: bool,
+    progressive: bool,
+    quote_templates: QuoteTemplates,
+    view: ViewGeometry,
+    highlight_style: HighlightStyle,
+    step_sizes: StepSizes,
+    prefetch: PrefetchPolicy,
+    memory_limits: MemoryLimits,
+    search_scope: SearchScope,
+    sessions: Option<Arc<dyn SessionStore>>,
+    session_name: Option<String>,
+    global_marks: GlobalMarks,
+    /// Global mark whose document still has to be opened by the frontend.
+    pending_mark: Option<GlobalMark>,
+    clock: SharedClock,
 }
 
 impl Session {
     pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self::with_clock(store, system_clock())
+    }
+
+    /// Session whose time-dependent behaviour follows `clock`.
+    pub fn with_clock(store: Arc<dyn StateStore>, clock: SharedClock) -> Self {
+        let global_marks = store.load_global_marks().unwrap_or_else(|err| {
+            warn!(?err, "failed to load global marks");
+            GlobalMarks::new()
+        });
         Self {
             documents: Vec::new(),
             active: 0,
             store,
             events: Arc::new(Mutex::new(Vec::new())),
+            scrolloff: 0,
+            reflow: false,
+            follow: false,
+            jump_to_change: false,
+            count_changed_pages: false,
+            copy_reflow: false,
+            progressive: true,
+            quote_templates: QuoteTemplates::default(),
+            view: ViewGeometry::default(),
+            highlight_style: HighlightStyle::default(),
+            step_sizes: StepSizes::default(),
+            prefetch: PrefetchPolicy::default(),
+            memory_limits: MemoryLimits::default(),
+            search_scope: SearchScope::default(),
+            sessions: None,
+            session_name: None,
+            global_marks,
+            pending_mark: None,
+            clock,
+        }
+    }
+
+    pub fn clock(&self) -> SharedClock {
+        self.clock.clone()
+    }
+
+    pub fn scrolloff(&self) -> usize {
+        self.scrolloff
+    }
+
+    /// Whether pages are shown as reflowed text instead of images.
+    pub fn reflow(&self) -> bool {
+        self.reflow
+    }
+
+    pub fn set_scrolloff(&mut self, cells: usize) {
+        self.scrolloff = cells;
+    }
+
+    /// Whether a reload that appends pages moves a reader who was on the
+    /// last page to the new last page.
+    pub fn follow(&self) -> bool {
+        self.follow
+    }
+
+    pub fn set_follow(&mut self, enabled: bool) {
+        self.follow = enabled;
+    }
+
+    /// Whether a reload moves to the first page whose text changed, e.g.
+    /// where a LaTeX edit landed.
+    pub fn jump_to_change(&self) -> bool {
+        self.jump_to_change
+    }
+
+    /// Reloads compare the text of the pages read so far, by viewing,
+    /// searching or selecting, with the new version's.
+    pub fn set_jump_to_change(&mut self, enabled: bool) {
+        self.jump_to_change = enabled;
+    }
+
+    /// Whether reloads report how many pages' text changed in
+    /// [`SessionEvent::DocumentReloaded`]. Like
+    /// [`set_jump_to_change`](Self::set_jump_to_change), only pages already
+    /// read are compared.
+    pub fn set_count_changed_pages(&mut self, enabled: bool) {
+        self.count_changed_pages = enabled;
+    }
+
+    fn compares_reloads(&self) -> bool {
+        self.jump_to_change || self.count_changed_pages
+    }
+
+    /// Whether copied selections have their hard-wrapped lines joined into
+    /// paragraphs.
+    pub fn copy_reflow(&self) -> bool {
+        self.copy_reflow
+    }
+
+    pub fn set_copy_reflow(&mut self, enabled: bool) {
+        self.copy_reflow = enabled;
+    }
+
+    /// Whether slow pages are drawn at a low resolution first; see
+    /// [`DocumentInstance::render_view_progressive`].
+    pub fn progressive(&self) -> bool {
+        self.progressive
+    }
+
+    pub fn set_progressive(&mut self, enabled: bool) {
+        self.progressive = enabled;
+    }
+
+    pub fn quote_templates(&self) -> &QuoteTemplates {
+        &self.quote_templates
+    }
+
+    pub fn set_quote_templates(&mut self, templates: QuoteTemplates) {
+        self.quote_templates = templates;
+    }
+
+    pub fn highlight_style(&self) -> HighlightStyle {
+        self.highlight_style
+    }
+
+    pub fn set_highlight_style(&mut self, style: HighlightStyle) {
+        self.highlight_style = style;
+    }
+
+    pub fn step_sizes(&self) -> StepSizes {
+        self.step_sizes
+    }
+
+    pub fn set_step_sizes(&mut self, steps: StepSizes) {
+        self.step_sizes = steps;
+    }
+
+    pub fn prefetch(&self) -> PrefetchPolicy {
+        self.prefetch
+    }
+
+    pub fn set_prefetch(&mut self, policy: PrefetchPolicy) {
+        self.prefetch = policy;
+    }
+
+    pub fn memory_limits(&self) -> MemoryLimits {
+        self.memory_limits
+    }
+
+    /// Applies `limits` to open documents and those opened later.
+    pub fn set_memory_limits(&mut self, limits: MemoryLimits) {
+        self.memory_limits = limits;
+        for doc in &mut self.documents {
+            doc.set_memory_limits(limits);
+        }
+    }
+
+    /// Where [`Command::SaveSession`] and [`Session::restore_session`] keep
+    /// named sessions.
+    pub fn set_session_store(&mut self, store: Arc<dyn SessionStore>) {
+        self.sessions = Some(store);
+    }
+
+    /// Name of the session that was restored or last saved.
+    pub fn session_name(&self) -> Option<&str> {
+        self.session_name.as_deref()
+    }
+
+    pub fn set_session_name(&mut self, name: String) -> Result<()> {
+        validate_session_name(&name)?;
+        self.session_name = Some(name);
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> SessionSnapshot {
+        SessionSnapshot {
+            documents: self
+                .documents
+                .iter()
+                .map(|doc| SessionDocument {
+                    path: doc.info.path.clone(),
+                    state: doc.state.clone(),
+                })
+                .collect(),
+            active: self.active,
+        }
+    }
+
+    /// Opens the documents of session `name` with their saved state. Files
+    /// that can no longer be opened are skipped. Returns `false` when no
+    /// such session exists.
+    pub async fn restore_session<P: DocumentProvider>(
+        &mut self,
+        provider: &P,
+        name: &str,
+    ) -> Result<bool> {
+        let store = self
+            .sessions
+            .clone()
+            .ok_or_else(|| anyhow!("sessions are not available"))?;
+        let Some(snapshot) = store.load(name)? else {
+            return Ok(false);
+        };
+        self.session_name = Some(name.to_string());
+
+        let mut active = None;
+        for (index, entry) in snapshot.documents.into_iter().enumerate() {
+            if let Err(err) = self.open_with(provider, entry.path.clone()).await {
+                warn!(?err, path = %entry.path.display(), "failed to reopen session document");
+                continue;
+            }
+            let opened = self.documents.len() - 1;
+            self.documents[opened].restore_state(entry.state);
+            if index <= snapshot.active {
+                active = Some(opened);
+            }
+        }
+        if let Some(index) = active {
+            self.apply(Command::SwitchDocument { index })?;
+        }
+        Ok(true)
+    }
+
+    pub fn global_marks(&self) -> &GlobalMarks {
+        &self.global_marks
+    }
+
+    /// Opens the document of a global mark that was jumped to while it was
+    /// not open, and moves to the marked position. Returns whether one was
+    /// pending.
+    pub async fn open_pending_mark<P: DocumentProvider>(&mut self, provider: &P) -> Result<bool> {
+        let Some(mark) = self.pending_mark.take() else {
+            return Ok(false);
+        };
+        self.open_with(provider, mark.path.clone()).await?;
+        if let Some(doc) = self.documents.last_mut() {
+            doc.jump_to_global_mark(&mark);
+            self.events
+                .lock()
+                .push(SessionEvent::RedrawNeeded(doc.info.id));
+        }
+        Ok(true)
+    }
+
+    fn goto_global_mark(&mut self, key: char) {
+        let Some(mark) = self.global_marks.get(&key).cloned() else {
+            return;
+        };
+        let id = document_id_for_path(&mark.path);
+        let Some(index) = self.documents.iter().position(|doc| doc.info.id == id) else {
+            self.pending_mark = Some(mark);
+            return;
+        };
+        let mut events = self.events.lock();
+        if index != self.active {
+            self.active = index;
+            events.push(SessionEvent::ActiveDocumentChanged(id));
+        }
+        self.documents[index].jump_to_global_mark(&mark);
+        events.push(SessionEvent::RedrawNeeded(id));
+    }
+
+    pub fn search_scope(&self) -> SearchScope {
+        self.search_scope
+    }
+
+    pub fn set_search_scope(&mut self, scope: SearchScope) {
+        self.search_scope = scope;
+    }
+
+    /// Records how the frontend laid out the active page on its last draw.
+    pub fn set_view_geometry(&mut self, view: ViewGeometry) {
+        self.view = view;
+    }
+
+    /// Adds the page each open document is on to its `:history`, as the
+    /// end of a session now. Closing a document records its own.
+    pub fn record_history(&mut self) {
+        let ended_at = unix_seconds(self.clock.wall_time());
+        for doc in &mut self.documents {
+            let page = doc.state.current_page;
+            history::record(&mut doc.state.history, page, ended_at);
+        }
+    }
+
+    /// Counts `elapsed` as time spent reading the active document's
+    /// current page on `day` (days since the Unix epoch in local time).
+    pub fn record_reading_time(&mut self, elapsed: Duration, day: i64) {
+        if let Some(doc) = self.documents.get_mut(self.active) {
+            let page = doc.state.current_page;
+            doc.state.stats.record(page, elapsed, day);
         }
     }
 
@@ -2057,17 +5790,26 @@ impl Session {
         &mut self,
         doc_id: DocumentId,
         query: String,
+        scope: SearchScope,
         matches: Vec<SearchMatch>,
         start_page: usize,
+        elapsed: Duration,
     ) -> Result<bool> {
         let Some(doc) = self.documents.iter_mut().find(|doc| doc.info.id == doc_id) else {
             return Ok(false);
         };
 
-        let changed = doc.apply_search_results(query, matches, start_page);
-        self.events
-            .lock()
-            .push(SessionEvent::RedrawNeeded(doc.info.id));
+        let total = matches.len();
+        let changed = doc.apply_search_results(query.clone(), scope, matches, start_page);
+        doc.follow_search_match(self.scrolloff, self.view);
+        let mut events = self.events.lock();
+        events.push(SessionEvent::RedrawNeeded(doc.info.id));
+        events.push(SessionEvent::SearchCompleted {
+            id: doc.info.id,
+            query,
+            matches: total,
+            elapsed,
+        });
         Ok(changed)
     }
 
@@ -2075,8 +5817,47 @@ impl Session {
         self.documents.get(self.active)
     }
 
+    pub fn documents(&self) -> &[DocumentInstance] {
+        &self.documents
+    }
+
+    /// The selected text for copying, joined into paragraphs when
+    /// [`copy_reflow`](Self::copy_reflow) is on.
     pub fn selection_text(&self) -> Option<String> {
-        self.active().and_then(|doc| doc.selection_text())
+        let doc = self.active()?;
+        if self.copy_reflow {
+            doc.selection_text_reflowed()
+        } else {
+            doc.selection_text()
+        }
+    }
+
+    /// The selected text wrapped in the quote template for `format`.
+    pub fn selection_quote(&self, format: QuoteFormat) -> Option<String> {
+        let doc = self.active()?;
+        let text = self.selection_text()?;
+        let pages = doc.selection_pages()?;
+        Some(fill_quote_template(
+            self.quote_templates.get(format),
+            format,
+            &text,
+            &doc.info,
+            pages,
+        ))
+    }
+
+    pub fn document(&self, doc_id: DocumentId) -> Option<&DocumentInstance> {
+        self.documents.iter().find(|doc| doc.info.id == doc_id)
+    }
+
+    /// Stops reporting `doc_id` as being rebuilt after reloading it was given up.
+    pub fn cancel_rebuild(&mut self, doc_id: DocumentId) {
+        if let Some(doc) = self.documents.iter_mut().find(|doc| doc.info.id == doc_id) {
+            if doc.rebuilding {
+                doc.rebuilding = false;
+                self.events.lock().push(SessionEvent::RedrawNeeded(doc_id));
+            }
+        }
     }
 
     pub fn contains_document(&self, doc_id: DocumentId) -> bool {
@@ -2103,7 +5884,8 @@ impl Session {
                 Vec::new()
             }
         };
-        let doc = DocumentInstance::new(info.clone(), backend, state, outline);
+        let mut doc = DocumentInstance::new(info.clone(), backend, state, outline);
+        doc.set_memory_limits(self.memory_limits);
         self.documents.push(doc);
         self.active = self.documents.len().saturating_sub(1);
         self.events
@@ -2126,7 +5908,16 @@ impl Session {
         };
 
         let path = self.documents[index].info.path.clone();
-        let backend = provider.open(&path).await?;
+        let backend = match provider.open(&path).await {
+            Ok(backend) => backend,
+            Err(err) => {
+                // Keep the old backend and its cached renders until the file
+                // can be opened again.
+                self.documents[index].rebuilding = true;
+                self.events.lock().push(SessionEvent::RedrawNeeded(doc_id));
+                return Err(err);
+            }
+        };
         let info = backend.info().clone();
         let outline = match backend.outline() {
             Ok(outline) => outline,
@@ -2140,8 +5931,34 @@ impl Session {
             }
         };
 
+        let previous_page_count = self.documents[index].info.page_count;
+        let page_count = info.page_count;
+        let was_on_last_page = self.documents[index].state.current_page + 1 >= previous_page_count;
+        let previous_digests = self.documents[index].take_page_digests();
         self.documents[index].reload(info, backend, outline);
-        self.events.lock().push(SessionEvent::RedrawNeeded(doc_id));
+        if self.follow && was_on_last_page && page_count > previous_page_count {
+            let doc = &mut self.documents[index];
+            doc.state.current_page = page_count - 1;
+            doc.state.viewport.reset();
+            doc.sync_jump_position();
+        }
+        let mut changed_pages = None;
+        if self.compares_reloads() {
+            let doc = &mut self.documents[index];
+            let changed = doc.changed_pages_since(&previous_digests, previous_page_count);
+            changed_pages = Some(changed.len());
+            if self.jump_to_change {
+                doc.jump_to_first_change(&changed);
+            }
+        }
+        let mut events = self.events.lock();
+        events.push(SessionEvent::RedrawNeeded(doc_id));
+        events.push(SessionEvent::DocumentReloaded {
+            id: doc_id,
+            previous_page_count,
+            page_count,
+            changed_pages,
+        });
         trace!(doc = %doc_id, "reloaded document after change");
         Ok(true)
     }
@@ -2151,9 +5968,26 @@ impl Session {
             Command::PutMark { key } => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
                     let curr_page = doc.state.current_page;
-                    doc.add_mark(key, curr_page);
+                    if is_global_mark(key) {
+                        let path = doc
+                            .info
+                            .path
+                            .canonicalize()
+                            .unwrap_or_else(|_| doc.info.path.clone());
+                        self.global_marks.insert(
+                            key,
+                            GlobalMark {
+                                path,
+                                page: curr_page,
+                                viewport: doc.state.viewport,
+                            },
+                        );
+                    } else {
+                        doc.add_mark(key, curr_page);
+                    }
                 }
             }
+            Command::GotoMark { key } if is_global_mark(key) => self.goto_global_mark(key),
             Command::GotoMark { key } => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
                     if let Some(page) = doc.get_page_from_mark(key) {
@@ -2192,9 +6026,23 @@ impl Session {
                     }
                 }
             }
+            Command::DeleteMark { key } if is_global_mark(key) => {
+                self.global_marks.remove(&key);
+            }
+            Command::DeleteMark { key } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    doc.state.marks.remove(&key);
+                }
+            }
+            Command::DeleteNamedMark { name } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    doc.state.named_marks.remove(&name);
+                }
+            }
             Command::Search { query } => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    doc.perform_search(query)?;
+                    doc.perform_search(query, self.search_scope)?;
+                    doc.follow_search_match(self.scrolloff, self.view);
                     self.events
                         .lock()
                         .push(SessionEvent::RedrawNeeded(doc.info.id));
@@ -2202,25 +6050,52 @@ impl Session {
             }
             Command::SearchNext { count } => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    if doc.next_search_match(count.max(1)).is_some() {
-                        self.events
-                            .lock()
-                            .push(SessionEvent::RedrawNeeded(doc.info.id));
-                    }
+                    let moved = doc.next_search_match(count.max(1)).is_some();
+                    doc.follow_search_match(self.scrolloff, self.view);
+                    let event = if doc
+                        .search_state
+                        .as_ref()
+                        .is_none_or(|state| state.matches.is_empty())
+                    {
+                        SessionEvent::MotionBlocked(MotionBoundary::NoMatches)
+                    } else if moved {
+                        SessionEvent::RedrawNeeded(doc.info.id)
+                    } else {
+                        return Ok(());
+                    };
+                    self.events.lock().push(event);
                 }
             }
             Command::SearchPrev { count } => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    if doc.previous_search_match(count.max(1)).is_some() {
+                    let moved = doc.previous_search_match(count.max(1)).is_some();
+                    doc.follow_search_match(self.scrolloff, self.view);
+                    let event = if doc
+                        .search_state
+                        .as_ref()
+                        .is_none_or(|state| state.matches.is_empty())
+                    {
+                        SessionEvent::MotionBlocked(MotionBoundary::NoMatches)
+                    } else if moved {
+                        SessionEvent::RedrawNeeded(doc.info.id)
+                    } else {
+                        return Ok(());
+                    };
+                    self.events.lock().push(event);
+                }
+            }
+            Command::EnterVisualMode => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.ensure_visual_cursor()? {
                         self.events
                             .lock()
                             .push(SessionEvent::RedrawNeeded(doc.info.id));
                     }
                 }
             }
-            Command::EnterVisualMode => {
+            Command::ToggleBlockSelection => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    if doc.ensure_visual_cursor()? {
+                    if doc.toggle_block_selection()? {
                         self.events
                             .lock()
                             .push(SessionEvent::RedrawNeeded(doc.info.id));
@@ -2238,7 +6113,9 @@ impl Session {
             }
             Command::MoveVisualCursor { motion, count } => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    if doc.move_visual_cursor(motion, count.max(1))? {
+                    let moved = doc.move_visual_cursor(motion, count.max(1))?;
+                    let panned = doc.follow_visual_cursor(self.scrolloff, self.view);
+                    if moved || panned {
                         self.events
                             .lock()
                             .push(SessionEvent::RedrawNeeded(doc.info.id));
@@ -2275,6 +6152,7 @@ impl Session {
             Command::SwapVisualCursor => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
                     if doc.swap_visual_cursor() {
+                        doc.follow_visual_cursor(self.scrolloff, self.view);
                         self.events
                             .lock()
                             .push(SessionEvent::RedrawNeeded(doc.info.id));
@@ -2315,10 +6193,19 @@ impl Session {
                     }
                 }
             }
-            Command::ActivateLink => {
+            Command::ToggleLinkFilter { filter } => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    match doc.activate_link() {
-                        LinkFollowResult::Navigated { .. } => {
+                    if doc.toggle_link_filter(filter) {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::ActivateLink => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    match doc.activate_link() {
+                        LinkFollowResult::Navigated { .. } => {
                             self.events
                                 .lock()
                                 .push(SessionEvent::RedrawNeeded(doc.info.id));
@@ -2332,6 +6219,103 @@ impl Session {
                     }
                 }
             }
+            Command::EnterFormMode => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    doc.start_form_mode()?;
+                    self.events
+                        .lock()
+                        .push(SessionEvent::RedrawNeeded(doc.info.id));
+                }
+            }
+            Command::LeaveFormMode => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    doc.clear_form_state();
+                    self.events
+                        .lock()
+                        .push(SessionEvent::RedrawNeeded(doc.info.id));
+                }
+            }
+            Command::FormNext { count } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.next_form_field(count.max(1)).is_some() {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::FormPrev { count } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.previous_form_field(count.max(1)).is_some() {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::ToggleFormField => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.toggle_current_form_field()? {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::SetFormFieldText { value } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if !matches!(
+                        doc.current_form_field().map(|field| &field.kind),
+                        Some(FormFieldKind::Text { .. })
+                    ) {
+                        anyhow::bail!("selected form field does not accept text");
+                    }
+                    if doc.set_current_form_field(FormFieldValue::Text(value))? {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::SaveDocumentAs { path } => {
+                if let Some(doc) = self.documents.get(self.active) {
+                    ensure_not_open_document(&doc.info, &path)?;
+                    doc.backend.save_as(&path)?;
+                    self.events
+                        .lock()
+                        .push(SessionEvent::DocumentSaved { path });
+                }
+            }
+            Command::SaveSession { name } => {
+                let store = self
+                    .sessions
+                    .clone()
+                    .ok_or_else(|| anyhow!("sessions are not available"))?;
+                let name = name
+                    .or_else(|| self.session_name.clone())
+                    .ok_or_else(|| anyhow!("no session name given"))?;
+                validate_session_name(&name)?;
+                store.save(&name, &self.snapshot())?;
+                self.session_name = Some(name);
+            }
+            Command::ExtractPages { pages, path } => {
+                if let Some(doc) = self.documents.get(self.active) {
+                    if pages.is_empty() || *pages.end() >= doc.info.page_count {
+                        anyhow::bail!(
+                            "page range {}-{} is outside the document (1-{})",
+                            pages.start() + 1,
+                            pages.end() + 1,
+                            doc.info.page_count
+                        );
+                    }
+                    ensure_not_open_document(&doc.info, &path)?;
+                    let count = pages.end() - pages.start() + 1;
+                    doc.backend.extract_pages(pages, &path)?;
+                    self.events
+                        .lock()
+                        .push(SessionEvent::PagesExtracted { path, pages: count });
+                }
+            }
             Command::OpenDocument { path: _ } => {
                 anyhow::bail!("use `open_with` to open documents asynchronously");
             }
@@ -2339,7 +6323,13 @@ impl Session {
                 if index >= self.documents.len() {
                     return Ok(());
                 }
-                let doc = self.documents.remove(index);
+                let mut doc = self.documents.remove(index);
+                let page = doc.state.current_page;
+                history::record(
+                    &mut doc.state.history,
+                    page,
+                    unix_seconds(self.clock.wall_time()),
+                );
                 self.store.save(&doc.info, &doc.state)?;
                 self.events
                     .lock()
@@ -2380,6 +6370,10 @@ impl Session {
                         self.events
                             .lock()
                             .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    } else if count > 0 {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::MotionBlocked(MotionBoundary::LastPage));
                     }
                 }
             }
@@ -2399,6 +6393,10 @@ impl Session {
                         self.events
                             .lock()
                             .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    } else if count > 0 {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::MotionBlocked(MotionBoundary::FirstPage));
                     }
                 }
             }
@@ -2416,15 +6414,22 @@ impl Session {
                     }
                 }
             }
-            Command::ScaleBy { factor } => {
+            Command::ScaleBy { .. } | Command::SetScale { .. } => {
+                let view = self.view;
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    let scale = (doc.state.scale * factor).clamp(0.25, 4.0);
+                    let scale = match command {
+                        Command::ScaleBy { factor } => doc.state.scale * factor,
+                        Command::SetScale { scale } => scale,
+                        _ => doc.state.scale,
+                    }
+                    .clamp(0.25, 4.0);
                     if (doc.state.scale - scale).abs() > f32::EPSILON {
-                        doc.state.scale = scale;
-                        if scale <= 1.0 + f32::EPSILON {
-                            doc.state.viewport.reset();
-                        } else {
-                            doc.state.viewport.clamp();
+                        match doc.zoom_focus() {
+                            Some(focus) => doc.zoom_centered_on(scale, focus.center(), view),
+                            None => {
+                                doc.state.scale = scale;
+                                doc.normalize_viewport();
+                            }
                         }
                         doc.sync_jump_position();
                         self.events
@@ -2448,6 +6453,46 @@ impl Session {
                     }
                 }
             }
+            Command::ScrollLines { lines } => {
+                let view = self.view;
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.scroll_lines(lines, view) {
+                        doc.sync_jump_position();
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::ScrollScreens { screens } => {
+                let view = self.view;
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    match doc.scroll_screens(screens, view) {
+                        Ok(true) => {
+                            doc.sync_jump_position();
+                            self.events
+                                .lock()
+                                .push(SessionEvent::RedrawNeeded(doc.info.id));
+                        }
+                        Ok(false) => {}
+                        Err(boundary) => self
+                            .events
+                            .lock()
+                            .push(SessionEvent::MotionBlocked(boundary)),
+                    }
+                }
+            }
+            Command::ZoomToRect { rect } => {
+                let view = self.view;
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.zoom_to_rect(rect, view) {
+                        doc.sync_jump_position();
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
             Command::AdjustViewport { delta_x, delta_y } => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
                     if doc.state.viewport.adjust(delta_x, delta_y) {
@@ -2459,6 +6504,21 @@ impl Session {
                     }
                 }
             }
+            Command::ToggleReflow | Command::SetReflow { .. } => {
+                let enabled = match command {
+                    Command::SetReflow { enabled } => enabled,
+                    _ => !self.reflow,
+                };
+                if enabled != self.reflow {
+                    self.reflow = enabled;
+                    if let Some(doc) = self.documents.get_mut(self.active) {
+                        doc.state.viewport.reset();
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
             Command::ToggleDarkMode => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
                     doc.state.dark_mode = !doc.state.dark_mode;
@@ -2467,9 +6527,99 @@ impl Session {
                         .push(SessionEvent::RedrawNeeded(doc.info.id));
                 }
             }
+            Command::SetDarkMode { enabled } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.set_dark_mode(enabled) {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::AddExternalHighlights { page, rects } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if !doc.add_external_highlights(page, rects) {
+                        anyhow::bail!("no valid highlight rectangles for page {}", page + 1);
+                    }
+                    if page == doc.state.current_page {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::ClearExternalHighlights { page } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.clear_external_highlights(page) {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::ToggleFitMode => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    let mode = match doc.state.fit_mode {
+                        FitMode::Page => FitMode::Width,
+                        FitMode::Width => FitMode::Page,
+                    };
+                    doc.set_fit_mode(mode);
+                    self.events
+                        .lock()
+                        .push(SessionEvent::RedrawNeeded(doc.info.id));
+                }
+            }
+            Command::SetFitMode { mode } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.set_fit_mode(mode) {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
             Command::JumpBackward => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    if let Some(position) = doc.pop_jump_backward() {
+                    match doc.pop_jump_backward() {
+                        Some(position) => {
+                            if doc.apply_document_position(position) {
+                                self.events
+                                    .lock()
+                                    .push(SessionEvent::RedrawNeeded(doc.info.id));
+                            }
+                        }
+                        None => self
+                            .events
+                            .lock()
+                            .push(SessionEvent::MotionBlocked(MotionBoundary::OldestJump)),
+                    }
+                }
+            }
+            Command::SetFirstPageNumber { number } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if doc.set_first_page_number(number) {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
+            Command::SetDailyGoal { pages } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    doc.state.daily_goal = pages.filter(|&pages| pages > 0);
+                }
+            }
+            Command::GotoPageLabel { label } => {
+                if let Some(doc) = self.documents.get(self.active) {
+                    let page = doc
+                        .page_for_label(&label)
+                        .ok_or_else(|| anyhow!("No page labelled '{}'", label))?;
+                    return self.apply(Command::GotoPage { page });
+                }
+            }
+            Command::GotoJump { index } => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    if let Some(position) = doc.take_jump(index) {
                         if doc.apply_document_position(position) {
                             self.events
                                 .lock()
@@ -2478,14 +6628,34 @@ impl Session {
                     }
                 }
             }
+            Command::SplitView | Command::SwapViews | Command::CloseOtherView => {
+                if let Some(doc) = self.documents.get_mut(self.active) {
+                    let changed = match command {
+                        Command::SplitView => doc.split_view(),
+                        Command::SwapViews => doc.swap_views(),
+                        _ => doc.close_other_view(),
+                    };
+                    if changed {
+                        self.events
+                            .lock()
+                            .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    }
+                }
+            }
             Command::JumpForward => {
                 if let Some(doc) = self.documents.get_mut(self.active) {
-                    if let Some(position) = doc.pop_jump_forward() {
-                        if doc.apply_document_position(position) {
-                            self.events
-                                .lock()
-                                .push(SessionEvent::RedrawNeeded(doc.info.id));
+                    match doc.pop_jump_forward() {
+                        Some(position) => {
+                            if doc.apply_document_position(position) {
+                                self.events
+                                    .lock()
+                                    .push(SessionEvent::RedrawNeeded(doc.info.id));
+                            }
                         }
+                        None => self
+                            .events
+                            .lock()
+                            .push(SessionEvent::MotionBlocked(MotionBoundary::NewestJump)),
                     }
                 }
             }
@@ -2494,21 +6664,47 @@ impl Session {
     }
 
     pub fn persist(&self) -> Result<()> {
-        for doc in &self.documents {
-            self.store.save(&doc.info, &doc.state)?;
-        }
+        let docs: Vec<_> = self
+            .documents
+            .iter()
+            .map(|doc| (&doc.info, &doc.state))
+            .collect();
+        self.store.save_all(&docs, &self.global_marks)
+    }
+}
+
+#[derive(Default)]
+pub struct MemorySessionStore {
+    inner: Mutex<HashMap<String, SessionSnapshot>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self, name: &str) -> Result<Option<SessionSnapshot>> {
+        Ok(self.inner.lock().get(name).cloned())
+    }
+
+    fn save(&self, name: &str, snapshot: &SessionSnapshot) -> Result<()> {
+        self.inner.lock().insert(name.to_string(), snapshot.clone());
         Ok(())
     }
 }
 
 pub struct MemoryStateStore {
     inner: Mutex<HashMap<DocumentId, PersistedDocumentState>>,
+    global_marks: Mutex<GlobalMarks>,
 }
 
 impl MemoryStateStore {
     pub fn new() -> Self {
         Self {
             inner: Mutex::new(HashMap::new()),
+            global_marks: Mutex::new(GlobalMarks::new()),
         }
     }
 }
@@ -2528,6 +6724,15 @@ impl StateStore for MemoryStateStore {
         self.inner.lock().insert(doc.id, state.clone());
         Ok(())
     }
+
+    fn load_global_marks(&self) -> Result<GlobalMarks> {
+        Ok(self.global_marks.lock().clone())
+    }
+
+    fn save_global_marks(&self, marks: &GlobalMarks) -> Result<()> {
+        *self.global_marks.lock() = marks.clone();
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -2537,79 +6742,65 @@ mod tests {
 
     use tempfile::tempdir;
 
-    struct FakeBackend {
-        info: DocumentInfo,
-    }
+    use crate::testing::{info_for, page_from_lines, test_info, FakeBackend, FakeFile, Gate};
+
+    struct FakeProvider;
 
     #[async_trait::async_trait]
-    impl DocumentBackend for FakeBackend {
-        fn info(&self) -> &DocumentInfo {
-            &self.info
+    impl DocumentProvider for FakeProvider {
+        async fn open(&self, path: &Path) -> Result<Arc<dyn DocumentBackend>> {
+            let info = info_for(path, 100);
+            Ok(Arc::new(FakeBackend::new(info)))
         }
+    }
 
-        fn render_page(&self, request: RenderRequest) -> Result<RenderImage> {
-            Ok(RenderImage {
-                width: 1,
-                height: 1,
-                pixels: vec![request.page_index as u8],
-            })
-        }
+    struct MagicProvider {
+        magic: &'static [u8],
+        name: &'static str,
+    }
 
-        fn page_text(&self, page_index: usize) -> Result<PageText> {
-            let content = format!("This is sample page {} with keyword", page_index);
-            let mut offset = 0;
-            let mut glyphs = Vec::new();
-            for ch in content.chars() {
-                let start = offset;
-                offset += ch.len_utf8();
-                glyphs.push(TextGlyph {
-                    range: start..offset,
-                    rect: NormalizedRect {
-                        left: 0.0,
-                        top: 0.0,
-                        right: 0.0,
-                        bottom: 0.0,
-                    },
-                });
-            }
-            Ok(PageText::new(content, glyphs))
+    #[async_trait::async_trait]
+    impl DocumentProvider for MagicProvider {
+        async fn open(&self, path: &Path) -> Result<Arc<dyn DocumentBackend>> {
+            let info = info_for(path, self.magic.len());
+            Ok(Arc::new(FakeBackend::new(info)))
         }
 
-        fn search_page(&self, page_index: usize, query: &str) -> Result<Vec<Vec<NormalizedRect>>> {
-            if query.trim().is_empty() {
-                return Ok(Vec::new());
-            }
-            let text = format!("This is sample page {} with keyword", page_index);
-            if text.to_lowercase().contains(&query.to_lowercase()) {
-                Ok(vec![vec![NormalizedRect {
-                    left: 0.1,
-                    top: 0.1,
-                    right: 0.9,
-                    bottom: 0.2,
-                }]])
-            } else {
-                Ok(Vec::new())
-            }
+        fn format_name(&self) -> &'static str {
+            self.name
         }
 
-        fn page_links(&self, _page_index: usize) -> Result<Vec<LinkDefinition>> {
-            Ok(Vec::new())
+        fn sniff(&self, header: &[u8]) -> bool {
+            header.starts_with(self.magic)
         }
     }
 
-    struct FakeProvider;
+    #[tokio::test]
+    async fn registry_selects_provider_by_content() {
+        let dir = tempdir().unwrap();
+        let registry = ProviderRegistry::new()
+            .register(MagicProvider {
+                magic: b"%PDF-",
+                name: "PDF",
+            })
+            .register(MagicProvider {
+                magic: b"PK",
+                name: "CBZ",
+            });
 
-    #[async_trait::async_trait]
-    impl DocumentProvider for FakeProvider {
-        async fn open(&self, path: &Path) -> Result<Arc<dyn DocumentBackend>> {
-            let info = DocumentInfo {
-                id: document_id_for_path(path),
-                path: path.to_path_buf(),
-                page_count: 100,
-                metadata: DocumentMetadata::default(),
-            };
-            Ok(Arc::new(FakeBackend { info }))
-        }
+        let misnamed = dir.path().join("paper.txt");
+        std::fs::write(&misnamed, b"%PDF-1.7\n...").unwrap();
+        let backend = registry.open(&misnamed).await.unwrap();
+        assert_eq!(backend.info().page_count, 5);
+
+        let comic = dir.path().join("comic.pdf");
+        std::fs::write(&comic, b"PK\x03\x04").unwrap();
+        assert_eq!(registry.open(&comic).await.unwrap().info().page_count, 2);
+
+        let unknown = dir.path().join("notes.pdf");
+        std::fs::write(&unknown, b"plain text").unwrap();
+        let err = registry.open(&unknown).await.err().unwrap();
+        assert!(err.to_string().contains("not recognised as PDF or CBZ"));
     }
 
     #[tokio::test]
@@ -2638,32 +6829,228 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn session_jump_history_tracks_positions() {
+    async fn closing_a_document_ends_its_session_in_the_history() {
         let store = Arc::new(MemoryStateStore::new());
-        let mut session = Session::new(store);
-        let provider = FakeProvider;
+        let clock = Arc::new(ManualClock::at(
+            std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_000),
+        ));
+        let mut session = Session::with_clock(store.clone(), clock.clone());
+        for path in ["/tmp/a.pdf", "/tmp/b.pdf"] {
+            session
+                .open_with(&FakeProvider, PathBuf::from(path))
+                .await
+                .unwrap();
+        }
+        session.apply(Command::GotoPage { page: 7 }).unwrap();
+        let info = session.active().unwrap().info.clone();
+        clock.advance(Duration::from_secs(60));
+        session.apply(Command::CloseDocument { index: 1 }).unwrap();
+
+        let history = store.load(&info).unwrap().unwrap().history;
+        assert_eq!(
+            history,
+            vec![HistoryEntry {
+                page: 7,
+                ended_at: 1_060
+            }]
+        );
+        // Quitting dates the documents still open by the same clock.
+        clock.advance(Duration::from_secs(60));
+        session.record_history();
+        assert_eq!(session.active().unwrap().state.history[0].ended_at, 1_120);
+    }
+
+    #[tokio::test]
+    async fn blocked_motions_report_the_boundary() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
         session
-            .open_with(&provider, PathBuf::from("/tmp/example.pdf"))
+            .open_with(&FakeProvider, PathBuf::from("/tmp/example.pdf"))
             .await
             .unwrap();
+        session.drain_events();
+        let blocked = |session: &Session| {
+            session
+                .drain_events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    SessionEvent::MotionBlocked(boundary) => Some(boundary),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
 
-        session.apply(Command::NextPage { count: 12 }).unwrap();
-        assert_eq!(session.active().unwrap().state.current_page, 12);
-
-        session.apply(Command::JumpBackward).unwrap();
-        assert_eq!(session.active().unwrap().state.current_page, 0);
-
+        session.apply(Command::PrevPage { count: 1 }).unwrap();
+        assert_eq!(blocked(&session), vec![MotionBoundary::FirstPage]);
+        session.apply(Command::NextPage { count: 1 }).unwrap();
+        assert!(blocked(&session).is_empty());
+        session.apply(Command::GotoPage { page: 99 }).unwrap();
+        session.apply(Command::NextPage { count: 1 }).unwrap();
+        assert_eq!(blocked(&session), vec![MotionBoundary::LastPage]);
         session.apply(Command::JumpForward).unwrap();
-        assert_eq!(session.active().unwrap().state.current_page, 12);
-
-        session.apply(Command::GotoPage { page: 25 }).unwrap();
-        assert_eq!(session.active().unwrap().state.current_page, 25);
-
-        session.apply(Command::PrevPage { count: 5 }).unwrap();
-        assert_eq!(session.active().unwrap().state.current_page, 20);
+        assert_eq!(blocked(&session), vec![MotionBoundary::NewestJump]);
+        session.apply(Command::SearchNext { count: 1 }).unwrap();
+        assert_eq!(blocked(&session), vec![MotionBoundary::NoMatches]);
+        assert_eq!(MotionBoundary::LastPage.message(), "Already at last page");
+    }
 
-        session.apply(Command::JumpBackward).unwrap();
-        assert_eq!(session.active().unwrap().state.current_page, 25);
+    #[tokio::test]
+    async fn session_sets_dark_and_fit_modes_explicitly() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&FakeProvider, PathBuf::from("/tmp/example.pdf"))
+            .await
+            .unwrap();
+
+        session
+            .apply(Command::SetDarkMode { enabled: true })
+            .unwrap();
+        session
+            .apply(Command::SetDarkMode { enabled: true })
+            .unwrap();
+        assert!(session.active().unwrap().state.dark_mode);
+        session
+            .apply(Command::SetDarkMode { enabled: false })
+            .unwrap();
+        assert!(!session.active().unwrap().state.dark_mode);
+
+        session.apply(Command::ToggleFitMode).unwrap();
+        assert_eq!(session.active().unwrap().state.fit_mode, FitMode::Width);
+        session
+            .apply(Command::AdjustViewport {
+                delta_x: 0.0,
+                delta_y: 0.5,
+            })
+            .unwrap();
+        session.apply(Command::ScaleBy { factor: 0.9 }).unwrap();
+        assert_eq!(session.active().unwrap().state.viewport.y, 0.5);
+
+        session
+            .apply(Command::SetFitMode {
+                mode: FitMode::Page,
+            })
+            .unwrap();
+        let doc = session.active().unwrap();
+        assert_eq!(doc.state.fit_mode, FitMode::Page);
+        assert_eq!(doc.state.viewport.y, 0.0);
+
+        session.apply(Command::SetScale { scale: 1.5 }).unwrap();
+        assert_eq!(session.active().unwrap().state.scale, 1.5);
+        session.apply(Command::SetScale { scale: 9.0 }).unwrap();
+        assert_eq!(session.active().unwrap().state.scale, 4.0);
+    }
+
+    #[tokio::test]
+    async fn session_tracks_external_highlights_per_page() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&FakeProvider, PathBuf::from("/tmp/example.pdf"))
+            .await
+            .unwrap();
+        let rect = NormalizedRect {
+            left: 0.1,
+            top: 0.2,
+            right: 0.3,
+            bottom: 0.4,
+        };
+
+        session
+            .apply(Command::AddExternalHighlights {
+                page: 0,
+                rects: vec![rect],
+            })
+            .unwrap();
+        session
+            .apply(Command::AddExternalHighlights {
+                page: 1,
+                rects: vec![rect, rect],
+            })
+            .unwrap();
+        assert!(session
+            .apply(Command::AddExternalHighlights {
+                page: 0,
+                rects: vec![NormalizedRect {
+                    left: 0.5,
+                    top: 0.5,
+                    right: 0.5,
+                    bottom: 0.6,
+                }],
+            })
+            .is_err());
+
+        let doc = session.active().unwrap();
+        assert_eq!(
+            doc.external_highlights_for_current_page()
+                .unwrap()
+                .current
+                .len(),
+            1
+        );
+
+        session
+            .apply(Command::ClearExternalHighlights { page: Some(0) })
+            .unwrap();
+        assert!(session
+            .active()
+            .unwrap()
+            .external_highlights_for_current_page()
+            .is_none());
+
+        session.apply(Command::NextPage { count: 1 }).unwrap();
+        assert_eq!(
+            session
+                .active()
+                .unwrap()
+                .external_highlights_for_current_page()
+                .unwrap()
+                .current
+                .len(),
+            2
+        );
+        session
+            .apply(Command::ClearExternalHighlights { page: None })
+            .unwrap();
+        assert!(session
+            .active()
+            .unwrap()
+            .external_highlights_for_current_page()
+            .is_none());
+    }
+
+    #[test]
+    fn persisted_state_defaults_fit_mode() {
+        let state: PersistedDocumentState =
+            serde_json::from_str(r#"{"current_page":3,"scale":1.0,"dark_mode":false,"marks":{}}"#)
+                .unwrap();
+        assert_eq!(state.fit_mode, FitMode::Page);
+    }
+
+    #[tokio::test]
+    async fn session_jump_history_tracks_positions() {
+        let store = Arc::new(MemoryStateStore::new());
+        let mut session = Session::new(store);
+        let provider = FakeProvider;
+        session
+            .open_with(&provider, PathBuf::from("/tmp/example.pdf"))
+            .await
+            .unwrap();
+
+        session.apply(Command::NextPage { count: 12 }).unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 12);
+
+        session.apply(Command::JumpBackward).unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 0);
+
+        session.apply(Command::JumpForward).unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 12);
+
+        session.apply(Command::GotoPage { page: 25 }).unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 25);
+
+        session.apply(Command::PrevPage { count: 5 }).unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 20);
+
+        session.apply(Command::JumpBackward).unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 25);
 
         session.apply(Command::JumpBackward).unwrap();
         assert_eq!(session.active().unwrap().state.current_page, 12);
@@ -2687,6 +7074,93 @@ mod tests {
         assert_eq!(session.active().unwrap().state.current_page, 40);
     }
 
+    #[tokio::test]
+    async fn goto_page_label_falls_back_to_page_numbers() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&FakeProvider, PathBuf::from("/tmp/example.pdf"))
+            .await
+            .unwrap();
+        session
+            .apply(Command::GotoPageLabel {
+                label: "37".to_string(),
+            })
+            .unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 36);
+        assert!(session
+            .apply(Command::GotoPageLabel {
+                label: "iv".to_string(),
+            })
+            .is_err());
+        assert_eq!(session.active().unwrap().state.current_page, 36);
+    }
+
+    #[tokio::test]
+    async fn jump_list_is_saved_and_can_be_jumped_into() {
+        let store = Arc::new(MemoryStateStore::new());
+        let mut session = Session::new(store.clone());
+        let provider = FakeProvider;
+        let path = PathBuf::from("/tmp/example.pdf");
+        session.open_with(&provider, path.clone()).await.unwrap();
+        session.apply(Command::GotoPage { page: 12 }).unwrap();
+        session.apply(Command::GotoPage { page: 25 }).unwrap();
+        session.apply(Command::GotoPage { page: 40 }).unwrap();
+        session.apply(Command::JumpBackward).unwrap();
+        session.persist().unwrap();
+
+        let mut session = Session::new(store);
+        session.open_with(&provider, path).await.unwrap();
+        let doc = session.active().unwrap();
+        assert_eq!(doc.state.current_page, 25);
+        let list = doc.jump_list();
+        let pages: Vec<usize> = list.entries.iter().map(|entry| entry.page).collect();
+        assert_eq!(pages, vec![0, 12, 40, 25]);
+        assert_eq!(list.current, 3);
+
+        session.apply(Command::GotoJump { index: 1 }).unwrap();
+        let doc = session.active().unwrap();
+        assert_eq!(doc.state.current_page, 12);
+        let pages: Vec<usize> = doc.jump_list().entries.iter().map(|e| e.page).collect();
+        assert_eq!(pages, vec![0, 12, 40, 25]);
+        assert_eq!(doc.jump_list().current, 1);
+
+        session.apply(Command::JumpForward).unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 40);
+        session.apply(Command::JumpBackward).unwrap();
+        session.apply(Command::JumpBackward).unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 0);
+    }
+
+    #[tokio::test]
+    async fn split_views_keep_their_own_positions() {
+        let store = Arc::new(MemoryStateStore::new());
+        let mut session = Session::new(store);
+        let provider = FakeProvider;
+        session
+            .open_with(&provider, PathBuf::from("/tmp/example.pdf"))
+            .await
+            .unwrap();
+
+        session.apply(Command::GotoPage { page: 3 }).unwrap();
+        session.apply(Command::SplitView).unwrap();
+        session.apply(Command::GotoPage { page: 30 }).unwrap();
+        let doc = session.active().unwrap();
+        assert_eq!(doc.state.current_page, 30);
+        assert_eq!(doc.other_view().unwrap().page, 3);
+
+        session.apply(Command::SwapViews).unwrap();
+        let doc = session.active().unwrap();
+        assert_eq!(doc.state.current_page, 3);
+        assert_eq!(doc.other_view().unwrap().page, 30);
+        let other = doc.render_view(doc.other_view().unwrap(), 1.0).unwrap();
+        assert_eq!(other.pixels, vec![30]);
+
+        session.apply(Command::CloseOtherView).unwrap();
+        let doc = session.active().unwrap();
+        assert!(doc.other_view().is_none());
+        assert_eq!(doc.state.current_page, 3);
+    }
+
     #[tokio::test]
     async fn session_search_navigates_matches() {
         let store = Arc::new(MemoryStateStore::new());
@@ -2733,6 +7207,10 @@ mod tests {
             assert_eq!(doc.state.current_page, 6);
             let summary = doc.search_summary().unwrap();
             assert_eq!(summary.current_index, Some(6));
+            assert_eq!(summary.current_page, Some(6));
+            assert_eq!(summary.pages.len(), doc.info.page_count);
+            assert_eq!(summary.pages[6], (6, 1));
+            assert_eq!(summary.matches_on(6), 1);
             let highlights = doc.search_highlights_for_current_page().unwrap();
             assert!(!highlights.current.is_empty());
         }
@@ -2757,61 +7235,292 @@ mod tests {
             let summary = doc.search_summary().unwrap();
             assert_eq!(summary.total, 0);
             assert!(summary.current_index.is_none());
+            assert!(summary.current_page.is_none());
+            assert_eq!(summary.matches_on(6), 0);
             assert!(doc.search_highlights_for_current_page().is_none());
         }
     }
 
-    struct LinkBackend {
-        info: DocumentInfo,
-        links: Vec<Vec<LinkDefinition>>,
+    fn labelled_document(labels: &[&str]) -> DocumentInstance {
+        let info = info_for(Path::new("/tmp/labels.pdf"), labels.len());
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_labels(labels));
+        let outline = vec![OutlineItem {
+            title: "Chapter 1".to_string(),
+            page_index: 3,
+            depth: 0,
+            page_label: None,
+        }];
+        DocumentInstance::new(info, backend, PersistedDocumentState::default(), outline)
     }
 
-    impl LinkBackend {
-        fn new(info: DocumentInfo, links: Vec<Vec<LinkDefinition>>) -> Self {
-            Self { info, links }
+    /// Pages whose number is a multiple of 7 mention a needle.
+    fn needle_text(page: usize) -> String {
+        if page.is_multiple_of(7) {
+            "a needle"
+        } else {
+            "hay"
         }
+        .to_string()
     }
 
-    #[async_trait::async_trait]
-    impl DocumentBackend for LinkBackend {
-        fn info(&self) -> &DocumentInfo {
-            &self.info
-        }
+    #[test]
+    fn searches_go_through_pages_in_batches_and_keep_page_order() {
+        let info = test_info(100);
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_text(needle_text));
+        let doc = DocumentInstance::new(
+            info,
+            backend.clone(),
+            PersistedDocumentState::default(),
+            Vec::new(),
+        );
+
+        let matches = doc
+            .search_context()
+            .build_search_matches("Needle", 0..100)
+            .unwrap();
+        let pages: Vec<usize> = matches.iter().map(|found| found.page).collect();
+        assert_eq!(pages, (0..100).step_by(7).collect::<Vec<_>>());
+
+        let mut batches = vec![8; 12];
+        batches.push(4);
+        assert_eq!(*backend.search_batches.lock(), batches);
+        assert_eq!(*backend.text_batches.lock(), batches);
+    }
 
-        fn render_page(&self, _request: RenderRequest) -> Result<RenderImage> {
-            Ok(RenderImage {
-                width: 1,
-                height: 1,
-                pixels: vec![0, 0, 0, 0],
+    #[test]
+    fn renders_do_not_wait_for_a_long_search() {
+        let info = test_info(200);
+        let gate = Gate::default();
+        // Calls on one backend take turns, as they do with pdfium.
+        let backend = Arc::new(
+            FakeBackend::new(info.clone())
+                .with_lock(Arc::default())
+                .paused_before(100, gate.clone()),
+        );
+        let doc = DocumentInstance::new(
+            info,
+            backend.clone(),
+            PersistedDocumentState::default(),
+            Vec::new(),
+        );
+        let search = doc.search_context();
+        let searching = std::thread::spawn(move || search.build_search_matches("keyword", 0..200));
+        gate.wait_for(1);
+
+        // The search is halfway, between two calls to the backend.
+        assert_eq!(backend.search_batches.lock().len(), 12);
+        backend
+            .render_page(RenderRequest {
+                page_index: 0,
+                scale: 1.0,
+                dark_mode: false,
             })
+            .unwrap();
+        assert!(!searching.is_finished());
+        gate.open();
+        assert_eq!(searching.join().unwrap().unwrap().len(), 200);
+    }
+
+    #[test]
+    fn text_cache_evicts_least_recently_used_pages_over_capacity() {
+        let text = |content: &str| Arc::new(PageText::new(content.repeat(100), Vec::new()));
+        let page_bytes = text("a").heap_bytes();
+        let mut cache = TextCache::with_capacity(page_bytes * 2);
+
+        cache.insert(0, text("a"));
+        cache.insert(1, text("b"));
+        assert!(cache.get(0).is_some());
+        cache.insert(2, text("c"));
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(0).is_some() && cache.get(2).is_some());
+        assert_eq!(cache.bytes, page_bytes * 2);
+
+        // A page bigger than the whole cache is still kept on its own.
+        cache.insert(3, text("dddd"));
+        assert_eq!(cache.pages.len(), 1);
+        assert!(cache.get(3).is_some());
+        cache.clear();
+        assert_eq!(cache.bytes, 0);
+    }
+
+    #[test]
+    fn memory_limits_shrink_the_caches() {
+        let info = test_info(100);
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_text(needle_text));
+        let mut doc =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        for page in 0..6 {
+            doc.render_page_with_scale(page, 1.0).unwrap();
         }
+        doc.search_context()
+            .build_search_matches("Needle", 0..100)
+            .unwrap();
+        assert_eq!(doc.cache_stats().rendered_pages, 6);
 
-        fn page_text(&self, _page_index: usize) -> Result<PageText> {
-            Ok(PageText::new(String::new(), Vec::new()))
+        doc.set_memory_limits(MemoryLimits {
+            rendered_pages: 2,
+            text_bytes: 0,
+            resolution: 0.5,
+        });
+        let stats = doc.cache_stats();
+        assert_eq!(stats.rendered_pages, 2);
+        assert_eq!(stats.rendered_capacity, 2);
+        assert_eq!(stats.text_pages, 1);
+        assert_eq!(stats.text_capacity, 0);
+
+        for page in 0..6 {
+            doc.render_page_with_scale(page, 1.0).unwrap();
         }
+        assert_eq!(doc.cache_stats().rendered_pages, 2);
+    }
+
+    #[test]
+    fn cache_stats_count_the_text_a_search_loaded() {
+        let info = test_info(100);
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_text(needle_text));
+        let doc =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        assert_eq!(doc.cache_stats().text_pages, 0);
+
+        doc.search_context()
+            .build_search_matches("Needle", 0..100)
+            .unwrap();
+        let stats = doc.cache_stats();
+        assert_eq!(stats.text_pages, 100);
+        assert!(stats.text_bytes > 0 && stats.text_bytes <= stats.text_capacity);
+        assert_eq!(stats.rendered_pages, 0);
+    }
+
+    #[test]
+    fn slow_pages_are_previewed_while_rendering_in_the_background() {
+        let info = info_for(Path::new("/tmp/maps.pdf"), 3);
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_scaled_renders());
+        let doc =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        let view = |page| DocumentPosition {
+            page,
+            ..doc.current_position()
+        };
 
-        fn search_page(
-            &self,
-            _page_index: usize,
-            _query: &str,
-        ) -> Result<Vec<Vec<NormalizedRect>>> {
-            Ok(Vec::new())
+        // Nothing is known to be slow yet.
+        let (image, scale) = doc.render_view_progressive(view(0), 1.0).unwrap();
+        assert_eq!((image.width, scale), (100, 1.0));
+        assert!(doc.take_background_renders().is_empty());
+
+        doc.render_times.lock().record(0, SLOW_RENDER);
+        let (image, scale) = doc.render_view_progressive(view(0), 2.0).unwrap();
+        assert_eq!((image.width, scale), (50, 0.5));
+        doc.render_view_progressive(view(0), 2.0).unwrap();
+        let mut renders = doc.take_background_renders();
+        assert_eq!(renders.len(), 1);
+        // Already rendering, so not queued again.
+        doc.render_view_progressive(view(0), 2.0).unwrap();
+        assert!(doc.take_background_renders().is_empty());
+
+        // Pages not rendered yet are taken to be as slow as the last one.
+        let (_, scale) = doc.render_view_progressive(view(1), 2.0).unwrap();
+        assert_eq!(scale, 0.5);
+        let page_one = doc.take_background_renders();
+        assert_eq!(page_one.len(), 1);
+        // Page 0 was not drawn again, so its render was cancelled.
+        assert!(!doc.finish_background_render(renders.remove(0).run()));
+
+        doc.render_view_progressive(view(0), 2.0).unwrap();
+        let mut renders = doc.take_background_renders();
+        assert!(doc.finish_background_render(renders.remove(0).run()));
+        let (image, scale) = doc.render_view_progressive(view(0), 2.0).unwrap();
+        assert_eq!((image.width, scale), (200, 2.0));
+        for render in page_one {
+            assert!(!doc.finish_background_render(render.run()));
         }
 
-        fn page_links(&self, page_index: usize) -> Result<Vec<LinkDefinition>> {
-            Ok(self.links.get(page_index).cloned().unwrap_or_default())
+        doc.render_times.lock().record(2, SLOW_RENDER);
+        doc.render_view_progressive(view(2), 2.0).unwrap();
+        let stale = doc.take_background_renders();
+        assert_eq!(stale.len(), 1);
+        doc.clear_renders();
+        for render in stale {
+            assert!(!doc.finish_background_render(render.run()));
         }
+        // The background render was fast, so page 0 is drawn directly now.
+        let (_, scale) = doc.render_view_progressive(view(0), 3.0).unwrap();
+        assert_eq!(scale, 3.0);
+    }
+
+    #[test]
+    fn page_sizes_are_learned_from_renders() {
+        let info = test_info(2);
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_scaled_renders());
+        let mut doc = DocumentInstance::new(
+            info.clone(),
+            backend.clone(),
+            PersistedDocumentState::default(),
+            Vec::new(),
+        );
+        assert_eq!(doc.page_size(0), None);
+        doc.render_page_with_scale(0, 2.0).unwrap();
+        assert_eq!(
+            doc.page_size(0),
+            Some(PageSize {
+                width: 100.0,
+                height: 0.5
+            })
+        );
+        assert_eq!(doc.page_size(1), None);
+
+        doc.reload(info, backend, Vec::new());
+        assert_eq!(doc.page_size(0), None);
+    }
+
+    #[test]
+    fn page_labels_name_and_find_pages() {
+        let doc = labelled_document(&["i", "ii", "iii", "1", "2", "3"]);
+        assert_eq!(doc.page_label(1).as_deref(), Some("ii"));
+        assert_eq!(doc.outline()[0].page_label.as_deref(), Some("1"));
+        assert_eq!(doc.page_for_label("II"), Some(1));
+        // A label wins over the page number it looks like.
+        assert_eq!(doc.page_for_label("2"), Some(4));
+        assert_eq!(doc.page_for_label("6"), Some(5));
+        assert_eq!(doc.page_for_label("iv"), None);
+        assert_eq!(doc.page_for_label("7"), None);
+
+        let plain = labelled_document(&["1", "2", "3", "4"]);
+        assert_eq!(plain.page_label(0), None);
+        assert_eq!(plain.outline()[0].page_label, None);
+        assert_eq!(plain.page_for_label("2"), Some(1));
+    }
+
+    #[test]
+    fn first_page_number_overrides_labels() {
+        let mut doc = labelled_document(&["i", "ii", "iii", "1", "2", "3"]);
+        assert!(doc.set_first_page_number(Some(-2)));
+        assert_eq!(doc.page_label(0).as_deref(), Some("-2"));
+        assert_eq!(doc.page_label(3).as_deref(), Some("1"));
+        assert_eq!(doc.outline()[0].page_label.as_deref(), Some("1"));
+        assert_eq!(doc.page_for_label("3"), Some(5));
+        assert_eq!(doc.page_for_label("ii"), None);
+        // Past the last printed number, numbers count pages again.
+        assert_eq!(doc.page_for_label("6"), Some(5));
+
+        assert!(doc.set_first_page_number(Some(13)));
+        assert_eq!(doc.page_for_label("14"), Some(1));
+        assert_eq!(doc.state.first_page_number, Some(13));
+
+        // Numbers past the ends of i64 label nothing rather than wrap, and
+        // looking them up falls back to counting pages.
+        assert!(doc.set_first_page_number(Some(i64::MAX)));
+        assert_eq!(doc.page_label(0).as_deref(), Some("9223372036854775807"));
+        assert_eq!(doc.page_label(1), None);
+        assert!(doc.set_first_page_number(Some(i64::MIN)));
+        assert_eq!(doc.page_for_label("5"), Some(4));
+
+        assert!(doc.set_first_page_number(None));
+        assert_eq!(doc.page_label(1).as_deref(), Some("ii"));
     }
 
     #[test]
     fn link_mode_navigation_and_activation() {
-        let path = PathBuf::from("/tmp/link-test.pdf");
-        let info = DocumentInfo {
-            id: document_id_for_path(&path),
-            path,
-            page_count: 3,
-            metadata: DocumentMetadata::default(),
-        };
+        let info = info_for(Path::new("/tmp/link-test.pdf"), 3);
 
         let links = vec![
             vec![LinkDefinition {
@@ -2821,7 +7530,7 @@ mod tests {
                     right: 0.3,
                     bottom: 0.2,
                 }],
-                action: LinkAction::GoTo { page: 1 },
+                action: LinkAction::GoTo { page: 1, top: None },
             }],
             vec![LinkDefinition {
                 rects: vec![NormalizedRect {
@@ -2837,7 +7546,7 @@ mod tests {
             Vec::new(),
         ];
 
-        let backend = Arc::new(LinkBackend::new(info.clone(), links));
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_link_lists(links));
         let mut instance =
             DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
 
@@ -2872,14 +7581,160 @@ mod tests {
     }
 
     #[test]
-    fn link_mode_skips_links_before_current_page() {
-        let path = PathBuf::from("/tmp/link-skip.pdf");
-        let info = DocumentInfo {
-            id: document_id_for_path(&path),
-            path,
-            page_count: 3,
-            metadata: DocumentMetadata::default(),
+    fn link_filters_restrict_cycling_to_one_kind() {
+        let info = info_for(Path::new("/tmp/link-filter.pdf"), 2);
+        let link = |left: f32, action: LinkAction| LinkDefinition {
+            rects: vec![NormalizedRect {
+                left,
+                top: 0.1,
+                right: left + 0.1,
+                bottom: 0.2,
+            }],
+            action,
+        };
+        let uri = |uri: &str| LinkAction::Uri {
+            uri: uri.to_string(),
+        };
+        let links = vec![
+            vec![
+                link(0.1, LinkAction::GoTo { page: 1, top: None }),
+                link(0.3, uri("https://example.com")),
+                link(0.5, uri("file:///tmp/other.pdf")),
+            ],
+            vec![
+                link(0.1, uri("appendix.pdf")),
+                link(0.3, LinkAction::GoTo { page: 0, top: None }),
+            ],
+        ];
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_link_lists(links));
+        let mut instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        assert!(!instance.toggle_link_filter(LinkFilter::Uri));
+        instance.start_link_mode().unwrap();
+
+        assert!(instance.toggle_link_filter(LinkFilter::File));
+        let summary = instance.link_summary().unwrap();
+        assert_eq!(summary.filter, LinkFilter::File);
+        assert_eq!(summary.total, 2);
+        assert_eq!(summary.current_index, Some(0));
+        instance.next_link(1);
+        assert_eq!(instance.state.current_page, 1);
+
+        instance.toggle_link_filter(LinkFilter::Internal);
+        let summary = instance.link_summary().unwrap();
+        assert_eq!(summary.total, 2);
+        assert_eq!(summary.current_index, Some(1));
+
+        instance.toggle_link_filter(LinkFilter::Uri);
+        assert_eq!(instance.link_summary().unwrap().total, 1);
+        instance.toggle_link_filter(LinkFilter::Uri);
+        let summary = instance.link_summary().unwrap();
+        assert_eq!(summary.filter, LinkFilter::All);
+        assert_eq!(summary.total, 5);
+    }
+
+    #[test]
+    fn links_wrapped_across_lines_are_merged() {
+        let text = column_glyphs(0.1, 0.9);
+        let merged = LinkDefinition::merge_adjacent(
+            vec![
+                // One URL split over the end of a line and the start of the next.
+                uri_link(0.7, 0.10, "https://example.com/long"),
+                uri_link(0.1, 0.12, "https://example.com/long"),
+                uri_link(0.4, 0.12, "https://example.com/other"),
+                // The same URL again further down the page stays its own link.
+                uri_link(0.1, 0.50, "https://example.com/other"),
+            ],
+            &text,
+        );
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0].rects.len(), 2);
+        assert_eq!(merged[1].rects.len(), 1);
+        assert_eq!(merged[2].rects[0].top, 0.50);
+    }
+
+    #[test]
+    fn separate_links_to_the_same_place_on_consecutive_lines_stay_apart() {
+        let text = column_glyphs(0.1, 0.9);
+        let links = || {
+            vec![
+                // "see [3]" in the middle of one line and of the next.
+                uri_link(0.4, 0.10, "#cite.3"),
+                uri_link(0.3, 0.12, "#cite.3"),
+                // Ends at the right margin, but the next one starts mid-line.
+                uri_link(0.7, 0.20, "#cite.4"),
+                uri_link(0.5, 0.22, "#cite.4"),
+            ]
         };
+        assert_eq!(LinkDefinition::merge_adjacent(links(), &text).len(), 4);
+
+        // Without the text's margins, nothing is taken to be wrapped.
+        let wrapped = vec![
+            uri_link(0.7, 0.10, "https://example.com/long"),
+            uri_link(0.1, 0.12, "https://example.com/long"),
+        ];
+        assert_eq!(LinkDefinition::merge_adjacent(wrapped, &[]).len(), 2);
+    }
+
+    #[test]
+    fn links_wrap_at_the_margins_of_their_column() {
+        let mut text = column_glyphs(0.1, 0.45);
+        text.extend(column_glyphs(0.55, 0.9));
+        let merged = LinkDefinition::merge_adjacent(
+            vec![
+                // Wraps at the right margin of the left column.
+                uri_link(0.25, 0.10, "https://example.com/left"),
+                uri_link(0.1, 0.12, "https://example.com/left"),
+                // Wraps at the right margin of the right column.
+                uri_link(0.7, 0.30, "https://example.com/right"),
+                uri_link(0.55, 0.32, "https://example.com/right"),
+                // Ends mid-line in the left column.
+                uri_link(0.1, 0.50, "https://example.com/cited"),
+                uri_link(0.1, 0.52, "https://example.com/cited"),
+            ],
+            &text,
+        );
+        let rects: Vec<usize> = merged.iter().map(|link| link.rects.len()).collect();
+        assert_eq!(rects, vec![2, 2, 1, 1]);
+    }
+
+    /// Glyph positions of a column of text running from `left` to `right`
+    /// down most of the page.
+    fn column_glyphs(left: f32, right: f32) -> Vec<NormalizedRect> {
+        let mut glyphs = Vec::new();
+        for line in 0..30 {
+            let top = 0.05 + line as f32 * 0.03;
+            let mut x = left;
+            while x < right - 0.005 {
+                glyphs.push(NormalizedRect {
+                    left: x,
+                    top,
+                    right: x + 0.01,
+                    bottom: top + 0.02,
+                });
+                x += 0.01;
+            }
+        }
+        glyphs
+    }
+
+    fn uri_link(left: f32, top: f32, uri: &str) -> LinkDefinition {
+        LinkDefinition {
+            rects: vec![NormalizedRect {
+                left,
+                top,
+                right: left + 0.2,
+                bottom: top + 0.02,
+            }],
+            action: LinkAction::Uri {
+                uri: uri.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn link_mode_skips_links_before_current_page() {
+        let info = info_for(Path::new("/tmp/link-skip.pdf"), 3);
 
         let links = vec![
             vec![LinkDefinition {
@@ -2889,7 +7744,7 @@ mod tests {
                     right: 0.2,
                     bottom: 0.2,
                 }],
-                action: LinkAction::GoTo { page: 0 },
+                action: LinkAction::GoTo { page: 0, top: None },
             }],
             Vec::new(),
             vec![LinkDefinition {
@@ -2899,13 +7754,15 @@ mod tests {
                     right: 0.4,
                     bottom: 0.4,
                 }],
-                action: LinkAction::GoTo { page: 2 },
+                action: LinkAction::GoTo { page: 2, top: None },
             }],
         ];
 
-        let backend = Arc::new(LinkBackend::new(info.clone(), links));
-        let mut state = PersistedDocumentState::default();
-        state.current_page = 1;
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_link_lists(links));
+        let state = PersistedDocumentState {
+            current_page: 1,
+            ..Default::default()
+        };
         let mut instance = DocumentInstance::new(info, backend, state, Vec::new());
 
         instance.start_link_mode().expect("link mode");
@@ -2918,46 +7775,1226 @@ mod tests {
     }
 
     #[test]
-    fn document_id_is_stable_for_same_path() {
-        let dir = tempdir().unwrap();
-        let file_path = dir.path().join("sample.pdf");
-        std::fs::write(&file_path, b"dummy").unwrap();
+    fn pages_whose_links_cannot_be_read_are_skipped() {
+        let backend = FakeBackend::new(test_info(3))
+            .with_links(|page| {
+                vec![LinkDefinition {
+                    rects: vec![NormalizedRect {
+                        left: 0.1,
+                        top: 0.1,
+                        right: 0.2,
+                        bottom: 0.2,
+                    }],
+                    action: LinkAction::Uri {
+                        uri: format!("https://example.com/{}", page),
+                    },
+                }]
+            })
+            .failing_on(1);
+        let info = backend.info.clone();
+        let mut instance = DocumentInstance::new(
+            info,
+            Arc::new(backend),
+            PersistedDocumentState::default(),
+            Vec::new(),
+        );
 
-        let first = document_id_for_path(&file_path);
-        let second = document_id_for_path(&file_path);
+        let pages: Vec<usize> = instance.uri_links().iter().map(|link| link.page).collect();
+        assert_eq!(pages, [0, 2]);
+        instance.start_link_mode().unwrap();
+        let state = instance.link_state.as_ref().unwrap();
+        assert_eq!(
+            state.all.iter().map(|link| link.page).collect::<Vec<_>>(),
+            [0, 2]
+        );
+    }
 
-        assert_eq!(first, second);
+    #[test]
+    fn uri_links_are_collected_once_across_pages() {
+        let info = info_for(Path::new("/tmp/link-uris.pdf"), 2);
+        let uri = |uri: &str| LinkDefinition {
+            rects: Vec::new(),
+            action: LinkAction::Uri {
+                uri: uri.to_string(),
+            },
+        };
+        let links = vec![
+            vec![
+                uri("https://a.example"),
+                LinkDefinition {
+                    rects: Vec::new(),
+                    action: LinkAction::GoTo { page: 1, top: None },
+                },
+            ],
+            vec![uri("https://b.example"), uri("https://a.example")],
+        ];
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_link_lists(links));
+        let instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+
+        let uris = instance.uri_links();
+        assert_eq!(
+            uris,
+            vec![
+                UriLink {
+                    page: 0,
+                    uri: "https://a.example".into()
+                },
+                UriLink {
+                    page: 1,
+                    uri: "https://b.example".into()
+                },
+            ]
+        );
     }
 
     #[test]
-    fn file_state_store_restores_state_with_stable_id() {
-        let dir = tempdir().unwrap();
-        let file_path = dir.path().join("sample.pdf");
-        std::fs::write(&file_path, b"dummy").unwrap();
+    fn doi_comes_from_metadata_or_the_first_pages() {
+        let document = |keywords: Vec<String>, backend: FakeBackend| {
+            let mut backend = backend;
+            backend.info.metadata.keywords = keywords;
+            let backend = Arc::new(backend);
+            let doc = DocumentInstance::new(
+                backend.info.clone(),
+                backend.clone(),
+                PersistedDocumentState::default(),
+                Vec::new(),
+            );
+            (doc, backend)
+        };
+        let article = |page: usize| {
+            match page {
+                0 => "Title",
+                1 => "Received 2020. DOI: 10.1000/xyz.123.",
+                _ => "10.1000/later",
+            }
+            .to_string()
+        };
+
+        let (mut doc, backend) = document(
+            Vec::new(),
+            FakeBackend::new(test_info(3)).with_text(article),
+        );
+        assert_eq!(doc.doi(), Some("10.1000/xyz.123".into()));
+        let reads = backend.text_batches.lock().len();
+        assert_eq!(doc.doi(), Some("10.1000/xyz.123".into()));
+        assert_eq!(backend.text_batches.lock().len(), reads);
+
+        let renamed =
+            Arc::new(FakeBackend::new(test_info(3)).with_text(|_| "doi:10.1000/new".to_string()));
+        doc.reload(renamed.info.clone(), renamed, Vec::new());
+        assert_eq!(doc.doi(), Some("10.1000/new".into()));
+
+        let (doc, _) = document(
+            vec!["doi:10.1000/meta".into()],
+            FakeBackend::new(test_info(1)).with_text(|_| "10.1000/text".to_string()),
+        );
+        assert_eq!(doc.doi(), Some("10.1000/meta".into()));
+
+        let (doc, _) = document(
+            Vec::new(),
+            FakeBackend::new(test_info(4))
+                .with_text(|page| if page == 3 { "10.1000/too-late" } else { "a" }.to_string()),
+        );
+        assert_eq!(doc.doi(), None);
+    }
+
+    /// Pages of `lines` lines spread over the whole page.
+    fn grid_backend(page_count: usize, lines: usize) -> FakeBackend {
+        let info = info_for(Path::new("/tmp/grid.pdf"), page_count);
+        FakeBackend::new(info).with_page_text(move |_| {
+            let line_height = 1.0 / lines as f32;
+            let mut text = String::new();
+            let mut glyphs = Vec::new();
+            for line in 0..lines {
+                let content = format!("line{:02}", line);
+                let count = content.chars().count() as f32;
+                for (col, ch) in content.chars().enumerate() {
+                    let start = text.len();
+                    text.push(ch);
+                    let left = 0.1 + 0.8 * col as f32 / count;
+                    glyphs.push(TextGlyph {
+                        range: start..text.len(),
+                        rect: NormalizedRect {
+                            left,
+                            top: line as f32 * line_height,
+                            right: left + 0.8 / count,
+                            bottom: (line as f32 + 0.8) * line_height,
+                        },
+                    });
+                }
+                text.push('\n');
+            }
+            PageText::new(text, glyphs)
+        })
+    }
 
-        let info = DocumentInfo {
-            id: document_id_for_path(&file_path),
-            path: file_path.clone(),
-            page_count: 3,
-            metadata: DocumentMetadata::default(),
+    #[test]
+    fn visual_cursor_keeps_scrolloff_margin_when_zoomed() {
+        let backend = Arc::new(grid_backend(1, 20));
+        let info = backend.info.clone();
+        let state = PersistedDocumentState {
+            scale: 2.0,
+            ..Default::default()
         };
+        let mut instance = DocumentInstance::new(info, backend, state, Vec::new());
 
-        let store = FileStateStore::new(dir.path().join("state")).unwrap();
+        let view = ViewGeometry {
+            cols: 40,
+            rows: 20,
+            visible_width: 0.5,
+            visible_height: 0.5,
+        };
+        instance.ensure_visual_cursor().unwrap();
+        assert!(!instance.follow_visual_cursor(2, view));
 
-        let mut state = PersistedDocumentState::default();
-        state.current_page = 2;
-        state.scale = 1.5;
-        state.dark_mode = true;
-        state.marks.insert('a', 1);
-        state.named_marks.insert("foo".into(), 2);
+        instance
+            .move_visual_cursor(SelectionMotion::Down, 10)
+            .unwrap();
+        assert!(instance.follow_visual_cursor(2, view));
+        let rect = instance.visual_cursor_highlight().unwrap();
+        let visible = 0.5;
+        let origin = instance.state.viewport.y * (1.0 - visible);
+        let margin = 2.0 / 20.0 * visible;
+        assert!(rect.bottom + margin <= origin + visible + 1e-4);
+        assert!(rect.top - margin >= origin - 1e-4);
 
-        store.save(&info, &state).unwrap();
+        instance
+            .move_visual_cursor(SelectionMotion::DocumentStart, 1)
+            .unwrap();
+        assert!(instance.follow_visual_cursor(2, view));
+        assert_eq!(instance.state.viewport.y, 0.0);
+    }
 
-        let restored = store.load(&info).unwrap().unwrap();
-        assert_eq!(restored.current_page, 2);
-        assert!(restored.dark_mode);
-        assert_eq!(restored.scale, 1.5);
-        assert_eq!(restored.marks.get(&'a'), Some(&1));
-        assert_eq!(restored.named_marks.get("foo"), Some(&2));
+    #[test]
+    fn scroll_lines_moves_by_the_median_line_height() {
+        let backend = Arc::new(grid_backend(1, 20));
+        let info = backend.info.clone();
+        let mut instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        let mut view = ViewGeometry {
+            cols: 40,
+            rows: 20,
+            visible_width: 1.0,
+            visible_height: 1.0,
+        };
+        assert!(!instance.scroll_lines(1, view));
+
+        view.visible_height = 0.5;
+        assert!(instance.scroll_lines(3, view));
+        // Lines are 0.05 apart and half the page is scrollable.
+        assert!((instance.state.viewport.y - 0.3).abs() < 1e-4);
+        assert!(instance.scroll_lines(-1, view));
+        assert!((instance.state.viewport.y - 0.2).abs() < 1e-4);
+        assert!(instance.scroll_lines(-10, view));
+        assert_eq!(instance.state.viewport.y, 0.0);
+    }
+
+    #[test]
+    fn scroll_screens_moves_by_the_view_and_on_to_the_next_page() {
+        let backend = Arc::new(grid_backend(2, 20));
+        let info = backend.info.clone();
+        let mut instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        let view = ViewGeometry {
+            cols: 40,
+            rows: 20,
+            visible_width: 1.0,
+            visible_height: 0.25,
+        };
+        // Half a screen is an eighth of the page, a sixth of the scroll range.
+        assert_eq!(instance.scroll_screens(0.5, view), Ok(true));
+        assert!((instance.state.viewport.y - 1.0 / 6.0).abs() < 1e-4);
+        assert_eq!(instance.scroll_screens(-1.0, view), Ok(true));
+        assert_eq!(instance.state.viewport.y, 0.0);
+        assert_eq!(
+            instance.scroll_screens(-1.0, view),
+            Err(MotionBoundary::FirstPage)
+        );
+
+        assert_eq!(instance.scroll_screens(4.0, view), Ok(true));
+        assert_eq!(instance.state.viewport.y, 1.0);
+        assert_eq!(instance.scroll_screens(1.0, view), Ok(true));
+        assert_eq!(instance.state.current_page, 1);
+        assert_eq!(instance.state.viewport.y, 0.0);
+        assert_eq!(instance.scroll_screens(-0.5, view), Ok(true));
+        assert_eq!(
+            (instance.state.current_page, instance.state.viewport.y),
+            (0, 1.0)
+        );
+    }
+
+    #[test]
+    fn zoomed_view_pans_to_the_current_search_match() {
+        let backend = Arc::new(grid_backend(1, 20));
+        let info = backend.info.clone();
+        let state = PersistedDocumentState {
+            scale: 2.0,
+            ..Default::default()
+        };
+        let mut instance = DocumentInstance::new(info, backend, state, Vec::new());
+        let view = ViewGeometry {
+            cols: 40,
+            rows: 20,
+            visible_width: 0.5,
+            visible_height: 0.5,
+        };
+        let rect = NormalizedRect {
+            left: 0.8,
+            top: 0.9,
+            right: 0.85,
+            bottom: 0.95,
+        };
+        let matches = vec![SearchMatch {
+            page: 0,
+            rects: vec![rect],
+        }];
+        instance.apply_search_results("x".into(), SearchScope::Document, matches, 0);
+        assert!(instance.follow_search_match(0, view));
+        let viewport = instance.state.viewport;
+        let (left, top) = (viewport.x * 0.5, viewport.y * 0.5);
+        assert!(rect.left >= left && rect.right <= left + 0.5 + 1e-4);
+        assert!(rect.top >= top && rect.bottom <= top + 0.5 + 1e-4);
+        assert!(!instance.follow_search_match(0, view));
+    }
+
+    #[test]
+    fn zooming_keeps_the_visual_cursor_centered() {
+        let backend = Arc::new(grid_backend(1, 20));
+        let info = backend.info.clone();
+        let mut instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        let view = ViewGeometry {
+            cols: 40,
+            rows: 20,
+            visible_width: 1.0,
+            visible_height: 1.0,
+        };
+        assert_eq!(instance.zoom_focus(), None);
+
+        instance.ensure_visual_cursor().unwrap();
+        instance
+            .move_visual_cursor(SelectionMotion::Down, 12)
+            .unwrap();
+        let focus = instance.zoom_focus().unwrap();
+        let (center_x, center_y) = focus.center();
+        instance.zoom_centered_on(2.0, (center_x, center_y), view);
+        assert_eq!(instance.state.scale, 2.0);
+        let visible = 0.5;
+        let top = instance.state.viewport.y * (1.0 - visible);
+        assert!((top + visible / 2.0 - center_y).abs() < 1e-4);
+        // The cursor is near the left edge, which stops the view.
+        assert_eq!(instance.state.viewport.x, 0.0);
+        assert!(center_x < visible / 2.0);
+    }
+
+    #[test]
+    fn zoom_to_rect_fills_the_view_with_the_rect() {
+        let backend = Arc::new(grid_backend(1, 20));
+        let info = backend.info.clone();
+        let mut instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        let view = ViewGeometry {
+            cols: 40,
+            rows: 20,
+            visible_width: 1.0,
+            visible_height: 1.0,
+        };
+        let rect = NormalizedRect {
+            left: 0.5,
+            top: 0.5,
+            right: 0.75,
+            bottom: 0.6,
+        };
+        assert!(instance.zoom_to_rect(rect, view));
+        // The wider side limits the zoom: a quarter of the page at 90%.
+        assert!((instance.state.scale - 3.6).abs() < 1e-4);
+        let visible = 1.0 / instance.state.scale;
+        let left = instance.state.viewport.x * (1.0 - visible);
+        assert!((left + visible / 2.0 - 0.625).abs() < 1e-4);
+        let top = instance.state.viewport.y * (1.0 - visible);
+        assert!((top + visible / 2.0 - 0.55).abs() < 1e-4);
+        assert!(!instance.zoom_to_rect(rect, view));
+
+        // A rect larger than the view cannot zoom out past fit.
+        let whole = NormalizedRect {
+            left: 0.0,
+            top: 0.0,
+            right: 1.0,
+            bottom: 1.0,
+        };
+        assert!(instance.zoom_to_rect(whole, view));
+        assert_eq!(instance.state.scale, 1.0);
+        assert_eq!(instance.state.viewport, ViewportOffset::default());
+    }
+
+    #[test]
+    fn paragraphs_join_lines_and_split_on_gaps() {
+        let page = page_from_lines(&[
+            "Scanned books are",
+            "hard to read with-",
+            "out reflow.",
+            "",
+            "Second paragraph.",
+        ]);
+        assert_eq!(
+            page.paragraphs(),
+            vec![
+                "Scanned books are hard to read without reflow.",
+                "Second paragraph."
+            ]
+        );
+    }
+
+    #[test]
+    fn word_ranges_follow_vim_words() {
+        let page = page_from_lines(&["Scanned books, are", "hard"]);
+        assert_eq!(page.word_range(9, 1, false), Some(8..13));
+        assert_eq!(page.word_range(9, 1, true), Some(7..13));
+        assert_eq!(page.word_range(1, 1, true), Some(0..8));
+        assert_eq!(page.word_range(0, 2, false), Some(0..8));
+        assert_eq!(page.word_range(13, 1, false), Some(13..14));
+        // Words do not run on across a line break.
+        assert_eq!(page.word_range(16, 1, false), Some(15..18));
+    }
+
+    #[test]
+    fn paragraph_ranges_and_sentences_come_from_the_layout() {
+        let page = page_from_lines(&[
+            "Scanned books are",
+            "hard to read with-",
+            "out reflow.",
+            "",
+            "Second paragraph",
+        ]);
+        assert_eq!(page.paragraph_range(3, 1), Some(0..46));
+        assert_eq!(page.paragraph_range(50, 1), Some(46..62));
+        assert_eq!(page.paragraph_range(0, 2), Some(0..62));
+        assert_eq!(page.sentence_starts(), vec![0, 46]);
+
+        let page = page_from_lines(&["One. Two?) Three 3.5 x", "end.", "Next"]);
+        assert_eq!(page.sentence_starts(), vec![0, 5, 11, 26]);
+    }
+
+    #[test]
+    fn rects_for_range_merges_glyphs_per_line() {
+        let page = page_from_lines(&["scanned", "pages"]);
+        let start = page.text.find("ned").unwrap();
+        let end = page.text.find("pag").unwrap() + 3;
+        let rects = page.rects_for_range(start..end);
+        assert_eq!(rects.len(), 2);
+        assert!((rects[0].left - 0.14).abs() < 1e-5);
+        assert!((rects[0].right - 0.17).abs() < 1e-5);
+        assert!((rects[1].left - 0.1).abs() < 1e-5);
+        assert!((rects[1].right - 0.13).abs() < 1e-5);
+    }
+
+    #[test]
+    fn glyph_text_spaces_words_the_text_layer_runs_together() {
+        let mut text = String::new();
+        let mut glyphs = Vec::new();
+        let mut push = |ch: char, left: f32, top: f32| {
+            let start = text.len();
+            text.push(ch);
+            glyphs.push(TextGlyph {
+                range: start..text.len(),
+                rect: NormalizedRect {
+                    left,
+                    top,
+                    right: left + 0.01,
+                    bottom: top + 0.02,
+                },
+            });
+        };
+        for (index, ch) in "two".chars().enumerate() {
+            push(ch, 0.1 + index as f32 * 0.01, 0.1);
+        }
+        for (index, ch) in "words".chars().enumerate() {
+            push(ch, 0.135 + index as f32 * 0.0101, 0.1);
+        }
+        push('x', 0.1, 0.2);
+        let page = PageText::new(text, glyphs);
+        assert_eq!(page.text, "twowordsx");
+        assert_eq!(page.glyph_text(0..8), "two words");
+        assert_eq!(page.glyph_text(3..9), "words\nx");
+        assert_eq!(page.glyph_text(0..2), "tw");
+    }
+
+    /// A page citing `[2]` and a page of references.
+    fn citation_backend() -> FakeBackend {
+        let rect = |from: usize, to: usize| NormalizedRect {
+            left: 0.1 + from as f32 * 0.01,
+            top: 0.1,
+            right: 0.1 + to as f32 * 0.01,
+            bottom: 0.14,
+        };
+        let citations = vec![
+            LinkDefinition {
+                rects: vec![rect(20, 28)],
+                action: LinkAction::GoTo { page: 0, top: None },
+            },
+            LinkDefinition {
+                rects: vec![rect(12, 15)],
+                action: LinkAction::GoTo { page: 1, top: None },
+            },
+        ];
+        FakeBackend::new(info_for(Path::new("/tmp/citations.pdf"), 2))
+            .with_page_text(|page| match page {
+                0 => page_from_lines(&["As shown in [2] and Figure 1."]),
+                _ => page_from_lines(&[
+                    "References",
+                    "[1] A. First. Old results. 1999.",
+                    "[2] B. Second. A long title that wraps onto the next",
+                    "line. Journal, 2020.",
+                    "[3] C. Third. Other. 2021.",
+                ]),
+            })
+            .with_link_lists(vec![citations])
+    }
+
+    #[test]
+    fn citation_preview_extracts_reference_entry() {
+        let backend = Arc::new(citation_backend());
+        let info = backend.info.clone();
+        let instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+
+        let preview = instance.citation_preview(0).unwrap().unwrap();
+        assert_eq!(preview.label, "[2]");
+        assert_eq!(preview.page, 1);
+        assert_eq!(
+            preview.text,
+            "[2] B. Second. A long title that wraps onto the next line. Journal, 2020."
+        );
+        assert!(instance.citation_preview(1).unwrap().is_none());
+        assert_eq!(instance.state.current_page, 0);
+    }
+
+    #[test]
+    fn search_iter_streams_matches_with_snippets() {
+        let backend = Arc::new(citation_backend());
+        let info = backend.info.clone();
+        let instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        let context = instance.search_context();
+
+        let options = SearchOptions {
+            context: 8,
+            ..SearchOptions::default()
+        };
+        let hits: Vec<SearchHit> = context.search_iter("second", options).collect();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].page, 1);
+        assert_eq!(hits[0].snippet, "[2] B. Second. A long");
+        assert_eq!(hits[0].rects.len(), 1);
+
+        let first_page = SearchOptions {
+            pages: Some(0..1),
+            ..SearchOptions::default()
+        };
+        assert_eq!(context.search_iter("second", first_page).count(), 0);
+        let first = context.search_iter("[2]", SearchOptions::default()).next();
+        assert_eq!(first.map(|hit| hit.page), Some(0));
+        assert!(context
+            .search_iter("", SearchOptions::default())
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn text_search_matches_get_rects_when_lowercasing_changes_length() {
+        let backend = Arc::new(citation_backend());
+        let info = backend.info.clone();
+        let instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+        let context = instance.search_context();
+
+        // "İ" lowercases to two characters, three bytes instead of two.
+        let page = Arc::new(page_from_lines(&["İzmir and İstanbul"]));
+        let found = context.find_in_text("stanbul", 0, Some(Arc::clone(&page)));
+        assert_eq!(found.len(), 1);
+        let range = found[0].range.clone().unwrap();
+        assert_eq!(&page.text[range], "stanbul");
+        assert_eq!(found[0].rects.len(), 1);
+        assert!((found[0].rects[0].left - 0.21).abs() < 1e-5);
+
+        // A match ending inside the lowercase form covers the whole "İ".
+        let found = context.find_in_text("and i", 0, Some(page));
+        assert_eq!(found[0].range, Some(7..13));
+    }
+
+    #[test]
+    fn citation_labels_are_numeric_references() {
+        assert!(is_citation_label("[12]"));
+        assert!(is_citation_label("[3, 4]"));
+        assert!(is_citation_label("7"));
+        assert!(!is_citation_label("Figure 1"));
+        assert!(!is_citation_label("[]"));
+    }
+
+    #[test]
+    fn form_mode_toggles_and_fills_fields() {
+        let info = info_for(Path::new("/tmp/form-test.pdf"), 2);
+        let rect = NormalizedRect {
+            left: 0.1,
+            top: 0.1,
+            right: 0.4,
+            bottom: 0.15,
+        };
+        let backend = Arc::new(FakeBackend::new(info.clone()).with_form_fields(
+            1,
+            vec![
+                FormField {
+                    id: 0,
+                    name: Some("name".into()),
+                    rect,
+                    kind: FormFieldKind::Text {
+                        value: String::new(),
+                    },
+                },
+                FormField {
+                    id: 3,
+                    name: Some("agree".into()),
+                    rect,
+                    kind: FormFieldKind::Checkbox { checked: false },
+                },
+            ],
+        ));
+        let mut instance =
+            DocumentInstance::new(info, backend, PersistedDocumentState::default(), Vec::new());
+
+        instance.start_form_mode().unwrap();
+        assert_eq!(instance.state.current_page, 1);
+        assert_eq!(instance.form_summary().unwrap().total, 2);
+        assert!(!instance.toggle_current_form_field().unwrap());
+        assert!(instance
+            .set_current_form_field(FormFieldValue::Text("Ada".into()))
+            .unwrap());
+        assert_eq!(
+            instance.current_form_field().unwrap().kind,
+            FormFieldKind::Text {
+                value: "Ada".into()
+            }
+        );
+
+        instance.next_form_field(1).unwrap();
+        assert!(instance.toggle_current_form_field().unwrap());
+        assert_eq!(
+            instance.current_form_field().unwrap().kind,
+            FormFieldKind::Checkbox { checked: true }
+        );
+        let highlights = instance.form_highlights_for_current_page().unwrap();
+        assert_eq!(highlights.current.len(), 1);
+        assert_eq!(highlights.others.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn save_as_refuses_to_overwrite_open_document() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&FakeProvider, PathBuf::from("/tmp/example.pdf"))
+            .await
+            .unwrap();
+
+        let err = session
+            .apply(Command::SaveDocumentAs {
+                path: PathBuf::from("/tmp/example.pdf"),
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("refusing"));
+        assert!(session
+            .apply(Command::SaveDocumentAs {
+                path: PathBuf::from("/tmp/example-filled.pdf"),
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn prefetch_policy_orders_pages_nearest_first() {
+        let policy = PrefetchPolicy::default();
+        assert_eq!(policy.pages(1, 10), vec![0, 2, 3]);
+        let forward = PrefetchPolicy {
+            radius: 3,
+            direction: PrefetchDirection::Forward,
+            ..policy
+        };
+        assert_eq!(forward.pages(7, 10), vec![8, 9]);
+        let backward = PrefetchPolicy {
+            direction: PrefetchDirection::Backward,
+            ..forward
+        };
+        assert_eq!(backward.pages(7, 10), vec![6, 5, 4]);
+        let disabled = PrefetchPolicy {
+            enabled: false,
+            ..policy
+        };
+        assert!(disabled.pages(5, 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn page_scoped_search_stays_on_the_current_page() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&FakeProvider, PathBuf::from("/tmp/example.pdf"))
+            .await
+            .unwrap();
+        session.set_search_scope(SearchScope::Page);
+        session.apply(Command::GotoPage { page: 3 }).unwrap();
+        session
+            .apply(Command::Search {
+                query: "keyword".to_string(),
+            })
+            .unwrap();
+        session.apply(Command::SearchNext { count: 2 }).unwrap();
+
+        let doc = session.active().unwrap();
+        assert_eq!(doc.state.current_page, 3);
+        let summary = doc.search_summary().unwrap();
+        assert_eq!(summary.scope, SearchScope::Page);
+        assert_eq!(summary.total, 1);
+        assert_eq!(summary.current_index, Some(0));
+    }
+
+    #[tokio::test]
+    async fn saved_sessions_restore_documents_and_state() {
+        let sessions: Arc<dyn SessionStore> = Arc::new(MemorySessionStore::new());
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session.set_session_store(Arc::clone(&sessions));
+        for path in ["/tmp/a.pdf", "/tmp/b.pdf", "/tmp/c.pdf"] {
+            session
+                .open_with(&FakeProvider, PathBuf::from(path))
+                .await
+                .unwrap();
+        }
+        session.apply(Command::SwitchDocument { index: 1 }).unwrap();
+        session.apply(Command::GotoPage { page: 4 }).unwrap();
+        assert!(session.apply(Command::SaveSession { name: None }).is_err());
+        session
+            .apply(Command::SaveSession {
+                name: Some("thesis".to_string()),
+            })
+            .unwrap();
+        assert!(session
+            .apply(Command::SaveSession {
+                name: Some("../escape".to_string()),
+            })
+            .is_err());
+
+        // The per-document store knows nothing, so state comes from the session.
+        let mut restored = Session::new(Arc::new(MemoryStateStore::new()));
+        restored.set_session_store(sessions);
+        assert!(!restored
+            .restore_session(&FakeProvider, "missing")
+            .await
+            .unwrap());
+        assert!(restored
+            .restore_session(&FakeProvider, "thesis")
+            .await
+            .unwrap());
+        assert_eq!(restored.session_name(), Some("thesis"));
+        let snapshot = restored.snapshot();
+        assert_eq!(snapshot.documents.len(), 3);
+        assert_eq!(snapshot.active, 1);
+        let active = restored.active().unwrap();
+        assert_eq!(active.info.path, PathBuf::from("/tmp/b.pdf"));
+        assert_eq!(active.state.current_page, 4);
+    }
+
+    #[tokio::test]
+    async fn global_marks_jump_across_documents() {
+        let store = Arc::new(MemoryStateStore::new());
+        let mut session = Session::new(store.clone());
+        for path in ["/tmp/a.pdf", "/tmp/b.pdf"] {
+            session
+                .open_with(&FakeProvider, PathBuf::from(path))
+                .await
+                .unwrap();
+        }
+        session.apply(Command::SwitchDocument { index: 0 }).unwrap();
+        session.apply(Command::GotoPage { page: 7 }).unwrap();
+        session.apply(Command::PutMark { key: 'A' }).unwrap();
+        session.apply(Command::PutMark { key: 'a' }).unwrap();
+        assert!(session.global_marks().contains_key(&'A'));
+        assert!(!session.global_marks().contains_key(&'a'));
+
+        session.apply(Command::SwitchDocument { index: 1 }).unwrap();
+        session.apply(Command::GotoMark { key: 'A' }).unwrap();
+        let active = session.active().unwrap();
+        assert_eq!(active.info.path, PathBuf::from("/tmp/a.pdf"));
+        assert_eq!(active.state.current_page, 7);
+        session.persist().unwrap();
+
+        // A new session reopens the marked document on demand.
+        let mut restored = Session::new(store);
+        restored
+            .open_with(&FakeProvider, PathBuf::from("/tmp/b.pdf"))
+            .await
+            .unwrap();
+        restored.apply(Command::GotoMark { key: 'A' }).unwrap();
+        assert_eq!(restored.documents().len(), 1);
+        assert!(restored.open_pending_mark(&FakeProvider).await.unwrap());
+        assert!(!restored.open_pending_mark(&FakeProvider).await.unwrap());
+        let active = restored.active().unwrap();
+        assert_eq!(active.info.path, PathBuf::from("/tmp/a.pdf"));
+        assert_eq!(active.state.current_page, 7);
+        assert_eq!(active.marks().get(&'a'), Some(&7));
+
+        restored.apply(Command::DeleteMark { key: 'a' }).unwrap();
+        restored.apply(Command::DeleteMark { key: 'A' }).unwrap();
+        assert!(restored.active().unwrap().marks().is_empty());
+        assert!(restored.global_marks().is_empty());
+    }
+
+    #[tokio::test]
+    async fn follow_mode_keeps_reader_on_appended_pages() {
+        let provider = FakeFile::new(vec![""; 5]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/log.pdf"))
+            .await
+            .unwrap();
+        let id = session.active().unwrap().info.id;
+        session.apply(Command::GotoPage { page: 4 }).unwrap();
+
+        provider.pages.lock().resize(7, "");
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 4);
+
+        session.set_follow(true);
+        session.apply(Command::GotoPage { page: 6 }).unwrap();
+        provider.pages.lock().resize(9, "");
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 8);
+
+        // Readers further back are left alone.
+        session.apply(Command::GotoPage { page: 2 }).unwrap();
+        provider.pages.lock().resize(12, "");
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 2);
+    }
+
+    #[tokio::test]
+    async fn reload_jumps_to_the_first_changed_page() {
+        let provider = FakeFile::new(vec!["intro", "method", "results", "outlook"]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/thesis.pdf"))
+            .await
+            .unwrap();
+        let id = session.active().unwrap().info.id;
+        session.set_jump_to_change(true);
+        // Only pages read before the reload are compared.
+        *provider.pages.lock() = vec!["intro", "method", "better results", "outlook"];
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 0);
+
+        read_every_page(&session);
+        *provider.pages.lock() = vec!["intro", "method", "best results", "outlook"];
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 2);
+
+        // An unchanged rebuild stays put.
+        session.apply(Command::GotoPage { page: 0 }).unwrap();
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 0);
+
+        provider.pages.lock().push("appendix");
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 4);
+
+        session.set_jump_to_change(false);
+        provider.pages.lock()[1] = "new method";
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.active().unwrap().state.current_page, 4);
+    }
+
+    #[tokio::test]
+    async fn reloads_count_the_pages_that_changed() {
+        let provider = FakeFile::new(vec!["intro", "method", "results"]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/thesis.pdf"))
+            .await
+            .unwrap();
+        let id = session.active().unwrap().info.id;
+        session.set_count_changed_pages(true);
+        read_every_page(&session);
+
+        *provider.pages.lock() = vec!["intro", "new method", "results", "appendix"];
+        session.drain_events();
+        session.reload_document(&provider, id).await.unwrap();
+        let changed = session
+            .drain_events()
+            .into_iter()
+            .find_map(|event| match event {
+                SessionEvent::DocumentReloaded { changed_pages, .. } => Some(changed_pages),
+                _ => None,
+            });
+        assert_eq!(changed, Some(Some(2)));
+        // Counting alone does not move the reader.
+        assert_eq!(session.active().unwrap().state.current_page, 0);
+    }
+
+    fn read_every_page(session: &Session) {
+        let doc = session.active().unwrap();
+        let pages: Vec<usize> = (0..doc.info.page_count).collect();
+        doc.search_context().load_page_texts(&pages);
+    }
+
+    #[test]
+    fn changed_pages_cover_added_and_removed_pages() {
+        let digests =
+            |pairs: &[(usize, u64)]| pairs.iter().copied().collect::<HashMap<usize, u64>>();
+        let read = digests(&[(0, 1), (1, 2), (2, 3)]);
+        assert!(changed_pages(&read, 3, &read, 3).is_empty());
+        let edited = digests(&[(0, 1), (1, 5), (2, 3)]);
+        assert_eq!(changed_pages(&read, 3, &edited, 3), vec![1]);
+        assert_eq!(changed_pages(&read, 3, &edited, 4), vec![1, 3]);
+        assert_eq!(changed_pages(&read, 3, &read, 2), vec![2]);
+        // Pages read in only one version are not compared.
+        let unread = digests(&[(0, 1)]);
+        assert!(changed_pages(&read, 3, &unread, 3).is_empty());
+        assert!(changed_pages(&unread, 3, &edited, 3).is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_reload_keeps_previous_version_while_rebuilding() {
+        let provider = FakeFile::new(vec!["first draft"]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/paper.pdf"))
+            .await
+            .unwrap();
+        let id = session.active().unwrap().info.id;
+
+        provider.pages.lock().clear();
+        assert!(session.reload_document(&provider, id).await.is_err());
+        let doc = session.active().unwrap();
+        assert!(doc.is_rebuilding());
+        assert_eq!(doc.current_page_text().unwrap().text, "first draft\n");
+
+        *provider.pages.lock() = vec!["second draft"];
+        assert!(session.reload_document(&provider, id).await.unwrap());
+        let doc = session.active().unwrap();
+        assert!(!doc.is_rebuilding());
+        assert_eq!(doc.current_page_text().unwrap().text, "second draft\n");
+
+        provider.pages.lock().clear();
+        assert!(session.reload_document(&provider, id).await.is_err());
+        session.cancel_rebuild(id);
+        assert!(!session.active().unwrap().is_rebuilding());
+    }
+
+    #[tokio::test]
+    async fn block_selection_copies_a_column() {
+        let provider = FakeFile::new(vec!["left one    right one\nleft two    right two"]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/columns.pdf"))
+            .await
+            .unwrap();
+        let motion = |motion, count| Command::MoveVisualCursor { motion, count };
+        session.apply(Command::EnterVisualMode).unwrap();
+        session
+            .apply(motion(SelectionMotion::DocumentStart, 1))
+            .unwrap();
+        session.apply(motion(SelectionMotion::Right, 12)).unwrap();
+        session.apply(Command::ToggleBlockSelection).unwrap();
+        session.apply(motion(SelectionMotion::Down, 1)).unwrap();
+        session.apply(motion(SelectionMotion::LineEnd, 1)).unwrap();
+        assert_eq!(
+            session.selection_text().as_deref(),
+            Some("right one\nright two")
+        );
+        let highlights = session
+            .active()
+            .unwrap()
+            .selection_highlights_for_current_page()
+            .unwrap();
+        assert_eq!(highlights.current.len(), 18);
+
+        // Switching back selects the text in reading order again.
+        session.apply(Command::ToggleBlockSelection).unwrap();
+        assert!(session.selection_text().unwrap().contains("left two"));
+    }
+
+    #[tokio::test]
+    async fn copy_reflow_joins_wrapped_lines() {
+        let provider = FakeFile::new(vec![
+            "Scanned books are\nhard to read with-\nout reflow.\n\nSecond paragraph.",
+        ]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/scan.pdf"))
+            .await
+            .unwrap();
+        let motion = |motion| Command::MoveVisualCursor { motion, count: 1 };
+        session.apply(Command::EnterVisualMode).unwrap();
+        session
+            .apply(motion(SelectionMotion::DocumentStart))
+            .unwrap();
+        session.apply(motion(SelectionMotion::WordForward)).unwrap();
+        session.apply(Command::StartSelection).unwrap();
+        session.apply(motion(SelectionMotion::DocumentEnd)).unwrap();
+        assert!(session.selection_text().unwrap().contains("with-\nout"));
+
+        session.set_copy_reflow(true);
+        assert_eq!(
+            session.selection_text().as_deref(),
+            Some("books are hard to read without reflow.\n\nSecond paragraph.")
+        );
+    }
+
+    #[tokio::test]
+    async fn selections_are_copied_as_quotes() {
+        let provider = FakeFile::new(vec!["Costs rose by 5% & more.\n\nThen fell."]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/smith2020.pdf"))
+            .await
+            .unwrap();
+        let motion = |motion| Command::MoveVisualCursor { motion, count: 1 };
+        session.apply(Command::EnterVisualMode).unwrap();
+        session
+            .apply(motion(SelectionMotion::DocumentStart))
+            .unwrap();
+        session.apply(Command::StartSelection).unwrap();
+        session.apply(motion(SelectionMotion::DocumentEnd)).unwrap();
+        let text = session.selection_text().unwrap();
+        assert_eq!(
+            session.selection_quote(QuoteFormat::Markdown).unwrap(),
+            format!(
+                "{}\n>\n> — unknown author, *smith2020*, p. 1",
+                text.lines()
+                    .map(|line| if line.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {line}")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        );
+        let latex = session.selection_quote(QuoteFormat::Latex).unwrap();
+        assert!(latex.starts_with("% unknown author, smith2020\n\\textcite[1]{smith2020}:\n"));
+        assert!(latex.contains("5\\% \\& more."));
+
+        session.set_quote_templates(QuoteTemplates {
+            markdown: "%k p%p: %s 100%%%x".to_string(),
+            ..QuoteTemplates::default()
+        });
+        assert_eq!(
+            session.selection_quote(QuoteFormat::Markdown).unwrap(),
+            format!("smith2020 p1: {text} 100%%x")
+        );
+    }
+
+    #[tokio::test]
+    async fn text_objects_select_and_extend() {
+        let provider = FakeFile::new(vec!["alpha beta. Gamma\ndelta"]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/draft.pdf"))
+            .await
+            .unwrap();
+        let motion = |motion| Command::MoveVisualCursor { motion, count: 1 };
+        session.apply(Command::EnterVisualMode).unwrap();
+        session
+            .apply(motion(SelectionMotion::DocumentStart))
+            .unwrap();
+        session.apply(motion(SelectionMotion::InnerWord)).unwrap();
+        assert_eq!(session.selection_text().as_deref(), Some("alpha"));
+        session.apply(motion(SelectionMotion::AWord)).unwrap();
+        assert_eq!(session.selection_text().as_deref(), Some("alpha beta"));
+
+        session.apply(Command::ClearSelection).unwrap();
+        session
+            .apply(motion(SelectionMotion::SentenceForward))
+            .unwrap();
+        session.apply(Command::StartSelection).unwrap();
+        session.apply(motion(SelectionMotion::AWord)).unwrap();
+        // Nothing follows the word on its line, so the blank before it is taken.
+        assert_eq!(session.selection_text().as_deref(), Some(" Gamma"));
+        session
+            .apply(motion(SelectionMotion::InnerParagraph))
+            .unwrap();
+        assert_eq!(
+            session.selection_text().as_deref(),
+            Some("alpha beta. Gamma\ndelta")
+        );
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_selection_and_cursor() {
+        let provider = FakeFile::new(vec!["alpha beta\ngamma"]);
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&provider, PathBuf::from("/tmp/draft.pdf"))
+            .await
+            .unwrap();
+        let id = session.active().unwrap().info.id;
+        session.apply(Command::EnterVisualMode).unwrap();
+        session
+            .apply(Command::MoveVisualCursor {
+                motion: SelectionMotion::DocumentStart,
+                count: 1,
+            })
+            .unwrap();
+        session.apply(Command::StartSelection).unwrap();
+        session
+            .apply(Command::MoveVisualCursor {
+                motion: SelectionMotion::Right,
+                count: 5,
+            })
+            .unwrap();
+        assert_eq!(session.selection_text().as_deref(), Some("alpha"));
+
+        *provider.pages.lock() = vec!["alpha beta\ngamma delta\nepsilon"];
+        session.reload_document(&provider, id).await.unwrap();
+        assert_eq!(session.selection_text().as_deref(), Some("alpha"));
+
+        session.apply(Command::ClearSelection).unwrap();
+        session
+            .apply(Command::MoveVisualCursor {
+                motion: SelectionMotion::DocumentEnd,
+                count: 1,
+            })
+            .unwrap();
+        // The cursor's glyph is gone after this edit; it lands on the last one.
+        *provider.pages.lock() = vec!["alpha"];
+        session.reload_document(&provider, id).await.unwrap();
+        session.apply(Command::StartSelection).unwrap();
+        session
+            .apply(Command::MoveVisualCursor {
+                motion: SelectionMotion::DocumentStart,
+                count: 1,
+            })
+            .unwrap();
+        assert_eq!(session.selection_text().as_deref(), Some("alph"));
+    }
+
+    #[tokio::test]
+    async fn extract_pages_checks_range_and_target() {
+        let mut session = Session::new(Arc::new(MemoryStateStore::new()));
+        session
+            .open_with(&FakeProvider, PathBuf::from("/tmp/example.pdf"))
+            .await
+            .unwrap();
+        let page_count = session.active().unwrap().info.page_count;
+
+        let err = session
+            .apply(Command::ExtractPages {
+                pages: 0..=page_count,
+                path: PathBuf::from("/tmp/part.pdf"),
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("outside the document"));
+        let err = session
+            .apply(Command::ExtractPages {
+                pages: 0..=0,
+                path: PathBuf::from("/tmp/example.pdf"),
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("refusing"));
+        let err = session
+            .apply(Command::ExtractPages {
+                pages: 0..=0,
+                path: PathBuf::from("/tmp/part.pdf"),
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("not supported"));
+    }
+
+    #[test]
+    fn content_id_follows_renamed_files() {
+        let dir = tempdir().unwrap();
+        let original = dir.path().join("paper.pdf");
+        std::fs::write(&original, b"%PDF-1.7 body").unwrap();
+        let id = document_id_for_content(&original).unwrap();
+
+        let renamed = dir.path().join("moved.pdf");
+        std::fs::rename(&original, &renamed).unwrap();
+        assert_eq!(document_id_for_content(&renamed).unwrap(), id);
+
+        // Only the prefix is read, so an appended tail changes the id through the size.
+        let mut bytes = vec![b'x'; CONTENT_ID_PREFIX_BYTES as usize];
+        std::fs::write(&renamed, &bytes).unwrap();
+        let prefix_only = document_id_for_content(&renamed).unwrap();
+        bytes.push(b'y');
+        std::fs::write(&renamed, &bytes).unwrap();
+        assert_ne!(document_id_for_content(&renamed).unwrap(), prefix_only);
+        assert!(document_id_for_content(&original).is_err());
+        assert_eq!(
+            document_id_for_bytes(&bytes),
+            document_id_for_content(&renamed).unwrap()
+        );
+    }
+
+    #[test]
+    fn document_id_is_stable_for_same_path() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("sample.pdf");
+        std::fs::write(&file_path, b"dummy").unwrap();
+
+        let first = document_id_for_path(&file_path);
+        let second = document_id_for_path(&file_path);
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn file_state_store_restores_state_with_stable_id() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("sample.pdf");
+        std::fs::write(&file_path, b"dummy").unwrap();
+
+        let info = info_for(&file_path, 3);
+
+        let store = FileStateStore::new(dir.path().join("state")).unwrap();
+
+        let mut state = PersistedDocumentState {
+            current_page: 2,
+            scale: 1.5,
+            dark_mode: true,
+            ..Default::default()
+        };
+        state.marks.insert('a', 1);
+        state.named_marks.insert("foo".into(), 2);
+
+        store.save(&info, &state).unwrap();
+
+        let restored = store.load(&info).unwrap().unwrap();
+        assert_eq!(restored.current_page, 2);
+        assert!(restored.dark_mode);
+        assert_eq!(restored.scale, 1.5);
+        assert_eq!(restored.marks.get(&'a'), Some(&1));
+        assert_eq!(restored.named_marks.get("foo"), Some(&2));
+    }
+
+    #[test]
+    fn synced_state_merges_copies_of_the_same_file() {
+        let dir = tempdir().unwrap();
+        let sync_dir = dir.path().join("sync");
+        let copy = |name: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, b"same bytes").unwrap();
+            info_for(&path, 50)
+        };
+        let laptop_doc = copy("laptop.pdf");
+        let desktop_doc = copy("desktop.pdf");
+        let laptop = FileStateStore::with_key(sync_dir.clone(), StateKey::Content).unwrap();
+        let desktop = FileStateStore::with_key(sync_dir.clone(), StateKey::Content).unwrap();
+
+        let mut on_laptop = laptop.load(&laptop_doc).unwrap().unwrap_or_default();
+        let mut on_desktop = desktop.load(&desktop_doc).unwrap().unwrap_or_default();
+        on_laptop.current_page = 30;
+        laptop.save(&laptop_doc, &on_laptop).unwrap();
+        on_desktop.dark_mode = true;
+        desktop.save(&desktop_doc, &on_desktop).unwrap();
+
+        let reader = FileStateStore::with_key(sync_dir, StateKey::Content).unwrap();
+        let merged = reader.load(&laptop_doc).unwrap().unwrap();
+        assert_eq!(merged.current_page, 30);
+        assert!(merged.dark_mode);
     }
 }
@@ -0,0 +1,80 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer};
+use serde_json::{Map, Value};
+
+use crate::PersistedDocumentState;
+
+/// Layout of [`PersistedDocumentState`] this build writes. A change that
+/// needs saved states rewritten bumps it and adds a step to [`MIGRATIONS`].
+pub const STATE_VERSION: u32 = 1;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<()>;
+
+/// `MIGRATIONS[n]` upgrades a saved state from version `n` to `n + 1`.
+const MIGRATIONS: [Migration; STATE_VERSION as usize] = [from_unversioned];
+
+/// States saved before versions were recorded already have the layout of
+/// version 1.
+fn from_unversioned(_state: &mut Map<String, Value>) -> Result<()> {
+    Ok(())
+}
+
+/// Decodes a saved state, upgrading it from whichever version wrote it. A
+/// state written by a newer termpdf keeps its version, and the fields this
+/// build does not know are carried along so saving it again keeps them.
+pub fn decode_state(json: &str) -> Result<PersistedDocumentState> {
+    migrate_state(serde_json::from_str(json)?)
+}
+
+fn migrate_state(mut value: Value) -> Result<PersistedDocumentState> {
+    let Value::Object(fields) = &mut value else {
+        bail!("saved state is not a JSON object");
+    };
+    let version = match fields.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .context("saved state has an invalid version")?,
+    };
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(fields)
+            .with_context(|| format!("failed to upgrade saved state from version {}", from))?;
+    }
+    fields.insert("version".into(), version.max(STATE_VERSION).into());
+    Ok(serde_json::from_value(value)?)
+}
+
+/// `deserialize_with` for states embedded in other saved files.
+pub(crate) fn deserialize_state<'de, D>(deserializer: D) -> Result<PersistedDocumentState, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    migrate_state(Value::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_states_are_upgraded_and_newer_fields_kept() {
+        let old = r#"{"current_page": 3, "scale": 1.5, "dark_mode": false, "marks": {"a": 2},
+            "stats": {"page_millis": {"3": 20000}}}"#;
+        let state = decode_state(old).unwrap();
+        assert_eq!(state.version, STATE_VERSION);
+        assert_eq!(state.current_page, 3);
+        assert_eq!(state.marks[&'a'], 2);
+        assert_eq!(state.stats.page_millis[&3], 20000);
+
+        let newer = r#"{"version": 99, "current_page": 1, "scale": 1.0, "dark_mode": true,
+            "marks": {}, "crop": {"left": 0.1}}"#;
+        let state = decode_state(newer).unwrap();
+        assert_eq!(state.version, 99);
+        let saved = serde_json::to_value(&state).unwrap();
+        assert_eq!(saved["crop"]["left"], 0.1);
+        assert_eq!(saved["version"], 99);
+
+        assert!(decode_state(r#"{"version": "two"}"#).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+use std::cmp::Ordering;
+
+use crate::{NormalizedRect, TextGlyph, LINE_THRESHOLD};
+
+/// Pages with fewer positioned glyphs are never reordered.
+const MIN_GLYPHS: usize = 8;
+/// Share of consecutive glyph pairs that may step backwards before a page is
+/// considered out of order.
+const DISORDER_RATIO: f32 = 0.1;
+/// Horizontal resolution used to look for gutters between text columns.
+const COLUMN_BINS: usize = 50;
+/// Gap between glyphs, relative to their height, that separates words.
+const WORD_GAP: f32 = 0.2;
+/// Lines a gutter must run beside in each of the columns it separates, and
+/// lines that must run across it, before a page counts as having its columns
+/// interleaved. Tables and aligned key/value text rarely have this many.
+const MIN_COLUMN_LINES: usize = 6;
+/// Share of the page height over which a gutter must stay empty.
+const MIN_GUTTER_SPAN: f32 = 0.3;
+
+/// Whether the page has to be rebuilt in reading order: its glyphs jump
+/// around, or they run across the gutter of a multi-column layout line by
+/// line, which would put both columns into the same lines.
+pub(crate) fn needs_reordering(text: &str, glyphs: &[TextGlyph]) -> bool {
+    is_disordered(glyphs) || is_interleaved(text, glyphs)
+}
+
+/// Whether the positioned glyphs jump around the page more than ordinary
+/// text does. Pages that run consistently right to left are left alone.
+pub(crate) fn is_disordered(glyphs: &[TextGlyph]) -> bool {
+    let centers: Vec<(f32, f32)> = glyphs
+        .iter()
+        .filter(|glyph| glyph.rect.is_valid())
+        .map(|glyph| glyph.rect.center())
+        .collect();
+    if centers.len() < MIN_GLYPHS {
+        return false;
+    }
+    let mut forward = 0usize;
+    let mut backward = 0usize;
+    let mut upward = 0usize;
+    for pair in centers.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if (y1 - y0).abs() <= LINE_THRESHOLD {
+            if x1 >= x0 {
+                forward += 1;
+            } else {
+                backward += 1;
+            }
+        } else if y1 < y0 {
+            upward += 1;
+        }
+    }
+    if backward > forward {
+        return false;
+    }
+    (backward + upward) as f32 > (centers.len() - 1) as f32 * DISORDER_RATIO
+}
+
+/// Whether lines of text continue from one column into the next, as when a
+/// two-column page was written row by row.
+fn is_interleaved(text: &str, glyphs: &[TextGlyph]) -> bool {
+    let rects: Vec<NormalizedRect> = visible_glyphs(text, glyphs).map(|(_, rect)| rect).collect();
+    if rects.len() < MIN_GLYPHS {
+        return false;
+    }
+    let splits = gutters(&rects);
+    if splits.is_empty() {
+        return false;
+    }
+    let column_of = |rect: &NormalizedRect| {
+        let (x, _) = rect.center();
+        splits.iter().filter(|split| x > **split).count()
+    };
+    let crossings = rects
+        .windows(2)
+        .filter(|pair| {
+            (pair[1].center().1 - pair[0].center().1).abs() <= LINE_THRESHOLD
+                && column_of(&pair[0]) < column_of(&pair[1])
+        })
+        .count();
+    crossings >= MIN_COLUMN_LINES
+}
+
+/// Horizontal extent, as `(left, right)`, of each column of running text
+/// among `rects`, left to right. Text without gutters is one column.
+pub(crate) fn column_extents(rects: &[NormalizedRect]) -> Vec<(f32, f32)> {
+    let splits = gutters(rects);
+    let mut extents = vec![(f32::MAX, f32::MIN); splits.len() + 1];
+    for rect in rects {
+        let (x, _) = rect.center();
+        let (left, right) = &mut extents[splits.iter().filter(|split| x > **split).count()];
+        *left = left.min(rect.left);
+        *right = right.max(rect.right);
+    }
+    extents.retain(|(left, right)| left <= right);
+    extents
+}
+
+/// Positions of the gutters between columns of running text.
+fn gutters(rects: &[NormalizedRect]) -> Vec<f32> {
+    column_splits(rects.iter())
+        .into_iter()
+        .filter(|split| is_gutter(rects, *split))
+        .collect()
+}
+
+/// Whether the gap at `split` separates two columns of running text: text
+/// runs beside it on both sides over a large part of the page, in many
+/// lines each.
+fn is_gutter(rects: &[NormalizedRect], split: f32) -> bool {
+    let left: Vec<f32> = rects
+        .iter()
+        .filter(|rect| rect.right <= split)
+        .map(|rect| rect.center().1)
+        .collect();
+    let right: Vec<f32> = rects
+        .iter()
+        .filter(|rect| rect.left >= split)
+        .map(|rect| rect.center().1)
+        .collect();
+    let extent = |ys: &[f32]| {
+        ys.iter().fold((f32::MAX, f32::MIN), |(top, bottom), y| {
+            (top.min(*y), bottom.max(*y))
+        })
+    };
+    let (left_top, left_bottom) = extent(&left);
+    let (right_top, right_bottom) = extent(&right);
+    let (top, bottom) = (left_top.max(right_top), left_bottom.min(right_bottom));
+    if bottom - top < MIN_GUTTER_SPAN {
+        return false;
+    }
+    let beside = |ys: &[f32]| {
+        ys.iter()
+            .copied()
+            .filter(|y| (top..=bottom).contains(y))
+            .collect::<Vec<f32>>()
+    };
+    line_count(beside(&left)) >= MIN_COLUMN_LINES && line_count(beside(&right)) >= MIN_COLUMN_LINES
+}
+
+/// Number of distinct lines among glyph centres at heights `ys`.
+fn line_count(mut ys: Vec<f32>) -> usize {
+    ys.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let mut lines = 0;
+    let mut line_y = None;
+    for y in ys {
+        if line_y.is_none_or(|line_y: f32| y - line_y > LINE_THRESHOLD) {
+            lines += 1;
+            line_y = Some(y);
+        }
+    }
+    lines
+}
+
+/// Positioned glyphs that are not blank, with their text.
+fn visible_glyphs<'a>(
+    text: &'a str,
+    glyphs: &'a [TextGlyph],
+) -> impl Iterator<Item = (&'a str, NormalizedRect)> + 'a {
+    glyphs
+        .iter()
+        .filter(|glyph| glyph.rect.is_valid())
+        .filter_map(|glyph| {
+            let value = text.get(glyph.range.clone())?;
+            (!value.trim().is_empty()).then_some((value, glyph.rect))
+        })
+}
+
+/// Rebuilds the page in reading order: columns left to right, lines top to
+/// bottom, glyphs left to right. Glyphs without a position and whitespace
+/// glyphs are dropped; the text gets spaces at word gaps and newlines between
+/// lines instead.
+pub(crate) fn reorder(text: &str, glyphs: &[TextGlyph]) -> (String, Vec<TextGlyph>) {
+    let placed: Vec<(&str, NormalizedRect)> = visible_glyphs(text, glyphs).collect();
+    let splits = column_splits(placed.iter().map(|(_, rect)| rect));
+    let column_of = |rect: &NormalizedRect| {
+        let (x, _) = rect.center();
+        splits.iter().filter(|split| x > **split).count()
+    };
+
+    let mut ordered: Vec<(usize, &str, NormalizedRect)> = placed
+        .iter()
+        .map(|(value, rect)| (column_of(rect), *value, *rect))
+        .collect();
+    ordered.sort_by(|a, b| {
+        a.0.cmp(&b.0).then(
+            a.2.center()
+                .1
+                .partial_cmp(&b.2.center().1)
+                .unwrap_or(Ordering::Equal),
+        )
+    });
+
+    let mut lines: Vec<Vec<(&str, NormalizedRect)>> = Vec::new();
+    let mut current: Option<(usize, f32)> = None;
+    for (column, value, rect) in ordered {
+        let y = rect.center().1;
+        match (current, lines.last_mut()) {
+            (Some((line_column, line_y)), Some(line))
+                if line_column == column && y - line_y <= LINE_THRESHOLD =>
+            {
+                line.push((value, rect));
+            }
+            _ => {
+                lines.push(vec![(value, rect)]);
+                current = Some((column, y));
+            }
+        }
+    }
+
+    let mut buffer = String::with_capacity(text.len());
+    let mut reordered = Vec::with_capacity(placed.len());
+    for (index, line) in lines.iter_mut().enumerate() {
+        line.sort_by(|a, b| a.1.left.partial_cmp(&b.1.left).unwrap_or(Ordering::Equal));
+        if index > 0 {
+            buffer.push('\n');
+        }
+        let mut previous: Option<NormalizedRect> = None;
+        for (value, rect) in line.iter() {
+            if let Some(previous) = previous {
+                if rect.left - previous.right > (rect.bottom - rect.top) * WORD_GAP {
+                    buffer.push(' ');
+                }
+            }
+            let start = buffer.len();
+            buffer.push_str(value);
+            reordered.push(TextGlyph {
+                range: start..buffer.len(),
+                rect: *rect,
+            });
+            previous = Some(*rect);
+        }
+    }
+    (buffer, reordered)
+}
+
+/// Horizontal positions of the empty gutters between columns of text.
+fn column_splits<'a>(rects: impl Iterator<Item = &'a NormalizedRect>) -> Vec<f32> {
+    let bin = |x: f32| ((x.clamp(0.0, 1.0) * COLUMN_BINS as f32) as usize).min(COLUMN_BINS - 1);
+    let mut coverage = [0usize; COLUMN_BINS];
+    let mut total = 0usize;
+    for rect in rects {
+        for count in &mut coverage[bin(rect.left)..=bin(rect.right)] {
+            *count += 1;
+        }
+        total += 1;
+    }
+    // A title spanning both columns should not hide the gutter.
+    let quiet = total / 200;
+
+    let mut splits = Vec::new();
+    let mut seen_text = false;
+    let mut gap_start = None;
+    for (index, count) in coverage.iter().enumerate() {
+        if *count > quiet {
+            if let Some(start) = gap_start.take() {
+                splits.push((start + index) as f32 * 0.5 / COLUMN_BINS as f32);
+            }
+            seen_text = true;
+        } else if seen_text && gap_start.is_none() {
+            gap_start = Some(index);
+        }
+    }
+    splits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PageText;
+
+    fn glyphs_for(words: &[(&str, f32, f32)]) -> (String, Vec<TextGlyph>) {
+        let mut text = String::new();
+        let mut glyphs = Vec::new();
+        for (word, left, top) in words {
+            for (index, ch) in word.chars().enumerate() {
+                let start = text.len();
+                text.push(ch);
+                let left = left + index as f32 * 0.01;
+                glyphs.push(TextGlyph {
+                    range: start..text.len(),
+                    rect: NormalizedRect {
+                        left,
+                        top: *top,
+                        right: left + 0.01,
+                        bottom: top + 0.02,
+                    },
+                });
+            }
+        }
+        (text, glyphs)
+    }
+
+    #[test]
+    fn ordered_text_is_left_alone() {
+        let (text, glyphs) = glyphs_for(&[
+            ("first", 0.1, 0.1),
+            ("line", 0.17, 0.1),
+            ("second", 0.1, 0.15),
+            ("line", 0.18, 0.15),
+        ]);
+        assert!(!is_disordered(&glyphs));
+        let page = PageText::new(text.clone(), glyphs);
+        assert_eq!(page.text, text);
+    }
+
+    #[test]
+    fn scrambled_columns_are_read_in_order() {
+        let (text, glyphs) = glyphs_for(&[
+            ("two", 0.6, 0.1),
+            ("left", 0.1, 0.1),
+            ("right", 0.6, 0.15),
+            ("column", 0.15, 0.1),
+            ("one", 0.16, 0.15),
+            ("body", 0.1, 0.15),
+        ]);
+        assert!(is_disordered(&glyphs));
+        let page = PageText::new(text, glyphs);
+        assert_eq!(page.text, "left column\nbody one\ntwo\nright");
+        assert_eq!(page.glyph_char(0), Some('l'));
+        assert_eq!(page.boundary_offset(page.glyph_count()), page.text.len());
+    }
+
+    #[test]
+    fn columns_written_row_by_row_are_separated() {
+        let rows = ["left", "column", "runs", "down", "the", "page", "ends"];
+        let words: Vec<(&str, f32, f32)> = rows
+            .iter()
+            .enumerate()
+            .flat_map(|(row, word)| {
+                let top = 0.1 + row as f32 * 0.06;
+                [(*word, 0.1, top), (*word, 0.6, top)]
+            })
+            .collect();
+        let (text, glyphs) = glyphs_for(&words);
+        assert!(!is_disordered(&glyphs));
+        assert!(needs_reordering(&text, &glyphs));
+        let page = PageText::new(text, glyphs);
+        let column = rows.join("\n");
+        assert_eq!(page.text, format!("{}\n{}", column, column));
+        // Lines no longer run across the gutter.
+        assert_eq!(page.line(0).unwrap().glyph_range, 0..4);
+    }
+
+    #[test]
+    fn a_two_column_table_is_not_reordered() {
+        let (text, glyphs) = glyphs_for(&[
+            ("name", 0.1, 0.1),
+            ("value", 0.4, 0.1),
+            ("width", 0.1, 0.13),
+            ("210mm", 0.4, 0.13),
+            ("height", 0.1, 0.16),
+            ("297mm", 0.4, 0.16),
+            ("pages", 0.1, 0.19),
+            ("12", 0.4, 0.19),
+        ]);
+        assert!(!needs_reordering(&text, &glyphs));
+        let page = PageText::new(text.clone(), glyphs);
+        assert_eq!(page.text, text);
+    }
+
+    #[test]
+    fn a_single_column_is_not_split() {
+        let (text, glyphs) = glyphs_for(&[
+            ("a", 0.1, 0.1),
+            ("single", 0.12, 0.1),
+            ("column", 0.19, 0.1),
+            ("of", 0.1, 0.15),
+            ("running", 0.13, 0.15),
+            ("text", 0.21, 0.15),
+        ]);
+        assert!(!needs_reordering(&text, &glyphs));
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// A document that was opened recently and the page it was left on.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentFile {
+    pub path: PathBuf,
+    pub page: usize,
+    /// Seconds since the Unix epoch.
+    pub opened_at: u64,
+}
+
+/// Most recently opened documents, newest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecentFiles {
+    entries: Vec<RecentFile>,
+}
+
+impl RecentFiles {
+    pub const CAPACITY: usize = 50;
+
+    /// Reads the list at `path`; a missing file is an empty list.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let buf = fs::read_to_string(path)
+            .with_context(|| format!("failed to read recent files {:?}", path))?;
+        serde_json::from_str(&buf)
+            .with_context(|| format!("failed to decode recent files {:?}", path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        let payload = serde_json::to_string_pretty(self)?;
+        let mut file = File::create(&tmp)
+            .with_context(|| format!("failed to open temp recent files {:?}", tmp))?;
+        file.write_all(payload.as_bytes())?;
+        file.flush()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// A list of `entries`, newest first, as a store kept them.
+    pub fn from_entries(mut entries: Vec<RecentFile>) -> Self {
+        entries.truncate(Self::CAPACITY);
+        Self { entries }
+    }
+
+    /// Moves `path` to the front of the list with its latest page.
+    pub fn record(&mut self, path: PathBuf, page: usize, opened_at: u64) {
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.insert(
+            0,
+            RecentFile {
+                path,
+                page,
+                opened_at,
+            },
+        );
+        self.entries.truncate(Self::CAPACITY);
+    }
+
+    pub fn entries(&self) -> &[RecentFile] {
+        &self.entries
+    }
+
+    /// Entries whose file still exists.
+    pub fn existing(&self) -> Vec<&RecentFile> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.path.exists())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn recording_moves_documents_to_the_front() {
+        let dir = tempdir().unwrap();
+        let list = dir.path().join("recent.json");
+        let mut recent = RecentFiles::load(&list).unwrap();
+        assert!(recent.entries().is_empty());
+
+        recent.record(PathBuf::from("/a.pdf"), 3, 10);
+        recent.record(PathBuf::from("/b.pdf"), 0, 20);
+        recent.record(PathBuf::from("/a.pdf"), 7, 30);
+        recent.save(&list).unwrap();
+
+        let recent = RecentFiles::load(&list).unwrap();
+        let paths: Vec<_> = recent.entries().iter().map(|e| e.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("/a.pdf"), PathBuf::from("/b.pdf")]
+        );
+        assert_eq!(recent.entries()[0].page, 7);
+        assert!(recent.existing().is_empty());
+
+        let mut many = RecentFiles::default();
+        for index in 0..RecentFiles::CAPACITY + 5 {
+            many.record(PathBuf::from(format!("/{}.pdf", index)), 0, index as u64);
+        }
+        assert_eq!(many.entries().len(), RecentFiles::CAPACITY);
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension, Statement, TransactionBehavior};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::sync::{merge_states, stamp_changes, unix_millis};
+use crate::{
+    decode_state, read_state_file, state_id, DocumentId, DocumentInfo, GlobalMarks,
+    PersistedDocumentState, RecentFile, RecentFiles, StateKey, StateStore,
+};
+
+const SCHEMA: &str = "
+    PRAGMA journal_mode = WAL;
+    CREATE TABLE IF NOT EXISTS documents (
+        id TEXT PRIMARY KEY,
+        path TEXT,
+        state TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS global_marks (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        marks TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS recent (
+        path TEXT PRIMARY KEY,
+        page INTEGER NOT NULL,
+        opened_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS recent_by_time ON recent (opened_at);
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+";
+
+/// The `meta` row recording that the JSON state was imported.
+const JSON_IMPORTED: &str = "json_imported";
+
+/// How long to wait for another termpdf that is writing the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Keeps every document's state, the global marks and the recent files in
+/// one SQLite database, so a session's documents are saved together or not
+/// at all. Like [`FileStateStore`](crate::FileStateStore), saving merges
+/// with what another process wrote in the meantime.
+pub struct SqliteStateStore {
+    inner: Mutex<Inner>,
+    key: StateKey,
+}
+
+struct Inner {
+    conn: Connection,
+    /// State of each document as last read or written, to tell which fields
+    /// this process changed.
+    baselines: HashMap<DocumentId, PersistedDocumentState>,
+}
+
+impl SqliteStateStore {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: &Path, key: StateKey) -> Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create state directory at {:?}", parent))?;
+        }
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open state database {:?}", path))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(SCHEMA)
+            .with_context(|| format!("failed to set up state database {:?}", path))?;
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                conn,
+                baselines: HashMap::new(),
+            }),
+            key,
+        })
+    }
+
+    /// Copies the state files and global marks a [`FileStateStore`] keeps in
+    /// `dir`, and the recent files at `recent`, into the database. Anything
+    /// the database already has wins. The import is recorded in the same
+    /// transaction, so it happens once per database even if termpdf stops
+    /// halfway or two copies start together. Returns how many documents'
+    /// states were imported, `None` when an earlier import was recorded.
+    ///
+    /// [`FileStateStore`]: crate::FileStateStore
+    pub fn import_json(&self, dir: &Path, recent: &Path) -> Result<Option<usize>> {
+        let mut inner = self.inner.lock();
+        let tx = inner
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let done = tx
+            .query_row(
+                "SELECT 1 FROM meta WHERE key = ?1",
+                params![JSON_IMPORTED],
+                |_| Ok(()),
+            )
+            .optional()?;
+        if done.is_some() {
+            return Ok(None);
+        }
+
+        let mut states = Vec::new();
+        match fs::read_dir(dir) {
+            Ok(entries) => {
+                for entry in entries {
+                    let path = entry?.path();
+                    if path.extension().is_none_or(|ext| ext != "json") {
+                        continue;
+                    }
+                    let Some(id) = path
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .and_then(|stem| Uuid::parse_str(stem).ok())
+                    else {
+                        continue;
+                    };
+                    match read_state_file(&path) {
+                        Ok(Some(state)) => states.push((id, state)),
+                        Ok(None) => {}
+                        Err(err) => warn!(?err, "skipping unreadable state file"),
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read state directory {:?}", dir))
+            }
+        }
+        let marks_path = dir.join("global_marks.json");
+        let marks = if marks_path.exists() {
+            let buf = fs::read_to_string(&marks_path)
+                .with_context(|| format!("failed to read global marks {:?}", marks_path))?;
+            Some(buf)
+        } else {
+            None
+        };
+        let recent = RecentFiles::load(recent)?;
+
+        let mut imported = 0;
+        {
+            let mut insert =
+                tx.prepare("INSERT OR IGNORE INTO documents (id, state) VALUES (?1, ?2)")?;
+            for (id, state) in &states {
+                imported +=
+                    insert.execute(params![id.to_string(), serde_json::to_string(state)?])?;
+            }
+            if let Some(marks) = &marks {
+                tx.execute(
+                    "INSERT OR IGNORE INTO global_marks (id, marks) VALUES (0, ?1)",
+                    params![marks],
+                )?;
+            }
+            let mut insert = tx.prepare(
+                "INSERT OR IGNORE INTO recent (path, page, opened_at) VALUES (?1, ?2, ?3)",
+            )?;
+            for file in recent.entries().iter().rev() {
+                insert_recent(&mut insert, file)?;
+            }
+        }
+        tx.execute(
+            "INSERT INTO meta (key, value) VALUES (?1, ?2)",
+            params![JSON_IMPORTED, unix_millis().to_string()],
+        )?;
+        tx.commit()?;
+        Ok(Some(imported))
+    }
+
+    fn save_locked(
+        &self,
+        conn: &Connection,
+        baselines: &mut HashMap<DocumentId, PersistedDocumentState>,
+        doc: &DocumentInfo,
+        state: &PersistedDocumentState,
+    ) -> Result<()> {
+        let id = state_id(self.key, doc);
+        let mut local = state.clone();
+        stamp_changes(&mut local, baselines.get(&id), unix_millis());
+        let merged = match load_state(conn, id) {
+            Ok(Some(stored)) => merge_states(local, stored),
+            Ok(None) => local,
+            Err(err) => {
+                warn!(?err, "overwriting unreadable state");
+                local
+            }
+        };
+        conn.execute(
+            "INSERT INTO documents (id, path, state) VALUES (?1, ?2, ?3)
+             ON CONFLICT (id) DO UPDATE SET path = excluded.path, state = excluded.state",
+            params![
+                id.to_string(),
+                doc.path.to_string_lossy(),
+                serde_json::to_string(&merged)?
+            ],
+        )?;
+        baselines.insert(id, merged);
+        Ok(())
+    }
+}
+
+impl StateStore for SqliteStateStore {
+    fn load(&self, doc: &DocumentInfo) -> Result<Option<PersistedDocumentState>> {
+        let id = state_id(self.key, doc);
+        let mut inner = self.inner.lock();
+        let state = load_state(&inner.conn, id)?;
+        inner
+            .baselines
+            .insert(id, state.clone().unwrap_or_default());
+        Ok(state)
+    }
+
+    fn save(&self, doc: &DocumentInfo, state: &PersistedDocumentState) -> Result<()> {
+        let mut guard = self.inner.lock();
+        let inner = &mut *guard;
+        self.save_locked(&inner.conn, &mut inner.baselines, doc, state)
+    }
+
+    fn load_global_marks(&self) -> Result<GlobalMarks> {
+        let inner = self.inner.lock();
+        let marks: Option<String> = inner
+            .conn
+            .query_row("SELECT marks FROM global_marks WHERE id = 0", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        match marks {
+            Some(marks) => serde_json::from_str(&marks).context("failed to decode global marks"),
+            None => Ok(GlobalMarks::new()),
+        }
+    }
+
+    fn save_global_marks(&self, marks: &GlobalMarks) -> Result<()> {
+        let inner = self.inner.lock();
+        save_marks(&inner.conn, marks)
+    }
+
+    fn save_all(
+        &self,
+        docs: &[(&DocumentInfo, &PersistedDocumentState)],
+        marks: &GlobalMarks,
+    ) -> Result<()> {
+        let mut guard = self.inner.lock();
+        let inner = &mut *guard;
+        let tx = inner
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        // Baselines only change once the transaction commits.
+        let mut baselines = inner.baselines.clone();
+        for (doc, state) in docs {
+            self.save_locked(&tx, &mut baselines, doc, state)?;
+        }
+        save_marks(&tx, marks)?;
+        tx.commit()?;
+        inner.baselines = baselines;
+        Ok(())
+    }
+
+    fn load_recent(&self) -> Result<Option<RecentFiles>> {
+        let inner = self.inner.lock();
+        let mut select = inner.conn.prepare(
+            "SELECT path, page, opened_at FROM recent
+             ORDER BY opened_at DESC, rowid DESC LIMIT ?1",
+        )?;
+        let entries = select
+            .query_map(params![RecentFiles::CAPACITY as i64], |row| {
+                Ok(RecentFile {
+                    path: PathBuf::from(row.get::<_, String>(0)?),
+                    page: row.get::<_, i64>(1)?.max(0) as usize,
+                    opened_at: row.get::<_, i64>(2)?.max(0) as u64,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(Some(RecentFiles::from_entries(entries)))
+    }
+
+    fn save_recent(&self, recent: &RecentFiles) -> Result<()> {
+        let mut inner = self.inner.lock();
+        let tx = inner
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)?;
+        tx.execute("DELETE FROM recent", [])?;
+        {
+            let mut insert =
+                tx.prepare("INSERT INTO recent (path, page, opened_at) VALUES (?1, ?2, ?3)")?;
+            // Oldest first, so rows recorded in the same second keep their
+            // order by rowid.
+            for file in recent.entries().iter().rev() {
+                insert_recent(&mut insert, file)?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn load_state(conn: &Connection, id: DocumentId) -> Result<Option<PersistedDocumentState>> {
+    let state: Option<String> = conn
+        .query_row(
+            "SELECT state FROM documents WHERE id = ?1",
+            params![id.to_string()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    state
+        .map(|state| {
+            decode_state(&state)
+                .with_context(|| format!("failed to decode state of document {}", id))
+        })
+        .transpose()
+}
+
+fn save_marks(conn: &Connection, marks: &GlobalMarks) -> Result<()> {
+    conn.execute(
+        "INSERT INTO global_marks (id, marks) VALUES (0, ?1)
+         ON CONFLICT (id) DO UPDATE SET marks = excluded.marks",
+        params![serde_json::to_string(marks)?],
+    )?;
+    Ok(())
+}
+
+fn insert_recent(insert: &mut Statement<'_>, file: &RecentFile) -> Result<()> {
+    insert.execute(params![
+        file.path.to_string_lossy(),
+        file.page as i64,
+        file.opened_at as i64
+    ])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::info_for;
+    use crate::{FileStateStore, GlobalMark};
+    use tempfile::tempdir;
+
+    fn info(path: &str) -> DocumentInfo {
+        info_for(Path::new(path), 10)
+    }
+
+    #[test]
+    fn saves_documents_together_and_imports_json_state() {
+        let dir = tempdir().unwrap();
+        let json = FileStateStore::new(dir.path().join("state")).unwrap();
+        let old = info("/papers/old.pdf");
+        json.save(
+            &old,
+            &PersistedDocumentState {
+                current_page: 4,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut recent = RecentFiles::default();
+        recent.record(old.path.clone(), 4, 100);
+        recent.record(PathBuf::from("/papers/new.pdf"), 0, 100);
+        recent.save(&dir.path().join("recent.json")).unwrap();
+
+        let db = dir.path().join("state.db");
+        let store = SqliteStateStore::open(&db, StateKey::Path).unwrap();
+        let imported = store
+            .import_json(&dir.path().join("state"), &dir.path().join("recent.json"))
+            .unwrap();
+        assert_eq!(imported, Some(1));
+        assert_eq!(store.load(&old).unwrap().unwrap().current_page, 4);
+        let loaded = store.load_recent().unwrap().unwrap();
+        assert_eq!(loaded.entries(), recent.entries());
+
+        let new = info("/papers/new.pdf");
+        let state = PersistedDocumentState {
+            current_page: 7,
+            ..Default::default()
+        };
+        let mut marks = GlobalMarks::new();
+        marks.insert(
+            'A',
+            GlobalMark {
+                path: new.path.clone(),
+                page: 7,
+                viewport: Default::default(),
+            },
+        );
+        store.save_all(&[(&new, &state)], &marks).unwrap();
+        drop(store);
+
+        let reopened = SqliteStateStore::open(&db, StateKey::Path).unwrap();
+        let again = reopened
+            .import_json(&dir.path().join("state"), &dir.path().join("recent.json"))
+            .unwrap();
+        assert_eq!(again, None);
+        assert_eq!(reopened.load(&new).unwrap().unwrap().current_page, 7);
+        assert_eq!(reopened.load_global_marks().unwrap()[&'A'].page, 7);
+        assert!(reopened
+            .load(&info("/papers/unknown.pdf"))
+            .unwrap()
+            .is_none());
+    }
+}
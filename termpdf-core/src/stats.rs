@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Time on a page before it counts as read rather than skimmed past.
+const PAGE_READ_AFTER: Duration = Duration::from_secs(10);
+
+/// How long each page of a document has been read.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadingStats {
+    /// Milliseconds spent on each page, by page index.
+    #[serde(default)]
+    pub page_millis: BTreeMap<usize, u64>,
+    /// The day each read page was first read on, in days since the Unix
+    /// epoch in local time.
+    #[serde(default)]
+    pub read_on: BTreeMap<usize, i64>,
+}
+
+impl ReadingStats {
+    /// Adds `elapsed` on `page`, which was read on `day` if this makes it
+    /// count as read.
+    pub fn record(&mut self, page: usize, elapsed: Duration, day: i64) {
+        let millis = elapsed.as_millis() as u64;
+        if millis == 0 {
+            return;
+        }
+        let on_page = self.page_millis.entry(page).or_default();
+        *on_page = on_page.saturating_add(millis);
+        if *on_page >= PAGE_READ_AFTER.as_millis() as u64 {
+            self.read_on.entry(page).or_insert(day);
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        Duration::from_millis(self.page_millis.values().sum())
+    }
+
+    pub fn on_page(&self, page: usize) -> Duration {
+        Duration::from_millis(self.page_millis.get(&page).copied().unwrap_or(0))
+    }
+
+    /// Pages looked at for long enough to have been read.
+    pub fn pages_read(&self) -> usize {
+        self.read_pages().count()
+    }
+
+    /// Pages first read on `day`.
+    pub fn pages_read_on(&self, day: i64) -> usize {
+        self.read_on.values().filter(|&&read| read == day).count()
+    }
+
+    /// The average time per read page applied to the pages not read yet;
+    /// `None` until a page has been read.
+    pub fn time_to_finish(&self, page_count: usize) -> Option<Duration> {
+        let (pages, millis) = self
+            .read_pages()
+            .fold((0u64, 0u64), |(pages, total), millis| {
+                (pages + 1, total.saturating_add(millis))
+            });
+        if pages == 0 {
+            return None;
+        }
+        let unread = (page_count as u64).saturating_sub(pages);
+        Some(Duration::from_millis(millis / pages * unread))
+    }
+
+    fn read_pages(&self) -> impl Iterator<Item = u64> + '_ {
+        let threshold = PAGE_READ_AFTER.as_millis() as u64;
+        self.page_millis
+            .values()
+            .copied()
+            .filter(move |&millis| millis >= threshold)
+    }
+
+    /// Combines the statistics of two copies of a document's state. Both only
+    /// ever grow, so the larger time for each page is the more recent one.
+    pub(crate) fn merge(mut self, other: &ReadingStats) -> Self {
+        for (&page, &millis) in &other.page_millis {
+            let entry = self.page_millis.entry(page).or_default();
+            *entry = (*entry).max(millis);
+        }
+        for (&page, &day) in &other.read_on {
+            let entry = self.read_on.entry(page).or_insert(day);
+            *entry = (*entry).min(day);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_pages_give_the_time_to_finish() {
+        let mut stats = ReadingStats::default();
+        assert_eq!(stats.time_to_finish(10), None);
+
+        stats.record(0, Duration::from_secs(60), 1);
+        stats.record(1, Duration::from_secs(2), 1);
+        stats.record(2, Duration::from_secs(100), 2);
+        stats.record(2, Duration::from_secs(20), 3);
+        assert_eq!(stats.total(), Duration::from_secs(182));
+        assert_eq!(stats.on_page(2), Duration::from_secs(120));
+        assert_eq!(stats.pages_read(), 2);
+        assert_eq!((stats.pages_read_on(1), stats.pages_read_on(2)), (1, 1));
+        // 90 s per read page, 8 pages to go.
+        assert_eq!(stats.time_to_finish(10), Some(Duration::from_secs(720)));
+
+        let mut other = ReadingStats::default();
+        other.record(1, Duration::from_secs(30), 3);
+        let merged = stats.merge(&other);
+        assert_eq!(merged.total(), Duration::from_secs(210));
+        assert_eq!(merged.pages_read(), 3);
+        assert_eq!(merged.pages_read_on(3), 1);
+    }
+}
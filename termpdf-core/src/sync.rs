@@ -0,0 +1,154 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{history, PersistedDocumentState};
+
+/// When each part of a [`PersistedDocumentState`] last changed, in
+/// milliseconds since the Unix epoch. Copies written by different machines
+/// are merged part by part, the newer one winning.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldTimestamps {
+    /// Current page and viewport.
+    #[serde(default)]
+    pub position: u64,
+    #[serde(default)]
+    pub scale: u64,
+    #[serde(default)]
+    pub dark_mode: u64,
+    #[serde(default)]
+    pub fit_mode: u64,
+    /// Character and named marks.
+    #[serde(default)]
+    pub marks: u64,
+    #[serde(default)]
+    pub first_page_number: u64,
+    #[serde(default)]
+    pub daily_goal: u64,
+}
+
+pub(crate) fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Stamps every part of `state` that differs from `baseline`, the copy last
+/// read or written by this process, with `now`.
+pub(crate) fn stamp_changes(
+    state: &mut PersistedDocumentState,
+    baseline: Option<&PersistedDocumentState>,
+    now: u64,
+) {
+    let Some(baseline) = baseline else {
+        state.modified = FieldTimestamps {
+            position: now,
+            scale: now,
+            dark_mode: now,
+            fit_mode: now,
+            marks: now,
+            first_page_number: now,
+            daily_goal: now,
+        };
+        return;
+    };
+    let stamp = |changed: bool, previous: u64| if changed { now } else { previous };
+    let previous = baseline.modified;
+    state.modified = FieldTimestamps {
+        position: stamp(
+            state.current_page != baseline.current_page || state.viewport != baseline.viewport,
+            previous.position,
+        ),
+        scale: stamp(state.scale != baseline.scale, previous.scale),
+        dark_mode: stamp(state.dark_mode != baseline.dark_mode, previous.dark_mode),
+        fit_mode: stamp(state.fit_mode != baseline.fit_mode, previous.fit_mode),
+        marks: stamp(
+            state.marks != baseline.marks || state.named_marks != baseline.named_marks,
+            previous.marks,
+        ),
+        first_page_number: stamp(
+            state.first_page_number != baseline.first_page_number,
+            previous.first_page_number,
+        ),
+        daily_goal: stamp(state.daily_goal != baseline.daily_goal, previous.daily_goal),
+    };
+}
+
+/// Combines two copies of a document's state, keeping the newer version of
+/// each part. Ties go to `local`.
+pub(crate) fn merge_states(
+    local: PersistedDocumentState,
+    remote: PersistedDocumentState,
+) -> PersistedDocumentState {
+    let mut merged = local.clone();
+    merged.stats = local.stats.merge(&remote.stats);
+    merged.history = history::merge(&local.history, &remote.history);
+    merged.version = local.version.max(remote.version);
+    for (field, value) in &remote.unknown {
+        merged
+            .unknown
+            .entry(field.clone())
+            .or_insert_with(|| value.clone());
+    }
+    if remote.modified.position > local.modified.position {
+        merged.current_page = remote.current_page;
+        merged.viewport = remote.viewport;
+        merged.jumps = remote.jumps;
+        merged.modified.position = remote.modified.position;
+    }
+    if remote.modified.scale > local.modified.scale {
+        merged.scale = remote.scale;
+        merged.modified.scale = remote.modified.scale;
+    }
+    if remote.modified.dark_mode > local.modified.dark_mode {
+        merged.dark_mode = remote.dark_mode;
+        merged.modified.dark_mode = remote.modified.dark_mode;
+    }
+    if remote.modified.fit_mode > local.modified.fit_mode {
+        merged.fit_mode = remote.fit_mode;
+        merged.modified.fit_mode = remote.modified.fit_mode;
+    }
+    if remote.modified.marks > local.modified.marks {
+        merged.marks = remote.marks;
+        merged.named_marks = remote.named_marks;
+        merged.modified.marks = remote.modified.marks;
+    }
+    if remote.modified.first_page_number > local.modified.first_page_number {
+        merged.first_page_number = remote.first_page_number;
+        merged.modified.first_page_number = remote.modified.first_page_number;
+    }
+    if remote.modified.daily_goal > local.modified.daily_goal {
+        merged.daily_goal = remote.daily_goal;
+        merged.modified.daily_goal = remote.modified.daily_goal;
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FitMode;
+
+    #[test]
+    fn merging_keeps_the_newest_version_of_each_field() {
+        let baseline = PersistedDocumentState::default();
+        let mut laptop = baseline.clone();
+        laptop.current_page = 40;
+        stamp_changes(&mut laptop, Some(&baseline), 100);
+
+        let mut desktop = baseline.clone();
+        desktop.dark_mode = true;
+        desktop.marks.insert('a', 3);
+        stamp_changes(&mut desktop, Some(&baseline), 200);
+        assert_eq!(desktop.modified.position, 0);
+
+        let merged = merge_states(laptop, desktop);
+        assert_eq!(merged.current_page, 40);
+        assert!(merged.dark_mode);
+        assert_eq!(merged.marks.get(&'a'), Some(&3));
+        assert_eq!(merged.fit_mode, FitMode::default());
+        assert_eq!(merged.modified.position, 100);
+        assert_eq!(merged.modified.marks, 200);
+    }
+}
@@ -0,0 +1,429 @@
+//! Documents for the unit tests: a backend whose behaviour each test tunes
+//! to what it needs, so tests do not each write their own.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use crate::{
+    document_id_for_path, DocumentBackend, DocumentInfo, DocumentMetadata, DocumentProvider,
+    FormField, FormFieldKind, FormFieldValue, LinkDefinition, NormalizedRect, PageText,
+    RenderImage, RenderRequest, TextGlyph,
+};
+
+/// A document at a made-up path with `pages` pages.
+pub(crate) fn test_info(pages: usize) -> DocumentInfo {
+    info_for(Path::new("/tmp/test.pdf"), pages)
+}
+
+/// A document at `path` with `pages` pages.
+pub(crate) fn info_for(path: &Path, pages: usize) -> DocumentInfo {
+    DocumentInfo {
+        id: document_id_for_path(path),
+        path: path.to_path_buf(),
+        page_count: pages,
+        metadata: DocumentMetadata::default(),
+    }
+}
+
+/// Holds back the calls that reach it until the test opens it, so a test
+/// decides when a slow call finishes instead of timing it.
+#[derive(Clone, Default)]
+pub(crate) struct Gate(Arc<(Mutex<GateState>, Condvar)>);
+
+#[derive(Default)]
+struct GateState {
+    open: bool,
+    waiting: usize,
+}
+
+impl Gate {
+    pub(crate) fn open(&self) {
+        let (state, changed) = &*self.0;
+        state.lock().open = true;
+        changed.notify_all();
+    }
+
+    /// Number of calls waiting at the gate.
+    pub(crate) fn waiting(&self) -> usize {
+        self.0 .0.lock().waiting
+    }
+
+    /// Blocks until `count` calls wait at the gate.
+    pub(crate) fn wait_for(&self, count: usize) {
+        let (state, changed) = &*self.0;
+        let mut state = state.lock();
+        while state.waiting < count {
+            changed.wait(&mut state);
+        }
+    }
+
+    /// Blocks until the gate is open.
+    pub(crate) fn pass(&self) {
+        let (state, changed) = &*self.0;
+        let mut state = state.lock();
+        state.waiting += 1;
+        changed.notify_all();
+        while !state.open {
+            changed.wait(&mut state);
+        }
+        state.waiting -= 1;
+    }
+}
+
+fn sample_text(page_index: usize) -> PageText {
+    unplaced_text(format!("This is sample page {} with keyword", page_index))
+}
+
+/// Text whose glyphs all sit at the page's top left corner, for tests that
+/// only read the words.
+fn unplaced_text(content: String) -> PageText {
+    let mut offset = 0;
+    let mut glyphs = Vec::new();
+    for ch in content.chars() {
+        let start = offset;
+        offset += ch.len_utf8();
+        glyphs.push(TextGlyph {
+            range: start..offset,
+            rect: NormalizedRect {
+                left: 0.0,
+                top: 0.0,
+                right: 0.0,
+                bottom: 0.0,
+            },
+        });
+    }
+    PageText::new(content, glyphs)
+}
+
+/// Text set in lines 0.05 high from the top left, one hundredth of the
+/// page per character.
+pub(crate) fn page_from_lines(lines: &[&str]) -> PageText {
+    let line_height = 0.05;
+    let mut text = String::new();
+    let mut glyphs = Vec::new();
+    for (row, line) in lines.iter().enumerate() {
+        for (col, ch) in line.chars().enumerate() {
+            let start = text.len();
+            text.push(ch);
+            let left = 0.1 + col as f32 * 0.01;
+            glyphs.push(TextGlyph {
+                range: start..text.len(),
+                rect: NormalizedRect {
+                    left,
+                    top: 0.1 + row as f32 * line_height,
+                    right: left + 0.01,
+                    bottom: 0.1 + (row as f32 + 0.8) * line_height,
+                },
+            });
+        }
+        text.push('\n');
+    }
+    PageText::new(text, glyphs)
+}
+
+type PerPage<T> = Box<dyn Fn(usize) -> T + Send + Sync>;
+
+/// Pages that read "This is sample page N with keyword" and that the
+/// backend itself can search.
+pub(crate) struct FakeBackend {
+    pub(crate) info: DocumentInfo,
+    text: PerPage<PageText>,
+    links: PerPage<Vec<LinkDefinition>>,
+    labels: Vec<Option<String>>,
+    /// The form fields of one page, which `set_form_field` fills in
+    form: Option<(usize, Mutex<Vec<FormField>>)>,
+    /// Whether renders are 100 pixels wide per unit of scale rather than
+    /// one pixel
+    scaled: bool,
+    /// Whether `search_page` finds matches, rather than leaving them to
+    /// the search through the page text
+    searches: bool,
+    /// Calls on this page wait at the gate
+    gate: Option<(usize, Gate)>,
+    /// Calls on this page wait at the gate before they start, without
+    /// holding the lock
+    pause: Option<(usize, Gate)>,
+    /// A render of this page panics
+    panics_on: Option<usize>,
+    /// Every call on this page fails
+    fails_on: Option<usize>,
+    /// Held during every call, as pdfium holds its global lock; backends
+    /// sharing it run one call at a time
+    lock: Option<Arc<Mutex<()>>>,
+    /// Pages per `search_pages` call
+    pub(crate) search_batches: Mutex<Vec<usize>>,
+    /// Pages per text request, 1 for `page_text`
+    pub(crate) text_batches: Mutex<Vec<usize>>,
+}
+
+impl FakeBackend {
+    pub(crate) fn new(info: DocumentInfo) -> Self {
+        Self {
+            info,
+            text: Box::new(sample_text),
+            links: Box::new(|_| Vec::new()),
+            labels: Vec::new(),
+            form: None,
+            scaled: false,
+            searches: true,
+            gate: None,
+            pause: None,
+            panics_on: None,
+            fails_on: None,
+            lock: None,
+            search_batches: Mutex::new(Vec::new()),
+            text_batches: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Pages read `text(page)`, and only the text search finds matches.
+    pub(crate) fn with_text(self, text: impl Fn(usize) -> String + Send + Sync + 'static) -> Self {
+        self.with_page_text(move |page| unplaced_text(text(page)))
+    }
+
+    /// Like [`with_text`](Self::with_text), for tests that need to know
+    /// where the glyphs are.
+    pub(crate) fn with_page_text(
+        mut self,
+        text: impl Fn(usize) -> PageText + Send + Sync + 'static,
+    ) -> Self {
+        self.text = Box::new(text);
+        self.searches = false;
+        self
+    }
+
+    /// Pages hold the links `links(page)`.
+    pub(crate) fn with_links(
+        mut self,
+        links: impl Fn(usize) -> Vec<LinkDefinition> + Send + Sync + 'static,
+    ) -> Self {
+        self.links = Box::new(links);
+        self
+    }
+
+    /// Page `n` holds the links `links[n]`.
+    pub(crate) fn with_link_lists(self, links: Vec<Vec<LinkDefinition>>) -> Self {
+        self.with_links(move |page| links.get(page).cloned().unwrap_or_default())
+    }
+
+    pub(crate) fn with_labels(mut self, labels: &[&str]) -> Self {
+        self.labels = labels.iter().map(|label| Some(label.to_string())).collect();
+        self
+    }
+
+    pub(crate) fn with_form_fields(mut self, page_index: usize, fields: Vec<FormField>) -> Self {
+        self.form = Some((page_index, Mutex::new(fields)));
+        self
+    }
+
+    pub(crate) fn with_scaled_renders(mut self) -> Self {
+        self.scaled = true;
+        self
+    }
+
+    pub(crate) fn gated_on(mut self, page_index: usize, gate: Gate) -> Self {
+        self.gate = Some((page_index, gate));
+        self
+    }
+
+    /// Like [`gated_on`](Self::gated_on), but calls wait before they take
+    /// the lock, so other calls go ahead meanwhile.
+    pub(crate) fn paused_before(mut self, page_index: usize, gate: Gate) -> Self {
+        self.pause = Some((page_index, gate));
+        self
+    }
+
+    pub(crate) fn panicking_on(mut self, page_index: usize) -> Self {
+        self.panics_on = Some(page_index);
+        self
+    }
+
+    pub(crate) fn failing_on(mut self, page_index: usize) -> Self {
+        self.fails_on = Some(page_index);
+        self
+    }
+
+    pub(crate) fn with_lock(mut self, lock: Arc<Mutex<()>>) -> Self {
+        self.lock = Some(lock);
+        self
+    }
+
+    /// Takes the lock once a call for `pages` may start.
+    fn hold(&self, pages: &[usize]) -> Option<MutexGuard<'_, ()>> {
+        if let Some((page, gate)) = &self.pause {
+            if pages.contains(page) {
+                gate.pass();
+            }
+        }
+        self.lock.as_ref().map(|lock| lock.lock())
+    }
+
+    /// Waits at the gate if `page_index` has one, then fails if the page
+    /// is damaged.
+    fn wait_at_gate(&self, page_index: usize) -> Result<()> {
+        if let Some((page, gate)) = &self.gate {
+            if *page == page_index {
+                gate.pass();
+            }
+        }
+        if self.fails_on == Some(page_index) {
+            return Err(anyhow!("page {} is damaged", page_index + 1));
+        }
+        Ok(())
+    }
+
+    fn search_one(&self, page_index: usize, query: &str) -> Vec<Vec<NormalizedRect>> {
+        let text = (self.text)(page_index).text;
+        if !self.searches
+            || query.trim().is_empty()
+            || !text.to_lowercase().contains(&query.to_lowercase())
+        {
+            return Vec::new();
+        }
+        vec![vec![NormalizedRect {
+            left: 0.1,
+            top: 0.1,
+            right: 0.9,
+            bottom: 0.2,
+        }]]
+    }
+}
+
+impl DocumentBackend for FakeBackend {
+    fn info(&self) -> &DocumentInfo {
+        &self.info
+    }
+
+    fn render_page(&self, request: RenderRequest) -> Result<RenderImage> {
+        let _held = self.hold(&[request.page_index]);
+        self.wait_at_gate(request.page_index)?;
+        if self.panics_on == Some(request.page_index) {
+            panic!("malformed page");
+        }
+        if self.scaled {
+            let width = (request.scale * 100.0) as u32;
+            return Ok(RenderImage {
+                width,
+                height: 1,
+                pixels: vec![0; width as usize * 4],
+            });
+        }
+        Ok(RenderImage {
+            width: 1,
+            height: 1,
+            pixels: vec![request.page_index as u8],
+        })
+    }
+
+    fn page_text(&self, page_index: usize) -> Result<PageText> {
+        let _held = self.hold(&[page_index]);
+        self.wait_at_gate(page_index)?;
+        self.text_batches.lock().push(1);
+        Ok((self.text)(page_index))
+    }
+
+    fn page_texts(&self, pages: &[usize]) -> Vec<Result<PageText>> {
+        let _held = self.hold(pages);
+        self.text_batches.lock().push(pages.len());
+        pages
+            .iter()
+            .map(|&page| {
+                self.wait_at_gate(page)?;
+                Ok((self.text)(page))
+            })
+            .collect()
+    }
+
+    fn search_page(&self, page_index: usize, query: &str) -> Result<Vec<Vec<NormalizedRect>>> {
+        let _held = self.hold(&[page_index]);
+        self.wait_at_gate(page_index)?;
+        Ok(self.search_one(page_index, query))
+    }
+
+    fn search_pages(&self, pages: &[usize], query: &str) -> Vec<Result<Vec<Vec<NormalizedRect>>>> {
+        let _held = self.hold(pages);
+        self.search_batches.lock().push(pages.len());
+        pages
+            .iter()
+            .map(|&page| {
+                self.wait_at_gate(page)?;
+                Ok(self.search_one(page, query))
+            })
+            .collect()
+    }
+
+    fn page_links(&self, page_index: usize) -> Result<Vec<LinkDefinition>> {
+        let _held = self.hold(&[page_index]);
+        self.wait_at_gate(page_index)?;
+        Ok((self.links)(page_index))
+    }
+
+    fn page_labels(&self) -> Result<Vec<Option<String>>> {
+        Ok(self.labels.clone())
+    }
+
+    fn form_fields(&self, page_index: usize) -> Result<Vec<FormField>> {
+        Ok(match &self.form {
+            Some((page, fields)) if *page == page_index => fields.lock().clone(),
+            _ => Vec::new(),
+        })
+    }
+
+    fn set_form_field(
+        &self,
+        page_index: usize,
+        field_id: usize,
+        value: FormFieldValue,
+    ) -> Result<()> {
+        let _held = self.hold(&[page_index]);
+        self.wait_at_gate(page_index)?;
+        let Some((_, fields)) = &self.form else {
+            return Ok(());
+        };
+        let mut fields = fields.lock();
+        let field = fields
+            .iter_mut()
+            .find(|field| field.id == field_id)
+            .ok_or_else(|| anyhow!("unknown field"))?;
+        match (&mut field.kind, value) {
+            (FormFieldKind::Text { value }, FormFieldValue::Text(text)) => *value = text,
+            (FormFieldKind::Checkbox { checked }, FormFieldValue::Checked(state)) => {
+                *checked = state
+            }
+            _ => anyhow::bail!("value does not match field type"),
+        }
+        Ok(())
+    }
+}
+
+/// A file that tests rewrite between reloads: page `n` reads `pages[n]`,
+/// one line per `\n`, and opening fails while it has no pages, as it does
+/// while a file is being written.
+pub(crate) struct FakeFile {
+    pub(crate) pages: Mutex<Vec<&'static str>>,
+}
+
+impl FakeFile {
+    pub(crate) fn new(pages: Vec<&'static str>) -> Self {
+        Self {
+            pages: Mutex::new(pages),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl DocumentProvider for FakeFile {
+    async fn open(&self, path: &Path) -> Result<Arc<dyn DocumentBackend>> {
+        let pages = self.pages.lock().clone();
+        if pages.is_empty() {
+            return Err(anyhow!("truncated file"));
+        }
+        let info = info_for(path, pages.len());
+        Ok(Arc::new(FakeBackend::new(info).with_page_text(
+            move |page| page_from_lines(&pages[page].lines().collect::<Vec<_>>()),
+        )))
+    }
+}
@@ -0,0 +1,821 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use tracing::warn;
+
+use crate::{
+    DocumentBackend, DocumentInfo, DocumentProvider, FormField, FormFieldValue, LinkDefinition,
+    NormalizedRect, OutlineItem, PageSize, PageText, RenderImage, RenderRequest,
+};
+
+/// How often a waiting render checks whether it was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(20);
+/// Least time allowed for calls that may take far longer than a render:
+/// reading a page's text, which may run OCR, and writing files.
+const SLOW_CALL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Asks a render to give up. Cancelling only stops the wait for a render
+/// already running on the worker; one still queued is skipped.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    check: Option<Arc<dyn Fn() -> bool + Send + Sync>>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A token that also counts as cancelled whenever `check` returns true,
+    /// e.g. while a key press is waiting to be handled.
+    pub fn when(check: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            check: Some(Arc::new(check)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.check.as_ref().is_some_and(|check| check())
+    }
+}
+
+/// The error of a render given up through its [`CancelToken`].
+#[derive(Debug)]
+pub struct RenderCancelled {
+    pub page_index: usize,
+}
+
+impl std::fmt::Display for RenderCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rendering page {} cancelled", self.page_index + 1)
+    }
+}
+
+impl std::error::Error for RenderCancelled {}
+
+impl std::fmt::Debug for CancelToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancelToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Reply from the worker: `None` once it starts on the call, then the
+/// result, where the outer `Err` means the backend panicked.
+type Reply<T> = Option<std::result::Result<Result<T>, ()>>;
+/// A call on the backend, skipped if its token is cancelled before the
+/// worker gets to it. The token is never one made with [`CancelToken::when`]:
+/// its check is only meant to run on the caller's thread.
+type Job = (CancelToken, Box<dyn FnOnce(&dyn DocumentBackend) + Send>);
+/// Opens the document again, for a worker replacing one stuck in a call.
+type Reopen = Arc<dyn Fn() -> Result<Arc<dyn DocumentBackend>> + Send + Sync>;
+
+struct Worker {
+    jobs: mpsc::Sender<Job>,
+    /// Tells workers apart, so that of two callers that saw one worker hang
+    /// only the first replaces it.
+    id: u64,
+}
+
+/// A call that outlived the timeout and still runs on an abandoned worker.
+struct Stuck {
+    what: String,
+    finished: Arc<AtomicBool>,
+}
+
+/// Runs every call on the document on a dedicated worker thread so a
+/// backend that hangs or panics on a malformed page cannot take the viewer
+/// down with it. A call that exceeds the timeout abandons the worker and
+/// starts a fresh one, which opens the document again so that it does not
+/// queue behind the stuck call; edits to form fields not yet saved are lost
+/// when that happens, which [`take_notice`](DocumentBackend::take_notice)
+/// reports. Pages whose render, search or link lookup hung or crashed keep
+/// failing fast until the document is reloaded. Reading text and writing
+/// files get a longer timeout and never mark a page failed, as OCR makes
+/// slow text extraction normal.
+///
+/// A backend may still serialise every call behind one lock, as pdfium
+/// does: then nothing gets through until the stuck call returns. Calls that
+/// time out while only waiting behind it fail without marking their page
+/// failed, so it is tried again later.
+///
+/// The timeout counts from when the worker starts on the call, not from
+/// when it was queued, and a render can be cancelled while it waits; see
+/// [`render_page_cancellable`](DocumentBackend::render_page_cancellable).
+pub struct SupervisedBackend {
+    info: DocumentInfo,
+    /// The backend the worker calls, replaced when the document is reopened.
+    backend: Arc<Mutex<Arc<dyn DocumentBackend>>>,
+    reopen: Option<Reopen>,
+    timeout: Duration,
+    /// Timeout of text extraction and writing files
+    slow_timeout: Duration,
+    worker: Mutex<Worker>,
+    stuck: Mutex<Option<Stuck>>,
+    failed_pages: Mutex<HashMap<usize, String>>,
+    /// Cancels renders made through plain `render_page`.
+    interrupt: CancelToken,
+    /// Whether form fields were filled since the document was last saved
+    form_edited: AtomicBool,
+    notice: Mutex<Option<String>>,
+}
+
+impl SupervisedBackend {
+    pub fn new(inner: Arc<dyn DocumentBackend>, timeout: Duration) -> Self {
+        let info = inner.info().clone();
+        let backend = Arc::new(Mutex::new(inner));
+        let worker = Mutex::new(Worker {
+            jobs: spawn_worker(backend.clone(), None),
+            id: 0,
+        });
+        Self {
+            info,
+            backend,
+            reopen: None,
+            timeout,
+            slow_timeout: timeout.max(SLOW_CALL_TIMEOUT),
+            worker,
+            stuck: Mutex::new(None),
+            failed_pages: Mutex::new(HashMap::new()),
+            interrupt: CancelToken::new(),
+            form_edited: AtomicBool::new(false),
+            notice: Mutex::new(None),
+        }
+    }
+
+    /// Lets `interrupt` cancel every render that is not given a token of
+    /// its own.
+    pub fn with_interrupt(mut self, interrupt: CancelToken) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    /// Lets a worker started after a hang open the document afresh with
+    /// `reopen` instead of reusing the backend the stuck call holds.
+    pub fn with_reopen(
+        mut self,
+        reopen: impl Fn() -> Result<Arc<dyn DocumentBackend>> + Send + Sync + 'static,
+    ) -> Self {
+        self.reopen = Some(Arc::new(reopen));
+        self
+    }
+
+    /// Sends a job to the worker, starting a fresh one if it died. Returns
+    /// the id of the worker that got it.
+    fn submit(&self, job: Job) -> Result<u64> {
+        let mut worker = self.worker.lock();
+        if let Err(mpsc::SendError(job)) = worker.jobs.send(job) {
+            self.replace_worker(&mut worker, None);
+            worker
+                .jobs
+                .send(job)
+                .map_err(|_| anyhow!("render worker unavailable"))?;
+        }
+        Ok(worker.id)
+    }
+
+    /// Replaces worker `id` after it died or, when `stuck` is given, got
+    /// stuck in a call.
+    fn restart_worker(&self, id: u64, stuck: Option<Stuck>) {
+        let mut worker = self.worker.lock();
+        if worker.id != id {
+            return;
+        }
+        if let Some(stuck) = stuck {
+            if self.reopen.is_some() && self.form_edited.swap(false, Ordering::Relaxed) {
+                *self.notice.lock() = Some(format!(
+                    "{} timed out; the document was opened again and unsaved form edits were lost",
+                    stuck.what
+                ));
+            }
+            *self.stuck.lock() = Some(stuck);
+            self.replace_worker(&mut worker, self.reopen.clone());
+        } else {
+            self.replace_worker(&mut worker, None);
+        }
+    }
+
+    fn replace_worker(&self, worker: &mut Worker, reopen: Option<Reopen>) {
+        worker.jobs = spawn_worker(self.backend.clone(), reopen);
+        worker.id = worker.id.wrapping_add(1);
+    }
+
+    /// Names the call that timed out and has not returned yet, if any.
+    fn stuck_call(&self) -> Option<String> {
+        let mut stuck = self.stuck.lock();
+        if stuck
+            .as_ref()
+            .is_some_and(|stuck| stuck.finished.load(Ordering::Relaxed))
+        {
+            *stuck = None;
+        }
+        stuck.as_ref().map(|stuck| stuck.what.clone())
+    }
+
+    fn record_failure(&self, page_index: usize, message: String) -> anyhow::Error {
+        warn!(page = page_index, %message, "page render failed");
+        self.failed_pages.lock().insert(page_index, message.clone());
+        anyhow!(message)
+    }
+
+    /// Runs `call` on the worker for a single page, e.g. `what` "rendering"
+    /// for page 3 is described as "rendering page 3". A page that hung or
+    /// crashed before fails straight away.
+    fn supervise<T: Send + 'static>(
+        &self,
+        what: &str,
+        page_index: usize,
+        cancel: &CancelToken,
+        call: impl FnOnce(&dyn DocumentBackend) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        if let Some(message) = self.failed_pages.lock().get(&page_index) {
+            return Err(anyhow!(message.clone()));
+        }
+        let what = format!("{} page {}", what, page_index + 1);
+        self.run(what, Some(page_index), self.timeout, cancel, call)
+    }
+
+    /// Runs `call`, which reads the text of page `page_index`, on the
+    /// worker with the slow timeout, failing fast for pages that hung or
+    /// crashed before but never marking one failed.
+    fn supervise_text<T: Send + 'static>(
+        &self,
+        page_index: usize,
+        call: impl FnOnce(&dyn DocumentBackend) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        if let Some(message) = self.failed_pages.lock().get(&page_index) {
+            return Err(anyhow!(message.clone()));
+        }
+        let what = format!("reading the text of page {}", page_index + 1);
+        self.run(what, None, self.slow_timeout, &CancelToken::new(), call)
+    }
+
+    /// Runs `call`, which concerns the whole document, on the worker.
+    fn supervise_document<T: Send + 'static>(
+        &self,
+        what: &str,
+        timeout: Duration,
+        call: impl FnOnce(&dyn DocumentBackend) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        self.run(what.to_owned(), None, timeout, &CancelToken::new(), call)
+    }
+
+    /// Runs `call` on the worker for several pages at once, skipping pages
+    /// that hung or crashed before. The batch gets `timeout` for each of its
+    /// pages together; when it fails every page in it gets the error, but
+    /// none is marked failed, as the culprit is unknown.
+    fn supervise_pages<T: Send + 'static>(
+        &self,
+        what: &str,
+        pages: &[usize],
+        timeout: Duration,
+        call: impl FnOnce(&dyn DocumentBackend, &[usize]) -> Vec<Result<T>> + Send + 'static,
+    ) -> Vec<Result<T>> {
+        let mut results: Vec<Option<Result<T>>> = {
+            let failed = self.failed_pages.lock();
+            pages
+                .iter()
+                .map(|page| {
+                    failed
+                        .get(page)
+                        .map(|message| Err(anyhow!(message.clone())))
+                })
+                .collect()
+        };
+        let pending: Vec<usize> = pages
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.is_none())
+            .map(|(&page, _)| page)
+            .collect();
+        if !pending.is_empty() {
+            let what = format!("{} {} pages", what, pending.len());
+            let timeout = timeout.saturating_mul(u32::try_from(pending.len()).unwrap_or(u32::MAX));
+            let batch = pending.clone();
+            let found = self.run(what, None, timeout, &CancelToken::new(), move |backend| {
+                Ok(call(backend, &batch))
+            });
+            let mut found = match found {
+                Ok(found) => found.into_iter(),
+                Err(err) => {
+                    let message = format!("{:#}", err);
+                    (0..pending.len())
+                        .map(|_| Err(anyhow!(message.clone())))
+                        .collect::<Vec<_>>()
+                        .into_iter()
+                }
+            };
+            for slot in results.iter_mut().filter(|slot| slot.is_none()) {
+                *slot = found.next();
+            }
+        }
+        results
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| Err(anyhow!("missing result"))))
+            .collect()
+    }
+
+    /// Runs `call` on the worker and waits for it, described as `what` in
+    /// errors. When it hangs or crashes, `page` is marked failed. Only
+    /// renders pass a `cancel` that can be cancelled. Whatever its own
+    /// `timeout`, a call gives up after the render timeout while another
+    /// call is stuck.
+    fn run<T: Send + 'static>(
+        &self,
+        what: String,
+        page: Option<usize>,
+        timeout: Duration,
+        cancel: &CancelToken,
+        call: impl FnOnce(&dyn DocumentBackend) -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let fail = |message: String| match page {
+            Some(page_index) => self.record_failure(page_index, message),
+            None => {
+                warn!(%message, "backend call failed");
+                anyhow!(message)
+            }
+        };
+
+        let job = CancelToken::new();
+        let (reply_tx, reply_rx) = mpsc::channel::<Reply<T>>();
+        let finished = Arc::new(AtomicBool::new(false));
+        let done = finished.clone();
+        let worker = self.submit((
+            job.clone(),
+            Box::new(move |backend: &dyn DocumentBackend| {
+                let _ = reply_tx.send(None);
+                let result = panic::catch_unwind(AssertUnwindSafe(|| call(backend)));
+                done.store(true, Ordering::Relaxed);
+                let _ = reply_tx.send(Some(result.map_err(|_| ())));
+            }),
+        ))?;
+        let submitted = Instant::now();
+        let mut started = None;
+        loop {
+            match reply_rx.recv_timeout(CANCEL_POLL) {
+                Ok(None) => started = Some(Instant::now()),
+                Ok(Some(Ok(result))) => return result,
+                Ok(Some(Err(()))) => return Err(fail(format!("renderer crashed while {}", what))),
+                Err(RecvTimeoutError::Timeout) => {
+                    if cancel.is_cancelled() {
+                        job.cancel();
+                        return Err(RenderCancelled {
+                            page_index: page.unwrap_or_default(),
+                        }
+                        .into());
+                    }
+                    // Waiting in the queue only counts while the worker is
+                    // stuck, since then the call may never start.
+                    let since = started.unwrap_or(submitted);
+                    let passed = |timeout: Duration| {
+                        since
+                            .checked_add(timeout)
+                            .is_some_and(|deadline| Instant::now() >= deadline)
+                    };
+                    if !passed(self.timeout.min(timeout)) {
+                        continue;
+                    }
+                    if let Some(stuck) = self.stuck_call() {
+                        job.cancel();
+                        return Err(anyhow!(
+                            "{} gave up waiting: {} has not finished",
+                            what,
+                            stuck
+                        ));
+                    }
+                    if started.is_none() || !passed(timeout) {
+                        continue;
+                    }
+                    let message = format!("{} timed out after {:.1}s", what, timeout.as_secs_f32());
+                    self.restart_worker(worker, Some(Stuck { what, finished }));
+                    return Err(fail(message));
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.restart_worker(worker, None);
+                    return Err(fail(format!("render worker died while {}", what)));
+                }
+            }
+        }
+    }
+}
+
+/// Starts a worker running jobs on the backend in `backend`, first
+/// replacing it with a freshly opened one when given `reopen`.
+fn spawn_worker(
+    backend: Arc<Mutex<Arc<dyn DocumentBackend>>>,
+    reopen: Option<Reopen>,
+) -> mpsc::Sender<Job> {
+    let (tx, rx) = mpsc::channel::<Job>();
+    let spawned = thread::Builder::new()
+        .name("termpdf-render".into())
+        .spawn(move || {
+            let reopened = reopen.map(|reopen| {
+                panic::catch_unwind(AssertUnwindSafe(|| reopen()))
+                    .unwrap_or_else(|_| Err(anyhow!("reopening the document panicked")))
+            });
+            let current = match reopened {
+                Some(Ok(reopened)) => {
+                    *backend.lock() = reopened.clone();
+                    reopened
+                }
+                Some(Err(err)) => {
+                    warn!(?err, "failed to reopen the document");
+                    backend.lock().clone()
+                }
+                None => backend.lock().clone(),
+            };
+            for (cancel, job) in rx {
+                if cancel.is_cancelled() {
+                    continue;
+                }
+                job(&*current);
+            }
+        });
+    if let Err(err) = spawned {
+        warn!(?err, "failed to spawn render worker");
+    }
+    tx
+}
+
+/// Runs `future` to completion on this thread, for opening documents
+/// through the async [`DocumentProvider`] from a worker.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut context = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+impl DocumentBackend for SupervisedBackend {
+    fn info(&self) -> &DocumentInfo {
+        &self.info
+    }
+
+    fn render_page(&self, request: RenderRequest) -> Result<RenderImage> {
+        self.render_page_cancellable(request, &self.interrupt)
+    }
+
+    fn render_page_cancellable(
+        &self,
+        request: RenderRequest,
+        cancel: &CancelToken,
+    ) -> Result<RenderImage> {
+        self.supervise("rendering", request.page_index, cancel, move |backend| {
+            backend.render_page(request)
+        })
+    }
+
+    fn outline(&self) -> Result<Vec<OutlineItem>> {
+        self.supervise_document("reading the outline", self.timeout, |backend| {
+            backend.outline()
+        })
+    }
+
+    fn page_size(&self, page_index: usize) -> Option<PageSize> {
+        let what = format!("measuring page {}", page_index + 1);
+        self.supervise_document(&what, self.timeout, move |backend| {
+            Ok(backend.page_size(page_index))
+        })
+        .ok()
+        .flatten()
+    }
+
+    fn page_labels(&self) -> Result<Vec<Option<String>>> {
+        self.supervise_document("reading the page labels", self.timeout, |backend| {
+            backend.page_labels()
+        })
+    }
+
+    fn page_text(&self, page_index: usize) -> Result<PageText> {
+        self.supervise_text(page_index, move |backend| backend.page_text(page_index))
+    }
+
+    fn search_page(&self, page_index: usize, query: &str) -> Result<Vec<Vec<NormalizedRect>>> {
+        let query = query.to_owned();
+        self.supervise(
+            "searching",
+            page_index,
+            &CancelToken::new(),
+            move |backend| backend.search_page(page_index, &query),
+        )
+    }
+
+    fn page_texts(&self, pages: &[usize]) -> Vec<Result<PageText>> {
+        self.supervise_pages(
+            "reading the text of",
+            pages,
+            self.slow_timeout,
+            |backend, pages| backend.page_texts(pages),
+        )
+    }
+
+    fn search_pages(&self, pages: &[usize], query: &str) -> Vec<Result<Vec<Vec<NormalizedRect>>>> {
+        let query = query.to_owned();
+        self.supervise_pages("searching", pages, self.timeout, move |backend, pages| {
+            backend.search_pages(pages, &query)
+        })
+    }
+
+    fn page_links(&self, page_index: usize) -> Result<Vec<LinkDefinition>> {
+        self.supervise(
+            "reading the links of",
+            page_index,
+            &CancelToken::new(),
+            move |backend| backend.page_links(page_index),
+        )
+    }
+
+    fn form_fields(&self, page_index: usize) -> Result<Vec<FormField>> {
+        let what = format!("reading the form fields of page {}", page_index + 1);
+        self.supervise_document(&what, self.timeout, move |backend| {
+            backend.form_fields(page_index)
+        })
+    }
+
+    fn set_form_field(
+        &self,
+        page_index: usize,
+        field_id: usize,
+        value: FormFieldValue,
+    ) -> Result<()> {
+        let what = format!("filling a form field on page {}", page_index + 1);
+        self.supervise_document(&what, self.timeout, move |backend| {
+            backend.set_form_field(page_index, field_id, value)
+        })?;
+        self.form_edited.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn save_as(&self, path: &Path) -> Result<()> {
+        let path = path.to_path_buf();
+        self.supervise_document("saving the document", self.slow_timeout, move |backend| {
+            backend.save_as(&path)
+        })?;
+        self.form_edited.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn extract_pages(&self, pages: RangeInclusive<usize>, path: &Path) -> Result<()> {
+        let path = path.to_path_buf();
+        self.supervise_document("extracting pages", self.slow_timeout, move |backend| {
+            backend.extract_pages(pages, &path)
+        })
+    }
+
+    fn take_notice(&self) -> Option<String> {
+        self.notice.lock().take()
+    }
+}
+
+/// Provider adapter that wraps every opened document in a [`SupervisedBackend`]
+/// that reopens it through the same provider after a hang.
+pub struct SupervisedProvider<P> {
+    inner: Arc<P>,
+    timeout: Duration,
+    interrupt: CancelToken,
+}
+
+impl<P> SupervisedProvider<P> {
+    pub fn new(inner: P, timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            timeout,
+            interrupt: CancelToken::new(),
+        }
+    }
+
+    /// See [`SupervisedBackend::with_interrupt`].
+    pub fn with_interrupt(mut self, interrupt: CancelToken) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: DocumentProvider + 'static> DocumentProvider for SupervisedProvider<P> {
+    async fn open(&self, path: &Path) -> Result<Arc<dyn DocumentBackend>> {
+        let backend = self.inner.open(path).await?;
+        let provider = self.inner.clone();
+        let path = path.to_path_buf();
+        Ok(Arc::new(
+            SupervisedBackend::new(backend, self.timeout)
+                .with_interrupt(self.interrupt.clone())
+                .with_reopen(move || block_on(provider.open(&path))),
+        ))
+    }
+
+    fn format_name(&self) -> &'static str {
+        self.inner.format_name()
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        self.inner.sniff(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{test_info, FakeBackend, Gate};
+    use std::sync::atomic::AtomicUsize;
+
+    /// Shorter than any call takes while held at a gate.
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    /// Page 1 hangs until `gate` opens and page 2 crashes the renderer.
+    fn flaky(gate: &Gate) -> FakeBackend {
+        FakeBackend::new(test_info(3))
+            .gated_on(1, gate.clone())
+            .panicking_on(2)
+    }
+
+    fn request(page_index: usize) -> RenderRequest {
+        RenderRequest {
+            page_index,
+            scale: 1.0,
+            dark_mode: false,
+        }
+    }
+
+    /// Waits for the call that timed out to return once its gate opened.
+    fn wait_for_stuck_call(backend: &SupervisedBackend) {
+        while backend.stuck_call().is_some() {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn supervised_backend_survives_hangs_and_panics() {
+        let gate = Gate::default();
+        let backend = SupervisedBackend::new(Arc::new(flaky(&gate)), TIMEOUT);
+
+        assert!(backend.render_page(request(0)).is_ok());
+        let err = backend.render_page(request(1)).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        gate.open();
+        wait_for_stuck_call(&backend);
+        let err = backend.render_page(request(2)).unwrap_err();
+        assert!(err.to_string().contains("crashed"));
+
+        assert!(backend.render_page(request(0)).is_ok());
+        let err = backend.render_page(request(1)).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+    }
+
+    #[test]
+    fn cancelled_renders_do_not_fail_the_page() {
+        let gate = Gate::default();
+        let waiting = gate.clone();
+        let backend = SupervisedBackend::new(Arc::new(flaky(&gate)), Duration::from_secs(10))
+            .with_interrupt(CancelToken::when(move || waiting.waiting() > 0));
+
+        let err = backend.render_page(request(1)).unwrap_err();
+        assert!(err.is::<RenderCancelled>());
+        assert!(backend.failed_pages.lock().is_empty());
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert!(backend
+            .render_page_cancellable(request(0), &cancel)
+            .is_err());
+        gate.open();
+    }
+
+    #[test]
+    fn time_spent_queued_does_not_count_towards_the_timeout() {
+        let backend = Arc::new(SupervisedBackend::new(
+            Arc::new(FakeBackend::new(test_info(3))),
+            TIMEOUT,
+        ));
+        // Holds up the worker with a job no timeout watches.
+        let gate = Gate::default();
+        let held = gate.clone();
+        backend
+            .submit((CancelToken::new(), Box::new(move |_| held.pass())))
+            .unwrap();
+        gate.wait_for(1);
+        let queued = {
+            let backend = backend.clone();
+            thread::spawn(move || backend.render_page(request(0)).is_ok())
+        };
+        // Only a lower bound: the render has to wait longer than the
+        // timeout, however long that ends up being.
+        thread::sleep(TIMEOUT * 4);
+        gate.open();
+        assert!(queued.join().unwrap());
+    }
+
+    #[test]
+    fn pages_waiting_behind_a_hang_are_not_marked_failed() {
+        let lock = Arc::new(Mutex::new(()));
+        let gate = Gate::default();
+        let shared = gate.clone();
+        let open = move || -> Arc<dyn DocumentBackend> {
+            Arc::new(
+                FakeBackend::new(test_info(3))
+                    .with_lock(lock.clone())
+                    .gated_on(1, shared.clone()),
+            )
+        };
+        let backend = SupervisedBackend::new(open(), TIMEOUT).with_reopen(move || Ok(open()));
+
+        let err = backend.render_page(request(1)).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        // The fresh worker waits for the lock the stuck render holds.
+        let err = backend.render_page(request(0)).unwrap_err();
+        assert!(err.to_string().contains("gave up waiting"));
+        assert!(err.to_string().contains("rendering page 2"));
+        // Text gets a longer timeout of its own, but not for waiting on a
+        // stuck call.
+        let err = backend.page_text(0).unwrap_err();
+        assert!(err.to_string().contains("gave up waiting"));
+        assert_eq!(backend.failed_pages.lock().keys().collect::<Vec<_>>(), [&1]);
+
+        gate.open();
+        wait_for_stuck_call(&backend);
+        assert!(backend.render_page(request(0)).is_ok());
+        assert!(backend.page_text(0).is_ok());
+        let err = backend.render_page(request(1)).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+    }
+
+    #[test]
+    fn restarts_reopen_the_document() {
+        let opened = Arc::new(AtomicUsize::new(0));
+        let counter = opened.clone();
+        let gate = Gate::default();
+        let shared = gate.clone();
+        let open = move || -> Arc<dyn DocumentBackend> {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Arc::new(FakeBackend::new(test_info(3)).gated_on(1, shared.clone()))
+        };
+        let backend = SupervisedBackend::new(open(), TIMEOUT).with_reopen(move || Ok(open()));
+
+        backend
+            .set_form_field(0, 0, FormFieldValue::Checked(true))
+            .unwrap();
+        assert!(backend.take_notice().is_none());
+        let err = backend.render_page(request(1)).unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        assert!(backend.render_page(request(0)).is_ok());
+        assert!(backend.page_text(0).is_ok());
+        assert!(backend.outline().is_ok());
+        assert_eq!(opened.load(Ordering::Relaxed), 2);
+        assert!(backend.page_links(1).is_err());
+        let notice = backend.take_notice().unwrap();
+        assert!(notice.contains("unsaved form edits were lost"));
+        assert!(backend.take_notice().is_none());
+        gate.open();
+    }
+
+    #[test]
+    fn slow_text_does_not_fail_the_page() {
+        let gate = Gate::default();
+        let backend = SupervisedBackend::new(
+            Arc::new(FakeBackend::new(test_info(3)).gated_on(0, gate.clone())),
+            TIMEOUT,
+        );
+        let opener = thread::spawn(move || {
+            gate.wait_for(1);
+            // Longer than the render timeout, as OCR on a page can be.
+            thread::sleep(TIMEOUT * 4);
+            gate.open();
+        });
+        assert!(backend.page_text(0).is_ok());
+        opener.join().unwrap();
+        assert!(backend.failed_pages.lock().is_empty());
+        assert!(backend.render_page(request(0)).is_ok());
+    }
+}
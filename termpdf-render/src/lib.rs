@@ -1,19 +1,25 @@
 use std::convert::TryFrom;
-use std::mem;
+use std::ops::RangeInclusive;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
+use std::thread;
 
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use parking_lot::Mutex;
 use pdfium_render::prelude::*;
+use termpdf_core::geometry::{PageRotation, PageSpace};
 use termpdf_core::{
     document_id_for_path, DocumentBackend, DocumentInfo, DocumentMetadata, DocumentProvider,
-    LinkAction, LinkDefinition, NormalizedRect, OutlineItem, PageText, RenderImage, RenderRequest,
-    TextGlyph,
+    FormField, FormFieldKind, FormFieldValue, LinkAction, LinkDefinition, NormalizedRect,
+    OutlineItem, PageSize, PageText, RenderImage, RenderRequest, TextGlyph,
 };
 use tracing::{instrument, warn};
 
+#[cfg(feature = "ocr")]
+mod ocr;
+
 pub struct PdfiumRenderFactory {
     pdfium: Arc<Pdfium>,
 }
@@ -36,22 +42,95 @@ impl DocumentProvider for PdfiumRenderFactory {
         let absolute = path
             .canonicalize()
             .with_context(|| format!("failed to resolve path for {:?}", path))?;
-        let info = build_document_info(&self.pdfium, &absolute)?;
+        let worker = DocumentWorker::spawn(Arc::clone(&self.pdfium), absolute.clone())?;
+        let path = absolute.clone();
+        let (info, page_sizes) = worker.run(move |_, document| {
+            Ok((build_document_info(document, &path)?, page_sizes(document)))
+        })?;
         Ok(Arc::new(PdfiumDocument::new(
-            Arc::clone(&self.pdfium),
-            absolute,
-            info,
+            worker, absolute, info, page_sizes,
         )))
     }
+
+    fn format_name(&self) -> &'static str {
+        "PDF"
+    }
+
+    fn sniff(&self, header: &[u8]) -> bool {
+        is_pdf_header(header)
+    }
+}
+
+type DocumentJob = Box<dyn FnOnce(&Pdfium, Result<&PdfDocument<'_>>) + Send>;
+
+/// The thread that owns one open document. Every call on the document goes
+/// through this thread's queue, in the order it was made. This keeps one
+/// document's calls from racing on its handle, but it does not let documents
+/// run at the same time: pdfium is not thread-safe, and pdfium-render takes
+/// one process-wide lock around every call, so a long render of one document
+/// still holds up the others.
+struct DocumentWorker {
+    jobs: mpsc::Sender<DocumentJob>,
+    path: PathBuf,
+}
+
+impl DocumentWorker {
+    fn spawn(pdfium: Arc<Pdfium>, path: PathBuf) -> Result<Self> {
+        let (jobs, queue) = mpsc::channel::<DocumentJob>();
+        let document_path = path.clone();
+        thread::Builder::new()
+            .name("termpdf-pdfium".into())
+            .spawn(move || {
+                // Opened on the first job; a failed open is retried by the next.
+                let mut document = None;
+                for job in queue {
+                    if document.is_none() {
+                        match pdfium.load_pdf_from_file(&document_path, None) {
+                            Ok(opened) => document = Some(opened),
+                            Err(err) => {
+                                let err = anyhow::Error::new(err)
+                                    .context(format!("failed to open {:?}", document_path));
+                                job(&pdfium, Err(err));
+                                continue;
+                            }
+                        }
+                    }
+                    let opened = document.as_ref().expect("document must be loaded");
+                    // A panicking job drops its reply; the document stays open
+                    // for the next one.
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&pdfium, Ok(opened))));
+                }
+            })
+            .context("failed to start pdfium worker")?;
+        Ok(Self { jobs, path })
+    }
+
+    /// Runs `f` on the document's thread and waits for its result.
+    fn run<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Pdfium, &PdfDocument<'_>) -> Result<R> + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let job: DocumentJob = Box::new(move |pdfium, document| {
+            let _ = reply_tx.send(document.and_then(|document| f(pdfium, document)));
+        });
+        self.jobs
+            .send(job)
+            .map_err(|_| anyhow!("pdfium worker for {:?} has stopped", self.path))?;
+        reply_rx
+            .recv()
+            .map_err(|_| anyhow!("pdfium crashed while reading {:?}", self.path))?
+    }
 }
 
 struct PdfiumDocument {
-    pdfium: Arc<Pdfium>,
+    worker: DocumentWorker,
     path: PathBuf,
     info: DocumentInfo,
+    page_sizes: Vec<PageSize>,
     cache: Mutex<Option<RenderCacheEntry>>,
     outline_cache: Mutex<Option<Vec<OutlineItem>>>,
-    document: Mutex<Option<PdfDocument<'static>>>,
 }
 
 struct RenderCacheEntry {
@@ -62,113 +141,234 @@ struct RenderCacheEntry {
 }
 
 impl PdfiumDocument {
-    fn new(pdfium: Arc<Pdfium>, path: PathBuf, info: DocumentInfo) -> Self {
+    fn new(
+        worker: DocumentWorker,
+        path: PathBuf,
+        info: DocumentInfo,
+        page_sizes: Vec<PageSize>,
+    ) -> Self {
         Self {
-            pdfium,
+            worker,
             path,
             info,
+            page_sizes,
             cache: Mutex::new(None),
             outline_cache: Mutex::new(None),
-            document: Mutex::new(None),
         }
     }
 
-    fn open_document(&self) -> Result<PdfDocument<'static>> {
-        let document = self
-            .pdfium
-            .load_pdf_from_file(&self.path, None)
-            .with_context(|| format!("failed to open {:?}", self.path))?;
-        // SAFETY: the returned PdfDocument holds a reference to the Pdfium bindings owned by
-        // self.pdfium. The document is stored inside self.document and will be dropped before the
-        // Pdfium instance because struct fields drop in reverse order of declaration (document
-        // precedes pdfium). This ensures the reference remains valid for the lifetime of the
-        // cached PdfDocument.
-        let document = unsafe { mem::transmute::<PdfDocument<'_>, PdfDocument<'static>>(document) };
-        Ok(document)
+    /// Recognises the text of a page that has none, such as a scan, when
+    /// built with OCR support.
+    #[cfg(feature = "ocr")]
+    fn recognize_if_empty(&self, page_index: usize, text: PageText) -> PageText {
+        if !text.text.trim().is_empty() {
+            return text;
+        }
+        let request = RenderRequest {
+            page_index,
+            scale: ocr::OCR_SCALE,
+            dark_mode: false,
+        };
+        let recognized = self
+            .worker
+            .run(move |_, document| render_document_page(document, &request))
+            .and_then(|image| ocr::recognize_page(&image));
+        match recognized {
+            Ok(recognized) => recognized,
+            Err(err) => {
+                warn!(?err, page = page_index, "OCR fallback failed");
+                text
+            }
+        }
     }
 
-    fn with_document<R, F>(&self, f: F) -> Result<R>
-    where
-        F: FnOnce(&PdfDocument<'static>) -> Result<R>,
-    {
-        let mut guard = self.document.lock();
-        if guard.is_none() {
-            let document = self.open_document()?;
-            *guard = Some(document);
-        }
-        let document = guard.as_ref().expect("document must be loaded");
-        f(document)
+    #[cfg(not(feature = "ocr"))]
+    fn recognize_if_empty(&self, _page_index: usize, text: PageText) -> PageText {
+        text
     }
+}
 
-    fn render_internal(
-        &self,
-        document: &PdfDocument<'_>,
-        request: &RenderRequest,
-    ) -> Result<RenderImage> {
-        let page_index: PdfPageIndex = request
-            .page_index
-            .try_into()
-            .map_err(|_| anyhow!("page {} is out of supported range", request.page_index))?;
-        let page = document
-            .pages()
-            .get(page_index)
-            .with_context(|| format!("page {} out of range", request.page_index))?;
-
-        let config = PdfRenderConfig::new().scale_page_by_factor(request.scale.max(0.1));
-        let bitmap = page
-            .render_with_config(&config)
-            .with_context(|| format!("failed to render page {}", request.page_index))?;
-        let image = bitmap.as_image().to_rgba8();
-        let mut pixels = image.into_raw();
-
-        if request.dark_mode {
-            invert_pixels(&mut pixels);
-        }
+/// Spreads the outcome of a batch job over its pages: when the job itself
+/// failed, every page gets its error.
+fn batch_results<T>(count: usize, batch: Result<Vec<Result<T>>>) -> Vec<Result<T>> {
+    match batch {
+        Ok(results) => results,
+        Err(err) => (0..count).map(|_| Err(anyhow!("{:#}", err))).collect(),
+    }
+}
 
-        Ok(RenderImage {
-            width: u32::try_from(bitmap.width()).unwrap_or_default(),
-            height: u32::try_from(bitmap.height()).unwrap_or_default(),
-            pixels,
-        })
+fn search_document_page(
+    document: &PdfDocument<'_>,
+    page_index: usize,
+    query: &str,
+) -> Result<Vec<Vec<NormalizedRect>>> {
+    let page_index: PdfPageIndex = page_index
+        .try_into()
+        .map_err(|_| anyhow!("page {} is out of supported range", page_index))?;
+    let page = document
+        .pages()
+        .get(page_index)
+        .with_context(|| format!("page {} out of range", page_index))?;
+    let text = page
+        .text()
+        .with_context(|| format!("failed to extract text for page {}", page_index))?;
+
+    let options = PdfSearchOptions::new();
+    let search = text
+        .search(query, &options)
+        .with_context(|| format!("failed to perform search on page {}", page_index))?;
+
+    let Some(space) = page_space(&page) else {
+        return Ok(Vec::new());
+    };
+
+    let mut results = Vec::new();
+    while let Some(segments) = search.find_next() {
+        let rects = segments
+            .iter()
+            .filter_map(|segment| normalize_pdf_rect(&segment.bounds(), &space))
+            .collect();
+        results.push(rects);
     }
 
-    fn link_action_from_pdfium(&self, link: &PdfLink<'_>) -> Option<LinkAction> {
-        if let Some(action) = link.action() {
-            match action.action_type() {
-                PdfActionType::GoToDestinationInSameDocument => {
-                    if let Some(local) = action.as_local_destination_action() {
-                        if let Ok(destination) = local.destination() {
-                            if let Ok(page_index) = destination.page_index() {
-                                return Some(LinkAction::GoTo {
-                                    page: page_index as usize,
-                                });
-                            }
+    Ok(results)
+}
+
+/// Where each character of the text on `page` sits.
+fn glyph_rects(page: &PdfPage<'_>, space: &PageSpace) -> Vec<NormalizedRect> {
+    let Ok(text) = page.text() else {
+        return Vec::new();
+    };
+    text.chars()
+        .iter()
+        .filter_map(|ch| ch.loose_bounds().ok())
+        .filter_map(|bounds| normalize_pdf_rect(&bounds, space))
+        .collect()
+}
+
+fn extract_page_text(document: &PdfDocument<'_>, page_index: usize) -> Result<PageText> {
+    let page_index: PdfPageIndex = page_index
+        .try_into()
+        .map_err(|_| anyhow!("page {} is out of supported range", page_index))?;
+    let page = document
+        .pages()
+        .get(page_index)
+        .with_context(|| format!("page {} out of range", page_index))?;
+
+    let text_page = page
+        .text()
+        .with_context(|| format!("failed to extract text for page {}", page_index))?;
+    let Some(space) = page_space(&page) else {
+        return Ok(PageText::new(String::new(), Vec::new()));
+    };
+
+    let mut buffer = String::new();
+    let mut glyphs = Vec::new();
+    for ch in text_page.chars().iter() {
+        let value = ch.unicode_char().unwrap_or(' ');
+        let start = buffer.len();
+        buffer.push(value);
+        let end = buffer.len();
+        let rect = ch
+            .tight_bounds()
+            .or_else(|_| ch.loose_bounds())
+            .ok()
+            .and_then(|bounds| normalize_pdf_rect(&bounds, &space))
+            .unwrap_or(NormalizedRect {
+                left: 0.0,
+                top: 0.0,
+                right: 0.0,
+                bottom: 0.0,
+            });
+        glyphs.push(TextGlyph {
+            range: start..end,
+            rect,
+        });
+    }
+
+    Ok(PageText::new(buffer, glyphs))
+}
+
+fn render_document_page(
+    document: &PdfDocument<'_>,
+    request: &RenderRequest,
+) -> Result<RenderImage> {
+    let page_index: PdfPageIndex = request
+        .page_index
+        .try_into()
+        .map_err(|_| anyhow!("page {} is out of supported range", request.page_index))?;
+    let page = document
+        .pages()
+        .get(page_index)
+        .with_context(|| format!("page {} out of range", request.page_index))?;
+
+    let config = PdfRenderConfig::new().scale_page_by_factor(request.scale.max(0.1));
+    let bitmap = page
+        .render_with_config(&config)
+        .with_context(|| format!("failed to render page {}", request.page_index))?;
+    let image = bitmap.as_image().to_rgba8();
+    let mut pixels = image.into_raw();
+
+    if request.dark_mode {
+        invert_pixels(&mut pixels);
+    }
+
+    Ok(RenderImage {
+        width: u32::try_from(bitmap.width()).unwrap_or_default(),
+        height: u32::try_from(bitmap.height()).unwrap_or_default(),
+        pixels,
+    })
+}
+
+fn link_action_from_pdfium(document: &PdfDocument<'_>, link: &PdfLink<'_>) -> Option<LinkAction> {
+    if let Some(action) = link.action() {
+        match action.action_type() {
+            PdfActionType::GoToDestinationInSameDocument => {
+                if let Some(local) = action.as_local_destination_action() {
+                    if let Ok(destination) = local.destination() {
+                        if let Some(action) = goto_action(document, &destination) {
+                            return Some(action);
                         }
                     }
                 }
-                PdfActionType::Uri => {
-                    if let Some(uri_action) = action.as_uri_action() {
-                        if let Ok(uri) = uri_action.uri() {
-                            if !uri.is_empty() {
-                                return Some(LinkAction::Uri { uri });
-                            }
+            }
+            PdfActionType::Uri => {
+                if let Some(uri_action) = action.as_uri_action() {
+                    if let Ok(uri) = uri_action.uri() {
+                        if !uri.is_empty() {
+                            return Some(LinkAction::Uri { uri });
                         }
                     }
                 }
-                _ => {}
-            }
-        }
-
-        if let Some(destination) = link.destination() {
-            if let Ok(page_index) = destination.page_index() {
-                return Some(LinkAction::GoTo {
-                    page: page_index as usize,
-                });
             }
+            _ => {}
         }
+    }
 
-        None
+    if let Some(destination) = link.destination() {
+        return goto_action(document, &destination);
     }
+
+    None
+}
+
+fn goto_action(document: &PdfDocument<'_>, destination: &PdfDestination<'_>) -> Option<LinkAction> {
+    let page_index = destination.page_index().ok()?;
+    let y = match destination.view_settings() {
+        Ok(PdfDestinationViewSettings::SpecificCoordinatesAndZoom(_, y, _))
+        | Ok(PdfDestinationViewSettings::FitPageHorizontallyToWindow(y))
+        | Ok(PdfDestinationViewSettings::FitBoundsHorizontallyToWindow(y)) => y,
+        Ok(PdfDestinationViewSettings::FitPageToRectangle(rect)) => Some(rect.top()),
+        _ => None,
+    };
+    let top = y.and_then(|y| {
+        let page = document.pages().get(page_index).ok()?;
+        page_space(&page)?.normalize_top(y.value)
+    });
+    Some(LinkAction::GoTo {
+        page: page_index as usize,
+        top,
+    })
 }
 
 impl DocumentBackend for PdfiumDocument {
@@ -190,7 +390,9 @@ impl DocumentBackend for PdfiumDocument {
             }
         }
 
-        let image = self.with_document(|document| self.render_internal(document, &request))?;
+        let image = self
+            .worker
+            .run(move |_, document| render_document_page(document, &request))?;
 
         let mut cache = self.cache.lock();
         *cache = Some(RenderCacheEntry {
@@ -203,6 +405,10 @@ impl DocumentBackend for PdfiumDocument {
         Ok(image)
     }
 
+    fn page_size(&self, page_index: usize) -> Option<PageSize> {
+        self.page_sizes.get(page_index).copied()
+    }
+
     fn outline(&self) -> Result<Vec<OutlineItem>> {
         {
             let cache = self.outline_cache.lock();
@@ -211,7 +417,7 @@ impl DocumentBackend for PdfiumDocument {
             }
         }
 
-        let outline = self.with_document(|document| {
+        let outline = self.worker.run(|_, document| {
             let mut outline = Vec::new();
             if let Some(root) = document.bookmarks().root() {
                 collect_outline(root, 0, &mut outline);
@@ -225,51 +431,55 @@ impl DocumentBackend for PdfiumDocument {
         Ok(outline)
     }
 
-    fn page_text(&self, page_index: usize) -> Result<PageText> {
-        self.with_document(|document| {
-            let page_index: PdfPageIndex = page_index
-                .try_into()
-                .map_err(|_| anyhow!("page {} is out of supported range", page_index))?;
-            let page = document
-                .pages()
-                .get(page_index)
-                .with_context(|| format!("page {} out of range", page_index))?;
-
-            let text_page = page
-                .text()
-                .with_context(|| format!("failed to extract text for page {}", page_index))?;
-            let page_width = page.width().value;
-            let page_height = page.height().value;
-            if page_width <= 0.0 || page_height <= 0.0 {
-                return Ok(PageText::new(String::new(), Vec::new()));
-            }
+    fn page_labels(&self) -> Result<Vec<Option<String>>> {
+        let page_count = self.info.page_count;
+        self.worker.run(move |_, document| {
+            let bindings = document.bindings();
+            let handle = bindings.get_handle_from_document(document);
+            let labels = (0..page_count)
+                .map(|index| {
+                    // Asked once for the length, then for the UTF-16 text.
+                    let length =
+                        bindings.FPDF_GetPageLabel(handle, index as i32, std::ptr::null_mut(), 0);
+                    if length == 0 {
+                        return None;
+                    }
+                    let mut buffer = vec![0u8; length as usize];
+                    bindings.FPDF_GetPageLabel(
+                        handle,
+                        index as i32,
+                        buffer.as_mut_ptr() as *mut std::ffi::c_void,
+                        length,
+                    );
+                    bindings
+                        .get_string_from_pdfium_utf16le_bytes(buffer)
+                        .filter(|label| !label.is_empty())
+                })
+                .collect();
+            Ok(labels)
+        })
+    }
 
-            let mut buffer = String::new();
-            let mut glyphs = Vec::new();
-            for ch in text_page.chars().iter() {
-                let value = ch.unicode_char().unwrap_or(' ');
-                let start = buffer.len();
-                buffer.push(value);
-                let end = buffer.len();
-                let rect = ch
-                    .tight_bounds()
-                    .or_else(|_| ch.loose_bounds())
-                    .ok()
-                    .and_then(|bounds| normalize_pdf_rect(&bounds, page_width, page_height))
-                    .unwrap_or(NormalizedRect {
-                        left: 0.0,
-                        top: 0.0,
-                        right: 0.0,
-                        bottom: 0.0,
-                    });
-                glyphs.push(TextGlyph {
-                    range: start..end,
-                    rect,
-                });
-            }
+    fn page_text(&self, page_index: usize) -> Result<PageText> {
+        let text = self
+            .worker
+            .run(move |_, document| extract_page_text(document, page_index))?;
+        Ok(self.recognize_if_empty(page_index, text))
+    }
 
-            Ok(PageText::new(buffer, glyphs))
-        })
+    fn page_texts(&self, pages: &[usize]) -> Vec<Result<PageText>> {
+        let indices = pages.to_vec();
+        let texts = self.worker.run(move |_, document| {
+            Ok(indices
+                .into_iter()
+                .map(|page| extract_page_text(document, page))
+                .collect::<Vec<_>>())
+        });
+        batch_results(pages.len(), texts)
+            .into_iter()
+            .zip(pages)
+            .map(|(text, &page)| text.map(|text| self.recognize_if_empty(page, text)))
+            .collect()
     }
 
     fn search_page(&self, page_index: usize, query: &str) -> Result<Vec<Vec<NormalizedRect>>> {
@@ -277,60 +487,32 @@ impl DocumentBackend for PdfiumDocument {
             return Ok(Vec::new());
         }
 
-        self.with_document(|document| {
-            let page_index: PdfPageIndex = page_index
-                .try_into()
-                .map_err(|_| anyhow!("page {} is out of supported range", page_index))?;
-            let page = document
-                .pages()
-                .get(page_index)
-                .with_context(|| format!("page {} out of range", page_index))?;
-            let text = page
-                .text()
-                .with_context(|| format!("failed to extract text for page {}", page_index))?;
-
-            let options = PdfSearchOptions::new();
-            let search = text
-                .search(query, &options)
-                .with_context(|| format!("failed to perform search on page {}", page_index))?;
-
-            let page_width = page.width().value;
-            let page_height = page.height().value;
-            if page_width <= 0.0 || page_height <= 0.0 {
-                return Ok(Vec::new());
-            }
+        let query = query.to_string();
+        self.worker
+            .run(move |_, document| search_document_page(document, page_index, &query))
+    }
 
-            let mut results = Vec::new();
-            while let Some(segments) = search.find_next() {
-                let mut rects = Vec::new();
-                for segment in segments.iter() {
-                    let bounds = segment.bounds();
-                    let left = (bounds.left().value / page_width).clamp(0.0, 1.0);
-                    let right = (bounds.right().value / page_width).clamp(0.0, 1.0);
-                    let top_ratio = bounds.top().value / page_height;
-                    let bottom_ratio = bounds.bottom().value / page_height;
-                    let top_norm = (1.0 - top_ratio).clamp(0.0, 1.0);
-                    let bottom_norm = (1.0 - bottom_ratio).clamp(0.0, 1.0);
-                    let rect = NormalizedRect {
-                        left,
-                        top: top_norm,
-                        right,
-                        bottom: bottom_norm,
-                    }
-                    .clamp();
-                    if rect.is_valid() {
-                        rects.push(rect);
-                    }
-                }
-                results.push(rects);
-            }
+    fn search_pages(&self, pages: &[usize], query: &str) -> Vec<Result<Vec<Vec<NormalizedRect>>>> {
+        if query.trim().is_empty() {
+            return pages.iter().map(|_| Ok(Vec::new())).collect();
+        }
 
-            Ok(results)
-        })
+        // One job for the lot, so a search does not queue behind every
+        // render in between its pages.
+        let indices = pages.to_vec();
+        let query = query.to_string();
+        let found = self.worker.run(move |_, document| {
+            Ok(indices
+                .into_iter()
+                .map(|page| search_document_page(document, page, &query))
+                .collect::<Vec<_>>())
+        });
+        batch_results(pages.len(), found)
     }
 
     fn page_links(&self, page_index: usize) -> Result<Vec<LinkDefinition>> {
-        self.with_document(|document| {
+        let path = self.path.clone();
+        self.worker.run(move |_, document| {
             let page_index: PdfPageIndex = page_index
                 .try_into()
                 .map_err(|_| anyhow!("page {} is out of supported range", page_index))?;
@@ -339,59 +521,195 @@ impl DocumentBackend for PdfiumDocument {
                 .get(page_index)
                 .with_context(|| format!("page {} out of range", page_index))?;
 
-            let page_width = page.width().value;
-            let page_height = page.height().value;
-            if page_width <= 0.0 || page_height <= 0.0 {
+            let Some(space) = page_space(&page) else {
                 return Ok(Vec::new());
-            }
+            };
 
             let mut definitions = Vec::new();
-            let links = page.links();
-            for link in links.iter() {
-                let rect = match link.rect() {
-                    Ok(rect) => rect,
+            for annotation in page.annotations().iter() {
+                let Some(link_annotation) = annotation.as_link_annotation() else {
+                    continue;
+                };
+                let link = match link_annotation.link() {
+                    Ok(link) => link,
                     Err(err) => {
                         warn!(
                             ?err,
                             page = page_index as usize,
-                            path = %self.path.display(),
-                            "failed to resolve link rectangle"
+                            path = %path.display(),
+                            "failed to resolve link"
                         );
                         continue;
                     }
                 };
+                let Some(action) = link_action_from_pdfium(document, &link) else {
+                    continue;
+                };
 
-                let left = (rect.left().value / page_width).clamp(0.0, 1.0);
-                let right = (rect.right().value / page_width).clamp(0.0, 1.0);
-                let top_ratio = rect.top().value / page_height;
-                let bottom_ratio = rect.bottom().value / page_height;
-                let top = (1.0 - top_ratio).clamp(0.0, 1.0);
-                let bottom = (1.0 - bottom_ratio).clamp(0.0, 1.0);
-                let rect = NormalizedRect {
-                    left,
-                    top,
-                    right,
-                    bottom,
+                // A link that wraps across lines lists a quadrilateral per
+                // line; its rectangle would also cover the text between them.
+                let mut rects: Vec<NormalizedRect> = annotation
+                    .attachment_points()
+                    .iter()
+                    .filter_map(|quad| normalize_pdf_rect(&quad.to_rect(), &space))
+                    .collect();
+                if rects.is_empty() {
+                    match annotation.bounds() {
+                        Ok(rect) => rects.extend(normalize_pdf_rect(&rect, &space)),
+                        Err(err) => {
+                            warn!(
+                                ?err,
+                                page = page_index as usize,
+                                path = %path.display(),
+                                "failed to resolve link rectangle"
+                            );
+                            continue;
+                        }
+                    }
                 }
-                .clamp();
-
-                if !rect.is_valid() {
+                if rects.is_empty() {
                     continue;
                 }
 
-                let Some(action) = self.link_action_from_pdfium(&link) else {
+                definitions.push(LinkDefinition { rects, action });
+            }
+
+            // Only pages with several links need the text's margins.
+            let glyphs = if definitions.len() > 1 {
+                glyph_rects(&page, &space)
+            } else {
+                Vec::new()
+            };
+            Ok(LinkDefinition::merge_adjacent(definitions, &glyphs))
+        })
+    }
+
+    fn form_fields(&self, page_index: usize) -> Result<Vec<FormField>> {
+        self.worker.run(move |_, document| {
+            let page = document
+                .pages()
+                .get(pdf_page_index(page_index)?)
+                .with_context(|| format!("page {} out of range", page_index))?;
+            let Some(space) = page_space(&page) else {
+                return Ok(Vec::new());
+            };
+
+            let mut fields = Vec::new();
+            for (id, annotation) in page.annotations().iter().enumerate() {
+                let Some(field) = annotation.as_form_field() else {
                     continue;
                 };
-
-                definitions.push(LinkDefinition {
-                    rects: vec![rect],
-                    action,
+                let Some(rect) = annotation
+                    .bounds()
+                    .ok()
+                    .and_then(|rect| normalize_pdf_rect(&rect, &space))
+                else {
+                    continue;
+                };
+                let kind = if let Some(text) = field.as_text_field() {
+                    FormFieldKind::Text {
+                        value: text.value().unwrap_or_default(),
+                    }
+                } else if let Some(checkbox) = field.as_checkbox_field() {
+                    FormFieldKind::Checkbox {
+                        checked: checkbox.is_checked().unwrap_or(false),
+                    }
+                } else if let Some(radio) = field.as_radio_button_field() {
+                    FormFieldKind::RadioButton {
+                        checked: radio.is_checked().unwrap_or(false),
+                    }
+                } else {
+                    FormFieldKind::Other
+                };
+                fields.push(FormField {
+                    id,
+                    name: field.name(),
+                    rect,
+                    kind,
                 });
             }
+            Ok(fields)
+        })
+    }
+
+    fn set_form_field(
+        &self,
+        page_index: usize,
+        field_id: usize,
+        value: FormFieldValue,
+    ) -> Result<()> {
+        self.worker.run(move |_, document| {
+            let mut page = document
+                .pages()
+                .get(pdf_page_index(page_index)?)
+                .with_context(|| format!("page {} out of range", page_index))?;
+            let mut annotation = page
+                .annotations_mut()
+                .get(field_id)
+                .with_context(|| format!("form field {} not found", field_id))?;
+            let field = annotation
+                .as_form_field_mut()
+                .ok_or_else(|| anyhow!("annotation {} is not a form field", field_id))?;
+            match value {
+                FormFieldValue::Text(text) => field
+                    .as_text_field_mut()
+                    .ok_or_else(|| anyhow!("form field is not a text field"))?
+                    .set_value(&text)?,
+                FormFieldValue::Checked(checked) => {
+                    if let Some(checkbox) = field.as_checkbox_field_mut() {
+                        checkbox.set_checked(checked)?;
+                    } else if let Some(radio) = field.as_radio_button_field_mut() {
+                        if checked {
+                            radio.set_checked()?;
+                        }
+                    } else {
+                        anyhow::bail!("form field cannot be checked");
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        *self.cache.lock() = None;
+        Ok(())
+    }
 
-            Ok(definitions)
+    fn save_as(&self, path: &Path) -> Result<()> {
+        let path = path.to_path_buf();
+        self.worker.run(move |_, document| {
+            document
+                .save_to_file(&path)
+                .with_context(|| format!("failed to save {:?}", path))
         })
     }
+
+    fn extract_pages(&self, pages: RangeInclusive<usize>, path: &Path) -> Result<()> {
+        let range = pdf_page_index(*pages.start())?..=pdf_page_index(*pages.end())?;
+        let path = path.to_path_buf();
+        self.worker.run(move |pdfium, document| {
+            let mut output = pdfium
+                .create_new_pdf()
+                .context("failed to create output document")?;
+            output
+                .pages_mut()
+                .copy_page_range_from_document(document, range, 0)
+                .context("failed to copy pages")?;
+            output
+                .save_to_file(&path)
+                .with_context(|| format!("failed to save {:?}", path))
+        })
+    }
+}
+
+fn pdf_page_index(page_index: usize) -> Result<PdfPageIndex> {
+    page_index
+        .try_into()
+        .map_err(|_| anyhow!("page {} is out of supported range", page_index))
+}
+
+/// PDF readers accept the `%PDF-` marker anywhere in the first kilobyte, so
+/// files with leading junk (or a wrong extension) are still recognised.
+fn is_pdf_header(header: &[u8]) -> bool {
+    header.windows(5).any(|window| window == b"%PDF-")
 }
 
 fn collect_outline(mut bookmark: PdfBookmark<'_>, depth: usize, out: &mut Vec<OutlineItem>) {
@@ -404,6 +722,7 @@ fn collect_outline(mut bookmark: PdfBookmark<'_>, depth: usize, out: &mut Vec<Ou
                         title,
                         page_index,
                         depth,
+                        page_label: None,
                     });
                 }
             }
@@ -420,11 +739,8 @@ fn collect_outline(mut bookmark: PdfBookmark<'_>, depth: usize, out: &mut Vec<Ou
     }
 }
 
-fn build_document_info(pdfium: &Pdfium, path: &Path) -> Result<DocumentInfo> {
-    let document = pdfium
-        .load_pdf_from_file(path, None)
-        .with_context(|| format!("failed to open {:?}", path))?;
-    let page_count = usize::try_from(document.pages().len()).unwrap_or_default();
+fn build_document_info(document: &PdfDocument<'_>, path: &Path) -> Result<DocumentInfo> {
+    let page_count = usize::from(document.pages().len());
     let metadata = document.metadata();
 
     let title = metadata
@@ -450,30 +766,41 @@ fn build_document_info(pdfium: &Pdfium, path: &Path) -> Result<DocumentInfo> {
     })
 }
 
-fn normalize_pdf_rect(rect: &PdfRect, page_width: f32, page_height: f32) -> Option<NormalizedRect> {
-    if page_width <= 0.0 || page_height <= 0.0 {
-        return None;
-    }
-    let left = (rect.left().value / page_width).clamp(0.0, 1.0);
-    let right = (rect.right().value / page_width).clamp(0.0, 1.0);
-    let top_ratio = rect.top().value / page_height;
-    let bottom_ratio = rect.bottom().value / page_height;
-    let top = (1.0 - top_ratio).clamp(0.0, 1.0);
-    let bottom = (1.0 - bottom_ratio).clamp(0.0, 1.0);
-    let rect = NormalizedRect {
-        left,
-        top,
-        right,
-        bottom,
-    }
-    .clamp();
-    if rect.is_valid() {
-        Some(rect)
-    } else {
-        None
+/// Every page's size, read without loading the pages; empty if pdfium
+/// cannot tell.
+fn page_sizes(document: &PdfDocument<'_>) -> Vec<PageSize> {
+    match document.pages().page_sizes() {
+        Ok(sizes) => sizes
+            .iter()
+            .map(|rect| PageSize {
+                width: rect.width().value,
+                height: rect.height().value,
+            })
+            .collect(),
+        Err(err) => {
+            warn!(?err, "failed to read page sizes");
+            Vec::new()
+        }
     }
 }
 
+fn page_space(page: &PdfPage<'_>) -> Option<PageSpace> {
+    let rotation = page
+        .rotation()
+        .map(|rotation| PageRotation::from_degrees(rotation.as_degrees() as i32))
+        .unwrap_or_default();
+    PageSpace::new(page.width().value, page.height().value, rotation)
+}
+
+fn normalize_pdf_rect(rect: &PdfRect, space: &PageSpace) -> Option<NormalizedRect> {
+    space.normalize_rect(
+        rect.left().value,
+        rect.top().value,
+        rect.right().value,
+        rect.bottom().value,
+    )
+}
+
 fn invert_pixels(pixels: &mut [u8]) {
     for chunk in pixels.chunks_exact_mut(4) {
         chunk[0] = 255 - chunk[0];
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context, Result};
+use image::{ImageFormat, RgbaImage};
+use termpdf_core::{NormalizedRect, PageText, RenderImage, TextGlyph};
+
+/// Resolution scanned pages are rasterised at before recognition (≈300 dpi).
+pub(crate) const OCR_SCALE: f32 = 300.0 / 72.0;
+
+/// Runs `tesseract` over a rendered page and turns the recognised words into
+/// synthetic glyphs, so search and selection work on image-only pages.
+pub(crate) fn recognize_page(image: &RenderImage) -> Result<PageText> {
+    let buffer = RgbaImage::from_raw(image.width, image.height, image.pixels.clone())
+        .ok_or_else(|| anyhow!("rendered page has an unexpected pixel buffer size"))?;
+    let mut png = Cursor::new(Vec::new());
+    buffer
+        .write_to(&mut png, ImageFormat::Png)
+        .context("failed to encode page for OCR")?;
+
+    let mut command = Command::new("tesseract");
+    command.args(["stdin", "stdout"]);
+    if let Ok(lang) = std::env::var("TERMPDF_OCR_LANG") {
+        command.args(["-l", &lang]);
+    }
+    let mut child = command
+        .arg("tsv")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run tesseract; is it installed and on PATH?")?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(png.get_ref())
+        .context("failed to send page to tesseract")?;
+    let output = child
+        .wait_with_output()
+        .context("failed to read tesseract output")?;
+    if !output.status.success() {
+        bail!(
+            "tesseract exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(parse_tsv(
+        &String::from_utf8_lossy(&output.stdout),
+        image.width as f32,
+        image.height as f32,
+    ))
+}
+
+struct Word {
+    text: String,
+    rect: NormalizedRect,
+}
+
+/// Builds page text from tesseract's TSV output. Words are joined by spaces
+/// and lines by newlines; each character gets an equal slice of its word box.
+fn parse_tsv(tsv: &str, width: f32, height: f32) -> PageText {
+    let mut lines: BTreeMap<(u32, u32, u32, u32), Vec<Word>> = BTreeMap::new();
+    if width <= 0.0 || height <= 0.0 {
+        return PageText::new(String::new(), Vec::new());
+    }
+
+    for row in tsv.lines().skip(1) {
+        let columns: Vec<&str> = row.split('\t').collect();
+        if columns.len() < 12 || columns[0] != "5" {
+            continue;
+        }
+        let text = columns[11].trim();
+        if text.is_empty() {
+            continue;
+        }
+        let numbers: Option<Vec<f32>> = columns[1..10]
+            .iter()
+            .map(|value| value.parse::<f32>().ok())
+            .collect();
+        let Some(numbers) = numbers else {
+            continue;
+        };
+        let key = (
+            numbers[0] as u32,
+            numbers[1] as u32,
+            numbers[2] as u32,
+            numbers[3] as u32,
+        );
+        let (left, top, w, h) = (numbers[5], numbers[6], numbers[7], numbers[8]);
+        let rect = NormalizedRect {
+            left: left / width,
+            top: top / height,
+            right: (left + w) / width,
+            bottom: (top + h) / height,
+        }
+        .clamp();
+        lines.entry(key).or_default().push(Word {
+            text: text.to_string(),
+            rect,
+        });
+    }
+
+    let mut buffer = String::new();
+    let mut glyphs: Vec<TextGlyph> = Vec::new();
+    for (line_index, words) in lines.values().enumerate() {
+        let top = words.iter().map(|w| w.rect.top).fold(f32::MAX, f32::min);
+        let bottom = words.iter().map(|w| w.rect.bottom).fold(0.0, f32::max);
+        if line_index > 0 {
+            if let Some(last) = glyphs.last().map(|glyph| glyph.rect) {
+                let end = NormalizedRect {
+                    left: last.right,
+                    right: last.right,
+                    ..last
+                };
+                push_glyph(&mut buffer, &mut glyphs, '\n', end);
+            }
+        }
+        for (word_index, word) in words.iter().enumerate() {
+            if word_index > 0 {
+                let previous = words[word_index - 1].rect;
+                let gap = NormalizedRect {
+                    left: previous.right,
+                    top,
+                    right: word.rect.left.max(previous.right),
+                    bottom,
+                };
+                push_glyph(&mut buffer, &mut glyphs, ' ', gap);
+            }
+            let count = word.text.chars().count().max(1) as f32;
+            let step = (word.rect.right - word.rect.left) / count;
+            for (index, ch) in word.text.chars().enumerate() {
+                let left = word.rect.left + step * index as f32;
+                let rect = NormalizedRect {
+                    left,
+                    right: left + step,
+                    ..word.rect
+                };
+                push_glyph(&mut buffer, &mut glyphs, ch, rect);
+            }
+        }
+    }
+
+    PageText::new(buffer, glyphs)
+}
+
+fn push_glyph(buffer: &mut String, glyphs: &mut Vec<TextGlyph>, ch: char, rect: NormalizedRect) {
+    let start = buffer.len();
+    buffer.push(ch);
+    glyphs.push(TextGlyph {
+        range: start..buffer.len(),
+        rect,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext";
+
+    #[test]
+    fn tsv_words_become_lines_of_glyphs() {
+        let tsv = [
+            HEADER,
+            "1\t1\t0\t0\t0\t0\t0\t0\t1000\t1000\t-1\t",
+            "5\t1\t1\t1\t1\t1\t100\t100\t200\t50\t96.0\tHello",
+            "5\t1\t1\t1\t1\t2\t350\t100\t200\t50\t95.1\tworld",
+            "5\t1\t1\t1\t2\t1\t100\t200\t100\t50\t91.3\tagain",
+            "5\t1\t1\t1\t2\t2\t300\t200\t100\t50\t-1\t ",
+        ]
+        .join("\n");
+
+        let text = parse_tsv(&tsv, 1000.0, 1000.0);
+        assert_eq!(text.text, "Hello world\nagain");
+        assert_eq!(text.glyph_count(), text.text.chars().count());
+
+        let h = &text.glyphs[0].rect;
+        assert!((h.left - 0.1).abs() < 1e-6 && (h.right - 0.14).abs() < 1e-6);
+        assert!((h.top - 0.1).abs() < 1e-6 && (h.bottom - 0.15).abs() < 1e-6);
+        assert_eq!(text.line_index_for_glyph(0), text.line_index_for_glyph(10));
+        assert_ne!(text.line_index_for_glyph(0), text.line_index_for_glyph(12));
+    }
+}
@@ -0,0 +1,285 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use uuid::Uuid;
+
+use crate::{write_command, Passthrough, SHM_DIR};
+
+/// What the terminal supports beyond plain text, as it answered at startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// kitty's graphics protocol, which page images are drawn with.
+    pub kitty_graphics: bool,
+    /// Synchronized output (mode 2026), which draws a frame at once.
+    pub synchronized_output: bool,
+    /// Width and height of a character cell in pixels.
+    pub cell_size: Option<(u32, u32)>,
+    /// Whether the terminal read an image from shared memory, which it
+    /// can only do on this machine and outside other containers.
+    pub shared_memory: bool,
+    /// Whether the terminal read an image from a temporary file.
+    pub temp_files: bool,
+}
+
+impl Capabilities {
+    /// What is assumed of a terminal that cannot be asked: the kitty
+    /// features termpdf is built around.
+    pub const ASSUMED: Capabilities = Capabilities {
+        kitty_graphics: true,
+        synchronized_output: true,
+        cell_size: None,
+        shared_memory: false,
+        temp_files: false,
+    };
+
+    /// Asks the terminal what it supports. Must be called in raw mode
+    /// before input is read. A device attributes query goes last, which
+    /// every terminal answers, so one that ignores the other queries does
+    /// not hold things up until `timeout`.
+    pub fn probe(passthrough: Passthrough, timeout: Duration) -> Self {
+        let mut queries = Vec::new();
+        // A 1×1 image that is only checked, never stored.
+        let _ = write_command(
+            &mut queries,
+            passthrough,
+            "i=31,s=1,v=1,a=q,t=d,f=24",
+            Some(b"AAAA"),
+        );
+        // The same image in shared memory and in a temporary file, which
+        // the terminal can only read when it shares this machine's files.
+        // Whatever it leaves unread is removed after the probe.
+        let shm = Path::new(SHM_DIR)
+            .is_dir()
+            .then(|| ProbeImage::create(Path::new(SHM_DIR)))
+            .flatten();
+        if let Some(image) = &shm {
+            let name = format!("/{}", image.name);
+            let _ = write_command(
+                &mut queries,
+                passthrough,
+                "i=32,s=1,v=1,a=q,t=s,f=24,S=3",
+                Some(BASE64.encode(name).as_bytes()),
+            );
+        }
+        let file = ProbeImage::create(&env::temp_dir());
+        if let Some(image) = &file {
+            let path = image.path.to_string_lossy().into_owned();
+            let _ = write_command(
+                &mut queries,
+                passthrough,
+                "i=33,s=1,v=1,a=q,t=t,f=24,S=3",
+                Some(BASE64.encode(path).as_bytes()),
+            );
+        }
+        queries.extend_from_slice(b"\x1b[?2026$p\x1b[16t\x1b[c");
+        let reply = query_terminal(&queries, timeout);
+        drop((shm, file));
+        let Some(reply) = reply else {
+            return Self::ASSUMED;
+        };
+        let mut capabilities = Self::from_reply(&reply);
+        // tmux answers the other queries itself but does not reliably send
+        // the outer terminal's graphics reply back.
+        if passthrough == Passthrough::Tmux {
+            capabilities.kitty_graphics = true;
+        }
+        capabilities
+    }
+
+    fn from_reply(reply: &[u8]) -> Self {
+        Self {
+            kitty_graphics: graphics_ok(reply, 31),
+            synchronized_output: synchronized_output(reply),
+            cell_size: parse_cell_size_reply(reply),
+            shared_memory: graphics_ok(reply, 32),
+            temp_files: graphics_ok(reply, 33),
+        }
+    }
+}
+
+/// A 1×1 image written for the terminal to read during the probe, under a
+/// name that tells kitty to delete it once read; removed when dropped in
+/// case the terminal did not.
+struct ProbeImage {
+    name: String,
+    path: PathBuf,
+}
+
+impl ProbeImage {
+    fn create(dir: &Path) -> Option<Self> {
+        let name = format!(
+            "tty-graphics-protocol-termpdf-probe-{}-{}",
+            process::id(),
+            Uuid::new_v4().simple()
+        );
+        let path = dir.join(&name);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .ok()?;
+        let image = Self { name, path };
+        file.write_all(&[0, 0, 0]).ok()?;
+        Some(image)
+    }
+}
+
+impl Drop for ProbeImage {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Whether the terminal answered the graphics query with image id `id`
+/// with OK.
+fn graphics_ok(reply: &[u8], id: u32) -> bool {
+    let ok = format!("\x1b_Gi={};OK\x1b", id);
+    reply
+        .windows(ok.len())
+        .any(|window| window == ok.as_bytes())
+}
+
+/// How often a probe without a reply yet looks at the terminal again.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Writes `queries` to the terminal and collects the reply up to the
+/// device attributes report that ends it. The terminal is read without
+/// blocking and a byte at a time, so nothing is left reading once the
+/// probe returns and keys typed after the reply stay for the input reader.
+fn query_terminal(queries: &[u8], timeout: Duration) -> Option<Vec<u8>> {
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open("/dev/tty")
+        .ok()?;
+    tty.write_all(queries).ok()?;
+    tty.flush().ok()?;
+    let deadline = Instant::now() + timeout;
+    let mut reply = Vec::new();
+    let mut byte = [0u8; 1];
+    while reply.len() < 1024 && !ends_with_device_attributes(&reply) {
+        match tty.read(&mut byte) {
+            Ok(1) => reply.push(byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return None;
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            _ => return None,
+        }
+    }
+    Some(reply)
+}
+
+/// Whether `reply` ends with a primary device attributes report,
+/// `CSI ? … c`.
+fn ends_with_device_attributes(reply: &[u8]) -> bool {
+    reply.ends_with(b"c") && device_attributes(reply).is_some()
+}
+
+/// The attributes in the last device attributes report, `CSI ? … c`,
+/// after the terminal class that comes first.
+fn device_attributes(reply: &[u8]) -> Option<Vec<u32>> {
+    let start = reply.windows(3).rposition(|window| window == b"\x1b[?")? + 3;
+    let end = start + reply[start..].iter().position(|byte| *byte == b'c')?;
+    let text = std::str::from_utf8(&reply[start..end]).ok()?;
+    let values: Option<Vec<u32>> = text.split(';').map(|value| value.parse().ok()).collect();
+    Some(values?.into_iter().skip(1).collect())
+}
+
+/// Whether the terminal knows mode 2026, from its mode report
+/// `CSI ? 2026 ; state $ y`: 0 is an unknown mode, 4 one that cannot be
+/// used.
+fn synchronized_output(reply: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(reply) else {
+        return false;
+    };
+    text.find("\x1b[?2026;")
+        .and_then(|start| text[start + 8..].split_once("$y"))
+        .is_some_and(|(state, _)| matches!(state, "1" | "2" | "3"))
+}
+
+/// Width and height from a cell size report, `CSI 6 ; height ; width t`.
+fn parse_cell_size_reply(reply: &[u8]) -> Option<(u32, u32)> {
+    let text = std::str::from_utf8(reply).ok()?;
+    let start = text.find("\x1b[6;")? + 4;
+    let end = start + text[start..].find('t')?;
+    let (height, width) = text[start..end].split_once(';')?;
+    let width = width.parse::<u32>().ok()?;
+    let height = height.parse::<u32>().ok()?;
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TransferMedium;
+
+    #[test]
+    fn cell_size_is_read_from_the_terminal_reply() {
+        let reply = b"\x1b[6;20;10t\x1b[?62;22c";
+        assert!(ends_with_device_attributes(reply));
+        assert_eq!(parse_cell_size_reply(reply), Some((10, 20)));
+
+        // Terminals without the report only answer the attributes query.
+        let reply = b"\x1b[?1;2c";
+        assert!(ends_with_device_attributes(reply));
+        assert_eq!(parse_cell_size_reply(reply), None);
+        assert!(!ends_with_device_attributes(b"\x1b[6;20;10t"));
+    }
+
+    #[test]
+    fn capabilities_are_read_from_the_replies() {
+        let kitty = b"\x1b_Gi=31;OK\x1b\\\x1b[?2026;2$y\x1b[6;20;10t\x1b[?62;52c";
+        let capabilities = Capabilities::from_reply(kitty);
+        assert!(capabilities.kitty_graphics);
+        assert!(!capabilities.shared_memory);
+        assert!(capabilities.synchronized_output);
+        assert_eq!(capabilities.cell_size, Some((10, 20)));
+
+        // alacritty: no graphics, and mode 2026 unknown to older versions.
+        let alacritty = b"\x1b[?2026;0$y\x1b[?6c";
+        let capabilities = Capabilities::from_reply(alacritty);
+        assert!(!capabilities.kitty_graphics);
+        assert!(!capabilities.synchronized_output);
+
+        let foot = b"\x1b_Gi=31;ENOTSUPPORTED\x1b\\\x1b[?62;4;22c";
+        let capabilities = Capabilities::from_reply(foot);
+        assert!(!capabilities.kitty_graphics);
+    }
+
+    #[test]
+    fn images_go_through_files_only_when_the_terminal_read_them() {
+        let medium = |reply: &[u8]| TransferMedium::detect(&Capabilities::from_reply(reply));
+        let local = b"\x1b_Gi=31;OK\x1b\\\x1b_Gi=32;OK\x1b\\\x1b_Gi=33;OK\x1b\\\x1b[?62c";
+        assert_eq!(medium(local), TransferMedium::SharedMemory);
+        // In a container the terminal sees neither.
+        let container = b"\x1b_Gi=31;OK\x1b\\\x1b_Gi=32;EBADF:no such object\x1b\\\x1b_Gi=33;EBADF:no such file\x1b\\\x1b[?62c";
+        assert_eq!(medium(container), TransferMedium::Direct);
+        let files = b"\x1b_Gi=31;OK\x1b\\\x1b_Gi=33;OK\x1b\\\x1b[?62c";
+        assert_eq!(medium(files), TransferMedium::File);
+        assert_eq!(
+            TransferMedium::detect(&Capabilities::ASSUMED),
+            TransferMedium::Direct
+        );
+
+        let dir = std::env::temp_dir();
+        let image = ProbeImage::create(&dir).unwrap();
+        let path = image.path.clone();
+        assert_eq!(fs::read(&path).unwrap().len(), 3);
+        drop(image);
+        assert!(!path.exists());
+    }
+}
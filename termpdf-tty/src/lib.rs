@@ -1,6 +1,16 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::hash::{Hash, Hasher};
 use std::io::{self, Write};
-
-use anyhow::Result;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
 use base64::engine::general_purpose::STANDARD as BASE64;
 use base64::Engine;
 use crossterm::{
@@ -8,13 +18,248 @@ use crossterm::{
     event::{Event, KeyCode, KeyEvent, KeyModifiers},
     terminal::{Clear, ClearType},
 };
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
 use png::{BitDepth, ColorType, Encoder};
-use termpdf_core::{Command, RenderImage};
+use termpdf_core::{
+    system_clock, Command, FitMode, LinkFilter, RenderImage, SharedClock, StepSizes,
+};
+use tracing::warn;
+use uuid::Uuid;
+
+mod capabilities;
+mod screen;
+mod widgets;
+mod width;
+
+pub use capabilities::Capabilities;
+pub use screen::{Buffer, Screen, Style};
+pub use widgets::{
+    truncate_with_ellipsis, Area, BorderedWindow, ListRow, ListView, Scrollbar, WindowLayout,
+};
+pub use width::{char_width, cluster_width, display_width, graphemes, prefix_for_width};
+
+/// Images kept in the terminal at once: the page and highlights on screen
+/// plus the neighbours sent ahead of a page turn.
+const TRANSMISSION_SLOTS: usize = 6;
+
+/// Images stacked on screen, bottom first. Each is replaced on its own, so
+/// toggling highlights does not send the page again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Page,
+    Highlights,
+}
+
+impl Layer {
+    const COUNT: usize = 2;
+
+    fn index(self) -> usize {
+        match self {
+            Layer::Page => 0,
+            Layer::Highlights => 1,
+        }
+    }
+
+    /// Both stay below the text so overlays drawn as text cover them.
+    fn z_index(self) -> i32 {
+        match self {
+            Layer::Page => -2,
+            Layer::Highlights => -1,
+        }
+    }
+}
 
 pub struct KittyRenderer<W: Write> {
     writer: W,
     image_id: u32,
     placement_id: u32,
+    /// Placement and content digest of the image shown on each layer, per
+    /// pane.
+    shown: Vec<[Option<(Placement, u64)>; Layer::COUNT]>,
+    /// Pane that `place` and `hide_layer` act on.
+    pane: usize,
+    transmissions: Transmissions,
+    transfer: TransferMedium,
+    encoding: ImageEncoding,
+    /// Transfer files written and perhaps not yet read by the terminal,
+    /// which deletes those it reads.
+    transfer_files: Vec<PathBuf>,
+    passthrough: Passthrough,
+    /// Cell of the outer terminal where this program's screen starts, e.g.
+    /// the top left corner of a tmux pane.
+    origin: (u16, u16),
+    /// Off for terminals without kitty's graphics, which would show the
+    /// commands as text.
+    graphics: bool,
+    synchronized_output: bool,
+    /// Text drawn around and over the images.
+    screen: Screen,
+}
+
+impl<W: Write> Drop for KittyRenderer<W> {
+    fn drop(&mut self) {
+        self.remove_transfer_files();
+    }
+}
+
+/// How graphics commands get past a terminal multiplexer to the terminal
+/// that draws them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Passthrough {
+    /// Commands go straight to the terminal.
+    None,
+    /// Each command is wrapped in tmux's passthrough escape, which tmux
+    /// only forwards with `allow-passthrough` on.
+    Tmux,
+}
+
+impl Passthrough {
+    /// tmux when running inside it, which it announces in `TMUX`.
+    pub fn detect() -> Self {
+        if env::var_os("TMUX").is_some_and(|value| !value.is_empty()) {
+            Passthrough::Tmux
+        } else {
+            Passthrough::None
+        }
+    }
+}
+
+impl FromStr for Passthrough {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Passthrough::None),
+            "tmux" => Ok(Passthrough::Tmux),
+            other => Err(anyhow!("Expected none or tmux, got '{}'", other)),
+        }
+    }
+}
+
+/// How image data reaches the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferMedium {
+    /// Base64 chunks written to the terminal; works everywhere, including SSH.
+    Direct,
+    /// A temporary file the terminal reads and deletes (`t=t`).
+    File,
+    /// A POSIX shared memory object the terminal reads and unlinks (`t=s`).
+    SharedMemory,
+}
+
+/// How pixels are packed before they are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageEncoding {
+    /// PNG (`f=100`): smallest, but encoding dominates redraws of big pages.
+    Png,
+    /// Raw RGBA (`f=32`), cheapest to produce.
+    Raw,
+    /// Raw RGBA compressed with zlib (`f=32,o=z`).
+    Zlib,
+}
+
+impl ImageEncoding {
+    /// Raw pixels when they are handed over locally, compressed ones when
+    /// they travel through the terminal as base64.
+    pub fn for_transfer(transfer: TransferMedium) -> Self {
+        match transfer {
+            TransferMedium::Direct => ImageEncoding::Zlib,
+            TransferMedium::File | TransferMedium::SharedMemory => ImageEncoding::Raw,
+        }
+    }
+}
+
+impl FromStr for ImageEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "png" => Ok(ImageEncoding::Png),
+            "raw" => Ok(ImageEncoding::Raw),
+            "zlib" => Ok(ImageEncoding::Zlib),
+            other => Err(anyhow!("Expected png, raw or zlib, got '{}'", other)),
+        }
+    }
+}
+
+/// Directory backing POSIX shared memory objects on Linux.
+const SHM_DIR: &str = "/dev/shm";
+
+impl TransferMedium {
+    /// Shared memory, or else a temporary file, when the terminal read the
+    /// image put there by [`Capabilities::probe`], which it cannot from
+    /// another machine or container; direct transfer otherwise.
+    pub fn detect(capabilities: &Capabilities) -> Self {
+        if capabilities.shared_memory {
+            TransferMedium::SharedMemory
+        } else if capabilities.temp_files {
+            TransferMedium::File
+        } else {
+            TransferMedium::Direct
+        }
+    }
+}
+
+impl FromStr for TransferMedium {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "direct" => Ok(TransferMedium::Direct),
+            "file" => Ok(TransferMedium::File),
+            "shm" => Ok(TransferMedium::SharedMemory),
+            other => Err(anyhow!("Expected direct, file or shm, got '{}'", other)),
+        }
+    }
+}
+
+/// Image ids of pages already uploaded to the terminal, keyed by content
+/// digest and ordered from least to most recently used.
+#[derive(Debug, Default)]
+struct Transmissions {
+    entries: Vec<(u64, u32)>,
+    next_id: u32,
+}
+
+impl Transmissions {
+    fn get(&mut self, digest: u64) -> Option<u32> {
+        let index = self.entries.iter().position(|(d, _)| *d == digest)?;
+        let entry = self.entries.remove(index);
+        self.entries.push(entry);
+        Some(entry.1)
+    }
+
+    /// Records a new upload and returns its id, plus the id of an upload
+    /// evicted to make room for it. Digests in `keep` are never evicted.
+    fn insert(&mut self, digest: u64, keep: &[u64]) -> (u32, Option<u32>) {
+        let mut evicted = None;
+        if self.entries.len() >= TRANSMISSION_SLOTS {
+            if let Some(index) = self.entries.iter().position(|(d, _)| !keep.contains(d)) {
+                evicted = Some(self.entries.remove(index).1);
+            }
+        }
+        // Id 1 belongs to `draw`; kitty ids are 32-bit and never zero.
+        self.next_id = self.next_id.checked_add(1).unwrap_or(2).max(2);
+        self.entries.push((digest, self.next_id));
+        (self.next_id, evicted)
+    }
+
+    fn id_of(&self, digest: u64) -> Option<u32> {
+        self.entries
+            .iter()
+            .find(|(d, _)| *d == digest)
+            .map(|(_, id)| *id)
+    }
+}
+
+/// Cells covered by the page image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub column: u16,
+    pub row: u16,
+    pub columns: u32,
+    pub rows: u32,
 }
 
 pub struct DrawParams {
@@ -37,83 +282,675 @@ impl<W: Write> KittyRenderer<W> {
             writer,
             image_id: 1,
             placement_id: 1,
+            shown: vec![[None; Layer::COUNT]],
+            pane: 0,
+            transmissions: Transmissions::default(),
+            transfer: TransferMedium::Direct,
+            encoding: ImageEncoding::Png,
+            transfer_files: Vec::new(),
+            passthrough: Passthrough::None,
+            origin: (0, 0),
+            graphics: true,
+            synchronized_output: true,
+            screen: Screen::new(),
         }
     }
 
+    pub fn screen(&mut self) -> &mut Screen {
+        &mut self.screen
+    }
+
+    /// Writes the text that changed since it was last written.
+    pub fn present(&mut self) -> Result<()> {
+        self.screen.flush(&mut self.writer)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Stops sending graphics commands, for terminals that cannot show
+    /// images.
+    pub fn set_graphics(&mut self, enabled: bool) {
+        self.graphics = enabled;
+    }
+
+    pub fn set_synchronized_output(&mut self, enabled: bool) {
+        self.synchronized_output = enabled;
+    }
+
+    pub fn set_passthrough(&mut self, passthrough: Passthrough) {
+        self.passthrough = passthrough;
+    }
+
+    /// Sets the outer terminal's cell at which this program's screen
+    /// starts. Placements are positioned by the outer terminal under tmux,
+    /// so they are offset by it.
+    pub fn set_origin(&mut self, column: u16, row: u16) {
+        self.origin = (column, row);
+    }
+
+    pub fn set_transfer(&mut self, transfer: TransferMedium) {
+        self.transfer = transfer;
+    }
+
+    pub fn transfer(&self) -> TransferMedium {
+        self.transfer
+    }
+
+    pub fn set_encoding(&mut self, encoding: ImageEncoding) {
+        self.encoding = encoding;
+    }
+
     pub fn writer(&mut self) -> &mut W {
         &mut self.writer
     }
 
     pub fn draw(&mut self, image: &RenderImage, params: DrawParams) -> Result<()> {
-        let mut buffer = Vec::new();
-        let mut encoder = Encoder::new(&mut buffer, image.width, image.height);
-        encoder.set_color(ColorType::Rgba);
-        encoder.set_depth(BitDepth::Eight);
-        let mut writer = encoder.write_header()?;
-        writer.write_image_data(&image.pixels)?;
-        writer.finish()?;
-
-        let encoded = BASE64.encode(&buffer);
+        let keys = format!(
+            "a=T,C=1,q=2,i={},p={},c={},r={},s={},v={},z=-1",
+            self.image_id,
+            self.placement_id,
+            params.columns,
+            params.rows,
+            image.width,
+            image.height,
+        );
+        self.send_image(image, &keys)
+    }
+
+    /// Uploads `image` without showing it, unless it is already in the
+    /// terminal, and returns its image id. A later [`place`](Self::place)
+    /// of the same pixels then only sends a placement command.
+    pub fn transmit(&mut self, image: &RenderImage) -> Result<u32> {
+        let digest = image_digest(image);
+        self.transmit_digest(image, digest)
+    }
+
+    fn transmit_digest(&mut self, image: &RenderImage, digest: u64) -> Result<u32> {
+        if let Some(id) = self.transmissions.get(digest) {
+            return Ok(id);
+        }
+        let shown: Vec<u64> = self
+            .shown
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|(_, d)| *d)
+            .collect();
+        let (id, evicted) = self.transmissions.insert(digest, &shown);
+        if let Some(evicted) = evicted {
+            // Uppercase frees the image data along with any placements.
+            self.command(&format!("a=d,d=I,i={},q=2", evicted), None)?;
+        }
+        let keys = format!("a=t,q=2,i={},s={},v={}", id, image.width, image.height);
+        self.send_image(image, &keys)?;
+        Ok(id)
+    }
+
+    /// Encodes `image` as configured and sends it with `keys`, which must
+    /// carry the size (`s=`, `v=`) as raw pixels need it.
+    fn send_image(&mut self, image: &RenderImage, keys: &str) -> Result<()> {
+        match self.encoding {
+            ImageEncoding::Png => {
+                let mut buffer = Vec::new();
+                let mut encoder = Encoder::new(&mut buffer, image.width, image.height);
+                encoder.set_color(ColorType::Rgba);
+                encoder.set_depth(BitDepth::Eight);
+                let mut writer = encoder.write_header()?;
+                writer.write_image_data(&image.pixels)?;
+                writer.finish()?;
+                self.send_payload(&buffer, &format!("{},f=100", keys))
+            }
+            ImageEncoding::Raw => self.send_payload(&image.pixels, &format!("{},f=32", keys)),
+            ImageEncoding::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+                encoder.write_all(&image.pixels)?;
+                let buffer = encoder.finish()?;
+                self.send_payload(&buffer, &format!("{},f=32,o=z", keys))
+            }
+        }
+    }
+
+    /// Sends image data over the configured medium. When the data cannot be
+    /// written out for the terminal to read, direct transfer is used from
+    /// then on.
+    fn send_payload(&mut self, data: &[u8], keys: &str) -> Result<()> {
+        if !self.graphics {
+            return Ok(());
+        }
+        let medium = match self.transfer {
+            TransferMedium::Direct => None,
+            TransferMedium::File => Some('t'),
+            TransferMedium::SharedMemory => Some('s'),
+        };
+        if let Some(medium) = medium {
+            match self.write_transfer_file(data) {
+                Ok(name) => {
+                    self.command(
+                        &format!("{},t={},S={}", keys, medium, data.len()),
+                        Some(BASE64.encode(name).as_bytes()),
+                    )?;
+                    self.writer.flush()?;
+                    return Ok(());
+                }
+                Err(err) => {
+                    warn!(?err, "falling back to direct image transfer");
+                    self.transfer = TransferMedium::Direct;
+                    self.remove_transfer_files();
+                }
+            }
+        }
+
+        let encoded = BASE64.encode(data);
         let mut chunks = encoded.as_bytes().chunks(4096).peekable();
         let mut first = true;
 
         while let Some(chunk) = chunks.next() {
-            let more = chunks.peek().is_some();
-            if first {
-                write!(
-                    self.writer,
-                    "\u{1b}_Ga=T,f=100,C=1,q=2,i={},p={},c={},r={},s={},v={},z=-1,m={}",
-                    self.image_id,
-                    self.placement_id,
-                    params.columns,
-                    params.rows,
-                    image.width,
-                    image.height,
-                    if more { 1 } else { 0 }
-                )?;
+            let more = if chunks.peek().is_some() { 1 } else { 0 };
+            let control = if first {
                 first = false;
+                format!("{},m={}", keys, more)
             } else {
-                write!(self.writer, "\u{1b}_Gm={},q=2", if more { 1 } else { 0 })?;
+                format!("m={},q=2", more)
+            };
+            self.command(&control, (!chunk.is_empty()).then_some(chunk))?;
+        }
+
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes `data` where the terminal can pick it up and returns the name
+    /// to send: a path for files, an object name for shared memory.
+    /// The file is created afresh, readable only by this user, under a
+    /// random name, so other users can neither read the pages nor have the
+    /// write follow a link they planted.
+    fn write_transfer_file(&mut self, data: &[u8]) -> Result<String> {
+        // Files the terminal has read are gone by now.
+        self.transfer_files.retain(|path| path.exists());
+        // kitty only deletes files whose name says they are meant for it.
+        let name = format!(
+            "tty-graphics-protocol-termpdf-{}-{}",
+            process::id(),
+            Uuid::new_v4().simple()
+        );
+        let (path, sent) = match self.transfer {
+            TransferMedium::SharedMemory => {
+                (PathBuf::from(SHM_DIR).join(&name), format!("/{}", name))
             }
-            if !chunk.is_empty() {
-                self.writer.write_all(b";")?;
-                self.writer.write_all(chunk)?;
+            _ => {
+                let path = env::temp_dir().join(&name);
+                let sent = path.to_string_lossy().into_owned();
+                (path, sent)
             }
-            write!(self.writer, "\u{1b}\\")?;
+        };
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&path)?;
+        if let Err(err) = file.write_all(data) {
+            let _ = fs::remove_file(&path);
+            return Err(err.into());
         }
+        self.transfer_files.push(path);
+        Ok(sent)
+    }
+
+    /// Deletes the transfer files the terminal has not read, e.g. because
+    /// it ignored a command or the program is exiting.
+    pub fn remove_transfer_files(&mut self) {
+        for path in self.transfer_files.drain(..) {
+            let _ = fs::remove_file(path);
+        }
+    }
 
+    /// Shows `image` at `placement` unless the same image is already shown
+    /// there, so redraws that change nothing on the page do not flicker.
+    /// Images sent earlier with [`transmit`](Self::transmit) are only
+    /// placed. Returns whether anything was sent.
+    pub fn place(&mut self, image: &RenderImage, placement: Placement) -> Result<bool> {
+        self.place_layer(Layer::Page, image, placement)
+    }
+
+    /// Selects the pane later placements go to, for layouts that show
+    /// several page views. Pane 0 is the only one otherwise.
+    pub fn select_pane(&mut self, pane: usize) {
+        if self.shown.len() <= pane {
+            self.shown.resize(pane + 1, [None; Layer::COUNT]);
+        }
+        self.pane = pane;
+    }
+
+    /// Removes everything `pane` shows, e.g. once a split is closed.
+    pub fn hide_pane(&mut self, pane: usize) -> Result<()> {
+        let selected = self.pane;
+        if pane < self.shown.len() {
+            self.pane = pane;
+            let hidden = self
+                .hide_layer(Layer::Page)
+                .and(self.hide_layer(Layer::Highlights));
+            self.pane = selected;
+            hidden?;
+        }
+        Ok(())
+    }
+
+    /// Placement id of `layer` in the selected pane; the same image can be
+    /// placed in two panes at once.
+    fn layer_placement_id(&self, layer: Layer) -> u32 {
+        self.placement_id + (self.pane * Layer::COUNT + layer.index()) as u32
+    }
+
+    /// Like [`place`](Self::place), for one layer of the page view.
+    pub fn place_layer(
+        &mut self,
+        layer: Layer,
+        image: &RenderImage,
+        placement: Placement,
+    ) -> Result<bool> {
+        let digest = image_digest(image);
+        if self.shown[self.pane][layer.index()] == Some((placement, digest)) {
+            return Ok(false);
+        }
+        let id = self.transmit_digest(image, digest)?;
+        self.hide_layer(layer)?;
+        let params = DrawParams::clamped(placement.columns, placement.rows);
+        let control = format!(
+            "a=p,i={},p={},c={},r={},C=1,q=2,z={}",
+            id,
+            self.layer_placement_id(layer),
+            params.columns,
+            params.rows,
+            layer.z_index()
+        );
+        match self.passthrough {
+            Passthrough::None => {
+                crossterm::queue!(self.writer, cursor::MoveTo(placement.column, placement.row))?;
+                self.command(&control, None)?;
+            }
+            // tmux does not move the outer terminal's cursor for its panes'
+            // cursor moves, so the placement carries its own, relative to
+            // the whole terminal, and puts the cursor back afterwards.
+            Passthrough::Tmux if self.graphics => {
+                let mut moved = format!(
+                    "\u{1b}7\u{1b}[{};{}H",
+                    self.origin.1 as u32 + placement.row as u32 + 1,
+                    self.origin.0 as u32 + placement.column as u32 + 1
+                )
+                .into_bytes();
+                write_command(&mut moved, Passthrough::None, &control, None)?;
+                moved.extend_from_slice(b"\x1b8");
+                write_passthrough(&mut self.writer, self.passthrough, &moved)?;
+            }
+            Passthrough::Tmux => {}
+        }
         self.writer.flush()?;
+        self.shown[self.pane][layer.index()] = Some((placement, digest));
+        Ok(true)
+    }
+
+    /// Removes what `layer` shows but keeps its image data, so it can be
+    /// placed again without another upload.
+    pub fn hide_layer(&mut self, layer: Layer) -> Result<()> {
+        let Some((_, digest)) = self.shown[self.pane][layer.index()].take() else {
+            return Ok(());
+        };
+        if let Some(id) = self.transmissions.id_of(digest) {
+            let control = format!("a=d,d=i,i={},p={},q=2", id, self.layer_placement_id(layer));
+            self.command(&control, None)?;
+        }
+        Ok(())
+    }
+
+    /// Removes every image on screen but keeps their data.
+    pub fn hide_images(&mut self) -> Result<()> {
+        for pane in 0..self.shown.len() {
+            self.hide_pane(pane)?;
+        }
         Ok(())
     }
 
     pub fn begin_sync_update(&mut self) -> Result<()> {
-        write!(self.writer, "\u{1b}[?2026h")?;
+        if self.synchronized_output {
+            write!(self.writer, "\u{1b}[?2026h")?;
+        }
         Ok(())
     }
 
-    /// Disables synchronized updates.
+    /// Writes the frame's text and disables synchronized updates.
     /// The terminal will render all buffered changes at once.
     pub fn end_sync_update(&mut self) -> Result<()> {
-        write!(self.writer, "\u{1b}[?2026l")?;
+        self.screen.flush(&mut self.writer)?;
+        if self.synchronized_output {
+            write!(self.writer, "\u{1b}[?2026l")?;
+        }
         self.writer.flush()?;
         Ok(())
     }
 
+    /// Removes the page image, for views that draw only text.
+    pub fn delete_image(&mut self) -> Result<()> {
+        self.hide_images()?;
+        self.command(&format!("a=d,d=i,i={},q=2", self.image_id), None)?;
+        Ok(())
+    }
+
+    /// Deletes every image from the terminal along with its data, as when
+    /// handing the terminal to another program; the next frame sends
+    /// everything again.
+    pub fn delete_all(&mut self) -> Result<()> {
+        if self.graphics {
+            delete_all_images(&mut self.writer, self.passthrough)?;
+        }
+        for layers in &mut self.shown {
+            *layers = [None; Layer::COUNT];
+        }
+        self.transmissions = Transmissions::default();
+        Ok(())
+    }
+
+    /// Writes one graphics command, with `payload` after its keys.
+    fn command(&mut self, control: &str, payload: Option<&[u8]>) -> io::Result<()> {
+        if !self.graphics {
+            return Ok(());
+        }
+        write_command(&mut self.writer, self.passthrough, control, payload)
+    }
+
     /// Clears the entire screen.
     pub fn clear_all(&mut self) -> Result<()> {
+        self.hide_images()?;
         crossterm::execute!(
             &mut self.writer,
             Clear(ClearType::All),
             cursor::MoveTo(0, 0)
         )?;
+        self.screen.cleared();
         Ok(())
     }
 }
 
+fn image_digest(image: &RenderImage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    image.width.hash(&mut hasher);
+    image.height.hash(&mut hasher);
+    image.pixels.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Deletes every image placed in the terminal along with its data, for
+/// cleaning up without a renderer, as when termpdf exits abnormally.
+pub fn delete_all_images<W: Write>(writer: &mut W, passthrough: Passthrough) -> io::Result<()> {
+    write_command(writer, passthrough, "a=d,d=A,q=2", None)?;
+    writer.flush()
+}
+
+/// Writes the graphics command `ESC _ G control ; payload ESC \`.
+fn write_command<W: Write>(
+    writer: &mut W,
+    passthrough: Passthrough,
+    control: &str,
+    payload: Option<&[u8]>,
+) -> io::Result<()> {
+    let mut command = Vec::with_capacity(control.len() + payload.map_or(0, <[u8]>::len) + 6);
+    command.extend_from_slice(b"\x1b_G");
+    command.extend_from_slice(control.as_bytes());
+    if let Some(payload) = payload {
+        command.push(b';');
+        command.extend_from_slice(payload);
+    }
+    command.extend_from_slice(b"\x1b\\");
+    write_passthrough(writer, passthrough, &command)
+}
+
+/// Writes `sequence` so that it reaches the outer terminal. tmux wants it
+/// inside `ESC P tmux; … ESC \` with every escape in it doubled.
+fn write_passthrough<W: Write>(
+    writer: &mut W,
+    passthrough: Passthrough,
+    sequence: &[u8],
+) -> io::Result<()> {
+    match passthrough {
+        Passthrough::None => writer.write_all(sequence),
+        Passthrough::Tmux => {
+            let mut wrapped = Vec::with_capacity(sequence.len() + 16);
+            wrapped.extend_from_slice(b"\x1bPtmux;");
+            for &byte in sequence {
+                if byte == 0x1b {
+                    wrapped.push(0x1b);
+                }
+                wrapped.push(byte);
+            }
+            wrapped.extend_from_slice(b"\x1b\\");
+            writer.write_all(&wrapped)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crossterm::event::{KeyEventKind, KeyEventState, KeyModifiers};
+    use std::sync::Arc;
+    use termpdf_core::ManualClock;
+
+    #[test]
+    fn placing_the_same_image_twice_sends_it_once() {
+        let mut renderer = KittyRenderer::new(Vec::new());
+        let mut image = RenderImage {
+            width: 1,
+            height: 1,
+            pixels: vec![255, 0, 0, 255],
+        };
+        let placement = Placement {
+            column: 2,
+            row: 0,
+            columns: 10,
+            rows: 5,
+        };
+        assert!(renderer.place(&image, placement).unwrap());
+        assert!(!renderer.place(&image, placement).unwrap());
+        let moved = Placement {
+            column: 3,
+            ..placement
+        };
+        assert!(renderer.place(&image, moved).unwrap());
+        image.pixels[1] = 255;
+        assert!(renderer.place(&image, moved).unwrap());
+        renderer.clear_all().unwrap();
+        assert!(renderer.place(&image, moved).unwrap());
+
+        renderer.writer.clear();
+        renderer.delete_all().unwrap();
+        assert!(renderer.place(&image, moved).unwrap());
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert!(output.starts_with("\u{1b}_Ga=d,d=A"), "{output:?}");
+        assert!(output.contains("a=t"), "{output:?}");
+    }
+
+    #[test]
+    fn transmitted_pages_are_only_placed() {
+        let mut renderer = KittyRenderer::new(Vec::new());
+        let page = |red: u8| RenderImage {
+            width: 1,
+            height: 1,
+            pixels: vec![red, 0, 0, 255],
+        };
+        let placement = Placement {
+            column: 0,
+            row: 0,
+            columns: 10,
+            rows: 5,
+        };
+        renderer.place(&page(1), placement).unwrap();
+        let next = renderer.transmit(&page(2)).unwrap();
+        assert_eq!(renderer.transmit(&page(2)).unwrap(), next);
+
+        renderer.writer.clear();
+        assert!(renderer.place(&page(2), placement).unwrap());
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert!(!output.contains("a=t"), "{output:?}");
+        assert!(output.contains(&format!("a=p,i={},p=1,c=10,r=5", next)));
+        assert!(output.contains("a=d,d=i,i=2"));
+        assert!(output.contains("z=-2"));
+
+        // Old pages are freed once the slots run out, never the one shown.
+        for red in 3..3 + TRANSMISSION_SLOTS as u8 {
+            renderer.transmit(&page(red)).unwrap();
+        }
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert!(output.contains("a=d,d=I,i=2"));
+        assert!(!output.contains(&format!("a=d,d=I,i={}", next)));
+        renderer.writer.clear();
+        assert!(renderer
+            .place(
+                &page(2),
+                Placement {
+                    row: 1,
+                    ..placement
+                }
+            )
+            .unwrap());
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert!(!output.contains("a=t"), "{output:?}");
+    }
+
+    #[test]
+    fn highlight_layer_changes_leave_the_page_alone() {
+        let mut renderer = KittyRenderer::new(Vec::new());
+        let image = |red: u8| RenderImage {
+            width: 1,
+            height: 1,
+            pixels: vec![red, 0, 0, 255],
+        };
+        let placement = Placement {
+            column: 0,
+            row: 0,
+            columns: 10,
+            rows: 5,
+        };
+        renderer.place(&image(1), placement).unwrap();
+        renderer
+            .place_layer(Layer::Highlights, &image(2), placement)
+            .unwrap();
+        renderer.writer.clear();
+
+        assert!(!renderer.place(&image(1), placement).unwrap());
+        assert!(renderer
+            .place_layer(Layer::Highlights, &image(3), placement)
+            .unwrap());
+        renderer.hide_layer(Layer::Highlights).unwrap();
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert_eq!(output.matches("a=t,").count(), 1, "{output:?}");
+        assert!(output.contains("z=-1"));
+        assert!(!output.contains("a=d,d=i,i=2,"));
+        assert!(!renderer.place(&image(1), placement).unwrap());
+    }
+
+    #[test]
+    fn panes_keep_their_own_placements() {
+        let mut renderer = KittyRenderer::new(Vec::new());
+        let image = RenderImage {
+            width: 1,
+            height: 1,
+            pixels: vec![9, 9, 9, 255],
+        };
+        let placement = Placement {
+            column: 0,
+            row: 0,
+            columns: 10,
+            rows: 5,
+        };
+        renderer.place(&image, placement).unwrap();
+        renderer.select_pane(1);
+        let right = Placement {
+            column: 11,
+            ..placement
+        };
+        assert!(renderer.place(&image, right).unwrap());
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert_eq!(output.matches("a=t,").count(), 1);
+        assert!(output.contains("a=p,i=2,p=3,"), "{output:?}");
+
+        renderer.writer.clear();
+        renderer.hide_pane(1).unwrap();
+        renderer.select_pane(0);
+        assert!(!renderer.place(&image, placement).unwrap());
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert_eq!(output, "\u{1b}_Ga=d,d=i,i=2,p=3,q=2\u{1b}\\");
+    }
+
+    #[test]
+    fn file_transfer_sends_the_path_instead_of_the_data() {
+        let mut renderer = KittyRenderer::new(Vec::new());
+        renderer.set_transfer(TransferMedium::File);
+        let image = RenderImage {
+            width: 1,
+            height: 1,
+            pixels: vec![255, 0, 0, 255],
+        };
+        renderer.transmit(&image).unwrap();
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert!(output.starts_with("\u{1b}_Ga=t,"), "{output:?}");
+        assert!(output.contains(",f=100,t=t,"));
+        let encoded = output
+            .split(';')
+            .nth(1)
+            .unwrap()
+            .trim_end_matches("\u{1b}\\");
+        let path = PathBuf::from(String::from_utf8(BASE64.decode(encoded).unwrap()).unwrap());
+        let data = fs::read(&path).unwrap();
+        assert_eq!(&data[1..4], b"PNG");
+        assert!(output.contains(&format!("S={};", data.len())));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // Files the terminal left unread are removed with the renderer.
+        drop(renderer);
+        assert!(!path.exists());
+
+        assert_eq!(
+            "shm".parse::<TransferMedium>().unwrap(),
+            TransferMedium::SharedMemory
+        );
+        assert!("pipe".parse::<TransferMedium>().is_err());
+    }
+
+    #[test]
+    fn raw_encodings_send_the_pixels() {
+        let image = RenderImage {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 0, 0, 255, 255],
+        };
+        let mut renderer = KittyRenderer::new(Vec::new());
+        renderer.set_encoding(ImageEncoding::Raw);
+        renderer.transmit(&image).unwrap();
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert!(output.contains("s=2,v=1,f=32,m=0;"), "{output:?}");
+        assert!(output.contains(&BASE64.encode(&image.pixels)));
+
+        let mut renderer = KittyRenderer::new(Vec::new());
+        renderer.set_encoding(ImageEncoding::Zlib);
+        renderer.transmit(&image).unwrap();
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        let (_, payload) = output.split_once("f=32,o=z,m=0;").unwrap();
+        let compressed = BASE64.decode(payload.trim_end_matches("\u{1b}\\")).unwrap();
+        let mut pixels = Vec::new();
+        std::io::Read::read_to_end(
+            &mut flate2::read::ZlibDecoder::new(&compressed[..]),
+            &mut pixels,
+        )
+        .unwrap();
+        assert_eq!(pixels, image.pixels);
+        assert_eq!(
+            ImageEncoding::for_transfer(TransferMedium::Direct),
+            ImageEncoding::Zlib
+        );
+    }
 
     #[test]
     fn kitty_draw_emits_protocol() {
@@ -125,12 +962,79 @@ mod tests {
         };
 
         renderer.draw(&image, DrawParams::clamped(10, 5)).unwrap();
-        let output = renderer.writer;
+        let output = std::mem::take(&mut renderer.writer);
         assert_eq!(output[0], 0x1b);
         assert_eq!(output[1], b'_');
         assert_eq!(output[2], b'G');
     }
 
+    #[test]
+    fn tmux_passthrough_wraps_commands_and_positions_placements() {
+        let mut renderer = KittyRenderer::new(Vec::new());
+        renderer.set_passthrough(Passthrough::Tmux);
+        renderer.set_origin(40, 2);
+        let image = RenderImage {
+            width: 1,
+            height: 1,
+            pixels: vec![255, 0, 0, 255],
+        };
+        let placement = Placement {
+            column: 3,
+            row: 1,
+            columns: 10,
+            rows: 5,
+        };
+        renderer.place(&image, placement).unwrap();
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert!(
+            output.starts_with("\u{1b}Ptmux;\u{1b}\u{1b}_Ga=t,"),
+            "{output:?}"
+        );
+        assert!(output
+            .contains("\u{1b}Ptmux;\u{1b}\u{1b}7\u{1b}\u{1b}[4;44H\u{1b}\u{1b}_Ga=p,i=2,p=1,"));
+        assert!(output.ends_with("\u{1b}\u{1b}\\\u{1b}\u{1b}8\u{1b}\\"));
+
+        let mut cleanup = Vec::new();
+        delete_all_images(&mut cleanup, Passthrough::Tmux).unwrap();
+        assert_eq!(
+            cleanup,
+            b"\x1bPtmux;\x1b\x1b_Ga=d,d=A,q=2\x1b\x1b\\\x1b\\".to_vec()
+        );
+        assert_eq!("TMUX".parse::<Passthrough>().unwrap(), Passthrough::Tmux);
+        assert!("screen".parse::<Passthrough>().is_err());
+    }
+
+    #[test]
+    fn terminals_without_graphics_get_no_commands() {
+        let mut renderer = KittyRenderer::new(Vec::new());
+        renderer.set_graphics(false);
+        renderer.set_synchronized_output(false);
+        renderer.set_transfer(TransferMedium::File);
+        let image = RenderImage {
+            width: 1,
+            height: 1,
+            pixels: vec![255, 0, 0, 255],
+        };
+        renderer.begin_sync_update().unwrap();
+        renderer
+            .place(
+                &image,
+                Placement {
+                    column: 0,
+                    row: 0,
+                    columns: 10,
+                    rows: 5,
+                },
+            )
+            .unwrap();
+        renderer.delete_image().unwrap();
+        renderer.delete_all().unwrap();
+        renderer.end_sync_update().unwrap();
+        let output = String::from_utf8(renderer.writer.clone()).unwrap();
+        assert!(!output.contains("\u{1b}_G"), "{output:?}");
+        assert!(!output.contains("2026"), "{output:?}");
+    }
+
     fn key_event(code: KeyCode) -> Event {
         key_event_with_modifiers(code, KeyModifiers::NONE)
     }
@@ -144,6 +1048,34 @@ mod tests {
         })
     }
 
+    #[test]
+    fn documented_normal_keys_are_mapped() {
+        for binding in KEY_BINDINGS
+            .iter()
+            .filter(|binding| binding.mode == "normal")
+        {
+            for key in binding.keys.split(", ") {
+                let mut chars = key.chars();
+                let (Some(ch), None) = (chars.next(), chars.next()) else {
+                    continue;
+                };
+                let modifiers = if ch.is_ascii_uppercase() {
+                    KeyModifiers::SHIFT
+                } else {
+                    KeyModifiers::NONE
+                };
+                let mut mapper = EventMapper::new();
+                let event =
+                    mapper.map_event(key_event_with_modifiers(KeyCode::Char(ch), modifiers));
+                assert!(
+                    !matches!(event, UiEvent::None),
+                    "{:?} is documented but does nothing",
+                    key
+                );
+            }
+        }
+    }
+
     #[test]
     fn event_mapper_uses_numeric_prefix_for_next_page() {
         let mut mapper = EventMapper::new();
@@ -181,6 +1113,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn event_mapper_toggles_reflow() {
+        let mut mapper = EventMapper::new();
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('R'))),
+            UiEvent::Command(Command::ToggleReflow)
+        ));
+    }
+
+    #[test]
+    fn event_mapper_uses_count_as_explicit_dark_mode() {
+        let mut mapper = EventMapper::new();
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('d'))),
+            UiEvent::Command(Command::ToggleDarkMode)
+        ));
+
+        mapper.map_event(key_event(KeyCode::Char('0')));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('d'))),
+            UiEvent::Command(Command::SetDarkMode { enabled: false })
+        ));
+
+        mapper.map_event(key_event(KeyCode::Char('1')));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('d'))),
+            UiEvent::Command(Command::SetDarkMode { enabled: true })
+        ));
+    }
+
+    #[test]
+    fn event_mapper_uses_count_as_explicit_fit_mode() {
+        let mut mapper = EventMapper::new();
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('s'))),
+            UiEvent::Command(Command::ToggleFitMode)
+        ));
+
+        mapper.map_event(key_event(KeyCode::Char('0')));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('s'))),
+            UiEvent::Command(Command::SetFitMode {
+                mode: FitMode::Page
+            })
+        ));
+
+        mapper.map_event(key_event(KeyCode::Char('2')));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('s'))),
+            UiEvent::Command(Command::SetFitMode {
+                mode: FitMode::Width
+            })
+        ));
+        assert_eq!(mapper.pending_input(), None);
+    }
+
     #[test]
     fn event_mapper_drops_prefix_on_other_command() {
         let mut mapper = EventMapper::new();
@@ -277,6 +1265,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn event_mapper_command_history_can_be_restored() {
+        let mut mapper = EventMapper::new();
+        let saved: Vec<String> = (0..EventMapper::COMMAND_HISTORY_LIMIT + 5)
+            .map(|index| format!("page {}", index))
+            .chain(["q".to_string(), "q".to_string(), " ".to_string()])
+            .collect();
+        mapper.set_command_history(saved);
+        let history = mapper.command_history();
+        assert_eq!(history.len(), EventMapper::COMMAND_HISTORY_LIMIT);
+        assert_eq!(history.first().map(String::as_str), Some("page 6"));
+        assert_eq!(history.last().map(String::as_str), Some("q"));
+    }
+
     #[test]
     fn event_mapper_command_mode_recalls_history() {
         let mut mapper = EventMapper::new();
@@ -324,21 +1326,54 @@ mod tests {
         }
     }
 
-    #[test]
-    fn event_mapper_pending_input_shows_char_stack_until_completed() {
-        let mut mapper = EventMapper::new();
-        assert!(mapper.pending_input().is_none());
+    #[test]
+    fn event_mapper_pending_input_shows_char_stack_until_completed() {
+        let mut mapper = EventMapper::new();
+        assert!(mapper.pending_input().is_none());
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('m'))),
+            UiEvent::None
+        ));
+        assert_eq!(mapper.pending_input().as_deref(), Some("m"));
+
+        match mapper.map_event(key_event(KeyCode::Char('G'))) {
+            UiEvent::Command(Command::PutMark { key }) => assert_eq!(key, 'G'),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(mapper.pending_input().is_none());
+    }
+
+    #[test]
+    fn event_mapper_counts_pick_the_page_for_g_and_gg() {
+        let mut mapper = EventMapper::new();
+        let shift_g = || key_event_with_modifiers(KeyCode::Char('G'), KeyModifiers::SHIFT);
+        assert!(matches!(
+            mapper.map_event(shift_g()),
+            UiEvent::Command(Command::GotoPage { page: usize::MAX })
+        ));
+
+        mapper.map_event(key_event(KeyCode::Char('1')));
+        mapper.map_event(key_event(KeyCode::Char('5')));
+        assert!(matches!(
+            mapper.map_event(shift_g()),
+            UiEvent::Command(Command::GotoPage { page: 14 })
+        ));
+
         assert!(matches!(
-            mapper.map_event(key_event(KeyCode::Char('m'))),
+            mapper.map_event(key_event(KeyCode::Char('g'))),
             UiEvent::None
         ));
-        assert_eq!(mapper.pending_input().as_deref(), Some("m"));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('g'))),
+            UiEvent::Command(Command::GotoPage { page: 0 })
+        ));
 
-        match mapper.map_event(key_event(KeyCode::Char('G'))) {
-            UiEvent::Command(Command::PutMark { key }) => assert_eq!(key, 'G'),
-            other => panic!("unexpected event: {:?}", other),
-        }
-        assert!(mapper.pending_input().is_none());
+        mapper.map_event(key_event(KeyCode::Char('7')));
+        mapper.map_event(key_event(KeyCode::Char('g')));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('g'))),
+            UiEvent::Command(Command::GotoPage { page: 6 })
+        ));
     }
 
     #[test]
@@ -368,6 +1403,77 @@ mod tests {
         assert_eq!(mapper.mode(), InputMode::Normal);
     }
 
+    #[test]
+    fn event_mapper_ctrl_v_selects_blocks() {
+        let mut mapper = EventMapper::new();
+        let ctrl_v = || key_event_with_modifiers(KeyCode::Char('v'), KeyModifiers::CONTROL);
+        match mapper.map_event(ctrl_v()) {
+            UiEvent::Commands(commands) => assert!(matches!(
+                commands.as_slice(),
+                [Command::EnterVisualMode, Command::ToggleBlockSelection]
+            )),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(mapper.mode(), InputMode::Visual);
+        assert!(matches!(
+            mapper.map_event(ctrl_v()),
+            UiEvent::VisualToggleBlock
+        ));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Esc)),
+            UiEvent::VisualClearSelection
+        ));
+    }
+
+    #[test]
+    fn event_mapper_visual_text_objects() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Visual);
+        let mut keys = |keys: &str| {
+            keys.chars()
+                .map(|ch| mapper.map_event(key_event(KeyCode::Char(ch))))
+                .last()
+                .unwrap()
+        };
+        assert!(matches!(
+            keys("2iw"),
+            UiEvent::VisualMotion {
+                motion: VisualMotion::InnerWord,
+                count: 2
+            }
+        ));
+        assert!(matches!(
+            keys("aw"),
+            UiEvent::VisualMotion {
+                motion: VisualMotion::AWord,
+                ..
+            }
+        ));
+        assert!(matches!(
+            keys("ip"),
+            UiEvent::VisualMotion {
+                motion: VisualMotion::InnerParagraph,
+                ..
+            }
+        ));
+        assert!(matches!(keys("ap"), UiEvent::None));
+        assert!(matches!(
+            keys("w"),
+            UiEvent::VisualMotion {
+                motion: VisualMotion::WordForward,
+                ..
+            }
+        ));
+        assert!(matches!(
+            keys(")"),
+            UiEvent::VisualMotion {
+                motion: VisualMotion::SentenceForward,
+                ..
+            }
+        ));
+        assert!(mapper.visual_selecting);
+    }
+
     #[test]
     fn event_mapper_visual_y_exits_and_emits_yank() {
         let mut mapper = EventMapper::new();
@@ -379,6 +1485,60 @@ mod tests {
         assert_eq!(mapper.mode(), InputMode::Normal);
     }
 
+    #[test]
+    fn event_mapper_visual_z_exits_and_zooms() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Visual);
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('z'))),
+            UiEvent::VisualZoom
+        ));
+        assert_eq!(mapper.mode(), InputMode::Normal);
+    }
+
+    #[test]
+    fn event_mapper_visual_plus_minus_zoom_without_leaving() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Visual);
+        mapper.map_event(key_event(KeyCode::Char('2')));
+        match mapper.map_event(key_event(KeyCode::Char('+'))) {
+            UiEvent::Command(Command::ScaleBy { factor }) => {
+                assert!((factor - 1.1f32.powi(2)).abs() < 1e-5)
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('-'))),
+            UiEvent::Command(Command::ScaleBy { .. })
+        ));
+        assert_eq!(mapper.mode(), InputMode::Visual);
+    }
+
+    #[test]
+    fn event_mapper_visual_gy_yanks_quotes() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Visual);
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('g'))),
+            UiEvent::None
+        ));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('y'))),
+            UiEvent::VisualYankMarkdown
+        ));
+        assert_eq!(mapper.mode(), InputMode::Normal);
+
+        mapper.set_mode(InputMode::Visual);
+        mapper.map_event(key_event(KeyCode::Char('g')));
+        assert!(matches!(
+            mapper.map_event(key_event_with_modifiers(
+                KeyCode::Char('Y'),
+                KeyModifiers::SHIFT
+            )),
+            UiEvent::VisualYankLatex
+        ));
+    }
+
     #[test]
     fn event_mapper_second_v_starts_selection() {
         let mut mapper = EventMapper::new();
@@ -429,7 +1589,7 @@ mod tests {
             KeyModifiers::CONTROL,
         )) {
             UiEvent::Command(Command::AdjustViewport { delta_x, delta_y }) => {
-                assert!((delta_x - EventMapper::PAN_STEP).abs() < f32::EPSILON);
+                assert!((delta_x - first_pan_step()).abs() < f32::EPSILON);
                 assert_eq!(delta_y, 0.0);
             }
             other => panic!("unexpected event: {:?}", other),
@@ -438,7 +1598,7 @@ mod tests {
         match mapper.map_event(key_event_with_modifiers(KeyCode::Up, KeyModifiers::CONTROL)) {
             UiEvent::Command(Command::AdjustViewport { delta_x, delta_y }) => {
                 assert_eq!(delta_x, 0.0);
-                assert!((delta_y + EventMapper::PAN_STEP).abs() < f32::EPSILON);
+                assert!((delta_y + first_pan_step()).abs() < f32::EPSILON);
             }
             other => panic!("unexpected event: {:?}", other),
         }
@@ -451,6 +1611,54 @@ mod tests {
             UiEvent::Command(Command::ResetScale) => {}
             other => panic!("unexpected event: {:?}", other),
         }
+
+        mapper.map_event(key_event(KeyCode::Char('1')));
+        mapper.map_event(key_event(KeyCode::Char('5')));
+        mapper.map_event(key_event(KeyCode::Char('0')));
+        match mapper.map_event(key_event(KeyCode::Char('='))) {
+            UiEvent::Command(Command::SetScale { scale }) => assert_eq!(scale, 1.5),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn event_mapper_multiplies_zoom_steps_by_count() {
+        let mut mapper = EventMapper::new();
+        mapper.map_event(key_event(KeyCode::Char('3')));
+        match mapper.map_event(key_event(KeyCode::Char('+'))) {
+            UiEvent::Command(Command::ScaleBy { factor }) => {
+                assert!((factor - 1.1f32.powi(3)).abs() < 1e-5)
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match mapper.map_event(key_event(KeyCode::Char('-'))) {
+            UiEvent::Command(Command::ScaleBy { factor }) => {
+                assert!((factor - 0.9).abs() < 1e-5)
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(mapper.pending_input().is_none());
+    }
+
+    #[test]
+    fn event_mapper_uses_configured_step_sizes() {
+        let mut mapper = EventMapper::new();
+        mapper.set_step_sizes(StepSizes {
+            pan: 0.05,
+            zoom: 0.25,
+        });
+        match mapper.map_event(key_event(KeyCode::Char('h'))) {
+            UiEvent::Command(Command::AdjustViewport { delta_x, .. }) => {
+                assert!((delta_x + 0.05 * PAN_START_FRACTION).abs() < f32::EPSILON)
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match mapper.map_event(key_event(KeyCode::Char('-'))) {
+            UiEvent::Command(Command::ScaleBy { factor }) => {
+                assert!((factor - 0.75).abs() < 1e-5)
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
     }
 
     #[test]
@@ -459,7 +1667,7 @@ mod tests {
 
         match mapper.map_event(key_event(KeyCode::Char('h'))) {
             UiEvent::Command(Command::AdjustViewport { delta_x, delta_y }) => {
-                assert!((delta_x + EventMapper::PAN_STEP).abs() < f32::EPSILON);
+                assert!((delta_x + first_pan_step()).abs() < f32::EPSILON);
                 assert_eq!(delta_y, 0.0);
             }
             other => panic!("unexpected event: {:?}", other),
@@ -471,12 +1679,65 @@ mod tests {
         )) {
             UiEvent::Command(Command::AdjustViewport { delta_x, delta_y }) => {
                 assert_eq!(delta_x, 0.0);
-                assert!((delta_y - EventMapper::PAN_STEP).abs() < f32::EPSILON);
+                assert!((delta_y - first_pan_step()).abs() < f32::EPSILON);
             }
             other => panic!("unexpected event: {:?}", other),
         }
     }
 
+    fn first_pan_step() -> f32 {
+        StepSizes::default().pan * PAN_START_FRACTION
+    }
+
+    #[test]
+    fn held_pan_keys_speed_up_to_the_full_step() {
+        let clock = Arc::new(ManualClock::new());
+        let mut mapper = EventMapper::new();
+        mapper.set_clock(clock.clone());
+        let pan_right = |mapper: &mut EventMapper| match mapper.map_event(key_event_with_modifiers(
+            KeyCode::Char('L'),
+            KeyModifiers::SHIFT,
+        )) {
+            UiEvent::Command(Command::AdjustViewport { delta_x, .. }) => delta_x,
+            other => panic!("unexpected event: {:?}", other),
+        };
+        let step = StepSizes::default().pan;
+
+        let deltas: Vec<f32> = (0..6)
+            .map(|_| {
+                clock.advance(Duration::from_millis(30));
+                pan_right(&mut mapper)
+            })
+            .collect();
+        assert!((deltas[0] - step * PAN_START_FRACTION).abs() < 1e-6);
+        assert!(deltas.windows(2).all(|pair| pair[1] >= pair[0]));
+        assert!((deltas[5] - step).abs() < 1e-6);
+
+        // A pause starts over with a small step.
+        clock.advance(PAN_REPEAT_WINDOW * 2);
+        assert!((pan_right(&mut mapper) - step * PAN_START_FRACTION).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ctrl_d_u_f_b_scroll_by_screens() {
+        let mut mapper = EventMapper::new();
+        let ctrl = |c| key_event_with_modifiers(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let screens = |mapper: &mut EventMapper, event| match mapper.map_event(event) {
+            UiEvent::Command(Command::ScrollScreens { screens }) => screens,
+            other => panic!("unexpected event: {:?}", other),
+        };
+        assert_eq!(screens(&mut mapper, ctrl('d')), 0.5);
+        assert_eq!(screens(&mut mapper, ctrl('u')), -0.5);
+        assert_eq!(screens(&mut mapper, ctrl('f')), 1.0);
+        assert_eq!(screens(&mut mapper, ctrl('b')), -1.0);
+
+        mapper.map_event(key_event(KeyCode::Char('2')));
+        match mapper.map_event(ctrl('f')) {
+            UiEvent::Command(Command::ScrollScreens { screens }) => assert_eq!(screens, 2.0),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
     #[test]
     fn event_mapper_numeric_prefix_scales_pan_distance() {
         let mut mapper = EventMapper::new();
@@ -489,7 +1750,7 @@ mod tests {
             KeyModifiers::SHIFT,
         )) {
             UiEvent::Command(Command::AdjustViewport { delta_x, delta_y }) => {
-                assert!((delta_x - 3.0 * EventMapper::PAN_STEP).abs() < f32::EPSILON);
+                assert!((delta_x - 3.0 * StepSizes::default().pan).abs() < f32::EPSILON);
                 assert_eq!(delta_y, 0.0);
             }
             other => panic!("unexpected event: {:?}", other),
@@ -505,6 +1766,66 @@ mod tests {
         }
     }
 
+    #[test]
+    fn event_mapper_maps_ctrl_e_and_ctrl_y_to_line_scrolling() {
+        let mut mapper = EventMapper::new();
+        match mapper.map_event(key_event_with_modifiers(
+            KeyCode::Char('e'),
+            KeyModifiers::CONTROL,
+        )) {
+            UiEvent::Command(Command::ScrollLines { lines }) => assert_eq!(lines, 1),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        mapper.map_event(key_event(KeyCode::Char('3')));
+        match mapper.map_event(key_event_with_modifiers(
+            KeyCode::Char('y'),
+            KeyModifiers::CONTROL,
+        )) {
+            UiEvent::Command(Command::ScrollLines { lines }) => assert_eq!(lines, -3),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn event_mapper_ctrl_w_switches_panes() {
+        let mut mapper = EventMapper::new();
+        let ctrl_w = || key_event_with_modifiers(KeyCode::Char('w'), KeyModifiers::CONTROL);
+        assert!(matches!(mapper.map_event(ctrl_w()), UiEvent::None));
+        assert_eq!(mapper.pending_input().as_deref(), Some("^W"));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('w'))),
+            UiEvent::FocusOtherPane
+        ));
+        mapper.map_event(ctrl_w());
+        assert!(matches!(
+            mapper.map_event(ctrl_w()),
+            UiEvent::FocusOtherPane
+        ));
+        mapper.map_event(ctrl_w());
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('x'))),
+            UiEvent::None
+        ));
+        assert!(mapper.pending_input().is_none());
+    }
+
+    #[test]
+    fn ctrl_z_suspends_in_any_mode() {
+        let mut mapper = EventMapper::new();
+        let ctrl_z = || key_event_with_modifiers(KeyCode::Char('z'), KeyModifiers::CONTROL);
+        mapper.map_event(key_event(KeyCode::Char('4')));
+        assert!(matches!(mapper.map_event(ctrl_z()), UiEvent::Suspend));
+        assert!(mapper.pending_input().is_none());
+
+        mapper.set_mode(InputMode::Command);
+        assert!(matches!(mapper.map_event(ctrl_z()), UiEvent::Suspend));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('z'))),
+            UiEvent::CommandModeChanged { .. }
+        ));
+    }
+
     #[test]
     fn event_mapper_maps_ctrl_o_to_jump_backward() {
         let mut mapper = EventMapper::new();
@@ -570,6 +1891,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn event_mapper_question_mark_starts_page_search() {
+        let mut mapper = EventMapper::new();
+
+        assert!(matches!(
+            mapper.map_event(key_event_with_modifiers(
+                KeyCode::Char('?'),
+                KeyModifiers::SHIFT
+            )),
+            UiEvent::BeginPageSearch
+        ));
+        assert_eq!(mapper.mode(), InputMode::Search);
+        mapper.map_event(key_event(KeyCode::Char('x')));
+        assert_eq!(mapper.pending_input().as_deref(), Some("?x"));
+        mapper.map_event(key_event(KeyCode::Esc));
+
+        mapper.map_event(key_event(KeyCode::Char('/')));
+        assert_eq!(mapper.pending_input().as_deref(), Some("/"));
+    }
+
     #[test]
     fn event_mapper_slash_enters_search_mode_and_collects_input() {
         let mut mapper = EventMapper::new();
@@ -597,67 +1938,175 @@ mod tests {
             other => panic!("unexpected event: {:?}", other),
         }
 
-        match mapper.map_event(key_event(KeyCode::Enter)) {
-            UiEvent::SearchSubmit { ref query } => assert_eq!(query, "g"),
+        match mapper.map_event(key_event(KeyCode::Enter)) {
+            UiEvent::SearchSubmit { ref query } => assert_eq!(query, "g"),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(mapper.pending_input().is_none());
+    }
+
+    #[test]
+    fn event_mapper_l_enters_link_mode() {
+        let mut mapper = EventMapper::new();
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('l'))),
+            UiEvent::Command(Command::EnterLinkMode)
+        ));
+        assert_eq!(mapper.mode(), InputMode::Link);
+        assert_eq!(mapper.pending_input().as_deref(), Some("link"));
+    }
+
+    #[test]
+    fn event_mapper_link_mode_accepts_prefix_for_navigation() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Link);
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('3'))),
+            UiEvent::None
+        ));
+        match mapper.map_event(key_event(KeyCode::Char('n'))) {
+            UiEvent::Command(Command::LinkNext { count }) => assert_eq!(count, 3),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn event_mapper_link_mode_supports_previous_navigation() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Link);
+        match mapper.map_event(key_event(KeyCode::Char('N'))) {
+            UiEvent::Command(Command::LinkPrev { count }) => assert_eq!(count, 1),
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn event_mapper_link_mode_follows_active_link() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Link);
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('g'))),
+            UiEvent::Command(Command::ActivateLink)
+        ));
+    }
+
+    #[test]
+    fn event_mapper_link_mode_toggles_filters() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Link);
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('u'))),
+            UiEvent::Command(Command::ToggleLinkFilter {
+                filter: LinkFilter::Uri
+            })
+        ));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('f'))),
+            UiEvent::Command(Command::ToggleLinkFilter {
+                filter: LinkFilter::File
+            })
+        ));
+        assert_eq!(mapper.mode(), InputMode::Link);
+    }
+
+    #[test]
+    fn event_mapper_link_mode_exit_on_escape() {
+        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Link);
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Esc)),
+            UiEvent::Command(Command::LeaveLinkMode)
+        ));
+        assert_eq!(mapper.mode(), InputMode::Normal);
+    }
+
+    #[test]
+    fn event_mapper_gr_shows_counted_citation() {
+        let mut mapper = EventMapper::new();
+        mapper.map_event(key_event(KeyCode::Char('2')));
+        mapper.map_event(key_event(KeyCode::Char('g')));
+        match mapper.map_event(key_event(KeyCode::Char('r'))) {
+            UiEvent::ShowCitation { count } => assert_eq!(count, 2),
             other => panic!("unexpected event: {:?}", other),
         }
         assert!(mapper.pending_input().is_none());
-    }
 
-    #[test]
-    fn event_mapper_l_enters_link_mode() {
-        let mut mapper = EventMapper::new();
+        mapper.set_mode(InputMode::Link);
         assert!(matches!(
-            mapper.map_event(key_event(KeyCode::Char('l'))),
-            UiEvent::Command(Command::EnterLinkMode)
+            mapper.map_event(key_event(KeyCode::Char('r'))),
+            UiEvent::ShowCitation { count: 1 }
         ));
         assert_eq!(mapper.mode(), InputMode::Link);
-        assert_eq!(mapper.pending_input().as_deref(), Some("link"));
     }
 
     #[test]
-    fn event_mapper_link_mode_accepts_prefix_for_navigation() {
+    fn event_mapper_form_mode_navigates_and_activates() {
         let mut mapper = EventMapper::new();
-        mapper.set_mode(InputMode::Link);
         assert!(matches!(
-            mapper.map_event(key_event(KeyCode::Char('3'))),
-            UiEvent::None
+            mapper.map_event(key_event_with_modifiers(
+                KeyCode::Char('F'),
+                KeyModifiers::SHIFT
+            )),
+            UiEvent::Command(Command::EnterFormMode)
         ));
-        match mapper.map_event(key_event(KeyCode::Char('n'))) {
-            UiEvent::Command(Command::LinkNext { count }) => assert_eq!(count, 3),
+        assert_eq!(mapper.pending_input().as_deref(), Some("form"));
+        mapper.map_event(key_event(KeyCode::Char('2')));
+        match mapper.map_event(key_event(KeyCode::Tab)) {
+            UiEvent::Command(Command::FormNext { count }) => assert_eq!(count, 2),
             other => panic!("unexpected event: {:?}", other),
         }
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Enter)),
+            UiEvent::FormActivate
+        ));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Esc)),
+            UiEvent::Command(Command::LeaveFormMode)
+        ));
+        assert_eq!(mapper.mode(), InputMode::Normal);
     }
 
     #[test]
-    fn event_mapper_link_mode_supports_previous_navigation() {
+    fn event_mapper_form_input_edits_prefilled_value() {
         let mut mapper = EventMapper::new();
-        mapper.set_mode(InputMode::Link);
-        match mapper.map_event(key_event(KeyCode::Char('N'))) {
-            UiEvent::Command(Command::LinkPrev { count }) => assert_eq!(count, 1),
+        mapper.set_mode(InputMode::Form);
+        mapper.begin_form_input("Ad");
+        assert_eq!(mapper.pending_input().as_deref(), Some("edit: Ad"));
+        mapper.map_event(key_event(KeyCode::Char('a')));
+        match mapper.map_event(key_event(KeyCode::Enter)) {
+            UiEvent::FormInputSubmit { value } => assert_eq!(value, "Ada"),
             other => panic!("unexpected event: {:?}", other),
         }
-    }
+        assert_eq!(mapper.mode(), InputMode::Form);
 
-    #[test]
-    fn event_mapper_link_mode_follows_active_link() {
-        let mut mapper = EventMapper::new();
-        mapper.set_mode(InputMode::Link);
+        mapper.begin_form_input("x");
         assert!(matches!(
-            mapper.map_event(key_event(KeyCode::Char('g'))),
-            UiEvent::Command(Command::ActivateLink)
+            mapper.map_event(key_event(KeyCode::Esc)),
+            UiEvent::FormInputCancel
         ));
+        assert_eq!(mapper.mode(), InputMode::Form);
+        assert_eq!(mapper.pending_input().as_deref(), Some("form"));
     }
 
     #[test]
-    fn event_mapper_link_mode_exit_on_escape() {
+    fn event_mapper_toc_mode_maps_yank_keys() {
         let mut mapper = EventMapper::new();
-        mapper.set_mode(InputMode::Link);
+        mapper.set_mode(InputMode::Toc);
         assert!(matches!(
-            mapper.map_event(key_event(KeyCode::Esc)),
-            UiEvent::Command(Command::LeaveLinkMode)
+            mapper.map_event(key_event(KeyCode::Char('y'))),
+            UiEvent::TocYank
+        ));
+        assert!(matches!(
+            mapper.map_event(key_event_with_modifiers(
+                KeyCode::Char('Y'),
+                KeyModifiers::SHIFT
+            )),
+            UiEvent::TocYankAll
+        ));
+        assert!(matches!(
+            mapper.map_event(key_event(KeyCode::Char('d'))),
+            UiEvent::TocDeleteSelection
         ));
-        assert_eq!(mapper.mode(), InputMode::Normal);
     }
 
     #[test]
@@ -796,32 +2245,86 @@ pub enum UiEvent {
     Commands(Vec<Command>),
     OpenTableOfContents,
     CloseOverlay,
-    TocMoveSelection { delta: isize },
+    TocMoveSelection {
+        delta: isize,
+    },
     TocBeginSearch,
-    TocSearchQueryChanged { query: String },
-    TocSearchSubmit { query: String },
+    TocSearchQueryChanged {
+        query: String,
+    },
+    TocSearchSubmit {
+        query: String,
+    },
     TocSearchCancel,
-    TocSearchNext { count: usize },
-    TocSearchPrev { count: usize },
+    TocSearchNext {
+        count: usize,
+    },
+    TocSearchPrev {
+        count: usize,
+    },
     TocGotoStart,
     TocGotoEnd,
     TocActivateSelection,
+    TocYank,
+    TocYankAll,
+    TocDeleteSelection,
     BeginSearch,
-    SearchQueryChanged { query: String },
-    SearchSubmit { query: String },
+    /// Like [`UiEvent::BeginSearch`], but the search covers the current page only.
+    BeginPageSearch,
+    SearchQueryChanged {
+        query: String,
+    },
+    SearchSubmit {
+        query: String,
+    },
     SearchCancel,
-    CommandModeBegin { buffer: String, cursor: usize },
-    CommandModeChanged { buffer: String, cursor: usize },
-    CommandModeSubmit { command: String },
+    CommandModeBegin {
+        buffer: String,
+        cursor: usize,
+    },
+    CommandModeChanged {
+        buffer: String,
+        cursor: usize,
+    },
+    CommandModeSubmit {
+        command: String,
+    },
     CommandModeCancel,
     BeginVisualMode,
-    VisualMotion { motion: VisualMotion, count: usize },
+    VisualMotion {
+        motion: VisualMotion,
+        count: usize,
+    },
     VisualStartSelection,
+    /// `Ctrl-v`: start a block selection or switch the selection between
+    /// block and character-wise.
+    VisualToggleBlock,
     VisualClearSelection,
     VisualYank,
+    /// `gy`: copy the selection as a Markdown quote.
+    VisualYankMarkdown,
+    /// `gY`: copy the selection as a LaTeX quote.
+    VisualYankLatex,
+    /// `z`: zoom in so the selection fills the view.
+    VisualZoom,
     VisualCancel,
     VisualReselectLast,
     VisualSwapCursor,
+    ShowCitation {
+        count: usize,
+    },
+    FormActivate,
+    FormInputChanged {
+        value: String,
+    },
+    FormInputSubmit {
+        value: String,
+    },
+    FormInputCancel,
+    /// `Ctrl-w w`: move the focus to the other pane of a split.
+    FocusOtherPane,
+    /// `Ctrl-z`: stop and hand the terminal back to the shell.
+    Suspend,
     Quit,
     None,
 }
@@ -840,25 +2343,27 @@ pub enum VisualMotion {
     DocumentEnd,
     PageForward,
     PageBackward,
+    SentenceForward,
+    SentenceBackward,
+    InnerWord,
+    AWord,
+    InnerParagraph,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum InputMode {
+    #[default]
     Normal,
     Toc,
     TocSearch,
     Search,
     Link,
+    Form,
+    FormInput,
     Command,
     Visual,
 }
 
-impl Default for InputMode {
-    fn default() -> Self {
-        InputMode::Normal
-    }
-}
-
 #[derive(Debug, Default)]
 pub struct EventMapper {
     pending_count: Option<usize>,
@@ -866,6 +2371,7 @@ pub struct EventMapper {
     char_stack: String,
     mode: InputMode,
     search_buffer: String,
+    page_search: bool,
     toc_search_buffer: String,
     command_buffer: String,
     command_cursor: usize,
@@ -873,16 +2379,183 @@ pub struct EventMapper {
     command_history_index: Option<usize>,
     command_draft: String,
     visual_selecting: bool,
+    form_buffer: String,
+    steps: StepSizes,
+    pan_repeat: Option<PanRepeat>,
+    clock: MapperClock,
+}
+
+/// A pan key pressed without a count starts with this fraction of the pan
+/// step, so a single press nudges the view.
+const PAN_START_FRACTION: f32 = 0.25;
+/// Each repeat of the same pan key within [`PAN_REPEAT_WINDOW`] moves this
+/// much further than the last, up to the whole step.
+const PAN_ACCELERATION: f32 = 1.5;
+/// Long enough to span a terminal's key repeat delay.
+const PAN_REPEAT_WINDOW: Duration = Duration::from_millis(600);
+
+/// The last uncounted pan, which a quick repeat speeds up from.
+#[derive(Debug, Clone, Copy)]
+struct PanRepeat {
+    direction: (i8, i8),
+    at: Instant,
+    /// Fraction of the pan step it moved.
+    speed: f32,
+}
+
+/// Times key repeats; wrapped so the mapper stays `Debug` and `Default`.
+#[derive(Clone)]
+struct MapperClock(SharedClock);
+
+impl Default for MapperClock {
+    fn default() -> Self {
+        Self(system_clock())
+    }
+}
+
+impl std::fmt::Debug for MapperClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MapperClock")
+    }
+}
+
+/// A documented key binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyBinding {
+    /// Mode in which the keys apply, e.g. `normal` or `visual`.
+    pub mode: &'static str,
+    pub keys: &'static str,
+    pub action: &'static str,
+}
+
+const fn bind(mode: &'static str, keys: &'static str, action: &'static str) -> KeyBinding {
+    KeyBinding { mode, keys, action }
 }
 
+/// The keys understood by [`EventMapper`], for help output and the man page.
+/// Keep in sync with the `map_event_*` methods.
+pub const KEY_BINDINGS: &[KeyBinding] = &[
+    bind("normal", "j, Down", "Next page (takes a count)"),
+    bind("normal", "k, Up", "Previous page (takes a count)"),
+    bind("normal", "gg", "First page, or page N with a count"),
+    bind("normal", "G, End", "Last page, or page N with a count"),
+    bind("normal", "+, -", "Zoom in or out"),
+    bind("normal", "=", "Reset zoom, or zoom to N% with a count"),
+    bind(
+        "normal",
+        "h, H, L, K, J",
+        "Pan a zoomed page, faster while held",
+    ),
+    bind(
+        "normal",
+        "Ctrl-Arrows",
+        "Pan a zoomed page, faster while held",
+    ),
+    bind(
+        "normal",
+        "Ctrl-d, Ctrl-u",
+        "Scroll half a screen, on to the next page at the end",
+    ),
+    bind(
+        "normal",
+        "Ctrl-f, Ctrl-b",
+        "Scroll a screen, on to the next page at the end",
+    ),
+    bind(
+        "normal",
+        "Ctrl-e, Ctrl-y",
+        "Scroll a zoomed page by one line",
+    ),
+    bind("normal", "s", "Toggle fit to page or width"),
+    bind("normal", "d", "Toggle dark mode"),
+    bind("normal", "R", "Toggle text reflow"),
+    bind("normal", "/", "Search the document"),
+    bind("normal", "?", "Search the current page"),
+    bind("normal", "n, N", "Next or previous search match"),
+    bind(
+        "normal",
+        "m<char>",
+        "Set a mark (uppercase marks are global)",
+    ),
+    bind("normal", "'<char>", "Jump to a mark"),
+    bind("normal", "Ctrl-o", "Jump back"),
+    bind("normal", "Ctrl-w w", "Focus the other pane of a split"),
+    bind("normal", "Ctrl-i, Tab", "Jump forward"),
+    bind("normal", "t", "Table of contents"),
+    bind("normal", "l", "Link hints"),
+    bind("normal", "gr", "Show the cited reference"),
+    bind("normal", "F", "Fill form fields"),
+    bind(
+        "normal",
+        "v",
+        "Visual mode; gv reselects the last selection",
+    ),
+    bind("normal", "Ctrl-v", "Visual mode with a block selection"),
+    bind("normal", ":", "Command prompt"),
+    bind("normal", "q", "Quit"),
+    bind("normal", "Ctrl-z", "Suspend to the shell (any mode)"),
+    bind("visual", "h, j, k, l", "Move the cursor"),
+    bind("visual", "w, b", "Next or previous word"),
+    bind("visual", "), (", "Next sentence or start of the sentence"),
+    bind("visual", "iw, aw", "Select the word (with its blanks)"),
+    bind("visual", "ip", "Select the paragraph"),
+    bind("visual", "0, ^, $", "Start or end of the line"),
+    bind("visual", "gg, G", "Start or end of the document"),
+    bind("visual", "Ctrl-f, Ctrl-b", "Page forward or back"),
+    bind("visual", "v", "Start selecting"),
+    bind(
+        "visual",
+        "Ctrl-v",
+        "Select a rectangle, e.g. one column of a page",
+    ),
+    bind("visual", "o", "Move to the other end of the selection"),
+    bind("visual", "y", "Copy the selection"),
+    bind("visual", "gy", "Copy the selection as a Markdown quote"),
+    bind("visual", "gY", "Copy the selection as a LaTeX quote"),
+    bind("visual", "z", "Zoom in on the selection"),
+    bind("visual", "+, -", "Zoom around the cursor (takes a count)"),
+    bind("visual", "Esc", "Clear the selection or leave visual mode"),
+    bind("link", "n, N", "Next or previous link"),
+    bind("link", "g", "Follow the link"),
+    bind("link", "r", "Show the cited reference"),
+    bind(
+        "link",
+        "a, u, i, f",
+        "Show all, external, internal or file links",
+    ),
+    bind("link", "Esc", "Leave link mode"),
+    bind("form", "n, j, Tab", "Next field"),
+    bind("form", "N, k, Shift-Tab", "Previous field"),
+    bind("form", "Enter, Space", "Edit or toggle the field"),
+    bind("form", "Esc", "Leave form mode"),
+    bind("list", "j, k", "Move the selection"),
+    bind("list", "g, G", "First or last entry"),
+    bind("list", "/", "Filter or search the list"),
+    bind("list", "Enter", "Open the entry"),
+    bind("list", "y, Y", "Copy the selected or every shown link"),
+    bind("list", "d", "Delete the selected mark"),
+    bind("list", "q, Esc", "Close the list"),
+];
+
 impl EventMapper {
-    const PAN_STEP: f32 = 0.1;
+    /// Enough steps to cross the whole zoom range.
+    const MAX_ZOOM_STEPS: usize = 50;
     const COMMAND_HISTORY_LIMIT: usize = 100;
 
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Pan and zoom distances for the keys that move the view.
+    pub fn set_step_sizes(&mut self, steps: StepSizes) {
+        self.steps = steps;
+    }
+
+    /// The clock held pan keys are timed with.
+    pub fn set_clock(&mut self, clock: SharedClock) {
+        self.clock = MapperClock(clock);
+    }
+
     pub fn set_mode(&mut self, mode: InputMode) {
         if self.mode != mode {
             if matches!(self.mode, InputMode::Search) {
@@ -897,6 +2570,9 @@ impl EventMapper {
             if matches!(self.mode, InputMode::Visual) {
                 self.visual_selecting = false;
             }
+            if matches!(self.mode, InputMode::FormInput) {
+                self.form_buffer.clear();
+            }
             self.reset_count();
             self.reset_char_stack();
             self.mode = mode;
@@ -920,12 +2596,28 @@ impl EventMapper {
     }
 
     pub fn map_event(&mut self, event: Event) -> UiEvent {
+        // Raw mode turns off the terminal's own Ctrl-z, so it works in every
+        // mode here instead.
+        if let Event::Key(KeyEvent {
+            code: KeyCode::Char('z'),
+            modifiers,
+            ..
+        }) = &event
+        {
+            if modifiers.contains(KeyModifiers::CONTROL) {
+                self.reset_count();
+                self.reset_char_stack();
+                return UiEvent::Suspend;
+            }
+        }
         match self.mode {
             InputMode::Normal => self.map_event_normal(event),
             InputMode::Toc => self.map_event_toc(event),
             InputMode::TocSearch => self.map_event_toc_search(event),
             InputMode::Search => self.map_event_search(event),
             InputMode::Link => self.map_event_link(event),
+            InputMode::Form => self.map_event_form(event),
+            InputMode::FormInput => self.map_event_form_input(event),
             InputMode::Command => self.map_event_command(event),
             InputMode::Visual => self.map_event_visual(event),
         }
@@ -942,6 +2634,19 @@ impl EventMapper {
                     }
                     UiEvent::None
                 }
+                (KeyCode::Char(c), _) if self.char_stack.as_str() == "^W" => {
+                    self.reset_count();
+                    self.reset_char_stack();
+                    match c {
+                        'w' | 'W' | 'p' | 'h' | 'j' | 'k' | 'l' => UiEvent::FocusOtherPane,
+                        _ => UiEvent::None,
+                    }
+                }
+                (KeyCode::Char('w'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.reset_char_stack();
+                    self.char_stack.push_str("^W");
+                    UiEvent::None
+                }
                 (KeyCode::Char(c), _) if (self.char_stack.as_str() == "m") => {
                     self.reset_char_stack();
                     UiEvent::Command(Command::PutMark { key: c })
@@ -963,27 +2668,31 @@ impl EventMapper {
                     UiEvent::None
                 }
                 (KeyCode::Char('='), _) => {
-                    self.reset_count();
                     self.reset_char_stack();
-                    UiEvent::Command(Command::ResetScale)
+                    match self.take_explicit_count() {
+                        Some(percent) => UiEvent::Command(Command::SetScale {
+                            scale: percent as f32 / 100.0,
+                        }),
+                        None => UiEvent::Command(Command::ResetScale),
+                    }
                 }
                 (KeyCode::Left, modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.pan(-Self::PAN_STEP, 0.0)
+                    self.pan(-self.steps.pan, 0.0)
                 }
                 (KeyCode::Right, modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.pan(Self::PAN_STEP, 0.0)
+                    self.pan(self.steps.pan, 0.0)
                 }
                 (KeyCode::Up, modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.pan(0.0, -Self::PAN_STEP)
+                    self.pan(0.0, -self.steps.pan)
                 }
                 (KeyCode::Down, modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.pan(0.0, Self::PAN_STEP)
+                    self.pan(0.0, self.steps.pan)
                 }
                 (KeyCode::Char('H'), KeyModifiers::SHIFT)
-                | (KeyCode::Char('h'), KeyModifiers::NONE) => self.pan(-Self::PAN_STEP, 0.0),
-                (KeyCode::Char('L'), KeyModifiers::SHIFT) => self.pan(Self::PAN_STEP, 0.0),
-                (KeyCode::Char('K'), KeyModifiers::SHIFT) => self.pan(0.0, -Self::PAN_STEP),
-                (KeyCode::Char('J'), KeyModifiers::SHIFT) => self.pan(0.0, Self::PAN_STEP),
+                | (KeyCode::Char('h'), KeyModifiers::NONE) => self.pan(-self.steps.pan, 0.0),
+                (KeyCode::Char('L'), KeyModifiers::SHIFT) => self.pan(self.steps.pan, 0.0),
+                (KeyCode::Char('K'), KeyModifiers::SHIFT) => self.pan(0.0, -self.steps.pan),
+                (KeyCode::Char('J'), KeyModifiers::SHIFT) => self.pan(0.0, self.steps.pan),
                 (KeyCode::Char('j'), KeyModifiers::NONE) | (KeyCode::Down, KeyModifiers::NONE) => {
                     let count = self.take_count();
                     UiEvent::Command(Command::NextPage { count })
@@ -993,13 +2702,25 @@ impl EventMapper {
                     UiEvent::Command(Command::PrevPage { count })
                 }
                 (KeyCode::Char('/'), KeyModifiers::NONE) => {
-                    self.start_search();
+                    self.start_search(false);
                     UiEvent::BeginSearch
                 }
+                (KeyCode::Char('?'), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
+                    self.start_search(true);
+                    UiEvent::BeginPageSearch
+                }
                 (KeyCode::Char('l'), KeyModifiers::NONE) => {
                     self.start_link_mode();
                     UiEvent::Command(Command::EnterLinkMode)
                 }
+                (KeyCode::Char('F'), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
+                    self.set_mode(InputMode::Form);
+                    UiEvent::Command(Command::EnterFormMode)
+                }
+                (KeyCode::Char('R'), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
+                    self.reset_count();
+                    UiEvent::Command(Command::ToggleReflow)
+                }
                 (KeyCode::Char(':'), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
                     self.set_mode(InputMode::Command);
                     let (buffer, cursor) = self.command_state_payload();
@@ -1019,6 +2740,24 @@ impl EventMapper {
                     self.reset_count();
                     UiEvent::Quit
                 }
+                (KeyCode::Char('e'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.scroll_lines(1)
+                }
+                (KeyCode::Char('y'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.scroll_lines(-1)
+                }
+                (KeyCode::Char('d'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.scroll_screens(0.5)
+                }
+                (KeyCode::Char('u'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.scroll_screens(-0.5)
+                }
+                (KeyCode::Char('f'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.scroll_screens(1.0)
+                }
+                (KeyCode::Char('b'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.scroll_screens(-1.0)
+                }
                 (KeyCode::Char('o'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
                     self.reset_count();
                     self.reset_char_stack();
@@ -1036,33 +2775,49 @@ impl EventMapper {
                     self.reset_char_stack();
                     UiEvent::Command(Command::JumpForward)
                 }
-                (KeyCode::Char('+'), _) => {
-                    self.reset_count();
-                    UiEvent::Command(Command::ScaleBy { factor: 1.1 })
-                }
-                (KeyCode::Char('-'), _) => {
-                    self.reset_count();
-                    UiEvent::Command(Command::ScaleBy { factor: 0.9 })
-                }
+                (KeyCode::Char('+'), _) => self.scale_by(1.0 + self.steps.zoom),
+                (KeyCode::Char('-'), _) => self.scale_by(1.0 - self.steps.zoom),
                 (KeyCode::Char('d'), _) => {
-                    self.reset_count();
-                    UiEvent::Command(Command::ToggleDarkMode)
+                    self.reset_char_stack();
+                    match self.take_explicit_count() {
+                        Some(count) => {
+                            UiEvent::Command(Command::SetDarkMode { enabled: count > 0 })
+                        }
+                        None => UiEvent::Command(Command::ToggleDarkMode),
+                    }
+                }
+                (KeyCode::Char('s'), KeyModifiers::NONE) => {
+                    self.reset_char_stack();
+                    match self.take_explicit_count() {
+                        Some(0) => UiEvent::Command(Command::SetFitMode {
+                            mode: FitMode::Page,
+                        }),
+                        Some(_) => UiEvent::Command(Command::SetFitMode {
+                            mode: FitMode::Width,
+                        }),
+                        None => UiEvent::Command(Command::ToggleFitMode),
+                    }
                 }
                 (KeyCode::Char('g'), KeyModifiers::NONE) => {
                     if self.char_stack.as_str() == "g" {
-                        self.reset_count();
                         self.reset_char_stack();
-                        UiEvent::Command(Command::GotoPage { page: 0 })
+                        // `{count}gg` goes to page `count`, like vim's lines.
+                        let page = self.take_count() - 1;
+                        UiEvent::Command(Command::GotoPage { page })
+                    } else if self.char_stack.is_empty() {
+                        self.push_char('g');
+                        UiEvent::None
                     } else {
                         self.reset_count();
-                        if self.char_stack.is_empty() {
-                            self.push_char('g');
-                        } else {
-                            self.reset_char_stack();
-                        }
+                        self.reset_char_stack();
                         UiEvent::None
                     }
                 }
+                (KeyCode::Char('r'), KeyModifiers::NONE) if self.char_stack.as_str() == "g" => {
+                    self.reset_char_stack();
+                    let count = self.take_count();
+                    UiEvent::ShowCitation { count }
+                }
                 (KeyCode::Char('v'), KeyModifiers::NONE) => {
                     self.reset_count();
                     if self.char_stack.as_str() == "g" {
@@ -1077,9 +2832,23 @@ impl EventMapper {
                         UiEvent::BeginVisualMode
                     }
                 }
-                (KeyCode::Char('G'), KeyModifiers::SHIFT) | (KeyCode::End, _) => {
+                (KeyCode::Char('v'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
                     self.reset_count();
-                    UiEvent::Command(Command::GotoPage { page: usize::MAX })
+                    self.reset_char_stack();
+                    self.set_mode(InputMode::Visual);
+                    self.visual_selecting = true;
+                    UiEvent::Commands(vec![
+                        Command::EnterVisualMode,
+                        Command::ToggleBlockSelection,
+                    ])
+                }
+                (KeyCode::Char('G'), KeyModifiers::SHIFT) | (KeyCode::End, _) => {
+                    self.reset_char_stack();
+                    let page = match self.take_explicit_count() {
+                        Some(count) if count > 0 => count - 1,
+                        _ => usize::MAX,
+                    };
+                    UiEvent::Command(Command::GotoPage { page })
                 }
                 (KeyCode::Char('t'), _) | (KeyCode::Char('T'), _) => {
                     self.reset_count();
@@ -1152,6 +2921,20 @@ impl EventMapper {
                     self.start_toc_search();
                     UiEvent::TocBeginSearch
                 }
+                (KeyCode::Char('y'), KeyModifiers::NONE) => {
+                    self.reset_count();
+                    UiEvent::TocYank
+                }
+                (KeyCode::Char('Y'), modifiers)
+                    if modifiers.is_empty() || modifiers == KeyModifiers::SHIFT =>
+                {
+                    self.reset_count();
+                    UiEvent::TocYankAll
+                }
+                (KeyCode::Char('d'), KeyModifiers::NONE) => {
+                    self.reset_count();
+                    UiEvent::TocDeleteSelection
+                }
                 _ => UiEvent::None,
             },
             _ => UiEvent::None,
@@ -1255,6 +3038,60 @@ impl EventMapper {
                     self.reset_char_stack();
                     UiEvent::Command(Command::ActivateLink)
                 }
+                (KeyCode::Char('r'), KeyModifiers::NONE) => {
+                    self.reset_count();
+                    UiEvent::ShowCitation { count: 1 }
+                }
+                (KeyCode::Char(key @ ('a' | 'u' | 'i' | 'f')), KeyModifiers::NONE) => {
+                    self.reset_count();
+                    let filter = match key {
+                        'u' => LinkFilter::Uri,
+                        'i' => LinkFilter::Internal,
+                        'f' => LinkFilter::File,
+                        _ => LinkFilter::All,
+                    };
+                    UiEvent::Command(Command::ToggleLinkFilter { filter })
+                }
+                _ => {
+                    self.reset_count();
+                    UiEvent::None
+                }
+            },
+            _ => UiEvent::None,
+        }
+    }
+
+    fn map_event_form(&mut self, event: Event) -> UiEvent {
+        match event {
+            Event::Key(KeyEvent {
+                code, modifiers, ..
+            }) => match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.set_mode(InputMode::Normal);
+                    UiEvent::Command(Command::LeaveFormMode)
+                }
+                (KeyCode::Char(c), KeyModifiers::NONE) if c.is_ascii_digit() => {
+                    if let Some(digit) = c.to_digit(10) {
+                        self.push_digit(digit as usize);
+                    }
+                    UiEvent::None
+                }
+                (KeyCode::Char('n'), KeyModifiers::NONE)
+                | (KeyCode::Char('j'), KeyModifiers::NONE)
+                | (KeyCode::Tab, KeyModifiers::NONE) => {
+                    let count = self.take_count();
+                    UiEvent::Command(Command::FormNext { count })
+                }
+                (KeyCode::Char('N'), KeyModifiers::SHIFT)
+                | (KeyCode::Char('k'), KeyModifiers::NONE)
+                | (KeyCode::BackTab, _) => {
+                    let count = self.take_count();
+                    UiEvent::Command(Command::FormPrev { count })
+                }
+                (KeyCode::Enter, _) | (KeyCode::Char(' '), KeyModifiers::NONE) => {
+                    self.reset_count();
+                    UiEvent::FormActivate
+                }
                 _ => {
                     self.reset_count();
                     UiEvent::None
@@ -1264,6 +3101,45 @@ impl EventMapper {
         }
     }
 
+    fn map_event_form_input(&mut self, event: Event) -> UiEvent {
+        match event {
+            Event::Key(KeyEvent {
+                code, modifiers, ..
+            }) => match (code, modifiers) {
+                (KeyCode::Esc, _) => {
+                    self.set_mode(InputMode::Form);
+                    UiEvent::FormInputCancel
+                }
+                (KeyCode::Enter, _) => {
+                    let value = std::mem::take(&mut self.form_buffer);
+                    self.set_mode(InputMode::Form);
+                    UiEvent::FormInputSubmit { value }
+                }
+                (KeyCode::Backspace, _) => {
+                    self.form_buffer.pop();
+                    UiEvent::FormInputChanged {
+                        value: self.form_buffer.clone(),
+                    }
+                }
+                (KeyCode::Char(c), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
+                    self.form_buffer.push(c);
+                    UiEvent::FormInputChanged {
+                        value: self.form_buffer.clone(),
+                    }
+                }
+                _ => UiEvent::None,
+            },
+            _ => UiEvent::None,
+        }
+    }
+
+    /// Switches to text entry for the selected form field, pre-filled with its
+    /// current value.
+    pub fn begin_form_input(&mut self, value: &str) {
+        self.set_mode(InputMode::FormInput);
+        self.form_buffer = value.to_string();
+    }
+
     fn map_event_command(&mut self, event: Event) -> UiEvent {
         match event {
             Event::Key(KeyEvent {
@@ -1358,6 +3234,20 @@ impl EventMapper {
                         UiEvent::VisualStartSelection
                     }
                 }
+                (KeyCode::Char(c @ ('y' | 'Y')), mods)
+                    if (mods.is_empty() || mods == KeyModifiers::SHIFT)
+                        && self.char_stack.as_str() == "g" =>
+                {
+                    self.set_mode(InputMode::Normal);
+                    self.reset_char_stack();
+                    let _ = self.take_count();
+                    self.visual_selecting = false;
+                    if c == 'y' {
+                        UiEvent::VisualYankMarkdown
+                    } else {
+                        UiEvent::VisualYankLatex
+                    }
+                }
                 (KeyCode::Char('y'), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
                     self.set_mode(InputMode::Normal);
                     self.reset_char_stack();
@@ -1365,11 +3255,24 @@ impl EventMapper {
                     self.visual_selecting = false;
                     UiEvent::VisualYank
                 }
+                (KeyCode::Char('z'), KeyModifiers::NONE) => {
+                    self.set_mode(InputMode::Normal);
+                    self.reset_char_stack();
+                    self.reset_count();
+                    self.visual_selecting = false;
+                    UiEvent::VisualZoom
+                }
                 (KeyCode::Char('o'), KeyModifiers::NONE) => {
                     self.reset_char_stack();
                     self.reset_count();
                     UiEvent::VisualSwapCursor
                 }
+                (KeyCode::Char('v'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.reset_char_stack();
+                    self.reset_count();
+                    self.visual_selecting = true;
+                    UiEvent::VisualToggleBlock
+                }
                 (KeyCode::Char(c), KeyModifiers::NONE)
                     if c.is_ascii_digit() && (c != '0' || self.pending_count.is_some()) =>
                 {
@@ -1399,6 +3302,34 @@ impl EventMapper {
                 (KeyCode::Char('k'), KeyModifiers::NONE)
                 | (KeyCode::Char('K'), KeyModifiers::SHIFT)
                 | (KeyCode::Up, KeyModifiers::NONE) => self.visual_motion(VisualMotion::Up),
+                (KeyCode::Char(c @ ('w' | 'p')), KeyModifiers::NONE)
+                    if matches!(self.char_stack.as_str(), "i" | "a") =>
+                {
+                    let motion = match (self.char_stack.as_str(), c) {
+                        ("i", 'w') => VisualMotion::InnerWord,
+                        ("a", 'w') => VisualMotion::AWord,
+                        ("i", _) => VisualMotion::InnerParagraph,
+                        _ => {
+                            self.reset_char_stack();
+                            self.reset_count();
+                            return UiEvent::None;
+                        }
+                    };
+                    // A text object selects, like `v` would have.
+                    self.visual_selecting = true;
+                    self.visual_motion(motion)
+                }
+                (KeyCode::Char(c @ ('i' | 'a')), KeyModifiers::NONE) => {
+                    self.reset_char_stack();
+                    self.push_char(c);
+                    UiEvent::None
+                }
+                (KeyCode::Char(')'), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
+                    self.visual_motion(VisualMotion::SentenceForward)
+                }
+                (KeyCode::Char('('), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
+                    self.visual_motion(VisualMotion::SentenceBackward)
+                }
                 (KeyCode::Char('w'), mods) if mods.is_empty() || mods == KeyModifiers::SHIFT => {
                     self.visual_motion(VisualMotion::WordForward)
                 }
@@ -1435,6 +3366,8 @@ impl EventMapper {
                 (KeyCode::Char('u'), modifiers) if modifiers.contains(KeyModifiers::CONTROL) => {
                     self.visual_motion(VisualMotion::PageBackward)
                 }
+                (KeyCode::Char('+'), _) => self.scale_by(1.0 + self.steps.zoom),
+                (KeyCode::Char('-'), _) => self.scale_by(1.0 - self.steps.zoom),
                 _ => {
                     self.reset_char_stack();
                     self.reset_count();
@@ -1464,6 +3397,11 @@ impl EventMapper {
         count
     }
 
+    fn take_explicit_count(&mut self) -> Option<usize> {
+        self.pending_digits.clear();
+        self.pending_count.take()
+    }
+
     fn reset_count(&mut self) {
         self.pending_count = None;
         self.pending_digits.clear();
@@ -1483,8 +3421,9 @@ impl EventMapper {
         self.command_draft.clear();
     }
 
-    fn start_search(&mut self) {
+    fn start_search(&mut self, page_only: bool) {
         self.set_mode(InputMode::Search);
+        self.page_search = page_only;
     }
 
     fn start_link_mode(&mut self) {
@@ -1503,8 +3442,16 @@ impl EventMapper {
         }
     }
 
+    /// Pans by `count` whole steps, or without a count by a fraction of a
+    /// step that grows while the key is held.
     fn pan(&mut self, delta_x: f32, delta_y: f32) -> UiEvent {
-        let multiplier = self.take_count() as f32;
+        let multiplier = match self.take_explicit_count() {
+            Some(count) => {
+                self.pan_repeat = None;
+                count as f32
+            }
+            None => self.pan_speed(delta_x, delta_y),
+        };
         self.reset_char_stack();
         UiEvent::Command(Command::AdjustViewport {
             delta_x: delta_x * multiplier,
@@ -1512,6 +3459,51 @@ impl EventMapper {
         })
     }
 
+    fn pan_speed(&mut self, delta_x: f32, delta_y: f32) -> f32 {
+        let now = self.clock.0.now();
+        let direction = (pan_direction(delta_x), pan_direction(delta_y));
+        let speed = match self.pan_repeat {
+            Some(last)
+                if last.direction == direction
+                    && now.saturating_duration_since(last.at) <= PAN_REPEAT_WINDOW =>
+            {
+                (last.speed * PAN_ACCELERATION).min(1.0)
+            }
+            _ => PAN_START_FRACTION,
+        };
+        self.pan_repeat = Some(PanRepeat {
+            direction,
+            at: now,
+            speed,
+        });
+        speed
+    }
+
+    fn scroll_screens(&mut self, screens: f32) -> UiEvent {
+        let count = self.take_count() as f32;
+        self.reset_char_stack();
+        UiEvent::Command(Command::ScrollScreens {
+            screens: screens * count,
+        })
+    }
+
+    /// Zooms by `step` once per pending count (`3+` is `step³`).
+    fn scale_by(&mut self, step: f32) -> UiEvent {
+        let count = self.take_count().min(Self::MAX_ZOOM_STEPS) as i32;
+        self.reset_char_stack();
+        UiEvent::Command(Command::ScaleBy {
+            factor: step.powi(count),
+        })
+    }
+
+    fn scroll_lines(&mut self, direction: isize) -> UiEvent {
+        let count = Self::clamp_count_to_isize(self.take_count());
+        self.reset_char_stack();
+        UiEvent::Command(Command::ScrollLines {
+            lines: direction * count,
+        })
+    }
+
     fn visual_motion(&mut self, motion: VisualMotion) -> UiEvent {
         let count = self.take_count();
         self.reset_char_stack();
@@ -1520,7 +3512,8 @@ impl EventMapper {
 
     pub fn pending_input(&self) -> Option<String> {
         if matches!(self.mode, InputMode::Search) {
-            return Some(format!("/{}", self.search_buffer));
+            let prompt = if self.page_search { '?' } else { '/' };
+            return Some(format!("{}{}", prompt, self.search_buffer));
         }
         if matches!(self.mode, InputMode::TocSearch) {
             return Some(format!("/{}", self.toc_search_buffer));
@@ -1528,8 +3521,15 @@ impl EventMapper {
         if matches!(self.mode, InputMode::Command) {
             return Some(format!(":{}", self.command_buffer));
         }
-        if matches!(self.mode, InputMode::Link) {
-            let mut label = String::from("link");
+        if matches!(self.mode, InputMode::FormInput) {
+            return Some(format!("edit: {}", self.form_buffer));
+        }
+        if matches!(self.mode, InputMode::Link | InputMode::Form) {
+            let mut label = String::from(if matches!(self.mode, InputMode::Link) {
+                "link"
+            } else {
+                "form"
+            });
             if !self.pending_digits.is_empty() {
                 label.push(' ');
                 label.push_str(&self.pending_digits);
@@ -1550,6 +3550,21 @@ impl EventMapper {
         }
     }
 
+    /// Submitted command lines, oldest first.
+    pub fn command_history(&self) -> &[String] {
+        &self.command_history
+    }
+
+    /// Replaces the command history, e.g. with one saved by an earlier run;
+    /// only the newest entries up to the history limit are kept.
+    pub fn set_command_history(&mut self, history: Vec<String>) {
+        self.command_history = Vec::new();
+        for command in history {
+            self.push_command_history(&command);
+        }
+        self.command_history_index = None;
+    }
+
     pub fn push_command_history(&mut self, command: &str) {
         if command.trim().is_empty() {
             return;
@@ -1666,12 +3681,11 @@ impl EventMapper {
     }
 }
 
+fn pan_direction(delta: f32) -> i8 {
+    i8::from(delta > 0.0) - i8::from(delta < 0.0)
+}
+
 #[deprecated(note = "Use EventMapper to retain numeric prefixes between key events")]
 pub fn map_event(event: Event) -> UiEvent {
     EventMapper::new().map_event(event)
 }
-
-pub fn write_status_line<W: Write>(writer: &mut W, label: &str) -> io::Result<()> {
-    write!(writer, "{}", label)?;
-    writer.flush()
-}
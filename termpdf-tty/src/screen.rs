@@ -0,0 +1,362 @@
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor,
+    style::{Attribute, Color, Print, SetAttribute, SetForegroundColor},
+};
+
+use crate::width::{cluster_width, graphemes};
+
+/// How the text of a cell is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub reverse: bool,
+    pub bold: bool,
+}
+
+impl Style {
+    pub const PLAIN: Style = Style {
+        fg: None,
+        reverse: false,
+        bold: false,
+    };
+    pub const REVERSE: Style = Style {
+        fg: None,
+        reverse: true,
+        bold: false,
+    };
+
+    pub fn fg(color: Color) -> Self {
+        Self {
+            fg: Some(color),
+            ..Self::PLAIN
+        }
+    }
+
+    pub fn bold(self) -> Self {
+        Self { bold: true, ..self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cell {
+    /// The grapheme cluster drawn in the cell; empty for a blank.
+    symbol: String,
+    style: Style,
+    /// Covered by the wide character in the cell before.
+    tail: bool,
+}
+
+const BLANK: Cell = Cell {
+    symbol: String::new(),
+    style: Style::PLAIN,
+    tail: false,
+};
+
+/// The text of one frame as a grid of cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Buffer {
+    columns: u16,
+    rows: u16,
+    cells: Vec<Cell>,
+}
+
+impl Buffer {
+    pub fn new(columns: u16, rows: u16) -> Self {
+        Self {
+            columns,
+            rows,
+            cells: vec![BLANK; columns as usize * rows as usize],
+        }
+    }
+
+    pub fn columns(&self) -> u16 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u16 {
+        self.rows
+    }
+
+    /// Writes `text` from `column` on, cut off at the right edge, and
+    /// returns the column after it. Wide characters and emoji take two
+    /// cells; marks with nothing to go on join the character before them.
+    pub fn set_str(&mut self, column: u16, row: u16, text: &str, style: Style) -> u16 {
+        if row >= self.rows {
+            return column;
+        }
+        let mut column = column;
+        for (_, symbol) in graphemes(text) {
+            let width = cluster_width(symbol) as u16;
+            if width == 0 {
+                if !symbol.starts_with(char::is_control) {
+                    self.add_mark(column, row, symbol);
+                }
+                continue;
+            }
+            if column + width > self.columns {
+                break;
+            }
+            self.put(column, row, symbol, style, width);
+            column += width;
+        }
+        column
+    }
+
+    pub fn clear_row(&mut self, row: u16) {
+        if row < self.rows {
+            let start = self.index(0, row);
+            self.cells[start..start + self.columns as usize].fill(BLANK);
+        }
+    }
+
+    /// The characters of `row`, for tests and debugging.
+    pub fn row_text(&self, row: u16) -> String {
+        if row >= self.rows {
+            return String::new();
+        }
+        let start = self.index(0, row);
+        self.cells[start..start + self.columns as usize]
+            .iter()
+            .filter(|cell| !cell.tail)
+            .map(|cell| cell.text())
+            .collect()
+    }
+
+    fn index(&self, column: u16, row: u16) -> usize {
+        row as usize * self.columns as usize + column as usize
+    }
+
+    fn get(&self, column: u16, row: u16) -> &Cell {
+        &self.cells[self.index(column, row)]
+    }
+
+    fn put(&mut self, column: u16, row: u16, symbol: &str, style: Style, width: u16) {
+        // A wide character partly overwritten is blanked as a whole.
+        if self.get(column, row).tail {
+            let head = self.index(column - 1, row);
+            self.cells[head] = BLANK;
+        }
+        let after = column + width;
+        if after < self.columns && self.get(after, row).tail {
+            let tail = self.index(after, row);
+            self.cells[tail] = BLANK;
+        }
+        let index = self.index(column, row);
+        self.cells[index] = Cell {
+            symbol: if symbol == " " {
+                String::new()
+            } else {
+                symbol.to_string()
+            },
+            style,
+            tail: false,
+        };
+        for offset in 1..width {
+            self.cells[index + offset as usize] = Cell {
+                symbol: String::new(),
+                style,
+                tail: true,
+            };
+        }
+    }
+
+    /// Puts combining marks on the character left of `column`.
+    fn add_mark(&mut self, column: u16, row: u16, mark: &str) {
+        let Some(mut head) = column.checked_sub(1) else {
+            return;
+        };
+        if self.get(head, row).tail && head > 0 {
+            head -= 1;
+        }
+        let index = self.index(head, row);
+        if !self.cells[index].symbol.is_empty() {
+            self.cells[index].symbol.push_str(mark);
+        }
+    }
+}
+
+impl Cell {
+    fn text(&self) -> &str {
+        if self.symbol.is_empty() {
+            " "
+        } else {
+            &self.symbol
+        }
+    }
+}
+
+/// The text layer drawn over page images. Each frame is composed in a
+/// buffer and only the cells that differ from what the terminal shows are
+/// written, so redraws do not flicker. Cells left blank let the images
+/// under them show.
+#[derive(Debug)]
+pub struct Screen {
+    frame: Buffer,
+    /// What the terminal shows; `None` when it is not known.
+    shown: Option<Buffer>,
+    cursor: Option<(u16, u16)>,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Self {
+            frame: Buffer::new(0, 0),
+            shown: None,
+            cursor: None,
+        }
+    }
+
+    /// Starts a blank frame, with the cursor hidden.
+    pub fn begin_frame(&mut self, columns: u16, rows: u16) {
+        self.frame = Buffer::new(columns, rows);
+        self.cursor = None;
+    }
+
+    /// The frame being drawn; it keeps the last frame's text until the
+    /// next [`begin_frame`](Self::begin_frame), so single rows can be
+    /// redrawn in between.
+    pub fn buffer(&mut self) -> &mut Buffer {
+        &mut self.frame
+    }
+
+    /// Where the cursor shows after the frame is written, if anywhere.
+    pub fn set_cursor(&mut self, position: Option<(u16, u16)>) {
+        self.cursor = position;
+    }
+
+    /// Records that the terminal's text was cleared.
+    pub fn cleared(&mut self) {
+        self.shown = Some(Buffer::new(self.frame.columns, self.frame.rows));
+    }
+
+    /// Writes the cells of the frame that differ from what is shown.
+    pub fn flush<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        let shown = self
+            .shown
+            .take()
+            .filter(|shown| shown.columns == self.frame.columns && shown.rows == self.frame.rows);
+        let mut style = None;
+        let mut next = None;
+        for row in 0..self.frame.rows {
+            for column in 0..self.frame.columns {
+                let cell = self.frame.get(column, row);
+                if cell.tail {
+                    // Written with the wide character before it.
+                    if next == Some((column, row)) {
+                        next = Some((column + 1, row));
+                    }
+                    continue;
+                }
+                if shown
+                    .as_ref()
+                    .is_some_and(|shown| shown.get(column, row) == cell)
+                {
+                    continue;
+                }
+                if next != Some((column, row)) {
+                    crossterm::queue!(writer, cursor::MoveTo(column, row))?;
+                }
+                if style != Some(cell.style) {
+                    apply_style(writer, cell.style)?;
+                    style = Some(cell.style);
+                }
+                crossterm::queue!(writer, Print(cell.text()))?;
+                next = Some((column + 1, row));
+            }
+        }
+        if style.is_some() {
+            crossterm::queue!(writer, SetAttribute(Attribute::Reset))?;
+        }
+        match self.cursor {
+            Some((column, row)) => {
+                crossterm::queue!(writer, cursor::MoveTo(column, row), cursor::Show)?
+            }
+            None => crossterm::queue!(writer, cursor::Hide)?,
+        }
+        self.shown = Some(self.frame.clone());
+        Ok(())
+    }
+}
+
+fn apply_style<W: Write>(writer: &mut W, style: Style) -> io::Result<()> {
+    crossterm::queue!(writer, SetAttribute(Attribute::Reset))?;
+    if style.reverse {
+        crossterm::queue!(writer, SetAttribute(Attribute::Reverse))?;
+    }
+    if style.bold {
+        crossterm::queue!(writer, SetAttribute(Attribute::Bold))?;
+    }
+    if let Some(color) = style.fg {
+        crossterm::queue!(writer, SetForegroundColor(color))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changed_cells_are_written() {
+        let mut screen = Screen::new();
+        screen.begin_frame(10, 2);
+        screen.buffer().set_str(0, 1, "page 1/9", Style::PLAIN);
+        let mut output = Vec::new();
+        screen.flush(&mut output).unwrap();
+        let first = String::from_utf8(output).unwrap();
+        assert!(first.contains("page 1/9"), "{first:?}");
+
+        screen.begin_frame(10, 2);
+        screen.buffer().set_str(0, 1, "page 2/9", Style::PLAIN);
+        screen.buffer().set_str(0, 0, "[toc]", Style::REVERSE);
+        screen.set_cursor(Some((3, 1)));
+        let mut output = Vec::new();
+        screen.flush(&mut output).unwrap();
+        let second = String::from_utf8(output).unwrap();
+        assert!(second.contains("[toc]"));
+        assert!(!second.contains("page"), "{second:?}");
+        assert!(second.contains('2'));
+        assert!(second.ends_with("\u{1b}[?25h"), "{second:?}");
+        assert_eq!(screen.buffer().row_text(1), "page 2/9  ");
+
+        // A cleared terminal only needs the text that is not blank.
+        screen.cleared();
+        let mut output = Vec::new();
+        screen.flush(&mut output).unwrap();
+        let redrawn = String::from_utf8(output).unwrap();
+        assert!(redrawn.contains("page\u{1b}[2;6H2/9"), "{redrawn:?}");
+        assert!(!redrawn.contains(' '));
+    }
+
+    #[test]
+    fn wide_characters_take_two_cells() {
+        let mut buffer = Buffer::new(8, 1);
+        assert_eq!(buffer.set_str(0, 0, "第一章", Style::PLAIN), 6);
+        assert_eq!(buffer.row_text(0), "第一章  ");
+        // A wide character that does not fit is left out.
+        assert_eq!(buffer.set_str(7, 0, "二x", Style::PLAIN), 7);
+        assert_eq!(buffer.set_str(3, 0, "e\u{301}", Style::PLAIN), 4);
+        assert_eq!(buffer.row_text(0), "第 e\u{301}章  ");
+        // An emoji sequence fills one wide cell.
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let mut emoji = Buffer::new(4, 1);
+        assert_eq!(emoji.set_str(0, 0, &format!("{family}!"), Style::PLAIN), 3);
+        assert_eq!(emoji.row_text(0), format!("{family}! "));
+
+        let mut screen = Screen::new();
+        screen.begin_frame(8, 1);
+        screen.buffer().set_str(0, 0, "章a", Style::PLAIN);
+        let mut output = Vec::new();
+        screen.flush(&mut output).unwrap();
+        let written = String::from_utf8(output).unwrap();
+        assert!(written.contains("章a"), "{written:?}");
+    }
+}
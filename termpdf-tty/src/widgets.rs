@@ -0,0 +1,538 @@
+use std::ops::Range;
+
+use crate::screen::{Buffer, Style};
+use crate::width::{display_width, prefix_for_width};
+
+/// Cells an overlay may cover: the page area above the status line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+/// One row of a window's content.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListRow {
+    pub text: String,
+    /// Drawn in bold, e.g. for the selected entry.
+    pub highlight: bool,
+}
+
+impl ListRow {
+    pub fn new(text: impl Into<String>, highlight: bool) -> Self {
+        Self {
+            text: text.into(),
+            highlight,
+        }
+    }
+}
+
+/// A bordered window drawn in reverse video in the middle of the page
+/// area: a title, an optional prompt line under it, and a list of rows.
+#[derive(Debug, Clone, Copy)]
+pub struct BorderedWindow<'a> {
+    title: &'a str,
+    prompt: Option<&'a str>,
+    footer: Option<&'a str>,
+    bold_title: bool,
+    centered_title: bool,
+    /// Entries to point out on the scrollbar, e.g. search matches.
+    marks: &'a [usize],
+}
+
+/// Where a window goes and how much content it shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowLayout {
+    pub column: u16,
+    pub row: u16,
+    /// Columns between the side borders.
+    pub inner_width: usize,
+    /// Rows of content between the header and the bottom border.
+    pub content_height: usize,
+}
+
+impl<'a> BorderedWindow<'a> {
+    /// Narrowest content a window is drawn with.
+    const MIN_WIDTH: usize = 20;
+
+    pub fn new(title: &'a str) -> Self {
+        Self {
+            title,
+            prompt: None,
+            footer: None,
+            bold_title: false,
+            centered_title: false,
+            marks: &[],
+        }
+    }
+
+    /// Adds a line under the title, e.g. for a search being typed.
+    pub fn with_prompt(mut self, prompt: Option<&'a str>) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    /// Adds text to the right of the bottom border, e.g. a position.
+    pub fn with_footer(mut self, footer: Option<&'a str>) -> Self {
+        self.footer = footer;
+        self
+    }
+
+    pub fn with_bold_title(mut self) -> Self {
+        self.bold_title = true;
+        self
+    }
+
+    pub fn with_centered_title(mut self) -> Self {
+        self.centered_title = true;
+        self
+    }
+
+    /// Points out `entries` (indices into the list) on the scrollbar, so
+    /// the ones off screen can be found.
+    pub fn with_marks(mut self, entries: &'a [usize]) -> Self {
+        self.marks = entries;
+        self
+    }
+
+    /// Rows besides the content: both borders, the title, the prompt and
+    /// the divider under them.
+    fn chrome_rows(&self) -> u32 {
+        if self.prompt.is_some() {
+            5
+        } else {
+            4
+        }
+    }
+
+    /// Fits the window for content `width` columns wide and `rows` rows
+    /// tall into the middle of `area`, shrinking it to leave a margin.
+    /// `None` when not even a small window fits.
+    pub fn layout(&self, area: Area, width: usize, rows: usize) -> Option<WindowLayout> {
+        let chrome = self.chrome_rows();
+        let max_width = area.columns.saturating_sub(6) as usize;
+        let max_height = area.rows.saturating_sub(2);
+        if max_width < Self::MIN_WIDTH || max_height <= chrome {
+            return None;
+        }
+        let inner_width = width
+            .max(display_width(self.title) + 1)
+            .clamp(Self::MIN_WIDTH, max_width);
+        let content_height = rows.clamp(1, (max_height - chrome) as usize);
+        let height = content_height as u32 + chrome;
+        Some(WindowLayout {
+            column: (area.columns.saturating_sub(inner_width as u32 + 2) / 2) as u16,
+            row: (area.rows.saturating_sub(height) / 2) as u16,
+            inner_width,
+            content_height,
+        })
+    }
+
+    /// Draws the window at `layout` with the rows of `list` that are on
+    /// screen, `row` giving each. A scrollbar takes the place of the right
+    /// border when the list does not fit.
+    pub fn draw(
+        &self,
+        buffer: &mut Buffer,
+        layout: &WindowLayout,
+        list: &ListView,
+        mut row: impl FnMut(usize) -> ListRow,
+    ) {
+        let width = layout.inner_width;
+        let column = layout.column;
+        let mut line = layout.row;
+        let border = format!("+{}+", "-".repeat(width));
+
+        print_row(buffer, column, line, &border, false);
+        line = line.saturating_add(1);
+        let title = if self.centered_title {
+            center(self.title, width)
+        } else {
+            truncate_with_ellipsis(format!(" {}", self.title), width)
+        };
+        print_row(
+            buffer,
+            column,
+            line,
+            &format!("|{}|", title),
+            self.bold_title,
+        );
+        line = line.saturating_add(1);
+        if let Some(prompt) = self.prompt {
+            let prompt = truncate_with_ellipsis(format!("  {}", prompt), width);
+            print_row(buffer, column, line, &format!("|{}|", prompt), false);
+            line = line.saturating_add(1);
+        }
+        print_row(buffer, column, line, &border, false);
+        line = line.saturating_add(1);
+
+        let scrollbar = list
+            .scrollbar(layout.content_height)
+            .map(|scrollbar| scrollbar.with_marks(self.marks));
+        for offset in 0..layout.content_height {
+            let index = list.offset() + offset;
+            let content = if index < list.len() {
+                row(index)
+            } else {
+                ListRow::default()
+            };
+            let right = scrollbar
+                .as_ref()
+                .map_or('|', |scrollbar| scrollbar.glyph(offset));
+            let text = format!("|{}{}", truncate_with_ellipsis(content.text, width), right);
+            print_row(buffer, column, line, &text, content.highlight);
+            line = line.saturating_add(1);
+        }
+        let bottom = match self.footer {
+            Some(footer) if display_width(footer) + 3 <= width => {
+                let dashes = width - display_width(footer) - 3;
+                format!("+{} {} -+", "-".repeat(dashes), footer)
+            }
+            _ => border,
+        };
+        print_row(buffer, column, line, &bottom, false);
+    }
+}
+
+/// A list with one entry selected, scrolled to show the entries from
+/// [`offset`](Self::offset) on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ListView {
+    len: usize,
+    selected: usize,
+    offset: usize,
+}
+
+impl ListView {
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            selected: 0,
+            offset: 0,
+        }
+    }
+
+    /// Starts with entry `index` selected.
+    pub fn with_selected(mut self, index: usize) -> Self {
+        self.select(index);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// The first entry on screen.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Changes how many entries there are, keeping the selection and the
+    /// scroll position among them.
+    pub fn set_len(&mut self, len: usize) {
+        self.len = len;
+        self.selected = self.selected.min(len.saturating_sub(1));
+        self.offset = self.offset.min(len.saturating_sub(1));
+    }
+
+    /// Selects entry `index`, or the last one past the end. Whether the
+    /// selection moved.
+    pub fn select(&mut self, index: usize) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+        let clamped = index.min(self.len - 1);
+        let changed = clamped != self.selected;
+        self.selected = clamped;
+        changed
+    }
+
+    pub fn move_selection(&mut self, delta: isize) -> bool {
+        self.select(self.selected.saturating_add_signed(delta))
+    }
+
+    pub fn select_first(&mut self) -> bool {
+        self.select(0)
+    }
+
+    pub fn select_last(&mut self) -> bool {
+        self.select(usize::MAX)
+    }
+
+    /// Scrolls just far enough for the selected entry to be among the
+    /// `visible` rows on screen.
+    pub fn ensure_visible(&mut self, visible: usize) {
+        if visible == 0 || self.len == 0 {
+            self.offset = 0;
+            return;
+        }
+        self.offset = self.offset.min(self.len.saturating_sub(visible));
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + visible {
+            self.offset = self.selected + 1 - visible;
+        }
+    }
+
+    /// Scrolls to show the entries from `offset` on, as far as showing
+    /// `visible` rows at a time allows.
+    pub fn scroll_to(&mut self, offset: usize, visible: usize) {
+        self.offset = offset.min(self.len.saturating_sub(visible));
+    }
+
+    /// The scrollbar for showing `visible` rows at a time, if they are not
+    /// enough for every entry.
+    pub fn scrollbar(&self, visible: usize) -> Option<Scrollbar> {
+        Scrollbar::new(self.len, self.offset, visible)
+    }
+}
+
+/// Where the shown part of a list lies in all of it, drawn as a column
+/// next to the rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scrollbar {
+    len: usize,
+    offset: usize,
+    visible: usize,
+    /// Track rows with a marked entry.
+    marks: Vec<usize>,
+}
+
+impl Scrollbar {
+    const TRACK: char = '|';
+    const THUMB: char = '#';
+    const MARK: char = '+';
+
+    /// `None` when all `len` entries fit in `visible` rows.
+    pub fn new(len: usize, offset: usize, visible: usize) -> Option<Self> {
+        (visible > 0 && len > visible).then(|| Self {
+            len,
+            offset: offset.min(len - visible),
+            visible,
+            marks: Vec::new(),
+        })
+    }
+
+    /// Marks the track rows of `entries` (indices into the list).
+    pub fn with_marks(mut self, entries: &[usize]) -> Self {
+        self.marks = entries
+            .iter()
+            .filter(|&&entry| entry < self.len)
+            .map(|&entry| entry * self.visible / self.len)
+            .collect();
+        self.marks.dedup();
+        self
+    }
+
+    /// Rows of the track the thumb covers. It reaches either end only when
+    /// the list is scrolled all the way there.
+    pub fn thumb(&self) -> Range<usize> {
+        let size = (self.visible * self.visible / self.len).max(1);
+        let last_offset = self.len - self.visible;
+        let start = if self.offset == 0 {
+            0
+        } else if self.offset == last_offset {
+            self.visible - size
+        } else {
+            (self.offset * (self.visible - size)).div_ceil(last_offset)
+        };
+        start..start + size
+    }
+
+    /// The glyph on row `row` of the track. Marks under the thumb are
+    /// left out, as their entries are on screen.
+    pub fn glyph(&self, row: usize) -> char {
+        if self.thumb().contains(&row) {
+            Self::THUMB
+        } else if self.marks.contains(&row) {
+            Self::MARK
+        } else {
+            Self::TRACK
+        }
+    }
+}
+
+fn print_row(buffer: &mut Buffer, column: u16, row: u16, content: &str, highlight: bool) {
+    let style = if highlight {
+        Style::REVERSE.bold()
+    } else {
+        Style::REVERSE
+    };
+    buffer.set_str(column, row, content, style);
+}
+
+/// Puts `text` in the middle of `width` cells, cutting it like
+/// [`truncate_with_ellipsis`] when it does not fit.
+fn center(text: &str, width: usize) -> String {
+    let text_width = display_width(text);
+    if text_width >= width {
+        return truncate_with_ellipsis(text.to_string(), width);
+    }
+    let left = (width - text_width) / 2;
+    truncate_with_ellipsis(format!("{}{}", " ".repeat(left), text), width)
+}
+
+/// Cuts `text` to `width` cells with a trailing `...`, or pads it with
+/// spaces to `width` cells.
+pub fn truncate_with_ellipsis(mut text: String, width: usize) -> String {
+    let mut text_width = display_width(&text);
+    if text_width > width {
+        text = if width <= 3 {
+            prefix_for_width(&text, width).to_string()
+        } else {
+            format!("{}...", prefix_for_width(&text, width - 3))
+        };
+        text_width = display_width(&text);
+    }
+    if text_width < width {
+        text.push_str(&" ".repeat(width - text_width));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AREA: Area = Area {
+        columns: 80,
+        rows: 24,
+    };
+
+    fn rows(buffer: &Buffer) -> Vec<String> {
+        (0..24)
+            .map(|row| buffer.row_text(row).trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn windows_fit_the_area() {
+        let window = BorderedWindow::new("Marks");
+        let layout = window.layout(AREA, 30, 100).unwrap();
+        assert_eq!(layout.inner_width, 30);
+        assert_eq!(layout.content_height, 18);
+        assert_eq!((layout.column, layout.row), (24, 1));
+        let small = window.layout(AREA, 3, 2).unwrap();
+        assert_eq!((small.inner_width, small.content_height), (20, 2));
+        let narrow = Area {
+            columns: 20,
+            rows: 24,
+        };
+        assert!(window.layout(narrow, 30, 5).is_none());
+    }
+
+    #[test]
+    fn scrollbars_show_the_offset_and_marks() {
+        assert_eq!(Scrollbar::new(5, 0, 10), None);
+        let top = Scrollbar::new(100, 0, 10).unwrap();
+        assert_eq!(top.thumb(), 0..1);
+        assert_eq!(top.glyph(0), '#');
+        assert_eq!(Scrollbar::new(100, 1, 10).unwrap().thumb(), 1..2);
+        assert_eq!(Scrollbar::new(100, 90, 10).unwrap().thumb(), 9..10);
+        assert_eq!(Scrollbar::new(20, 5, 10).unwrap().thumb(), 3..8);
+        let marked = Scrollbar::new(100, 0, 10)
+            .unwrap()
+            .with_marks(&[5, 55, 57, 99]);
+        let track: String = (0..10).map(|row| marked.glyph(row)).collect();
+        assert_eq!(track, "#||||+|||+");
+    }
+
+    #[test]
+    fn lists_scroll_to_keep_the_selection_on_screen() {
+        let mut list = ListView::new(10).with_selected(4);
+        assert_eq!(list.selected(), 4);
+        assert!(list.move_selection(3));
+        list.ensure_visible(5);
+        assert_eq!(list.offset(), 3);
+        assert!(list.select_first());
+        list.ensure_visible(5);
+        assert_eq!(list.offset(), 0);
+        assert!(list.select_last());
+        assert!(!list.move_selection(1));
+        list.ensure_visible(5);
+        assert_eq!((list.selected(), list.offset()), (9, 5));
+
+        // Fewer entries pull the selection and the scroll position in.
+        list.set_len(3);
+        assert_eq!((list.selected(), list.offset()), (2, 2));
+        list.ensure_visible(5);
+        assert_eq!(list.offset(), 0);
+        list.set_len(0);
+        assert!(!list.select_first());
+
+        let mut text = ListView::new(10);
+        text.scroll_to(8, 4);
+        assert_eq!(text.offset(), 6);
+    }
+
+    #[test]
+    fn windows_draw_their_title_rows_and_footer() {
+        let window = BorderedWindow::new("Marks");
+        let mut buffer = Buffer::new(80, 24);
+        let list = ListView::new(3);
+        let layout = window.layout(AREA, 10, list.len()).unwrap();
+        window.draw(&mut buffer, &layout, &list, |index| {
+            ListRow::new(format!("mark {}", index), index == 1)
+        });
+        let rows = rows(&buffer);
+        let top = layout.row as usize;
+        assert_eq!(rows[top], format!("+{}+", "-".repeat(20)));
+        assert_eq!(rows[top + 1], format!("| Marks{}|", " ".repeat(14)));
+        assert_eq!(rows[top + 2], rows[top]);
+        assert_eq!(rows[top + 5], format!("|mark 2{}|", " ".repeat(14)));
+        assert_eq!(rows[top + 6], rows[top]);
+
+        let footer = window.with_footer(Some("2/3"));
+        footer.draw(&mut buffer, &layout, &list, |index| {
+            ListRow::new(format!("mark {}", index), false)
+        });
+        assert_eq!(
+            buffer.row_text(layout.row + 6).trim(),
+            format!("+{} 2/3 -+", "-".repeat(14))
+        );
+    }
+
+    #[test]
+    fn centered_titles_sit_in_the_middle() {
+        let window = BorderedWindow::new("Contents").with_centered_title();
+        let mut buffer = Buffer::new(80, 24);
+        let layout = window.layout(AREA, 10, 1).unwrap();
+        window.draw(&mut buffer, &layout, &ListView::new(1), |_| {
+            ListRow::new("Introduction", false)
+        });
+        assert_eq!(
+            buffer.row_text(layout.row + 1).trim(),
+            format!("|{}Contents{}|", " ".repeat(6), " ".repeat(6))
+        );
+    }
+
+    #[test]
+    fn wide_text_is_cut_by_cells() {
+        let window = BorderedWindow::new("目次");
+        let mut buffer = Buffer::new(80, 24);
+        let layout = window.layout(AREA, 30, 1).unwrap();
+        window.draw(&mut buffer, &layout, &ListView::new(1), |_| {
+            ListRow::new("第一章 はじめに 長いタイトルが続く", false)
+        });
+        let title = buffer.row_text(layout.row + 1);
+        let entry = buffer.row_text(layout.row + 3);
+        assert_eq!(title.trim().split('|').nth(1).map(display_width), Some(30));
+        assert!(
+            entry.contains("第一章 はじめに 長いタイト... |"),
+            "{entry:?}"
+        );
+        assert_eq!(
+            truncate_with_ellipsis("Cafe\u{301}".into(), 6),
+            "Cafe\u{301}  "
+        );
+        assert_eq!(truncate_with_ellipsis("日本語".into(), 3), "日 ");
+    }
+}
@@ -0,0 +1,274 @@
+//! How many terminal cells text takes up. Terminals draw East Asian wide
+//! characters (CJK ideographs, kana, hangul, fullwidth forms, most emoji)
+//! across two cells and combining marks on top of the character before
+//! them, so neither bytes nor `char`s measure text on screen. Text is
+//! measured by grapheme cluster: a character with its marks, an emoji
+//! sequence joined by ZWJ, or a pair of regional indicators forming a flag.
+//!
+//! The tables are abridged from Unicode 15.1: [`WIDE`] from the `W` and `F`
+//! entries of `EastAsianWidth.txt`, [`ZERO_WIDTH`] from the nonspacing and
+//! enclosing marks (`Mn`, `Me`) and format characters (`Cf`) of
+//! `UnicodeData.txt`. Only blocks likely to turn up in documents are
+//! listed; anything else counts as one cell.
+
+/// Ranges of characters drawn over the previous one, taking no cell.
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036F), // combining diacritical marks
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD),
+    (0x0610, 0x061A),
+    (0x064B, 0x065F),
+    (0x0670, 0x0670),
+    (0x06D6, 0x06DC),
+    (0x0900, 0x0902), // Devanagari signs
+    (0x093A, 0x093C),
+    (0x0941, 0x0948),
+    (0x094D, 0x094D),
+    (0x0E31, 0x0E31), // Thai vowels and tone marks
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x1160, 0x11FF), // hangul jamo vowels and finals
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200B, 0x200F), // zero-width space, joiners and direction marks
+    (0x202A, 0x202E),
+    (0x2060, 0x2064),
+    (0x20D0, 0x20FF), // combining marks for symbols
+    (0x302A, 0x302D),
+    (0x3099, 0x309A), // kana voicing marks
+    (0xFE00, 0xFE0F), // variation selectors
+    (0xFE20, 0xFE2F),
+    (0xFEFF, 0xFEFF),
+    (0xE0001, 0xE007F), // tags, e.g. in subdivision flags
+    (0xE0100, 0xE01EF),
+];
+
+/// Ranges of East Asian wide and fullwidth characters, taking two cells.
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115F), // hangul jamo initials
+    (0x231A, 0x231B),
+    (0x2329, 0x232A),
+    (0x23E9, 0x23EC),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x274C, 0x274C),
+    (0x2753, 0x2755),
+    (0x2795, 0x2797),
+    (0x2B1B, 0x2B1C),
+    (0x2E80, 0x303E), // CJK radicals, punctuation
+    (0x3041, 0x3096), // hiragana
+    (0x309B, 0x33FF), // katakana, bopomofo, CJK compatibility
+    (0x3400, 0x4DBF), // CJK extension A
+    (0x4E00, 0x9FFF), // CJK unified ideographs
+    (0xA000, 0xA4CF), // Yi
+    (0xA960, 0xA97F),
+    (0xAC00, 0xD7A3), // hangul syllables
+    (0xF900, 0xFAFF), // CJK compatibility ideographs
+    (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F),
+    (0xFF00, 0xFF60), // fullwidth forms
+    (0xFFE0, 0xFFE6),
+    (0x16FE0, 0x16FE4),
+    (0x17000, 0x18CFF), // Tangut
+    (0x1B000, 0x1B16F), // kana supplement
+    (0x1F004, 0x1F004),
+    (0x1F0CF, 0x1F0CF),
+    (0x1F18E, 0x1F18E),
+    (0x1F191, 0x1F19A),
+    (0x1F200, 0x1F251),
+    (0x1F300, 0x1F64F), // pictographs and emoticons
+    (0x1F680, 0x1F6FF),
+    (0x1F7E0, 0x1F7EB),
+    (0x1F90C, 0x1F9FF),
+    (0x1FA70, 0x1FAFF),
+    (0x20000, 0x2FFFD), // CJK extensions B and later
+    (0x30000, 0x3FFFD),
+];
+
+fn in_table(table: &[(u32, u32)], c: char) -> bool {
+    let code = c as u32;
+    table
+        .binary_search_by(|&(start, end)| {
+            if end < code {
+                std::cmp::Ordering::Less
+            } else if start > code {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+/// Cells `c` takes up: 0 for combining marks and control characters, 2
+/// for wide characters and 1 otherwise.
+pub fn char_width(c: char) -> usize {
+    if c.is_control() || in_table(ZERO_WIDTH, c) {
+        0
+    } else if in_table(WIDE, c) {
+        2
+    } else {
+        1
+    }
+}
+
+const ZWJ: char = '\u{200D}';
+/// Asks for the emoji rather than the text form of the character before.
+const EMOJI_PRESENTATION: char = '\u{FE0F}';
+/// Asks for the text rather than the emoji form of the character before.
+const TEXT_PRESENTATION: char = '\u{FE0E}';
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+/// Skin tone modifiers, drawn as part of the emoji before them.
+fn is_emoji_modifier(c: char) -> bool {
+    ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+}
+
+/// Whether `next` belongs to the grapheme cluster `cluster` so far.
+fn extends(cluster: &str, next: char) -> bool {
+    let Some(last) = cluster.chars().next_back() else {
+        return false;
+    };
+    if next.is_control() || last.is_control() {
+        return false;
+    }
+    if last == ZWJ || is_emoji_modifier(next) || in_table(ZERO_WIDTH, next) {
+        return true;
+    }
+    // Regional indicators pair up into flags.
+    is_regional_indicator(next)
+        && cluster
+            .chars()
+            .rev()
+            .take_while(|c| is_regional_indicator(*c))
+            .count()
+            % 2
+            == 1
+}
+
+/// The grapheme clusters of `text` with their byte offsets: each character
+/// with the marks on it, emoji joined by ZWJ and flags as one.
+pub fn graphemes(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut rest = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, _) = rest.next()?;
+        let mut end = text.len();
+        while let Some(&(index, next)) = rest.peek() {
+            if !extends(&text[start..index], next) {
+                end = index;
+                break;
+            }
+            rest.next();
+        }
+        Some((start, &text[start..end]))
+    })
+}
+
+/// Cells the grapheme cluster `cluster` takes up: that of its first
+/// character, except that emoji presentation, flags and skin tones make it
+/// two cells and text presentation one.
+pub fn cluster_width(cluster: &str) -> usize {
+    let mut chars = cluster.chars();
+    let Some(base) = chars.next() else {
+        return 0;
+    };
+    let width = char_width(base);
+    if width == 0 {
+        return 0;
+    }
+    let rest: Vec<char> = chars.collect();
+    if rest.contains(&TEXT_PRESENTATION) {
+        1
+    } else if rest.contains(&EMOJI_PRESENTATION)
+        || rest.iter().copied().any(is_emoji_modifier)
+        || (is_regional_indicator(base) && rest.first().is_some_and(|c| is_regional_indicator(*c)))
+    {
+        2
+    } else {
+        width
+    }
+}
+
+/// Cells `text` takes up.
+pub fn display_width(text: &str) -> usize {
+    graphemes(text)
+        .map(|(_, cluster)| cluster_width(cluster))
+        .sum()
+}
+
+/// The longest start of `text` that fits in `width` cells, keeping
+/// grapheme clusters whole.
+pub fn prefix_for_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (index, cluster) in graphemes(text) {
+        let cells = cluster_width(cluster);
+        if used + cells > width {
+            return &text[..index];
+        }
+        used += cells;
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_and_combining_characters_are_measured_in_cells() {
+        for table in [ZERO_WIDTH, WIDE] {
+            assert!(table.windows(2).all(|pair| pair[0].1 < pair[1].0));
+        }
+        assert_eq!(display_width("Chapter 1"), 9);
+        assert_eq!(display_width("第一章"), 6);
+        assert_eq!(display_width("한국어"), 6);
+        assert_eq!(display_width("ＡＢ"), 4);
+        // "é" spelled as "e" and a combining acute accent.
+        assert_eq!(display_width("Cafe\u{301}"), 4);
+        assert_eq!(display_width("\t"), 0);
+
+        assert_eq!(prefix_for_width("第一章", 5), "第一");
+        assert_eq!(prefix_for_width("Cafe\u{301}s", 4), "Cafe\u{301}");
+        assert_eq!(prefix_for_width("abc", 10), "abc");
+    }
+
+    #[test]
+    fn emoji_sequences_and_flags_are_one_cluster() {
+        let clusters = |text| {
+            graphemes(text)
+                .map(|(_, cluster)| cluster)
+                .collect::<Vec<&str>>()
+        };
+        // A family joined by ZWJ, a flag and a thumbs-up with a skin tone.
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let flag = "\u{1F1EB}\u{1F1F7}";
+        let thumb = "\u{1F44D}\u{1F3FD}";
+        assert_eq!(clusters(family), vec![family]);
+        assert_eq!(display_width(family), 2);
+        assert_eq!(display_width(flag), 2);
+        assert_eq!(display_width(thumb), 2);
+        // Two flags in a row split between their pairs.
+        let flags = "\u{1F1EB}\u{1F1F7}\u{1F1E9}\u{1F1EA}";
+        assert_eq!(clusters(flags), vec![flag, "\u{1F1E9}\u{1F1EA}"]);
+        // A heart is narrow as text and wide as an emoji.
+        assert_eq!(display_width("\u{2764}"), 1);
+        assert_eq!(display_width("\u{2764}\u{FE0F}"), 2);
+        assert_eq!(display_width("\u{231A}\u{FE0E}"), 1);
+
+        assert_eq!(prefix_for_width(&format!("a{family}b"), 2), "a");
+        assert_eq!(
+            prefix_for_width(&format!("a{family}b"), 3),
+            format!("a{family}")
+        );
+        assert_eq!(clusters("a\tb"), vec!["a", "\t", "b"]);
+    }
+}